@@ -0,0 +1,10 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/rustcanbus.proto");
+        // protox instead of protoc, so building needs nothing outside cargo.
+        let descriptors = protox::compile(["proto/rustcanbus.proto"], ["proto"]).expect("invalid proto/rustcanbus.proto");
+        tonic_build::configure().compile_fds(descriptors).expect("failed to generate the gRPC code");
+    }
+}
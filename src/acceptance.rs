@@ -0,0 +1,290 @@
+use crate::ffi::VciInitConfig;
+use crate::id::Id;
+
+/// Which frame formats the controller accepts (`VciInitConfig.filter`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameKinds {
+    #[default]
+    All,
+    StandardOnly,
+    ExtendedOnly,
+}
+
+impl FrameKinds {
+    pub fn raw(self) -> u8 {
+        match self {
+            FrameKinds::All => 1,
+            FrameKinds::StandardOnly => 2,
+            FrameKinds::ExtendedOnly => 3,
+        }
+    }
+}
+
+/// Computed hardware acceptance filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcceptanceFilter {
+    pub acc_code: u32,
+    pub acc_mask: u32,
+    pub kinds: FrameKinds,
+    /// Number of IDs the mask lets through that were not asked for. Non-zero means the single
+    /// SJA1000 filter can't express the request exactly; filter the rest in software. `None`
+    /// means the request mixes standard and extended IDs, which no single filter can narrow, so
+    /// the hardware filter is off and everything of the configured kinds passes.
+    pub extra_ids: Option<u64>,
+}
+
+impl AcceptanceFilter {
+    pub const ACCEPT_ALL: AcceptanceFilter = AcceptanceFilter {
+        acc_code: 0,
+        acc_mask: 0xFFFF_FFFF,
+        kinds: FrameKinds::All,
+        extra_ids: Some(0),
+    };
+
+    /// Whether the controller actually narrows what it passes by ID.
+    pub fn is_filtering(&self) -> bool {
+        self.extra_ids.is_some() && self.acc_mask != AcceptanceFilter::ACCEPT_ALL.acc_mask
+    }
+
+    pub fn apply(&self, config: &mut VciInitConfig) {
+        config.acc_code = self.acc_code;
+        config.acc_mask = self.acc_mask;
+        config.filter = self.kinds.raw();
+    }
+}
+
+/// Builds the tightest single acceptance filter that passes every requested ID.
+///
+/// The SJA1000 compares left-aligned ID bits: standard IDs sit in bits 31..21 (the RTR bit and
+/// first two data bytes below them are left as don't-care), extended IDs in bits 31..3. A mask
+/// bit of 1 means "don't care".
+#[derive(Debug, Clone, Default)]
+pub struct FilterBuilder {
+    ranges: Vec<(Id, Id)>,
+    kinds: Option<FrameKinds>,
+}
+
+impl FilterBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn id(mut self, id: Id) -> Self {
+        self.ranges.push((id, id));
+        self
+    }
+
+    /// Inclusive range; both ends must be the same kind of ID.
+    pub fn range(mut self, first: Id, last: Id) -> Self {
+        let (first, last) = if first.raw() <= last.raw() { (first, last) } else { (last, first) };
+        self.ranges.push((first, last));
+        self
+    }
+
+    /// Overrides the frame-format setting derived from the IDs.
+    pub fn kinds(mut self, kinds: FrameKinds) -> Self {
+        self.kinds = Some(kinds);
+        self
+    }
+
+    pub fn build(&self) -> AcceptanceFilter {
+        let standard = self.ranges.iter().filter(|(first, _)| !first.is_extended()).count();
+        let extended = self.ranges.len() - standard;
+        let mixed = self.ranges.iter().any(|(first, last)| first.is_extended() != last.is_extended());
+
+        if self.ranges.is_empty() || mixed || (standard > 0 && extended > 0) {
+            // One mask can't be tight for both layouts at once; let the controller pass everything.
+            let mut filter = AcceptanceFilter::ACCEPT_ALL;
+            filter.kinds = self.kinds.unwrap_or(FrameKinds::All);
+            if !self.ranges.is_empty() {
+                filter.extra_ids = None;
+            }
+            return filter;
+        }
+
+        let is_extended = extended > 0;
+        let base = self.ranges[0].0.raw();
+        let varying = self
+            .ranges
+            .iter()
+            .fold(0u32, |acc, (first, last)| acc | (first.raw() ^ base) | smear(first.raw() ^ last.raw()));
+        let code = base & !varying;
+
+        let requested = union_size(&self.ranges);
+        let passed = 1u64 << varying.count_ones();
+
+        let (acc_code, acc_mask) = if is_extended {
+            (code << 3, (varying << 3) | 0b111)
+        } else {
+            (code << 21, (varying << 21) | 0x001F_FFFF)
+        };
+        AcceptanceFilter {
+            acc_code,
+            acc_mask,
+            kinds: self.kinds.unwrap_or(if is_extended { FrameKinds::ExtendedOnly } else { FrameKinds::StandardOnly }),
+            extra_ids: Some(passed - requested),
+        }
+    }
+}
+
+/// Sets every bit at or below the highest set bit of `x`.
+fn smear(x: u32) -> u32 {
+    match x {
+        0 => 0,
+        _ => u32::MAX >> x.leading_zeros(),
+    }
+}
+
+fn union_size(ranges: &[(Id, Id)]) -> u64 {
+    let mut spans: Vec<(u64, u64)> = ranges.iter().map(|(a, b)| (a.raw() as u64, b.raw() as u64)).collect();
+    spans.sort_unstable();
+    let mut total = 0;
+    let mut current: Option<(u64, u64)> = None;
+    for (start, end) in spans {
+        match current {
+            Some((s, e)) if start <= e + 1 => current = Some((s, e.max(end))),
+            Some((s, e)) => {
+                total += e - s + 1;
+                current = Some((start, end));
+            }
+            None => current = Some((start, end)),
+        }
+    }
+    if let Some((s, e)) = current {
+        total += e - s + 1;
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::XorShift;
+
+    fn std_id(id: u16) -> Id {
+        Id::standard(id).unwrap()
+    }
+
+    fn ext_id(id: u32) -> Id {
+        Id::extended(id).unwrap()
+    }
+
+    /// Whether the SJA1000 passes `id` through `filter`, comparing the bits the mask cares about.
+    fn passes(filter: &AcceptanceFilter, id: Id) -> bool {
+        let kind_ok = match filter.kinds {
+            FrameKinds::All => true,
+            FrameKinds::StandardOnly => !id.is_extended(),
+            FrameKinds::ExtendedOnly => id.is_extended(),
+        };
+        let aligned = if id.is_extended() { id.raw() << 3 } else { id.raw() << 21 };
+        kind_ok && (aligned ^ filter.acc_code) & !filter.acc_mask == 0
+    }
+
+    /// Every requested ID passes, and `extra_ids` counts exactly the standard IDs that pass
+    /// without being asked for.
+    fn check_standard(builder: &FilterBuilder, requested: impl Fn(u16) -> bool) -> AcceptanceFilter {
+        let filter = builder.build();
+        let mut extra = 0;
+        for id in 0..=Id::MAX_STANDARD {
+            match (requested(id), passes(&filter, std_id(id))) {
+                (true, passes) => assert!(passes, "0x{id:03X} is requested"),
+                (false, true) => extra += 1,
+                (false, false) => {}
+            }
+        }
+        assert_eq!(filter.extra_ids, Some(extra), "{filter:X?}");
+        filter
+    }
+
+    #[test]
+    fn aligned_standard_range_is_exact() {
+        let filter = check_standard(&FilterBuilder::new().range(std_id(0x100), std_id(0x10F)), |id| (0x100..=0x10F).contains(&id));
+        assert_eq!(filter, AcceptanceFilter { acc_code: 0x2000_0000, acc_mask: 0x01FF_FFFF, kinds: FrameKinds::StandardOnly, extra_ids: Some(0) });
+    }
+
+    #[test]
+    fn single_standard_ids() {
+        let filter = check_standard(&FilterBuilder::new().id(std_id(0x7FF)), |id| id == 0x7FF);
+        assert_eq!((filter.acc_code, filter.acc_mask), (0xFFE0_0000, 0x001F_FFFF));
+        let filter = check_standard(&FilterBuilder::new().id(std_id(0)), |id| id == 0);
+        assert_eq!((filter.acc_code, filter.acc_mask), (0, 0x001F_FFFF));
+    }
+
+    #[test]
+    fn unaligned_and_scattered_standard_ids_report_the_extras() {
+        let filter = check_standard(&FilterBuilder::new().range(std_id(0x100), std_id(0x110)), |id| (0x100..=0x110).contains(&id));
+        assert_eq!(filter.extra_ids, Some(0x20 - 0x11));
+
+        let filter = check_standard(&FilterBuilder::new().id(std_id(0x123)).id(std_id(0x321)), |id| id == 0x123 || id == 0x321);
+        assert_eq!(filter.extra_ids, Some((1 << (0x123u32 ^ 0x321).count_ones()) - 2));
+    }
+
+    #[test]
+    fn overlapping_and_reversed_ranges_count_once() {
+        let builder = FilterBuilder::new().range(std_id(0x207), std_id(0x200)).id(std_id(0x203)).range(std_id(0x204), std_id(0x205));
+        let filter = check_standard(&builder, |id| (0x200..=0x207).contains(&id));
+        assert_eq!((filter.acc_code, filter.acc_mask, filter.extra_ids), (0x4000_0000, 0x00FF_FFFF, Some(0)));
+    }
+
+    #[test]
+    fn random_standard_sets() {
+        let mut rng = XorShift(0xD1B5_4A32_D192_ED03);
+        for _ in 0..200 {
+            let mut builder = FilterBuilder::new();
+            let mut wanted = Vec::new();
+            for _ in 0..1 + rng.below(3) {
+                let first = rng.below(0x800) as u16;
+                let last = (first + rng.below(0x40) as u16).min(Id::MAX_STANDARD);
+                builder = builder.range(std_id(first), std_id(last));
+                wanted.push(first..=last);
+            }
+            check_standard(&builder, |id| wanted.iter().any(|range| range.contains(&id)));
+        }
+    }
+
+    #[test]
+    fn extended_ranges() {
+        let filter = FilterBuilder::new().range(ext_id(0x18FF_0000), ext_id(0x18FF_FFFF)).build();
+        assert_eq!(filter, AcceptanceFilter { acc_code: 0xC7F8_0000, acc_mask: 0x0007_FFFF, kinds: FrameKinds::ExtendedOnly, extra_ids: Some(0) });
+        for (id, expected) in [(0x18FF_0000, true), (0x18FF_ABCD, true), (0x18FF_FFFF, true), (0x18FE_FFFF, false), (0x1900_0000, false)] {
+            assert_eq!(passes(&filter, ext_id(id)), expected, "0x{id:08X}");
+        }
+        assert!(!passes(&filter, std_id(0x7FF)), "standard frames are refused");
+
+        let filter = FilterBuilder::new().id(ext_id(Id::MAX_EXTENDED)).build();
+        assert_eq!((filter.acc_code, filter.acc_mask, filter.extra_ids), (0xFFFF_FFF8, 0b111, Some(0)));
+
+        let filter = FilterBuilder::new().range(ext_id(0x100), ext_id(0x17F)).id(ext_id(0x0FF)).build();
+        assert!([0x0FF, 0x100, 0x140, 0x17F].into_iter().all(|id| passes(&filter, ext_id(id))));
+        assert_eq!(filter.extra_ids, Some((1u64 << (0x0FFu32 ^ 0x100 | 0x7F).count_ones()) - 0x81));
+    }
+
+    #[test]
+    fn mixed_standard_and_extended_turn_the_filter_off() {
+        let both = FilterBuilder::new().id(std_id(0x100)).id(ext_id(0x100));
+        assert_eq!(both.build(), AcceptanceFilter { extra_ids: None, ..AcceptanceFilter::ACCEPT_ALL });
+        assert!(!both.build().is_filtering());
+        let across = FilterBuilder::new().range(std_id(0x7FF), ext_id(0x800));
+        assert_eq!(across.build(), AcceptanceFilter { extra_ids: None, ..AcceptanceFilter::ACCEPT_ALL });
+        assert_eq!(both.kinds(FrameKinds::ExtendedOnly).build().kinds, FrameKinds::ExtendedOnly);
+    }
+
+    #[test]
+    fn empty_builder_passes_everything_exactly() {
+        assert_eq!(FilterBuilder::new().build(), AcceptanceFilter::ACCEPT_ALL);
+        assert!(!FilterBuilder::new().build().is_filtering());
+        assert!(FilterBuilder::new().id(std_id(0x42)).build().is_filtering());
+        assert_eq!(FilterBuilder::new().kinds(FrameKinds::StandardOnly).build().kinds, FrameKinds::StandardOnly);
+    }
+
+    #[test]
+    fn kinds_override_and_apply() {
+        let filter = FilterBuilder::new().id(std_id(0x42)).kinds(FrameKinds::All).build();
+        assert_eq!((filter.kinds, filter.extra_ids), (FrameKinds::All, Some(0)));
+        let mut config = VciInitConfig::default();
+        filter.apply(&mut config);
+        assert_eq!((config.acc_code, config.acc_mask, config.filter), (0x42 << 21, 0x001F_FFFF, 1));
+        FilterBuilder::new().id(ext_id(0x42)).build().apply(&mut config);
+        assert_eq!((config.acc_code, config.acc_mask, config.filter), (0x42 << 3, 0b111, 3));
+    }
+}
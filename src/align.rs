@@ -0,0 +1,351 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::timestamp::{shift, signed_nanos, TICK};
+
+/// Frames whose arrival on the host is further apart than this aren't taken for the same one.
+const MATCH_WINDOW: Duration = Duration::from_millis(100);
+/// How many of the latest matched frames the offset is the median of.
+const MATCH_SAMPLES: usize = 255;
+
+/// How [`ChannelAlignment`] estimates the offset between an adapter's two timestamp counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignmentMode {
+    /// From the host time of each channel's `VCI_StartCAN`, when its counter restarts from 0.
+    Start,
+    /// As `Start`, then from frames seen on both channels, as with both ports on one bus. Behind
+    /// a gateway the forwarding delay counts as offset, so forwarded frames line up instead.
+    Frames,
+}
+
+/// The offset [`ChannelAlignment`] estimated and how well matched frames agree with it, in
+/// microseconds. Offsets are positive when CAN2's counter started after CAN1's.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AlignmentReport {
+    /// From the channels' start times; `None` until both have been started.
+    pub start_offset_us: Option<i64>,
+    /// The median of the latest matched frames, which replaces the start estimate once there is one.
+    pub matched_offset_us: Option<i64>,
+    /// Frames seen on both channels.
+    pub matched: u64,
+    /// How far the latest matched frames are from that median, on average and at most.
+    pub residual_mean_us: Option<f64>,
+    pub residual_max_us: Option<u64>,
+}
+
+type Content = (Id, bool, u8, [u8; 8]);
+
+/// Puts the frames of an adapter's two channels on one timeline.
+///
+/// Each channel's timestamps count from its own `VCI_StartCAN`, so converting them separately
+/// leaves the channels apart by however long the two starts were apart, give or take each one's
+/// USB latency. Both counters run off the adapter's one oscillator, though, so the difference
+/// between them is a constant number of ticks. CAN1's times are kept as they are and CAN2's are
+/// mapped onto CAN1's clock through that offset, estimated from the start times and, with
+/// [`AlignmentMode::Frames`], from frames seen on both channels. Until both channels are started
+/// and CAN1 has received a frame, CAN2's times are left alone.
+#[derive(Debug, Clone)]
+pub struct ChannelAlignment {
+    mode: AlignmentMode,
+    /// Host time of each channel's last start.
+    starts: [Option<Instant>; 2],
+    /// Ticks and converted time of CAN1's latest frame, for mapping CAN2's ticks onto its clock.
+    reference: Option<(u64, SystemTime, Instant)>,
+    /// Each channel's unmatched frames that arrived within the last [`MATCH_WINDOW`].
+    recent: [VecDeque<(Content, u64, Instant)>; 2],
+    /// CAN1 minus CAN2 ticks of the latest matched frames.
+    samples: VecDeque<i64>,
+    matched: u64,
+    /// Last time given to a CAN2 frame, so times don't go backwards when the estimate moves.
+    last: Option<(SystemTime, Instant)>,
+}
+
+impl ChannelAlignment {
+    pub fn new(mode: AlignmentMode) -> Self {
+        Self {
+            mode,
+            starts: [None; 2],
+            reference: None,
+            recent: Default::default(),
+            samples: VecDeque::new(),
+            matched: 0,
+            last: None,
+        }
+    }
+
+    /// Records that `channel` was (re)started at `at`, restarting its counter; what was learned
+    /// about the old offset no longer holds.
+    pub fn started(&mut self, channel: u32, at: Instant) {
+        let Some(start) = self.starts.get_mut(channel as usize) else {
+            return;
+        };
+        *start = Some(at);
+        if channel == 0 {
+            self.reference = None;
+        }
+        self.recent.iter_mut().for_each(VecDeque::clear);
+        self.samples.clear();
+    }
+
+    /// The time of a frame received on `channel`, whose unwrapped timestamp `ticks` its own clock
+    /// converted to `time`, on the common timeline. `received` is when the host read it.
+    pub fn align(&mut self, channel: u32, frame: &Frame, ticks: u64, time: (SystemTime, Instant), received: Instant) -> (SystemTime, Instant) {
+        if self.mode == AlignmentMode::Frames && channel < 2 {
+            self.observe(channel, frame, ticks, received);
+        }
+        if channel == 0 {
+            self.reference = Some((ticks, time.0, time.1));
+            return time;
+        }
+        let (Some(offset), Some((base, wall, instant))) = (self.offset(), self.reference) else {
+            return time;
+        };
+        let nanos = (ticks as i64 + offset - base as i64).saturating_mul(TICK.as_nanos() as i64);
+        let mut aligned = (shift_wall(wall, nanos), shift(instant, nanos));
+        if let Some(last) = self.last.filter(|last| last.1 > aligned.1) {
+            aligned = last;
+        }
+        self.last = Some(aligned);
+        aligned
+    }
+
+    pub fn report(&self) -> AlignmentReport {
+        let us = |ticks: i64| ticks * TICK.as_micros() as i64;
+        let matched = self.matched_offset();
+        let residuals: Vec<u64> = matched.map_or(Vec::new(), |offset| self.samples.iter().map(|sample| us(sample - offset).unsigned_abs()).collect());
+        AlignmentReport {
+            start_offset_us: self.start_offset().map(us),
+            matched_offset_us: matched.map(us),
+            matched: self.matched,
+            residual_mean_us: (!residuals.is_empty()).then(|| residuals.iter().sum::<u64>() as f64 / residuals.len() as f64),
+            residual_max_us: residuals.iter().max().copied(),
+        }
+    }
+
+    /// CAN2's offset from CAN1 in ticks, the best estimate so far.
+    fn offset(&self) -> Option<i64> {
+        self.matched_offset().or_else(|| self.start_offset())
+    }
+
+    fn start_offset(&self) -> Option<i64> {
+        let (first, second) = (self.starts[0]?, self.starts[1]?);
+        Some((signed_nanos(second, first) as f64 / TICK.as_nanos() as f64).round() as i64)
+    }
+
+    fn matched_offset(&self) -> Option<i64> {
+        let mut samples: Vec<i64> = self.samples.iter().copied().collect();
+        samples.sort_unstable();
+        samples.get(samples.len() / 2).copied()
+    }
+
+    /// Pairs the frame with an identical one on the other channel, if one arrived close enough;
+    /// of several, the one nearest the current estimate, as periodic frames often repeat.
+    fn observe(&mut self, channel: u32, frame: &Frame, ticks: u64, received: Instant) {
+        for recent in &mut self.recent {
+            while recent.front().is_some_and(|&(_, _, at)| received.saturating_duration_since(at) > MATCH_WINDOW) {
+                recent.pop_front();
+            }
+        }
+        let content = content(frame);
+        let difference = |other: u64| match channel {
+            0 => ticks as i64 - other as i64,
+            _ => other as i64 - ticks as i64,
+        };
+        let estimate = self.offset();
+        let other = &mut self.recent[1 - channel as usize];
+        let best = other
+            .iter()
+            .enumerate()
+            .filter(|(_, (other, _, at))| *other == content && signed_nanos(received, *at).unsigned_abs() <= MATCH_WINDOW.as_nanos() as u64)
+            .min_by_key(|(_, &(_, other_ticks, at))| match estimate {
+                Some(offset) => (difference(other_ticks) - offset).unsigned_abs(),
+                None => signed_nanos(received, at).unsigned_abs(),
+            })
+            .map(|(index, _)| index);
+        match best.and_then(|index| other.remove(index)) {
+            Some((_, other_ticks, _)) => {
+                if self.samples.len() == MATCH_SAMPLES {
+                    self.samples.pop_front();
+                }
+                self.samples.push_back(difference(other_ticks));
+                self.matched += 1;
+            }
+            None => self.recent[channel as usize].push_back((content, ticks, received)),
+        }
+    }
+}
+
+fn content(frame: &Frame) -> Content {
+    let mut data = [0; 8];
+    data[..frame.data().len()].copy_from_slice(frame.data());
+    (frame.id(), frame.is_remote(), frame.dlc(), data)
+}
+
+fn shift_wall(time: SystemTime, nanos: i64) -> SystemTime {
+    let by = Duration::from_nanos(nanos.unsigned_abs());
+    match nanos >= 0 {
+        true => time + by,
+        false => time.checked_sub(by).unwrap_or(time),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// CAN2's counter starts this many ticks (12.3 ms) after CAN1's.
+    const OFFSET: u64 = 123;
+
+    /// An adapter whose two counters started [`OFFSET`] apart, and the host's view of it.
+    struct Bus {
+        base: Instant,
+        wall: SystemTime,
+    }
+
+    impl Bus {
+        fn new() -> Self {
+            Self { base: Instant::now(), wall: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000) }
+        }
+
+        /// When CAN1's counter read `ticks`.
+        fn at(&self, ticks: u64) -> Instant {
+            self.base + TICK * ticks as u32
+        }
+
+        /// Host times of the two starts, CAN2's reported `late` ticks after it really happened,
+        /// as USB latency does.
+        fn start(&self, alignment: &mut ChannelAlignment, late: u64) {
+            alignment.started(0, self.base);
+            alignment.started(1, self.at(OFFSET + late));
+        }
+
+        /// A frame on CAN1 at its counter's `ticks`, converted by CAN1's own clock.
+        fn can1(&self, alignment: &mut ChannelAlignment, frame: &Frame, ticks: u64) -> Instant {
+            let time = (self.wall + TICK * ticks as u32, self.at(ticks));
+            assert_eq!(alignment.align(0, frame, ticks, time, self.at(ticks) + MATCH_WINDOW / 10), time, "CAN1 is the reference");
+            time.1
+        }
+
+        /// A frame on CAN2 at CAN1's `ticks`, converted by CAN2's own clock as if its counter had
+        /// started with CAN1's, and with the wall time an hour out, so what isn't aligned shows.
+        fn can2(&self, alignment: &mut ChannelAlignment, frame: &Frame, ticks: u64) -> (SystemTime, Instant) {
+            let own = ticks - OFFSET;
+            let time = (self.wall + TICK * own as u32 + Duration::from_secs(3600), self.at(own));
+            alignment.align(1, frame, own, time, self.at(ticks) + MATCH_WINDOW / 10)
+        }
+    }
+
+    fn frame(id: u16, byte: u8) -> Frame {
+        Frame::new(Id::Standard(id), &[byte]).unwrap()
+    }
+
+    #[test]
+    fn start_times_put_can2_on_can1s_clock() {
+        let (bus, mut alignment) = (Bus::new(), ChannelAlignment::new(AlignmentMode::Start));
+        bus.start(&mut alignment, 0);
+        bus.can1(&mut alignment, &frame(0x100, 0), 1000);
+        for ticks in [1001, 5000, 5001, 90_000] {
+            let aligned = bus.can2(&mut alignment, &frame(0x200, 0), ticks);
+            assert_eq!(aligned, (bus.wall + TICK * ticks as u32, bus.at(ticks)), "{ticks}");
+        }
+        // CAN1 moving on doesn't move CAN2's times.
+        bus.can1(&mut alignment, &frame(0x100, 1), 95_000);
+        assert_eq!(bus.can2(&mut alignment, &frame(0x200, 1), 95_010).1, bus.at(95_010));
+
+        let report = alignment.report();
+        assert_eq!(report, AlignmentReport { start_offset_us: Some(12_300), ..AlignmentReport::default() });
+    }
+
+    #[test]
+    fn can2_is_left_alone_until_there_is_an_offset_and_a_reference() {
+        let (bus, mut alignment) = (Bus::new(), ChannelAlignment::new(AlignmentMode::Start));
+        let own = |ticks: u64| bus.at(ticks - OFFSET);
+        assert_eq!(bus.can2(&mut alignment, &frame(0x200, 0), 1000).1, own(1000), "nothing started");
+        alignment.started(0, bus.base);
+        bus.can1(&mut alignment, &frame(0x100, 0), 1000);
+        assert_eq!(bus.can2(&mut alignment, &frame(0x200, 0), 1001).1, own(1001), "CAN2 not started");
+        alignment.started(1, bus.at(OFFSET));
+        assert_eq!(bus.can2(&mut alignment, &frame(0x200, 0), 1002).1, bus.at(1002));
+
+        // Restarting CAN1 restarts its counter: no reference until it receives again.
+        alignment.started(0, bus.base);
+        assert_eq!(bus.can2(&mut alignment, &frame(0x200, 0), 1003).1, own(1003));
+        bus.can1(&mut alignment, &frame(0x100, 1), 1004);
+        assert_eq!(bus.can2(&mut alignment, &frame(0x200, 1), 1005).1, bus.at(1005));
+        assert_eq!(alignment.report().start_offset_us, Some(12_300));
+    }
+
+    #[test]
+    fn frames_on_both_channels_correct_a_late_start() {
+        let (bus, mut alignment) = (Bus::new(), ChannelAlignment::new(AlignmentMode::Frames));
+        // The host saw CAN2 start 3.7 ms late, so the start times alone are that far out.
+        bus.start(&mut alignment, 37);
+        bus.can1(&mut alignment, &frame(0x100, 0), 1000);
+        assert_eq!(bus.can2(&mut alignment, &frame(0x200, 0), 1500).1, bus.at(1537));
+        assert_eq!(alignment.report().matched_offset_us, None, "different frames don't match");
+
+        // Both ports on one bus: every frame shows up on both, CAN1's first.
+        for n in 0..10u8 {
+            let ticks = 2000 + 100 * u64::from(n);
+            bus.can1(&mut alignment, &frame(0x300, n), ticks);
+            assert_eq!(bus.can2(&mut alignment, &frame(0x300, n), ticks).1, bus.at(ticks), "frame {n}");
+        }
+        // In either order.
+        let early = bus.can2(&mut alignment, &frame(0x301, 0), 4000);
+        bus.can1(&mut alignment, &frame(0x301, 0), 4000);
+        assert_eq!(early.1, bus.at(4000));
+
+        let report = alignment.report();
+        assert_eq!((report.start_offset_us, report.matched_offset_us, report.matched), (Some(16_000), Some(12_300), 11));
+        assert_eq!((report.residual_mean_us, report.residual_max_us), (Some(0.0), Some(0)));
+    }
+
+    #[test]
+    fn jitter_shows_as_residual_skew() {
+        let (bus, mut alignment) = (Bus::new(), ChannelAlignment::new(AlignmentMode::Frames));
+        bus.start(&mut alignment, 0);
+        // CAN2 stamps one frame in five a tick late and one a tick early.
+        for n in 0..50u64 {
+            let ticks = 10_000 + 50 * n;
+            let data = frame(0x400, n as u8);
+            bus.can1(&mut alignment, &data, ticks);
+            let jitter = [0, 0, 1, 0, -1][n as usize % 5];
+            bus.can2(&mut alignment, &data, ticks.saturating_add_signed(jitter));
+        }
+        let report = alignment.report();
+        assert_eq!((report.matched_offset_us, report.matched), (Some(12_300), 50), "the median ignores the jitter");
+        assert_eq!((report.residual_mean_us, report.residual_max_us), (Some(40.0), Some(100)));
+    }
+
+    #[test]
+    fn repeated_frames_pair_with_the_nearest_to_the_estimate() {
+        let (bus, mut alignment) = (Bus::new(), ChannelAlignment::new(AlignmentMode::Frames));
+        bus.start(&mut alignment, 0);
+        // The same periodic frame every 2 ms, well within the match window of each other.
+        let periodic = frame(0x500, 0xAA);
+        for ticks in (20_000..20_200).step_by(20) {
+            bus.can1(&mut alignment, &periodic, ticks);
+        }
+        for ticks in (20_000..20_200).step_by(20) {
+            assert_eq!(bus.can2(&mut alignment, &periodic, ticks).1, bus.at(ticks));
+        }
+        let report = alignment.report();
+        assert_eq!((report.matched_offset_us, report.matched, report.residual_max_us), (Some(12_300), 10, Some(0)));
+    }
+
+    #[test]
+    fn can2_times_never_go_backwards_when_the_estimate_moves() {
+        let (bus, mut alignment) = (Bus::new(), ChannelAlignment::new(AlignmentMode::Frames));
+        // 5 ms late: CAN2's times are 5 ms ahead until the first match, then drop back.
+        bus.start(&mut alignment, 50);
+        bus.can1(&mut alignment, &frame(0x100, 0), 1000);
+        let ahead = bus.can2(&mut alignment, &frame(0x200, 0), 1010);
+        assert_eq!(ahead.1, bus.at(1060));
+        bus.can1(&mut alignment, &frame(0x300, 0), 1020);
+        let held = bus.can2(&mut alignment, &frame(0x300, 0), 1020);
+        assert_eq!(held, ahead, "held rather than stepping back");
+        assert_eq!(bus.can2(&mut alignment, &frame(0x200, 1), 1100).1, bus.at(1100));
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    time::{Duration, SystemTime},
+};
+
+use crate::frame::Frame;
+use crate::sink::{Direction, FrameSink};
+use crate::timestamp::{host_time, CivilTime};
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Formats a time the way Vector headers do: `Thu Oct 14 10:00:00.000 am 2026` (UTC).
+fn format_asc_date(time: SystemTime) -> String {
+    let t = CivilTime::from_system_time(time);
+    let (hour12, meridiem) = match t.hour {
+        0 => (12, "am"),
+        1..=11 => (t.hour, "am"),
+        12 => (12, "pm"),
+        _ => (t.hour - 12, "pm"),
+    };
+    format!(
+        "{} {} {:02} {:02}:{:02}:{:02}.{:03} {} {}",
+        WEEKDAYS[t.weekday as usize],
+        MONTHS[t.month as usize - 1],
+        t.day,
+        hour12,
+        t.minute,
+        t.second,
+        t.millis,
+        meridiem,
+        t.year
+    )
+}
+
+/// Formats one ASC frame line. `offset` is the time since the start of measurement; channels
+/// are numbered from 1 as in CANoe.
+pub fn format_asc_line(offset: Duration, channel: u32, frame: &Frame, direction: Direction) -> String {
+    let id = if frame.is_extended() {
+        format!("{:X}x", frame.id().raw())
+    } else {
+        format!("{:X}", frame.id().raw())
+    };
+    let dir = match direction {
+        Direction::Rx => "Rx",
+        Direction::Tx => "Tx",
+    };
+    let mut line = format!("{:>11.6} {:<2} {:<15} {:<4} ", offset.as_secs_f64(), channel + 1, id, dir);
+    if frame.is_remote() {
+        let _ = write!(line, "r {:X}", frame.dlc());
+    } else {
+        let _ = write!(line, "d {:X}", frame.dlc());
+        for byte in frame.data() {
+            let _ = write!(line, " {byte:02X}");
+        }
+    }
+    line
+}
+
+/// Vector ASC writer: hex base, timestamps relative to the start of measurement.
+pub struct AscWriter<W: Write> {
+    out: W,
+    start: SystemTime,
+    header_written: bool,
+}
+
+impl<W: Write> AscWriter<W> {
+    /// Starts a measurement now.
+    pub fn new(out: W) -> Self {
+        Self::with_start(out, SystemTime::now())
+    }
+
+    pub fn with_start(out: W, start: SystemTime) -> Self {
+        Self {
+            out,
+            start,
+            header_written: false,
+        }
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let date = format_asc_date(self.start);
+        writeln!(self.out, "date {date}")?;
+        writeln!(self.out, "base hex  timestamps absolute")?;
+        writeln!(self.out, "internal events logged")?;
+        writeln!(self.out, "// version 9.0.0")?;
+        writeln!(self.out, "Begin Triggerblock {date}")?;
+        writeln!(self.out, "{:>11.6} Start of measurement", 0.0)?;
+        self.header_written = true;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> FrameSink for AscWriter<W> {
+    fn write_frame(&mut self, channel: u32, frame: &Frame, direction: Direction) -> io::Result<()> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+        let time = host_time(frame);
+        let offset = time.duration_since(self.start).unwrap_or_default();
+        writeln!(self.out, "{}", format_asc_line(offset, channel, frame, direction))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+        writeln!(self.out, "End TriggerBlock")?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Instant, UNIX_EPOCH};
+
+    use super::*;
+    use crate::id::Id;
+
+    /// Wed Oct 14 10:00:00 2026 UTC.
+    const START: u64 = 1_791_972_000;
+
+    const GOLDEN: &str = "\
+date Wed Oct 14 10:00:00.000 am 2026
+base hex  timestamps absolute
+internal events logged
+// version 9.0.0
+Begin Triggerblock Wed Oct 14 10:00:00.000 am 2026
+   0.000000 Start of measurement
+   0.001000 1  123             Rx   d 3 11 22 33
+   0.012500 2  18FF50E5x       Tx   d 8 01 02 03 04 05 06 07 08
+   1.500000 1  7FF             Rx   r 8
+  12.345600 2  1x              Rx   d 0
+ 100.000000 1  0               Tx   d 1 FF
+End TriggerBlock
+";
+
+    fn received(frame: Frame, offset: Duration) -> Frame {
+        Frame { host_time: Some((UNIX_EPOCH + Duration::from_secs(START) + offset, Instant::now())), ..frame }
+    }
+
+    fn frames() -> Vec<(Duration, u32, Frame, Direction)> {
+        vec![
+            (Duration::from_millis(1), 0, Frame::new(Id::Standard(0x123), &[0x11, 0x22, 0x33]).unwrap(), Direction::Rx),
+            (Duration::from_micros(12_500), 1, Frame::new(Id::Extended(0x18FF_50E5), &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap(), Direction::Tx),
+            (Duration::from_millis(1_500), 0, Frame::remote(Id::Standard(0x7FF), 8).unwrap(), Direction::Rx),
+            (Duration::from_micros(12_345_600), 1, Frame::new(Id::Extended(1), &[]).unwrap(), Direction::Rx),
+            (Duration::from_secs(100), 0, Frame::new(Id::Standard(0), &[0xFF]).unwrap(), Direction::Tx),
+        ]
+    }
+
+    fn write(frames: &[(Duration, u32, Frame, Direction)]) -> String {
+        let mut writer = AscWriter::with_start(Vec::new(), UNIX_EPOCH + Duration::from_secs(START));
+        for &(offset, channel, frame, direction) in frames {
+            writer.write_frame(channel, &received(frame, offset), direction).unwrap();
+        }
+        writer.finish().unwrap();
+        String::from_utf8(writer.out).unwrap()
+    }
+
+    /// Reads a frame line back the way CANoe would, for the round trip.
+    fn parse_line(line: &str) -> (Duration, u32, Frame, Direction) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let offset = Duration::from_secs_f64(fields[0].parse().unwrap());
+        let channel = fields[1].parse::<u32>().unwrap() - 1;
+        let id = match fields[2].strip_suffix('x') {
+            Some(id) => Id::Extended(u32::from_str_radix(id, 16).unwrap()),
+            None => Id::Standard(u16::from_str_radix(fields[2], 16).unwrap()),
+        };
+        let direction = match fields[3] {
+            "Rx" => Direction::Rx,
+            "Tx" => Direction::Tx,
+            other => panic!("direction {other}"),
+        };
+        let dlc = u8::from_str_radix(fields[5], 16).unwrap();
+        let frame = match fields[4] {
+            "r" => Frame::remote(id, dlc).unwrap(),
+            _ => {
+                let data: Vec<u8> = fields[6..].iter().map(|byte| u8::from_str_radix(byte, 16).unwrap()).collect();
+                assert_eq!(data.len(), dlc as usize, "{line}");
+                Frame::new(id, &data).unwrap()
+            }
+        };
+        (offset, channel, frame, direction)
+    }
+
+    #[test]
+    fn writes_the_golden_file() {
+        assert_eq!(write(&frames()), GOLDEN);
+    }
+
+    #[test]
+    fn frame_lines_read_back_as_written() {
+        let written = write(&frames());
+        let lines: Vec<&str> = written.lines().skip(6).take_while(|line| !line.starts_with("End")).collect();
+        assert_eq!(lines.len(), frames().len());
+        for (line, (offset, channel, frame, direction)) in lines.into_iter().zip(frames()) {
+            let (read_offset, read_channel, read_frame, read_direction) = parse_line(line);
+            assert!(read_offset.abs_diff(offset) < Duration::from_micros(1), "{line}");
+            assert_eq!((read_channel, read_frame, read_direction), (channel, frame, direction), "{line}");
+        }
+    }
+
+    #[test]
+    fn empty_measurements_still_have_a_header_and_trailer() {
+        let written = write(&[]);
+        assert_eq!(written.lines().count(), 7);
+        assert!(written.starts_with("date Wed Oct 14 10:00:00.000 am 2026\n"));
+        assert!(written.ends_with("Start of measurement\nEnd TriggerBlock\n"));
+    }
+
+    #[test]
+    fn frames_from_before_the_start_are_at_zero() {
+        let mut writer = AscWriter::with_start(Vec::new(), UNIX_EPOCH + Duration::from_secs(START + 1));
+        writer.write_frame(0, &received(Frame::new(Id::Standard(1), &[]).unwrap(), Duration::ZERO), Direction::Rx).unwrap();
+        let written = String::from_utf8(writer.out).unwrap();
+        assert!(written.lines().last().unwrap().starts_with("   0.000000 1  1 "), "{written}");
+    }
+
+    #[test]
+    fn header_dates_use_a_twelve_hour_clock() {
+        let date = |secs: u64, millis: u64| format_asc_date(UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis));
+        assert_eq!(date(START, 0), "Wed Oct 14 10:00:00.000 am 2026");
+        assert_eq!(date(1_767_569_405, 250), "Sun Jan 04 11:30:05.250 pm 2026");
+        assert_eq!(date(0, 0), "Thu Jan 01 12:00:00.000 am 1970");
+        assert_eq!(date(43_200 + 59, 999), "Thu Jan 01 12:00:59.999 pm 1970");
+    }
+}
@@ -0,0 +1,121 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bitrate::Bitrate;
+use crate::device::Channel;
+use crate::error::CanError;
+use crate::ffi::VciInitConfig;
+use crate::mode::ChannelMode;
+use crate::reference::RefType;
+use crate::status::ErrorFlags;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Outcome of [`AutoBaud::detect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaudDetection {
+    /// Frames arrived at this rate without bus errors.
+    Detected(Bitrate),
+    /// Nothing arrived and no errors were seen at any rate: the bus looks idle.
+    NoTraffic,
+    /// There was activity, but no candidate rate received it cleanly.
+    NoMatch,
+}
+
+/// Listen-only bitrate probing: the channel is initialized at each candidate rate in turn and
+/// watched for `window`. The first rate that receives frames while the error counters and bus
+/// error flags stay quiet wins. Passes repeat until `timeout`, so intermittent traffic is still
+/// caught.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutoBaud {
+    pub candidates: Vec<Bitrate>,
+    pub window: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for AutoBaud {
+    /// Common rates first, 300 ms per rate, 10 s overall.
+    fn default() -> Self {
+        Self {
+            candidates: vec![
+                Bitrate::Kbps500,
+                Bitrate::Kbps250,
+                Bitrate::Kbps125,
+                Bitrate::Mbps1,
+                Bitrate::Kbps100,
+                Bitrate::Kbps50,
+                Bitrate::Kbps800,
+                Bitrate::Kbps666,
+                Bitrate::Kbps400,
+                Bitrate::Kbps200,
+                Bitrate::Kbps80,
+                Bitrate::Kbps40,
+                Bitrate::Kbps20,
+                Bitrate::Kbps10,
+                Bitrate::Kbps5,
+            ],
+            window: Duration::from_millis(300),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// What one probe window saw.
+struct Probe {
+    frames: usize,
+    errors: bool,
+}
+
+impl AutoBaud {
+    /// Probes `channel` using `config` for everything but the timing and mode. The channel is
+    /// left in reset mode; initialize it again with the detected rate.
+    pub fn detect(&self, channel: &Channel, config: &VciInitConfig) -> Result<BaudDetection, CanError> {
+        if self.candidates.is_empty() {
+            return Ok(BaudDetection::NoTraffic);
+        }
+        let start = Instant::now();
+        let mut activity = false;
+        loop {
+            for &bitrate in &self.candidates {
+                let probe = self.probe(channel, config, bitrate)?;
+                if probe.frames > 0 && !probe.errors {
+                    return Ok(BaudDetection::Detected(bitrate));
+                }
+                activity |= probe.frames > 0 || probe.errors;
+                if start.elapsed() >= self.timeout {
+                    return Ok(if activity { BaudDetection::NoMatch } else { BaudDetection::NoTraffic });
+                }
+            }
+        }
+    }
+
+    fn probe(&self, channel: &Channel, config: &VciInitConfig, bitrate: Bitrate) -> Result<Probe, CanError> {
+        let (timing0, timing1) = bitrate.timing();
+        let config = VciInitConfig { timing0, timing1, ..*config }.with_mode(ChannelMode::ListenOnly);
+        let _ = channel.reset();
+        if let Some(reference) = RefType::for_bitrate(bitrate) {
+            channel.set_reference(&reference)?;
+        }
+        channel.init(&config)?;
+        channel.start()?;
+        channel.clear_buffer()?;
+        channel.error_info()?;
+        let baseline = channel.status()?.rx_error_counter;
+
+        let mut probe = Probe { frames: 0, errors: false };
+        let until = Instant::now() + self.window;
+        while Instant::now() < until {
+            if channel.pending()? > 0 {
+                probe.frames += channel.receive_batch(100, Duration::ZERO)?.len();
+            }
+            let flags = channel.error_info()?.flags;
+            if flags.contains(ErrorFlags::BUS_ERROR) || flags.contains(ErrorFlags::ERROR_PASSIVE) {
+                probe.errors = true;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        probe.errors |= channel.status()?.rx_error_counter > baseline;
+        channel.reset()?;
+        Ok(probe)
+    }
+}
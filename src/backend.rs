@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use crate::ffi::{CanLibrary, VciBoardInfo, VciCanObj, VciCanStatus, VciErrInfo, VciInitConfig};
+use crate::mock::MockBackend;
+
+/// What a [`Device`](crate::Device) talks to: the vendor DLL, or a [`MockBackend`] for running
+/// without hardware. Made from either with `into()`; the VCI calls behind it stay inside the
+/// crate, so the raw structures the DLL fills in never reach callers unchecked.
+#[derive(Clone)]
+pub struct Backend(pub(crate) Arc<dyn CanBackend>);
+
+impl From<Arc<CanLibrary>> for Backend {
+    fn from(library: Arc<CanLibrary>) -> Self {
+        Self(library)
+    }
+}
+
+impl From<Arc<MockBackend>> for Backend {
+    fn from(mock: Arc<MockBackend>) -> Self {
+        Self(mock)
+    }
+}
+
+/// The VCI calls a [`Device`](crate::Device) is built on, so it can run against something other
+/// than the vendor DLL.
+///
+/// Methods mirror the `VCI_*` functions and return their raw codes: 1 for success, 0 for
+/// failure and -1 for a device error, or a frame count for `transmit`, `receive` and
+/// `get_receive_num`.
+pub(crate) trait CanBackend: Send + Sync {
+    fn open_device(&self, dev_type: u32, dev_index: u32) -> i32;
+    fn close_device(&self, dev_type: u32, dev_index: u32) -> i32;
+    fn usb_device_reset(&self, dev_type: u32, dev_index: u32) -> Option<i32>;
+    /// Fills `infos` with the attached adapters and returns how many there are.
+    fn find_usb_devices(&self, infos: &mut [VciBoardInfo]) -> Option<i32>;
+    fn read_board_info(&self, dev_type: u32, dev_index: u32, info: &mut VciBoardInfo) -> Option<i32>;
+    fn init_can(&self, dev_type: u32, dev_index: u32, can_index: u32, config: &VciInitConfig) -> i32;
+    fn start_can(&self, dev_type: u32, dev_index: u32, can_index: u32) -> i32;
+    fn reset_can(&self, dev_type: u32, dev_index: u32, can_index: u32) -> Option<i32>;
+    fn clear_buffer(&self, dev_type: u32, dev_index: u32, can_index: u32) -> Option<i32>;
+    fn transmit(&self, dev_type: u32, dev_index: u32, can_index: u32, frames: &[VciCanObj]) -> i32;
+    /// Waits up to `wait_ms` for frames and returns how many were written to `frames`.
+    fn receive(&self, dev_type: u32, dev_index: u32, can_index: u32, frames: &mut [VciCanObj], wait_ms: i32) -> i32;
+    fn get_receive_num(&self, dev_type: u32, dev_index: u32, can_index: u32) -> Option<i32>;
+    fn read_err_info(&self, dev_type: u32, dev_index: u32, can_index: u32, info: &mut VciErrInfo) -> Option<i32>;
+    fn read_can_status(&self, dev_type: u32, dev_index: u32, can_index: u32, status: &mut VciCanStatus) -> Option<i32>;
+    fn set_reference(&self, dev_type: u32, dev_index: u32, can_index: u32, ref_type: u32, data: &mut [u8]) -> Option<i32>;
+}
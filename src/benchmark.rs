@@ -0,0 +1,131 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::busload::frame_bits;
+use crate::device::Channel;
+use crate::error::CanError;
+use crate::frame::Frame;
+use crate::id::Id;
+
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(10);
+const RECEIVE_BATCH: usize = 2500;
+/// How long the receiver keeps counting after the sender stops.
+const DRAIN: Duration = Duration::from_millis(200);
+
+/// Back-to-back transmit of `dlc`-byte frames for `duration`, `batch` frames per
+/// [`Channel::transmit_all`] call, counting on the other channel what reached the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Benchmark {
+    pub id: Id,
+    pub dlc: u8,
+    pub duration: Duration,
+    pub batch: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchmarkResult {
+    pub dlc: u8,
+    /// Time spent transmitting.
+    pub elapsed: Duration,
+    /// Frames offered to the DLL.
+    pub offered: u64,
+    /// Frames the DLL accepted.
+    pub accepted: u64,
+    /// `transmit_all` calls that took fewer frames than offered.
+    pub short_batches: u64,
+    /// Frames seen on the receiving channel.
+    pub received: u64,
+    /// Worst-case length of one test frame, stuff bits included.
+    pub frame_bits: u32,
+}
+
+impl Benchmark {
+    /// Runs the benchmark, sending on `tx` and counting on `rx`. Clearing `running` ends it
+    /// early.
+    pub fn run(&self, tx: &Channel, rx: &Channel, running: &AtomicBool) -> Result<BenchmarkResult, CanError> {
+        let data = [0x55u8; 8];
+        let frame = Frame::new(self.id, &data[..self.dlc.min(8) as usize]).expect("at most 8 bytes");
+        let batch = vec![frame; self.batch.max(1)];
+        let sending = AtomicBool::new(true);
+
+        thread::scope(|scope| {
+            let receiver = scope.spawn(|| self.count_received(rx, running, &sending));
+            let start = Instant::now();
+            let (mut offered, mut accepted, mut short_batches) = (0, 0, 0);
+            let mut outcome = Ok(());
+            while running.load(Ordering::SeqCst) && start.elapsed() < self.duration {
+                match tx.transmit_all(&batch) {
+                    Ok(sent) => {
+                        offered += batch.len() as u64;
+                        accepted += sent as u64;
+                        if sent < batch.len() {
+                            short_batches += 1;
+                            thread::yield_now();
+                        }
+                    }
+                    Err(err) => {
+                        outcome = Err(err);
+                        break;
+                    }
+                }
+            }
+            let elapsed = start.elapsed();
+            sending.store(false, Ordering::SeqCst);
+            let received = receiver.join().expect("benchmark receiver panicked");
+            outcome?;
+            Ok(BenchmarkResult {
+                dlc: frame.dlc(),
+                elapsed,
+                offered,
+                accepted,
+                short_batches,
+                received: received?,
+                frame_bits: frame_bits(&frame),
+            })
+        })
+    }
+
+    fn count_received(&self, rx: &Channel, running: &AtomicBool, sending: &AtomicBool) -> Result<u64, CanError> {
+        let mut received = 0;
+        let mut done_at = None;
+        while running.load(Ordering::SeqCst) {
+            if done_at.is_none() && !sending.load(Ordering::SeqCst) {
+                done_at = Some(Instant::now());
+            }
+            if done_at.is_some_and(|at| at.elapsed() >= DRAIN) {
+                break;
+            }
+            let frames = rx.receive_pending(RECEIVE_BATCH, RECEIVE_TIMEOUT)?;
+            received += frames.iter().filter(|frame| frame.id() == self.id).count() as u64;
+        }
+        Ok(received)
+    }
+}
+
+impl BenchmarkResult {
+    /// Frames per second that reached the bus.
+    pub fn frames_per_sec(&self) -> f64 {
+        per_sec(self.received, self.elapsed)
+    }
+
+    /// Frames per second the DLL accepted.
+    pub fn accepted_per_sec(&self) -> f64 {
+        per_sec(self.accepted, self.elapsed)
+    }
+
+    /// Share of the bus the received frames occupied, in percent of `bps`.
+    pub fn utilization(&self, bps: u32) -> f64 {
+        if bps == 0 {
+            return 0.0;
+        }
+        self.frames_per_sec() * f64::from(self.frame_bits) * 100.0 / f64::from(bps)
+    }
+}
+
+fn per_sec(count: u64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+    count as f64 / elapsed.as_secs_f64()
+}
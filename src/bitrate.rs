@@ -0,0 +1,362 @@
+use std::{fmt, str::FromStr};
+
+/// SJA1000 CAN clock on the CANalyst-II; one time quantum is `(BRP + 1) / CAN_CLOCK`.
+const CAN_CLOCK: u32 = 8_000_000;
+/// Largest relative rate error [`calc_btr`] accepts, in parts per million.
+const MAX_RATE_ERROR_PPM: u64 = 1_000;
+
+/// Standard CANalyst-II bus rates for the SJA1000's 8 MHz CAN clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bitrate {
+    Kbps5,
+    Kbps10,
+    Kbps20,
+    Kbps40,
+    Kbps50,
+    Kbps80,
+    Kbps100,
+    Kbps125,
+    Kbps200,
+    Kbps250,
+    Kbps400,
+    Kbps500,
+    Kbps666,
+    Kbps800,
+    Mbps1,
+    Custom { timing0: u8, timing1: u8 },
+}
+
+impl Bitrate {
+    pub const STANDARD: [Bitrate; 15] = [
+        Bitrate::Kbps5,
+        Bitrate::Kbps10,
+        Bitrate::Kbps20,
+        Bitrate::Kbps40,
+        Bitrate::Kbps50,
+        Bitrate::Kbps80,
+        Bitrate::Kbps100,
+        Bitrate::Kbps125,
+        Bitrate::Kbps200,
+        Bitrate::Kbps250,
+        Bitrate::Kbps400,
+        Bitrate::Kbps500,
+        Bitrate::Kbps666,
+        Bitrate::Kbps800,
+        Bitrate::Mbps1,
+    ];
+
+    /// BTR0/BTR1 register values, as written to `VciInitConfig::timing0`/`timing1`.
+    pub fn timing(&self) -> (u8, u8) {
+        match *self {
+            Bitrate::Kbps5 => (0xBF, 0xFF),
+            Bitrate::Kbps10 => (0x31, 0x1C),
+            Bitrate::Kbps20 => (0x18, 0x1C),
+            Bitrate::Kbps40 => (0x87, 0xFF),
+            Bitrate::Kbps50 => (0x09, 0x1C),
+            Bitrate::Kbps80 => (0x83, 0xFF),
+            Bitrate::Kbps100 => (0x04, 0x1C),
+            Bitrate::Kbps125 => (0x03, 0x1C),
+            Bitrate::Kbps200 => (0x81, 0xFA),
+            Bitrate::Kbps250 => (0x01, 0x1C),
+            Bitrate::Kbps400 => (0x80, 0xFA),
+            Bitrate::Kbps500 => (0x00, 0x1C),
+            Bitrate::Kbps666 => (0x80, 0xB6),
+            Bitrate::Kbps800 => (0x00, 0x16),
+            Bitrate::Mbps1 => (0x00, 0x14),
+            Bitrate::Custom { timing0, timing1 } => (timing0, timing1),
+        }
+    }
+
+    /// The standard rate using these register values, or `Custom` if none does.
+    pub fn from_timing(timing0: u8, timing1: u8) -> Bitrate {
+        Bitrate::STANDARD
+            .into_iter()
+            .find(|rate| rate.timing() == (timing0, timing1))
+            .unwrap_or(Bitrate::Custom { timing0, timing1 })
+    }
+
+    /// Rate the register values actually produce, in bits per second.
+    pub fn actual_bps(&self) -> f64 {
+        let (timing0, timing1) = self.timing();
+        let (brp, quanta) = (u32::from(timing0 & 0x3F) + 1, bit_quanta(timing1));
+        f64::from(CAN_CLOCK) / f64::from(brp * quanta)
+    }
+
+    /// Sample point of the register values, in permille of the bit time.
+    pub fn sample_point_permille(&self) -> u32 {
+        let timing1 = self.timing().1;
+        let tseg1 = u32::from(timing1 & 0x0F) + 1;
+        (1 + tseg1) * 1000 / bit_quanta(timing1)
+    }
+
+    /// Whether some firmware only applies this rate when it is also passed through
+    /// `VCI_SetReference` (see [`RefType::for_bitrate`](crate::RefType::for_bitrate)).
+    pub fn requires_reference(&self) -> bool {
+        matches!(self, Bitrate::Kbps666)
+    }
+
+    /// Nominal rate in bits per second; `None` for custom timings.
+    pub fn bps(&self) -> Option<u32> {
+        Some(match self {
+            Bitrate::Kbps5 => 5_000,
+            Bitrate::Kbps10 => 10_000,
+            Bitrate::Kbps20 => 20_000,
+            Bitrate::Kbps40 => 40_000,
+            Bitrate::Kbps50 => 50_000,
+            Bitrate::Kbps80 => 80_000,
+            Bitrate::Kbps100 => 100_000,
+            Bitrate::Kbps125 => 125_000,
+            Bitrate::Kbps200 => 200_000,
+            Bitrate::Kbps250 => 250_000,
+            Bitrate::Kbps400 => 400_000,
+            Bitrate::Kbps500 => 500_000,
+            Bitrate::Kbps666 => 666_000,
+            Bitrate::Kbps800 => 800_000,
+            Bitrate::Mbps1 => 1_000_000,
+            Bitrate::Custom { .. } => return None,
+        })
+    }
+}
+
+/// Time quanta per bit: sync segment plus TSEG1 and TSEG2 from BTR1.
+fn bit_quanta(timing1: u8) -> u32 {
+    1 + u32::from(timing1 & 0x0F) + 1 + u32::from((timing1 >> 4) & 0x07) + 1
+}
+
+/// No SJA1000 bit timing reaches the requested rate closely enough.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BtrError {
+    pub requested: u32,
+    /// Closest rate that can be produced, in bits per second.
+    pub nearest: u32,
+}
+
+impl fmt::Display for BtrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bps can't be produced from the 8 MHz CAN clock, nearest achievable rate is {} bps",
+            self.requested, self.nearest
+        )
+    }
+}
+
+impl std::error::Error for BtrError {}
+
+/// BTR0/BTR1 for an arbitrary rate. Searches every prescaler and segment split with 8 to 25
+/// quanta per bit, keeps those within 0.1% of `bitrate_bps`, and picks the one whose sample
+/// point is closest to `sample_point_permille` (875 = 87.5%), preferring more quanta per bit on
+/// ties. SJW is one quantum and sampling single. A standard rate asked for at its table sample
+/// point gets its table values, wider SJW and triple sampling included; 666k's would be 0.1%
+/// off otherwise.
+pub fn calc_btr(bitrate_bps: u32, sample_point_permille: u16) -> Result<(u8, u8), BtrError> {
+    let table = Bitrate::STANDARD
+        .into_iter()
+        .find(|rate| rate.bps() == Some(bitrate_bps) && rate.sample_point_permille() == u32::from(sample_point_permille));
+    if let Some(rate) = table {
+        return Ok(rate.timing());
+    }
+    let target = u64::from(bitrate_bps.max(1));
+    let mut best = None;
+    let mut best_score = (u64::MAX, u64::MAX, u32::MAX);
+    let mut nearest = u64::MAX;
+    for brp in 1..=64u32 {
+        for tseg1 in 1..=16u32 {
+            for tseg2 in 2..=8u32 {
+                let quanta = 1 + tseg1 + tseg2;
+                if quanta < 8 {
+                    continue;
+                }
+                let divisor = u64::from(brp * quanta);
+                let rate = (u64::from(CAN_CLOCK) + divisor / 2) / divisor;
+                if target.abs_diff(rate) < target.abs_diff(nearest) {
+                    nearest = rate;
+                }
+                let error_ppm = (u64::from(CAN_CLOCK) * 1_000_000).abs_diff(target * divisor * 1_000_000) / (target * divisor);
+                if error_ppm > MAX_RATE_ERROR_PPM {
+                    continue;
+                }
+                let sample_point = u64::from((1 + tseg1) * 1000 / quanta);
+                let score = (sample_point.abs_diff(u64::from(sample_point_permille)), error_ppm, 25 - quanta);
+                if score < best_score {
+                    let timing0 = (brp - 1) as u8;
+                    let timing1 = (((tseg2 - 1) << 4) | (tseg1 - 1)) as u8;
+                    best = Some((timing0, timing1));
+                    best_score = score;
+                }
+            }
+        }
+    }
+    best.ok_or(BtrError { requested: bitrate_bps, nearest: nearest as u32 })
+}
+
+impl fmt::Display for Bitrate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.bps() {
+            Some(1_000_000) => write!(f, "1M"),
+            Some(bps) => write!(f, "{}k", bps / 1000),
+            None => {
+                let (timing0, timing1) = self.timing();
+                write!(f, "custom (BTR0=0x{timing0:02X}, BTR1=0x{timing1:02X})")
+            }
+        }
+    }
+}
+
+impl FromStr for Bitrate {
+    type Err = String;
+
+    /// Accepts `250k`, `1M`, `1000k`, `83.333k` or a plain bits-per-second value such as
+    /// `500000`. Rates outside the standard table get a timing from [`calc_btr`] with an 87.5%
+    /// sample point.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_ascii_lowercase();
+        let (number, scale) = if let Some(k) = lower.strip_suffix('k') {
+            (k, 1_000.0)
+        } else if let Some(m) = lower.strip_suffix('m') {
+            (m, 1_000_000.0)
+        } else {
+            (lower.as_str(), 1.0)
+        };
+        let bps = number
+            .parse::<f64>()
+            .ok()
+            .map(|n| (n * scale).round())
+            .filter(|bps| (1.0..=f64::from(u32::MAX)).contains(bps))
+            .ok_or_else(|| format!("invalid bitrate '{s}', expected e.g. 250k, 1M or 83333"))? as u32;
+        if let Some(standard) = Bitrate::STANDARD.into_iter().find(|b| b.bps() == Some(bps)) {
+            return Ok(standard);
+        }
+        let (timing0, timing1) = calc_btr(bps, 875).map_err(|err| err.to_string())?;
+        Ok(Bitrate::Custom { timing0, timing1 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::XorShift;
+
+    /// Every standard rate with its BTR0/BTR1 pair from the ControlCAN manual, and the sample
+    /// point in permille those registers give.
+    const TABLE: [(Bitrate, u32, (u8, u8), u32); 15] = [
+        (Bitrate::Kbps5, 5_000, (0xBF, 0xFF), 680),
+        (Bitrate::Kbps10, 10_000, (0x31, 0x1C), 875),
+        (Bitrate::Kbps20, 20_000, (0x18, 0x1C), 875),
+        (Bitrate::Kbps40, 40_000, (0x87, 0xFF), 680),
+        (Bitrate::Kbps50, 50_000, (0x09, 0x1C), 875),
+        (Bitrate::Kbps80, 80_000, (0x83, 0xFF), 680),
+        (Bitrate::Kbps100, 100_000, (0x04, 0x1C), 875),
+        (Bitrate::Kbps125, 125_000, (0x03, 0x1C), 875),
+        (Bitrate::Kbps200, 200_000, (0x81, 0xFA), 600),
+        (Bitrate::Kbps250, 250_000, (0x01, 0x1C), 875),
+        (Bitrate::Kbps400, 400_000, (0x80, 0xFA), 600),
+        (Bitrate::Kbps500, 500_000, (0x00, 0x1C), 875),
+        (Bitrate::Kbps666, 666_000, (0x80, 0xB6), 666),
+        (Bitrate::Kbps800, 800_000, (0x00, 0x16), 800),
+        (Bitrate::Mbps1, 1_000_000, (0x00, 0x14), 750),
+    ];
+
+    #[test]
+    fn standard_rates_match_the_manual() {
+        assert_eq!(Bitrate::STANDARD, TABLE.map(|(rate, ..)| rate));
+        for (rate, bps, timing, sample_point) in TABLE {
+            assert_eq!(rate.timing(), timing, "{rate}");
+            assert_eq!(rate.bps(), Some(bps), "{rate}");
+            assert_eq!(Bitrate::from_timing(timing.0, timing.1), rate, "{rate}");
+            assert_eq!(rate.sample_point_permille(), sample_point, "{rate}");
+            // 666k is really 666.667k, the nearest the 8 MHz clock gets.
+            let tolerance = if rate == Bitrate::Kbps666 { 667.0 } else { 0.0 };
+            assert!((rate.actual_bps() - f64::from(bps)).abs() <= tolerance, "{rate}: {}", rate.actual_bps());
+        }
+    }
+
+    #[test]
+    fn other_register_values_are_custom() {
+        // 500k's prescaler and segments with a wider SJW is not the table's 500k.
+        for (timing0, timing1) in [(0x40, 0x1C), (0x00, 0x9C), (0x00, 0x00), (0xFF, 0xFF)] {
+            let rate = Bitrate::from_timing(timing0, timing1);
+            assert_eq!(rate, Bitrate::Custom { timing0, timing1 });
+            assert_eq!((rate.timing(), rate.bps()), ((timing0, timing1), None));
+        }
+        assert_eq!(Bitrate::Custom { timing0: 0x01, timing1: 0x1C }.timing(), Bitrate::Kbps250.timing());
+    }
+
+    #[test]
+    fn names_parse_back() {
+        for (rate, bps, ..) in TABLE {
+            assert_eq!(rate.to_string().parse::<Bitrate>().unwrap(), rate);
+            assert_eq!(bps.to_string().parse::<Bitrate>().unwrap(), rate);
+        }
+        assert_eq!("1000k".parse::<Bitrate>().unwrap(), Bitrate::Mbps1);
+        assert_eq!(" 125K ".parse::<Bitrate>().unwrap(), Bitrate::Kbps125);
+        assert_eq!(Bitrate::Custom { timing0: 0x05, timing1: 0x1C }.to_string(), "custom (BTR0=0x05, BTR1=0x1C)");
+        for bad in ["", "fast", "0", "-250k", "1e10"] {
+            assert!(bad.parse::<Bitrate>().is_err(), "{bad:?}");
+        }
+    }
+
+    /// Every rate from 5 kbit/s to 1 Mbit/s that [`calc_btr`] accepts must decode back to within
+    /// [`MAX_RATE_ERROR_PPM`] of the request, with a legal segment split; the ones it refuses
+    /// must really have no timing that close.
+    #[test]
+    fn calculated_timings_decode_to_the_requested_rate() {
+        let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+        let (mut accepted, mut refused) = (0, 0);
+        for _ in 0..3_000 {
+            let bps = 5_000 + rng.below(995_001) as u32;
+            let sample_point = 500 + rng.below(400) as u16;
+            match calc_btr(bps, sample_point) {
+                Ok((timing0, timing1)) => {
+                    accepted += 1;
+                    let rate = Bitrate::Custom { timing0, timing1 };
+                    let error_ppm = (rate.actual_bps() / f64::from(bps) - 1.0).abs() * 1e6;
+                    assert!(error_ppm <= MAX_RATE_ERROR_PPM as f64, "{bps} bps gave {} bps", rate.actual_bps());
+                    assert_eq!(timing0 >> 6, 0, "{bps} bps: SJW of one quantum");
+                    assert!(timing1 >> 7 == 0 && (timing1 >> 4) & 0x07 >= 1, "{bps} bps: single sampling, TSEG2 of 2 or more");
+                    assert!((8..=25).contains(&bit_quanta(timing1)), "{bps} bps");
+                }
+                Err(err) => {
+                    refused += 1;
+                    assert_eq!(err.requested, bps);
+                    let best_ppm = (1..=64u32)
+                        .flat_map(|brp| (8..=25u32).map(move |quanta| brp * quanta))
+                        .map(|divisor| (f64::from(CAN_CLOCK) / f64::from(divisor) / f64::from(bps) - 1.0).abs() * 1e6)
+                        .fold(f64::INFINITY, f64::min);
+                    assert!(best_ppm > MAX_RATE_ERROR_PPM as f64, "{bps} bps refused, but {best_ppm:.0} ppm is possible");
+                }
+            }
+        }
+        assert!(accepted > 100 && refused > 100, "{accepted} accepted, {refused} refused");
+    }
+
+    #[test]
+    fn standard_rates_reproduce_their_table_values() {
+        for (rate, bps, timing, sample_point) in TABLE {
+            assert_eq!(calc_btr(bps, sample_point as u16), Ok(timing), "{rate}");
+        }
+        // At the usual 87.5% the 16-quanta rates still come out as in the table.
+        for (rate, bps, timing, _) in TABLE.into_iter().filter(|&(.., sample_point)| sample_point == 875) {
+            assert_eq!(calc_btr(bps, 875), Ok(timing), "{rate}");
+        }
+    }
+
+    #[test]
+    fn sample_point_is_as_close_as_the_segments_allow() {
+        // 500k leaves 16 quanta per bit; 80% falls between 12 and 13 of them.
+        for (sample_point, timing1, achieved) in [(875, 0x1C, 875), (750, 0x3A, 750), (800, 0x2B, 812), (500, 0x76, 500)] {
+            let (timing0, got) = calc_btr(500_000, sample_point).unwrap();
+            assert_eq!((timing0, got), (0x00, timing1), "{sample_point}");
+            assert_eq!(Bitrate::Custom { timing0, timing1: got }.sample_point_permille(), achieved);
+        }
+    }
+
+    #[test]
+    fn unreachable_rates_name_the_nearest() {
+        // 8 MHz / 12 quanta / prescaler 1 is the fastest rate near 666 kbit/s, 0.1% off.
+        assert_eq!(calc_btr(666_000, 875), Err(BtrError { requested: 666_000, nearest: 666_667 }));
+        assert_eq!(calc_btr(2_000_000, 875), Err(BtrError { requested: 2_000_000, nearest: 1_000_000 }));
+        assert_eq!(calc_btr(1_000, 875).unwrap_err().nearest, 5_000);
+        assert!(calc_btr(83_333, 875).is_ok());
+    }
+}
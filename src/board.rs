@@ -0,0 +1,31 @@
+use std::fmt;
+
+/// Adapter identification as reported by `VCI_ReadBoardInfo`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoardInfo {
+    pub hw_version: u16,
+    pub fw_version: u16,
+    pub driver_version: u16,
+    pub interface_version: u16,
+    pub irq: u16,
+    pub can_channels: u8,
+    pub serial: String,
+    pub hw_type: String,
+}
+
+/// Formats a VCI version word such as `0x0360` as `3.60`.
+pub fn format_version(version: u16) -> String {
+    format!("{:X}.{:02X}", version >> 8, version & 0xFF)
+}
+
+impl fmt::Display for BoardInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Hardware type:     {}", self.hw_type)?;
+        writeln!(f, "Serial number:     {}", self.serial)?;
+        writeln!(f, "Hardware version:  V{}", format_version(self.hw_version))?;
+        writeln!(f, "Firmware version:  V{}", format_version(self.fw_version))?;
+        writeln!(f, "Driver version:    V{}", format_version(self.driver_version))?;
+        writeln!(f, "Interface version: V{}", format_version(self.interface_version))?;
+        write!(f, "CAN channels:      {}", self.can_channels)
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::frame::Frame;
+
+/// Window the load is averaged over.
+pub const BUS_LOAD_WINDOW: Duration = Duration::from_secs(1);
+
+/// Worst-case length of `frame` on the wire in bits, including the maximum number of stuff
+/// bits and the 3-bit interframe space.
+pub fn frame_bits(frame: &Frame) -> u32 {
+    // Remote frames carry no data field whatever their DLC.
+    let data_bits = 8 * frame.data().len() as u32;
+    // Fixed fields, and the part of them (SOF through CRC) that is subject to stuffing.
+    let (fixed, stuffed) = if frame.is_extended() { (67, 54) } else { (47, 34) };
+    fixed + data_bits + (stuffed + data_bits - 1) / 4
+}
+
+/// Sliding-window bus load estimate from the frames seen on one channel.
+#[derive(Debug, Clone, Default)]
+pub struct BusLoad {
+    frames: VecDeque<(Instant, u32)>,
+    bits: u64,
+}
+
+impl BusLoad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, frame: &Frame, at: Instant) {
+        let bits = frame_bits(frame);
+        self.frames.push_back((at, bits));
+        self.bits += u64::from(bits);
+        self.expire(at);
+    }
+
+    /// Bits seen during the window ending at `now`.
+    pub fn bits(&mut self, now: Instant) -> u64 {
+        self.expire(now);
+        self.bits
+    }
+
+    /// Percentage of the window the bus was busy at `bps`.
+    pub fn percent(&mut self, bps: u32, now: Instant) -> f64 {
+        let capacity = f64::from(bps) * BUS_LOAD_WINDOW.as_secs_f64();
+        self.bits(now) as f64 / capacity * 100.0
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(at, bits)) = self.frames.front() {
+            if now.saturating_duration_since(at) < BUS_LOAD_WINDOW {
+                break;
+            }
+            self.frames.pop_front();
+            self.bits -= u64::from(bits);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::Id;
+    use crate::mock::started_pair;
+
+    fn std_frame(len: usize) -> Frame {
+        Frame::new(Id::Standard(0x123), &[0x55; 8][..len]).unwrap()
+    }
+
+    fn ext_frame(len: usize) -> Frame {
+        Frame::new(Id::Extended(0x18FF_50E5), &[0x55; 8][..len]).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn worst_case_frame_lengths() {
+        // 8 data bits plus 2 worst-case stuff bits per byte on top of 55 and 80 bits.
+        for len in 0..=8 {
+            assert_eq!(frame_bits(&std_frame(len)), 55 + 10 * len as u32, "{len} bytes");
+            assert_eq!(frame_bits(&ext_frame(len)), 80 + 10 * len as u32, "{len} bytes");
+        }
+        assert_eq!(frame_bits(&Frame::remote(Id::Standard(0x7DF), 8).unwrap()), 55);
+        assert_eq!(frame_bits(&Frame::remote(Id::Extended(0x18DA_F110), 8).unwrap()), 80);
+    }
+
+    #[test]
+    fn loads_of_synthetic_streams() {
+        let start = Instant::now();
+        // 1000 8-byte standard frames a second at 500 kbit/s: 135,000 bits of 500,000.
+        let mut load = BusLoad::new();
+        for n in 0..1000 {
+            load.record(&std_frame(8), start + Duration::from_millis(n));
+        }
+        let end = start + Duration::from_millis(999);
+        assert_eq!(load.bits(end), 135_000);
+        assert_close(load.percent(500_000, end), 27.0);
+        // The same stream at 250 and 125 kbit/s.
+        assert_close(load.percent(250_000, end), 54.0);
+        assert_close(load.percent(125_000, end), 108.0);
+
+        // 100 extended 4-byte frames (120 bits) and 200 empty standard ones (55 bits) at 125k:
+        // 12,000 + 11,000 bits.
+        let mut load = BusLoad::new();
+        for n in 0..200 {
+            if n < 100 {
+                load.record(&ext_frame(4), start + Duration::from_millis(n * 5));
+            }
+            load.record(&std_frame(0), start + Duration::from_millis(n * 5 + 1));
+        }
+        assert_close(load.percent(125_000, start + Duration::from_millis(999)), 18.4);
+        assert_eq!(BusLoad::new().percent(500_000, start), 0.0);
+    }
+
+    #[test]
+    fn frames_leave_the_window_after_a_second() {
+        let start = Instant::now();
+        let mut load = BusLoad::new();
+        load.record(&std_frame(8), start);
+        load.record(&ext_frame(8), start + Duration::from_millis(500));
+        assert_eq!(load.bits(start + Duration::from_millis(999)), 295);
+        assert_eq!(load.bits(start + BUS_LOAD_WINDOW), 160);
+        assert_eq!(load.bits(start + Duration::from_millis(1499)), 160);
+        assert_eq!(load.bits(start + Duration::from_millis(1500)), 0);
+        // A late `record` expires older frames too.
+        load.record(&std_frame(0), start + Duration::from_secs(10));
+        assert_eq!(load.bits(start + Duration::from_secs(10)), 55);
+    }
+
+    #[test]
+    fn channels_use_their_configured_bitrate() {
+        let (_mock, device, can1, can2) = started_pair();
+        for _ in 0..100 {
+            can1.transmit(&std_frame(8)).unwrap();
+        }
+        assert!(can1.flush_transmit(Duration::from_millis(500)));
+        // 13,500 bits at 500 kbit/s, both where they were sent and where they were received.
+        assert_close(can1.bus_load().unwrap(), 2.7);
+        let mut received = 0;
+        while received < 100 {
+            received += can2.receive(Duration::from_millis(500)).unwrap().len();
+        }
+        assert_close(can2.bus_load().unwrap(), 2.7);
+
+        let slow = crate::VciInitConfig::with_bitrate(crate::bitrate::Bitrate::Kbps125);
+        can1.reconfigure(&slow).unwrap();
+        can1.transmit(&std_frame(8)).unwrap();
+        assert!(can1.flush_transmit(Duration::from_millis(500)));
+        assert!(can1.bus_load().unwrap() >= 10.8, "the same frames are four times the load at 125k");
+        assert_eq!(device.channel(0).bus_load(), can1.bus_load());
+    }
+}
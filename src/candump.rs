@@ -0,0 +1,361 @@
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::device::CHANNEL_COUNT;
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::sink::{Direction, FrameSink};
+use crate::timestamp::host_time;
+
+/// Formats `frame` in candump's `-l` log format: `(1634567890.123456) can0 123#DEADBEEF`.
+///
+/// Extended IDs are written with 8 hex digits, standard with 3; remote frames use the `R`
+/// suffix followed by the DLC when it is non-zero.
+pub fn format_candump(time: SystemTime, channel: u32, frame: &Frame) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut line = format!(
+        "({}.{:06}) can{} {}#",
+        since_epoch.as_secs(),
+        since_epoch.subsec_micros(),
+        channel,
+        format_candump_id(frame)
+    );
+    if frame.is_remote() {
+        line.push('R');
+        if frame.dlc() > 0 {
+            let _ = write!(line, "{:X}", frame.dlc());
+        }
+    } else {
+        for byte in frame.data() {
+            let _ = write!(line, "{byte:02X}");
+        }
+    }
+    line
+}
+
+fn format_candump_id(frame: &Frame) -> String {
+    if frame.is_extended() {
+        format!("{:08X}", frame.id().raw())
+    } else {
+        format!("{:03X}", frame.id().raw())
+    }
+}
+
+/// Writes frames as a candump log. Output is buffered by the caller's writer; call
+/// [`FrameSink::finish`] on shutdown so the tail isn't lost.
+pub struct CandumpWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> CandumpWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W: Write + Send> FrameSink for CandumpWriter<W> {
+    /// candump logs carry no direction, so transmitted frames look like received ones.
+    fn write_frame(&mut self, channel: u32, frame: &Frame, _direction: Direction) -> io::Result<()> {
+        let time = host_time(frame);
+        writeln!(self.out, "{}", format_candump(time, channel, frame))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// One parsed candump log line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CandumpRecord {
+    pub time: Duration,
+    pub channel: u32,
+    pub frame: Frame,
+}
+
+/// Parses the `ID#DATA` / `ID#R[dlc]` part of a candump line. Three-digit IDs are standard,
+/// eight-digit IDs extended; data bytes may be separated by `.`.
+pub fn parse_candump_frame(s: &str) -> Result<Frame, String> {
+    let (id, rest) = s.split_once('#').ok_or_else(|| format!("missing '#' in '{s}'"))?;
+    let raw = parse_hex(id).ok_or_else(|| format!("invalid CAN ID '{id}'"))?;
+    let id = match id.len() {
+        3 if raw <= Id::MAX_STANDARD as u32 => Id::Standard(raw as u16),
+        8 => Id::extended(raw).ok_or_else(|| format!("CAN ID '{id}' exceeds 29 bits"))?,
+        _ => return Err(format!("CAN ID '{id}' must be 3 (standard) or 8 (extended) hex digits")),
+    };
+
+    parse_payload(id, rest)
+}
+
+/// Hex digits only, which `from_str_radix` alone doesn't check: it takes a leading `+`.
+fn parse_hex(digits: &str) -> Option<u32> {
+    digits.bytes().all(|b| b.is_ascii_hexdigit()).then(|| u32::from_str_radix(digits, 16).ok()).flatten()
+}
+
+/// Parses the part after `#`: `R[len]` for a remote frame, otherwise hex bytes that may be
+/// separated by `.` or whitespace.
+fn parse_payload(id: Id, rest: &str) -> Result<Frame, String> {
+    let rest = rest.trim();
+    if let Some(dlc) = rest.strip_prefix(['R', 'r']) {
+        let dlc = match dlc.trim() {
+            "" => 0,
+            dlc => parse_hex(dlc).and_then(|dlc| u8::try_from(dlc).ok()).ok_or_else(|| format!("invalid RTR length '{dlc}'"))?,
+        };
+        return Frame::remote(id, dlc).ok_or_else(|| format!("RTR length {dlc} exceeds 8"));
+    }
+
+    let hex: String = rest.chars().filter(|&c| c != '.' && !c.is_whitespace()).collect();
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return Err(format!("expected an even number of hex digits, got '{rest}'"));
+    }
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("invalid hex data '{rest}'"));
+    }
+    let bytes: Vec<u8> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("two hex digits"))
+        .collect();
+    Frame::new(id, &bytes).ok_or_else(|| format!("'{rest}' is longer than 8 bytes"))
+}
+
+/// Parses a hand-typed frame such as `123#DEADBEEF`, `18FF50E5x#01 02 03` or `7DF#R8`. Unlike
+/// [`parse_candump_frame`], the ID may have any number of digits: an `x` suffix, 8 digits or a
+/// value above 0x7FF makes it extended.
+pub fn parse_frame_spec(s: &str) -> Result<Frame, String> {
+    let (id, rest) = s.trim().split_once('#').ok_or_else(|| format!("missing '#' in '{s}'"))?;
+    let id = id.trim();
+    let (digits, force_extended) = match id.strip_suffix(['x', 'X']) {
+        Some(digits) => (digits, true),
+        None => (id, false),
+    };
+    let digits = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")).unwrap_or(digits);
+    if digits.is_empty() {
+        return Err(format!("missing CAN ID in '{s}'"));
+    }
+    let raw = parse_hex(digits).ok_or_else(|| format!("invalid CAN ID '{id}'"))?;
+    let id = if force_extended || digits.len() == 8 || raw > Id::MAX_STANDARD as u32 {
+        Id::extended(raw).ok_or_else(|| format!("CAN ID '{id}' exceeds 29 bits"))?
+    } else {
+        Id::Standard(raw as u16)
+    };
+    parse_payload(id, rest)
+}
+
+/// Parses a full log line such as `(1634567890.123456) can0 123#DEADBEEF`. The interface
+/// name must be `canN`; `N` becomes the channel index.
+pub fn parse_candump_line(line: &str) -> Result<CandumpRecord, String> {
+    let mut fields = line.split_whitespace();
+    let (Some(time), Some(iface), Some(frame), None) = (fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return Err("expected '(timestamp) interface frame'".to_string());
+    };
+
+    let time = time
+        .strip_prefix('(')
+        .and_then(|t| t.strip_suffix(')'))
+        .ok_or_else(|| format!("timestamp '{time}' is not parenthesized"))?;
+    let (secs, micros) = time.split_once('.').unwrap_or((time, "0"));
+    // Checked before padding and cutting to 6 digits, which would split a multi-byte character.
+    if [secs, micros].iter().any(|part| !part.bytes().all(|b| b.is_ascii_digit())) {
+        return Err(format!("invalid timestamp '{time}'"));
+    }
+    let secs: u64 = secs.parse().map_err(|_| format!("invalid timestamp '{time}'"))?;
+    let micros: u32 = format!("{micros:0<6}")[..6].parse().expect("six ASCII digits");
+
+    let channel = iface
+        .strip_prefix("can")
+        .and_then(|n| n.parse::<u32>().ok())
+        .filter(|&n| n < CHANNEL_COUNT)
+        .ok_or_else(|| format!("unknown interface '{iface}', expected can0 or can1"))?;
+
+    Ok(CandumpRecord {
+        time: Duration::new(secs, micros * 1000),
+        channel,
+        frame: parse_candump_frame(frame)?,
+    })
+}
+
+/// Result of reading a candump log: good records plus `(line number, reason)` for every line
+/// that failed to parse.
+#[derive(Debug, Clone, Default)]
+pub struct CandumpLog {
+    pub records: Vec<CandumpRecord>,
+    pub errors: Vec<(usize, String)>,
+}
+
+/// Reads a candump log, skipping (and reporting) malformed lines. Blank lines are ignored.
+pub fn read_candump<R: BufRead>(reader: R) -> io::Result<CandumpLog> {
+    let mut records = Vec::new();
+    let mut errors = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_candump_line(&line) {
+            Ok(record) => records.push(record),
+            Err(reason) => errors.push((index + 1, reason)),
+        }
+    }
+    Ok(CandumpLog { records, errors })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::XorShift;
+
+    #[test]
+    fn parses_log_lines() {
+        let record = parse_candump_line("(1634567890.123456) can1 18FF50E5#0102").unwrap();
+        assert_eq!(record.time, Duration::new(1_634_567_890, 123_456_000));
+        assert_eq!(record.channel, 1);
+        assert_eq!(record.frame, Frame::new(Id::Extended(0x18FF_50E5), &[1, 2]).unwrap());
+
+        assert_eq!(parse_candump_line("(5.1) can0 123#").unwrap().time, Duration::from_millis(5_100));
+        assert_eq!(parse_candump_line("(5.1234567) can0 123#").unwrap().time, Duration::from_micros(5_123_456));
+        assert_eq!(parse_candump_line("(5) can0 123#R").unwrap().time, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn rejects_malformed_timestamps() {
+        for line in [
+            "(1.é) can0 123#00",
+            "(1.12345é) can0 123#00",
+            "(1.€€) can0 123#00",
+            "(é.123456) can0 123#00",
+            "(1.+12345) can0 123#00",
+            "(+1.123456) can0 123#00",
+            "(1.12a456) can0 123#00",
+            "(1.-1) can0 123#00",
+            "(.) can0 123#00",
+            "1.123456 can0 123#00",
+            "(1.123456 can0 123#00",
+            "(99999999999999999999.0) can0 123#00",
+        ] {
+            let err = parse_candump_line(line).expect_err(line);
+            assert!(err.contains("timestamp"), "{line}: {err}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for (line, reason) in [
+            ("", "expected"),
+            ("(1.0) can0", "expected"),
+            ("(1.0) can0 123#00 extra", "expected"),
+            ("(1.0) vcan0 123#00", "interface"),
+            ("(1.0) can2 123#00", "interface"),
+            ("(1.0) canX 123#00", "interface"),
+            ("(1.0) can0 12#00", "3 (standard) or 8"),
+            ("(1.0) can0 800#00", "3 (standard) or 8"),
+            ("(1.0) can0 20000000#00", "29 bits"),
+            ("(1.0) can0 12G#00", "invalid CAN ID"),
+            ("(1.0) can0 +23#00", "invalid CAN ID"),
+            ("(1.0) can0 123", "missing '#'"),
+            ("(1.0) can0 123#0", "even number"),
+            ("(1.0) can0 123#é0", "even number"),
+            ("(1.0) can0 123#GG", "invalid hex"),
+            ("(1.0) can0 123#+1", "invalid hex"),
+            ("(1.0) can0 123#000102030405060708", "longer than 8"),
+            ("(1.0) can0 123#R9", "exceeds 8"),
+            ("(1.0) can0 123#RZ", "RTR length"),
+        ] {
+            let err = parse_candump_line(line).expect_err(line);
+            assert!(err.contains(reason), "{line}: {err}");
+        }
+    }
+
+    #[test]
+    fn formatted_lines_parse_back() {
+        let frames = [
+            Frame::new(Id::Standard(0x7FF), &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap(),
+            Frame::new(Id::Extended(0x0000_0001), &[]).unwrap(),
+            Frame::remote(Id::Standard(0x123), 8).unwrap(),
+        ];
+        let time = UNIX_EPOCH + Duration::from_micros(1_634_567_890_000_042);
+        for frame in frames {
+            let line = format_candump(time, 1, &frame);
+            let record = parse_candump_line(&line).unwrap();
+            assert_eq!((record.time, record.channel, record.frame), (Duration::from_micros(1_634_567_890_000_042), 1, frame), "{line}");
+        }
+    }
+
+    #[test]
+    fn read_candump_reports_bad_lines_by_number() {
+        let log = "(1.000000) can0 123#00\n\n(1.é) can0 123#00\n(2.000000) can1 456#R\n";
+        let log = read_candump(log.as_bytes()).unwrap();
+        assert_eq!(log.records.len(), 2);
+        assert_eq!(log.errors.len(), 1);
+        assert_eq!(log.errors[0].0, 3);
+    }
+
+    #[test]
+    fn parses_frame_specs() {
+        let std_frame = |id, data: &[u8]| Frame::new(Id::Standard(id), data).unwrap();
+        let ext_frame = |id, data: &[u8]| Frame::new(Id::Extended(id), data).unwrap();
+        for (spec, frame) in [
+            ("123#DEADBEEF", std_frame(0x123, &[0xDE, 0xAD, 0xBE, 0xEF])),
+            ("18FF50E5x#01 02 03", ext_frame(0x18FF_50E5, &[1, 2, 3])),
+            (" 0x7FF # 01.02 ", std_frame(0x7FF, &[1, 2])),
+            ("7#", std_frame(0x7, &[])),
+            ("123x#", ext_frame(0x123, &[])),
+            // The suffix, not a prefix without digits.
+            ("0x#01", ext_frame(0, &[1])),
+            ("800#00", ext_frame(0x800, &[0])),
+            ("00000123#", ext_frame(0x123, &[])),
+            ("1FFFFFFF#0102030405060708", ext_frame(Id::MAX_EXTENDED, &[1, 2, 3, 4, 5, 6, 7, 8])),
+            ("7DF#R8", Frame::remote(Id::Standard(0x7DF), 8).unwrap()),
+            ("7DF#r", Frame::remote(Id::Standard(0x7DF), 0).unwrap()),
+        ] {
+            assert_eq!(parse_frame_spec(spec), Ok(frame), "{spec:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_frame_specs() {
+        for (spec, error) in [
+            ("", "missing '#' in ''"),
+            ("123DEADBEEF", "missing '#' in '123DEADBEEF'"),
+            ("#01", "missing CAN ID in '#01'"),
+            ("x#01", "missing CAN ID in 'x#01'"),
+            ("12G#00", "invalid CAN ID '12G'"),
+            ("-1#00", "invalid CAN ID '-1'"),
+            ("+12#00", "invalid CAN ID '+12'"),
+            ("0x0x1#00", "invalid CAN ID '0x0x1'"),
+            ("123456789#", "invalid CAN ID '123456789'"),
+            ("20000000#00", "CAN ID '20000000' exceeds 29 bits"),
+            ("FFFFFFFFx#", "CAN ID 'FFFFFFFFx' exceeds 29 bits"),
+            ("123#0", "expected an even number of hex digits, got '0'"),
+            ("123#0 12", "expected an even number of hex digits, got '0 12'"),
+            ("123#é", "expected an even number of hex digits, got 'é'"),
+            ("123#GG", "invalid hex data 'GG'"),
+            ("123#+1", "invalid hex data '+1'"),
+            ("123#010203040506070809", "'010203040506070809' is longer than 8 bytes"),
+            ("123#R9", "RTR length 9 exceeds 8"),
+            ("123#RZ", "invalid RTR length 'Z'"),
+            ("123#R-1", "invalid RTR length '-1'"),
+            ("123#R+1", "invalid RTR length '+1'"),
+        ] {
+            assert_eq!(parse_frame_spec(spec), Err(error.to_string()), "{spec:?}");
+        }
+    }
+
+    /// Whatever is typed at the prompt, the parser returns rather than panicking.
+    #[test]
+    fn arbitrary_frame_specs_dont_panic() {
+        let alphabet: Vec<char> = "0123456789abcdefABCDEFxXrR#. -+é€\t".chars().collect();
+        let mut rng = XorShift(0xA076_1D64_78BD_642F);
+        for _ in 0..20_000 {
+            let len = rng.below(24);
+            let spec: String = (0..len).map(|_| rng.pick(&alphabet)).collect();
+            if let Ok(frame) = parse_frame_spec(&spec) {
+                assert!(frame.data().len() <= 8 && frame.dlc() <= 8, "{spec:?}");
+            }
+            let _ = parse_candump_frame(&spec);
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+use crate::device::Channel;
+use crate::error::CanError;
+use crate::fanout::Subscription;
+use crate::frame::Frame;
+use crate::id::Id;
+
+/// Function codes (COB-ID bases) of the CANopen predefined connection set.
+const NMT_COB_ID: u16 = 0x000;
+const SDO_RESPONSE: u16 = 0x580;
+const SDO_REQUEST: u16 = 0x600;
+const HEARTBEAT: u16 = 0x700;
+
+/// SDO command bytes.
+const UPLOAD_REQUEST: u8 = 0x40;
+const DOWNLOAD_REQUEST: u8 = 0x20;
+const EXPEDITED: u8 = 0x02;
+const SIZE_INDICATED: u8 = 0x01;
+const UPLOAD_RESPONSE: u8 = 2;
+const DOWNLOAD_RESPONSE: u8 = 3;
+const ABORT: u8 = 4;
+
+const SUBSCRIPTION_CAPACITY: usize = 256;
+
+/// Node state from a heartbeat or boot-up message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NmtState {
+    BootUp,
+    Stopped,
+    Operational,
+    PreOperational,
+    Unknown(u8),
+}
+
+impl NmtState {
+    pub fn from_byte(byte: u8) -> Self {
+        // The top bit is the toggle bit of node guarding responses.
+        match byte & 0x7F {
+            0x00 => Self::BootUp,
+            0x04 => Self::Stopped,
+            0x05 => Self::Operational,
+            0x7F => Self::PreOperational,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+impl fmt::Display for NmtState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BootUp => write!(f, "boot-up"),
+            Self::Stopped => write!(f, "stopped"),
+            Self::Operational => write!(f, "operational"),
+            Self::PreOperational => write!(f, "pre-operational"),
+            Self::Unknown(byte) => write!(f, "unknown state 0x{byte:02X}"),
+        }
+    }
+}
+
+/// NMT command specifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NmtCommand {
+    Start,
+    Stop,
+    EnterPreOperational,
+    ResetNode,
+    ResetCommunication,
+}
+
+impl NmtCommand {
+    fn specifier(self) -> u8 {
+        match self {
+            Self::Start => 0x01,
+            Self::Stop => 0x02,
+            Self::EnterPreOperational => 0x80,
+            Self::ResetNode => 0x81,
+            Self::ResetCommunication => 0x82,
+        }
+    }
+
+    /// The NMT frame sending this command to `node`, or to every node for node 0.
+    pub fn frame(self, node: u8) -> Frame {
+        Frame::new(Id::Standard(NMT_COB_ID), &[self.specifier(), node]).expect("NMT frames fit in 8 bytes")
+    }
+}
+
+/// The node and state in a heartbeat (or boot-up) frame: ID 0x700 + node, one data byte.
+pub fn parse_heartbeat(frame: &Frame) -> Option<(u8, NmtState)> {
+    let Id::Standard(raw) = frame.id() else {
+        return None;
+    };
+    let node = raw.checked_sub(HEARTBEAT).filter(|node| (1..=127).contains(node))? as u8;
+    match frame.data() {
+        [state] => Some((node, NmtState::from_byte(*state))),
+        _ => None,
+    }
+}
+
+/// What [`HeartbeatMonitor`] noticed about a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeEvent {
+    /// First heartbeat from the node (`from` is `None`), a new state, or a heartbeat after it was
+    /// lost.
+    Changed { node: u8, from: Option<NmtState>, to: NmtState },
+    /// No heartbeat within the timeout.
+    Lost { node: u8, last: NmtState },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeStatus {
+    pub state: NmtState,
+    pub last_seen: Instant,
+    pub lost: bool,
+}
+
+/// NMT state of every node heard from, with a heartbeat consumer timeout.
+#[derive(Debug, Clone)]
+pub struct HeartbeatMonitor {
+    nodes: BTreeMap<u8, NodeStatus>,
+    timeout: Duration,
+}
+
+impl HeartbeatMonitor {
+    /// Nodes silent for longer than `timeout` are reported lost.
+    pub fn new(timeout: Duration) -> Self {
+        Self { nodes: BTreeMap::new(), timeout }
+    }
+
+    /// Records a received frame; anything but a heartbeat is ignored.
+    pub fn observe(&mut self, frame: &Frame, now: Instant) -> Option<NodeEvent> {
+        let (node, state) = parse_heartbeat(frame)?;
+        let previous = self.nodes.insert(node, NodeStatus { state, last_seen: now, lost: false });
+        match previous {
+            Some(status) if status.state == state && !status.lost => None,
+            _ => Some(NodeEvent::Changed { node, from: previous.map(|status| status.state), to: state }),
+        }
+    }
+
+    /// Reports nodes whose heartbeat has been missing since the last check.
+    pub fn check(&mut self, now: Instant) -> Vec<NodeEvent> {
+        let timeout = self.timeout;
+        self.nodes
+            .iter_mut()
+            .filter(|(_, status)| !status.lost && now.saturating_duration_since(status.last_seen) > timeout)
+            .map(|(&node, status)| {
+                status.lost = true;
+                NodeEvent::Lost { node, last: status.state }
+            })
+            .collect()
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (u8, &NodeStatus)> {
+        self.nodes.iter().map(|(&node, status)| (node, status))
+    }
+}
+
+/// An SDO abort code (CiA 301).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SdoAbort(pub u32);
+
+impl SdoAbort {
+    const MESSAGES: [(u32, &'static str); 28] = [
+        (0x0503_0000, "toggle bit not alternated"),
+        (0x0504_0000, "SDO protocol timed out"),
+        (0x0504_0001, "client/server command specifier not valid or unknown"),
+        (0x0504_0005, "out of memory"),
+        (0x0601_0000, "unsupported access to an object"),
+        (0x0601_0001, "attempt to read a write only object"),
+        (0x0601_0002, "attempt to write a read only object"),
+        (0x0602_0000, "object does not exist in the object dictionary"),
+        (0x0604_0041, "object cannot be mapped to the PDO"),
+        (0x0604_0042, "the objects to be mapped would exceed the PDO length"),
+        (0x0604_0043, "general parameter incompatibility"),
+        (0x0604_0047, "general internal incompatibility in the device"),
+        (0x0606_0000, "access failed due to a hardware error"),
+        (0x0607_0010, "data type does not match, length of service parameter does not match"),
+        (0x0607_0012, "data type does not match, length of service parameter too high"),
+        (0x0607_0013, "data type does not match, length of service parameter too low"),
+        (0x0609_0011, "sub-index does not exist"),
+        (0x0609_0030, "invalid value for parameter"),
+        (0x0609_0031, "value of parameter written too high"),
+        (0x0609_0032, "value of parameter written too low"),
+        (0x0609_0036, "maximum value is less than minimum value"),
+        (0x060A_0023, "resource not available: SDO connection"),
+        (0x0800_0000, "general error"),
+        (0x0800_0020, "data cannot be transferred or stored to the application"),
+        (0x0800_0021, "data cannot be transferred or stored to the application because of local control"),
+        (0x0800_0022, "data cannot be transferred or stored to the application because of the present device state"),
+        (0x0800_0023, "object dictionary dynamic generation failed or no object dictionary is present"),
+        (0x0800_0024, "no data available"),
+    ];
+
+    /// What the code means, if it is one of the codes CiA 301 defines.
+    pub fn message(self) -> Option<&'static str> {
+        Self::MESSAGES.iter().find(|&&(code, _)| code == self.0).map(|&(_, message)| message)
+    }
+}
+
+impl fmt::Display for SdoAbort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.message() {
+            Some(message) => write!(f, "{message} (0x{:08X})", self.0),
+            None => write!(f, "abort code 0x{:08X}", self.0),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SdoError {
+    Can(CanError),
+    /// The server didn't answer within [`SdoClient::timeout`].
+    Timeout { index: u16, subindex: u8 },
+    Abort { index: u16, subindex: u8, code: SdoAbort },
+    /// The server started a segmented upload, which isn't supported; `size` is the object's
+    /// length if it said.
+    Segmented { index: u16, subindex: u8, size: Option<u32> },
+    /// Expedited downloads carry 1 to 4 bytes.
+    DataLength { len: usize },
+    /// A response whose command byte doesn't answer the request.
+    Unexpected { data: Vec<u8> },
+}
+
+impl fmt::Display for SdoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Can(err) => err.fmt(f),
+            Self::Timeout { index, subindex } => write!(f, "no SDO response for 0x{index:04X}:{subindex:02X}"),
+            Self::Abort { index, subindex, code } => write!(f, "SDO 0x{index:04X}:{subindex:02X} aborted: {code}"),
+            Self::Segmented { index, subindex, size } => {
+                write!(f, "0x{index:04X}:{subindex:02X} needs a segmented transfer, which is not supported")?;
+                match size {
+                    Some(size) => write!(f, " ({size} bytes)"),
+                    None => Ok(()),
+                }
+            }
+            Self::DataLength { len } => write!(f, "expedited SDO downloads carry 1 to 4 bytes, got {len}"),
+            Self::Unexpected { data } => write!(f, "unexpected SDO response {data:02X?}"),
+        }
+    }
+}
+
+impl std::error::Error for SdoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Can(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<CanError> for SdoError {
+    fn from(err: CanError) -> Self {
+        Self::Can(err)
+    }
+}
+
+/// Expedited SDO client for one node's default SDO server: requests on 0x600 + node,
+/// responses on 0x580 + node.
+pub struct SdoClient {
+    channel: Channel,
+    frames: Subscription,
+    node: u8,
+    pub timeout: Duration,
+}
+
+impl SdoClient {
+    /// 1 s timeout.
+    pub fn new(channel: &Channel, node: u8) -> Self {
+        Self { channel: channel.clone(), frames: channel.subscribe(SUBSCRIPTION_CAPACITY), node, timeout: Duration::from_secs(1) }
+    }
+
+    pub fn node(&self) -> u8 {
+        self.node
+    }
+
+    /// Reads an object of up to 4 bytes. Objects the server would send segmented fail with
+    /// [`SdoError::Segmented`].
+    pub fn upload(&self, index: u16, subindex: u8) -> Result<Vec<u8>, SdoError> {
+        let response = self.request(index, subindex, [UPLOAD_REQUEST, 0, 0, 0, 0])?;
+        let command = response[0];
+        if command >> 5 != UPLOAD_RESPONSE {
+            return Err(SdoError::Unexpected { data: response.to_vec() });
+        }
+        let size_indicated = command & SIZE_INDICATED != 0;
+        if command & EXPEDITED == 0 {
+            let size = size_indicated.then(|| u32::from_le_bytes([response[4], response[5], response[6], response[7]]));
+            return Err(SdoError::Segmented { index, subindex, size });
+        }
+        let len = if size_indicated { 4 - usize::from(command >> 2 & 0x3) } else { 4 };
+        Ok(response[4..4 + len].to_vec())
+    }
+
+    /// Writes 1 to 4 bytes to an object.
+    pub fn download(&self, index: u16, subindex: u8, data: &[u8]) -> Result<(), SdoError> {
+        if data.is_empty() || data.len() > 4 {
+            return Err(SdoError::DataLength { len: data.len() });
+        }
+        let unused = 4 - data.len() as u8;
+        let mut payload = [DOWNLOAD_REQUEST | unused << 2 | EXPEDITED | SIZE_INDICATED, 0, 0, 0, 0];
+        payload[1..=data.len()].copy_from_slice(data);
+        let response = self.request(index, subindex, payload)?;
+        if response[0] >> 5 != DOWNLOAD_RESPONSE {
+            return Err(SdoError::Unexpected { data: response.to_vec() });
+        }
+        Ok(())
+    }
+
+    /// Sends a command byte and four data bytes for `index`/`subindex` and returns the server's
+    /// answer about that object, skipping responses about others.
+    fn request(&self, index: u16, subindex: u8, [command, data @ ..]: [u8; 5]) -> Result<[u8; 8], SdoError> {
+        let [low, high] = index.to_le_bytes();
+        let request = [command, low, high, subindex, data[0], data[1], data[2], data[3]];
+        let tx = Id::Standard(SDO_REQUEST + u16::from(self.node));
+        let rx = Id::Standard(SDO_RESPONSE + u16::from(self.node));
+        while self.frames.try_recv().is_ok() {}
+        self.channel.transmit(&Frame::new(tx, &request).expect("SDO frames fit in 8 bytes"))?;
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let frame = match self.frames.recv_timeout(left) {
+                Ok(frame) => frame,
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => {
+                    return Err(SdoError::Timeout { index, subindex });
+                }
+            };
+            let Ok(response) = <[u8; 8]>::try_from(frame.data()) else {
+                continue;
+            };
+            if frame.id() != rx {
+                continue;
+            }
+            // Aborts count whatever object they name, as some servers don't echo it.
+            if response[0] >> 5 == ABORT {
+                let code = SdoAbort(u32::from_le_bytes([response[4], response[5], response[6], response[7]]));
+                return Err(SdoError::Abort { index, subindex, code });
+            }
+            if response[1..4] == request[1..4] {
+                return Ok(response);
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::sink::{Direction, FrameSink};
+
+/// What starts a [`TriggeredCapture`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    /// Any frame with this ID.
+    Id(Id),
+    /// A frame with this ID whose payload bits under `mask` equal those of `data`. Payloads
+    /// shorter than `data` don't match.
+    Match { id: Id, data: Vec<u8>, mask: Vec<u8> },
+    /// A controller error counter rising by at least this much between two status polls. The
+    /// capture can't see error counters itself; whoever polls them calls
+    /// [`TriggeredCapture::fire`].
+    ErrorJump(u8),
+    /// Only [`TriggeredCapture::fire`], e.g. on a keypress.
+    Manual,
+}
+
+impl Trigger {
+    /// Whether `frame` fires this trigger; always `false` for the triggers that aren't frames.
+    pub fn matches(&self, frame: &Frame) -> bool {
+        match self {
+            Self::Id(id) => frame.id() == *id,
+            Self::Match { id, data, mask } => {
+                frame.id() == *id
+                    && !frame.is_remote()
+                    && frame.data().len() >= data.len()
+                    && frame.data().iter().zip(data).zip(mask).all(|((got, want), mask)| got & mask == want & mask)
+            }
+            Self::ErrorJump(_) | Self::Manual => false,
+        }
+    }
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{byte:02X}")).collect::<String>();
+        match self {
+            Self::Id(id) => write!(f, "{id}"),
+            Self::Match { id, data, mask } => write!(f, "{id}#{}/{}", hex(data), hex(mask)),
+            Self::ErrorJump(threshold) => write!(f, "error counters +{threshold}"),
+            Self::Manual => f.write_str("manual"),
+        }
+    }
+}
+
+/// How much a [`TriggeredCapture`] keeps and writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureConfig {
+    /// Frames received up to this long before the trigger are written.
+    pub pre_trigger: Duration,
+    /// Frames keep being written for this long after the trigger. Another trigger meanwhile
+    /// extends the window rather than starting a second capture.
+    pub post_trigger: Duration,
+    /// The most frames kept from before a trigger, however recent.
+    pub max_frames: usize,
+    /// Wait for the next trigger after a capture ends, instead of stopping.
+    pub rearm: bool,
+}
+
+/// Opens the sink for capture number `n`, counting from 1.
+pub type SinkFactory = Box<dyn FnMut(u32) -> io::Result<Box<dyn FrameSink>> + Send>;
+
+/// Something a [`TriggeredCapture`] did, for the caller to report.
+#[derive(Debug)]
+pub enum CaptureEvent {
+    /// Capture `number` started because of `reason`, with `buffered` frames from before.
+    Started { number: u32, reason: String, buffered: usize },
+    /// Capture `number` ended after writing `frames` frames in all.
+    Finished { number: u32, frames: u64 },
+    /// Opening or writing capture `number` failed; the capture is abandoned.
+    Failed { number: u32, error: io::Error },
+}
+
+impl fmt::Display for CaptureEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Started { number, reason, buffered } => {
+                write!(f, "Capture {number} triggered by {reason} ({buffered} frames from before)")
+            }
+            Self::Finished { number, frames } => write!(f, "Capture {number} finished, {frames} frames written"),
+            Self::Failed { number, error } => write!(f, "Capture {number} failed: {error}"),
+        }
+    }
+}
+
+struct Active {
+    number: u32,
+    sink: Box<dyn FrameSink>,
+    until: Instant,
+    frames: u64,
+}
+
+/// Keeps the most recent received frames in a ring and, when a trigger fires, writes them
+/// followed by everything received during the post-trigger window to a sink.
+///
+/// Each frame is stored once, in the ring, and from there written to the capture, so holding
+/// the pre-trigger window costs no more than the frames themselves.
+pub struct TriggeredCapture {
+    config: CaptureConfig,
+    triggers: Vec<Trigger>,
+    open: SinkFactory,
+    ring: VecDeque<(Instant, u32, Frame)>,
+    active: Option<Active>,
+    captures: u32,
+    stopped: bool,
+}
+
+impl TriggeredCapture {
+    pub fn new(config: CaptureConfig, triggers: Vec<Trigger>, open: SinkFactory) -> Self {
+        Self { config, triggers, open, ring: VecDeque::new(), active: None, captures: 0, stopped: false }
+    }
+
+    /// The smallest [`Trigger::ErrorJump`] threshold, if any is set.
+    pub fn error_jump(&self) -> Option<u8> {
+        self.triggers
+            .iter()
+            .filter_map(|trigger| match trigger {
+                Trigger::ErrorJump(threshold) => Some(*threshold),
+                _ => None,
+            })
+            .min()
+    }
+
+    pub fn has_manual_trigger(&self) -> bool {
+        self.triggers.contains(&Trigger::Manual)
+    }
+
+    /// Whether a trigger would start a capture now, rather than extend one or be ignored.
+    pub fn is_armed(&self) -> bool {
+        self.active.is_none() && !self.stopped
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Captures started so far.
+    pub fn captures(&self) -> u32 {
+        self.captures
+    }
+
+    /// Takes in one received frame: it's written to a capture in progress, kept for the next
+    /// one, and checked against the triggers.
+    pub fn observe(&mut self, channel: u32, frame: &Frame, now: Instant) -> Vec<CaptureEvent> {
+        let mut events = self.poll(now);
+        if self.stopped {
+            return events;
+        }
+        if let Some(active) = &mut self.active {
+            match active.sink.write_frame(channel, frame, Direction::Rx) {
+                Ok(()) => active.frames += 1,
+                Err(error) => {
+                    let number = active.number;
+                    self.abandon();
+                    events.push(CaptureEvent::Failed { number, error });
+                }
+            }
+        }
+        self.ring.push_back((now, channel, *frame));
+        self.trim(now);
+        if let Some(trigger) = self.triggers.iter().find(|trigger| trigger.matches(frame)) {
+            let reason = format!("CAN{} {trigger}", channel + 1);
+            // The frame is already in the ring, so a new capture writes it with the rest.
+            events.extend(self.fire(&reason, now));
+        }
+        events
+    }
+
+    /// Fires a trigger: starts a capture if armed, or extends the one in progress.
+    pub fn fire(&mut self, reason: &str, now: Instant) -> Vec<CaptureEvent> {
+        let mut events = self.poll(now);
+        if self.stopped {
+            return events;
+        }
+        let until = now + self.config.post_trigger;
+        if let Some(active) = &mut self.active {
+            active.until = active.until.max(until);
+            return events;
+        }
+        self.captures += 1;
+        let number = self.captures;
+        self.trim(now);
+        let mut sink = match (self.open)(number) {
+            Ok(sink) => sink,
+            Err(error) => {
+                self.stopped = !self.config.rearm;
+                events.push(CaptureEvent::Failed { number, error });
+                return events;
+            }
+        };
+        let buffered = self.ring.len();
+        for (_, channel, frame) in &self.ring {
+            if let Err(error) = sink.write_frame(*channel, frame, Direction::Rx) {
+                self.stopped = !self.config.rearm;
+                events.push(CaptureEvent::Failed { number, error });
+                return events;
+            }
+        }
+        self.active = Some(Active { number, sink, until, frames: buffered as u64 });
+        events.push(CaptureEvent::Started { number, reason: reason.to_string(), buffered });
+        events
+    }
+
+    /// Ends the capture in progress once its post-trigger window is over. Call it regularly, so a
+    /// capture also ends when the bus goes quiet.
+    pub fn poll(&mut self, now: Instant) -> Vec<CaptureEvent> {
+        match &self.active {
+            Some(active) if now >= active.until => self.finish(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Ends the capture in progress early, e.g. on shutdown, writing out what it has so far.
+    pub fn finish(&mut self) -> Vec<CaptureEvent> {
+        let Some(mut active) = self.active.take() else {
+            return Vec::new();
+        };
+        self.stopped = !self.config.rearm;
+        vec![match active.sink.finish() {
+            Ok(()) => CaptureEvent::Finished { number: active.number, frames: active.frames },
+            Err(error) => CaptureEvent::Failed { number: active.number, error },
+        }]
+    }
+
+    fn abandon(&mut self) {
+        self.active = None;
+        self.stopped = !self.config.rearm;
+    }
+
+    /// Drops frames from the ring that are too old or too many for the pre-trigger window.
+    fn trim(&mut self, now: Instant) {
+        while self.ring.len() > self.config.max_frames {
+            self.ring.pop_front();
+        }
+        while self.ring.front().is_some_and(|(at, _, _)| now.saturating_duration_since(*at) > self.config.pre_trigger) {
+            self.ring.pop_front();
+        }
+    }
+}
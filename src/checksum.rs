@@ -0,0 +1,125 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Single-byte checksums ECUs put in their frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Checksum {
+    /// All bytes XORed together.
+    Xor,
+    /// The two's complement of the byte sum, so the bytes and the checksum add up to zero.
+    SumComplement,
+    /// CRC-8 SAE J1850: polynomial 0x1D, initial value and final XOR 0xFF, not reflected.
+    Crc8SaeJ1850,
+}
+
+impl Checksum {
+    pub fn compute(self, bytes: &[u8]) -> u8 {
+        match self {
+            Self::Xor => bytes.iter().fold(0, |acc, byte| acc ^ byte),
+            Self::SumComplement => bytes.iter().fold(0u8, |acc, &byte| acc.wrapping_add(byte)).wrapping_neg(),
+            Self::Crc8SaeJ1850 => crc8_sae_j1850(bytes),
+        }
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Xor => "xor",
+            Self::SumComplement => "sum-complement",
+            Self::Crc8SaeJ1850 => "crc8",
+        })
+    }
+}
+
+impl FromStr for Checksum {
+    type Err = String;
+
+    /// `xor`, `sum-complement` (or `sum`), `crc8` (or `crc8-j1850`).
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "xor" => Ok(Self::Xor),
+            "sum" | "sum-complement" => Ok(Self::SumComplement),
+            "crc8" | "crc8-j1850" | "crc8-sae-j1850" => Ok(Self::Crc8SaeJ1850),
+            _ => Err(format!("unknown checksum '{s}', expected xor, sum-complement or crc8")),
+        }
+    }
+}
+
+/// CRC-8 SAE J1850; `b"123456789"` gives 0x4B.
+pub fn crc8_sae_j1850(bytes: &[u8]) -> u8 {
+    let mut crc = 0xFFu8;
+    for &byte in bytes {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x1D } else { crc << 1 };
+        }
+    }
+    crc ^ 0xFF
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc8_sae_j1850_vectors() {
+        // The check value of the catalogue, and the examples of the AUTOSAR CRC library.
+        let vectors: [(&[u8], u8); 8] = [
+            (b"123456789", 0x4B),
+            (&[0x00, 0x00, 0x00, 0x00], 0x59),
+            (&[0xF2, 0x01, 0x83], 0x37),
+            (&[0x0F, 0xAA, 0x00, 0x55], 0x79),
+            (&[0x00, 0xFF, 0x55, 0x11], 0xB8),
+            (&[0x33, 0x22, 0x55, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF], 0xCB),
+            (&[0x92, 0x6B, 0x55], 0x8C),
+            (&[0xFF, 0xFF, 0xFF, 0xFF], 0x74),
+        ];
+        for (bytes, crc) in vectors {
+            assert_eq!(Checksum::Crc8SaeJ1850.compute(bytes), crc, "{bytes:02X?}");
+        }
+        // Nothing to cover leaves the initial value, inverted.
+        assert_eq!(crc8_sae_j1850(&[]), 0x00);
+    }
+
+    #[test]
+    fn xor_vectors() {
+        let vectors: [(&[u8], u8); 5] = [
+            (&[], 0x00),
+            (&[0x5A], 0x5A),
+            (&[0x01, 0x02, 0x04, 0x08], 0x0F),
+            (&[0xFF, 0xFF], 0x00),
+            (&[0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE], 0xF0),
+        ];
+        for (bytes, xor) in vectors {
+            assert_eq!(Checksum::Xor.compute(bytes), xor, "{bytes:02X?}");
+        }
+    }
+
+    #[test]
+    fn sum_complement_vectors() {
+        let vectors: [(&[u8], u8); 5] = [
+            (&[], 0x00),
+            (&[0x01], 0xFF),
+            (&[0x10, 0x20, 0x30], 0xA0),
+            (&[0xFF, 0xFF], 0x02),
+            (&[0x80, 0x80], 0x00),
+        ];
+        for (bytes, checksum) in vectors {
+            assert_eq!(Checksum::SumComplement.compute(bytes), checksum, "{bytes:02X?}");
+            // The bytes and their checksum add up to zero.
+            let total = bytes.iter().fold(checksum, |acc, &byte| acc.wrapping_add(byte));
+            assert_eq!(total, 0, "{bytes:02X?}");
+        }
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for checksum in [Checksum::Xor, Checksum::SumComplement, Checksum::Crc8SaeJ1850] {
+            assert_eq!(checksum.to_string().parse::<Checksum>(), Ok(checksum));
+        }
+        assert_eq!(" SUM ".parse::<Checksum>(), Ok(Checksum::SumComplement));
+        assert_eq!("crc8-sae-j1850".parse::<Checksum>(), Ok(Checksum::Crc8SaeJ1850));
+        assert_eq!("crc16".parse::<Checksum>(), Err("unknown checksum 'crc16', expected xor, sum-complement or crc8".to_string()));
+    }
+}
@@ -0,0 +1,1318 @@
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+use rustcanbus::{parse_frame_spec, AlignmentMode, Bitrate, ChannelMode, ChecksumField, Compression, CounterField, Expectation, Fault, FaultAction, FaultAlert, FilterTerm, Frame, Id, IdNames, IntegritySpec, Rotation, SendType, Trigger, CHANNEL_COUNT, SLCAN_PORT, SOCKETCAND_PORT, WS_PORT};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    Candump,
+    Asc,
+    Csv,
+    /// pcapng with SocketCAN link type, one interface per channel
+    Pcap,
+    /// PEAK TRC 2.1, as PCAN-View saves traces
+    Trc,
+    /// ASAM MDF 4.1 with the bus logging standard's CAN_DataFrame and CAN_RemoteFrame groups
+    Mdf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogCompression {
+    Gzip,
+    Zstd,
+}
+
+impl From<LogCompression> for Compression {
+    fn from(compression: LogCompression) -> Self {
+        match compression {
+            LogCompression::Gzip => Compression::Gzip,
+            LogCompression::Zstd => Compression::Zstd,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ChannelAlign {
+    /// Offset from the channels' start times
+    Start,
+    /// Refined by frames seen on both channels, e.g. with both ports on one bus
+    Frames,
+}
+
+impl From<ChannelAlign> for AlignmentMode {
+    fn from(align: ChannelAlign) -> Self {
+        match align {
+            ChannelAlign::Start => AlignmentMode::Start,
+            ChannelAlign::Frames => AlignmentMode::Frames,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Server {
+    /// socketcand raw mode, one bus per channel (`can0`, `can1`)
+    Socketcand,
+    /// slcan (LAWICEL) ASCII protocol for the --channel channel, as `slcand` and python-can's
+    /// `slcan` interface speak it
+    Slcan,
+    /// JSON frames over WebSocket, for browser dashboards
+    Ws,
+    /// gRPC service from proto/rustcanbus.proto: frame streaming, transmit, status, configure
+    #[cfg(feature = "grpc")]
+    Grpc,
+}
+
+impl Server {
+    pub fn default_port(self) -> u16 {
+        match self {
+            Server::Socketcand => SOCKETCAND_PORT,
+            Server::Slcan => SLCAN_PORT,
+            Server::Ws => WS_PORT,
+            #[cfg(feature = "grpc")]
+            Server::Grpc => 50051,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per received frame (NDJSON)
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Demo {
+    Receive,
+    Transmit,
+    Both,
+}
+
+impl Demo {
+    pub fn receives(self) -> bool {
+        matches!(self, Demo::Receive | Demo::Both)
+    }
+
+    pub fn transmits(self) -> bool {
+        matches!(self, Demo::Transmit | Demo::Both)
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "rustcanbus", version, about = "CANalyst-II demo built on ControlCAN.dll")]
+#[command(group(ArgGroup::new("pgn_ids").args(["j1939", "nmea2000"])))]
+#[command(group(ArgGroup::new("frame_logs").args(["log", "capture", "fuzz_log"]).multiple(true)))]
+pub struct Args {
+    /// TOML file holding any of these options, e.g. `bitrate = "500k"` or
+    /// `filter = ["can0:123", "~7DF"]`, optionally grouped in tables such as `[device]`.
+    /// Options given on the command line take precedence
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Load NAME.toml from --profiles-dir, a --config file whose options must not be given
+    /// differently on the command line. 'w' saves the running setup as a new profile and 'l'
+    /// switches to another
+    #[arg(long, value_name = "NAME", conflicts_with = "config")]
+    pub profile: Option<String>,
+
+    /// Directory of the --profile files. Defaults to $RUSTCANBUS_PROFILES, then
+    /// rustcanbus/profiles in the user's configuration directory
+    #[arg(long, value_name = "PATH")]
+    pub profiles_dir: Option<PathBuf>,
+
+    /// Print the options in effect, from the command line and --config, as a --config file
+    /// and exit
+    #[arg(long)]
+    pub dump_config: bool,
+
+    /// VCI device type (4 = USBCAN-2A/CANalyst-II)
+    #[arg(long, default_value_t = 4)]
+    pub dev_type: u32,
+
+    /// Adapter index when several are plugged in
+    #[arg(long, default_value_t = 0)]
+    pub dev_index: u32,
+
+    /// Port to use as CAN1, then CAN2, as ADAPTER:PORT with PORT can0 or can1, e.g.
+    /// `--device 0:can0 --device 1:can0` to work across two adapters. With one, CAN2 is the
+    /// other port of the same adapter. Defaults to both ports of --dev-index
+    #[arg(long = "device", value_name = "ADAPTER:PORT", value_parser = parse_port, conflicts_with = "dev_index")]
+    pub ports: Vec<(u32, u32)>,
+
+    /// ControlCAN.dll (libcontrolcan.so or libusbcan.so on Linux) to load, or a directory
+    /// containing it. Defaults to $RUSTCANBUS_DLL, then the executable's directory, the working
+    /// directory and the system path
+    #[arg(long, value_name = "PATH")]
+    pub dll: Option<PathBuf>,
+
+    /// CAN channel the demo transmits on (0 = CAN1, 1 = CAN2); frames are received on both
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u32).range(0..=1))]
+    pub channel: u32,
+
+    /// Bus bitrate, e.g. 125k, 250k, 500k, 1M; other rates such as 83333 are computed for the
+    /// 8 MHz CAN clock
+    #[arg(long, default_value = "250k")]
+    pub bitrate: Bitrate,
+
+    /// Sample point in percent for a computed --bitrate timing, e.g. `--bitrate 83333
+    /// --sample-point 87.5`
+    #[arg(long, value_parser = parse_sample_point)]
+    pub sample_point: Option<u16>,
+
+    /// Detect the bus bitrate on --channel by listening at each --auto-baud-order rate, then
+    /// initialize both channels with it (overrides --bitrate)
+    #[arg(long)]
+    pub auto_baud: bool,
+
+    /// Rates --auto-baud tries, in order; defaults to the common rates first
+    #[arg(long, value_delimiter = ',', requires = "auto_baud")]
+    pub auto_baud_order: Vec<Bitrate>,
+
+    /// Give up --auto-baud after this many seconds without a match
+    #[arg(long, default_value_t = 10.0, requires = "auto_baud")]
+    pub auto_baud_timeout: f64,
+
+    /// Maximum frames requested per VCI_Receive call by the threads reading received frames,
+    /// --gateway's included
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..=2500))]
+    pub rx_buffer: u32,
+
+    /// Longest VCI_Receive wait, in milliseconds, of the threads reading received frames; they
+    /// poll again at once while frames are flowing and back off to this once the bus is idle
+    #[arg(long, default_value_t = 50)]
+    pub rx_wait_ms: u64,
+
+    /// Milliseconds to sleep after a poll that received nothing
+    #[arg(long, default_value_t = 0)]
+    pub rx_sleep_ms: u64,
+
+    /// Always wait the full --rx-wait-ms instead of adapting the wait to the traffic
+    #[arg(long)]
+    pub rx_fixed_wait: bool,
+
+    /// Transmit mode: normal, single-shot, self-test or single-shot-self-test
+    #[arg(long, default_value = "normal")]
+    pub send_type: SendType,
+
+    /// Initialize both channels listen-only: never ACK or transmit
+    #[arg(long)]
+    pub listen_only: bool,
+
+    /// Bitrate of one channel instead of --bitrate, as `canN:RATE` with `N` counting from 0
+    /// like --channel, e.g. `--channel-bitrate can1:125k`
+    #[arg(long = "channel-bitrate", value_name = "canN:RATE", value_parser = parse_channel_setting::<Bitrate>)]
+    pub channel_bitrates: Vec<(u32, Bitrate)>,
+
+    /// Mode of one channel instead of --listen-only's: normal, listen-only or self-test (the
+    /// controller's internal loopback), e.g. `--channel-mode can0:listen-only`
+    #[arg(long = "channel-mode", value_name = "canN:MODE", value_parser = parse_channel_setting::<ChannelMode>)]
+    pub channel_modes: Vec<(u32, ChannelMode)>,
+
+    /// Which demo loops to run
+    #[arg(long, value_enum, default_value_t = Demo::Both)]
+    pub demo: Demo,
+
+    /// Serve the channels over TCP to clients of this protocol
+    #[arg(long)]
+    pub server: Option<Server>,
+
+    /// Port for --server; defaults to the protocol's usual one (29536 for socketcand, 3333 for
+    /// slcan, 8080 for ws, 50051 for grpc)
+    #[arg(long, requires = "server")]
+    pub port: Option<u16>,
+
+    /// Most clients --server ws accepts at once; more are refused with HTTP 503
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..=1000))]
+    pub max_clients: u32,
+
+    /// Serve Prometheus metrics on http://0.0.0.0:PORT/metrics, and /healthz, which answers
+    /// 503 while an adapter is disconnected. PORT defaults to 9090
+    #[arg(long, value_name = "PORT", num_args = 0..=1, default_missing_value = "9090")]
+    pub metrics_port: Option<u16>,
+
+    /// Serve --server slcan on a pseudo-terminal instead of TCP, for `slcand`
+    #[cfg(unix)]
+    #[arg(long, requires = "server", conflicts_with = "port")]
+    pub pty: bool,
+
+    /// Forward every frame received on CAN1 to CAN2 and vice versa instead of running the demo
+    #[arg(long, conflicts_with_all = ["replay", "tx_table", "cyclic"])]
+    pub gateway: bool,
+
+    /// Gateway rules file: one `DIRECTION ID[/MASK] forward|drop|map NEWID [set N=HH]...` rule
+    /// per line (`ID:MASK` works too, as in --filter), first match wins, plus an optional
+    /// `default forward|drop`
+    #[arg(long, requires = "gateway")]
+    pub gateway_rules: Option<PathBuf>,
+
+    /// Check each channel on its own in self-test mode with a set of known frames, print pass or
+    /// fail per frame and exit, failing if any frame didn't come back intact
+    #[arg(long, conflicts_with_all = ["gateway", "replay", "tx_table", "cyclic", "send_signal", "listen_only", "channel_modes", "channel_bitrates", "latency_test", "benchmark"])]
+    pub self_test: bool,
+
+    /// Also check the frames get from CAN1 to CAN2 with --self-test; needs the two channels
+    /// wired together
+    #[arg(long, requires = "self_test")]
+    pub self_test_looped: bool,
+
+    /// Measure CAN1 -> CAN2 latency with timestamped probe frames and exit; needs the two
+    /// channels wired together
+    #[arg(long, conflicts_with_all = ["gateway", "replay", "tx_table", "cyclic", "send_signal", "listen_only", "channel_modes"])]
+    pub latency_test: bool,
+
+    /// Number of --latency-test probes
+    #[arg(long, default_value_t = 1000, requires = "latency_test")]
+    pub latency_count: u32,
+
+    /// --latency-test probes per second
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..=10000), requires = "latency_test")]
+    pub latency_rate: u32,
+
+    /// CAN ID of the --latency-test probes
+    #[arg(long, default_value = "7F0", value_parser = parse_id, requires = "latency_test")]
+    pub latency_id: Id,
+
+    /// Write one `seq,sent_us,latency_us` row per --latency-test probe to this CSV file
+    #[arg(long, requires = "latency_test")]
+    pub latency_csv: Option<PathBuf>,
+
+    /// Transmit back-to-back on CAN1 for each --benchmark-dlc, count what arrives on CAN2, report
+    /// frames/s and bus utilization and exit; needs the two channels wired together
+    #[arg(long, conflicts_with_all = ["gateway", "replay", "tx_table", "cyclic", "send_signal", "listen_only", "channel_modes", "latency_test"])]
+    pub benchmark: bool,
+
+    /// Seconds to transmit for each --benchmark DLC
+    #[arg(long, default_value_t = 5.0, requires = "benchmark")]
+    pub benchmark_secs: f64,
+
+    /// Payload lengths to benchmark, e.g. `--benchmark-dlc 0,8`
+    #[arg(long, value_delimiter = ',', default_values_t = [0u8, 4, 8], value_parser = clap::value_parser!(u8).range(0..=8), requires = "benchmark")]
+    pub benchmark_dlc: Vec<u8>,
+
+    /// Frames per VCI_Transmit call in --benchmark
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..=2500), requires = "benchmark")]
+    pub benchmark_batch: u32,
+
+    /// Listen on both channels for this many seconds, then print every unique ID seen with its
+    /// frame count, period, DLCs and changing bytes, and exit. `--output json` prints one
+    /// object per ID instead of the table
+    #[arg(long, conflicts_with_all = ["gateway", "replay", "fuzz", "tx_table", "cyclic", "send_signal", "latency_test", "benchmark"])]
+    pub discover: Option<f64>,
+
+    /// Also write the --discover summary to this CSV file
+    #[arg(long, requires = "discover")]
+    pub discover_csv: Option<PathBuf>,
+
+    /// Leave channels in bus-off instead of resetting and restarting them
+    #[arg(long)]
+    pub no_auto_recover: bool,
+
+    /// Act when a channel enters error-passive or bus-off for the Nth time this run (default the
+    /// first), as `FAULT[:N]=exec:COMMAND` or `FAULT[:N]=send:ID#DATA`, e.g.
+    /// `--fault-alert bus-off:3=exec:notify-send 'CAN bus-off'`. Commands run through the shell
+    /// with RUSTCANBUS_CHANNEL, RUSTCANBUS_FAULT and RUSTCANBUS_COUNT set; frames go out on the
+    /// channel that faulted once it's off bus-off. Can be given several times
+    #[arg(long, value_parser = parse_fault_alert)]
+    pub fault_alert: Vec<FaultAlert>,
+
+    /// USB-reset and reopen the adapter after this many consecutive failed receive/transmit
+    /// calls (0 disables)
+    #[arg(long, default_value_t = 20)]
+    pub usb_reset_after: u32,
+
+    /// Treat the adapter as unplugged after this many consecutive failed receive/transmit calls
+    /// and keep reopening it (0 disables)
+    #[arg(long, default_value_t = 0)]
+    pub reconnect_after: u32,
+
+    /// Milliseconds between reopen attempts while reconnecting
+    #[arg(long, default_value_t = 1000)]
+    pub reconnect_interval_ms: u64,
+
+    /// Width of the adapter's timestamp counter in bits, for firmware that wraps before 32
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u8).range(8..=32))]
+    pub timestamp_bits: u8,
+
+    /// Put CAN2's received frames on CAN1's clock so merged logs and displays order them right;
+    /// the estimated offset is reported at exit
+    #[arg(long, value_enum)]
+    pub align_channels: Option<ChannelAlign>,
+
+    /// Hold frames sent while disconnected and flush them on reconnect, instead of dropping them
+    #[arg(long)]
+    pub hold_tx: bool,
+
+    /// Transmit at most this many frames per second on each channel, whatever sends them:
+    /// cyclic messages, replay, fuzzing, the 't' prompt or the gateway
+    #[arg(long, value_parser = parse_tx_rate)]
+    pub tx_rate: Option<f64>,
+
+    /// Keep the bus load our own frames add on each channel below this percentage
+    #[arg(long, value_parser = parse_percent)]
+    pub tx_bus_load: Option<f64>,
+
+    /// Frames queued per channel for its TX thread, which sends them within --tx-rate and
+    /// --tx-bus-load; once it is full, senders wait for room
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..))]
+    pub tx_queue: u32,
+
+    /// Drop frames when the --tx-queue is full instead of waiting for room
+    #[arg(long)]
+    pub tx_drop_on_full: bool,
+
+    /// Milliseconds to keep offering frames the adapter's full transmit buffer didn't take,
+    /// backing off between attempts, before giving up on them (0 disables)
+    #[arg(long, default_value_t = 100)]
+    pub tx_retry_ms: u64,
+
+    /// List attached adapters and exit
+    #[arg(long)]
+    pub list_devices: bool,
+
+    /// Answer remote frames for ID with a data frame, e.g. `--rtr-reply 123=DEADBEEF`
+    /// (IDs above 0x7FF or written with 8 digits are extended)
+    #[arg(long, value_parser = parse_rtr_reply)]
+    pub rtr_reply: Vec<Frame>,
+
+    /// Rhai script run on every received frame through its `on_frame(frame)` function; it can
+    /// call transmit(ch, id, data), log(msg), set_cyclic(index, data) and set/get(name)
+    #[cfg(feature = "scripting")]
+    #[arg(long, value_name = "FILE")]
+    pub script: Option<PathBuf>,
+
+    /// Bridge to an MQTT broker: publish received frames and transmit frames published to
+    /// <prefix><channel>/tx
+    #[cfg(feature = "mqtt")]
+    #[arg(long, value_name = "HOST[:PORT]", value_parser = parse_broker)]
+    pub mqtt: Option<(String, u16)>,
+
+    /// Topic prefix for --mqtt; frames go to <prefix><channel>/<id>, signals of --dbc messages
+    /// to <prefix><channel>/<message>/<signal>
+    #[cfg(feature = "mqtt")]
+    #[arg(long, default_value = "can/", requires = "mqtt")]
+    pub topic_prefix: String,
+
+    /// MQTT QoS for publishing and the transmit subscription
+    #[cfg(feature = "mqtt")]
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=2), requires = "mqtt")]
+    pub mqtt_qos: u8,
+
+    /// MQTT client ID; defaults to rustcanbus-<pid>
+    #[cfg(feature = "mqtt")]
+    #[arg(long, requires = "mqtt")]
+    pub mqtt_client_id: Option<String>,
+
+    /// Seconds between reconnect attempts while the broker is unreachable
+    #[cfg(feature = "mqtt")]
+    #[arg(long, default_value_t = 5.0, requires = "mqtt")]
+    pub mqtt_reconnect: f64,
+
+    /// Messages buffered while the broker is unreachable; further ones are dropped
+    #[cfg(feature = "mqtt")]
+    #[arg(long, default_value_t = 10000, requires = "mqtt")]
+    pub mqtt_buffer: u32,
+
+    /// Frame processor for received frames, run in the order given: `rate-limit=HZ`,
+    /// `csv=FILE`, or the path of a plugin library with an optional `=CONFIG`
+    #[arg(long = "processor", value_name = "NAME[=CONFIG]", value_parser = parse_processor)]
+    pub processors: Vec<ProcessorSpec>,
+
+    /// Hardware acceptance filter: IDs or ranges the controller should pass, e.g.
+    /// `--accept 100-10F --accept 200`
+    #[arg(long, value_parser = parse_id_range)]
+    pub accept: Vec<(Id, Id)>,
+
+    /// Software filter in candump syntax: only show these IDs, e.g. `--filter 100-1FF,123:7FF`
+    /// (ID:MASK), `~150` to hide one (hiding wins), an `x` suffix for extended frames only.
+    /// Prefix an entry with `can0:` or `can1:` to filter that channel only, e.g. `can1:300`
+    #[arg(long, value_parser = parse_channel_filter, value_delimiter = ',')]
+    pub filter: Vec<ChannelFilter>,
+
+    /// Software filter: never show these IDs, like a `~` entry of --filter; takes the same
+    /// syntax and channel prefixes
+    #[arg(long, value_parser = parse_channel_filter, value_delimiter = ',')]
+    pub drop: Vec<ChannelFilter>,
+
+    /// Count an ID seen on both channels as one in the statistics and the monitor view, instead
+    /// of once per channel
+    #[arg(long)]
+    pub merge_channels: bool,
+
+    /// How received frames are printed
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// Don't color the text output. It is never colored when stdout isn't a terminal or
+    /// NO_COLOR is set
+    #[arg(long)]
+    pub no_color: bool,
+
+    /// TOML file assigning colors to IDs in the text output, e.g. `[ids]` `"0x123" = "yellow"`
+    #[arg(long)]
+    pub colors: Option<PathBuf>,
+
+    /// TOML file naming IDs, e.g. `0x321 = "BMS_Status"`, shown next to the ID in the output.
+    /// Names can be used wherever an ID is, e.g. `--filter BMS_Status` or `BMS_Status#00FF`
+    /// at the prompt
+    #[arg(long, value_name = "PATH")]
+    pub names: Option<PathBuf>,
+
+    /// Print every received frame as a scrolling line instead of the per-ID monitor view
+    #[arg(long)]
+    pub stream: bool,
+
+    /// Plot a byte or DBC signal as a sparkline under the monitor view, e.g. `0x321:byte2` or
+    /// `EngineSpeed`; repeat to stack plots. 'p' shows and hides them
+    #[arg(long, value_parser = parse_plot, conflicts_with = "stream")]
+    pub plot: Vec<PlotSpec>,
+
+    /// Samples each --plot keeps
+    #[arg(long, default_value_t = 200, value_parser = clap::value_parser!(u32).range(1..))]
+    pub plot_samples: u32,
+
+    /// Run without a console: no keyboard, monitor view or per-frame printing (unless --stream),
+    /// only SIGINT/SIGTERM to stop and a periodic status line. Implied when stdin isn't a
+    /// terminal
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Seconds between headless status lines; 0 disables them
+    #[arg(long, default_value_t = 60.0)]
+    pub status_interval: f64,
+
+    /// File the headless status lines are appended to, instead of stderr
+    #[arg(long, value_name = "PATH")]
+    pub status_log: Option<PathBuf>,
+
+    /// Write diagnostics as one JSON object per line, e.g. for a log shipper. Which ones is set
+    /// with RUST_LOG, `info` by default, e.g. `RUST_LOG=rustcanbus::device=trace`
+    #[arg(long)]
+    pub diag_json: bool,
+
+    /// File diagnostics are appended to, instead of stderr
+    #[arg(long, value_name = "PATH")]
+    pub diag_log: Option<PathBuf>,
+
+    /// How long the monitor view highlights bytes that changed, in milliseconds
+    #[arg(long, default_value_t = 1000)]
+    pub highlight_ms: u64,
+
+    /// With 'h' pressed in the monitor view, hide IDs whose data hasn't changed for this many
+    /// seconds
+    #[arg(long, default_value_t = 5.0)]
+    pub changed_within: f64,
+
+    /// Track per-ID rate and inter-arrival gaps (from device timestamps), show them in the monitor
+    /// view and print a summary on exit
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Also write the per-ID statistics to this CSV file on exit
+    #[arg(long)]
+    pub stats_csv: Option<PathBuf>,
+
+    /// Alert when a cyclic ID stops arriving, as `ID@PERIOD_MS[~TOLERANCE%]`, e.g.
+    /// `--expect 2A0@100~50`; the tolerance defaults to 50%. Without a terminal a timeout makes
+    /// the process exit non-zero
+    #[arg(long, value_parser = parse_expectation)]
+    pub expect: Vec<Expectation>,
+
+    /// Check a rolling counter and/or checksum in the frames of an ID, as
+    /// `ID:counter=BYTE[.lo|.hi],checksum=ALGO@BYTE[:FIRST-LAST]`, e.g.
+    /// `--integrity 0x123:counter=6.lo,checksum=crc8@7`. ALGO is xor, sum-complement or crc8;
+    /// the checksum covers the other payload bytes, or bytes FIRST to LAST. Skips and
+    /// failures are counted in the monitor view and summed up at the end
+    #[arg(long, value_parser = parse_integrity)]
+    pub integrity: Vec<(Id, IntegritySpec)>,
+
+    /// Write every received frame to this file
+    #[arg(long)]
+    pub log: Option<PathBuf>,
+
+    /// Format of the --log file
+    #[arg(long, value_enum, default_value_t = LogFormat::Candump, requires = "log")]
+    pub log_format: LogFormat,
+
+    /// Split the --log, --capture and --fuzz-log files, as `size=500M`, `duration=1h` or both,
+    /// plus `keep=N` to delete all but the newest N: `--log-rotate size=500M,duration=1h,keep=24`.
+    /// Each file is named after the given one with the UTC time it was opened, e.g.
+    /// `bus-20261014-093000.log`. Sizes take K, M or G (powers of 1024), durations s, m, h or d
+    #[arg(long, value_parser = parse_rotation, requires = "frame_logs")]
+    pub log_rotate: Option<Rotation>,
+
+    /// Compress the --log, --capture and --fuzz-log files as they're written, adding `.gz` or
+    /// `.zst` to their names. A file cut short by a crash or power loss still decompresses up to
+    /// its last few seconds
+    #[arg(long, value_enum, requires = "frame_logs")]
+    pub log_compress: Option<LogCompression>,
+
+    /// Keep recently received frames in memory and, when a --trigger fires, write those from
+    /// the pre-trigger window and everything received in the post-trigger window to this file
+    #[arg(long, requires = "trigger")]
+    pub capture: Option<PathBuf>,
+
+    /// Format of the --capture file
+    #[arg(long, value_enum, default_value_t = LogFormat::Candump, requires = "capture")]
+    pub capture_format: LogFormat,
+
+    /// What starts a --capture: `ID`, `ID#DATA/MASK` (the payload bits set in MASK equal those
+    /// of DATA; without a mask all of DATA must match), `errors+N` (an error counter rising by
+    /// N or more) or `key` (pressing 'g'). Can be given several times
+    #[arg(long, value_parser = parse_trigger, requires = "capture")]
+    pub trigger: Vec<Trigger>,
+
+    /// Seconds of frames before the trigger to write
+    #[arg(long, default_value_t = 5.0, requires = "capture")]
+    pub pre_trigger: f64,
+
+    /// Seconds to keep writing after the trigger; a trigger meanwhile extends the capture
+    #[arg(long, default_value_t = 5.0, requires = "capture")]
+    pub post_trigger: f64,
+
+    /// The most frames kept from before a trigger
+    #[arg(long, default_value_t = 100_000, value_parser = clap::value_parser!(u32).range(1..), requires = "capture")]
+    pub capture_frames: u32,
+
+    /// Wait for the next trigger after each capture, numbering the files (`FILE-1.log`,
+    /// `FILE-2.log`, ...), instead of stopping after the first
+    #[arg(long, requires = "capture")]
+    pub rearm: bool,
+
+    /// Cyclic message for the transmit demo as `ID#DATA@PERIOD_MS`, e.g. `--cyclic 100#0102@10
+    /// --cyclic 18FF50E5x#00@1000`; without one the demo sends `001#01` every 10 ms
+    #[arg(long, value_parser = parse_cyclic)]
+    pub cyclic: Vec<(Frame, Duration)>,
+
+    /// Cyclic messages from a TOML transmit table (one `[name]` table per message with id, channel,
+    /// period_ms, data and optional extended, start_delay_ms and repeat); 'r' reloads it
+    #[arg(long)]
+    pub tx_table: Option<PathBuf>,
+
+    /// Print the parsed --tx-table schedule and exit without opening the device
+    #[arg(long, requires = "tx_table")]
+    pub dry_run: bool,
+
+    /// Transmit the frames of a candump log, or of a PCAN-View `.trc` trace (versions 1.1, 2.0
+    /// and 2.1), with their recorded timing instead of the demo
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+
+    /// Replay speed factor (2.0 plays twice as fast)
+    #[arg(long, default_value_t = 1.0, requires = "replay")]
+    pub speed: f64,
+
+    /// Restart the replay from the beginning when it reaches the end
+    #[arg(long = "loop", requires = "replay")]
+    pub loop_replay: bool,
+
+    /// Send every replayed frame on this channel instead of the one recorded in the log
+    #[arg(long, requires = "replay", value_parser = clap::value_parser!(u32).range(0..=1))]
+    pub replay_channel: Option<u32>,
+
+    /// Transmit randomized frames on --channel instead of the demo, for robustness testing. The
+    /// seed is printed, so a run can be repeated exactly with --fuzz-seed
+    #[arg(long, conflicts_with_all = ["gateway", "replay", "tx_table", "cyclic", "listen_only", "channel_modes", "latency_test", "benchmark"])]
+    pub fuzz: bool,
+
+    /// IDs to fuzz, as ID or FIRST-LAST
+    #[arg(long, default_value = "000-7FF", value_parser = parse_id_range, requires = "fuzz")]
+    pub fuzz_ids: (Id, Id),
+
+    /// Payload lengths to fuzz, as LEN or MIN-MAX
+    #[arg(long, default_value = "0-8", value_parser = parse_dlc_range, requires = "fuzz")]
+    pub fuzz_dlc: (u8, u8),
+
+    /// Fuzzed frames per second, at most 1000
+    #[arg(long, default_value_t = 100.0, value_parser = parse_fuzz_rate, requires = "fuzz")]
+    pub fuzz_rate: f64,
+
+    /// Seed for the fuzzer; random if not given
+    #[arg(long, requires = "fuzz")]
+    pub fuzz_seed: Option<u64>,
+
+    /// Send --fuzz-weight percent of the fuzzed frames on this ID
+    #[arg(long, value_parser = parse_id, requires = "fuzz")]
+    pub fuzz_target: Option<Id>,
+
+    /// Share of the fuzzed frames sent on --fuzz-target, in percent
+    #[arg(long, default_value_t = 80, value_parser = clap::value_parser!(u32).range(0..=100), requires = "fuzz_target")]
+    pub fuzz_weight: u32,
+
+    /// Instead of random frames, send this `ID#DATA` frame with one payload bit flipped per
+    /// frame, walking through every bit in turn
+    #[arg(long, value_parser = parse_frame, requires = "fuzz", conflicts_with = "fuzz_target")]
+    pub fuzz_bit_flip: Option<Frame>,
+
+    /// Stop fuzzing after this many frames
+    #[arg(long, requires = "fuzz")]
+    pub fuzz_count: Option<u64>,
+
+    /// Stop fuzzing after this many seconds
+    #[arg(long, requires = "fuzz")]
+    pub fuzz_duration: Option<f64>,
+
+    /// Write every fuzzed frame to this candump log, for --replay to send again
+    #[arg(long, requires = "fuzz")]
+    pub fuzz_log: Option<PathBuf>,
+
+    /// Also write the decoded signals of every --dbc message to MDF logs, one channel group per
+    /// message
+    #[arg(long, requires = "dbc")]
+    pub mdf_signals: bool,
+
+    /// Also log frames we transmit (marked Tx where the format supports it)
+    #[arg(long, requires = "log")]
+    pub log_tx: bool,
+
+    /// Decode received frames with the messages and signals of this DBC file
+    #[arg(long)]
+    pub dbc: Option<PathBuf>,
+
+    /// Show extended IDs as J1939 priority, PGN, source and destination, naming and decoding
+    /// the parameter groups the tool knows (EEC1, CCVS, DM1, ...)
+    #[arg(long)]
+    pub j1939: bool,
+
+    /// Show extended IDs as NMEA 2000 PGN and source, reassembling fast packets and decoding
+    /// heading, depth, position, COG/SOG and wind
+    #[arg(long)]
+    pub nmea2000: bool,
+
+    /// With --j1939 or --nmea2000, give the monitor view one row per PGN and source address
+    /// rather than per raw ID
+    #[arg(long, requires = "pgn_ids")]
+    pub group_by_pgn: bool,
+
+    /// Encode `Message.Signal=value` assignments with the --dbc definitions, send one frame per
+    /// message on --channel and exit, e.g. `--send-signal EngineCmd.TargetRPM=1500,EngineCmd.Enable=1`
+    #[arg(long, value_parser = parse_signal_value, value_delimiter = ',', requires = "dbc")]
+    pub send_signal: Vec<SignalAssignment>,
+
+    /// Fail instead of clamping when a --send-signal value is outside the signal's range
+    #[arg(long, requires = "send_signal")]
+    pub reject_out_of_range: bool,
+
+    /// Print adapter information and the optional DLL functions it lacks, then exit without
+    /// initializing CAN
+    #[arg(long)]
+    pub info: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Args {
+    /// The last --channel-bitrate for `slot` (0 = CAN1), else --bitrate.
+    pub fn bitrate_for(&self, slot: u32) -> Bitrate {
+        self.channel_bitrates.iter().rev().find(|(channel, _)| *channel == slot).map_or(self.bitrate, |&(_, bitrate)| bitrate)
+    }
+
+    /// The last --channel-mode for `slot`, else listen-only with --listen-only.
+    pub fn mode_for(&self, slot: u32) -> ChannelMode {
+        let default = if self.listen_only { ChannelMode::ListenOnly } else { ChannelMode::Normal };
+        self.channel_modes.iter().rev().find(|(channel, _)| *channel == slot).map_or(default, |&(_, mode)| mode)
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Send one UDS request over ISO-TP on --channel, print the response and exit
+    Uds(UdsArgs),
+    /// Poll OBD-II mode 01 PIDs from every ECU on --channel until Ctrl+C, as a live table or
+    /// with `--output json` one JSON object per reading
+    Obd(ObdArgs),
+    /// CANopen on --channel: watch heartbeats, send NMT commands or make expedited SDO transfers
+    Canopen {
+        #[command(subcommand)]
+        command: CanopenCommand,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum CanopenCommand {
+    /// Show each node's NMT state from its heartbeats until Ctrl+C
+    Heartbeat {
+        /// Report a node lost after this many milliseconds without a heartbeat
+        #[arg(long, default_value_t = 3000)]
+        timeout_ms: u64,
+    },
+    /// Send an NMT command to a node, or to all nodes with node 0
+    Nmt {
+        #[arg(value_enum)]
+        command: NmtAction,
+        #[arg(value_parser = parse_nmt_node)]
+        node: u8,
+    },
+    /// Read or write an object dictionary entry with an expedited SDO transfer (up to 4 bytes)
+    Sdo {
+        /// Milliseconds to wait for the node's answer
+        #[arg(long, default_value_t = 1000)]
+        timeout_ms: u64,
+
+        #[command(subcommand)]
+        request: SdoRequest,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NmtAction {
+    Start,
+    Stop,
+    PreOperational,
+    Reset,
+    ResetComm,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum SdoRequest {
+    /// Upload an entry, e.g. `sdo read 0x32 0x1018 0x01`
+    Read {
+        #[arg(value_parser = parse_node)]
+        node: u8,
+        #[arg(value_parser = parse_u16)]
+        index: u16,
+        #[arg(value_parser = parse_byte)]
+        subindex: u8,
+    },
+    /// Download 1 to 4 bytes given in hex, least significant first, e.g. `sdo write 0x32 0x1017 0x00 E803`
+    Write {
+        #[arg(value_parser = parse_node)]
+        node: u8,
+        #[arg(value_parser = parse_u16)]
+        index: u16,
+        #[arg(value_parser = parse_byte)]
+        subindex: u8,
+        #[arg(value_parser = parse_hex_bytes)]
+        data: HexBytes,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ObdArgs {
+    /// Mode 01 PIDs to poll, in hex; ones no ECU supports are skipped
+    #[arg(long = "pid", value_parser = parse_byte, value_delimiter = ',', default_values = ["0C", "0D", "05", "11"])]
+    pub pids: Vec<u8>,
+
+    /// Milliseconds between polls of the PID list
+    #[arg(long, default_value_t = 500)]
+    pub interval_ms: u64,
+
+    /// Milliseconds to wait for ECUs to answer each request
+    #[arg(long, default_value_t = 100)]
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct UdsArgs {
+    /// Request CAN ID
+    #[arg(long, value_parser = parse_id)]
+    pub tx: Id,
+
+    /// Response CAN ID
+    #[arg(long, value_parser = parse_id)]
+    pub rx: Id,
+
+    /// Extended addressing as REQUEST:RESPONSE address bytes in hex, e.g. `10:F1`
+    #[arg(long, value_name = "TX:RX", value_parser = parse_address_pair)]
+    pub ext_addr: Option<(u8, u8)>,
+
+    /// Milliseconds the ECU has to start answering (P2)
+    #[arg(long, default_value_t = 150)]
+    pub p2_ms: u64,
+
+    /// Milliseconds to wait after each response-pending (P2*)
+    #[arg(long, default_value_t = 5000)]
+    pub p2_star_ms: u64,
+
+    /// Send TesterPresent every this many milliseconds while the request runs (0 disables)
+    #[arg(long, default_value_t = 0)]
+    pub tester_present_ms: u64,
+
+    #[command(subcommand)]
+    pub request: UdsRequest,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum UdsRequest {
+    /// DiagnosticSessionControl (0x10): 1 default, 2 programming, 3 extended
+    Session {
+        #[arg(value_parser = parse_byte)]
+        session: u8,
+    },
+    /// ECUReset (0x11): 1 hard, 2 key off/on, 3 soft
+    Reset {
+        #[arg(value_parser = parse_byte, default_value = "1")]
+        reset_type: u8,
+    },
+    /// ReadDataByIdentifier (0x22)
+    ReadDid {
+        #[arg(value_parser = parse_u16)]
+        did: u16,
+    },
+    /// WriteDataByIdentifier (0x2E) with hex data, e.g. `write-did 0xF190 57303132`
+    WriteDid {
+        #[arg(value_parser = parse_u16)]
+        did: u16,
+        #[arg(value_parser = parse_hex_bytes)]
+        data: HexBytes,
+    },
+    /// RoutineControl (0x31): control 1 start, 2 stop, 3 request results
+    Routine {
+        #[arg(value_parser = parse_byte)]
+        control: u8,
+        #[arg(value_parser = parse_u16)]
+        routine: u16,
+        #[arg(value_parser = parse_hex_bytes)]
+        data: Option<HexBytes>,
+    },
+    /// TesterPresent (0x3E)
+    TesterPresent,
+}
+
+/// Bytes given as a hex string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexBytes(pub Vec<u8>);
+
+/// Leaked so the output can hold on to it; a profile loaded while running replaces it.
+static NAMES: RwLock<Option<&'static IdNames>> = RwLock::new(None);
+
+/// Loads the `--names` file from the raw arguments before clap parses them, so the ID
+/// arguments can take names.
+pub fn load_names(args: impl IntoIterator<Item = OsString>) -> Result<(), String> {
+    let mut args = args.into_iter().skip(1);
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy().into_owned();
+        if arg == "--" {
+            break;
+        } else if arg == "--names" {
+            path = args.next().map(PathBuf::from);
+        } else if let Some(value) = arg.strip_prefix("--names=") {
+            path = Some(PathBuf::from(value));
+        }
+    }
+    let Some(path) = path else {
+        *NAMES.write().unwrap() = None;
+        return Ok(());
+    };
+    let text = std::fs::read_to_string(&path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let names = IdNames::parse(&text).map_err(|err| format!("{}: {err}", path.display()))?;
+    *NAMES.write().unwrap() = Some(Box::leak(Box::new(names)));
+    Ok(())
+}
+
+pub fn names() -> Option<&'static IdNames> {
+    *NAMES.read().unwrap()
+}
+
+/// Puts back what [`names`] returned, after loading another profile's to check it.
+pub fn set_names(names: Option<&'static IdNames>) {
+    *NAMES.write().unwrap() = names;
+}
+
+/// `s` with any ID names replaced by their IDs.
+fn expand_names(s: &str) -> Result<Cow<'_, str>, String> {
+    match names() {
+        Some(names) => names.expand(s).map(Cow::Owned),
+        None => Ok(Cow::Borrowed(s)),
+    }
+}
+
+/// [`parse_frame_spec`], with an ID name allowed in place of the ID, e.g. `BMS_Status#00FF`.
+pub fn parse_frame(s: &str) -> Result<Frame, String> {
+    match s.split_once('#') {
+        Some((id, rest)) => parse_frame_spec(&format!("{}#{rest}", expand_names(id)?)),
+        None => parse_frame_spec(s),
+    }
+}
+
+/// Parses a hex CAN ID (`0x` prefix optional) or a `--names` name. IDs above 0x7FF or written
+/// with more than three digits are extended.
+pub fn parse_id(s: &str) -> Result<Id, String> {
+    let s = expand_names(s)?;
+    let s = s.trim();
+    let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+    let raw = u32::from_str_radix(digits, 16).map_err(|_| format!("invalid CAN ID '{s}'"))?;
+    if digits.len() > 3 || raw > Id::MAX_STANDARD as u32 {
+        Id::extended(raw).ok_or(format!("CAN ID '{s}' exceeds 29 bits"))
+    } else {
+        Ok(Id::Standard(raw as u16))
+    }
+}
+
+/// Parses `ID` or `FIRST-LAST` into an inclusive range.
+pub fn parse_id_range(s: &str) -> Result<(Id, Id), String> {
+    match s.split_once('-') {
+        Some((first, last)) => {
+            let (first, last) = (parse_id(first)?, parse_id(last)?);
+            if first.is_extended() != last.is_extended() {
+                return Err(format!("range '{s}' mixes standard and extended IDs"));
+            }
+            Ok((first, last))
+        }
+        None => parse_id(s).map(|id| (id, id)),
+    }
+}
+
+/// Entry of --filter/--drop, optionally limited to one channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelFilter {
+    /// `None` applies to both channels.
+    pub channel: Option<u32>,
+    pub term: FilterTerm,
+}
+
+/// `[canN:]TERM`; `N` counts from 0 like `--channel`.
+pub fn parse_channel_filter(s: &str) -> Result<ChannelFilter, String> {
+    let s = s.trim();
+    let (channel, term) = match s.strip_prefix("can").and_then(|rest| rest.split_once(':')) {
+        Some((channel, term)) => match channel.parse() {
+            Ok(channel) if channel < CHANNEL_COUNT => (Some(channel), term),
+            _ => return Err(format!("invalid channel 'can{channel}', expected can0 or can1")),
+        },
+        None => (None, s),
+    };
+    Ok(ChannelFilter { channel, term: expand_names(term)?.parse()? })
+}
+
+/// `canN:VALUE` of --channel-bitrate and --channel-mode.
+fn parse_channel_setting<T: FromStr<Err = String>>(s: &str) -> Result<(u32, T), String> {
+    let (channel, value) = s.trim().split_once(':').ok_or("expected canN:VALUE, e.g. can1:125k")?;
+    let index = channel.strip_prefix("can").and_then(|n| n.parse().ok()).filter(|&n| n < CHANNEL_COUNT);
+    let index = index.ok_or_else(|| format!("invalid channel '{channel}', expected can0 or can1"))?;
+    Ok((index, value.parse()?))
+}
+
+/// What --plot draws.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlotSpec {
+    Byte { id: Id, index: usize },
+    /// Looked up in the --dbc file.
+    Signal(String),
+}
+
+/// `ID:byteN` with `N` from 0 to 7, or a signal name.
+fn parse_plot(s: &str) -> Result<PlotSpec, String> {
+    let s = s.trim();
+    match s.rsplit_once(':') {
+        Some((id, byte)) => {
+            let index = byte
+                .strip_prefix("byte")
+                .and_then(|index| index.parse().ok())
+                .filter(|&index| index < 8)
+                .ok_or_else(|| format!("invalid byte '{byte}', expected byte0 to byte7"))?;
+            Ok(PlotSpec::Byte { id: parse_id(id)?, index })
+        }
+        None if s.is_empty() => Err("empty plot".to_string()),
+        None => Ok(PlotSpec::Signal(s.to_string())),
+    }
+}
+
+/// `HOST` or `HOST:PORT`, with MQTT's 1883 when the port is left out.
+#[cfg(feature = "mqtt")]
+fn parse_broker(s: &str) -> Result<(String, u16), String> {
+    match s.rsplit_once(':') {
+        Some((host, port)) => Ok((host.to_string(), port.parse().map_err(|_| format!("invalid port '{port}'"))?)),
+        None => Ok((s.to_string(), 1883)),
+    }
+}
+
+/// `ADAPTER:PORT`; the port may be written `can0`/`can1` or just `0`/`1`.
+fn parse_port(s: &str) -> Result<(u32, u32), String> {
+    let (adapter, port) = s.split_once(':').ok_or("expected ADAPTER:PORT, e.g. 0:can1")?;
+    let adapter = adapter.trim().parse().map_err(|_| format!("invalid adapter index '{adapter}'"))?;
+    let port = port.trim();
+    let port = port.strip_prefix("can").unwrap_or(port);
+    match port.parse() {
+        Ok(port) if port < CHANNEL_COUNT => Ok((adapter, port)),
+        _ => Err(format!("invalid port '{port}', expected can0 or can1")),
+    }
+}
+
+/// Percent with up to one decimal, as permille.
+fn parse_sample_point(s: &str) -> Result<u16, String> {
+    let percent: f64 = s.trim().trim_end_matches('%').parse().map_err(|_| format!("invalid sample point '{s}'"))?;
+    if !(50.0..=95.0).contains(&percent) {
+        return Err(format!("sample point {percent}% is outside 50-95%"));
+    }
+    Ok((percent * 10.0).round() as u16)
+}
+
+fn parse_integrity(s: &str) -> Result<(Id, IntegritySpec), String> {
+    let (id, fields) = s.split_once(':').ok_or("expected ID:counter=BYTE[.lo|.hi],checksum=ALGO@BYTE")?;
+    let byte = |text: &str| text.trim().parse::<usize>().ok().filter(|&byte| byte < 8).ok_or(format!("invalid byte position '{text}', expected 0-7"));
+    let mut spec = IntegritySpec::default();
+    for field in fields.split(',') {
+        match field.split_once('=') {
+            Some(("counter", position)) => {
+                spec.counter = Some(match position.split_once('.') {
+                    Some((at, "lo")) => CounterField::low_nibble(byte(at)?),
+                    Some((at, "hi")) => CounterField::high_nibble(byte(at)?),
+                    Some((_, nibble)) => return Err(format!("invalid nibble '{nibble}', expected lo or hi")),
+                    None => CounterField::whole_byte(byte(position)?),
+                })
+            }
+            Some(("checksum", checksum)) => {
+                let (algorithm, position) = checksum.split_once('@').ok_or("expected checksum=ALGO@BYTE")?;
+                let (at, range) = match position.split_once(':') {
+                    Some((at, range)) => {
+                        let (first, last) = range.split_once('-').ok_or(format!("invalid byte range '{range}'"))?;
+                        let (first, last) = (byte(first)?, byte(last)?);
+                        if first > last {
+                            return Err(format!("empty byte range '{range}'"));
+                        }
+                        (at, Some(first..last + 1))
+                    }
+                    None => (position, None),
+                };
+                spec.checksum = Some(ChecksumField { algorithm: algorithm.parse()?, byte: byte(at)?, range });
+            }
+            _ => return Err(format!("unknown field '{field}', expected counter=... or checksum=...")),
+        }
+    }
+    Ok((parse_id(id)?, spec))
+}
+
+/// `ID`, `ID#DATA[/MASK]`, `errors+N` or `key`.
+fn parse_trigger(s: &str) -> Result<Trigger, String> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("key") {
+        return Ok(Trigger::Manual);
+    }
+    if let Some(threshold) = s.strip_prefix("errors+") {
+        return match threshold.trim().parse::<u8>() {
+            Ok(threshold) if threshold > 0 => Ok(Trigger::ErrorJump(threshold)),
+            _ => Err(format!("invalid error counter jump '{threshold}', expected 1-255")),
+        };
+    }
+    let Some((id, pattern)) = s.split_once('#') else {
+        return parse_id(s).map(Trigger::Id);
+    };
+    let (data, mask) = match pattern.split_once('/') {
+        Some((data, mask)) => (parse_hex_bytes(data)?.0, parse_hex_bytes(mask)?.0),
+        None => {
+            let data = parse_hex_bytes(pattern)?.0;
+            let mask = vec![0xFF; data.len()];
+            (data, mask)
+        }
+    };
+    if data.len() != mask.len() || data.len() > 8 {
+        return Err(format!("'{pattern}' needs DATA and MASK of the same length, at most 8 bytes"));
+    }
+    Ok(Trigger::Match { id: parse_id(id)?, data, mask })
+}
+
+fn parse_dlc_range(s: &str) -> Result<(u8, u8), String> {
+    let (min, max) = s.split_once('-').unwrap_or((s, s));
+    let parse = |len: &str| len.trim().parse::<u8>().ok().filter(|&len| len <= 8).ok_or(format!("invalid length '{len}', expected 0-8"));
+    let (min, max) = (parse(min)?, parse(max)?);
+    if min > max {
+        return Err(format!("empty length range '{s}'"));
+    }
+    Ok((min, max))
+}
+
+fn parse_fuzz_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s.trim().parse().map_err(|_| format!("invalid rate '{s}'"))?;
+    if !(rate > 0.0 && rate <= 1000.0) {
+        return Err(format!("rate {rate} is not above 0 and at most 1000 frames per second"));
+    }
+    Ok(rate)
+}
+
+fn parse_tx_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s.trim().parse().map_err(|_| format!("invalid rate '{s}'"))?;
+    if !(rate > 0.0 && rate.is_finite()) {
+        return Err(format!("rate {rate} is not above 0 frames per second"));
+    }
+    Ok(rate)
+}
+
+fn parse_percent(s: &str) -> Result<f64, String> {
+    let percent: f64 = s.trim().trim_end_matches('%').parse().map_err(|_| format!("invalid percentage '{s}'"))?;
+    if !(percent > 0.0 && percent <= 100.0) {
+        return Err(format!("{percent}% is not above 0 and at most 100%"));
+    }
+    Ok(percent)
+}
+
+pub fn parse_cyclic(s: &str) -> Result<(Frame, Duration), String> {
+    let (frame, period) = s.rsplit_once('@').ok_or("expected ID#DATA@PERIOD_MS")?;
+    let period: u64 = period.trim().parse().map_err(|_| format!("invalid period '{period}'"))?;
+    if period == 0 {
+        return Err("period must be at least 1 ms".to_string());
+    }
+    Ok((parse_frame(frame)?, Duration::from_millis(period)))
+}
+
+fn parse_rotation(s: &str) -> Result<Rotation, String> {
+    let mut rotation = Rotation::default();
+    for field in s.split(',') {
+        let (key, value) = field.split_once('=').ok_or("expected size=SIZE, duration=TIME or keep=N")?;
+        let value = value.trim();
+        let (number, unit) = value.split_at(value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len()));
+        let number: u64 = number.parse().ok().filter(|&n| n > 0).ok_or(format!("invalid {} '{value}'", key.trim()))?;
+        match (key.trim(), unit.to_ascii_lowercase().as_str()) {
+            ("size", unit @ ("" | "k" | "m" | "g")) => {
+                let shift = match unit {
+                    "k" => 10,
+                    "m" => 20,
+                    "g" => 30,
+                    _ => 0,
+                };
+                rotation.max_bytes = Some(number.checked_shl(shift).filter(|bytes| bytes >> shift == number).ok_or(format!("size '{value}' is too large"))?);
+            }
+            ("duration", unit @ ("s" | "m" | "h" | "d")) => {
+                let seconds = match unit {
+                    "s" => 1,
+                    "m" => 60,
+                    "h" => 3_600,
+                    _ => 86_400,
+                };
+                rotation.max_age = Some(Duration::from_secs(number.saturating_mul(seconds)));
+            }
+            ("keep", "") => rotation.keep = Some(number as usize),
+            ("size", _) => return Err(format!("invalid size '{value}', expected e.g. 500M")),
+            ("duration", _) => return Err(format!("invalid duration '{value}', expected e.g. 30m or 1h")),
+            ("keep", _) => return Err(format!("invalid keep '{value}', expected a number of files")),
+            (key, _) => return Err(format!("unknown rotation setting '{key}', expected size, duration or keep")),
+        }
+    }
+    if rotation.max_bytes.is_none() && rotation.max_age.is_none() {
+        return Err("expected a size= or duration= to rotate at".to_string());
+    }
+    Ok(rotation)
+}
+
+fn parse_expectation(s: &str) -> Result<Expectation, String> {
+    let (id, rest) = s.split_once('@').ok_or("expected ID@PERIOD_MS[~TOLERANCE%]")?;
+    let (period, tolerance) = match rest.split_once(['~', '±']) {
+        Some((period, tolerance)) => (period, Some(tolerance)),
+        None => (rest, None),
+    };
+    let period = period.trim().trim_end_matches("ms");
+    let period: u64 = period.parse().map_err(|_| format!("invalid period '{period}'"))?;
+    if period == 0 {
+        return Err("period must be at least 1 ms".to_string());
+    }
+    let tolerance = match tolerance {
+        Some(t) => {
+            let t = t.trim().trim_end_matches('%');
+            let percent: f64 = t.parse().map_err(|_| format!("invalid tolerance '{t}'"))?;
+            if !(0.0..=1000.0).contains(&percent) {
+                return Err(format!("tolerance {percent}% is outside 0-1000%"));
+            }
+            percent / 100.0
+        }
+        None => 0.5,
+    };
+    Ok(Expectation { id: parse_id(id)?, period: Duration::from_millis(period), tolerance })
+}
+
+/// `FAULT[:N]=exec:COMMAND` or `FAULT[:N]=send:ID#DATA`.
+fn parse_fault_alert(s: &str) -> Result<FaultAlert, String> {
+    let (when, action) = s.split_once('=').ok_or("expected FAULT[:N]=exec:COMMAND or FAULT[:N]=send:ID#DATA")?;
+    let (fault, count) = match when.split_once(':') {
+        Some((fault, count)) => (fault, count.trim().parse::<u64>().ok().filter(|&count| count > 0).ok_or(format!("invalid count '{count}'"))?),
+        None => (when, 1),
+    };
+    let fault = match fault.trim() {
+        "error-passive" => Fault::ErrorPassive,
+        "bus-off" => Fault::BusOff,
+        fault => return Err(format!("unknown fault '{fault}', expected error-passive or bus-off")),
+    };
+    let action = match action.split_once(':') {
+        Some(("exec", command)) if !command.trim().is_empty() => FaultAction::Exec(command.to_string()),
+        Some(("send", frame)) => FaultAction::Send(parse_frame(frame)?),
+        _ => return Err(format!("unknown action '{action}', expected exec:COMMAND or send:ID#DATA")),
+    };
+    Ok(FaultAlert { fault, count, action })
+}
+
+/// `NAME[=CONFIG]` from --processor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessorSpec {
+    pub name: String,
+    pub config: String,
+}
+
+fn parse_processor(s: &str) -> Result<ProcessorSpec, String> {
+    let (name, config) = s.split_once('=').unwrap_or((s, ""));
+    if name.is_empty() {
+        return Err("missing processor name".to_string());
+    }
+    Ok(ProcessorSpec { name: name.to_string(), config: config.to_string() })
+}
+
+/// `Message.Signal=value` from --send-signal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignalAssignment {
+    pub message: String,
+    pub signal: String,
+    pub value: f64,
+}
+
+fn parse_signal_value(s: &str) -> Result<SignalAssignment, String> {
+    let (path, value) = s.split_once('=').ok_or("expected Message.Signal=value")?;
+    let (message, signal) = path.trim().split_once('.').ok_or("expected Message.Signal=value")?;
+    let value = value.trim().parse().map_err(|_| format!("invalid signal value '{value}'"))?;
+    Ok(SignalAssignment { message: message.to_string(), signal: signal.to_string(), value })
+}
+
+fn parse_rtr_reply(s: &str) -> Result<Frame, String> {
+    let (id, data) = s.split_once('=').ok_or("expected ID=HEXDATA")?;
+    let id = parse_id(id)?;
+    let HexBytes(bytes) = parse_hex_bytes(data)?;
+    Frame::new(id, &bytes).ok_or(format!("'{data}' is longer than 8 bytes"))
+}
+
+fn parse_hex_bytes(data: &str) -> Result<HexBytes, String> {
+    if !data.is_ascii() || !data.len().is_multiple_of(2) {
+        return Err(format!("expected an even number of hex digits, got '{data}'"));
+    }
+    (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&data[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map(HexBytes)
+        .map_err(|_| format!("invalid hex data '{data}'"))
+}
+
+/// A hex byte, `0x` prefix optional.
+fn parse_byte(s: &str) -> Result<u8, String> {
+    let digits = s.trim().trim_start_matches("0x").trim_start_matches("0X");
+    u8::from_str_radix(digits, 16).map_err(|_| format!("invalid hex byte '{s}'"))
+}
+
+/// A hex 16-bit identifier such as a DID, `0x` prefix optional.
+fn parse_u16(s: &str) -> Result<u16, String> {
+    let digits = s.trim().trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid 16-bit hex value '{s}'"))
+}
+
+/// A CANopen node ID, 1-127 in hex.
+fn parse_node(s: &str) -> Result<u8, String> {
+    match parse_byte(s)? {
+        node @ 1..=127 => Ok(node),
+        node => Err(format!("node ID 0x{node:02X} is outside 0x01-0x7F")),
+    }
+}
+
+/// A node ID, or 0 for all nodes.
+fn parse_nmt_node(s: &str) -> Result<u8, String> {
+    match parse_byte(s)? {
+        0 => Ok(0),
+        _ => parse_node(s),
+    }
+}
+
+fn parse_address_pair(s: &str) -> Result<(u8, u8), String> {
+    let (tx, rx) = s.split_once(':').ok_or("expected TX:RX, e.g. 10:F1")?;
+    Ok((parse_byte(tx)?, parse_byte(rx)?))
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::RwLock;
+
+use crossterm::style::{Color, Stylize};
+use rustcanbus::Id;
+use toml::{Table, Value};
+
+/// CAN1 and CAN2.
+const CHANNEL_COLORS: [Color; 2] = [Color::Cyan, Color::Magenta];
+
+/// Cycled over IDs without an assigned color. Red is left for errors.
+const PALETTE: [Color; 6] = [Color::Green, Color::Yellow, Color::Blue, Color::DarkCyan, Color::DarkYellow, Color::DarkMagenta];
+
+static COLORS: RwLock<Option<&'static Colors>> = RwLock::new(None);
+
+/// How the text output is colored, set with [`init`]. Until then nothing is.
+#[derive(Debug, Default)]
+pub struct Colors {
+    pub enabled: bool,
+    /// From `--colors`; the rest of the IDs take a palette color.
+    pub ids: HashMap<Id, Color>,
+}
+
+/// Leaks `colors`, which is set once per run.
+pub fn init(colors: Colors) {
+    *COLORS.write().unwrap() = Some(Box::leak(Box::new(colors)));
+}
+
+fn colors() -> Option<&'static Colors> {
+    COLORS.read().unwrap().filter(|colors| colors.enabled)
+}
+
+pub fn channel_color(channel: u32) -> Option<Color> {
+    colors().map(|_| CHANNEL_COLORS[channel as usize % CHANNEL_COLORS.len()])
+}
+
+/// The same for every frame of an ID, so it can be picked out as it scrolls by.
+pub fn id_color(id: Id) -> Option<Color> {
+    let colors = colors()?;
+    let index = (id.raw() as usize).wrapping_add(usize::from(id.is_extended()) * 7) % PALETTE.len();
+    Some(colors.ids.get(&id).copied().unwrap_or(PALETTE[index]))
+}
+
+pub fn channel(channel: u32, text: &str) -> String {
+    paint(text, channel_color(channel))
+}
+
+pub fn id(id: Id, text: &str) -> String {
+    paint(text, id_color(id))
+}
+
+fn paint(text: &str, color: Option<Color>) -> String {
+    match color {
+        Some(color) => text.with(color).to_string(),
+        None => text.to_string(),
+    }
+}
+
+/// Reads `--colors`: an `[ids]` table of IDs and color names, e.g. `"0x123" = "yellow"` or
+/// `"0x18FEF100" = "dark_green"`. IDs over three digits are extended.
+pub fn load_id_colors(path: &Path) -> Result<HashMap<Id, Color>, String> {
+    let text = std::fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let table: Table = text.parse().map_err(|err: toml::de::Error| format!("{}: {}", path.display(), err.message()))?;
+    let mut ids = HashMap::new();
+    let Some(entries) = table.get("ids") else {
+        return Ok(ids);
+    };
+    let entries = entries.as_table().ok_or_else(|| format!("{}: `ids` must be a table", path.display()))?;
+    for (key, value) in entries {
+        let id = crate::cli::parse_id(key).map_err(|err| format!("{}: {err}", path.display()))?;
+        let color = match value {
+            Value::String(name) => Color::try_from(name.as_str())
+                .map_err(|()| format!("{}: unknown color '{name}' for {key}", path.display()))?,
+            _ => return Err(format!("{}: the color for {key} must be a string", path.display())),
+        };
+        ids.insert(id, color);
+    }
+    Ok(ids)
+}
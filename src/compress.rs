@@ -0,0 +1,120 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use flate2::write::GzEncoder;
+
+/// Output is closed off into a complete gzip member or zstd frame after this much input...
+const BOUNDARY_BYTES: u64 = 1 << 20;
+/// ...or at the first write this long after the last boundary.
+const BOUNDARY_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// The file name extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Gzip => "gz",
+            Self::Zstd => "zst",
+        }
+    }
+}
+
+enum Encoder<W: Write> {
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    fn new(compression: Compression, out: W) -> io::Result<Self> {
+        Ok(match compression {
+            Compression::Gzip => Self::Gzip(GzEncoder::new(out, flate2::Compression::default())),
+            Compression::Zstd => {
+                let mut encoder = zstd::Encoder::new(out, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+                encoder.include_checksum(true)?;
+                Self::Zstd(encoder)
+            }
+        })
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Self::Gzip(encoder) => encoder,
+            Self::Zstd(encoder) => encoder,
+        }
+    }
+
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Gzip(encoder) => encoder.finish(),
+            Self::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+/// Compresses everything written to it as a series of gzip members or zstd frames, each
+/// complete with its checksum, which decompress as one stream. A file cut short, say by a power
+/// loss, is readable up to the last boundary, at most ten seconds or a megabyte of log back, and
+/// usually beyond it. [`Write::flush`] also pushes out what's pending in the current one.
+///
+/// The last member or frame is completed by [`CompressedWriter::finish`], or on drop, so a log
+/// writer wrapping this needs nothing extra.
+pub struct CompressedWriter<W: Write> {
+    compression: Compression,
+    encoder: Option<Encoder<W>>,
+    since_boundary: u64,
+    boundary_at: Instant,
+}
+
+impl<W: Write> CompressedWriter<W> {
+    pub fn new(compression: Compression, out: W) -> io::Result<Self> {
+        Ok(Self { compression, encoder: Some(Encoder::new(compression, out)?), since_boundary: 0, boundary_at: Instant::now() })
+    }
+
+    /// Completes the last member or frame and returns the inner writer, flushed.
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut out = self.encoder.take().ok_or_else(failed)?.finish()?;
+        out.flush()?;
+        Ok(out)
+    }
+
+    fn boundary(&mut self) -> io::Result<()> {
+        let mut out = self.encoder.take().ok_or_else(failed)?.finish()?;
+        out.flush()?;
+        self.encoder = Some(Encoder::new(self.compression, out)?);
+        self.since_boundary = 0;
+        self.boundary_at = Instant::now();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.since_boundary > 0 && (self.since_boundary >= BOUNDARY_BYTES || self.boundary_at.elapsed() >= BOUNDARY_INTERVAL) {
+            self.boundary()?;
+        }
+        let written = self.encoder.as_mut().ok_or_else(failed)?.writer().write(buf)?;
+        self.since_boundary += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.as_mut().ok_or_else(failed)?.writer().flush()
+    }
+}
+
+impl<W: Write> Drop for CompressedWriter<W> {
+    fn drop(&mut self) {
+        if let Some(encoder) = self.encoder.take() {
+            let _ = encoder.finish().and_then(|mut out| out.flush());
+        }
+    }
+}
+
+fn failed() -> io::Error {
+    io::Error::other("an earlier compressor error closed the stream")
+}
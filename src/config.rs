@@ -0,0 +1,419 @@
+use std::any::TypeId;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use clap::error::{ContextKind, ContextValue};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, CommandFactory};
+use toml_edit::{Array, DocumentMut, ImDocument, Item, Value};
+
+use crate::cli::Args;
+
+/// Options a --config file or profile can't hold.
+const COMMAND_LINE_ONLY: [&str; 6] = ["config", "profile", "profiles-dir", "dump-config", "help", "version"];
+
+/// A --config file or profile, every setting turned into the option it stands for.
+pub struct Config {
+    path: PathBuf,
+    /// Long name and line of each setting used, i.e. not overridden on the command line.
+    settings: Vec<(String, usize)>,
+}
+
+impl Config {
+    /// Where the setting behind a command line error is, e.g. `rig.toml:4: bitrate`.
+    pub fn locate(&self, err: &clap::Error) -> Option<String> {
+        let args = [ContextKind::InvalidArg, ContextKind::PriorArg].into_iter().filter_map(|kind| match err.get(kind)? {
+            ContextValue::String(arg) => Some(vec![arg.clone()]),
+            ContextValue::Strings(args) => Some(args.clone()),
+            _ => None,
+        });
+        args.flatten().find_map(|arg| {
+            let long = arg.strip_prefix("--")?.split([' ', '=']).next()?;
+            let (name, line) = self.settings.iter().find(|(name, _)| name == long)?;
+            Some(format!("{}:{line}: {name}", self.path.display()))
+        })
+    }
+}
+
+/// Expands `--config PATH` or `--profile NAME` in `argv`: each setting becomes its option,
+/// placed before the command line's own so the parsed result is the same as typing them.
+/// Options also given on the command line win over a --config file; a profile must agree
+/// with them.
+pub fn apply(argv: Vec<OsString>) -> Result<(Vec<OsString>, Option<Config>), String> {
+    let (path, profile) = match (option_value(&argv, "config"), option_value(&argv, "profile")) {
+        (Some(path), _) => (PathBuf::from(path), None),
+        (None, Some(name)) => (profile_path(&argv, &name)?, Some(name)),
+        (None, None) => return Ok((argv, None)),
+    };
+    let text = fs::read_to_string(&path).map_err(|err| match &profile {
+        Some(name) if err.kind() == ErrorKind::NotFound => format!("no profile '{name}' ({} not found)", path.display()),
+        _ => format!("{}: {err}", path.display()),
+    })?;
+    let line = |span: Option<Range<usize>>| span.map_or(0, |span| text[..span.start].matches('\n').count() + 1);
+    let located = |span: Option<Range<usize>>, message: String| format!("{}:{}: {message}", path.display(), line(span));
+    let document = ImDocument::parse(text.as_str()).map_err(|err| located(err.span(), err.message().trim_end().replace('\n', ", ")))?;
+
+    let mut command = Args::command();
+    command.build();
+    let given = given_options(&argv, &command);
+    let mut settings = Vec::new();
+    let mut options = Vec::new();
+    let mut seen = HashSet::new();
+    // Tables only group options; their names mean nothing.
+    let root = document.as_table();
+    let sections = root.iter().filter_map(|(_, item)| item.as_table()).map(|table| (table, true));
+    for (table, nested) in std::iter::once((root, false)).chain(sections) {
+        for (key, item) in table.iter() {
+            let span = table.get_key_value(key).and_then(|(key, _)| key.span());
+            let value = match item {
+                Item::Value(value) => value,
+                Item::Table(_) if !nested => continue,
+                _ => return Err(located(span, format!("'{key}' must be an option, tables can't be nested"))),
+            };
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(key) && !COMMAND_LINE_ONLY.contains(&key))
+                .ok_or_else(|| located(span.clone(), format!("unknown option '{key}'")))?;
+            if !seen.insert(key) {
+                return Err(located(span, format!("'{key}' is set twice")));
+            }
+            let values = option_values(arg, value).map_err(|err| located(span.clone(), format!("{key}: {err}")))?;
+            match (given.get(key), &profile) {
+                (None, _) => {}
+                (Some(_), None) => continue,
+                (Some(theirs), Some(_)) if Some(theirs) == values.as_ref() => continue,
+                (Some(theirs), Some(name)) => {
+                    let theirs = format!("--{key} {}", theirs.join(" "));
+                    return Err(located(span, format!("profile '{name}' sets '{key}' differently from {} on the command line", theirs.trim_end())));
+                }
+            }
+            let Some(values) = values else {
+                continue;
+            };
+            match values.is_empty() {
+                true => options.push(format!("--{key}").into()),
+                false => options.extend(values.iter().map(|value| OsString::from(format!("--{key}={value}")))),
+            }
+            settings.push((key.to_string(), line(span)));
+        }
+    }
+
+    let mut merged = argv;
+    let rest = merged.split_off(1.min(merged.len()));
+    merged.extend(options);
+    merged.extend(rest);
+    Ok((merged, Some(Config { path, settings })))
+}
+
+/// The options set from the command line, --config or a profile, as a --config file, with
+/// `additions` appended to the lists of options that can be given more than once.
+pub fn dump(matches: &ArgMatches, additions: &[(&str, &[String])]) -> String {
+    let mut command = Args::command();
+    command.build();
+    let mut document = DocumentMut::new();
+    for arg in command.get_arguments() {
+        let Some(long) = arg.get_long().filter(|long| !COMMAND_LINE_ONLY.contains(long)) else {
+            continue;
+        };
+        let id = arg.get_id().as_str();
+        let mut values: Vec<Value> = match matches.value_source(id) {
+            Some(ValueSource::CommandLine) => match (takes_values(arg), matches.get_raw(id)) {
+                (true, Some(raw)) => raw.map(|value| scalar(&value.to_string_lossy(), numeric(arg))).collect(),
+                _ => vec![Value::from(true)],
+            },
+            _ => Vec::new(),
+        };
+        if matches!(arg.get_action(), ArgAction::Append) {
+            let added = additions.iter().filter(|(name, _)| *name == long).flat_map(|(_, values)| values.iter());
+            values.extend(added.map(|value| scalar(value, numeric(arg))));
+        }
+        if values.is_empty() {
+            continue;
+        }
+        document[long] = match arg.get_action() {
+            ArgAction::Append => Item::Value(Value::Array(values.into_iter().collect::<Array>())),
+            _ => Item::Value(values.swap_remove(0)),
+        };
+    }
+    document.to_string()
+}
+
+/// `argv` with its --config or --profile replaced by `--profile NAME`.
+pub fn switch_profile(argv: &[OsString], name: &str) -> Vec<OsString> {
+    let mut switched = Vec::with_capacity(argv.len() + 1);
+    let mut args = argv.iter();
+    switched.extend(args.next().cloned());
+    switched.push(format!("--profile={name}").into());
+    while let Some(arg) = args.next() {
+        let text = arg.to_string_lossy();
+        if text == "--" {
+            switched.push(arg.clone());
+            switched.extend(args.by_ref().cloned());
+            break;
+        }
+        if text == "--config" || text == "--profile" {
+            args.next();
+        } else if !text.starts_with("--config=") && !text.starts_with("--profile=") {
+            switched.push(arg.clone());
+        }
+    }
+    switched
+}
+
+/// Writes `text` as the new profile `name`; an existing one is left alone.
+pub fn save_profile(argv: &[OsString], name: &str, text: &str) -> Result<PathBuf, String> {
+    let path = profile_path(argv, name)?;
+    if path.exists() {
+        return Err(format!("profile '{name}' already exists at {}", path.display()));
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+    }
+    fs::write(&path, text).map_err(|err| format!("{}: {err}", path.display()))?;
+    Ok(path)
+}
+
+/// `NAME.toml` in --profiles-dir, $RUSTCANBUS_PROFILES or the user's configuration directory.
+fn profile_path(argv: &[OsString], name: &str) -> Result<PathBuf, String> {
+    let valid = !name.is_empty() && !name.starts_with('.') && name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+    if !valid {
+        return Err(format!("invalid profile name '{name}': use letters, digits, '-', '_' and '.'"));
+    }
+    let dir = option_value(argv, "profiles-dir")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("RUSTCANBUS_PROFILES").map(PathBuf::from))
+        .or_else(|| user_config_dir().map(|dir| dir.join("rustcanbus").join("profiles")))
+        .ok_or("no profiles directory: set --profiles-dir or $RUSTCANBUS_PROFILES")?;
+    Ok(dir.join(format!("{name}.toml")))
+}
+
+/// %APPDATA% on Windows, $XDG_CONFIG_HOME or ~/.config elsewhere.
+fn user_config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return env::var_os("APPDATA").map(PathBuf::from);
+    }
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+}
+
+/// The value given with `--{long}`, if any; the last one wins.
+fn option_value(argv: &[OsString], long: &str) -> Option<String> {
+    let (flag, prefix) = (format!("--{long}"), format!("--{long}="));
+    let mut args = argv.iter().skip(1);
+    let mut value = None;
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy();
+        if arg == "--" {
+            break;
+        } else if arg == flag {
+            value = args.next().map(|value| value.to_string_lossy().into_owned());
+        } else if let Some(rest) = arg.strip_prefix(&prefix) {
+            value = Some(rest.to_string());
+        }
+    }
+    value
+}
+
+/// The options on the command line by long name, with the values given to each.
+fn given_options(argv: &[OsString], command: &clap::Command) -> HashMap<String, Vec<String>> {
+    let mut given: HashMap<String, Vec<String>> = HashMap::new();
+    let mut args = argv.iter().skip(1).map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        let Some(option) = arg.strip_prefix("--") else {
+            continue;
+        };
+        let (long, value) = match option.split_once('=') {
+            Some((long, value)) => (long, Some(value.to_string())),
+            None => (option, None),
+        };
+        let needs_value = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long))
+            .and_then(Arg::get_num_args)
+            .is_some_and(|range| range.min_values() > 0);
+        let values = given.entry(long.to_string()).or_default();
+        match value {
+            Some(value) => values.push(value),
+            None if needs_value => values.extend(args.next().map(|value| value.into_owned())),
+            None => {}
+        }
+    }
+    given
+}
+
+fn takes_values(arg: &Arg) -> bool {
+    arg.get_num_args().is_some_and(|range| range.takes_values())
+}
+
+/// The values `value` gives `arg`: none for a switch that is on, `None` for one that is off,
+/// one per element of an array for options that can be given more than once.
+fn option_values(arg: &Arg, value: &Value) -> Result<Option<Vec<String>>, String> {
+    let optional_value = arg.get_num_args().is_some_and(|range| range.min_values() == 0);
+    match value {
+        Value::Boolean(flag) if !takes_values(arg) || optional_value => Ok(flag.value().then(Vec::new)),
+        _ if !takes_values(arg) => Err("expected true or false".to_string()),
+        Value::Array(values) => match arg.get_action() {
+            ArgAction::Append => values.iter().map(scalar_text).collect::<Result<_, _>>().map(Some),
+            _ => Err("takes a single value, not a list".to_string()),
+        },
+        value => Ok(Some(vec![scalar_text(value)?])),
+    }
+}
+
+fn scalar_text(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(text) => Ok(text.value().clone()),
+        Value::Integer(number) => Ok(number.value().to_string()),
+        Value::Float(number) => Ok(number.value().to_string()),
+        Value::Boolean(flag) => Ok(flag.value().to_string()),
+        _ => Err("expected a string, number or boolean".to_string()),
+    }
+}
+
+/// Whether `arg` parses into a number, so its values can be written as TOML numbers.
+fn numeric(arg: &Arg) -> bool {
+    let parsed = arg.get_value_parser().type_id();
+    [TypeId::of::<u8>(), TypeId::of::<u16>(), TypeId::of::<u32>(), TypeId::of::<u64>(), TypeId::of::<f64>()].iter().any(|id| parsed == *id)
+}
+
+/// A number where `arg` takes one and `text` reads back the same, so `dev-index = 0` but
+/// `latency-id = "7DF"`.
+fn scalar(text: &str, numeric: bool) -> Value {
+    if numeric {
+        if let Ok(number) = text.parse::<i64>() {
+            if number.to_string() == text {
+                return Value::from(number);
+            }
+        }
+        if let Ok(number) = text.parse::<f64>() {
+            if number.is_finite() && number.to_string() == text {
+                return Value::from(number);
+            }
+        }
+    }
+    Value::from(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use clap::{CommandFactory, FromArgMatches};
+    use rustcanbus::{Bitrate, ChannelMode};
+
+    use super::*;
+
+    /// A config file of its own for each test, as they run in parallel.
+    fn config_file(text: &str) -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("rustcanbus-config-test-{}-{}.toml", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+        let path = env::temp_dir().join(name);
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn argv(path: &Path, args: &[&str]) -> Vec<OsString> {
+        let mut argv: Vec<OsString> = vec!["rustcanbus".into(), "--config".into(), path.into()];
+        argv.extend(args.iter().map(OsString::from));
+        argv
+    }
+
+    /// What `main` makes of the command line, or the located error.
+    fn parse(text: &str, args: &[&str]) -> Result<Args, String> {
+        let path = config_file(text);
+        let result = apply(argv(&path, args)).and_then(|(argv, config)| {
+            let matches = Args::command().try_get_matches_from(&argv).map_err(|err| {
+                let setting = config.as_ref().and_then(|config| config.locate(&err)).unwrap_or_default();
+                format!("{setting} {}", err.kind())
+            })?;
+            Args::from_arg_matches(&matches).map_err(|err| err.to_string())
+        });
+        fs::remove_file(&path).unwrap();
+        result.map_err(|err| err.replace(&path.display().to_string(), "FILE"))
+    }
+
+    #[test]
+    fn schema_errors_name_the_line() {
+        for (text, expected) in [
+            ("bitrate = \"500k\"\n\nbitrat = \"250k\"\n", "FILE:3: unknown option 'bitrat'"),
+            ("[device]\ndll = \"x\"\n[device.nested]\nbitrate = \"1M\"\n", "FILE:3: 'nested' must be an option, tables can't be nested"),
+            ("listen-only = true\n[bus]\nlisten-only = true\n", "FILE:3: 'listen-only' is set twice"),
+            ("\nlisten-only = \"yes\"\n", "FILE:2: listen-only: expected true or false"),
+            ("bitrate = [\"250k\", \"500k\"]\n", "FILE:1: bitrate: takes a single value, not a list"),
+            ("channel-bitrate = [{ can1 = \"125k\" }]\n", "FILE:1: channel-bitrate: expected a string, number or boolean"),
+            ("config = \"other.toml\"\n", "FILE:1: unknown option 'config'"),
+            ("bitrate = \"500k\"\nfilter = [\"123\"\n", "FILE:3: "),
+        ] {
+            let err = parse(text, &[]).map(drop).expect_err(text);
+            assert!(err.starts_with(expected), "{text:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn invalid_values_are_located() {
+        for (text, expected) in [
+            ("dev-index = 0\nbitrate = \"fast\"\n", "FILE:2: bitrate"),
+            ("\n\nchannel-bitrate = [\"can0:500k\", \"can2:125k\"]\n", "FILE:3: channel-bitrate"),
+            ("[can]\nchannel-mode = \"can1:sleeping\"\n", "FILE:2: channel-mode"),
+            ("channel-mode = \"listen-only\"\n", "FILE:1: channel-mode"),
+        ] {
+            let err = parse(text, &[]).map(drop).expect_err(text);
+            assert!(err.starts_with(expected), "{text:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn per_channel_bitrate_and_mode() {
+        let text = "bitrate = \"500k\"\n[can1]\nchannel-bitrate = \"can1:125k\"\nchannel-mode = [\"can0:listen-only\", \"can1:self-test\"]\n";
+        let args = parse(text, &[]).unwrap();
+        assert_eq!((args.bitrate_for(0), args.bitrate_for(1)), (Bitrate::Kbps500, Bitrate::Kbps125));
+        assert_eq!((args.mode_for(0), args.mode_for(1)), (ChannelMode::ListenOnly, ChannelMode::SelfTest));
+
+        let args = parse("listen-only = true\nchannel-mode = [\"can1:normal\"]\n", &[]).unwrap();
+        assert_eq!((args.mode_for(0), args.mode_for(1)), (ChannelMode::ListenOnly, ChannelMode::Normal));
+    }
+
+    #[test]
+    fn command_line_wins_over_the_file() {
+        let text = "bitrate = \"500k\"\ndev-index = 1\nfilter = [\"100\", \"200\"]\nchannel-bitrate = [\"can1:125k\"]\nlisten-only = true\n";
+        let args = parse(text, &["--bitrate", "1M", "--filter=can1:300", "--channel-bitrate", "can0:250k"]).unwrap();
+        assert_eq!(args.bitrate, Bitrate::Mbps1);
+        assert_eq!(args.dev_index, 1, "settings not on the command line still apply");
+        assert_eq!(args.filter.len(), 1);
+        assert_eq!(args.filter[0].channel, Some(1));
+        assert_eq!((args.bitrate_for(0), args.bitrate_for(1)), (Bitrate::Kbps250, Bitrate::Mbps1), "the file's can1 rate is replaced, not merged");
+        assert!(args.listen_only);
+
+        let args = parse(text, &[]).unwrap();
+        assert_eq!((args.bitrate_for(0), args.bitrate_for(1)), (Bitrate::Kbps500, Bitrate::Kbps125));
+        assert_eq!(args.filter.len(), 2);
+    }
+
+    #[test]
+    fn profiles_must_agree_with_the_command_line() {
+        let path = config_file("bitrate = \"500k\"\nchannel-mode = [\"can1:listen-only\"]\n");
+        let dir = path.parent().unwrap().to_str().unwrap().to_string();
+        let name = path.file_stem().unwrap().to_str().unwrap().to_string();
+        let profile = |args: &[&str]| {
+            let mut argv: Vec<OsString> = ["rustcanbus", "--profiles-dir", &dir, "--profile", &name].map(OsString::from).into();
+            argv.extend(args.iter().map(OsString::from));
+            apply(argv).map(drop)
+        };
+
+        assert!(profile(&["--bitrate", "500k"]).is_ok());
+        let err = profile(&["--channel-mode", "can1:normal"]).unwrap_err();
+        let expected = format!("{}:2: profile '{name}' sets 'channel-mode' differently from --channel-mode can1:normal on the command line", path.display());
+        assert_eq!(err, expected);
+        fs::remove_file(&path).unwrap();
+    }
+}
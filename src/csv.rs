@@ -0,0 +1,76 @@
+use std::io::{self, Write};
+
+use crate::frame::Frame;
+use crate::sink::{Direction, FrameSink};
+use crate::timestamp::host_time;
+
+const HEADER: &str = "timestamp,channel,id,extended,rtr,dlc,d0,d1,d2,d3,d4,d5,d6,d7";
+
+/// Quotes a CSV field if it contains a delimiter, quote or line break.
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Formats one CSV row. IDs are written as `0x...` so spreadsheets don't read values such as
+/// `1E5` as numbers; bytes beyond the DLC (and every byte of a remote frame) are left empty.
+pub fn format_csv_row(timestamp: f64, channel: u32, frame: &Frame) -> String {
+    let data = frame.data();
+    let mut row = format!(
+        "{timestamp:.6},{channel},{},{},{},{}",
+        csv_field(&format!("0x{:X}", frame.id().raw())),
+        frame.is_extended() as u8,
+        frame.is_remote() as u8,
+        frame.dlc()
+    );
+    for i in 0..8 {
+        row.push(',');
+        if let Some(byte) = data.get(i) {
+            row.push_str(&format!("{byte:02X}"));
+        }
+    }
+    row
+}
+
+/// CSV writer with one column per data byte. The header row is written before the first frame,
+/// so every new output (e.g. after log rotation) starts with its own header.
+pub struct CsvWriter<W: Write> {
+    out: W,
+    header_written: bool,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            header_written: false,
+        }
+    }
+}
+
+impl<W: Write + Send> FrameSink for CsvWriter<W> {
+    fn write_frame(&mut self, channel: u32, frame: &Frame, _direction: Direction) -> io::Result<()> {
+        if !self.header_written {
+            writeln!(self.out, "{HEADER}")?;
+            self.header_written = true;
+        }
+        let time = host_time(frame);
+        let timestamp = time.duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+        writeln!(self.out, "{}", format_csv_row(timestamp, channel, frame))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            writeln!(self.out, "{HEADER}")?;
+            self.header_written = true;
+        }
+        self.out.flush()
+    }
+}
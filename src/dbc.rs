@@ -0,0 +1,802 @@
+use std::{collections::BTreeMap, collections::HashMap, fmt};
+
+use crate::frame::Frame;
+use crate::id::Id;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// `@1`: Intel, start bit is the LSB.
+    LittleEndian,
+    /// `@0`: Motorola, start bit is the MSB in DBC's sawtooth bit numbering.
+    BigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplex {
+    None,
+    /// The `M` signal selecting which multiplexed signals are present.
+    Multiplexor,
+    /// `mN`: present only when the multiplexor equals `N`.
+    Multiplexed(u64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signal {
+    pub name: String,
+    pub start_bit: u32,
+    pub size: u32,
+    pub byte_order: ByteOrder,
+    pub signed: bool,
+    pub factor: f64,
+    pub offset: f64,
+    pub min: f64,
+    pub max: f64,
+    pub unit: String,
+    pub multiplex: Multiplex,
+    pub values: BTreeMap<i64, String>,
+    /// Raw value from the `GenSigStartValue` attribute, used when encoding without a value.
+    pub start_value: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub id: Id,
+    pub name: String,
+    pub dlc: u8,
+    pub signals: Vec<Signal>,
+}
+
+/// One decoded signal value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignalValue<'a> {
+    pub signal: &'a Signal,
+    pub raw: i64,
+    pub physical: f64,
+    /// Value-table label for `raw`, if the DBC defines one.
+    pub label: Option<&'a str>,
+}
+
+impl fmt::Display for SignalValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=", self.signal.name)?;
+        match self.label {
+            Some(label) => write!(f, "{label}")?,
+            None => write!(f, "{}", self.physical)?,
+        }
+        if !self.signal.unit.is_empty() && self.label.is_none() {
+            write!(f, " {}", self.signal.unit)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbcError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DbcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DBC line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for DbcError {}
+
+/// Message and signal definitions parsed from a DBC file (`BO_`, `SG_`, `VAL_` and the
+/// `GenSigStartValue` attribute; everything else is ignored).
+#[derive(Debug, Clone, Default)]
+pub struct Dbc {
+    messages: Vec<Message>,
+    by_id: HashMap<Id, usize>,
+}
+
+impl Dbc {
+    pub fn parse(text: &str) -> Result<Self, DbcError> {
+        let mut dbc = Dbc::default();
+        let mut lines = text.lines().enumerate().peekable();
+
+        while let Some((index, line)) = lines.next() {
+            let line_no = index + 1;
+            let err = |message: String| DbcError { line: line_no, message };
+            let trimmed = line.trim();
+
+            if let Some(rest) = trimmed.strip_prefix("BO_ ") {
+                let message = parse_message(rest).map_err(err)?;
+                dbc.by_id.insert(message.id, dbc.messages.len());
+                dbc.messages.push(message);
+            } else if let Some(rest) = trimmed.strip_prefix("SG_ ") {
+                let signal = parse_signal(rest).map_err(err)?;
+                let message = dbc.messages.last_mut().ok_or_else(|| err("SG_ before any BO_".to_string()))?;
+                message.signals.push(signal);
+            } else if trimmed.starts_with("VAL_ ") {
+                let mut statement = trimmed.to_string();
+                while !statement.trim_end().ends_with(';') {
+                    match lines.next() {
+                        Some((_, more)) => {
+                            statement.push(' ');
+                            statement.push_str(more.trim());
+                        }
+                        None => return Err(err("unterminated VAL_ statement".to_string())),
+                    }
+                }
+                dbc.apply_value_table(&statement["VAL_ ".len()..]).map_err(err)?;
+            } else if let Some(rest) = trimmed.strip_prefix("BA_ \"GenSigStartValue\" SG_ ") {
+                dbc.apply_start_value(rest).map_err(err)?;
+            }
+        }
+        Ok(dbc)
+    }
+
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    pub fn message(&self, id: Id) -> Option<&Message> {
+        self.by_id.get(&id).map(|&i| &self.messages[i])
+    }
+
+    pub fn message_by_name(&self, name: &str) -> Option<&Message> {
+        self.messages.iter().find(|m| m.name == name)
+    }
+
+    fn message_mut(&mut self, id: Id) -> Option<&mut Message> {
+        self.by_id.get(&id).map(|&i| &mut self.messages[i])
+    }
+
+    /// Decodes `frame` if its ID is defined; `None` for unknown IDs.
+    pub fn decode<'a>(&'a self, frame: &Frame) -> Option<(&'a Message, Vec<SignalValue<'a>>)> {
+        let message = self.message(frame.id())?;
+        Some((message, message.decode(frame.data())))
+    }
+
+    fn apply_value_table(&mut self, rest: &str) -> Result<(), String> {
+        let rest = rest.trim_end().trim_end_matches(';');
+        let mut parts = rest.split_whitespace();
+        let (Some(id), Some(signal)) = (parts.next(), parts.next()) else {
+            return Err("malformed VAL_ statement".to_string());
+        };
+        let id = message_id(id)?;
+        let values = parse_value_pairs(rest.split_once(signal).map(|(_, v)| v).unwrap_or(""))?;
+        if let Some(signal) = self
+            .message_mut(id)
+            .and_then(|m| m.signals.iter_mut().find(|s| s.name == signal))
+        {
+            signal.values = values;
+        }
+        Ok(())
+    }
+
+    /// `100 Enable 1;`
+    fn apply_start_value(&mut self, rest: &str) -> Result<(), String> {
+        let mut parts = rest.trim_end().trim_end_matches(';').split_whitespace();
+        let (Some(id), Some(signal), Some(value)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err("malformed GenSigStartValue attribute".to_string());
+        };
+        let id = message_id(id)?;
+        let value = value.parse().map_err(|_| format!("invalid start value '{value}'"))?;
+        if let Some(signal) = self
+            .message_mut(id)
+            .and_then(|m| m.signals.iter_mut().find(|s| s.name == signal))
+        {
+            signal.start_value = Some(value);
+        }
+        Ok(())
+    }
+}
+
+/// What [`encode_signals`] does with a physical value outside the signal's `[min|max]` or bit
+/// width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutOfRange {
+    #[default]
+    Clamp,
+    Reject,
+}
+
+/// Packs physical signal values into a message payload. Signals not listed get their
+/// `GenSigStartValue` or zero; multiplexed signals are only written when the multiplexor
+/// selects them.
+pub fn encode_signals(message: &Message, values: &[(&str, f64)], range: OutOfRange) -> Result<[u8; 8], String> {
+    for (name, _) in values {
+        if message.signal(name).is_none() {
+            return Err(format!("{} has no signal '{name}'", message.name));
+        }
+    }
+    let value_of = |signal: &Signal| -> Result<i64, String> {
+        match values.iter().rev().find(|(name, _)| *name == signal.name) {
+            Some(&(_, physical)) => signal.to_raw(physical, range),
+            None => Ok(signal.start_value.map_or(0, |raw| raw.round() as i64)),
+        }
+    };
+
+    let selector = match message.signals.iter().find(|s| s.multiplex == Multiplex::Multiplexor) {
+        Some(signal) => Some(value_of(signal)? as u64),
+        None => None,
+    };
+
+    let mut data = [0u8; 8];
+    for signal in &message.signals {
+        if let Multiplex::Multiplexed(value) = signal.multiplex {
+            if selector != Some(value) {
+                if values.iter().any(|(name, _)| *name == signal.name) {
+                    return Err(format!("{} is not present unless the multiplexor is {value}", signal.name));
+                }
+                continue;
+            }
+        }
+        signal.insert(&mut data, value_of(signal)?)?;
+    }
+    Ok(data)
+}
+
+impl Message {
+    pub fn signal(&self, name: &str) -> Option<&Signal> {
+        self.signals.iter().find(|s| s.name == name)
+    }
+
+    /// Decodes every signal present in `data`. Signals that don't fit in the payload, and
+    /// multiplexed signals whose selector doesn't match, are skipped.
+    pub fn decode<'a>(&'a self, data: &[u8]) -> Vec<SignalValue<'a>> {
+        let selector = self
+            .signals
+            .iter()
+            .find(|s| s.multiplex == Multiplex::Multiplexor)
+            .and_then(|s| s.extract(data))
+            .map(|raw| raw as u64);
+
+        self.signals
+            .iter()
+            .filter(|s| match s.multiplex {
+                Multiplex::Multiplexed(value) => selector == Some(value),
+                _ => true,
+            })
+            .filter_map(|signal| {
+                let raw = signal.extract(data)?;
+                Some(SignalValue {
+                    signal,
+                    raw,
+                    physical: signal.physical(raw),
+                    label: signal.values.get(&raw).map(String::as_str),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Signal {
+    /// DBC bit positions (in the 64-bit payload) occupied by this signal, LSB first.
+    pub(crate) fn bit_positions(&self) -> Vec<u32> {
+        match self.byte_order {
+            ByteOrder::LittleEndian => (self.start_bit..self.start_bit + self.size).collect(),
+            ByteOrder::BigEndian => {
+                let mut positions = Vec::with_capacity(self.size as usize);
+                let mut pos = self.start_bit;
+                for _ in 0..self.size {
+                    positions.push(pos);
+                    pos = if pos.is_multiple_of(8) { pos + 15 } else { pos.wrapping_sub(1) };
+                }
+                positions.reverse();
+                positions
+            }
+        }
+    }
+
+    /// Raw value, sign-extended for signed signals; `None` if the signal extends past `data`.
+    pub fn extract(&self, data: &[u8]) -> Option<i64> {
+        if self.size == 0 || self.size > 64 {
+            return None;
+        }
+        let mut raw: u64 = 0;
+        for (i, pos) in self.bit_positions().into_iter().enumerate() {
+            let byte = *data.get((pos / 8) as usize)?;
+            raw |= (((byte >> (pos % 8)) & 1) as u64) << i;
+        }
+        Some(if self.signed && self.size < 64 && raw & (1 << (self.size - 1)) != 0 {
+            (raw | (u64::MAX << self.size)) as i64
+        } else {
+            raw as i64
+        })
+    }
+
+    pub fn physical(&self, raw: i64) -> f64 {
+        raw as f64 * self.factor + self.offset
+    }
+
+    /// Smallest and largest raw value that fit in the signal's bits.
+    fn raw_limits(&self) -> (i64, i64) {
+        let size = self.size.clamp(1, 64);
+        match (self.signed, size) {
+            (true, _) => (i64::MIN >> (64 - size), i64::MAX >> (64 - size)),
+            (false, 64) => (0, i64::MAX),
+            (false, _) => (0, (1i64 << size) - 1),
+        }
+    }
+
+    /// Scales a physical value to a raw one, rounding to the nearest step. A `[0|0]` range in the
+    /// DBC means unbounded, so only the bit width is checked then.
+    pub fn to_raw(&self, physical: f64, range: OutOfRange) -> Result<i64, String> {
+        if !physical.is_finite() {
+            return Err(format!("{}: {physical} is not a number", self.name));
+        }
+        let bounded = self.min < self.max;
+        let physical = if bounded && !(self.min..=self.max).contains(&physical) {
+            match range {
+                OutOfRange::Clamp => physical.clamp(self.min, self.max),
+                OutOfRange::Reject => {
+                    return Err(format!("{}: {physical} is outside [{}, {}]", self.name, self.min, self.max))
+                }
+            }
+        } else {
+            physical
+        };
+
+        let factor = if self.factor == 0.0 { 1.0 } else { self.factor };
+        let raw = ((physical - self.offset) / factor).round();
+        let (lo, hi) = self.raw_limits();
+        if raw < lo as f64 || raw > hi as f64 {
+            match range {
+                OutOfRange::Clamp => Ok(raw.clamp(lo as f64, hi as f64) as i64),
+                OutOfRange::Reject => Err(format!("{}: {physical} doesn't fit in {} bits", self.name, self.size)),
+            }
+        } else {
+            Ok(raw as i64)
+        }
+    }
+
+    /// Writes `raw` into the signal's bits of `data`, leaving other bits untouched.
+    pub fn insert(&self, data: &mut [u8; 8], raw: i64) -> Result<(), String> {
+        let positions = self.bit_positions();
+        if self.size == 0 || self.size > 64 || positions.iter().any(|&pos| pos >= 64) {
+            return Err(format!("{} doesn't fit in an 8-byte payload", self.name));
+        }
+        for (i, pos) in positions.into_iter().enumerate() {
+            let (byte, bit) = ((pos / 8) as usize, pos % 8);
+            if (raw as u64 >> i) & 1 != 0 {
+                data[byte] |= 1 << bit;
+            } else {
+                data[byte] &= !(1 << bit);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn message_id(s: &str) -> Result<Id, String> {
+    let raw: u32 = s.parse().map_err(|_| format!("invalid message ID '{s}'"))?;
+    if raw & 0x8000_0000 != 0 {
+        Id::extended(raw & Id::MAX_EXTENDED).ok_or_else(|| format!("invalid extended ID '{s}'"))
+    } else if raw <= Id::MAX_STANDARD as u32 {
+        Ok(Id::Standard(raw as u16))
+    } else {
+        Err(format!("standard ID '{s}' exceeds 11 bits"))
+    }
+}
+
+/// `2364540158 EEC1: 8 Vector__XXX`
+fn parse_message(rest: &str) -> Result<Message, String> {
+    let (head, tail) = rest.split_once(':').ok_or("missing ':' in BO_")?;
+    let mut head = head.split_whitespace();
+    let (Some(id), Some(name)) = (head.next(), head.next()) else {
+        return Err("expected 'BO_ <id> <name>:'".to_string());
+    };
+    let dlc = tail
+        .split_whitespace()
+        .next()
+        .and_then(|d| d.parse().ok())
+        .ok_or("invalid message size")?;
+    Ok(Message {
+        id: message_id(id)?,
+        name: name.to_string(),
+        dlc,
+        signals: Vec::new(),
+    })
+}
+
+/// `EngineSpeed m1 : 24|16@1+ (0.125,0) [0|8031.875] "rpm" Vector__XXX`
+fn parse_signal(rest: &str) -> Result<Signal, String> {
+    let (head, tail) = rest.split_once(':').ok_or("missing ':' in SG_")?;
+    let mut head = head.split_whitespace();
+    let name = head.next().ok_or("missing signal name")?.to_string();
+    let multiplex = match head.next() {
+        None => Multiplex::None,
+        Some("M") => Multiplex::Multiplexor,
+        Some(m) => {
+            let value = m
+                .strip_prefix('m')
+                .map(|v| v.trim_end_matches('M'))
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| format!("invalid multiplex indicator '{m}'"))?;
+            Multiplex::Multiplexed(value)
+        }
+    };
+
+    let tail = tail.trim();
+    let (layout, tail) = tail.split_once(char::is_whitespace).ok_or("missing signal scaling")?;
+    let (start, layout) = layout.split_once('|').ok_or("missing '|' in bit layout")?;
+    let (size, layout) = layout.split_once('@').ok_or("missing '@' in bit layout")?;
+    let byte_order = match layout.get(..1) {
+        Some("1") => ByteOrder::LittleEndian,
+        Some("0") => ByteOrder::BigEndian,
+        _ => return Err(format!("invalid byte order in '{layout}'")),
+    };
+    let signed = match layout.get(1..2) {
+        Some("+") => false,
+        Some("-") => true,
+        _ => return Err(format!("invalid sign in '{layout}'")),
+    };
+
+    let (factor, offset) = between(tail, '(', ')')
+        .and_then(|s| s.split_once(','))
+        .ok_or("missing (factor,offset)")?;
+    let (min, max) = between(tail, '[', ']')
+        .and_then(|s| s.split_once('|'))
+        .ok_or("missing [min|max]")?;
+    let unit = between(tail, '"', '"').unwrap_or("").to_string();
+
+    let number = |s: &str| s.trim().parse::<f64>().map_err(|_| format!("invalid number '{s}'"));
+    Ok(Signal {
+        name,
+        start_bit: start.parse().map_err(|_| format!("invalid start bit '{start}'"))?,
+        size: size.parse().map_err(|_| format!("invalid signal size '{size}'"))?,
+        byte_order,
+        signed,
+        factor: number(factor)?,
+        offset: number(offset)?,
+        min: number(min)?,
+        max: number(max)?,
+        unit,
+        multiplex,
+        values: BTreeMap::new(),
+        start_value: None,
+    })
+}
+
+fn between(s: &str, open: char, close: char) -> Option<&str> {
+    let start = s.find(open)? + open.len_utf8();
+    let end = s[start..].find(close)? + start;
+    Some(&s[start..end])
+}
+
+/// `0 "Off" 1 "On"`
+fn parse_value_pairs(s: &str) -> Result<BTreeMap<i64, String>, String> {
+    let mut values = BTreeMap::new();
+    let mut rest = s.trim();
+    while !rest.is_empty() {
+        let (number, after) = rest.split_once(char::is_whitespace).ok_or("malformed value table")?;
+        let raw: i64 = number.parse().map_err(|_| format!("invalid table value '{number}'"))?;
+        let after = after.trim_start();
+        let label = between(after, '"', '"').ok_or("missing value label")?;
+        values.insert(raw, label.to_string());
+        rest = after[label.len() + 2..].trim_start();
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::XorShift;
+
+    fn signal(start_bit: u32, size: u32, byte_order: ByteOrder, signed: bool) -> Signal {
+        Signal {
+            name: "S".to_string(),
+            start_bit,
+            size,
+            byte_order,
+            signed,
+            factor: 1.0,
+            offset: 0.0,
+            min: 0.0,
+            max: 0.0,
+            unit: String::new(),
+            multiplex: Multiplex::None,
+            values: BTreeMap::new(),
+            start_value: None,
+        }
+    }
+
+    fn packed(signal: &Signal, raw: i64) -> [u8; 8] {
+        let mut data = [0; 8];
+        signal.insert(&mut data, raw).unwrap();
+        assert_eq!(signal.extract(&data), Some(raw), "{raw} reads back");
+        data
+    }
+
+    #[test]
+    fn intel_bit_positions() {
+        let aligned = signal(8, 16, ByteOrder::LittleEndian, false);
+        assert_eq!(aligned.bit_positions(), (8..24).collect::<Vec<_>>());
+        assert_eq!(packed(&aligned, 0x1234), [0, 0x34, 0x12, 0, 0, 0, 0, 0]);
+
+        // Bits 6 and 7 of byte 0, then 0 and 1 of byte 1.
+        let crossing = signal(6, 4, ByteOrder::LittleEndian, false);
+        assert_eq!(crossing.bit_positions(), [6, 7, 8, 9]);
+        assert_eq!(packed(&crossing, 0b1011), [0xC0, 0x02, 0, 0, 0, 0, 0, 0]);
+
+        let nibbles = signal(4, 12, ByteOrder::LittleEndian, false);
+        assert_eq!(packed(&nibbles, 0xABC), [0xC0, 0xAB, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn motorola_bit_positions() {
+        // Start bit 7 is the MSB of byte 0; the LSB ends up in bit 0 of byte 1.
+        let aligned = signal(7, 16, ByteOrder::BigEndian, false);
+        assert_eq!(aligned.bit_positions(), (8..16).chain(0..8).collect::<Vec<_>>());
+        assert_eq!(packed(&aligned, 0x1234), [0x12, 0x34, 0, 0, 0, 0, 0, 0]);
+
+        // MSB in bit 3 of byte 0, running down into bits 7..2 of byte 1.
+        let crossing = signal(3, 10, ByteOrder::BigEndian, false);
+        assert_eq!(crossing.bit_positions(), [10, 11, 12, 13, 14, 15, 0, 1, 2, 3]);
+        assert_eq!(packed(&crossing, 0x3FF), [0x0F, 0xFC, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(packed(&crossing, 0x201), [0x08, 0x04, 0, 0, 0, 0, 0, 0]);
+
+        let three_bytes = signal(23, 24, ByteOrder::BigEndian, false);
+        assert_eq!(packed(&three_bytes, 0xABCDEF), [0, 0, 0xAB, 0xCD, 0xEF, 0, 0, 0]);
+    }
+
+    #[test]
+    fn full_payload_signals() {
+        let intel = signal(0, 64, ByteOrder::LittleEndian, false);
+        assert_eq!(packed(&intel, 0x0102_0304_0506_0708), [8, 7, 6, 5, 4, 3, 2, 1]);
+        let motorola = signal(7, 64, ByteOrder::BigEndian, true);
+        assert_eq!(packed(&motorola, -2), [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE]);
+    }
+
+    #[test]
+    fn signed_signals_sign_extend() {
+        let byte = signal(16, 8, ByteOrder::LittleEndian, true);
+        assert_eq!(packed(&byte, -1), [0, 0, 0xFF, 0, 0, 0, 0, 0]);
+        assert_eq!(packed(&byte, -128), [0, 0, 0x80, 0, 0, 0, 0, 0]);
+        assert_eq!(packed(&byte, 127), [0, 0, 0x7F, 0, 0, 0, 0, 0]);
+
+        let twelve = signal(3, 12, ByteOrder::BigEndian, true);
+        let data = packed(&twelve, -100);
+        assert_eq!(signal(3, 12, ByteOrder::BigEndian, false).extract(&data), Some(4096 - 100));
+
+        let odd = signal(5, 5, ByteOrder::LittleEndian, true);
+        for raw in -16..16 {
+            packed(&odd, raw);
+        }
+    }
+
+    #[test]
+    fn insert_leaves_other_bits_alone() {
+        let mut data = [0xFF; 8];
+        signal(6, 4, ByteOrder::LittleEndian, false).insert(&mut data, 0).unwrap();
+        assert_eq!(data, [0x3F, 0xFC, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        signal(3, 10, ByteOrder::BigEndian, false).insert(&mut data, 0).unwrap();
+        assert_eq!(data, [0x30, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn signals_outside_the_payload() {
+        let mut data = [0; 8];
+        assert!(signal(60, 8, ByteOrder::LittleEndian, false).insert(&mut data, 1).is_err());
+        assert!(signal(0, 0, ByteOrder::LittleEndian, false).insert(&mut data, 1).is_err());
+        assert!(signal(0, 65, ByteOrder::LittleEndian, false).insert(&mut data, 1).is_err());
+        // A short payload decodes only the signals that fit in it.
+        assert_eq!(signal(8, 16, ByteOrder::LittleEndian, false).extract(&[1, 2]), None);
+        assert_eq!(signal(0, 8, ByteOrder::LittleEndian, false).extract(&[1, 2]), Some(1));
+    }
+
+    #[test]
+    fn scaling_rounds_to_the_nearest_step() {
+        let temperature = Signal { factor: 0.1, offset: -40.0, min: -40.0, max: 125.0, ..signal(0, 16, ByteOrder::LittleEndian, false) };
+        assert_eq!(temperature.to_raw(25.3, OutOfRange::Reject), Ok(653));
+        assert_eq!(temperature.to_raw(25.34, OutOfRange::Reject), Ok(653));
+        assert_eq!(temperature.to_raw(-40.0, OutOfRange::Reject), Ok(0));
+        assert!((temperature.physical(653) - 25.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn clamp_or_reject_out_of_range_values() {
+        let percent = Signal { min: 0.0, max: 100.0, ..signal(0, 8, ByteOrder::LittleEndian, false) };
+        assert_eq!(percent.to_raw(150.0, OutOfRange::Clamp), Ok(100));
+        assert_eq!(percent.to_raw(-5.0, OutOfRange::Clamp), Ok(0));
+        assert_eq!(percent.to_raw(150.0, OutOfRange::Reject), Err("S: 150 is outside [0, 100]".to_string()));
+
+        // [0|0] leaves only the bit width to check.
+        let unbounded = signal(0, 8, ByteOrder::LittleEndian, false);
+        assert_eq!(unbounded.to_raw(300.0, OutOfRange::Clamp), Ok(255));
+        assert_eq!(unbounded.to_raw(-1.0, OutOfRange::Clamp), Ok(0));
+        assert_eq!(unbounded.to_raw(300.0, OutOfRange::Reject), Err("S: 300 doesn't fit in 8 bits".to_string()));
+        let signed = signal(0, 8, ByteOrder::LittleEndian, true);
+        assert_eq!(signed.to_raw(-200.0, OutOfRange::Clamp), Ok(-128));
+        assert_eq!(signed.to_raw(200.0, OutOfRange::Clamp), Ok(127));
+        assert!(signed.to_raw(-129.0, OutOfRange::Reject).is_err());
+
+        for range in [OutOfRange::Clamp, OutOfRange::Reject] {
+            assert!(unbounded.to_raw(f64::NAN, range).is_err());
+            assert!(unbounded.to_raw(f64::INFINITY, range).is_err());
+        }
+    }
+
+    const MIXED: &str = r#"
+BO_ 291 Mixed: 8 ECU
+ SG_ Speed : 0|16@1+ (0.01,0) [0|655.35] "km/h" ECU
+ SG_ Torque : 23|12@0- (0.5,0) [-1024|1023.5] "Nm" ECU
+ SG_ Gear : 24|3@1+ (1,0) [0|7] "" ECU
+ SG_ Temp : 39|8@0+ (1,-40) [-40|215] "degC" ECU
+ SG_ Flag : 27|1@1+ (1,0) [0|1] "" ECU
+ SG_ Level : 53|10@0+ (0.1,0) [0|100] "%" ECU
+ SG_ Counter : 56|4@1+ (1,0) [0|15] "" ECU
+
+BA_ "GenSigStartValue" SG_ 291 Counter 9;
+"#;
+
+    #[test]
+    fn encode_then_decode_round_trips() {
+        let dbc = Dbc::parse(MIXED).unwrap();
+        let message = dbc.message(Id::Standard(291)).unwrap();
+        let mut used = [false; 64];
+        for position in message.signals.iter().flat_map(Signal::bit_positions) {
+            assert!(!std::mem::replace(&mut used[position as usize], true), "bit {position} is used twice");
+        }
+        let mut rng = XorShift(0x0123_4567_89AB_CDEF);
+        for _ in 0..500 {
+            let values: Vec<(&str, f64)> = message
+                .signals
+                .iter()
+                .filter(|signal| signal.name != "Counter")
+                .map(|signal| {
+                    let steps = ((signal.max - signal.min) / signal.factor).round() as u64;
+                    (signal.name.as_str(), signal.min + rng.below(steps + 1) as f64 * signal.factor)
+                })
+                .collect();
+            let data = encode_signals(message, &values, OutOfRange::Reject).unwrap();
+            let decoded = message.decode(&data);
+            assert_eq!(decoded.len(), message.signals.len());
+            for (name, physical) in &values {
+                let value = decoded.iter().find(|value| value.signal.name == *name).unwrap();
+                assert!((value.physical - physical).abs() < value.signal.factor / 2.0, "{name}: {physical} came back as {}", value.physical);
+            }
+            let counter = decoded.iter().find(|value| value.signal.name == "Counter").unwrap();
+            assert_eq!(counter.raw, 9, "unlisted signals get their start value");
+        }
+    }
+
+    #[test]
+    fn encode_refuses_unknown_and_unselected_signals() {
+        let dbc = Dbc::parse(MIXED).unwrap();
+        let message = dbc.message(Id::Standard(291)).unwrap();
+        assert_eq!(encode_signals(message, &[("Rpm", 1.0)], OutOfRange::Clamp), Err("Mixed has no signal 'Rpm'".to_string()));
+        assert!(encode_signals(message, &[("Gear", 9.0)], OutOfRange::Reject).is_err());
+        let data = encode_signals(message, &[("Gear", 9.0)], OutOfRange::Clamp).unwrap();
+        assert_eq!(message.signal("Gear").unwrap().extract(&data), Some(7));
+
+        let dbc = Dbc::parse(
+            "BO_ 400 Muxed: 8 ECU\n SG_ Page M : 0|8@1+ (1,0) [0|0] \"\" ECU\n SG_ A m1 : 8|8@1+ (1,0) [0|0] \"\" ECU\n SG_ B m2 : 8|8@1+ (1,0) [0|0] \"\" ECU\n",
+        )
+        .unwrap();
+        let message = dbc.message(Id::Standard(400)).unwrap();
+        assert_eq!(encode_signals(message, &[("Page", 2.0), ("B", 0x55 as f64)], OutOfRange::Reject).unwrap()[..2], [2, 0x55]);
+        assert_eq!(
+            encode_signals(message, &[("Page", 2.0), ("A", 1.0)], OutOfRange::Reject),
+            Err("A is not present unless the multiplexor is 1".to_string())
+        );
+    }
+
+    const VEHICLE: &str = r#"VERSION ""
+
+NS_ :
+    CM_
+    VAL_
+
+BU_: Engine TCU ECU
+
+BO_ 2364540158 EEC1: 8 Engine
+ SG_ EngineSpeed : 24|16@1+ (0.125,0) [0|8031.875] "rpm" Vector__XXX
+ SG_ ActualEnginePercentTorque : 16|8@1+ (1,-125) [-125|125] "%" Vector__XXX
+ SG_ EngineTorqueMode : 0|4@1+ (1,0) [0|15] "" Vector__XXX
+
+BO_ 1072 Gearbox: 4 TCU
+ SG_ GearSelected : 7|4@0+ (1,0) [0|15] "" ECU
+ SG_ OilTemp : 15|16@0- (0.1,0) [-50|200] "degC" ECU
+
+BO_ 1280 Diag: 8 ECU
+ SG_ Page M : 0|8@1+ (1,0) [0|255] "" Vector__XXX
+ SG_ Voltage m1 : 8|16@1+ (0.001,0) [0|65.535] "V" Vector__XXX
+ SG_ Current m1 : 24|16@1- (0.01,0) [-327.68|327.67] "A" Vector__XXX
+ SG_ Status m2 : 8|8@1+ (1,0) [0|255] "" Vector__XXX
+ SG_ Errors m2 : 16|16@1+ (1,0) [0|65535] "" Vector__XXX
+
+CM_ SG_ 2364540158 EngineSpeed "Actual engine speed";
+BA_DEF_ SG_ "GenSigStartValue" INT 0 65535;
+VAL_ 2364540158 EngineTorqueMode 0 "Low idle governor" 1 "Accelerator pedal"
+    2 "Cruise control" ;
+VAL_ 1072 GearSelected 0 "Park" 1 "Reverse" 2 "Neutral" 3 "Drive" ;
+VAL_ 1280 Status 0 "OK" 1 "Warning" 2 "Fault" ;
+"#;
+
+    /// A decoded signal's name, raw and physical value, and label.
+    type Decoded<'a> = (&'a str, i64, f64, Option<&'a str>);
+
+    /// The message name and signals decoded from a candump log line.
+    fn decode_line<'a>(dbc: &'a Dbc, line: &str) -> Option<(&'a str, Vec<Decoded<'a>>)> {
+        let record = crate::candump::parse_candump_line(line).unwrap();
+        let (message, values) = dbc.decode(&record.frame)?;
+        Some((&message.name, values.into_iter().map(|value| (value.signal.name.as_str(), value.raw, value.physical, value.label)).collect()))
+    }
+
+    fn assert_decodes(dbc: &Dbc, line: &str, name: &str, expected: &[Decoded]) {
+        let (message, values) = decode_line(dbc, line).unwrap_or_else(|| panic!("{line} has no message"));
+        assert_eq!(message, name, "{line}");
+        assert_eq!(values.len(), expected.len(), "{line}: {values:?}");
+        for (got, want) in values.iter().zip(expected) {
+            assert_eq!((got.0, got.1, got.3), (want.0, want.1, want.3), "{line}");
+            assert!((got.2 - want.2).abs() < 1e-9, "{line}: {} is {} rather than {}", got.0, got.2, want.2);
+        }
+    }
+
+    #[test]
+    fn parses_the_test_dbc() {
+        let dbc = Dbc::parse(VEHICLE).unwrap();
+        let names: Vec<(&str, Id, u8, usize)> = dbc.messages().iter().map(|m| (m.name.as_str(), m.id, m.dlc, m.signals.len())).collect();
+        assert_eq!(names, [("EEC1", Id::Extended(0x0CF0_04FE), 8, 3), ("Gearbox", Id::Standard(0x430), 4, 2), ("Diag", Id::Standard(0x500), 8, 5)]);
+        let mode = dbc.message_by_name("EEC1").unwrap().signal("EngineTorqueMode").unwrap();
+        assert_eq!(mode.values.len(), 3, "the value table continues on the next line");
+        assert_eq!(mode.values[&2], "Cruise control");
+        let diag = dbc.message_by_name("Diag").unwrap();
+        assert_eq!(diag.signal("Page").unwrap().multiplex, Multiplex::Multiplexor);
+        assert_eq!(diag.signal("Errors").unwrap().multiplex, Multiplex::Multiplexed(2));
+    }
+
+    #[test]
+    fn decodes_captured_frames() {
+        let dbc = Dbc::parse(VEHICLE).unwrap();
+        assert_decodes(
+            &dbc,
+            "(1700000000.000100) can0 0CF004FE#F17D7DE01100FFFF",
+            "EEC1",
+            &[("EngineSpeed", 0x11E0, 572.0, None), ("ActualEnginePercentTorque", 125, 0.0, None), ("EngineTorqueMode", 1, 1.0, Some("Accelerator pedal"))],
+        );
+        assert_decodes(
+            &dbc,
+            "(1700000000.010000) can0 0CF004FE#F0FF96401F00FFFF",
+            "EEC1",
+            &[("EngineSpeed", 0x1F40, 1000.0, None), ("ActualEnginePercentTorque", 150, 25.0, None), ("EngineTorqueMode", 0, 0.0, Some("Low idle governor"))],
+        );
+        // Motorola signals, one of them signed.
+        assert_decodes(&dbc, "(1700000000.020000) can1 430#31FF3800", "Gearbox", &[("GearSelected", 3, 3.0, Some("Drive")), ("OilTemp", -200, -20.0, None)]);
+        assert_decodes(&dbc, "(1700000000.030000) can1 430#F004D200", "Gearbox", &[("GearSelected", 15, 15.0, None), ("OilTemp", 1234, 123.4, None)]);
+    }
+
+    #[test]
+    fn decodes_multiplexed_pages() {
+        let dbc = Dbc::parse(VEHICLE).unwrap();
+        assert_decodes(&dbc, "(1700000000.000000) can0 500#0110279CFF000000", "Diag", &[("Page", 1, 1.0, None), ("Voltage", 10_000, 10.0, None), ("Current", -100, -1.0, None)]);
+        assert_decodes(&dbc, "(1700000000.000000) can0 500#02020500", "Diag", &[("Page", 2, 2.0, None), ("Status", 2, 2.0, Some("Fault")), ("Errors", 5, 5.0, None)]);
+        // A page the DBC doesn't define decodes to its selector alone.
+        assert_decodes(&dbc, "(1700000000.000000) can0 500#07FFFFFFFFFFFFFF", "Diag", &[("Page", 7, 7.0, None)]);
+    }
+
+    #[test]
+    fn short_and_unknown_frames() {
+        let dbc = Dbc::parse(VEHICLE).unwrap();
+        // Only the signals inside a short payload are decoded.
+        assert_decodes(&dbc, "(1700000000.000000) can0 0CF004FE#F27D64", "EEC1", &[("ActualEnginePercentTorque", 100, -25.0, None), ("EngineTorqueMode", 2, 2.0, Some("Cruise control"))]);
+        assert_decodes(&dbc, "(1700000000.000000) can0 500#", "Diag", &[]);
+        assert!(decode_line(&dbc, "(1700000000.000000) can0 123#0102").is_none());
+        assert!(decode_line(&dbc, "(1700000000.000000) can0 00000430#31FF3800").is_none(), "an extended 0x430 is another message");
+    }
+
+    #[test]
+    fn decoded_values_print_with_unit_or_label() {
+        let dbc = Dbc::parse(VEHICLE).unwrap();
+        let frame = crate::candump::parse_candump_frame("0CF004FE#F17D7DE01100FFFF").unwrap();
+        let printed: Vec<String> = dbc.decode(&frame).unwrap().1.iter().map(ToString::to_string).collect();
+        assert_eq!(printed, ["EngineSpeed=572 rpm", "ActualEnginePercentTorque=0 %", "EngineTorqueMode=Accelerator pedal"]);
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let error = |text: &str| Dbc::parse(text).unwrap_err();
+        assert_eq!(error("VERSION \"\"\n SG_ Lost : 0|8@1+ (1,0) [0|0] \"\" X\n"), DbcError { line: 2, message: "SG_ before any BO_".to_string() });
+        assert_eq!(error("BO_ 100 A: 8 X\n SG_ Bad : 0|8@2+ (1,0) [0|0] \"\" X\n").line, 2);
+        assert_eq!(error("BO_ 100 A: 8 X\n SG_ Bad : 0|8@1+ (one,0) [0|0] \"\" X\n").message, "invalid number 'one'");
+        assert_eq!(error("BO_ 4096 A: 8 X\n").message, "standard ID '4096' exceeds 11 bits");
+        assert_eq!(error("BO_ 100 A: 8 X\nVAL_ 100 S 0 \"Off\"\n").message, "unterminated VAL_ statement");
+    }
+}
@@ -0,0 +1,1031 @@
+use std::{
+    path::Path,
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Condvar, Mutex, PoisonError, RwLock, Weak,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use tracing::{debug, error, info, info_span, trace, warn, Level};
+
+use crate::align::{AlignmentMode, AlignmentReport, ChannelAlignment};
+use crate::backend::{Backend, CanBackend};
+use crate::board::BoardInfo;
+use crate::busload::BusLoad;
+use crate::error::{check_count, check_status, CanError};
+use crate::fanout::{FanOut, Subscription};
+use crate::handle::{ChannelHandle, ChannelState};
+use crate::ffi::{CanLibrary, VciBoardInfo, VciCanObj, VciCanStatus, VciErrInfo, VciInitConfig};
+use crate::frame::{Frame, SendType};
+use crate::mode::ChannelMode;
+use crate::polling::{Poller, ReceivePolling, MAX_BATCH, MAX_POLL_WAIT};
+use crate::reconnect::{ConnectionObserver, ConnectionState, DisconnectedTx, Reconnect, HELD_TX_LIMIT};
+use crate::reference::RefType;
+use crate::retry::TxRetry;
+use crate::shaper::{Shaper, ShaperStats, TxShaping};
+use crate::status::{CanStatus, ErrorInfo};
+use crate::timestamp::DeviceClock;
+
+/// Number of CAN ports on a CANalyst-II.
+pub const CHANNEL_COUNT: u32 = 2;
+
+/// `VCI_FindUsbDevice2` takes no length; the vendor documents a 50-entry buffer.
+const MAX_ENUMERATED_DEVICES: usize = 50;
+
+/// Pause of a subscription reader thread after a `VCI_Receive` call fails.
+const READ_ERROR_PAUSE: Duration = Duration::from_millis(50);
+
+/// Retry interval if reconnection is switched off while a reconnect is under way.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a channel's TX worker, or a transmit waiting for room in its queue, checks
+/// whether the device went away.
+const SHAPER_POLL: Duration = Duration::from_millis(100);
+
+/// How long closing a device waits for the frames still in its transmit queues to go out.
+const CLOSE_FLUSH: Duration = Duration::from_secs(1);
+
+/// Time the adapter needs to re-enumerate after `VCI_UsbDeviceReset`.
+const USB_RESET_SETTLE: Duration = Duration::from_millis(1000);
+
+/// Maps a backend's `None`, a function the DLL lacks, to [`CanError::Unsupported`].
+fn supported(code: Option<i32>, symbol: &'static str) -> Result<i32, CanError> {
+    code.ok_or(CanError::Unsupported(symbol))
+}
+
+/// State shared by every `Channel` clone for one port.
+#[derive(Default)]
+struct ChannelShared {
+    config: Mutex<Option<VciInitConfig>>,
+    state: Mutex<ChannelState>,
+    /// Set while a [`ChannelHandle`] for the port is alive.
+    taken: AtomicBool,
+    send_type: Mutex<SendType>,
+    /// Frames received or transmitted on the port, for [`Channel::bus_load`].
+    load: Mutex<BusLoad>,
+    subscribers: FanOut,
+    /// Frames kept by [`DisconnectedTx::Hold`] until the adapter is back.
+    held: Mutex<VecDeque<VciCanObj>>,
+    dropped_while_disconnected: AtomicU64,
+    receive_failures: AtomicU64,
+    received: AtomicU64,
+    /// Received frames marked [`Frame::is_suspect`].
+    suspect: AtomicU64,
+    transmitted: AtomicU64,
+    clock: Mutex<DeviceClock>,
+    /// Host time of the last `VCI_StartCAN`, when the timestamps restarted.
+    started: Mutex<Option<Instant>>,
+    /// Set with [`Channel::set_receive_polling`].
+    polling: Mutex<ReceivePolling>,
+    /// Set with [`Channel::set_tx_retry`].
+    retry: Mutex<Option<TxRetry>>,
+    /// The transmit queue in front of the TX worker, with the limits set with
+    /// [`Channel::set_tx_shaping`].
+    shaper: Mutex<Shaper>,
+    /// Signalled when frames are queued for the TX worker, or it made room or sent a batch.
+    shaper_wake: Condvar,
+    /// Set once the port's TX worker is running.
+    tx_worker: AtomicBool,
+}
+
+struct DeviceInner {
+    lib: Arc<dyn CanBackend>,
+    dev_type: u32,
+    dev_index: u32,
+    channels: [ChannelShared; CHANNEL_COUNT as usize],
+    /// Channel calls hold this for reading; a USB reset takes it for writing so no thread is
+    /// inside the DLL while the handle is being torn down and reopened.
+    gate: RwLock<()>,
+    consecutive_failures: AtomicU32,
+    usb_reset_threshold: AtomicU32,
+    reconnect: Mutex<Option<Reconnect>>,
+    connection: Mutex<ConnectionState>,
+    observer: Mutex<Option<ConnectionObserver>>,
+    /// Set by [`Device::close`] so a reconnect in progress gives up.
+    closed: AtomicBool,
+    reconnects: AtomicU64,
+    /// Set with [`Device::set_channel_alignment`].
+    alignment: Mutex<Option<ChannelAlignment>>,
+}
+
+impl DeviceInner {
+    fn usb_reset(&self) -> Result<(), CanError> {
+        let _guard = self.gate.write().unwrap();
+        let code = supported(self.lib.usb_device_reset(self.dev_type, self.dev_index), "VCI_UsbDeviceReset")?;
+        check_status(code, |code| CanError::UsbReset { code })?;
+        thread::sleep(USB_RESET_SETTLE);
+        self.reopen()
+    }
+
+    /// Closes and reopens the handle, then re-initializes and restarts every channel with its
+    /// stored configuration. The caller holds the gate for writing.
+    fn reopen(&self) -> Result<(), CanError> {
+        self.lib.close_device(self.dev_type, self.dev_index);
+        let code = self.lib.open_device(self.dev_type, self.dev_index);
+        check_status(code, |code| CanError::OpenDevice { code })?;
+
+        for (index, shared) in (0..CHANNEL_COUNT).zip(&self.channels) {
+            let Some(config) = *shared.config.lock().unwrap() else {
+                continue;
+            };
+            let code = self.lib.init_can(self.dev_type, self.dev_index, index, &config);
+            check_status(code, |code| CanError::InitCan { channel: index, code })?;
+            if *shared.state.lock().unwrap() == ChannelState::Started {
+                let before = Instant::now();
+                let code = self.lib.start_can(self.dev_type, self.dev_index, index);
+                check_status(code, |code| CanError::StartCan { channel: index, code })?;
+                self.restarted(index, before);
+            }
+        }
+        self.consecutive_failures.store(0, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Restarts a channel's clock after a `VCI_StartCAN` call made at `before`, dating the restart
+    /// halfway through the call.
+    fn restarted(&self, index: u32, before: Instant) {
+        let at = before + before.elapsed() / 2;
+        let shared = &self.channels[index as usize];
+        shared.clock.lock().unwrap().reset();
+        *shared.started.lock().unwrap() = Some(at);
+        if let Some(alignment) = self.alignment.lock().unwrap().as_mut() {
+            alignment.started(index, at);
+        }
+    }
+
+    /// Tracks consecutive -1 returns from receive/transmit and triggers a USB reset, then a
+    /// reconnect, once the configured thresholds are reached.
+    fn record_io(self: &Arc<Self>, code: i32) {
+        if code >= 0 {
+            self.consecutive_failures.store(0, Ordering::SeqCst);
+            return;
+        }
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
+        let threshold = self.usb_reset_threshold.load(Ordering::SeqCst);
+        if threshold != 0 && failures == threshold {
+            warn!(dev_index = self.dev_index, failures, "resetting the USB connection");
+            if let Err(err) = self.usb_reset() {
+                error!(dev_index = self.dev_index, %err, "USB reset failed");
+            }
+        }
+        let reconnect = *self.reconnect.lock().unwrap();
+        if reconnect.is_some_and(|reconnect| failures == reconnect.after_failures.max(1)) {
+            self.lose_connection();
+        }
+    }
+
+    fn connection_state(&self) -> ConnectionState {
+        *self.connection.lock().unwrap()
+    }
+
+    fn set_connection_state(&self, state: ConnectionState) {
+        let previous = std::mem::replace(&mut *self.connection.lock().unwrap(), state);
+        if previous != state {
+            match state {
+                ConnectionState::Connected => info!(dev_index = self.dev_index, "connection {state}"),
+                ConnectionState::Reconnecting => warn!(dev_index = self.dev_index, "connection {state}"),
+                ConnectionState::Lost => error!(dev_index = self.dev_index, "connection {state}"),
+            }
+            if let Some(observer) = &*self.observer.lock().unwrap() {
+                observer(state);
+            }
+        }
+    }
+
+    /// Waits up to `timeout` until port `index`'s TX worker has handed everything queued to the
+    /// DLL, and returns whether it did. Poisoned locks are ignored, for [`DeviceInner::shutdown`].
+    fn flush_transmit(&self, index: u32, timeout: Duration) -> bool {
+        let shared = &self.channels[index as usize];
+        let deadline = Instant::now() + timeout;
+        let mut shaper = shared.shaper.lock().unwrap_or_else(PoisonError::into_inner);
+        while !shaper.queue.is_empty() || shaper.sending > 0 {
+            let now = Instant::now();
+            if now >= deadline || self.closed.load(Ordering::SeqCst) {
+                return false;
+            }
+            shaper = shared.shaper_wake.wait_timeout(shaper, (deadline - now).min(SHAPER_POLL)).unwrap_or_else(PoisonError::into_inner).0;
+        }
+        true
+    }
+
+    /// Gives the transmit queues [`CLOSE_FLUSH`] to empty, then stops the started channels and
+    /// closes the handle, once. Also runs while unwinding from a panic, so poisoned locks are
+    /// ignored.
+    fn shutdown(&self) -> Result<(), CanError> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let deadline = Instant::now() + CLOSE_FLUSH;
+        for index in 0..CHANNEL_COUNT {
+            self.flush_transmit(index, deadline.saturating_duration_since(Instant::now()));
+        }
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let _guard = self.gate.write().unwrap_or_else(PoisonError::into_inner);
+        for (index, shared) in (0..CHANNEL_COUNT).zip(&self.channels) {
+            if *shared.state.lock().unwrap_or_else(PoisonError::into_inner) == ChannelState::Started {
+                let _ = self.lib.reset_can(self.dev_type, self.dev_index, index);
+            }
+        }
+        let code = self.lib.close_device(self.dev_type, self.dev_index);
+        check_status(code, |code| CanError::CloseDevice { code })
+    }
+
+    /// Marks the adapter lost and starts reconnecting in the background, unless that is
+    /// already under way.
+    fn lose_connection(self: &Arc<Self>) {
+        if self.connection_state() != ConnectionState::Connected {
+            return;
+        }
+        self.set_connection_state(ConnectionState::Lost);
+        let inner = Arc::downgrade(self);
+        thread::spawn(move || reconnect(&inner));
+    }
+
+    /// Sends what [`DisconnectedTx::Hold`] kept; whatever the adapter won't take is counted as
+    /// dropped.
+    fn flush_held(&self) {
+        let _guard = self.gate.read().unwrap();
+        for (index, shared) in (0..CHANNEL_COUNT).zip(&self.channels) {
+            let held: Vec<VciCanObj> = shared.held.lock().unwrap().drain(..).collect();
+            let mut sent = 0;
+            while sent < held.len() {
+                let code = self.lib.transmit(self.dev_type, self.dev_index, index, &held[sent..]);
+                if code <= 0 {
+                    break;
+                }
+                sent += (code as usize).min(held.len() - sent);
+            }
+            shared.transmitted.fetch_add(sent as u64, Ordering::Relaxed);
+            shared.dropped_while_disconnected.fetch_add((held.len() - sent) as u64, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        let _ = self.inner.shutdown();
+    }
+}
+
+/// Reopen loop run on its own thread after [`DeviceInner::lose_connection`]. Ends once the
+/// adapter is back, or the device is closed or dropped.
+fn reconnect(inner: &Weak<DeviceInner>) {
+    while let Some(device) = inner.upgrade() {
+        if device.closed.load(Ordering::SeqCst) {
+            return;
+        }
+        device.set_connection_state(ConnectionState::Reconnecting);
+        let reopened = {
+            let _guard = device.gate.write().unwrap();
+            device.reopen()
+        };
+        if reopened.is_ok() {
+            device.reconnects.fetch_add(1, Ordering::SeqCst);
+            device.set_connection_state(ConnectionState::Connected);
+            device.flush_held();
+            return;
+        }
+        let interval = device.reconnect.lock().unwrap().map_or(RECONNECT_INTERVAL, |reconnect| reconnect.interval);
+        drop(device);
+        thread::sleep(interval);
+    }
+}
+
+/// Sends a channel's queued frames as its shaping limits allow, batched into as few
+/// `VCI_Transmit` calls as they can, on its own thread. The only sender of the channel's frames,
+/// so they go out in the order they were queued. Ends once the device is closed or dropped.
+fn tx_worker(inner: &Weak<DeviceInner>, index: u32) {
+    while let Some(device) = inner.upgrade() {
+        if device.closed.load(Ordering::SeqCst) {
+            return;
+        }
+        let channel = Channel { inner: device, index };
+        let shared = channel.shared();
+        let mut shaper = shared.shaper.lock().unwrap();
+        let now = Instant::now();
+        let mut batch = Vec::new();
+        while let Some(obj) = shaper.queue.front().copied() {
+            if !shaper.admit(&Frame::from(&obj), now) {
+                break;
+            }
+            batch.push(obj);
+            shaper.queue.pop_front();
+        }
+        if batch.is_empty() {
+            let next = shaper.queue.front().copied();
+            shaper.held_back |= next.is_some();
+            let wait = next.map_or(SHAPER_POLL, |obj| shaper.wait(&Frame::from(&obj), now).min(SHAPER_POLL));
+            drop(shared.shaper_wake.wait_timeout(shaper, wait).unwrap());
+            continue;
+        }
+        shaper.sending = batch.len();
+        shared.shaper_wake.notify_all();
+        // Nobody else sends from the queue, so senders may queue more while the DLL takes this.
+        drop(shaper);
+        let sent = match channel.connected() {
+            Ok(()) => channel.send_now(&batch),
+            Err(_) => channel.transmit_disconnected(&batch),
+        };
+        let sent = match sent {
+            Ok(sent) | Err(CanError::TxTimeout { sent, .. }) => sent,
+            Err(_) => 0,
+        };
+        let mut shaper = shared.shaper.lock().unwrap();
+        shaper.sending = 0;
+        match std::mem::take(&mut shaper.held_back) {
+            true => shaper.stats.delayed += sent as u64,
+            false => shaper.stats.passed += sent as u64,
+        }
+        shaper.stats.dropped += (batch.len() - sent) as u64;
+        shared.shaper_wake.notify_all();
+    }
+}
+
+/// One opened adapter. Devices are independent: several can be open at once, e.g. indices 0
+/// and 1 through one shared [`CanLibrary`] with [`Device::open_with`]. Calls on one device never
+/// wait for another, except opening, closing and USB resets, which the library serializes.
+pub struct Device {
+    inner: Arc<DeviceInner>,
+}
+
+impl Device {
+    /// Loads the vendor DLL from the default locations (see [`CanLibrary::load`]) and opens the
+    /// adapter.
+    pub fn open(dev_type: u32, dev_index: u32) -> Result<Self, CanError> {
+        Self::open_dll(None, dev_type, dev_index)
+    }
+
+    /// Like [`Device::open`], loading the DLL from `dll` if given.
+    pub fn open_dll(dll: Option<&Path>, dev_type: u32, dev_index: u32) -> Result<Self, CanError> {
+        Self::open_with(CanLibrary::load(dll)?, dev_type, dev_index)
+    }
+
+    /// Opens the adapter through `backend` instead of the vendor DLL, e.g. a
+    /// [`MockBackend`](crate::MockBackend).
+    pub fn open_with(backend: impl Into<Backend>, dev_type: u32, dev_index: u32) -> Result<Self, CanError> {
+        let lib = backend.into().0;
+        let _span = info_span!("open", dev_type, dev_index).entered();
+        let code = lib.open_device(dev_type, dev_index);
+        check_status(code, |code| CanError::OpenDevice { code }).inspect_err(|err| warn!(%err))?;
+        debug!("opened");
+        Ok(Self {
+            inner: Arc::new(DeviceInner {
+                lib,
+                dev_type,
+                dev_index,
+                channels: Default::default(),
+                gate: RwLock::new(()),
+                consecutive_failures: AtomicU32::new(0),
+                usb_reset_threshold: AtomicU32::new(0),
+                reconnect: Mutex::new(None),
+                connection: Mutex::new(ConnectionState::Connected),
+                observer: Mutex::new(None),
+                closed: AtomicBool::new(false),
+                reconnects: AtomicU64::new(0),
+                alignment: Mutex::new(None),
+            }),
+        })
+    }
+
+    /// Puts the frames received on both channels on CAN1's clock, see [`ChannelAlignment`], or
+    /// with `None` converts each channel's timestamps on their own again.
+    pub fn set_channel_alignment(&self, mode: Option<AlignmentMode>) {
+        let alignment = mode.map(|mode| {
+            let mut alignment = ChannelAlignment::new(mode);
+            for (index, shared) in (0..).zip(&self.inner.channels) {
+                if let Some(at) = *shared.started.lock().unwrap() {
+                    alignment.started(index, at);
+                }
+            }
+            alignment
+        });
+        *self.inner.alignment.lock().unwrap() = alignment;
+    }
+
+    /// The offset estimated between the channels' clocks, while they're aligned.
+    pub fn alignment_report(&self) -> Option<AlignmentReport> {
+        self.inner.alignment.lock().unwrap().as_ref().map(ChannelAlignment::report)
+    }
+
+    /// Lists every adapter currently attached, in `dev_index` order. Does not open any of them.
+    pub fn enumerate() -> Result<Vec<BoardInfo>, CanError> {
+        Self::enumerate_with(CanLibrary::load(None)?)
+    }
+
+    /// [`Device::enumerate`] through `backend` instead of the vendor DLL.
+    pub fn enumerate_with(backend: impl Into<Backend>) -> Result<Vec<BoardInfo>, CanError> {
+        let backend = backend.into().0;
+        let mut infos = vec![VciBoardInfo::default(); MAX_ENUMERATED_DEVICES];
+        let count = supported(backend.find_usb_devices(&mut infos), "VCI_FindUsbDevice2")?;
+        infos.truncate(count.clamp(0, MAX_ENUMERATED_DEVICES as i32) as usize);
+        Ok(infos.iter().map(BoardInfo::from).collect())
+    }
+
+    /// The `dev_index` the adapter was opened with.
+    pub fn index(&self) -> u32 {
+        self.inner.dev_index
+    }
+
+    pub fn board_info(&self) -> Result<BoardInfo, CanError> {
+        let inner = &self.inner;
+        let _guard = inner.gate.read().unwrap();
+        let mut info = VciBoardInfo::default();
+        let code = inner.lib.read_board_info(inner.dev_type, inner.dev_index, &mut info);
+        let code = supported(code, "VCI_ReadBoardInfo")?;
+        check_status(code, |code| CanError::ReadBoardInfo { code })?;
+        Ok(BoardInfo::from(&info))
+    }
+
+    /// Handle to port `index` (0 = CAN1, 1 = CAN2). Panics if `index >= CHANNEL_COUNT`.
+    pub fn channel(&self, index: u32) -> Channel {
+        assert!(index < CHANNEL_COUNT, "CANalyst-II has no channel {index}");
+        Channel {
+            inner: Arc::clone(&self.inner),
+            index,
+        }
+    }
+
+    /// The one [`ChannelHandle`] for port `index`, which checks its lifecycle; `Err` with
+    /// [`CanError::ChannelInUse`] while an earlier one is still alive. [`Device::channel`]
+    /// hands out plain, unchecked views of the same port for any number of threads.
+    pub fn take_channel(&self, index: u32) -> Result<ChannelHandle, CanError> {
+        let channel = self.channel(index);
+        if channel.shared().taken.swap(true, Ordering::SeqCst) {
+            return Err(CanError::ChannelInUse { channel: index });
+        }
+        Ok(ChannelHandle::new(channel))
+    }
+
+    /// Power-cycles the adapter's USB interface, reopens it and re-initializes and restarts
+    /// every channel with its stored configuration. Blocks until in-flight channel calls on
+    /// other threads have returned.
+    pub fn usb_reset(&self) -> Result<(), CanError> {
+        self.inner.usb_reset()
+    }
+
+    /// Performs [`Device::usb_reset`] automatically after `threshold` consecutive receive or
+    /// transmit calls fail with -1. `None` disables it (the default).
+    pub fn set_auto_usb_reset(&self, threshold: Option<u32>) {
+        self.inner.usb_reset_threshold.store(threshold.unwrap_or(0), Ordering::SeqCst);
+    }
+
+    /// Gives up the handle after `reconnect.after_failures` consecutive receive or transmit
+    /// failures (-1) and keeps reopening the adapter every `reconnect.interval`, restoring each
+    /// channel's configuration. Meanwhile transmits follow `reconnect.tx` and receives fail with
+    /// [`CanError::Disconnected`]. `None` disables it (the default).
+    pub fn set_auto_reconnect(&self, reconnect: Option<Reconnect>) {
+        *self.inner.reconnect.lock().unwrap() = reconnect;
+    }
+
+    pub fn connection_state(&self) -> ConnectionState {
+        self.inner.connection_state()
+    }
+
+    /// Called on the thread that notices each change, so keep it short.
+    pub fn set_connection_observer(&self, observer: Option<ConnectionObserver>) {
+        *self.inner.observer.lock().unwrap() = observer;
+    }
+
+    /// Resets the started channels and closes the handle. Dropping the device does the same,
+    /// ignoring errors.
+    pub fn close(self) -> Result<(), CanError> {
+        self.inner.shutdown()
+    }
+}
+
+/// One CAN port of an opened [`Device`]. Cheap to clone and safe to move into worker threads.
+/// Handles compare equal when they refer to the same port of the same opened device.
+#[derive(Clone)]
+pub struct Channel {
+    inner: Arc<DeviceInner>,
+    index: u32,
+}
+
+impl PartialEq for Channel {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner) && self.index == other.index
+    }
+}
+
+impl Eq for Channel {}
+
+impl Channel {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Lets [`Device::take_channel`] hand out a new handle for the port.
+    pub(crate) fn release(&self) {
+        self.shared().taken.store(false, Ordering::SeqCst);
+    }
+
+    /// `dev_index` of the adapter this port belongs to.
+    pub fn device_index(&self) -> u32 {
+        self.inner.dev_index
+    }
+
+    pub fn connection_state(&self) -> ConnectionState {
+        self.inner.connection_state()
+    }
+
+    /// Frames refused or discarded while the adapter was disconnected, see [`DisconnectedTx`].
+    pub fn dropped_while_disconnected(&self) -> u64 {
+        self.shared().dropped_while_disconnected.load(Ordering::SeqCst)
+    }
+
+    /// For firmware whose timestamp counter wraps after `modulus` ticks rather than 2^32; a step
+    /// back of more than `threshold` ticks counts as a wrap. See [`DeviceClock::with_wrap`].
+    pub fn set_timestamp_wrap(&self, modulus: u64, threshold: u64) {
+        *self.shared().clock.lock().unwrap() = DeviceClock::with_wrap(modulus, threshold);
+    }
+
+    /// `VCI_Receive` calls that returned an error (-1) rather than 0 for an idle bus, including
+    /// the ones made by the [`Channel::subscribe`] reader, which otherwise go unreported.
+    pub fn receive_failures(&self) -> u64 {
+        self.shared().receive_failures.load(Ordering::SeqCst)
+    }
+
+    /// Frames received on the port since the device was opened, whoever read them.
+    pub fn received(&self) -> u64 {
+        self.shared().received.load(Ordering::Relaxed)
+    }
+
+    /// Of those, the ones the DLL delivered malformed, see [`Frame::is_suspect`].
+    pub fn suspect_frames(&self) -> u64 {
+        self.shared().suspect.load(Ordering::Relaxed)
+    }
+
+    /// Frames the adapter accepted for transmission on the port since the device was opened.
+    pub fn transmitted(&self) -> u64 {
+        self.shared().transmitted.load(Ordering::Relaxed)
+    }
+
+    /// Times the adapter behind this port was reopened after being unplugged.
+    pub fn reconnects(&self) -> u64 {
+        self.inner.reconnects.load(Ordering::SeqCst)
+    }
+
+    fn connected(&self) -> Result<(), CanError> {
+        match self.inner.connection_state() {
+            ConnectionState::Connected => Ok(()),
+            _ => Err(CanError::Disconnected { channel: self.index }),
+        }
+    }
+
+    /// Applies the [`DisconnectedTx`] policy to frames offered while the adapter is away and
+    /// returns how many were held.
+    fn transmit_disconnected(&self, objs: &[VciCanObj]) -> Result<usize, CanError> {
+        let policy = self.inner.reconnect.lock().unwrap().map(|reconnect| reconnect.tx).unwrap_or_default();
+        let shared = self.shared();
+        let mut held = 0;
+        if policy == DisconnectedTx::Hold {
+            let mut queue = shared.held.lock().unwrap();
+            held = HELD_TX_LIMIT.saturating_sub(queue.len()).min(objs.len());
+            queue.extend(&objs[..held]);
+        }
+        shared.dropped_while_disconnected.fetch_add((objs.len() - held) as u64, Ordering::SeqCst);
+        match held {
+            0 => Err(CanError::Disconnected { channel: self.index }),
+            _ => Ok(held),
+        }
+    }
+
+    fn shared(&self) -> &ChannelShared {
+        &self.inner.channels[self.index as usize]
+    }
+
+    /// Runs one raw VCI call with the device gate held for reading.
+    fn call<T>(&self, f: impl FnOnce(&dyn CanBackend, u32, u32, u32) -> T) -> T {
+        let inner = &self.inner;
+        let _guard = inner.gate.read().unwrap();
+        f(inner.lib.as_ref(), inner.dev_type, inner.dev_index, self.index)
+    }
+
+    /// Initializes the controller and remembers `config` for [`Channel::recover`].
+    pub fn init(&self, config: &VciInitConfig) -> Result<(), CanError> {
+        let _span = info_span!("init", dev_index = self.inner.dev_index, channel = self.index).entered();
+        let code = self.call(|lib, t, d, c| lib.init_can(t, d, c, config));
+        check_status(code, |code| CanError::InitCan { channel: self.index, code }).inspect_err(|err| warn!(%err))?;
+        debug!(timing0 = config.timing0, timing1 = config.timing1, mode = config.mode, "initialized");
+        *self.shared().config.lock().unwrap() = Some(*config);
+        let mut state = self.shared().state.lock().unwrap();
+        if *state != ChannelState::Started {
+            *state = ChannelState::Initialized;
+        }
+        Ok(())
+    }
+
+    /// Where the port is in its lifecycle, whichever handle moved it there.
+    pub fn state(&self) -> ChannelState {
+        *self.shared().state.lock().unwrap()
+    }
+
+    /// The configuration last passed to a successful [`Channel::init`].
+    pub fn config(&self) -> Option<VciInitConfig> {
+        *self.shared().config.lock().unwrap()
+    }
+
+    pub fn start(&self) -> Result<(), CanError> {
+        let _span = info_span!("start", dev_index = self.inner.dev_index, channel = self.index).entered();
+        let before = Instant::now();
+        let code = self.call(|lib, t, d, c| lib.start_can(t, d, c));
+        check_status(code, |code| CanError::StartCan { channel: self.index, code }).inspect_err(|err| warn!(%err))?;
+        debug!("started");
+        *self.shared().state.lock().unwrap() = ChannelState::Started;
+        // The adapter restarts its timestamps with the channel.
+        self.inner.restarted(self.index, before);
+        self.start_tx_worker();
+        Ok(())
+    }
+
+    /// Starts the port's TX worker, unless it is already running.
+    fn start_tx_worker(&self) {
+        if !self.shared().tx_worker.swap(true, Ordering::SeqCst) {
+            let (inner, index) = (Arc::downgrade(&self.inner), self.index);
+            thread::spawn(move || tx_worker(&inner, index));
+        }
+    }
+
+    /// Puts the controller back into reset mode; `start` or `recover` brings it back online.
+    /// Frames still queued get [`CLOSE_FLUSH`] to go out first.
+    pub fn reset(&self) -> Result<(), CanError> {
+        self.flush_transmit(CLOSE_FLUSH);
+        let code = supported(self.call(|lib, t, d, c| lib.reset_can(t, d, c)), "VCI_ResetCAN")?;
+        check_status(code, |code| CanError::ResetCan { channel: self.index, code })?;
+        *self.shared().state.lock().unwrap() = ChannelState::Stopped;
+        Ok(())
+    }
+
+    /// Reset, re-init with the stored configuration and restart, e.g. after bus-off.
+    pub fn recover(&self) -> Result<(), CanError> {
+        let config = self.config().ok_or(CanError::NotInitialized { channel: self.index })?;
+        self.reset()?;
+        self.init(&config)?;
+        self.start()
+    }
+
+    /// Like [`Channel::recover`] but with a new configuration. A DLL without `VCI_ResetCAN`
+    /// is re-initialized without the reset.
+    pub fn reconfigure(&self, config: &VciInitConfig) -> Result<(), CanError> {
+        match self.reset() {
+            Ok(()) | Err(CanError::Unsupported(_)) => {}
+            Err(err) => return Err(err),
+        }
+        self.init(config)?;
+        self.start()
+    }
+
+    /// Discards everything queued in the adapter's receive and transmit buffers for this channel.
+    pub fn clear_buffer(&self) -> Result<(), CanError> {
+        let code = supported(self.call(|lib, t, d, c| lib.clear_buffer(t, d, c)), "VCI_ClearBuffer")?;
+        check_status(code, |code| CanError::ClearBuffer { channel: self.index, code })
+    }
+
+    /// Reads (and clears) the channel's last error report.
+    pub fn error_info(&self) -> Result<ErrorInfo, CanError> {
+        let mut info = VciErrInfo::default();
+        let code = supported(self.call(|lib, t, d, c| lib.read_err_info(t, d, c, &mut info)), "VCI_ReadErrInfo")?;
+        check_status(code, |code| CanError::ReadErrInfo { channel: self.index, code })?;
+        Ok(ErrorInfo::from(&info))
+    }
+
+    pub fn status(&self) -> Result<CanStatus, CanError> {
+        let mut status = VciCanStatus::default();
+        let code = supported(self.call(|lib, t, d, c| lib.read_can_status(t, d, c, &mut status)), "VCI_ReadCANStatus")?;
+        check_status(code, |code| CanError::ReadCanStatus { channel: self.index, code })?;
+        Ok(CanStatus::from(&status))
+    }
+
+    /// Passes a `VCI_SetReference` setting to the firmware. Settings that affect
+    /// initialization, such as [`RefType::BaudRate`], must be made before [`Channel::init`].
+    pub fn set_reference(&self, reference: &RefType) -> Result<(), CanError> {
+        let mut data = reference.data();
+        let code = self.call(|lib, t, d, c| lib.set_reference(t, d, c, reference.raw_type(), &mut data));
+        let code = supported(code, "VCI_SetReference")?;
+        check_status(code, |code| CanError::SetReference { channel: self.index, code })
+    }
+
+    /// Send type used by [`Channel::transmit`]; defaults to [`SendType::Normal`].
+    pub fn set_send_type(&self, send_type: SendType) {
+        *self.shared().send_type.lock().unwrap() = send_type;
+    }
+
+    pub fn send_type(&self) -> SendType {
+        *self.shared().send_type.lock().unwrap()
+    }
+
+    /// Limits everything transmitted on this channel, whoever sends it, to `shaping`, and sets
+    /// the size of its transmit queue. Frames wait in the queue for the channel's TX worker,
+    /// which sends them as the limits allow, batched into as few `VCI_Transmit` calls as it can.
+    /// `None` lifts the limits and goes back to [`TxShaping::default`]; what is queued stays
+    /// queued either way. A bus load limit needs the channel initialized with a known bitrate.
+    pub fn set_tx_shaping(&self, shaping: Option<TxShaping>) -> Result<(), CanError> {
+        let bps = self.config().and_then(|config| config.bitrate().bps());
+        if shaping.is_some_and(|shaping| shaping.bus_load_percent.is_some()) && bps.is_none() {
+            return Err(match self.config() {
+                None => CanError::NotInitialized { channel: self.index },
+                Some(_) => CanError::UnknownBitrate { channel: self.index },
+            });
+        }
+        let shared = self.shared();
+        shared.shaper.lock().unwrap().reconfigure(shaping.unwrap_or_default(), bps, Instant::now());
+        shared.shaper_wake.notify_all();
+        Ok(())
+    }
+
+    /// Retries frames the adapter's full transmit buffer didn't take, with backoff, instead of
+    /// returning a short count straight away. `None`, the default, doesn't retry.
+    pub fn set_tx_retry(&self, retry: Option<TxRetry>) {
+        *self.shared().retry.lock().unwrap() = retry;
+    }
+
+    /// What the transmit queue and its limits have done so far.
+    pub fn shaper_stats(&self) -> ShaperStats {
+        self.shared().shaper.lock().unwrap().stats()
+    }
+
+    /// Waits up to `timeout` until the TX worker has handed everything queued to the DLL, and
+    /// returns whether it did.
+    pub fn flush_transmit(&self, timeout: Duration) -> bool {
+        self.inner.flush_transmit(self.index, timeout)
+    }
+
+    /// Returns once `frame` is queued for the TX worker, waiting for room if the queue is full;
+    /// failures sending it later only show in [`Channel::shaper_stats`].
+    pub fn transmit(&self, frame: &Frame) -> Result<(), CanError> {
+        self.transmit_with(frame, self.send_type())
+    }
+
+    /// Like [`Channel::transmit`], but fails with [`CanError::TxQueueFull`] instead of waiting
+    /// when the transmit queue is full. Never waits for the DLL.
+    pub fn try_transmit(&self, frame: &Frame) -> Result<(), CanError> {
+        self.transmit_until(frame, self.send_type(), Some(Instant::now()))
+    }
+
+    /// Like [`Channel::transmit`], but waits at most `timeout` for room in a full transmit
+    /// queue before failing with [`CanError::TxQueueFull`].
+    pub fn transmit_timeout(&self, frame: &Frame, timeout: Duration) -> Result<(), CanError> {
+        self.transmit_until(frame, self.send_type(), Some(Instant::now() + timeout))
+    }
+
+    /// Fails with [`CanError::ListenOnly`] without touching the DLL if the channel was
+    /// initialized in [`ChannelMode::ListenOnly`].
+    pub fn transmit_with(&self, frame: &Frame, send_type: SendType) -> Result<(), CanError> {
+        self.transmit_until(frame, send_type, None)
+    }
+
+    /// Waits for room in a full transmit queue until `until`, or as long as it takes.
+    fn transmit_until(&self, frame: &Frame, send_type: SendType, until: Option<Instant>) -> Result<(), CanError> {
+        if self.config().and_then(|c| c.channel_mode()) == Some(ChannelMode::ListenOnly) {
+            return Err(CanError::ListenOnly { channel: self.index });
+        }
+        let mut obj = VciCanObj::from(frame);
+        obj.send_type = send_type.raw();
+        match self.transmit_objs(std::slice::from_ref(&obj), until)? {
+            0 => Err(CanError::Transmit { channel: self.index, code: 0 }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Queues `frames` for the TX worker and returns how many were queued, waiting for room
+    /// while the queue is full; with [`TxShaping::drop_on_full`] the rest is refused instead,
+    /// and [`CanError::TxQueueFull`] returned if none fit. The worker sends them in as few
+    /// `VCI_Transmit` calls as it can, offering again whatever the DLL takes only part of. A
+    /// call that takes none means the adapter's buffer is full: the frames are dropped, or with
+    /// [`Channel::set_tx_retry`] offered again up to its deadline first. Frames dropped show in
+    /// [`Channel::shaper_stats`].
+    pub fn transmit_all(&self, frames: &[Frame]) -> Result<usize, CanError> {
+        if self.config().and_then(|c| c.channel_mode()) == Some(ChannelMode::ListenOnly) {
+            return Err(CanError::ListenOnly { channel: self.index });
+        }
+        let send_type = self.send_type().raw();
+        let objs: Vec<VciCanObj> = frames
+            .iter()
+            .map(|frame| VciCanObj { send_type, ..VciCanObj::from(frame) })
+            .collect();
+        self.transmit_objs(&objs, None)
+    }
+
+    fn transmit_objs(&self, objs: &[VciCanObj], until: Option<Instant>) -> Result<usize, CanError> {
+        if self.connected().is_err() {
+            return self.transmit_disconnected(objs);
+        }
+        self.start_tx_worker();
+        let shared = self.shared();
+        let mut shaper = shared.shaper.lock().unwrap();
+        let mut accepted = 0;
+        while accepted < objs.len() {
+            let room = shaper.config.queue.saturating_sub(shaper.queue.len());
+            if room > 0 {
+                let take = room.min(objs.len() - accepted);
+                shaper.queue.extend(&objs[accepted..accepted + take]);
+                accepted += take;
+                shared.shaper_wake.notify_all();
+                continue;
+            }
+            let now = Instant::now();
+            if shaper.config.drop_on_full || until.is_some_and(|until| now >= until) {
+                let refused = (objs.len() - accepted) as u64;
+                shaper.stats.refused += refused;
+                shaper.stats.dropped += refused;
+                if accepted == 0 {
+                    return Err(CanError::TxQueueFull { channel: self.index });
+                }
+                break;
+            }
+            if self.inner.closed.load(Ordering::SeqCst) {
+                break;
+            }
+            let wait = until.map_or(SHAPER_POLL, |until| (until - now).min(SHAPER_POLL));
+            shaper = shared.shaper_wake.wait_timeout(shaper, wait).unwrap().0;
+        }
+        Ok(accepted)
+    }
+
+    /// Hands `objs` to the DLL, on the TX worker.
+    fn send_now(&self, objs: &[VciCanObj]) -> Result<usize, CanError> {
+        let retry = self.shared().retry.lock().unwrap().clone();
+        let give_up = retry.as_ref().map(|retry| Instant::now() + retry.deadline);
+        let mut backoff = retry.as_ref().map_or(Duration::ZERO, |retry| retry.initial_backoff);
+        let mut sent = 0;
+        while sent < objs.len() {
+            let rest = &objs[sent..];
+            let code = self.call(|lib, t, d, c| lib.transmit(t, d, c, rest));
+            self.inner.record_io(code);
+            let accepted = match check_count(code, |code| CanError::Transmit { channel: self.index, code }) {
+                Ok(accepted) => (accepted as usize).min(rest.len()),
+                Err(err) if sent == 0 => {
+                    debug!(channel = self.index, %err);
+                    return Err(err);
+                }
+                Err(err) => {
+                    debug!(channel = self.index, %err, sent);
+                    break;
+                }
+            };
+            if accepted == 0 {
+                let (Some(retry), Some(give_up)) = (&retry, give_up) else {
+                    break;
+                };
+                if retry.pause(&mut backoff, give_up, &self.inner.closed) {
+                    continue;
+                }
+                warn!(channel = self.index, sent, abandoned = objs.len() - sent, "transmit buffer stayed full, dropping frames");
+                return Err(CanError::TxTimeout { channel: self.index, sent, abandoned: objs.len() - sent });
+            }
+            backoff = retry.as_ref().map_or(Duration::ZERO, |retry| retry.initial_backoff);
+            let now = Instant::now();
+            let mut load = self.shared().load.lock().unwrap();
+            for obj in &rest[..accepted] {
+                let frame = Frame::from(obj);
+                load.record(&frame, now);
+                trace!(channel = self.index, id = %frame.id(), data = ?frame.data(), "tx");
+            }
+            drop(load);
+            self.shared().transmitted.fetch_add(accepted as u64, Ordering::Relaxed);
+            sent += accepted;
+        }
+        Ok(sent)
+    }
+
+    /// Waits up to `timeout` for the next frame and returns it along with whatever else is
+    /// already waiting. An empty batch means the bus was idle; a failed `VCI_Receive` is always
+    /// an error, never an empty batch.
+    pub fn receive(&self, timeout: Duration) -> Result<Vec<Frame>, CanError> {
+        let pending = self.pending().map_or(1, |pending| pending as usize);
+        self.receive_batch(pending.clamp(1, MAX_BATCH), timeout)
+    }
+
+    /// Drains up to `max_frames` frames in one `VCI_Receive` call. Returns however many the
+    /// DLL actually delivered, which may be none.
+    pub fn receive_batch(&self, max_frames: usize, timeout: Duration) -> Result<Vec<Frame>, CanError> {
+        if let Err(err) = self.connected() {
+            thread::sleep(timeout);
+            return Err(err);
+        }
+        let mut objs = vec![VciCanObj::default(); max_frames.max(1)];
+        let wait = timeout.as_millis().min(i32::MAX as u128) as i32;
+        let code = self.call(|lib, t, d, c| lib.receive(t, d, c, &mut objs, wait));
+        if code < 0 {
+            self.shared().receive_failures.fetch_add(1, Ordering::SeqCst);
+            debug!(channel = self.index, code, "VCI_Receive failed");
+        }
+        self.inner.record_io(code);
+        let received = check_count(code, |code| CanError::Receive { channel: self.index, code })?;
+        objs.truncate(received as usize);
+        let now = Instant::now();
+        let mut clock = self.shared().clock.lock().unwrap();
+        let mut alignment = self.inner.alignment.lock().unwrap();
+        let frames: Vec<Frame> = objs
+            .iter()
+            .map(|obj| match obj.timestamp() {
+                None => Frame { host_time: Some((SystemTime::now(), now)), ..Frame::from(obj) },
+                Some(time_stamp) => {
+                    let ticks = clock.unwrap_ticks(time_stamp);
+                    let mut frame = Frame { ticks, ..Frame::from(obj) };
+                    let time = clock.convert(ticks, now);
+                    frame.host_time = Some(match alignment.as_mut() {
+                        Some(alignment) => alignment.align(self.index, &frame, ticks, time, now),
+                        None => time,
+                    });
+                    frame
+                }
+            })
+            .collect();
+        drop(alignment);
+        drop(clock);
+        if !frames.is_empty() {
+            let now = Instant::now();
+            let mut load = self.shared().load.lock().unwrap();
+            for frame in &frames {
+                load.record(frame, now);
+            }
+            drop(load);
+            self.shared().received.fetch_add(frames.len() as u64, Ordering::Relaxed);
+            let suspect = frames.iter().filter(|frame| frame.is_suspect()).count() as u64;
+            if suspect > 0 && self.shared().suspect.fetch_add(suspect, Ordering::Relaxed) == 0 {
+                let raw = objs.iter().find(|obj| Frame::from(*obj).is_suspect());
+                warn!(channel = self.index, ?raw, "the DLL delivered a malformed frame, clamped it and marked it suspect; counting any more");
+            }
+            if tracing::enabled!(Level::TRACE) {
+                for frame in &frames {
+                    trace!(channel = self.index, id = %frame.id(), data = ?frame.data(), "rx");
+                }
+            }
+            self.shared().subscribers.publish(self.index, &frames);
+        }
+        Ok(frames)
+    }
+
+    /// Number of frames waiting in the adapter's receive buffer.
+    pub fn pending(&self) -> Result<u32, CanError> {
+        self.connected()?;
+        let code = supported(self.call(|lib, t, d, c| lib.get_receive_num(t, d, c)), "VCI_GetReceiveNum")?;
+        check_count(code, |code| CanError::GetReceiveNum { channel: self.index, code })
+    }
+
+    /// Estimated bus load in percent over the last second, from the frames this process received
+    /// and transmitted on the port. `None` until initialized, or with a custom bit timing whose
+    /// rate isn't known.
+    pub fn bus_load(&self) -> Option<f64> {
+        let bps = self.config()?.bitrate().bps()?;
+        Some(self.shared().load.lock().unwrap().percent(bps, Instant::now()))
+    }
+
+    /// How the [`Channel::subscribe`] reader polls the adapter: how long each `VCI_Receive`
+    /// call waits and how many frames it asks for, how long it sleeps between empty polls, and
+    /// whether it adapts the wait to the traffic. Applies from the reader's next poll; waits and sleeps are split so it
+    /// stops within 100 ms of the last subscription going away, whatever they are.
+    pub fn set_receive_polling(&self, polling: ReceivePolling) {
+        *self.shared().polling.lock().unwrap() = polling;
+    }
+
+    pub fn receive_polling(&self) -> ReceivePolling {
+        *self.shared().polling.lock().unwrap()
+    }
+
+    /// Subscribes to every frame received on the port, with a private queue of `capacity`
+    /// frames. The first subscription starts a thread that drains the adapter continuously; it
+    /// stops once every subscription has been dropped. Frames received through other calls on
+    /// this port are delivered to subscribers as well.
+    pub fn subscribe(&self, capacity: usize) -> Subscription {
+        let (subscription, start_reader) = self.shared().subscribers.subscribe(capacity);
+        if start_reader {
+            let channel = self.clone();
+            thread::spawn(move || {
+                let mut poller = Poller::new();
+                while channel.shared().subscribers.keep_reading() {
+                    let polling = channel.receive_polling();
+                    let mut sleep = match channel.receive_pending(polling.max_frames.clamp(1, MAX_BATCH), poller.wait(&polling)) {
+                        Ok(frames) => poller.received(frames.len(), &polling),
+                        Err(_) => READ_ERROR_PAUSE,
+                    };
+                    while !sleep.is_zero() && channel.shared().subscribers.keep_reading() {
+                        let step = sleep.min(MAX_POLL_WAIT);
+                        thread::sleep(step);
+                        sleep -= step;
+                    }
+                }
+            });
+        }
+        subscription
+    }
+
+    /// Receives everything currently pending (capped at `max_frames`) in one call, or waits up
+    /// to `timeout` for the next frame when the buffer is empty. The pending count is only a
+    /// hint: the DLL may hand back fewer frames than were reported. DLLs without
+    /// `VCI_GetReceiveNum` are asked for `max_frames` straight away.
+    pub fn receive_pending(&self, max_frames: usize, timeout: Duration) -> Result<Vec<Frame>, CanError> {
+        let pending = match self.pending() {
+            Ok(pending) => pending as usize,
+            Err(CanError::Unsupported(_)) => max_frames,
+            Err(err) => return Err(err),
+        };
+        self.receive_batch(pending.clamp(1, max_frames.max(1)), timeout)
+    }
+}
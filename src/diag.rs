@@ -0,0 +1,79 @@
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
+
+/// Diagnostics kept while the monitor view has the terminal; older ones are dropped.
+const HELD_EVENTS: usize = 200;
+
+static HELD: Mutex<Option<VecDeque<Vec<u8>>>> = Mutex::new(None);
+
+/// Sends the diagnostics to `path`, or stderr, as text or one JSON object per line. RUST_LOG
+/// picks which, `info` when unset. Text on a terminal goes without timestamps, colored if
+/// `color`. Only the first call of a process, before any profile switch, takes effect.
+pub fn init(json: bool, path: Option<&Path>, color: bool) -> Result<(), String> {
+    if tracing::dispatcher::has_been_set() {
+        return Ok(());
+    }
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env()
+        .map_err(|err| format!("RUST_LOG: {err}"))?;
+    let (writer, terminal) = match path {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path).map_err(|err| format!("{}: {err}", path.display()))?;
+            (BoxMakeWriter::new(Mutex::new(file)), false)
+        }
+        None => (BoxMakeWriter::new(|| Stderr), io::stderr().is_terminal()),
+    };
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(writer);
+    let result = match (json, terminal) {
+        (true, _) => builder.json().flatten_event(true).with_span_list(false).try_init(),
+        (false, true) => builder.with_ansi(color).with_target(false).without_time().try_init(),
+        (false, false) => builder.with_ansi(false).try_init(),
+    };
+    result.map_err(|err| err.to_string())
+}
+
+/// Keeps the diagnostics meant for a terminal until [`release`], so they don't draw over the
+/// monitor view.
+pub fn hold() {
+    if io::stderr().is_terminal() {
+        *HELD.lock().unwrap() = Some(VecDeque::new());
+    }
+}
+
+/// Prints the diagnostics kept since [`hold`] and goes back to printing them as they come.
+pub fn release() {
+    let Some(held) = HELD.lock().unwrap().take() else {
+        return;
+    };
+    let mut stderr = io::stderr().lock();
+    for event in held {
+        let _ = stderr.write_all(&event);
+    }
+}
+
+struct Stderr;
+
+impl Write for Stderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(held) = HELD.lock().unwrap().as_mut() {
+            if held.len() == HELD_EVENTS {
+                held.pop_front();
+            }
+            held.push_back(buf.to_vec());
+            return Ok(buf.len());
+        }
+        io::stderr().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::device::Channel;
+use crate::error::CanError;
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::idstats::IdStats;
+
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(10);
+const RECEIVE_BATCH: usize = 256;
+
+/// What a [`Discovery`] saw of one ID on one channel.
+#[derive(Debug, Clone)]
+pub struct DiscoveredId {
+    pub channel: u32,
+    pub id: Id,
+    pub count: u64,
+    /// Whether any of the frames was a remote frame.
+    pub remote: bool,
+    /// Bit `n` is set once a frame with DLC `n` was seen.
+    dlcs: u16,
+    /// Bit `n` is set once payload byte `n` differed from the frame before.
+    changed: u8,
+    last: Frame,
+    stats: IdStats,
+}
+
+impl DiscoveredId {
+    /// Mean time between frames, from the device timestamps; `None` for an ID seen once.
+    pub fn period(&self) -> Option<Duration> {
+        self.stats.mean_gap()
+    }
+
+    pub fn stats(&self) -> &IdStats {
+        &self.stats
+    }
+
+    /// Every DLC seen, smallest first.
+    pub fn dlcs(&self) -> Vec<u8> {
+        (0..=8).filter(|dlc| self.dlcs & (1 << dlc) != 0).collect()
+    }
+
+    /// Whether the payload ever differed between two frames, including a change of length.
+    pub fn payload_changed(&self) -> bool {
+        self.changed != 0 || self.dlcs.count_ones() > 1
+    }
+
+    /// Positions of the bytes that took more than one value, in order.
+    pub fn changing_bytes(&self) -> Vec<usize> {
+        (0..8).filter(|byte| self.changed & (1 << byte) != 0).collect()
+    }
+}
+
+/// Summary of every unique ID on a bus, for getting to know an unfamiliar one. Standard and
+/// extended IDs with the same number are different IDs; they iterate standard IDs first, each
+/// in ID order, then channel.
+#[derive(Debug, Clone, Default)]
+pub struct Discovery {
+    ids: BTreeMap<(Id, u32), DiscoveredId>,
+    frames: u64,
+}
+
+impl Discovery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receives on every channel for `duration`, or until `stop` is set, e.g. by Ctrl+C.
+    pub fn listen(channels: &[Channel], duration: Duration, stop: &AtomicBool) -> Result<Self, CanError> {
+        let mut discovery = Self::new();
+        let start = Instant::now();
+        while !stop.load(Ordering::SeqCst) && start.elapsed() < duration {
+            for (index, channel) in (0..).zip(channels) {
+                for frame in channel.receive_pending(RECEIVE_BATCH, RECEIVE_TIMEOUT)? {
+                    discovery.observe(index, &frame);
+                }
+            }
+        }
+        Ok(discovery)
+    }
+
+    pub fn observe(&mut self, channel: u32, frame: &Frame) {
+        self.frames += 1;
+        let data = frame.data();
+        let entry = self.ids.entry((frame.id(), channel)).or_insert_with(|| DiscoveredId {
+            channel,
+            id: frame.id(),
+            count: 0,
+            remote: false,
+            dlcs: 0,
+            changed: 0,
+            last: *frame,
+            stats: IdStats::new(),
+        });
+        entry.count += 1;
+        entry.remote |= frame.is_remote();
+        entry.dlcs |= 1 << frame.dlc().min(8);
+        // Only bytes both frames have can be compared; a length change shows in the DLCs.
+        for (i, (old, new)) in entry.last.data().iter().zip(data).enumerate() {
+            if old != new {
+                entry.changed |= 1 << i;
+            }
+        }
+        entry.last = *frame;
+        entry.stats.record(frame.device_ticks());
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DiscoveredId> {
+        self.ids.values()
+    }
+
+    pub fn standard(&self) -> impl Iterator<Item = &DiscoveredId> {
+        self.iter().filter(|entry| !entry.id.is_extended())
+    }
+
+    pub fn extended(&self) -> impl Iterator<Item = &DiscoveredId> {
+        self.iter().filter(|entry| entry.id.is_extended())
+    }
+
+    /// Unique ID and channel pairs seen.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Frames seen in all.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+}
@@ -0,0 +1,89 @@
+//! `embedded-can` trait implementations, so code generic over `embedded_can::blocking::Can` can
+//! drive a CANalyst-II channel.
+
+use std::time::Duration;
+
+use embedded_can::{ErrorKind, ExtendedId, StandardId};
+
+use crate::device::Channel;
+use crate::error::CanError;
+use crate::frame::Frame;
+use crate::id::Id;
+
+/// How long each `VCI_Receive` call in the blocking `receive` waits before trying again.
+const RECEIVE_POLL: Duration = Duration::from_millis(100);
+
+impl From<Id> for embedded_can::Id {
+    /// Out-of-range raw values (only possible for an `Id` built by hand) are masked to 11 or 29
+    /// bits.
+    fn from(id: Id) -> Self {
+        match id {
+            Id::Standard(id) => StandardId::new(id & Id::MAX_STANDARD).expect("masked to 11 bits").into(),
+            Id::Extended(id) => ExtendedId::new(id & Id::MAX_EXTENDED).expect("masked to 29 bits").into(),
+        }
+    }
+}
+
+impl From<embedded_can::Id> for Id {
+    fn from(id: embedded_can::Id) -> Self {
+        match id {
+            embedded_can::Id::Standard(id) => Id::Standard(id.as_raw()),
+            embedded_can::Id::Extended(id) => Id::Extended(id.as_raw()),
+        }
+    }
+}
+
+impl embedded_can::Frame for Frame {
+    fn new(id: impl Into<embedded_can::Id>, data: &[u8]) -> Option<Self> {
+        Frame::new(id.into().into(), data)
+    }
+
+    fn new_remote(id: impl Into<embedded_can::Id>, dlc: usize) -> Option<Self> {
+        Frame::remote(id.into().into(), u8::try_from(dlc).ok()?)
+    }
+
+    fn is_extended(&self) -> bool {
+        Frame::is_extended(self)
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        self.is_remote()
+    }
+
+    fn id(&self) -> embedded_can::Id {
+        Frame::id(self).into()
+    }
+
+    fn dlc(&self) -> usize {
+        Frame::dlc(self).into()
+    }
+
+    fn data(&self) -> &[u8] {
+        Frame::data(self)
+    }
+}
+
+impl embedded_can::Error for CanError {
+    /// VCI calls only report that they failed, not which bus error caused it.
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl embedded_can::blocking::Can for Channel {
+    type Frame = Frame;
+    type Error = CanError;
+
+    fn transmit(&mut self, frame: &Frame) -> Result<(), CanError> {
+        Channel::transmit(self, frame)
+    }
+
+    /// Blocks until a frame arrives.
+    fn receive(&mut self) -> Result<Frame, CanError> {
+        loop {
+            if let Some(frame) = self.receive_batch(1, RECEIVE_POLL)?.pop() {
+                return Ok(frame);
+            }
+        }
+    }
+}
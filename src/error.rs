@@ -0,0 +1,177 @@
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum CanError {
+    /// Every location tried, in order, with why loading failed there.
+    DllLoad { tried: Vec<(PathBuf, libloading::Error)> },
+    SymbolMissing(&'static str),
+    /// The loaded DLL lacks this optional function.
+    Unsupported(&'static str),
+    OpenDevice { code: i32 },
+    CloseDevice { code: i32 },
+    ReadBoardInfo { code: i32 },
+    UsbReset { code: i32 },
+    InitCan { channel: u32, code: i32 },
+    StartCan { channel: u32, code: i32 },
+    ResetCan { channel: u32, code: i32 },
+    NotInitialized { channel: u32 },
+    /// A [`ChannelHandle`](crate::ChannelHandle) was asked to transmit before it was started.
+    NotStarted { channel: u32 },
+    AlreadyStarted { channel: u32 },
+    /// [`Device::take_channel`](crate::Device::take_channel) was called for a port whose
+    /// handle is still alive.
+    ChannelInUse { channel: u32 },
+    ListenOnly { channel: u32 },
+    /// The adapter is gone and a reconnect is under way.
+    Disconnected { channel: u32 },
+    /// The transmit shaper's queue is full and set to drop rather than wait.
+    TxQueueFull { channel: u32 },
+    /// The adapter's transmit buffer stayed full past the
+    /// [`TxRetry`](crate::TxRetry) deadline: `sent` frames went out, `abandoned` didn't.
+    TxTimeout { channel: u32, sent: usize, abandoned: usize },
+    /// The channel's bit timing is custom, so its bitrate isn't known.
+    UnknownBitrate { channel: u32 },
+    Transmit { channel: u32, code: i32 },
+    Receive { channel: u32, code: i32 },
+    GetReceiveNum { channel: u32, code: i32 },
+    ClearBuffer { channel: u32, code: i32 },
+    ReadErrInfo { channel: u32, code: i32 },
+    ReadCanStatus { channel: u32, code: i32 },
+    SetReference { channel: u32, code: i32 },
+}
+
+impl CanError {
+    /// Raw return value of the failing VCI call, if the error came from one.
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            Self::DllLoad { .. }
+            | Self::SymbolMissing(_)
+            | Self::Unsupported(_)
+            | Self::NotInitialized { .. }
+            | Self::NotStarted { .. }
+            | Self::AlreadyStarted { .. }
+            | Self::ChannelInUse { .. }
+            | Self::ListenOnly { .. }
+            | Self::Disconnected { .. }
+            | Self::TxQueueFull { .. }
+            | Self::TxTimeout { .. }
+            | Self::UnknownBitrate { .. } => None,
+            Self::OpenDevice { code }
+            | Self::CloseDevice { code }
+            | Self::ReadBoardInfo { code }
+            | Self::UsbReset { code }
+            | Self::InitCan { code, .. }
+            | Self::StartCan { code, .. }
+            | Self::ResetCan { code, .. }
+            | Self::Transmit { code, .. }
+            | Self::Receive { code, .. }
+            | Self::GetReceiveNum { code, .. }
+            | Self::ClearBuffer { code, .. }
+            | Self::ReadErrInfo { code, .. }
+            | Self::ReadCanStatus { code, .. }
+            | Self::SetReference { code, .. } => Some(*code),
+        }
+    }
+}
+
+impl fmt::Display for CanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DllLoad { tried } => {
+                write!(f, "failed to load the vendor library")?;
+                match tried.as_slice() {
+                    [] => Ok(()),
+                    [(path, source)] => write!(f, " {}: {source}", path.display()),
+                    tried => {
+                        write!(f, "; tried:")?;
+                        for (path, source) in tried {
+                            write!(f, "\n  {}: {source}", path.display())?;
+                        }
+                        Ok(())
+                    }
+                }
+            }
+            Self::SymbolMissing(symbol) => write!(f, "DLL does not export {symbol}"),
+            Self::Unsupported(symbol) => write!(f, "{symbol} is not available in the loaded DLL"),
+            Self::OpenDevice { code } => write!(f, "failed to open device (VCI_OpenDevice returned {code})"),
+            Self::CloseDevice { code } => write!(f, "failed to close device (VCI_CloseDevice returned {code})"),
+            Self::ReadBoardInfo { code } => {
+                write!(f, "failed to read board info (VCI_ReadBoardInfo returned {code})")
+            }
+            Self::UsbReset { code } => write!(f, "USB reset failed (VCI_UsbDeviceReset returned {code})"),
+            Self::InitCan { channel, code } => {
+                write!(f, "failed to initialize CAN{} (VCI_InitCAN returned {code})", channel + 1)
+            }
+            Self::StartCan { channel, code } => {
+                write!(f, "failed to start CAN{} (VCI_StartCAN returned {code})", channel + 1)
+            }
+            Self::ResetCan { channel, code } => {
+                write!(f, "failed to reset CAN{} (VCI_ResetCAN returned {code})", channel + 1)
+            }
+            Self::NotInitialized { channel } => write!(f, "CAN{} has not been initialized", channel + 1),
+            Self::NotStarted { channel } => write!(f, "CAN{} has not been started", channel + 1),
+            Self::AlreadyStarted { channel } => write!(f, "CAN{} is already started", channel + 1),
+            Self::ChannelInUse { channel } => write!(f, "CAN{} is already in use by another handle", channel + 1),
+            Self::ListenOnly { channel } => write!(f, "CAN{} is in listen-only mode and cannot transmit", channel + 1),
+            Self::Disconnected { channel } => write!(f, "CAN{} adapter is disconnected", channel + 1),
+            Self::TxQueueFull { channel } => write!(f, "CAN{} transmit queue is full, frame dropped", channel + 1),
+            Self::TxTimeout { channel, sent, abandoned } => write!(
+                f,
+                "CAN{} transmit buffer stayed full, gave up on {abandoned} frame(s) after sending {sent}",
+                channel + 1
+            ),
+            Self::UnknownBitrate { channel } => {
+                write!(f, "CAN{} uses a custom bit timing, so its bitrate is not known", channel + 1)
+            }
+            Self::Transmit { channel, code } => {
+                write!(f, "failed to transmit on CAN{} (VCI_Transmit returned {code})", channel + 1)
+            }
+            Self::Receive { channel, code } => {
+                write!(f, "failed to receive on CAN{} (VCI_Receive returned {code})", channel + 1)
+            }
+            Self::GetReceiveNum { channel, code } => {
+                write!(f, "failed to query CAN{} receive buffer (VCI_GetReceiveNum returned {code})", channel + 1)
+            }
+            Self::ClearBuffer { channel, code } => {
+                write!(f, "failed to clear CAN{} buffer (VCI_ClearBuffer returned {code})", channel + 1)
+            }
+            Self::ReadErrInfo { channel, code } => {
+                write!(f, "failed to read CAN{} error info (VCI_ReadErrInfo returned {code})", channel + 1)
+            }
+            Self::ReadCanStatus { channel, code } => {
+                write!(f, "failed to read CAN{} status (VCI_ReadCANStatus returned {code})", channel + 1)
+            }
+            Self::SetReference { channel, code } => {
+                write!(f, "failed to set CAN{} reference (VCI_SetReference returned {code})", channel + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::DllLoad { tried } => tried.last().map(|(_, source)| source as _),
+            _ => None,
+        }
+    }
+}
+
+/// Maps a VCI status return (1 = success, 0 = failure, -1 = device error) to a `Result`.
+pub(crate) fn check_status(code: i32, err: impl FnOnce(i32) -> CanError) -> Result<(), CanError> {
+    if code == 1 {
+        Ok(())
+    } else {
+        Err(err(code))
+    }
+}
+
+/// Maps a VCI frame-count return to a `Result`; negative values are device errors.
+pub(crate) fn check_count(code: i32, err: impl FnOnce(i32) -> CanError) -> Result<u32, CanError> {
+    if code < 0 {
+        Err(err(code))
+    } else {
+        Ok(code as u32)
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::frame::Frame;
+
+/// A consumer's view of a channel's received frames, from [`Channel::subscribe`].
+///
+/// Each subscription has its own bounded queue. Frames that arrive while it is full are dropped
+/// for this subscription only and counted in [`Subscription::dropped`]; the reader and the other
+/// subscribers are never held up by a slow one.
+///
+/// [`Channel::subscribe`]: crate::Channel::subscribe
+pub struct Subscription {
+    frames: Receiver<Frame>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    /// Waits for the next frame. Fails once the channel's reader has stopped.
+    pub fn recv(&self) -> Result<Frame, mpsc::RecvError> {
+        self.frames.recv()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Frame, RecvTimeoutError> {
+        self.frames.recv_timeout(timeout)
+    }
+
+    pub fn try_recv(&self) -> Result<Frame, TryRecvError> {
+        self.frames.try_recv()
+    }
+
+    /// Frames this subscription missed because its queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+struct Subscriber {
+    frames: SyncSender<Frame>,
+    /// Shared with the `Subscription`; only this side left means it was dropped.
+    dropped: Arc<AtomicU64>,
+}
+
+#[derive(Default)]
+struct State {
+    subscribers: Vec<Subscriber>,
+    /// Whether a reader thread is running for the channel.
+    reading: bool,
+}
+
+/// Subscribers of one channel.
+#[derive(Default)]
+pub(crate) struct FanOut {
+    state: Mutex<State>,
+}
+
+impl FanOut {
+    /// Adds a subscriber with room for `capacity` frames. Returns `true` if the caller should
+    /// start the reader thread.
+    pub(crate) fn subscribe(&self, capacity: usize) -> (Subscription, bool) {
+        let (sender, frames) = mpsc::sync_channel(capacity.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let mut state = self.state.lock().unwrap();
+        state.subscribers.push(Subscriber { frames: sender, dropped: Arc::clone(&dropped) });
+        let start = !state.reading;
+        state.reading = true;
+        (Subscription { frames, dropped }, start)
+    }
+
+    /// Hands `frames` to every subscriber without blocking. A subscriber falling behind is
+    /// warned about at its 1st, 10th, 100th... dropped frame.
+    pub(crate) fn publish(&self, channel: u32, frames: &[Frame]) {
+        let mut state = self.state.lock().unwrap();
+        if state.subscribers.is_empty() {
+            return;
+        }
+        state.subscribers.retain(|subscriber| {
+            for frame in frames {
+                match subscriber.frames.try_send(*frame) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => {
+                        let dropped = subscriber.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                        if 10u64.pow(dropped.ilog10()) == dropped {
+                            warn!(channel, dropped, "subscriber queue full, dropping frames");
+                        }
+                    }
+                    Err(TrySendError::Disconnected(_)) => return false,
+                }
+            }
+            true
+        });
+    }
+
+    /// Called by the reader between receive calls. Forgets dropped subscriptions and returns
+    /// `false`, marking the reader stopped, once none are left.
+    pub(crate) fn keep_reading(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.subscribers.retain(|subscriber| Arc::strong_count(&subscriber.dropped) > 1);
+        state.reading = !state.subscribers.is_empty();
+        state.reading
+    }
+}
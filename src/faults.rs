@@ -0,0 +1,147 @@
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::frame::Frame;
+
+/// Events a [`FaultTracker`] keeps for the run's summary; the counts go on past it.
+const HISTORY: usize = 1000;
+
+/// A fault confinement state whose entries a [`FaultTracker`] counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fault {
+    ErrorPassive,
+    BusOff,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ErrorPassive => "error-passive",
+            Self::BusOff => "bus-off",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultEvent {
+    /// The channel entered `fault` for the `count`th time this run.
+    Entered { fault: Fault, at: SystemTime, count: u64 },
+    /// The channel left `fault`, `after` it entered it.
+    Recovered { fault: Fault, at: SystemTime, after: Duration },
+}
+
+impl FaultEvent {
+    pub fn fault(&self) -> Fault {
+        match *self {
+            Self::Entered { fault, .. } | Self::Recovered { fault, .. } => fault,
+        }
+    }
+}
+
+/// One entry into a fault, for the summary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultRecord {
+    pub fault: Fault,
+    /// Since the tracker was created.
+    pub entered: Duration,
+    /// How long the channel stayed in it; `None` while it still is.
+    pub recovery: Option<Duration>,
+}
+
+/// Counts how often one channel entered error-passive and bus-off, from the state seen at each
+/// status poll. Only changes are events: a channel that sits in bus-off for a hundred polls
+/// counts once, and once more when it comes back and goes bus-off again. Bus-off counts as still
+/// error-passive, so error-passive only recovers once the channel is back to error-warning or
+/// better.
+#[derive(Debug, Clone)]
+pub struct FaultTracker {
+    started: Instant,
+    /// When the channel entered each fault, while it's in it: error-passive, bus-off.
+    since: [Option<Instant>; 2],
+    counts: [u64; 2],
+    history: Vec<FaultRecord>,
+    /// Entries of `history` still open, per fault.
+    open: [Option<usize>; 2],
+}
+
+impl FaultTracker {
+    pub fn new(now: Instant) -> Self {
+        Self { started: now, since: [None; 2], counts: [0; 2], history: Vec::new(), open: [None; 2] }
+    }
+
+    /// Feeds the state of one poll and returns what changed, entries before recoveries.
+    pub fn update(&mut self, passive: bool, bus_off: bool, now: Instant) -> Vec<FaultEvent> {
+        let wall = SystemTime::now();
+        let mut events = Vec::new();
+        for (fault, active) in [(Fault::ErrorPassive, passive || bus_off), (Fault::BusOff, bus_off)] {
+            let index = fault as usize;
+            match (self.since[index], active) {
+                (None, true) => {
+                    self.since[index] = Some(now);
+                    self.counts[index] += 1;
+                    if self.history.len() < HISTORY {
+                        self.open[index] = Some(self.history.len());
+                        self.history.push(FaultRecord { fault, entered: now.saturating_duration_since(self.started), recovery: None });
+                    }
+                    events.push(FaultEvent::Entered { fault, at: wall, count: self.counts[index] });
+                }
+                (Some(since), false) => {
+                    self.since[index] = None;
+                    let after = now.saturating_duration_since(since);
+                    if let Some(open) = self.open[index].take() {
+                        self.history[open].recovery = Some(after);
+                    }
+                    events.push(FaultEvent::Recovered { fault, at: wall, after });
+                }
+                _ => {}
+            }
+        }
+        events.sort_by_key(|event| matches!(event, FaultEvent::Recovered { .. }));
+        events
+    }
+
+    /// Times the channel entered `fault`.
+    pub fn count(&self, fault: Fault) -> u64 {
+        self.counts[fault as usize]
+    }
+
+    /// Whether the channel is in `fault` as of the last poll.
+    pub fn is_in(&self, fault: Fault) -> bool {
+        self.since[fault as usize].is_some()
+    }
+
+    /// The first thousand entries, oldest first.
+    pub fn history(&self) -> &[FaultRecord] {
+        &self.history
+    }
+
+    /// How long the last finished stay in `fault` lasted.
+    pub fn last_recovery(&self, fault: Fault) -> Option<Duration> {
+        self.history.iter().rev().filter(|record| record.fault == fault).find_map(|record| record.recovery)
+    }
+}
+
+/// What a [`FaultAlert`] does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FaultAction {
+    /// Runs a shell command.
+    Exec(String),
+    /// Transmits a frame on the channel that faulted, once it's off bus-off again.
+    Send(Frame),
+}
+
+/// Acts when a channel enters `fault` for the `count`th time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultAlert {
+    pub fault: Fault,
+    pub count: u64,
+    pub action: FaultAction,
+}
+
+impl FaultAlert {
+    /// Whether `event` crosses this alert's threshold. It does once per channel, however many
+    /// more times the channel faults.
+    pub fn fires(&self, event: &FaultEvent) -> bool {
+        matches!(*event, FaultEvent::Entered { fault, count, .. } if fault == self.fault && count == self.count)
+    }
+}
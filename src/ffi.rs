@@ -0,0 +1,606 @@
+use libloading::Library;
+use std::env;
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::backend::CanBackend;
+use crate::bitrate::Bitrate;
+use crate::board::BoardInfo;
+use crate::error::CanError;
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::mode::ChannelMode;
+use crate::status::{CanStatus, ErrorFlags, ErrorInfo};
+
+/// A frame as `VCI_Transmit` and `VCI_Receive` exchange it. Only ever turned into a [`Frame`]
+/// inside the crate, so whatever the DLL wrote goes through the checks of that conversion.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct VciCanObj {
+    pub id: u32,
+    pub time_stamp: u32,
+    pub time_flag: u8,
+    pub send_type: u8,
+    pub remote_flag: u8,
+    pub extern_flag: u8,
+    pub data_len: u8,
+    pub data: [u8; 8],
+    pub reserved: [u8; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VciInitConfig {
+    pub acc_code: u32,
+    pub acc_mask: u32,
+    pub reserved: u32,
+    pub filter: u8,
+    pub timing0: u8,
+    pub timing1: u8,
+    pub mode: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VciBoardInfo {
+    pub hw_version: u16,
+    pub fw_version: u16,
+    pub dr_version: u16,
+    pub in_version: u16,
+    pub irq_num: u16,
+    pub can_num: u8,
+    pub str_serial_num: [u8; 20],
+    pub str_hw_type: [u8; 40],
+    pub reserved: [u16; 4],
+}
+
+impl Default for VciBoardInfo {
+    fn default() -> Self {
+        Self {
+            hw_version: 0,
+            fw_version: 0,
+            dr_version: 0,
+            in_version: 0,
+            irq_num: 0,
+            can_num: 0,
+            str_serial_num: [0; 20],
+            str_hw_type: [0; 40],
+            reserved: [0; 4],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VciErrInfo {
+    pub err_code: u32,
+    pub passive_err_data: [u8; 3],
+    pub ar_lost_err_data: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VciCanStatus {
+    pub err_interrupt: u8,
+    pub reg_mode: u8,
+    pub reg_status: u8,
+    pub reg_al_capture: u8,
+    pub reg_ec_capture: u8,
+    pub reg_ew_limit: u8,
+    pub reg_re_counter: u8,
+    pub reg_te_counter: u8,
+    pub reserved: u32,
+}
+
+impl VciInitConfig {
+    /// Accept-all filter, normal mode, at the given bitrate.
+    pub fn with_bitrate(bitrate: Bitrate) -> Self {
+        let (timing0, timing1) = bitrate.timing();
+        Self {
+            acc_code: 0,
+            acc_mask: 0xFFFFFFFF,
+            reserved: 0,
+            filter: 1,
+            timing0,
+            timing1,
+            mode: 0,
+        }
+    }
+
+    pub fn with_mode(mut self, mode: ChannelMode) -> Self {
+        self.mode = mode.raw();
+        self
+    }
+
+    /// Decoded `mode`; unknown raw values are reported as `None`.
+    pub fn channel_mode(&self) -> Option<ChannelMode> {
+        ChannelMode::from_raw(self.mode)
+    }
+
+    pub fn bitrate(&self) -> Bitrate {
+        Bitrate::from_timing(self.timing0, self.timing1)
+    }
+}
+
+/// File names of the vendor library, in the order they are tried. The functions are stdcall on
+/// Windows and cdecl elsewhere, which `extern "system"` picks per target.
+#[cfg(windows)]
+const DEFAULT_LIBRARIES: &[&str] = &["ControlCAN.dll"];
+#[cfg(not(windows))]
+const DEFAULT_LIBRARIES: &[&str] = &["libcontrolcan.so", "libusbcan.so"];
+
+/// Environment variable naming the vendor library when no path is passed to
+/// [`CanLibrary::load`].
+const LIBRARY_ENV: &str = "RUSTCANBUS_DLL";
+
+/// The loaded vendor library; the backend behind [`Device::open`](crate::Device::open).
+pub struct CanLibrary {
+    _lib: Arc<Library>,
+    path: PathBuf,
+    /// Held across open, close, USB reset and enumeration. Those touch the DLL's table of
+    /// attached adapters, which isn't safe to change from two threads at once; per-channel
+    /// calls on different devices don't need it.
+    devices: Mutex<()>,
+    pub(crate) vci_open_device: unsafe extern "system" fn(u32, u32, u32) -> i32,
+    pub(crate) vci_close_device: unsafe extern "system" fn(u32, u32) -> i32,
+    pub(crate) vci_usb_device_reset: Option<unsafe extern "system" fn(u32, u32, u32) -> i32>,
+    pub(crate) vci_init_can: unsafe extern "system" fn(u32, u32, u32, *const VciInitConfig) -> i32,
+    pub(crate) vci_start_can: unsafe extern "system" fn(u32, u32, u32) -> i32,
+    pub(crate) vci_reset_can: Option<unsafe extern "system" fn(u32, u32, u32) -> i32>,
+    pub(crate) vci_transmit: unsafe extern "system" fn(u32, u32, u32, *const VciCanObj, u32) -> i32,
+    pub(crate) vci_receive: unsafe extern "system" fn(u32, u32, u32, *mut VciCanObj, u32, i32) -> i32,
+    pub(crate) vci_read_board_info: Option<unsafe extern "system" fn(u32, u32, *mut VciBoardInfo) -> i32>,
+    pub(crate) vci_get_receive_num: Option<unsafe extern "system" fn(u32, u32, u32) -> i32>,
+    pub(crate) vci_find_usb_device2: Option<unsafe extern "system" fn(*mut VciBoardInfo) -> i32>,
+    pub(crate) vci_clear_buffer: Option<unsafe extern "system" fn(u32, u32, u32) -> i32>,
+    pub(crate) vci_read_err_info: Option<unsafe extern "system" fn(u32, u32, u32, *mut VciErrInfo) -> i32>,
+    pub(crate) vci_read_can_status: Option<unsafe extern "system" fn(u32, u32, u32, *mut VciCanStatus) -> i32>,
+    pub(crate) vci_set_reference: Option<unsafe extern "system" fn(u32, u32, u32, u32, *mut c_void) -> i32>,
+}
+
+impl CanLibrary {
+    /// Loads the vendor library from `path`, a file or a directory containing `ControlCAN.dll`
+    /// (`libcontrolcan.so` or `libusbcan.so` on Linux). Without one, `RUSTCANBUS_DLL` is used
+    /// the same way. If neither is set, the executable's directory, the working directory and
+    /// the system search path are tried in that order.
+    ///
+    /// An explicit location is the only one tried, so a typo isn't papered over by another copy
+    /// of the DLL. [`CanError::DllLoad`] lists every location that failed.
+    pub fn load(path: Option<&Path>) -> Result<Arc<Self>, CanError> {
+        let mut tried = Vec::new();
+        for candidate in search_paths(path) {
+            // Passed as an `OsStr`, so non-ASCII Windows paths reach `LoadLibraryW` intact.
+            match unsafe { Library::new(candidate.as_os_str()) } {
+                Ok(lib) => return Self::bind(lib, candidate),
+                Err(source) => tried.push((candidate, source)),
+            }
+        }
+        Err(CanError::DllLoad { tried })
+    }
+
+    /// Where the library was loaded from. A bare file name means the system search path found it.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The functions older DLLs may lack, and whether this one exports each. Calls needing a
+    /// missing one fail with [`CanError::Unsupported`].
+    pub fn optional_functions(&self) -> [(&'static str, bool); 9] {
+        [
+            ("VCI_UsbDeviceReset", self.vci_usb_device_reset.is_some()),
+            ("VCI_ResetCAN", self.vci_reset_can.is_some()),
+            ("VCI_ReadBoardInfo", self.vci_read_board_info.is_some()),
+            ("VCI_GetReceiveNum", self.vci_get_receive_num.is_some()),
+            ("VCI_FindUsbDevice2", self.vci_find_usb_device2.is_some()),
+            ("VCI_ClearBuffer", self.vci_clear_buffer.is_some()),
+            ("VCI_ReadErrInfo", self.vci_read_err_info.is_some()),
+            ("VCI_ReadCANStatus", self.vci_read_can_status.is_some()),
+            ("VCI_SetReference", self.vci_set_reference.is_some()),
+        ]
+    }
+
+    fn bind(lib: Library, path: PathBuf) -> Result<Arc<Self>, CanError> {
+        let lib = Arc::new(lib);
+
+        Ok(Arc::new(Self {
+            _lib: lib.clone(),
+            path,
+            devices: Mutex::new(()),
+            vci_open_device: symbol(&lib, "VCI_OpenDevice")?,
+            vci_close_device: symbol(&lib, "VCI_CloseDevice")?,
+            vci_usb_device_reset: optional_symbol(&lib, "VCI_UsbDeviceReset"),
+            vci_init_can: symbol(&lib, "VCI_InitCAN")?,
+            vci_start_can: symbol(&lib, "VCI_StartCAN")?,
+            vci_reset_can: optional_symbol(&lib, "VCI_ResetCAN"),
+            vci_transmit: symbol(&lib, "VCI_Transmit")?,
+            vci_receive: symbol(&lib, "VCI_Receive")?,
+            vci_read_board_info: optional_symbol(&lib, "VCI_ReadBoardInfo"),
+            vci_get_receive_num: optional_symbol(&lib, "VCI_GetReceiveNum"),
+            vci_find_usb_device2: optional_symbol(&lib, "VCI_FindUsbDevice2"),
+            vci_clear_buffer: optional_symbol(&lib, "VCI_ClearBuffer"),
+            vci_read_err_info: optional_symbol(&lib, "VCI_ReadErrInfo"),
+            vci_read_can_status: optional_symbol(&lib, "VCI_ReadCANStatus"),
+            vci_set_reference: optional_symbol(&lib, "VCI_SetReference"),
+        }))
+    }
+}
+
+fn search_paths(explicit: Option<&Path>) -> Vec<PathBuf> {
+    let given = explicit
+        .map(Path::to_path_buf)
+        .or_else(|| env::var_os(LIBRARY_ENV).filter(|value| !value.is_empty()).map(PathBuf::from));
+    match given {
+        Some(dir) if dir.is_dir() => return DEFAULT_LIBRARIES.iter().map(|name| dir.join(name)).collect(),
+        Some(file) => return vec![file],
+        None => {}
+    }
+    let mut dirs = Vec::new();
+    if let Some(dir) = env::current_exe().ok().as_deref().and_then(Path::parent) {
+        dirs.push(dir.to_path_buf());
+    }
+    if let Ok(dir) = env::current_dir() {
+        dirs.push(dir);
+    }
+    dirs.dedup();
+    let mut paths: Vec<PathBuf> = dirs.iter().flat_map(|dir| DEFAULT_LIBRARIES.iter().map(|name| dir.join(name))).collect();
+    paths.extend(DEFAULT_LIBRARIES.iter().map(PathBuf::from));
+    paths
+}
+
+fn symbol<T: Copy>(lib: &Library, name: &'static str) -> Result<T, CanError> {
+    optional_symbol(lib, name).ok_or(CanError::SymbolMissing(name))
+}
+
+fn optional_symbol<T: Copy>(lib: &Library, name: &str) -> Option<T> {
+    unsafe { lib.get::<T>(name.as_bytes()) }.ok().map(|sym| *sym)
+}
+
+impl CanBackend for CanLibrary {
+    fn open_device(&self, dev_type: u32, dev_index: u32) -> i32 {
+        let _guard = self.devices.lock().unwrap();
+        unsafe { (self.vci_open_device)(dev_type, dev_index, 0) }
+    }
+
+    fn close_device(&self, dev_type: u32, dev_index: u32) -> i32 {
+        let _guard = self.devices.lock().unwrap();
+        unsafe { (self.vci_close_device)(dev_type, dev_index) }
+    }
+
+    fn usb_device_reset(&self, dev_type: u32, dev_index: u32) -> Option<i32> {
+        let _guard = self.devices.lock().unwrap();
+        self.vci_usb_device_reset.map(|f| unsafe { f(dev_type, dev_index, 0) })
+    }
+
+    /// `VCI_FindUsbDevice2` takes no length, so `infos` must hold at least 50 entries.
+    fn find_usb_devices(&self, infos: &mut [VciBoardInfo]) -> Option<i32> {
+        assert!(infos.len() >= 50, "VCI_FindUsbDevice2 may write 50 entries");
+        let _guard = self.devices.lock().unwrap();
+        self.vci_find_usb_device2.map(|f| unsafe { f(infos.as_mut_ptr()) })
+    }
+
+    fn read_board_info(&self, dev_type: u32, dev_index: u32, info: &mut VciBoardInfo) -> Option<i32> {
+        self.vci_read_board_info.map(|f| unsafe { f(dev_type, dev_index, info) })
+    }
+
+    fn init_can(&self, dev_type: u32, dev_index: u32, can_index: u32, config: &VciInitConfig) -> i32 {
+        unsafe { (self.vci_init_can)(dev_type, dev_index, can_index, config) }
+    }
+
+    fn start_can(&self, dev_type: u32, dev_index: u32, can_index: u32) -> i32 {
+        unsafe { (self.vci_start_can)(dev_type, dev_index, can_index) }
+    }
+
+    fn reset_can(&self, dev_type: u32, dev_index: u32, can_index: u32) -> Option<i32> {
+        self.vci_reset_can.map(|f| unsafe { f(dev_type, dev_index, can_index) })
+    }
+
+    fn clear_buffer(&self, dev_type: u32, dev_index: u32, can_index: u32) -> Option<i32> {
+        self.vci_clear_buffer.map(|f| unsafe { f(dev_type, dev_index, can_index) })
+    }
+
+    fn transmit(&self, dev_type: u32, dev_index: u32, can_index: u32, frames: &[VciCanObj]) -> i32 {
+        unsafe { (self.vci_transmit)(dev_type, dev_index, can_index, frames.as_ptr(), frames.len() as u32) }
+    }
+
+    fn receive(&self, dev_type: u32, dev_index: u32, can_index: u32, frames: &mut [VciCanObj], wait_ms: i32) -> i32 {
+        unsafe { (self.vci_receive)(dev_type, dev_index, can_index, frames.as_mut_ptr(), frames.len() as u32, wait_ms) }
+    }
+
+    fn get_receive_num(&self, dev_type: u32, dev_index: u32, can_index: u32) -> Option<i32> {
+        self.vci_get_receive_num.map(|f| unsafe { f(dev_type, dev_index, can_index) })
+    }
+
+    fn read_err_info(&self, dev_type: u32, dev_index: u32, can_index: u32, info: &mut VciErrInfo) -> Option<i32> {
+        self.vci_read_err_info.map(|f| unsafe { f(dev_type, dev_index, can_index, info) })
+    }
+
+    fn read_can_status(&self, dev_type: u32, dev_index: u32, can_index: u32, status: &mut VciCanStatus) -> Option<i32> {
+        self.vci_read_can_status.map(|f| unsafe { f(dev_type, dev_index, can_index, status) })
+    }
+
+    fn set_reference(&self, dev_type: u32, dev_index: u32, can_index: u32, ref_type: u32, data: &mut [u8]) -> Option<i32> {
+        let data = if data.is_empty() { std::ptr::null_mut() } else { data.as_mut_ptr().cast() };
+        self.vci_set_reference.map(|f| unsafe { f(dev_type, dev_index, can_index, ref_type, data) })
+    }
+}
+
+impl From<&Frame> for VciCanObj {
+    fn from(frame: &Frame) -> Self {
+        Self {
+            id: frame.id.raw(),
+            extern_flag: frame.id.is_extended() as u8,
+            remote_flag: frame.remote as u8,
+            data_len: frame.len,
+            data: frame.data,
+            ..Default::default()
+        }
+    }
+}
+
+impl VciCanObj {
+    /// The device timestamp, unless the adapter flagged it invalid.
+    pub(crate) fn timestamp(&self) -> Option<u32> {
+        (self.time_flag != 0).then_some(self.time_stamp)
+    }
+}
+
+/// Never fails, whatever the DLL wrote: the data length is clamped to 8, flag bytes count as set
+/// when non-zero and the ID is masked to its kind's width. A frame that needed any of that, or
+/// came with a flag byte other than 0 and 1, is marked [`Frame::is_suspect`]. The reserved bytes
+/// and `send_type` are ignored.
+impl From<&VciCanObj> for Frame {
+    fn from(obj: &VciCanObj) -> Self {
+        let extended = obj.extern_flag != 0;
+        let (id, in_range) = if extended {
+            (Id::Extended(obj.id & Id::MAX_EXTENDED), obj.id <= Id::MAX_EXTENDED)
+        } else {
+            (Id::Standard((obj.id & Id::MAX_STANDARD as u32) as u16), obj.id <= Id::MAX_STANDARD as u32)
+        };
+        let suspect = !in_range || obj.data_len > 8 || [obj.time_flag, obj.remote_flag, obj.extern_flag].iter().any(|&flag| flag > 1);
+        Self {
+            id,
+            data: obj.data,
+            len: obj.data_len.min(8),
+            remote: obj.remote_flag != 0,
+            time_stamp: obj.time_stamp,
+            ticks: u64::from(obj.time_stamp),
+            host_time: None,
+            suspect,
+        }
+    }
+}
+
+impl From<&VciBoardInfo> for BoardInfo {
+    fn from(info: &VciBoardInfo) -> Self {
+        Self {
+            hw_version: info.hw_version,
+            fw_version: info.fw_version,
+            driver_version: info.dr_version,
+            interface_version: info.in_version,
+            irq: info.irq_num,
+            can_channels: info.can_num,
+            serial: c_string(&info.str_serial_num),
+            hw_type: c_string(&info.str_hw_type),
+        }
+    }
+}
+
+impl From<&VciErrInfo> for ErrorInfo {
+    fn from(info: &VciErrInfo) -> Self {
+        Self {
+            flags: ErrorFlags(info.err_code),
+            passive: info.passive_err_data,
+            arbitration_lost: info.ar_lost_err_data,
+        }
+    }
+}
+
+impl From<&VciCanStatus> for CanStatus {
+    fn from(status: &VciCanStatus) -> Self {
+        Self {
+            error_interrupt: status.err_interrupt,
+            mode: status.reg_mode,
+            status: status.reg_status,
+            arbitration_lost_capture: status.reg_al_capture,
+            error_code_capture: status.reg_ec_capture,
+            error_warning_limit: status.reg_ew_limit,
+            rx_error_counter: status.reg_re_counter,
+            tx_error_counter: status.reg_te_counter,
+        }
+    }
+}
+
+/// Converts a fixed-size, NUL-padded C string field into an owned `String`.
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::XorShift;
+    use crate::status::ErrorState;
+
+    /// Whatever 24 bytes the DLL might have written into one `VCI_CAN_OBJ`.
+    fn obj_from(bytes: &[u8; 24]) -> VciCanObj {
+        VciCanObj {
+            id: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            time_stamp: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            time_flag: bytes[8],
+            send_type: bytes[9],
+            remote_flag: bytes[10],
+            extern_flag: bytes[11],
+            data_len: bytes[12],
+            data: bytes[13..21].try_into().unwrap(),
+            reserved: bytes[21..24].try_into().unwrap(),
+        }
+    }
+
+    /// Uniformly random bytes are nearly always malformed, so half the objects only get values
+    /// around the edges of each field.
+    fn random_obj(rng: &mut XorShift) -> VciCanObj {
+        let mut bytes = [0u8; 24];
+        for chunk in bytes.chunks_mut(8) {
+            chunk.copy_from_slice(&rng.next().to_le_bytes()[..chunk.len()]);
+        }
+        let mut obj = obj_from(&bytes);
+        if rng.next().is_multiple_of(2) {
+            obj.id = rng.pick(&[0, 0x123, 0x7FF, 0x800, 0x1FFF_FFFF, 0x2000_0000, u32::MAX]) & (u32::MAX >> (rng.below(3) * 11));
+            obj.data_len = rng.pick(&[0, 1, 7, 8, 9, 255]) & rng.pick(&[0x0F, 0xFF]);
+            obj.time_flag = rng.pick(&[0, 1, 0, 1, 0, 1, 0, 1, 2, 255]);
+            obj.remote_flag = rng.pick(&[0, 1, 0, 1, 0, 1, 0, 1, 2, 255]);
+            obj.extern_flag = rng.pick(&[0, 1, 0, 1, 0, 1, 0, 1, 2, 255]);
+        }
+        obj
+    }
+
+    #[test]
+    fn any_bytes_from_the_dll_make_a_sane_frame() {
+        let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+        let mut suspect = 0;
+        for _ in 0..100_000 {
+            let obj = random_obj(&mut rng);
+            let frame = Frame::from(&obj);
+            let extended = obj.extern_flag != 0;
+            let max = if extended { Id::MAX_EXTENDED } else { Id::MAX_STANDARD as u32 };
+            let bad = obj.id > max || obj.data_len > 8 || [obj.time_flag, obj.remote_flag, obj.extern_flag].iter().any(|&flag| flag > 1);
+
+            assert!(frame.dlc() <= 8 && frame.data().len() <= 8, "{obj:?}");
+            assert!(frame.id().is_valid(), "{obj:?}");
+            assert_eq!(frame.is_extended(), extended, "{obj:?}");
+            assert_eq!(frame.id().raw(), obj.id & max, "{obj:?}");
+            assert_eq!(frame.is_remote(), obj.remote_flag != 0, "{obj:?}");
+            assert_eq!(frame.is_suspect(), bad, "{obj:?}");
+            if !frame.is_remote() {
+                assert_eq!(frame.data(), &obj.data[..frame.dlc() as usize], "{obj:?}");
+            }
+            suspect += usize::from(bad);
+        }
+        assert!((5_000..95_000).contains(&suspect), "{suspect} suspect; the input isn't mixed enough");
+    }
+
+    #[test]
+    fn well_formed_frames_are_not_suspect() {
+        let standard = VciCanObj { id: 0x7FF, time_flag: 1, data_len: 8, data: [1, 2, 3, 4, 5, 6, 7, 8], ..Default::default() };
+        let extended = VciCanObj { id: 0x1FFF_FFFF, extern_flag: 1, remote_flag: 1, data_len: 3, ..Default::default() };
+        for obj in [standard, extended] {
+            let frame = Frame::from(&obj);
+            assert!(!frame.is_suspect());
+            assert_eq!(VciCanObj::from(&frame).id, obj.id);
+        }
+    }
+
+    #[test]
+    fn malformed_fields_are_clamped_and_flagged() {
+        let frame = Frame::from(&VciCanObj { id: 0x1234, data_len: 200, data: [0xAA; 8], ..Default::default() });
+        assert!(frame.is_suspect());
+        assert_eq!((frame.id(), frame.dlc()), (Id::Standard(0x234), 8));
+
+        let frame = Frame::from(&VciCanObj { id: 0xFFFF_FFFF, extern_flag: 1, ..Default::default() });
+        assert!(frame.is_suspect());
+        assert_eq!(frame.id(), Id::Extended(0x1FFF_FFFF));
+
+        let frame = Frame::from(&VciCanObj { id: 0x100, extern_flag: 0x80, ..Default::default() });
+        assert!(frame.is_suspect() && frame.is_extended());
+    }
+
+    /// The 12 bytes of a `VCI_CAN_STATUS`, in field order.
+    fn status_from(bytes: [u8; 12]) -> CanStatus {
+        let raw = VciCanStatus {
+            err_interrupt: bytes[0],
+            reg_mode: bytes[1],
+            reg_status: bytes[2],
+            reg_al_capture: bytes[3],
+            reg_ec_capture: bytes[4],
+            reg_ew_limit: bytes[5],
+            reg_re_counter: bytes[6],
+            reg_te_counter: bytes[7],
+            reserved: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+        };
+        CanStatus::from(&raw)
+    }
+
+    /// The 8 bytes of a `VCI_ERR_INFO`, in field order.
+    fn err_info_from(bytes: [u8; 8]) -> ErrorInfo {
+        let raw = VciErrInfo {
+            err_code: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            passive_err_data: bytes[4..7].try_into().unwrap(),
+            ar_lost_err_data: bytes[7],
+        };
+        ErrorInfo::from(&raw)
+    }
+
+    #[test]
+    fn decodes_captured_controller_status() {
+        // Idle after start: transmit buffer released and last transmission complete.
+        let idle = status_from([0x00, 0x00, 0x0C, 0x00, 0x00, 0x60, 0x00, 0x00, 0, 0, 0, 0]);
+        assert!(idle.transmit_buffer_released() && idle.transmission_complete());
+        assert!(!idle.receive_buffer_full() && !idle.data_overrun() && !idle.error_status() && !idle.bus_off());
+        assert_eq!(idle.error_state(), ErrorState::Active);
+        assert_eq!(idle.to_string(), "error-active | REC=0 TEC=0 | buffer: ok | mode: normal (MOD=0x00 SR=0x0C)");
+
+        // Sending into an unacknowledged bus: TEC climbing past the warning limit, frame stuck.
+        let nack = status_from([0x04, 0x00, 0x78, 0x19, 0xD9, 0x60, 0x00, 0x68, 0, 0, 0, 0]);
+        assert!(nack.receiving() && nack.transmitting() && nack.error_status() && nack.transmission_complete());
+        assert_eq!((nack.error_code_capture, nack.arbitration_lost_capture), (0xD9, 0x19));
+        assert_eq!(nack.error_state(), ErrorState::Warning);
+        assert_eq!(nack.to_string(), "error-warning | REC=0 TEC=104 | buffer: tx buffer locked | mode: normal (MOD=0x00 SR=0x78)");
+
+        // Sniffing a bus at the wrong bitrate.
+        let passive = status_from([0x20, 0x02, 0x47, 0x00, 0x1A, 0x60, 0x87, 0x00, 0, 0, 0, 0]);
+        assert!(passive.listen_only() && passive.receive_buffer_full() && passive.data_overrun());
+        assert_eq!(passive.error_state(), ErrorState::Passive);
+        assert_eq!(passive.to_string(), "error-passive | REC=135 TEC=0 | buffer: rx buffer full, data overrun | mode: listen-only (MOD=0x02 SR=0x47)");
+
+        // The controller resets itself on bus-off and leaves TEC at 127.
+        let bus_off = status_from([0x80, 0x01, 0xC0, 0x00, 0x00, 0x60, 0x00, 0x7F, 0, 0, 0, 0]);
+        assert!(bus_off.bus_off() && bus_off.reset_mode());
+        assert_eq!(bus_off.error_state(), ErrorState::BusOff);
+        assert_eq!(bus_off.to_string(), "bus-off | REC=0 TEC=127 | buffer: tx buffer locked | mode: reset (MOD=0x01 SR=0xC0)");
+
+        let self_test = status_from([0x00, 0x04, 0x0C, 0x00, 0x00, 0x60, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert!(self_test.self_test() && !self_test.listen_only());
+        assert!(self_test.to_string().contains("mode: self-test"));
+    }
+
+    #[test]
+    fn error_state_follows_the_counters_and_warning_limit() {
+        let state = |limit: u8, rec: u8, tec: u8, status: u8| status_from([0, 0, status, 0, 0, limit, rec, tec, 0, 0, 0, 0]).error_state();
+        assert_eq!(state(0x60, 95, 95, 0x0C), ErrorState::Active);
+        assert_eq!(state(0x60, 96, 0, 0x0C), ErrorState::Warning);
+        assert_eq!(state(0x60, 0, 127, 0x0C), ErrorState::Warning);
+        assert_eq!(state(0x60, 0, 128, 0x0C), ErrorState::Passive);
+        assert_eq!(state(0x60, 255, 255, 0x0C), ErrorState::Passive);
+        // Without a limit read back, the SJA1000's reset default applies.
+        assert_eq!(state(0, 95, 0, 0x0C), ErrorState::Active);
+        assert_eq!(state(0, 96, 0, 0x0C), ErrorState::Warning);
+        assert_eq!(state(10, 10, 0, 0x0C), ErrorState::Warning);
+        assert_eq!(state(200, 150, 0, 0x0C), ErrorState::Passive, "passive at 128 whatever the limit");
+        // The status register's flags win over the counters.
+        assert_eq!(state(0x60, 0, 0, 0x4C), ErrorState::Warning);
+        assert_eq!(state(0x60, 0, 0, 0x80), ErrorState::BusOff);
+    }
+
+    #[test]
+    fn decodes_captured_error_info() {
+        let none = err_info_from([0, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(none.flags.is_empty());
+        assert_eq!(none.flags.to_string(), "no error");
+
+        // Error-passive from receive errors, and arbitration lost at bit 3 of the ID.
+        let passive = err_info_from([0x0C, 0x00, 0x00, 0x00, 0x1A, 0x88, 0x05, 0x03]);
+        assert!(passive.flags.contains(ErrorFlags::ERROR_PASSIVE) && passive.flags.contains(ErrorFlags::ARBITRATION_LOST));
+        assert_eq!(passive.flags.names(), ["error-passive", "arbitration lost"]);
+        assert_eq!((passive.passive[0], passive.rx_error_counter(), passive.tx_error_counter(), passive.arbitration_lost), (0x1A, 0x88, 0x05, 0x03));
+
+        let overflow = err_info_from([0x41, 0x08, 0x00, 0x00, 0, 0, 0, 0]);
+        assert_eq!(overflow.flags.bits(), 0x0841);
+        assert_eq!(overflow.flags.to_string(), "controller FIFO overflow, buffer overflow, device buffer overflow");
+
+        let unknown = err_info_from([0xA0, 0x00, 0x01, 0x00, 0, 0, 0, 0]);
+        assert_eq!(unknown.flags.names(), ["bus-off"]);
+        assert_eq!(unknown.flags.to_string(), "bus-off, unknown 0x10080");
+    }
+}
@@ -0,0 +1,473 @@
+use std::str::FromStr;
+
+use crate::frame::Frame;
+use crate::id::Id;
+
+/// Matches one ID, or every ID whose bits under `mask` equal those of `id`. Standard and
+/// extended IDs never match each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdMatch {
+    pub id: Id,
+    pub mask: u32,
+}
+
+impl IdMatch {
+    pub fn exact(id: Id) -> Self {
+        let mask = if id.is_extended() { Id::MAX_EXTENDED } else { Id::MAX_STANDARD as u32 };
+        Self { id, mask }
+    }
+
+    pub fn matches(&self, id: Id) -> bool {
+        id.is_extended() == self.id.is_extended() && id.raw() & self.mask == self.id.raw() & self.mask
+    }
+}
+
+/// What one filter entry matches, before any inversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdPattern {
+    Match(IdMatch),
+    /// Inclusive; both ends are of the same kind.
+    Range(Id, Id),
+}
+
+impl IdPattern {
+    pub fn matches(&self, id: Id) -> bool {
+        match *self {
+            Self::Match(matcher) => matcher.matches(id),
+            Self::Range(first, last) => id.is_extended() == first.is_extended() && (first.raw()..=last.raw()).contains(&id.raw()),
+        }
+    }
+}
+
+/// One filter entry in the syntax of candump and friends, parsed with [`FromStr`]:
+///
+/// - `123` one ID, `100-1FF` an inclusive range
+/// - `123:7FF`, or `123/7FF` as in gateway rules, the IDs whose bits under the mask match
+/// - `~` in front inverts the entry; candump's `123~7FF` does too
+/// - an `x` at the end restricts it to extended frames, as 8 digits or a value above `7FF` do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterTerm {
+    pub pattern: IdPattern,
+    pub inverted: bool,
+}
+
+impl FilterTerm {
+    /// Whether `id` passes this entry on its own.
+    pub fn matches(&self, id: Id) -> bool {
+        self.pattern.matches(id) != self.inverted
+    }
+
+    pub fn inverted(self) -> Self {
+        Self { inverted: !self.inverted, ..self }
+    }
+}
+
+impl FromStr for FilterTerm {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let (s, mut inverted) = match text.trim().strip_prefix('~') {
+            Some(rest) => (rest.trim(), true),
+            None => (text.trim(), false),
+        };
+        if s.is_empty() {
+            return Err("empty filter".to_string());
+        }
+        let (s, extended) = strip_extended(s);
+        let pattern = if let Some((id, mask)) = s.split_once('~') {
+            inverted = !inverted;
+            IdPattern::Match(id_match(id, mask, extended)?)
+        } else if let Some((id, mask)) = s.split_once([':', '/']) {
+            IdPattern::Match(id_match(id, mask, extended)?)
+        } else if let Some((first, last)) = s.split_once('-') {
+            let (first, last) = (parse_filter_id(first, extended)?, parse_filter_id(last, extended)?);
+            if first.is_extended() != last.is_extended() {
+                return Err(format!("range '{s}' mixes standard and extended IDs; add an x to make both extended"));
+            }
+            if last.raw() < first.raw() {
+                return Err(format!("range '{s}' ends before it starts"));
+            }
+            IdPattern::Range(first, last)
+        } else {
+            let id = parse_filter_id(s, extended)?;
+            IdPattern::Range(id, id)
+        };
+        Ok(Self { pattern, inverted })
+    }
+}
+
+/// Comma-separated [`FilterTerm`]s, e.g. `100-1FF,~123,18FF0000:1FFF0000`. Errors name the
+/// entry at fault.
+pub fn parse_filters(s: &str) -> Result<Vec<FilterTerm>, String> {
+    s.split(',').map(|term| term.parse().map_err(|err| format!("filter '{}': {err}", term.trim()))).collect()
+}
+
+/// `ID`, `ID/MASK` or `ID:MASK`, as used by gateway rules; no ranges or inversion.
+pub fn parse_id_match(s: &str) -> Result<IdMatch, String> {
+    let (s, extended) = strip_extended(s.trim());
+    match s.split_once([':', '/']) {
+        Some((id, mask)) => id_match(id, mask, extended),
+        None => Ok(IdMatch::exact(parse_filter_id(s, extended)?)),
+    }
+}
+
+/// Hex ID; an `x` suffix, `extended`, 8 digits or a value above 0x7FF makes it extended.
+pub(crate) fn parse_filter_id(s: &str, extended: bool) -> Result<Id, String> {
+    let (digits, force_extended) = strip_extended(s.trim());
+    let digits = digits.trim_start_matches("0x").trim_start_matches("0X");
+    let raw = u32::from_str_radix(digits, 16).map_err(|_| format!("invalid CAN ID '{}'", s.trim()))?;
+    if extended || force_extended || digits.len() == 8 || raw > Id::MAX_STANDARD as u32 {
+        Id::extended(raw).ok_or_else(|| format!("CAN ID '{}' exceeds 29 bits", s.trim()))
+    } else {
+        Ok(Id::Standard(raw as u16))
+    }
+}
+
+fn id_match(id: &str, mask: &str, extended: bool) -> Result<IdMatch, String> {
+    let id = parse_filter_id(id, extended)?;
+    let digits = mask.trim().trim_start_matches("0x").trim_start_matches("0X");
+    let mask = u32::from_str_radix(digits, 16).map_err(|_| format!("invalid mask '{}'", mask.trim()))?;
+    let (width, bits) = if id.is_extended() { (Id::MAX_EXTENDED, 29) } else { (Id::MAX_STANDARD as u32, 11) };
+    if mask > width {
+        return Err(format!("mask 0x{mask:X} is wider than the {bits} bits of {id}"));
+    }
+    Ok(IdMatch { id, mask })
+}
+
+fn strip_extended(s: &str) -> (&str, bool) {
+    match s.strip_suffix(['x', 'X']) {
+        Some(rest) => (rest, true),
+        None => (s, false),
+    }
+}
+
+/// Sorted, non-overlapping inclusive ID ranges, kept separately for the standard and extended
+/// ID spaces so `0x123` and `0x00000123` never alias. Lookups are a binary search.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdSet {
+    standard: Vec<(u32, u32)>,
+    extended: Vec<(u32, u32)>,
+}
+
+impl IdSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.standard.is_empty() && self.extended.is_empty()
+    }
+
+    /// Adds the inclusive range `first..=last`. Mixed standard/extended ends are ignored.
+    pub fn insert(&mut self, first: Id, last: Id) {
+        if first.is_extended() != last.is_extended() {
+            return;
+        }
+        let (lo, hi) = (first.raw().min(last.raw()), first.raw().max(last.raw()));
+        let spans = self.spans_mut(first.is_extended());
+        spans.push((lo, hi));
+        normalize(spans);
+    }
+
+    pub fn insert_id(&mut self, id: Id) {
+        self.insert(id, id);
+    }
+
+    /// Removes the inclusive range `first..=last`, splitting existing ranges as needed.
+    pub fn remove(&mut self, first: Id, last: Id) {
+        if first.is_extended() != last.is_extended() {
+            return;
+        }
+        let (lo, hi) = (first.raw().min(last.raw()), first.raw().max(last.raw()));
+        let spans = self.spans_mut(first.is_extended());
+        let mut kept = Vec::with_capacity(spans.len() + 1);
+        for &(s, e) in spans.iter() {
+            if e < lo || s > hi {
+                kept.push((s, e));
+                continue;
+            }
+            if s < lo {
+                kept.push((s, lo - 1));
+            }
+            if e > hi {
+                kept.push((hi + 1, e));
+            }
+        }
+        *spans = kept;
+    }
+
+    pub fn clear(&mut self) {
+        self.standard.clear();
+        self.extended.clear();
+    }
+
+    pub fn contains(&self, id: Id) -> bool {
+        let spans = if id.is_extended() { &self.extended } else { &self.standard };
+        let raw = id.raw();
+        let i = spans.partition_point(|&(_, end)| end < raw);
+        spans.get(i).is_some_and(|&(start, _)| start <= raw)
+    }
+
+    fn spans_mut(&mut self, extended: bool) -> &mut Vec<(u32, u32)> {
+        if extended {
+            &mut self.extended
+        } else {
+            &mut self.standard
+        }
+    }
+}
+
+fn normalize(spans: &mut Vec<(u32, u32)>) {
+    spans.sort_unstable();
+    let mut merged: Vec<(u32, u32)> = Vec::with_capacity(spans.len());
+    for &(s, e) in spans.iter() {
+        match merged.last_mut() {
+            Some(last) if s <= last.1.saturating_add(1) => last.1 = last.1.max(e),
+            _ => merged.push((s, e)),
+        }
+    }
+    *spans = merged;
+}
+
+/// Receive-side software filter. An empty allow list passes everything; the block list always
+/// wins over the allow list, so `100-1FF,~150` passes 0x100 to 0x1FF except 0x150.
+#[derive(Debug, Clone, Default)]
+pub struct SoftwareFilter {
+    pub allow: IdSet,
+    pub block: IdSet,
+    /// Masked entries from [`SoftwareFilter::add`], which an [`IdSet`] can't hold.
+    allow_matches: Vec<IdMatch>,
+    block_matches: Vec<IdMatch>,
+    enabled: bool,
+}
+
+impl SoftwareFilter {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Allows what `term` matches, or blocks it if it is inverted.
+    pub fn add(&mut self, term: &FilterTerm) {
+        let (set, matches) = match term.inverted {
+            false => (&mut self.allow, &mut self.allow_matches),
+            true => (&mut self.block, &mut self.block_matches),
+        };
+        match term.pattern {
+            IdPattern::Range(first, last) => set.insert(first, last),
+            IdPattern::Match(matcher) => matches.push(matcher),
+        }
+    }
+
+    pub fn accepts_id(&self, id: Id) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        if self.block.contains(id) || self.block_matches.iter().any(|matcher| matcher.matches(id)) {
+            return false;
+        }
+        (self.allow.is_empty() && self.allow_matches.is_empty())
+            || self.allow.contains(id)
+            || self.allow_matches.iter().any(|matcher| matcher.matches(id))
+    }
+
+    pub fn accepts(&self, frame: &Frame) -> bool {
+        self.accepts_id(frame.id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::XorShift;
+
+    fn filter(terms: &str) -> SoftwareFilter {
+        let mut filter = SoftwareFilter::new();
+        parse_filters(terms).unwrap().iter().for_each(|term| filter.add(term));
+        filter
+    }
+
+    fn passed(filter: &SoftwareFilter, ids: impl IntoIterator<Item = Id>) -> Vec<u32> {
+        ids.into_iter().filter(|&id| filter.accepts_id(id)).map(|id| id.raw()).collect()
+    }
+
+    #[test]
+    fn block_wins_over_an_overlapping_allow() {
+        let standard = |range: std::ops::RangeInclusive<u16>| range.map(Id::Standard);
+        let filter = filter("100-1FF,~150,~1F0-2FF");
+        assert_eq!(passed(&filter, standard(0x14F..=0x151)), [0x14F, 0x151]);
+        assert_eq!(passed(&filter, standard(0x1EE..=0x200)), [0x1EE, 0x1EF]);
+        assert!(passed(&filter, standard(0x200..=0x7FF)).is_empty(), "blocked, and outside the allow list anyway");
+
+        // Whichever order the entries come in.
+        for order in ["~100-1FF,100-1FF", "100-1FF,~100-1FF", "~123,123,~123"] {
+            assert!(passed(&self::filter(order), standard(0..=0x7FF)).is_empty(), "{order}");
+        }
+        // Masked entries too: 0x120 to 0x12F blocked out of an allowed 0x100 to 0x1FF.
+        let filter = self::filter("100:700,~120:7F0");
+        assert_eq!(passed(&filter, standard(0x11E..=0x131)), [0x11E, 0x11F, 0x130, 0x131]);
+        let filter = self::filter("~123,120:7F0");
+        assert_eq!(passed(&filter, standard(0x120..=0x124)), [0x120, 0x121, 0x122, 0x124]);
+    }
+
+    #[test]
+    fn each_form_parses_to_its_pattern() {
+        let term = |text: &str| text.parse::<FilterTerm>().unwrap();
+        let (std, ext) = (Id::Standard, Id::Extended);
+        let masked = |id, mask| IdPattern::Match(IdMatch { id, mask });
+        for (text, pattern, inverted) in [
+            ("123", IdPattern::Range(std(0x123), std(0x123)), false),
+            (" 0x123 ", IdPattern::Range(std(0x123), std(0x123)), false),
+            ("100-1FF", IdPattern::Range(std(0x100), std(0x1FF)), false),
+            ("100-1FFx", IdPattern::Range(ext(0x100), ext(0x1FF)), false),
+            ("123:7FF", masked(std(0x123), 0x7FF), false),
+            ("123/700", masked(std(0x123), 0x700), false),
+            ("18FF0000:1FFF0000x", masked(ext(0x18FF_0000), 0x1FFF_0000), false),
+            ("123x", IdPattern::Range(ext(0x123), ext(0x123)), false),
+            ("00000123", IdPattern::Range(ext(0x123), ext(0x123)), false),
+            ("800", IdPattern::Range(ext(0x800), ext(0x800)), false),
+            ("~123", IdPattern::Range(std(0x123), std(0x123)), true),
+            ("~100-1FF", IdPattern::Range(std(0x100), std(0x1FF)), true),
+            ("123~7FF", masked(std(0x123), 0x7FF), true),
+            // Inverted twice, candump's way and ours, is not inverted.
+            ("~123~7FF", masked(std(0x123), 0x7FF), false),
+        ] {
+            assert_eq!(term(text), FilterTerm { pattern, inverted }, "{text}");
+        }
+        assert!(term("~150").matches(Id::Standard(0x151)) && !term("~150").matches(Id::Standard(0x150)));
+        assert_eq!(term("~150").inverted(), term("150"));
+    }
+
+    #[test]
+    fn errors_say_what_is_wrong() {
+        for (text, message) in [
+            ("", "empty filter"),
+            ("~", "empty filter"),
+            ("12G", "invalid CAN ID '12G'"),
+            ("20000000", "CAN ID '20000000' exceeds 29 bits"),
+            ("123:zz", "invalid mask 'zz'"),
+            ("123:FFF", "mask 0xFFF is wider than the 11 bits of 0x123"),
+            ("1FF-100", "range '1FF-100' ends before it starts"),
+            ("100-18FF0000", "range '100-18FF0000' mixes standard and extended IDs; add an x to make both extended"),
+        ] {
+            assert_eq!(text.parse::<FilterTerm>().unwrap_err(), message, "{text:?}");
+        }
+        assert_eq!(parse_filters("100-1FF, 12G ,~150").unwrap_err(), "filter '12G': invalid CAN ID '12G'");
+        assert_eq!(parse_filters("100,,200").unwrap_err(), "filter '': empty filter");
+
+        // Gateway rules take the same IDs and masks, without ranges or inversion.
+        assert_eq!(parse_id_match("18FF0000/1FFF0000x"), Ok(IdMatch { id: Id::Extended(0x18FF_0000), mask: 0x1FFF_0000 }));
+        assert_eq!(parse_id_match("123"), Ok(IdMatch::exact(Id::Standard(0x123))));
+        assert_eq!(parse_id_match("100-1FF").unwrap_err(), "invalid CAN ID '100-1FF'");
+        assert_eq!(parse_id_match("~123").unwrap_err(), "invalid CAN ID '~123'");
+    }
+
+    #[test]
+    fn inverted_masks_take_precedence_like_inverted_ranges() {
+        let standard = |range: std::ops::RangeInclusive<u16>| range.map(Id::Standard);
+        // candump's inverted mask alone passes everything else, of either kind.
+        let filter = filter("123~7FF");
+        assert_eq!(passed(&filter, standard(0x122..=0x124)), [0x122, 0x124]);
+        assert!(filter.accepts_id(Id::Extended(0x123)));
+        assert_eq!(passed(&self::filter("100-1FF,123~7FF"), standard(0x122..=0x124)), [0x122, 0x124]);
+        // Inverted twice it is an allow entry, so only 0x123 passes.
+        assert_eq!(passed(&self::filter("~123~7FF"), standard(0..=0x7FF)), [0x123]);
+
+        for order in ["~100:700,100-1FF", "100-1FF,~100:700", "100-1FF,100~700"] {
+            assert!(passed(&self::filter(order), standard(0..=0x7FF)).is_empty(), "{order}");
+        }
+
+        // PGN 0xFF00 to 0xFFFF blocked out of everything at priority 6.
+        let filter = self::filter("18000000:1C000000x,~18FF0000:1FFF0000x");
+        let ids = [0x18FE_50E5, 0x18FF_50E5, 0x18FF_FF00, 0x1CFE_50E5, 0x0CFE_50E5];
+        assert_eq!(passed(&filter, ids.map(Id::Extended)), [0x18FE_50E5]);
+    }
+
+    #[test]
+    fn a_block_list_alone_passes_everything_else() {
+        let filter = filter("~7E8,~7E0-7E7x");
+        assert!(!filter.accepts_id(Id::Standard(0x7E8)));
+        assert!(filter.accepts_id(Id::Standard(0x7E0)));
+        assert!(!filter.accepts_id(Id::Extended(0x7E0)));
+        assert!(filter.accepts_id(Id::Extended(0x7E8)));
+
+        let mut disabled = filter.clone();
+        disabled.set_enabled(false);
+        assert!(disabled.accepts_id(Id::Standard(0x7E8)) && !disabled.is_enabled());
+    }
+
+    #[test]
+    fn standard_and_extended_ids_are_separate_spaces() {
+        let filter = filter("100-1FF,~18FF50E5");
+        assert!(filter.accepts_id(Id::Standard(0x123)));
+        assert!(!filter.accepts_id(Id::Extended(0x123)), "allowing standard IDs doesn't allow the same extended ones");
+        assert!(!filter.accepts_id(Id::Extended(0x18FF_50E5)));
+
+        let filter = self::filter("100-1FFx,~150");
+        assert!(filter.accepts_id(Id::Extended(0x150)), "blocking a standard ID doesn't block the extended one");
+        assert!(!filter.accepts_id(Id::Standard(0x150)) && !filter.accepts_id(Id::Standard(0x151)));
+
+        // Eight digits make an ID extended without the x.
+        let filter = self::filter("00000123");
+        assert_eq!((filter.accepts_id(Id::Extended(0x123)), filter.accepts_id(Id::Standard(0x123))), (true, false));
+
+        let filter = self::filter("123:7FF");
+        assert!(filter.accepts_id(Id::Standard(0x123)) && !filter.accepts_id(Id::Extended(0x123)));
+    }
+
+    #[test]
+    fn id_sets_merge_split_and_keep_the_spaces_apart() {
+        let mut set = IdSet::new();
+        set.insert(Id::Standard(0x100), Id::Standard(0x1FF));
+        set.insert(Id::Standard(0x200), Id::Standard(0x20F));
+        set.insert(Id::Standard(0x180), Id::Standard(0x120));
+        set.insert(Id::Standard(0x300), Id::Extended(0x3FF));
+        assert_eq!(set.standard, [(0x100, 0x20F)]);
+        assert!(set.extended.is_empty(), "mixed ends are ignored");
+
+        set.remove(Id::Standard(0x150), Id::Standard(0x15F));
+        set.remove(Id::Extended(0x100), Id::Extended(0x20F));
+        assert_eq!(set.standard, [(0x100, 0x14F), (0x160, 0x20F)]);
+
+        set.insert_id(Id::Extended(0x150));
+        assert!(set.contains(Id::Extended(0x150)) && !set.contains(Id::Standard(0x150)));
+        assert!(set.contains(Id::Standard(0x100)) && set.contains(Id::Standard(0x20F)) && !set.contains(Id::Standard(0x210)));
+        set.clear();
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn random_rules_match_a_linear_scan() {
+        let mut rng = XorShift(0x2545_F491_4F6C_DD1D);
+        for _ in 0..200 {
+            let terms: Vec<FilterTerm> = (0..=rng.below(6))
+                .map(|_| {
+                    let first = rng.below(0x800) as u16;
+                    let last = (first + rng.below(0x80) as u16).min(0x7FF);
+                    let (first, last) = if rng.next().is_multiple_of(2) { (Id::Standard(first), Id::Standard(last)) } else { (Id::Extended(first as u32), Id::Extended(last as u32)) };
+                    FilterTerm { pattern: IdPattern::Range(first, last), inverted: rng.next().is_multiple_of(2) }
+                })
+                .collect();
+            let mut filter = SoftwareFilter::new();
+            terms.iter().for_each(|term| filter.add(term));
+            let allows = terms.iter().filter(|term| !term.inverted).count();
+            for raw in 0..0x800u32 {
+                for id in [Id::Standard(raw as u16), Id::Extended(raw)] {
+                    let blocked = terms.iter().any(|term| term.inverted && term.pattern.matches(id));
+                    let allowed = allows == 0 || terms.iter().any(|term| !term.inverted && term.pattern.matches(id));
+                    assert_eq!(filter.accepts_id(id), !blocked && allowed, "{id} with {terms:?}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::str::FromStr;
+use std::time::{Instant, SystemTime};
+
+use crate::id::Id;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub(crate) id: Id,
+    pub(crate) data: [u8; 8],
+    pub(crate) len: u8,
+    pub(crate) remote: bool,
+    pub(crate) time_stamp: u32,
+    /// `time_stamp` with counter wraps unrolled by the receiving channel's clock.
+    pub(crate) ticks: u64,
+    /// Host time of a received frame, filled in by [`Channel`](crate::Channel).
+    pub(crate) host_time: Option<(SystemTime, Instant)>,
+    /// Set when the DLL delivered the frame with fields out of range, see [`Frame::is_suspect`].
+    pub(crate) suspect: bool,
+}
+
+impl Frame {
+    /// Builds a data frame, or `None` if `data` is longer than 8 bytes or `id` is out of range
+    /// for its kind.
+    pub fn new(id: Id, data: &[u8]) -> Option<Self> {
+        if data.len() > 8 || !id.is_valid() {
+            return None;
+        }
+        let mut buf = [0u8; 8];
+        buf[..data.len()].copy_from_slice(data);
+        Some(Self {
+            id,
+            data: buf,
+            len: data.len() as u8,
+            remote: false,
+            time_stamp: 0,
+            ticks: 0,
+            host_time: None,
+            suspect: false,
+        })
+    }
+
+    /// Builds a remote transmission request for `dlc` bytes, or `None` if `dlc > 8` or `id` is
+    /// out of range.
+    pub fn remote(id: Id, dlc: u8) -> Option<Self> {
+        if dlc > 8 || !id.is_valid() {
+            return None;
+        }
+        Some(Self {
+            id,
+            data: [0; 8],
+            len: dlc,
+            remote: true,
+            time_stamp: 0,
+            ticks: 0,
+            host_time: None,
+            suspect: false,
+        })
+    }
+
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    pub fn is_extended(&self) -> bool {
+        self.id.is_extended()
+    }
+
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    /// Data length code; for remote frames this is the requested length, not a payload size.
+    pub fn dlc(&self) -> u8 {
+        self.len
+    }
+
+    /// Payload bytes. Always empty for remote frames.
+    pub fn data(&self) -> &[u8] {
+        if self.remote {
+            &[]
+        } else {
+            &self.data[..self.len as usize]
+        }
+    }
+
+    /// Device timestamp in 0.1 ms units; zero for frames built locally.
+    pub fn time_stamp(&self) -> u32 {
+        self.time_stamp
+    }
+
+    /// Device timestamp in 0.1 ms units extended to 64 bits across counter wraps, for gaps
+    /// between frames on one channel. Equal to [`Frame::time_stamp`] for frames not received
+    /// through a [`Channel`](crate::Channel).
+    pub fn device_ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Wall-clock time a received frame was on the bus, converted from the device timestamp with
+    /// the channel's [`DeviceClock`](crate::DeviceClock), or the host receive time when the
+    /// adapter flagged the timestamp invalid. `None` for frames built locally.
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        self.host_time.map(|(wall, _)| wall)
+    }
+
+    /// Whether the adapter delivered this frame with a data length above 8, flag bytes other than
+    /// 0 and 1, or an ID too wide for its kind. Those were clamped to something usable, but the
+    /// rest of the frame may be garbage too.
+    pub fn is_suspect(&self) -> bool {
+        self.suspect
+    }
+
+    /// [`Frame::timestamp`] on the host's monotonic clock.
+    pub fn instant(&self) -> Option<Instant> {
+        self.host_time.map(|(_, instant)| instant)
+    }
+}
+
+/// Transmit mode written to `VciCanObj.send_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SendType {
+    /// Retransmit until acknowledged.
+    #[default]
+    Normal,
+    /// Transmit once; a missing ACK is not retried, so an absent node can't block the queue.
+    SingleShot,
+    /// Transmit and receive our own frame back through the controller (local echo). The echo
+    /// shows up as an ordinary received frame on the same channel.
+    SelfTest,
+    /// Single-shot with local echo.
+    SingleShotSelfTest,
+}
+
+impl SendType {
+    pub(crate) fn raw(self) -> u8 {
+        match self {
+            SendType::Normal => 0,
+            SendType::SingleShot => 1,
+            SendType::SelfTest => 2,
+            SendType::SingleShotSelfTest => 3,
+        }
+    }
+}
+
+impl FromStr for SendType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "normal" => Ok(SendType::Normal),
+            "single-shot" => Ok(SendType::SingleShot),
+            "self-test" => Ok(SendType::SelfTest),
+            "single-shot-self-test" => Ok(SendType::SingleShotSelfTest),
+            _ => Err(format!(
+                "unknown send type '{s}', expected normal, single-shot, self-test or single-shot-self-test"
+            )),
+        }
+    }
+}
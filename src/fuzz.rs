@@ -0,0 +1,107 @@
+use crate::frame::Frame;
+use crate::id::Id;
+
+/// What a [`Fuzzer`] generates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzConfig {
+    /// IDs are drawn from `first..=last`; both standard or both extended.
+    pub first: Id,
+    pub last: Id,
+    pub min_dlc: u8,
+    pub max_dlc: u8,
+    /// An ID that gets this share (0.0 to 1.0) of the frames, e.g. 0.8; the rest are drawn
+    /// from the range.
+    pub target: Option<(Id, f64)>,
+    /// Instead of random frames, this one with a single bit of its payload flipped per frame:
+    /// bit 0 of byte 0 first, then each following bit, starting over after the last.
+    pub bit_flip: Option<Frame>,
+    /// The same seed and settings give the same frames in the same order.
+    pub seed: u64,
+}
+
+impl FuzzConfig {
+    /// Random frames of 0 to 8 bytes on `first..=last`.
+    pub fn new(first: Id, last: Id, seed: u64) -> Self {
+        Self { first, last, min_dlc: 0, max_dlc: 8, target: None, bit_flip: None, seed }
+    }
+}
+
+/// An endless, reproducible stream of fuzzing frames, usually fed to
+/// [`Scheduler::add_generated`](crate::Scheduler::add_generated).
+#[derive(Debug, Clone)]
+pub struct Fuzzer {
+    config: FuzzConfig,
+    state: u64,
+    generated: u64,
+}
+
+impl Fuzzer {
+    pub fn new(config: FuzzConfig) -> Self {
+        Self { state: config.seed, config, generated: 0 }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.config.seed
+    }
+
+    /// Frames handed out so far.
+    pub fn generated(&self) -> u64 {
+        self.generated
+    }
+
+    /// SplitMix64: tiny, fast, and plenty random for picking IDs and payloads.
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `low..=high`.
+    fn between(&mut self, low: u64, high: u64) -> u64 {
+        let (low, high) = (low.min(high), low.max(high));
+        low + self.next_u64() % (high - low + 1)
+    }
+
+    fn random_id(&mut self) -> Id {
+        if let Some((target, weight)) = self.config.target {
+            // 53 random bits give a uniform float in [0, 1).
+            if ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < weight {
+                return target;
+            }
+        }
+        let raw = self.between(self.config.first.raw().into(), self.config.last.raw().into()) as u32;
+        // Between two valid IDs of the same kind, so valid too.
+        if self.config.first.is_extended() { Id::Extended(raw) } else { Id::Standard(raw as u16) }
+    }
+
+    fn random_frame(&mut self) -> Frame {
+        let id = self.random_id();
+        let dlc = self.between(self.config.min_dlc.min(8).into(), self.config.max_dlc.min(8).into()) as usize;
+        let payload = self.next_u64().to_le_bytes();
+        Frame::new(id, &payload[..dlc]).expect("at most 8 bytes")
+    }
+
+    fn flipped(&self, base: &Frame) -> Frame {
+        let mut data = base.data().to_vec();
+        if !data.is_empty() {
+            let bit = (self.generated % (data.len() as u64 * 8)) as usize;
+            data[bit / 8] ^= 1 << (bit % 8);
+        }
+        Frame::new(base.id(), &data).expect("same length as the base frame")
+    }
+}
+
+impl Iterator for Fuzzer {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let frame = match self.config.bit_flip {
+            Some(base) => self.flipped(&base),
+            None => self.random_frame(),
+        };
+        self.generated += 1;
+        Some(frame)
+    }
+}
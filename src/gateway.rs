@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::device::Channel;
+use crate::frame::Frame;
+use crate::rules::GatewayRules;
+
+/// How long a forwarded frame is remembered for echo suppression.
+const ECHO_WINDOW: Duration = Duration::from_millis(100);
+const MAX_REMEMBERED: usize = 256;
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Counters for one forwarding direction.
+#[derive(Debug, Default)]
+pub struct GatewayStats {
+    forwarded: AtomicU64,
+    dropped: AtomicU64,
+    filtered: AtomicU64,
+    echoes: AtomicU64,
+}
+
+impl GatewayStats {
+    /// Frames transmitted on the destination channel.
+    pub fn forwarded(&self) -> u64 {
+        self.forwarded.load(Ordering::Relaxed)
+    }
+
+    /// Frames that could not be transmitted on the destination channel.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Frames dropped by a gateway rule.
+    pub fn filtered(&self) -> u64 {
+        self.filtered.load(Ordering::Relaxed)
+    }
+
+    /// Frames recognized as our own forwarded traffic coming back, and not sent again.
+    pub fn echoes(&self) -> u64 {
+        self.echoes.load(Ordering::Relaxed)
+    }
+}
+
+/// Frames recently transmitted on one channel, so the opposite direction can tell an echo of
+/// them from genuine traffic.
+#[derive(Default)]
+struct Recent {
+    frames: Mutex<VecDeque<(Instant, Frame)>>,
+}
+
+impl Recent {
+    fn remember(&self, frame: &Frame) {
+        let mut frames = self.frames.lock().unwrap();
+        if frames.len() == MAX_REMEMBERED {
+            frames.pop_front();
+        }
+        frames.push_back((Instant::now(), *frame));
+    }
+
+    /// Removes and reports a remembered frame with the same content as `frame`.
+    fn take_echo(&self, frame: &Frame) -> bool {
+        let mut frames = self.frames.lock().unwrap();
+        let now = Instant::now();
+        while frames.front().is_some_and(|(at, _)| now.duration_since(*at) > ECHO_WINDOW) {
+            frames.pop_front();
+        }
+        match frames.iter().position(|(_, sent)| same_content(sent, frame)) {
+            Some(index) => {
+                frames.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn same_content(a: &Frame, b: &Frame) -> bool {
+    a.id() == b.id() && a.is_remote() == b.is_remote() && a.dlc() == b.dlc() && a.data() == b.data()
+}
+
+/// Bridges two channels: every frame received on one is passed through the rules and
+/// transmitted on the other, with one thread per direction so a stalled side doesn't hold up
+/// the other.
+pub struct Gateway {
+    running: Arc<AtomicBool>,
+    stats: [Arc<GatewayStats>; 2],
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Gateway {
+    /// Starts forwarding between `a` and `b`, reading at most `batch` frames per receive call.
+    /// `GatewayRules::default()` forwards everything unchanged.
+    pub fn start(a: &Channel, b: &Channel, batch: usize, rules: GatewayRules) -> Self {
+        let rules = Arc::new(rules);
+        let running = Arc::new(AtomicBool::new(true));
+        let stats: [Arc<GatewayStats>; 2] = Default::default();
+        let recent: [Arc<Recent>; 2] = Default::default();
+
+        let threads = [(a, b, 0), (b, a, 1)]
+            .into_iter()
+            .map(|(from, to, dir)| {
+                let (from, to) = (from.clone(), to.clone());
+                let running = Arc::clone(&running);
+                let rules = Arc::clone(&rules);
+                let stats = Arc::clone(&stats[dir]);
+                let sent_on_from = Arc::clone(&recent[1 - dir]);
+                let sent_on_to = Arc::clone(&recent[dir]);
+                let path = Path { from, to, direction: dir, rules, stats, sent_on_from, sent_on_to };
+                thread::spawn(move || forward(&path, batch, &running))
+            })
+            .collect();
+
+        Self {
+            running,
+            stats,
+            threads,
+        }
+    }
+
+    /// Counters for `a`→`b` (`0`) or `b`→`a` (`1`).
+    pub fn stats(&self, direction: usize) -> &GatewayStats {
+        &self.stats[direction]
+    }
+
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Gateway {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// One forwarding direction.
+struct Path {
+    from: Channel,
+    to: Channel,
+    direction: usize,
+    rules: Arc<GatewayRules>,
+    stats: Arc<GatewayStats>,
+    sent_on_from: Arc<Recent>,
+    sent_on_to: Arc<Recent>,
+}
+
+fn forward(path: &Path, batch: usize, running: &AtomicBool) {
+    let stats = &path.stats;
+    while running.load(Ordering::SeqCst) {
+        let frames = match path.from.receive_pending(batch, RECEIVE_TIMEOUT) {
+            Ok(frames) => frames,
+            Err(_) => {
+                thread::sleep(RECEIVE_TIMEOUT);
+                continue;
+            }
+        };
+        if frames.is_empty() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        for frame in frames {
+            if path.sent_on_from.take_echo(&frame) {
+                stats.echoes.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            let Some(frame) = path.rules.apply(path.direction, &frame) else {
+                stats.filtered.fetch_add(1, Ordering::Relaxed);
+                continue;
+            };
+            path.sent_on_to.remember(&frame);
+            match path.to.transmit(&frame) {
+                Ok(()) => stats.forwarded.fetch_add(1, Ordering::Relaxed),
+                Err(_) => stats.dropped.fetch_add(1, Ordering::Relaxed),
+            };
+        }
+    }
+}
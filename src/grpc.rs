@@ -0,0 +1,251 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, UNIX_EPOCH};
+
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+use crate::bitrate::Bitrate;
+use crate::device::Channel;
+use crate::error::CanError;
+use crate::ffi::VciInitConfig;
+use crate::filter::{FilterTerm, SoftwareFilter};
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::mode::ChannelMode;
+use crate::timestamp::host_time;
+
+use proto::can_bus_server::{CanBus, CanBusServer};
+
+/// Messages and the client and server stubs generated from `proto/rustcanbus.proto`.
+pub mod proto {
+    tonic::include_proto!("rustcanbus");
+}
+
+/// How often streaming threads look at the stop flag.
+const POLL: Duration = Duration::from_millis(100);
+/// Frames queued per stream and channel on the fan-out layer.
+const STREAM_QUEUE: usize = 4096;
+/// Frames in flight between a stream's threads and the gRPC connection.
+const STREAM_BUFFER: usize = 256;
+
+/// Serves the channels over gRPC (see `proto/rustcanbus.proto`) from its own runtime, so it
+/// runs next to anything else using them. Every `StreamFrames` call gets its own queue per
+/// channel and applies its filter before sending; a slow client loses frames from its queue
+/// instead of holding up reception.
+pub struct GrpcServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<Result<(), tonic::transport::Error>>>,
+}
+
+impl GrpcServer {
+    pub fn start(addr: SocketAddr, channels: &[Channel]) -> std::io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+        // Bound here so a taken port fails the start rather than the server thread.
+        let listener = runtime.block_on(tokio::net::TcpListener::bind(addr))?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let service = Service { channels: channels.to_vec(), stop: Arc::clone(&stop) };
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let thread = thread::spawn(move || {
+            runtime.block_on(
+                tonic::transport::Server::builder().add_service(CanBusServer::new(service)).serve_with_incoming_shutdown(
+                    tokio_stream::wrappers::TcpListenerStream::new(listener),
+                    async {
+                        let _ = shutdown_rx.await;
+                    },
+                ),
+            )
+        });
+        Ok(Self { addr, stop, shutdown: Some(shutdown), thread: Some(thread) })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Ends every stream and stops serving. Also done on drop.
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for GrpcServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+struct Service {
+    channels: Vec<Channel>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Service {
+    fn channel(&self, index: u32) -> Result<&Channel, String> {
+        self.channels.get(index as usize).ok_or_else(|| format!("no channel {index}"))
+    }
+
+    fn status(&self, index: u32) -> proto::ChannelStatus {
+        let channel = &self.channels[index as usize];
+        let config = channel.config();
+        let mode = match config.and_then(|config| ChannelMode::from_raw(config.mode)) {
+            None => proto::Mode::Unspecified,
+            Some(ChannelMode::Normal) => proto::Mode::Normal,
+            Some(ChannelMode::ListenOnly) => proto::Mode::ListenOnly,
+            Some(ChannelMode::SelfTest) => proto::Mode::SelfTest,
+        };
+        let mut status = proto::ChannelStatus {
+            channel: index,
+            bitrate: config.map(|config| config.bitrate().to_string()).unwrap_or_default(),
+            bus_load: channel.bus_load(),
+            ..Default::default()
+        };
+        status.set_mode(mode);
+        if let Ok(registers) = channel.status() {
+            status.error_state = registers.error_state().to_string();
+            status.rx_errors = registers.rx_error_counter.into();
+            status.tx_errors = registers.tx_error_counter.into();
+        }
+        status
+    }
+}
+
+/// The filter a `StreamFrames` call asked for.
+fn id_filter(request: &proto::FilterRequest) -> Result<SoftwareFilter, String> {
+    let mut filter = SoftwareFilter::new();
+    for range in &request.ranges {
+        let first = to_id(range.first, range.extended)?;
+        let last = if range.last < range.first { first } else { to_id(range.last, range.extended)? };
+        filter.allow.insert(first, last);
+    }
+    for text in &request.filters {
+        let term: FilterTerm = text.parse().map_err(|err| format!("filter '{text}': {err}"))?;
+        filter.add(&term);
+    }
+    Ok(filter)
+}
+
+fn to_message(channel: u32, frame: &Frame) -> proto::Frame {
+    proto::Frame {
+        channel,
+        id: frame.id().raw(),
+        extended: frame.is_extended(),
+        remote: frame.is_remote(),
+        dlc: frame.dlc().into(),
+        data: frame.data().to_vec(),
+        timestamp_us: host_time(frame).duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64,
+    }
+}
+
+fn to_id(raw: u32, extended: bool) -> Result<Id, String> {
+    if extended { Id::extended(raw) } else { u16::try_from(raw).ok().and_then(Id::standard) }
+        .ok_or_else(|| format!("invalid CAN ID {raw:#X}"))
+}
+
+fn to_frame(message: &proto::Frame) -> Result<Frame, String> {
+    let id = to_id(message.id, message.extended)?;
+    let frame = if message.remote {
+        u8::try_from(message.dlc).ok().and_then(|dlc| Frame::remote(id, dlc))
+    } else {
+        Frame::new(id, &message.data)
+    };
+    frame.ok_or_else(|| "at most 8 data bytes fit in a frame".to_string())
+}
+
+fn transmit_status(err: CanError) -> Status {
+    match err {
+        CanError::NotInitialized { .. } | CanError::ListenOnly { .. } => Status::failed_precondition(err.to_string()),
+        CanError::Disconnected { .. } => Status::unavailable(err.to_string()),
+        _ => Status::internal(err.to_string()),
+    }
+}
+
+type FrameStream = Pin<Box<dyn Stream<Item = Result<proto::Frame, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl CanBus for Service {
+    type StreamFramesStream = FrameStream;
+
+    async fn stream_frames(&self, request: Request<proto::FilterRequest>) -> Result<Response<FrameStream>, Status> {
+        let request = request.into_inner();
+        let filter = id_filter(&request).map_err(Status::invalid_argument)?;
+        let indices: Vec<u32> = match request.channel {
+            Some(index) => {
+                self.channel(index).map_err(Status::invalid_argument)?;
+                vec![index]
+            }
+            None => (0..self.channels.len() as u32).collect(),
+        };
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        // One plain thread per channel: subscriptions block, the runtime's workers must not.
+        for index in indices {
+            let subscription = self.channels[index as usize].subscribe(STREAM_QUEUE);
+            let (tx, filter, stop) = (tx.clone(), filter.clone(), Arc::clone(&self.stop));
+            thread::spawn(move || {
+                while !stop.load(Ordering::SeqCst) && !tx.is_closed() {
+                    let frame = match subscription.recv_timeout(POLL) {
+                        Ok(frame) => frame,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    };
+                    if !filter.accepts(&frame) {
+                        continue;
+                    }
+                    if tx.blocking_send(Ok(to_message(index, &frame))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn transmit(&self, request: Request<proto::Frame>) -> Result<Response<proto::TransmitResult>, Status> {
+        let message = request.into_inner();
+        let frame = to_frame(&message).map_err(Status::invalid_argument)?;
+        let channel = self.channel(message.channel).map_err(Status::invalid_argument)?;
+        channel.transmit(&frame).map_err(transmit_status)?;
+        Ok(Response::new(proto::TransmitResult {}))
+    }
+
+    async fn get_status(&self, _request: Request<proto::StatusRequest>) -> Result<Response<proto::StatusReply>, Status> {
+        let channels = (0..self.channels.len() as u32).map(|index| self.status(index)).collect();
+        Ok(Response::new(proto::StatusReply { channels }))
+    }
+
+    async fn configure(&self, request: Request<proto::ChannelConfig>) -> Result<Response<proto::ChannelStatus>, Status> {
+        let request = request.into_inner();
+        let channel = self.channel(request.channel).map_err(Status::invalid_argument)?;
+        let mut config = channel.config().unwrap_or_else(|| VciInitConfig::with_bitrate(Bitrate::Kbps500));
+        if !request.bitrate.is_empty() {
+            let bitrate: Bitrate = request.bitrate.parse().map_err(Status::invalid_argument)?;
+            (config.timing0, config.timing1) = bitrate.timing();
+        }
+        let mode = match request.mode() {
+            proto::Mode::Unspecified => None,
+            proto::Mode::Normal => Some(ChannelMode::Normal),
+            proto::Mode::ListenOnly => Some(ChannelMode::ListenOnly),
+            proto::Mode::SelfTest => Some(ChannelMode::SelfTest),
+        };
+        if let Some(mode) = mode {
+            config = config.with_mode(mode);
+        }
+        channel.reconfigure(&config).map_err(|err| Status::internal(err.to_string()))?;
+        Ok(Response::new(self.status(request.channel)))
+    }
+}
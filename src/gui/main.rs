@@ -0,0 +1,271 @@
+mod session;
+
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use eframe::egui::{self, Color32, RichText};
+use rustcanbus::{parse_frame_spec, Bitrate, CyclicId, Frame, CHANNEL_COUNT};
+use session::{ChannelStatus, ConnectConfig, Session};
+
+/// How long a changed byte stays highlighted, as in the monitor view.
+const HOLD: Duration = Duration::from_secs(1);
+const REFRESH: Duration = Duration::from_millis(100);
+
+fn main() -> eframe::Result {
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([1100.0, 700.0]),
+        ..Default::default()
+    };
+    eframe::run_native("rustcanbus", options, Box::new(|_| Ok(Box::new(App::new()))))
+}
+
+/// A cyclic message added from the transmit panel.
+struct CyclicRow {
+    id: CyclicId,
+    channel: u32,
+    frame: Frame,
+    period: Duration,
+}
+
+struct App {
+    session: Session,
+    dll: String,
+    simulated: bool,
+    dev_type: u32,
+    dev_index: u32,
+    bitrate: Bitrate,
+    log_path: String,
+    tx_channel: u32,
+    tx_frame: String,
+    tx_period_ms: u32,
+    cyclic: Vec<CyclicRow>,
+    /// Result of the last transmit panel action.
+    tx_message: Option<String>,
+}
+
+impl App {
+    fn new() -> Self {
+        Self {
+            session: Session::spawn(),
+            dll: String::new(),
+            simulated: false,
+            dev_type: 4,
+            dev_index: 0,
+            bitrate: Bitrate::Kbps500,
+            log_path: "rustcanbus.log".to_string(),
+            tx_channel: 0,
+            tx_frame: "123#DEADBEEF".to_string(),
+            tx_period_ms: 100,
+            cyclic: Vec::new(),
+            tx_message: None,
+        }
+    }
+
+    fn connection_bar(&mut self, ui: &mut egui::Ui) {
+        let connected = self.session.shared.connected();
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(!connected, |ui| {
+                ui.label("DLL");
+                ui.add(egui::TextEdit::singleline(&mut self.dll).hint_text("default search path").desired_width(180.0));
+                ui.checkbox(&mut self.simulated, "Simulated adapter");
+                ui.label("Type");
+                ui.add(egui::DragValue::new(&mut self.dev_type));
+                ui.label("Index");
+                ui.add(egui::DragValue::new(&mut self.dev_index));
+                egui::ComboBox::from_id_salt("bitrate").selected_text(format!("{}bps", self.bitrate)).show_ui(ui, |ui| {
+                    for bitrate in Bitrate::STANDARD {
+                        ui.selectable_value(&mut self.bitrate, bitrate, format!("{bitrate}bps"));
+                    }
+                });
+            });
+            if connected {
+                if ui.button("Disconnect").clicked() {
+                    self.session.disconnect();
+                }
+            } else if ui.button("Connect").clicked() {
+                self.session.connect(ConnectConfig {
+                    dll: Some(PathBuf::from(self.dll.trim())).filter(|_| !self.dll.trim().is_empty()),
+                    simulated: self.simulated,
+                    dev_type: self.dev_type,
+                    dev_index: self.dev_index,
+                    bitrate: self.bitrate,
+                });
+            }
+            ui.separator();
+            let logging = self.session.shared.log.lock().unwrap().is_some();
+            ui.add_enabled(!logging, egui::TextEdit::singleline(&mut self.log_path).desired_width(160.0));
+            if logging {
+                if ui.button("Stop log").clicked() {
+                    self.session.stop_log();
+                }
+            } else if ui.button("Start log").clicked() {
+                if let Err(err) = self.session.start_log(PathBuf::from(self.log_path.trim())) {
+                    *self.session.shared.message.lock().unwrap() = err;
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            let statuses = self.session.shared.status.lock().unwrap().clone();
+            for (slot, status) in statuses.iter().enumerate() {
+                channel_indicator(ui, slot, status, connected);
+                ui.separator();
+            }
+            let shared = &self.session.shared;
+            ui.label(format!("rx {} tx {}", shared.received.load(Ordering::Relaxed), shared.sent.load(Ordering::Relaxed)));
+            ui.separator();
+            ui.label(shared.message.lock().unwrap().as_str());
+        });
+    }
+
+    fn transmit_panel(&mut self, ui: &mut egui::Ui) {
+        let connected = self.session.shared.connected();
+        if !connected {
+            // Disconnecting stopped every cyclic message.
+            self.cyclic.clear();
+        }
+        ui.heading("Transmit");
+        ui.horizontal(|ui| {
+            for channel in 0..CHANNEL_COUNT {
+                ui.selectable_value(&mut self.tx_channel, channel, format!("CAN{}", channel + 1));
+            }
+        });
+        ui.add(egui::TextEdit::singleline(&mut self.tx_frame).hint_text("ID#DATA, e.g. 123#DEADBEEF").font(egui::TextStyle::Monospace));
+        ui.add_enabled_ui(connected, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Send").clicked() {
+                    self.tx_message = Some(match parse_frame_spec(&self.tx_frame) {
+                        Ok(frame) => match self.session.transmit(self.tx_channel, &frame) {
+                            Ok(()) => format!("Sent {} on CAN{}", self.tx_frame.trim(), self.tx_channel + 1),
+                            Err(err) => err,
+                        },
+                        Err(err) => err,
+                    });
+                }
+                ui.separator();
+                ui.add(egui::DragValue::new(&mut self.tx_period_ms).range(1..=60_000).suffix(" ms"));
+                if ui.button("Add cyclic").clicked() {
+                    self.add_cyclic();
+                }
+            });
+        });
+        if let Some(message) = &self.tx_message {
+            ui.label(message);
+        }
+
+        ui.separator();
+        ui.heading("Cyclic messages");
+        let mut removed = None;
+        egui::Grid::new("cyclic").striped(true).show(ui, |ui| {
+            ui.strong("Ch");
+            ui.strong("Frame");
+            ui.strong("Period");
+            ui.end_row();
+            for (row, entry) in self.cyclic.iter().enumerate() {
+                ui.label(format!("CAN{}", entry.channel + 1));
+                ui.monospace(frame_text(&entry.frame));
+                ui.label(format!("{} ms", entry.period.as_millis()));
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(row);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(row) = removed {
+            let entry = self.cyclic.remove(row);
+            self.session.shared.scheduler.remove(entry.id);
+        }
+    }
+
+    fn add_cyclic(&mut self) {
+        let frame = match parse_frame_spec(&self.tx_frame) {
+            Ok(frame) => frame,
+            Err(err) => {
+                self.tx_message = Some(err);
+                return;
+            }
+        };
+        let channels = self.session.shared.channels.lock().unwrap();
+        let Some(channel) = channels.get(self.tx_channel as usize) else {
+            self.tx_message = Some("not connected".to_string());
+            return;
+        };
+        let period = Duration::from_millis(u64::from(self.tx_period_ms));
+        let id = self.session.shared.scheduler.add(channel, frame, period);
+        self.cyclic.push(CyclicRow { id, channel: self.tx_channel, frame, period });
+        self.tx_message = Some(format!("Sending {} every {} ms on CAN{}", frame_text(&frame), period.as_millis(), self.tx_channel + 1));
+    }
+
+    fn frame_table(&self, ui: &mut egui::Ui) {
+        let tracker = self.session.shared.tracker.lock().unwrap();
+        ui.label(format!("{} IDs", tracker.len()));
+        let now = Instant::now();
+        egui::ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| {
+            egui::Grid::new("frames").striped(true).min_col_width(40.0).show(ui, |ui| {
+                for title in ["Ch", "ID", "DLC", "Data", "Count", "Cycle"] {
+                    ui.strong(title);
+                }
+                ui.end_row();
+                for entry in tracker.iter() {
+                    let frame = &entry.frame;
+                    ui.label(format!("CAN{}", entry.channel + 1));
+                    ui.monospace(frame.id().to_string());
+                    ui.monospace(frame.dlc().to_string());
+                    ui.horizontal(|ui| {
+                        ui.spacing_mut().item_spacing.x = 4.0;
+                        if frame.is_remote() {
+                            ui.monospace("RTR");
+                        }
+                        for (i, byte) in frame.data().iter().enumerate() {
+                            let text = RichText::new(format!("{byte:02X}")).monospace();
+                            match entry.byte_changed(i, HOLD, now) {
+                                true => ui.label(text.color(Color32::RED).strong()),
+                                false => ui.label(text),
+                            };
+                        }
+                    });
+                    ui.monospace(entry.count.to_string());
+                    ui.monospace(entry.cycle.map_or_else(|| "-".to_string(), |cycle| format!("{:.1} ms", cycle.as_secs_f64() * 1000.0)));
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::TopBottomPanel::top("connection").show(ctx, |ui| self.connection_bar(ui));
+        egui::SidePanel::right("transmit").min_width(320.0).show(ctx, |ui| self.transmit_panel(ui));
+        egui::CentralPanel::default().show(ctx, |ui| self.frame_table(ui));
+        ctx.request_repaint_after(REFRESH);
+    }
+}
+
+/// `CAN1 load 12.3% REC 0 TEC 0`, in red once bus-off and yellow while error passive.
+fn channel_indicator(ui: &mut egui::Ui, slot: usize, status: &ChannelStatus, connected: bool) {
+    if !connected {
+        ui.label(format!("CAN{} offline", slot + 1));
+        return;
+    }
+    let load = status.bus_load.map_or_else(|| "n/a".to_string(), |load| format!("{load:.1}%"));
+    let mut text = format!("CAN{} load {load} REC {} TEC {}", slot + 1, status.rx_errors, status.tx_errors);
+    let color = if status.bus_off {
+        text += " BUS-OFF";
+        Color32::RED
+    } else if status.error_passive {
+        text += " error passive";
+        Color32::YELLOW
+    } else {
+        ui.visuals().text_color()
+    };
+    ui.label(RichText::new(text).color(color));
+}
+
+/// `0x123#DEADBEEF` or `0x321#R4`.
+fn frame_text(frame: &Frame) -> String {
+    match frame.is_remote() {
+        true => format!("{}#R{}", frame.id(), frame.dlc()),
+        false => format!("{}#{}", frame.id(), frame.data().iter().map(|byte| format!("{byte:02X}")).collect::<String>()),
+    }
+}
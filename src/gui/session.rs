@@ -0,0 +1,249 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rustcanbus::{
+    Backend, Bitrate, CanLibrary, CandumpWriter, Channel, ChannelHandle, Device, Direction, Frame, FrameSink,
+    IdTracker, MockBackend, Scheduler, VciInitConfig, CHANNEL_COUNT,
+};
+
+/// Frames a channel's consumer can fall behind by before it misses some.
+const CONSUMER_QUEUE: usize = 4096;
+/// How often the worker reads the controllers' status.
+const STATUS_INTERVAL: Duration = Duration::from_millis(500);
+
+pub type Log = CandumpWriter<BufWriter<File>>;
+
+#[derive(Debug, Clone)]
+pub struct ConnectConfig {
+    pub dll: Option<PathBuf>,
+    /// Use the mock adapter, whose two channels are wired together, instead of the DLL.
+    pub simulated: bool,
+    pub dev_type: u32,
+    pub dev_index: u32,
+    pub bitrate: Bitrate,
+}
+
+enum Command {
+    Connect(ConnectConfig),
+    Disconnect,
+}
+
+/// Bus load and controller status of one channel, as last read by the worker.
+#[derive(Debug, Clone, Default)]
+pub struct ChannelStatus {
+    pub bus_load: Option<f64>,
+    pub rx_errors: u8,
+    pub tx_errors: u8,
+    pub error_passive: bool,
+    pub bus_off: bool,
+}
+
+/// State the worker keeps current for the UI.
+#[derive(Default)]
+pub struct Shared {
+    pub tracker: Mutex<IdTracker>,
+    /// Both channels while connected. Transmitting on them only queues the frame for the
+    /// channel's TX thread.
+    pub channels: Mutex<Vec<Channel>>,
+    pub status: Mutex<[ChannelStatus; CHANNEL_COUNT as usize]>,
+    /// Outcome of the last connect, disconnect or log action.
+    pub message: Mutex<String>,
+    pub log: Mutex<Option<(PathBuf, Log)>>,
+    pub scheduler: Scheduler,
+    pub received: AtomicU64,
+    pub sent: AtomicU64,
+}
+
+impl Shared {
+    pub fn connected(&self) -> bool {
+        !self.channels.lock().unwrap().is_empty()
+    }
+
+    fn set_message(&self, message: String) {
+        *self.message.lock().unwrap() = message;
+    }
+
+    fn write_log(&self, channel: u32, frame: &Frame, direction: Direction) {
+        let mut log = self.log.lock().unwrap();
+        if let Some((path, writer)) = log.as_mut() {
+            if let Err(err) = writer.write_frame(channel, frame, direction) {
+                let message = format!("Log write to {} failed, stopped logging: {err}", path.display());
+                *log = None;
+                self.set_message(message);
+            }
+        }
+    }
+}
+
+/// Owns the adapter on a worker thread: every DLL call but the TX threads' happens there or
+/// on the channels' reader threads, fed to the UI through `Shared`.
+pub struct Session {
+    commands: Option<Sender<Command>>,
+    worker: Option<JoinHandle<()>>,
+    pub shared: Arc<Shared>,
+}
+
+impl Session {
+    pub fn spawn() -> Self {
+        let (commands, received) = mpsc::channel();
+        let shared = Arc::new(Shared::default());
+        let worker_shared = Arc::clone(&shared);
+        let worker = thread::spawn(move || {
+            let mut connection = None;
+            loop {
+                match received.recv_timeout(STATUS_INTERVAL) {
+                    Ok(Command::Connect(config)) => {
+                        close(connection.take(), &worker_shared);
+                        match Connection::open(&config, &worker_shared) {
+                            Ok(opened) => {
+                                worker_shared.set_message(format!("Connected to adapter {} at {}bps", config.dev_index, config.bitrate));
+                                connection = Some(opened);
+                            }
+                            Err(err) => worker_shared.set_message(format!("Connect failed: {err}")),
+                        }
+                    }
+                    Ok(Command::Disconnect) => {
+                        if connection.is_some() {
+                            close(connection.take(), &worker_shared);
+                            worker_shared.set_message("Disconnected".to_string());
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if let Some(connection) = &connection {
+                    connection.read_status(&worker_shared);
+                }
+            }
+            close(connection, &worker_shared);
+        });
+        Self { commands: Some(commands), worker: Some(worker), shared }
+    }
+
+    pub fn connect(&self, config: ConnectConfig) {
+        self.send(Command::Connect(config));
+    }
+
+    pub fn disconnect(&self) {
+        self.send(Command::Disconnect);
+    }
+
+    fn send(&self, command: Command) {
+        if let Some(commands) = &self.commands {
+            let _ = commands.send(command);
+        }
+    }
+
+    /// Queues `frame` on `channel` without waiting, logging it if a log is open.
+    pub fn transmit(&self, channel: u32, frame: &Frame) -> Result<(), String> {
+        let channels = self.shared.channels.lock().unwrap();
+        let port = channels.get(channel as usize).ok_or("not connected")?;
+        port.try_transmit(frame).map_err(|err| err.to_string())?;
+        self.shared.sent.fetch_add(1, Ordering::Relaxed);
+        self.shared.write_log(channel, frame, Direction::Tx);
+        Ok(())
+    }
+
+    pub fn start_log(&self, path: PathBuf) -> Result<(), String> {
+        let file = File::create(&path).map_err(|err| format!("{}: {err}", path.display()))?;
+        self.stop_log();
+        *self.shared.log.lock().unwrap() = Some((path, CandumpWriter::new(BufWriter::new(file))));
+        Ok(())
+    }
+
+    pub fn stop_log(&self) {
+        if let Some((path, mut writer)) = self.shared.log.lock().unwrap().take() {
+            if let Err(err) = writer.finish() {
+                self.shared.set_message(format!("Closing {} failed: {err}", path.display()));
+            }
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // Closing the command queue ends the worker, which disconnects first.
+        self.commands = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        self.stop_log();
+    }
+}
+
+struct Connection {
+    device: Device,
+    handles: Vec<ChannelHandle>,
+}
+
+impl Connection {
+    fn open(config: &ConnectConfig, shared: &Arc<Shared>) -> Result<Self, String> {
+        let backend: Backend = match config.simulated {
+            true => Arc::new(MockBackend::new()).into(),
+            false => CanLibrary::load(config.dll.as_deref()).map_err(|err| err.to_string())?.into(),
+        };
+        let device = Device::open_with(backend, config.dev_type, config.dev_index).map_err(|err| err.to_string())?;
+        let init = VciInitConfig::with_bitrate(config.bitrate);
+        let mut handles = Vec::new();
+        for port in 0..CHANNEL_COUNT {
+            let mut handle = device.take_channel(port).map_err(|err| err.to_string())?;
+            handle.init(&init).map_err(|err| err.to_string())?;
+            handle.start().map_err(|err| err.to_string())?;
+            let shared = Arc::clone(shared);
+            handle.spawn(move |channel, running| {
+                let subscription = channel.subscribe(CONSUMER_QUEUE);
+                while running.load(Ordering::SeqCst) {
+                    match subscription.recv_timeout(Duration::from_millis(100)) {
+                        Ok(frame) => {
+                            shared.received.fetch_add(1, Ordering::Relaxed);
+                            shared.tracker.lock().unwrap().update(port, &frame, Instant::now());
+                            shared.write_log(port, &frame, Direction::Rx);
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+            });
+            handles.push(handle);
+        }
+        *shared.channels.lock().unwrap() = handles.iter().map(|handle| handle.channel().clone()).collect();
+        Ok(Self { device, handles })
+    }
+
+    fn read_status(&self, shared: &Shared) {
+        let mut statuses = shared.status.lock().unwrap();
+        for (handle, status) in self.handles.iter().zip(statuses.iter_mut()) {
+            status.bus_load = handle.channel().bus_load();
+            // DLLs without VCI_ReadCANStatus just keep the counters at zero.
+            if let Ok(read) = handle.channel().status() {
+                status.rx_errors = read.rx_error_counter;
+                status.tx_errors = read.tx_error_counter;
+                status.error_passive = read.rx_error_counter >= 128 || read.tx_error_counter >= 128;
+                status.bus_off = read.bus_off();
+            }
+        }
+    }
+}
+
+/// Stops the cyclic messages and the consumers, then resets and closes the adapter.
+fn close(connection: Option<Connection>, shared: &Shared) {
+    let Some(Connection { device, mut handles }) = connection else {
+        return;
+    };
+    shared.scheduler.clear();
+    shared.channels.lock().unwrap().clear();
+    *shared.status.lock().unwrap() = Default::default();
+    for handle in &mut handles {
+        let _ = handle.stop();
+    }
+    drop(handles);
+    if let Err(err) = device.close() {
+        shared.set_message(format!("Closing the adapter failed: {err}"));
+    }
+}
@@ -0,0 +1,312 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::device::Channel;
+use crate::error::CanError;
+use crate::ffi::VciInitConfig;
+use crate::frame::Frame;
+
+/// Where a port is in its lifecycle: created → initialized → started → stopped, and from
+/// stopped back to initialized or started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChannelState {
+    #[default]
+    Created,
+    Initialized,
+    Started,
+    /// Reset after being started; the configuration is kept.
+    Stopped,
+}
+
+impl fmt::Display for ChannelState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Created => "created",
+            Self::Initialized => "initialized",
+            Self::Started => "started",
+            Self::Stopped => "stopped",
+        })
+    }
+}
+
+/// The owner of one port, from [`Device::take_channel`](crate::Device::take_channel): at most
+/// one exists per port at a time. It enforces the lifecycle, so transmitting before
+/// [`ChannelHandle::start`] is [`CanError::NotStarted`] and starting twice is
+/// [`CanError::AlreadyStarted`], and it owns the worker threads started with
+/// [`ChannelHandle::spawn`]. [`ChannelHandle::channel`] is the unchecked [`Channel`] to hand to
+/// the gateway, scheduler and the like.
+pub struct ChannelHandle {
+    channel: Channel,
+    running: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ChannelHandle {
+    pub(crate) fn new(channel: Channel) -> Self {
+        Self { channel, running: Arc::new(AtomicBool::new(true)), workers: Vec::new() }
+    }
+
+    pub fn channel(&self) -> &Channel {
+        &self.channel
+    }
+
+    pub fn index(&self) -> u32 {
+        self.channel.index()
+    }
+
+    pub fn state(&self) -> ChannelState {
+        self.channel.state()
+    }
+
+    /// Fails with [`CanError::AlreadyStarted`] on a started channel; stop it first.
+    pub fn init(&self, config: &VciInitConfig) -> Result<(), CanError> {
+        match self.state() {
+            ChannelState::Started => Err(CanError::AlreadyStarted { channel: self.index() }),
+            _ => self.channel.init(config),
+        }
+    }
+
+    pub fn start(&self) -> Result<(), CanError> {
+        match self.state() {
+            ChannelState::Created => Err(CanError::NotInitialized { channel: self.index() }),
+            ChannelState::Started => Err(CanError::AlreadyStarted { channel: self.index() }),
+            ChannelState::Initialized | ChannelState::Stopped => self.channel.start(),
+        }
+    }
+
+    /// Stops the worker threads, waiting for them to return, then resets the controller. A
+    /// channel that isn't started only loses its workers.
+    pub fn stop(&mut self) -> Result<(), CanError> {
+        self.stop_workers();
+        match self.state() {
+            ChannelState::Started => self.channel.reset(),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn transmit(&self, frame: &Frame) -> Result<(), CanError> {
+        self.started()?;
+        self.channel.transmit(frame)
+    }
+
+    pub fn try_transmit(&self, frame: &Frame) -> Result<(), CanError> {
+        self.started()?;
+        self.channel.try_transmit(frame)
+    }
+
+    pub fn transmit_timeout(&self, frame: &Frame, timeout: Duration) -> Result<(), CanError> {
+        self.started()?;
+        self.channel.transmit_timeout(frame, timeout)
+    }
+
+    pub fn transmit_all(&self, frames: &[Frame]) -> Result<usize, CanError> {
+        self.started()?;
+        self.channel.transmit_all(frames)
+    }
+
+    pub fn receive(&self, timeout: Duration) -> Result<Vec<Frame>, CanError> {
+        self.started()?;
+        self.channel.receive(timeout)
+    }
+
+    /// Runs `work` on a thread owned by the handle, with the channel and a flag that is cleared
+    /// when the handle is stopped or dropped, which then waits for the thread to return.
+    pub fn spawn(&mut self, work: impl FnOnce(Channel, Arc<AtomicBool>) + Send + 'static) {
+        if !self.running.load(Ordering::SeqCst) {
+            self.running = Arc::new(AtomicBool::new(true));
+        }
+        let (channel, running) = (self.channel.clone(), Arc::clone(&self.running));
+        self.workers.push(thread::spawn(move || work(channel, running)));
+    }
+
+    fn started(&self) -> Result<(), CanError> {
+        match self.state() {
+            ChannelState::Started => Ok(()),
+            _ => Err(CanError::NotStarted { channel: self.index() }),
+        }
+    }
+
+    fn stop_workers(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for ChannelHandle {
+    fn drop(&mut self) {
+        self.stop_workers();
+        self.channel.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::bitrate::Bitrate;
+    use crate::device::Device;
+    use crate::id::Id;
+    use crate::mock::MockBackend;
+
+    fn opened() -> (Arc<MockBackend>, Device) {
+        let mock = Arc::new(MockBackend::new());
+        let device = Device::open_with(mock.clone(), 4, 0).expect("the mock opens");
+        (mock, device)
+    }
+
+    fn config() -> VciInitConfig {
+        VciInitConfig::with_bitrate(Bitrate::Kbps500)
+    }
+
+    fn frame() -> Frame {
+        Frame::new(Id::Standard(0x123), &[1, 2]).unwrap()
+    }
+
+    /// Every call that needs a started channel, by name.
+    fn needing_start(handle: &ChannelHandle) -> Vec<(&'static str, Result<(), CanError>)> {
+        vec![
+            ("transmit", handle.transmit(&frame())),
+            ("try_transmit", handle.try_transmit(&frame())),
+            ("transmit_timeout", handle.transmit_timeout(&frame(), Duration::from_millis(1))),
+            ("transmit_all", handle.transmit_all(&[frame()]).map(drop)),
+            ("receive", handle.receive(Duration::from_millis(1)).map(drop)),
+        ]
+    }
+
+    #[test]
+    fn goes_through_the_lifecycle() {
+        let (_mock, device) = opened();
+        let mut handle = device.take_channel(0).unwrap();
+        assert_eq!(handle.state(), ChannelState::Created);
+        handle.init(&config()).unwrap();
+        assert_eq!(handle.state(), ChannelState::Initialized);
+        handle.init(&config()).unwrap();
+        assert_eq!(handle.state(), ChannelState::Initialized, "initializing again is fine before starting");
+        handle.start().unwrap();
+        assert_eq!(handle.state(), ChannelState::Started);
+        handle.stop().unwrap();
+        assert_eq!(handle.state(), ChannelState::Stopped);
+        assert_eq!(handle.channel().config().map(|config| config.bitrate()), Some(Bitrate::Kbps500), "stopping keeps the configuration");
+        handle.start().unwrap();
+        assert_eq!(handle.state(), ChannelState::Started);
+        handle.stop().unwrap();
+        handle.init(&VciInitConfig::with_bitrate(Bitrate::Kbps250)).unwrap();
+        assert_eq!(handle.state(), ChannelState::Initialized);
+    }
+
+    #[test]
+    fn starting_needs_an_initialized_channel() {
+        let (mock, device) = opened();
+        let handle = device.take_channel(0).unwrap();
+        // Refused without calling the DLL, which would use up this failure.
+        mock.fail_next(crate::mock::MockCall::StartCan, -9, 1);
+        assert!(matches!(handle.start(), Err(CanError::NotInitialized { channel: 0 })));
+        assert_eq!(handle.state(), ChannelState::Created);
+        handle.init(&config()).unwrap();
+        assert!(matches!(handle.start(), Err(CanError::StartCan { channel: 0, code: -9 })));
+        assert_eq!(handle.state(), ChannelState::Initialized);
+    }
+
+    #[test]
+    fn double_start_and_init_while_started_are_refused() {
+        let (_mock, device) = opened();
+        let handle = device.take_channel(1).unwrap();
+        handle.init(&config()).unwrap();
+        handle.start().unwrap();
+        assert!(matches!(handle.start(), Err(CanError::AlreadyStarted { channel: 1 })));
+        assert!(matches!(handle.init(&config()), Err(CanError::AlreadyStarted { channel: 1 })));
+        assert_eq!(handle.state(), ChannelState::Started);
+    }
+
+    #[test]
+    fn transmitting_and_receiving_need_a_started_channel() {
+        let (mock, device) = opened();
+        let peer = device.channel(1);
+        peer.init(&config()).unwrap();
+        peer.start().unwrap();
+        let mut handle = device.take_channel(0).unwrap();
+        for (call, result) in needing_start(&handle) {
+            assert!(matches!(result, Err(CanError::NotStarted { channel: 0 })), "{call} when created");
+        }
+        handle.init(&config()).unwrap();
+        for (call, result) in needing_start(&handle) {
+            assert!(matches!(result, Err(CanError::NotStarted { channel: 0 })), "{call} when initialized");
+        }
+        handle.start().unwrap();
+        for (call, result) in needing_start(&handle) {
+            assert!(result.is_ok(), "{call} when started: {result:?}");
+        }
+        assert!(handle.channel().flush_transmit(Duration::from_millis(500)));
+        assert_eq!(mock.take_transmitted(0).len(), 4);
+        handle.stop().unwrap();
+        for (call, result) in needing_start(&handle) {
+            assert!(matches!(result, Err(CanError::NotStarted { channel: 0 })), "{call} when stopped");
+        }
+        assert!(mock.take_transmitted(0).is_empty());
+    }
+
+    #[test]
+    fn one_handle_per_port() {
+        let (_mock, device) = opened();
+        let first = device.take_channel(0).unwrap();
+        assert!(matches!(device.take_channel(0), Err(CanError::ChannelInUse { channel: 0 })));
+        let second = device.take_channel(1).unwrap();
+        assert_eq!((first.index(), second.index()), (0, 1));
+        // Plain channels aren't owners and don't count.
+        let _view = device.channel(0);
+        drop(first);
+        let again = device.take_channel(0).unwrap();
+        assert!(matches!(device.take_channel(1), Err(CanError::ChannelInUse { channel: 1 })));
+        drop((again, second));
+        assert!(device.take_channel(0).is_ok() && device.take_channel(1).is_ok());
+    }
+
+    #[test]
+    fn stopping_joins_the_workers() {
+        let (_mock, device) = opened();
+        let mut handle = device.take_channel(0).unwrap();
+        handle.init(&config()).unwrap();
+        handle.start().unwrap();
+        let finished = Arc::new(AtomicUsize::new(0));
+        for _ in 0..3 {
+            let finished = Arc::clone(&finished);
+            handle.spawn(move |channel, running| {
+                assert_eq!(channel.index(), 0);
+                while running.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                finished.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        handle.stop().unwrap();
+        assert_eq!(finished.load(Ordering::SeqCst), 3, "stop returned before its workers did");
+
+        // Workers spawned after a stop run again, and dropping the handle stops them too.
+        let again = Arc::clone(&finished);
+        handle.spawn(move |_, running| {
+            while running.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            again.fetch_add(1, Ordering::SeqCst);
+        });
+        drop(handle);
+        assert_eq!(finished.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn stopping_an_unstarted_handle_keeps_its_state() {
+        let (_mock, device) = opened();
+        let mut handle = device.take_channel(0).unwrap();
+        handle.stop().unwrap();
+        assert_eq!(handle.state(), ChannelState::Created);
+        handle.init(&config()).unwrap();
+        handle.stop().unwrap();
+        assert_eq!(handle.state(), ChannelState::Initialized);
+    }
+}
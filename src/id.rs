@@ -0,0 +1,51 @@
+use std::fmt;
+
+/// CAN identifier: 11-bit standard or 29-bit extended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Id {
+    Standard(u16),
+    Extended(u32),
+}
+
+impl Id {
+    pub const MAX_STANDARD: u16 = 0x7FF;
+    pub const MAX_EXTENDED: u32 = 0x1FFF_FFFF;
+
+    /// `None` if `id` does not fit in 11 bits.
+    pub fn standard(id: u16) -> Option<Id> {
+        (id <= Self::MAX_STANDARD).then_some(Id::Standard(id))
+    }
+
+    /// `None` if `id` does not fit in 29 bits.
+    pub fn extended(id: u32) -> Option<Id> {
+        (id <= Self::MAX_EXTENDED).then_some(Id::Extended(id))
+    }
+
+    pub fn raw(&self) -> u32 {
+        match *self {
+            Id::Standard(id) => id as u32,
+            Id::Extended(id) => id,
+        }
+    }
+
+    pub fn is_extended(&self) -> bool {
+        matches!(self, Id::Extended(_))
+    }
+
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Id::Standard(id) => id <= Self::MAX_STANDARD,
+            Id::Extended(id) => id <= Self::MAX_EXTENDED,
+        }
+    }
+}
+
+impl fmt::Display for Id {
+    /// Three hex digits for standard IDs, eight for extended (`0x123`, `0x18FF50E5`).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Id::Standard(id) => write!(f, "0x{id:03X}"),
+            Id::Extended(id) => write!(f, "0x{id:08X}"),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::timestamp::{ticks_duration, TICK};
+
+/// One second of device ticks, the window [`IdStats::rate_hz`] counts frames over.
+const RATE_WINDOW_TICKS: u64 = 10_000;
+
+/// Inter-arrival statistics for one ID, measured with the adapter's timestamps so USB batching
+/// on the host doesn't distort the gaps. Fed [`Frame::device_ticks`](crate::Frame::device_ticks),
+/// which keeps counting across wraps of the 32-bit counter.
+#[derive(Debug, Clone, Default)]
+pub struct IdStats {
+    count: u64,
+    last: Option<u64>,
+    min_gap: Option<u64>,
+    max_gap: u64,
+    /// Sum of all gaps, i.e. ticks since the first frame.
+    gap_sum: u64,
+    /// `gap_sum` at each frame in the last second of device time.
+    window: VecDeque<u64>,
+}
+
+impl IdStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, ticks: u64) {
+        self.count += 1;
+        if let Some(last) = self.last {
+            let gap = ticks.saturating_sub(last);
+            self.min_gap = Some(self.min_gap.map_or(gap, |min| min.min(gap)));
+            self.max_gap = self.max_gap.max(gap);
+            self.gap_sum += gap;
+            while self.window.front().is_some_and(|&at| self.gap_sum - at >= RATE_WINDOW_TICKS) {
+                self.window.pop_front();
+            }
+        }
+        self.window.push_back(self.gap_sum);
+        self.last = Some(ticks);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Frames received during the second of device time ending at the latest frame.
+    pub fn rate_hz(&self) -> f64 {
+        self.window.len() as f64
+    }
+
+    pub fn min_gap(&self) -> Option<Duration> {
+        self.min_gap.map(ticks_duration)
+    }
+
+    pub fn max_gap(&self) -> Option<Duration> {
+        (self.count > 1).then(|| ticks_duration(self.max_gap))
+    }
+
+    pub fn mean_gap(&self) -> Option<Duration> {
+        let gaps = self.count.checked_sub(1).filter(|&gaps| gaps > 0)?;
+        Some(TICK.mul_f64(self.gap_sum as f64 / gaps as f64))
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Range;
+
+use crate::checksum::Checksum;
+use crate::frame::Frame;
+use crate::id::Id;
+
+/// A rolling counter of `width` bits (1 to 8) starting `shift` bits up from the least
+/// significant bit of payload byte `byte`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CounterField {
+    pub byte: usize,
+    pub shift: u8,
+    pub width: u8,
+}
+
+impl CounterField {
+    pub fn low_nibble(byte: usize) -> Self {
+        Self { byte, shift: 0, width: 4 }
+    }
+
+    pub fn high_nibble(byte: usize) -> Self {
+        Self { byte, shift: 4, width: 4 }
+    }
+
+    pub fn whole_byte(byte: usize) -> Self {
+        Self { byte, shift: 0, width: 8 }
+    }
+
+    /// Number of values before the counter wraps to 0.
+    pub fn modulus(&self) -> u16 {
+        1 << self.width.clamp(1, 8)
+    }
+
+    fn mask(&self) -> u8 {
+        (self.modulus() - 1) as u8
+    }
+
+    /// `None` if the payload doesn't reach the counter.
+    pub fn read(&self, data: &[u8]) -> Option<u8> {
+        data.get(self.byte).map(|byte| (byte >> self.shift) & self.mask())
+    }
+
+    /// Puts `value`, cut to the field's width, into the payload, keeping the byte's other bits.
+    /// `false` if the payload doesn't reach the counter.
+    pub fn write(&self, data: &mut [u8], value: u8) -> bool {
+        let Some(byte) = data.get_mut(self.byte) else {
+            return false;
+        };
+        let mask = self.mask() << self.shift;
+        *byte = (*byte & !mask) | ((value << self.shift) & mask);
+        true
+    }
+
+    /// The value after `value`, wrapping to 0 at the field's width.
+    pub fn next(&self, value: u8) -> u8 {
+        ((u16::from(value) + 1) % self.modulus()) as u8
+    }
+}
+
+/// A checksum byte and the payload bytes it covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumField {
+    pub algorithm: Checksum,
+    pub byte: usize,
+    /// Bytes covered, e.g. `0..7`; `None` covers the whole payload. The checksum byte itself is
+    /// always left out.
+    pub range: Option<Range<usize>>,
+}
+
+impl ChecksumField {
+    /// What the checksum byte should be, or `None` if the payload is too short for the field.
+    pub fn compute(&self, data: &[u8]) -> Option<u8> {
+        let range = self.range.clone().unwrap_or(0..data.len());
+        if self.byte >= data.len() || range.end > data.len() {
+            return None;
+        }
+        let covered: Vec<u8> = range.filter(|&i| i != self.byte).map(|i| data[i]).collect();
+        Some(self.algorithm.compute(&covered))
+    }
+
+    /// Recomputes the checksum byte in place; `false` if the payload is too short for it.
+    pub fn write(&self, data: &mut [u8]) -> bool {
+        match self.compute(data) {
+            Some(checksum) => {
+                data[self.byte] = checksum;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// The rolling counter and checksum in the frames of one ID, checked by an
+/// [`IntegrityChecker`] or filled in by [`Scheduler::protect`](crate::Scheduler::protect).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegritySpec {
+    pub counter: Option<CounterField>,
+    pub checksum: Option<ChecksumField>,
+}
+
+impl IntegritySpec {
+    /// `frame` as it should go out with the counter at `counter`: the counter is written first,
+    /// then the checksum computed over the result. Fields the payload is too short for, and
+    /// remote frames, are left alone.
+    pub fn apply(&self, frame: &Frame, counter: u8) -> Frame {
+        if frame.is_remote() {
+            return *frame;
+        }
+        let mut data = frame.data().to_vec();
+        if let Some(field) = self.counter {
+            field.write(&mut data, counter);
+        }
+        if let Some(field) = &self.checksum {
+            field.write(&mut data);
+        }
+        Frame::new(frame.id(), &data).expect("same length as the frame")
+    }
+}
+
+/// A problem [`IntegrityChecker::check`] found in one frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegrityEvent {
+    /// The counter jumped: `missed` frames went missing between the last one and this one.
+    CounterSkip { expected: u8, got: u8, missed: u16 },
+    /// The counter didn't move, e.g. a frame sent twice or a stuck sender.
+    CounterRepeat { value: u8 },
+    ChecksumFailure { expected: u8, got: u8 },
+    /// The payload is too short to hold the declared fields.
+    TooShort { dlc: u8 },
+}
+
+impl fmt::Display for IntegrityEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CounterSkip { expected, got, missed } => {
+                write!(f, "counter skipped {missed} frame(s) (expected {expected}, got {got})")
+            }
+            Self::CounterRepeat { value } => write!(f, "counter repeated ({value})"),
+            Self::ChecksumFailure { expected, got } => write!(f, "checksum {got:02X}, expected {expected:02X}"),
+            Self::TooShort { dlc } => write!(f, "{dlc} bytes are too short for the declared fields"),
+        }
+    }
+}
+
+/// Running totals for one ID on one channel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IntegrityStats {
+    pub frames: u64,
+    /// Counter jumps, each losing one or more frames.
+    pub skips: u64,
+    /// Frames lost over all skips.
+    pub missed: u64,
+    pub repeats: u64,
+    pub checksum_failures: u64,
+    pub too_short: u64,
+}
+
+impl IntegrityStats {
+    fn add(&mut self, other: &Self) {
+        self.frames += other.frames;
+        self.skips += other.skips;
+        self.missed += other.missed;
+        self.repeats += other.repeats;
+        self.checksum_failures += other.checksum_failures;
+        self.too_short += other.too_short;
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Track {
+    last_counter: Option<u8>,
+    stats: IntegrityStats,
+}
+
+/// Checks rolling counters and checksums of the IDs declared with [`IntegrityChecker::declare`].
+/// Each channel keeps its own counter sequence.
+#[derive(Debug, Clone, Default)]
+pub struct IntegrityChecker {
+    specs: HashMap<Id, IntegritySpec>,
+    tracks: BTreeMap<(u32, Id), Track>,
+}
+
+impl IntegrityChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn declare(&mut self, id: Id, spec: IntegritySpec) {
+        self.specs.insert(id, spec);
+    }
+
+    pub fn is_declared(&self, id: Id) -> bool {
+        self.specs.contains_key(&id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.specs.is_empty()
+    }
+
+    /// Checks one received frame; undeclared IDs and remote frames are ignored.
+    pub fn check(&mut self, channel: u32, frame: &Frame) -> Vec<IntegrityEvent> {
+        let Some(spec) = self.specs.get(&frame.id()).filter(|_| !frame.is_remote()) else {
+            return Vec::new();
+        };
+        let track = self.tracks.entry((channel, frame.id())).or_default();
+        track.stats.frames += 1;
+        let data = frame.data();
+        let mut events = Vec::new();
+        let counter = spec.counter.map(|field| (field, field.read(data)));
+        let checksum = spec.checksum.as_ref().map(|field| (field, field.compute(data)));
+        if matches!(counter, Some((_, None))) || matches!(checksum, Some((_, None))) {
+            track.stats.too_short += 1;
+            // The sequence starts over with the next frame that has a counter.
+            track.last_counter = None;
+            return vec![IntegrityEvent::TooShort { dlc: frame.dlc() }];
+        }
+        if let Some((field, Some(got))) = counter {
+            if let Some(last) = track.last_counter {
+                let expected = (u16::from(last) + 1) % field.modulus();
+                let missed = (u16::from(got) + field.modulus() - expected) % field.modulus();
+                if got == last {
+                    track.stats.repeats += 1;
+                    events.push(IntegrityEvent::CounterRepeat { value: got });
+                } else if missed > 0 {
+                    track.stats.skips += 1;
+                    track.stats.missed += u64::from(missed);
+                    events.push(IntegrityEvent::CounterSkip { expected: expected as u8, got, missed });
+                }
+            }
+            track.last_counter = Some(got);
+        }
+        if let Some((field, Some(expected))) = checksum {
+            let got = data[field.byte];
+            if got != expected {
+                track.stats.checksum_failures += 1;
+                events.push(IntegrityEvent::ChecksumFailure { expected, got });
+            }
+        }
+        events
+    }
+
+    pub fn stats(&self, channel: u32, id: Id) -> Option<&IntegrityStats> {
+        self.tracks.get(&(channel, id)).map(|track| &track.stats)
+    }
+
+    /// Every channel and declared ID seen so far, by channel, then ID.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, Id, &IntegrityStats)> {
+        self.tracks.iter().map(|(&(channel, id), track)| (channel, id, &track.stats))
+    }
+
+    /// All channels and IDs added up.
+    pub fn totals(&self) -> IntegrityStats {
+        let mut totals = IntegrityStats::default();
+        for track in self.tracks.values() {
+            totals.add(&track.stats);
+        }
+        totals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(data: &[u8]) -> Frame {
+        Frame::new(Id::Standard(0x0C9), data).unwrap()
+    }
+
+    #[test]
+    fn counters_wrap_at_their_width() {
+        for (field, last) in [
+            (CounterField { byte: 0, shift: 3, width: 1 }, 1),
+            (CounterField::low_nibble(0), 15),
+            (CounterField::high_nibble(0), 15),
+            (CounterField { byte: 0, shift: 2, width: 5 }, 31),
+            (CounterField::whole_byte(0), 255),
+        ] {
+            let values: Vec<u16> = std::iter::successors(Some(0), |&value| Some(field.next(value))).map(u16::from).take(usize::from(last) + 3).collect();
+            assert!(values.iter().copied().eq((0..=u16::from(last)).chain(0..2)), "{field:?}: {values:?}");
+            assert_eq!(field.modulus(), u16::from(last) + 1);
+            assert_eq!(field.next(last), 0, "{field:?}");
+        }
+    }
+
+    #[test]
+    fn counters_keep_the_other_bits_of_their_byte() {
+        let mut data = [0xA5, 0xFF];
+        assert!(CounterField::low_nibble(1).write(&mut data, 0x3));
+        assert_eq!(data, [0xA5, 0xF3]);
+        assert!(CounterField::high_nibble(1).write(&mut data, 0x1C), "cut to the width");
+        assert_eq!(data, [0xA5, 0xC3]);
+        let field = CounterField { byte: 0, shift: 3, width: 2 };
+        assert!(field.write(&mut data, 0b10));
+        assert_eq!((data[0], field.read(&data)), (0b1011_0101, Some(0b10)));
+        assert!(!CounterField::whole_byte(2).write(&mut data, 1));
+        assert_eq!(CounterField::whole_byte(2).read(&data), None);
+    }
+
+    #[test]
+    fn checksums_leave_their_own_byte_out() {
+        let data = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x77];
+        let xor = |byte, range| ChecksumField { algorithm: Checksum::Xor, byte, range };
+        assert_eq!(xor(7, None).compute(&data), Some(0x7F));
+        assert_eq!(xor(7, Some(0..8)).compute(&data), Some(0x7F), "a range over the checksum byte");
+        assert_eq!(xor(0, Some(0..3)).compute(&data), Some(0x06), "the checksum first");
+        assert_eq!(xor(3, Some(1..5)).compute(&data), Some(0x16), "in the middle");
+        assert_eq!(xor(7, Some(2..4)).compute(&data), Some(0x0C), "outside the range");
+        let crc = ChecksumField { algorithm: Checksum::Crc8SaeJ1850, byte: 7, range: Some(0..8) };
+        assert_eq!(crc.compute(&data), Some(Checksum::Crc8SaeJ1850.compute(&data[..7])));
+
+        assert_eq!(xor(8, None).compute(&data), None);
+        assert_eq!(xor(0, Some(0..9)).compute(&data), None);
+        let mut short = [1, 2, 3];
+        assert!(!xor(3, None).write(&mut short));
+        assert_eq!(short, [1, 2, 3]);
+    }
+
+    #[test]
+    fn the_checksum_covers_the_new_counter() {
+        let spec = IntegritySpec {
+            counter: Some(CounterField::low_nibble(6)),
+            checksum: Some(ChecksumField { algorithm: Checksum::Crc8SaeJ1850, byte: 7, range: None }),
+        };
+        let sent = spec.apply(&frame(&[0x10, 0x27, 0, 0, 0, 0, 0xAE, 0]), 15);
+        assert_eq!(sent.data(), [0x10, 0x27, 0, 0, 0, 0, 0xAF, 0x4E]);
+        assert_eq!(sent.id(), Id::Standard(0x0C9));
+
+        // Fields past a short payload are skipped, the rest still filled in.
+        let short = spec.apply(&frame(&[0x10, 0x27, 0, 0, 0, 0, 0xAE]), 1);
+        assert_eq!(short.data(), [0x10, 0x27, 0, 0, 0, 0, 0xA1]);
+        let remote = Frame::remote(Id::Standard(0x0C9), 8).unwrap();
+        assert_eq!(spec.apply(&remote, 1), remote);
+    }
+
+    #[test]
+    fn the_checker_accepts_what_apply_generates() {
+        let spec = IntegritySpec {
+            counter: Some(CounterField::high_nibble(0)),
+            checksum: Some(ChecksumField { algorithm: Checksum::SumComplement, byte: 1, range: None }),
+        };
+        let mut checker = IntegrityChecker::new();
+        checker.declare(Id::Standard(0x0C9), spec.clone());
+        let mut counter = 13;
+        for _ in 0..40 {
+            assert_eq!(checker.check(0, &spec.apply(&frame(&[0, 0, 0x55]), counter)), []);
+            counter = spec.counter.unwrap().next(counter);
+        }
+        assert_eq!(checker.stats(0, Id::Standard(0x0C9)).unwrap().frames, 40);
+
+        let skipped = spec.apply(&frame(&[0, 0, 0x55]), spec.counter.unwrap().next(counter));
+        assert_eq!(checker.check(0, &skipped), [IntegrityEvent::CounterSkip { expected: counter, got: (counter + 1) % 16, missed: 1 }]);
+        assert_eq!(checker.check(0, &skipped), [IntegrityEvent::CounterRepeat { value: (counter + 1) % 16 }]);
+        assert_eq!(checker.check(0, &frame(&[0x10])), [IntegrityEvent::TooShort { dlc: 1 }]);
+        let totals = checker.totals();
+        assert_eq!((totals.frames, totals.skips, totals.repeats, totals.too_short), (43, 1, 1, 1));
+    }
+}
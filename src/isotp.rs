@@ -0,0 +1,554 @@
+use std::fmt;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::device::Channel;
+use crate::error::CanError;
+use crate::fanout::Subscription;
+use crate::frame::Frame;
+use crate::id::Id;
+
+/// Largest payload a classic ISO-TP first frame can announce.
+pub const ISOTP_MAX_LEN: usize = 4095;
+
+/// Frames the socket's subscription can fall behind by; a block of consecutive frames fits many
+/// times over.
+const SUBSCRIPTION_CAPACITY: usize = 1024;
+
+/// What [`IsoTpError::Timeout`] waits for before a message has started.
+pub(crate) const START_OF_MESSAGE: &str = "a single or first frame";
+
+const SINGLE: u8 = 0x0;
+const FIRST: u8 = 0x1;
+const CONSECUTIVE: u8 = 0x2;
+const FLOW_CONTROL: u8 = 0x3;
+
+const FC_CONTINUE: u8 = 0x0;
+const FC_WAIT: u8 = 0x1;
+const FC_OVERFLOW: u8 = 0x2;
+
+/// Where the protocol control information starts in each frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Addressing {
+    /// PCI in the first data byte.
+    #[default]
+    Normal,
+    /// The first data byte is an address: `tx_address` on frames we send, and only frames
+    /// starting with `rx_address` are ours.
+    Extended { tx_address: u8, rx_address: u8 },
+}
+
+impl Addressing {
+    fn offset(self) -> usize {
+        match self {
+            Addressing::Normal => 0,
+            Addressing::Extended { .. } => 1,
+        }
+    }
+}
+
+/// Settings for an [`IsoTpSocket`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsoTpConfig {
+    pub tx_id: Id,
+    pub rx_id: Id,
+    pub addressing: Addressing,
+    /// Fill byte for unused payload bytes, so every frame has DLC 8; `None` sends minimal DLCs.
+    pub padding: Option<u8>,
+    /// Consecutive frames the peer may send between our flow control frames; 0 means all.
+    pub block_size: u8,
+    /// Raw STmin we ask the peer to leave between consecutive frames.
+    pub st_min: u8,
+    /// How long to wait for the peer's next flow control or consecutive frame (N_Bs / N_Cr).
+    pub timeout: Duration,
+    /// Flow control "wait" frames accepted in a row before giving up (N_WFTmax).
+    pub max_wait_frames: u32,
+    /// Longest message [`IsoTpSocket::recv`] accepts; longer first frames are answered with an
+    /// overflow flow control.
+    pub max_len: usize,
+}
+
+impl IsoTpConfig {
+    /// Normal addressing, 0xCC padding, no block limit or STmin, 1 s timeouts.
+    pub fn new(tx_id: Id, rx_id: Id) -> Self {
+        Self {
+            tx_id,
+            rx_id,
+            addressing: Addressing::Normal,
+            padding: Some(0xCC),
+            block_size: 0,
+            st_min: 0,
+            timeout: Duration::from_secs(1),
+            max_wait_frames: 10,
+            max_len: ISOTP_MAX_LEN,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum IsoTpError {
+    Can(CanError),
+    /// Nothing arrived in time; `waiting_for` names the frame that was expected.
+    Timeout { waiting_for: &'static str },
+    /// A consecutive frame carried the wrong sequence number.
+    WrongSequence { expected: u8, got: u8 },
+    /// The peer answered our first frame with an overflow flow control.
+    PeerOverflow,
+    /// The peer announced a message longer than [`IsoTpConfig::max_len`].
+    Overflow { len: usize },
+    /// More than [`IsoTpConfig::max_wait_frames`] flow control "wait" frames in a row.
+    TooManyWaits,
+    /// The payload doesn't fit in a single ISO-TP message.
+    TooLong { len: usize },
+    /// ISO-TP has no empty messages.
+    Empty,
+    /// A frame with a reserved PCI type or flow status, a length that doesn't fit its frame, or
+    /// a first frame announcing a length a single frame carries.
+    Malformed { data: Vec<u8> },
+}
+
+impl fmt::Display for IsoTpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Can(err) => err.fmt(f),
+            Self::Timeout { waiting_for } => write!(f, "ISO-TP timeout waiting for {waiting_for}"),
+            Self::WrongSequence { expected, got } => {
+                write!(f, "ISO-TP consecutive frame out of order (expected {expected:X}, got {got:X})")
+            }
+            Self::PeerOverflow => write!(f, "ISO-TP peer reported buffer overflow"),
+            Self::Overflow { len } => write!(f, "ISO-TP message of {len} bytes is too long to receive"),
+            Self::TooManyWaits => write!(f, "ISO-TP peer kept sending wait flow control"),
+            Self::TooLong { len } => write!(f, "{len} bytes is too long for an ISO-TP message (max {ISOTP_MAX_LEN})"),
+            Self::Empty => write!(f, "cannot send an empty ISO-TP message"),
+            Self::Malformed { data } => write!(f, "malformed ISO-TP frame {data:02X?}"),
+        }
+    }
+}
+
+impl std::error::Error for IsoTpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Can(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<CanError> for IsoTpError {
+    fn from(err: CanError) -> Self {
+        Self::Can(err)
+    }
+}
+
+/// One ISO 15765-2 connection on a [`Channel`]: messages sent on `tx_id`, replies and flow
+/// control expected on `rx_id`. Frames on other IDs are ignored, as are frames for this
+/// connection that arrive while nobody is calling [`IsoTpSocket::recv`] (beyond what the
+/// subscription buffers).
+pub struct IsoTpSocket {
+    channel: Channel,
+    frames: Subscription,
+    config: IsoTpConfig,
+}
+
+impl IsoTpSocket {
+    pub fn new(channel: &Channel, config: IsoTpConfig) -> Self {
+        Self { channel: channel.clone(), frames: channel.subscribe(SUBSCRIPTION_CAPACITY), config }
+    }
+
+    pub fn config(&self) -> &IsoTpConfig {
+        &self.config
+    }
+
+    /// Sends `data` as a single frame, or segmented with flow control from the peer.
+    pub fn send(&self, data: &[u8]) -> Result<(), IsoTpError> {
+        let room = 8 - self.config.addressing.offset();
+        if data.is_empty() {
+            return Err(IsoTpError::Empty);
+        }
+        if let Some(frame) = self.single_frame(data) {
+            return Ok(self.channel.transmit(&frame)?);
+        }
+        if data.len() > ISOTP_MAX_LEN {
+            return Err(IsoTpError::TooLong { len: data.len() });
+        }
+        let first = room - 2;
+        let len = data.len() as u16;
+        self.transmit(&[&[(FIRST << 4) | (len >> 8) as u8, len as u8], &data[..first]].concat())?;
+
+        let mut chunks = data[first..].chunks(room - 1);
+        let mut seq = 1u8;
+        loop {
+            let (block_size, st_min) = self.await_flow_control()?;
+            let mut sent = 0u32;
+            while block_size == 0 || sent < u32::from(block_size) {
+                let Some(chunk) = chunks.next() else {
+                    return Ok(());
+                };
+                if sent > 0 {
+                    thread::sleep(st_min);
+                }
+                self.transmit(&[&[(CONSECUTIVE << 4) | seq], chunk].concat())?;
+                seq = (seq + 1) & 0x0F;
+                sent += 1;
+            }
+            if chunks.len() == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for the start of a message, then receives the rest of it, sending
+    /// flow control as configured. A new single or first frame in the middle of a transfer
+    /// abandons it and starts over, as the standard asks.
+    pub fn recv(&self, timeout: Duration) -> Result<Vec<u8>, IsoTpError> {
+        let deadline = Instant::now() + timeout;
+        let mut pdu = self.next_pdu(timeout, START_OF_MESSAGE)?;
+        'message: loop {
+            let (pci, rest) = split_pci(&pdu)?;
+            let (len, mut data) = match pci >> 4 {
+                SINGLE => {
+                    let len = usize::from(pci & 0x0F);
+                    if len == 0 || len > rest.len() {
+                        return Err(IsoTpError::Malformed { data: pdu });
+                    }
+                    return Ok(rest[..len].to_vec());
+                }
+                FIRST => {
+                    let [low, rest @ ..] = rest else {
+                        return Err(IsoTpError::Malformed { data: pdu });
+                    };
+                    let len = usize::from(pci & 0x0F) << 8 | usize::from(*low);
+                    // 0 is the escape to a 32-bit length, which classic CAN doesn't carry; a
+                    // message a single frame could carry mustn't be segmented.
+                    if len < 8 - self.config.addressing.offset() {
+                        return Err(IsoTpError::Malformed { data: pdu });
+                    }
+                    if len > self.config.max_len {
+                        self.send_flow_control(FC_OVERFLOW)?;
+                        return Err(IsoTpError::Overflow { len });
+                    }
+                    (len, rest.to_vec())
+                }
+                // Stray flow control or consecutive frames outside a transfer, which don't
+                // extend the wait.
+                _ => {
+                    pdu = self.next_pdu(deadline.saturating_duration_since(Instant::now()), START_OF_MESSAGE)?;
+                    continue;
+                }
+            };
+
+            let mut seq = 1u8;
+            while data.len() < len {
+                self.send_flow_control(FC_CONTINUE)?;
+                let mut received = 0u32;
+                while data.len() < len && (self.config.block_size == 0 || received < u32::from(self.config.block_size)) {
+                    pdu = self.next_pdu(self.config.timeout, "a consecutive frame")?;
+                    let (pci, rest) = split_pci(&pdu)?;
+                    match pci >> 4 {
+                        CONSECUTIVE if pci & 0x0F == seq => {
+                            let take = rest.len().min(len - data.len());
+                            data.extend_from_slice(&rest[..take]);
+                            seq = (seq + 1) & 0x0F;
+                            received += 1;
+                        }
+                        CONSECUTIVE => return Err(IsoTpError::WrongSequence { expected: seq, got: pci & 0x0F }),
+                        SINGLE | FIRST => continue 'message,
+                        _ => {}
+                    }
+                }
+            }
+            return Ok(data);
+        }
+    }
+
+    /// Waits for the peer's flow control and returns its block size and separation time.
+    fn await_flow_control(&self) -> Result<(u8, Duration), IsoTpError> {
+        let mut waits = 0;
+        loop {
+            let pdu = self.next_pdu(self.config.timeout, "flow control")?;
+            let (pci, rest) = split_pci(&pdu)?;
+            if pci >> 4 != FLOW_CONTROL {
+                continue;
+            }
+            match (pci & 0x0F, rest) {
+                (FC_CONTINUE, [block_size, st_min, ..]) => return Ok((*block_size, st_min_duration(*st_min))),
+                (FC_WAIT, _) => {
+                    waits += 1;
+                    if waits > self.config.max_wait_frames {
+                        return Err(IsoTpError::TooManyWaits);
+                    }
+                }
+                (FC_OVERFLOW, _) => return Err(IsoTpError::PeerOverflow),
+                _ => return Err(IsoTpError::Malformed { data: pdu }),
+            }
+        }
+    }
+
+    fn send_flow_control(&self, status: u8) -> Result<(), IsoTpError> {
+        self.transmit(&[(FLOW_CONTROL << 4) | status, self.config.block_size, self.config.st_min])
+    }
+
+    fn transmit(&self, pdu: &[u8]) -> Result<(), IsoTpError> {
+        Ok(self.channel.transmit(&self.frame(pdu))?)
+    }
+
+    /// The frame carrying one PCI and its data, with the address byte and padding added.
+    pub(crate) fn frame(&self, pdu: &[u8]) -> Frame {
+        let mut data = Vec::with_capacity(8);
+        if let Addressing::Extended { tx_address, .. } = self.config.addressing {
+            data.push(tx_address);
+        }
+        data.extend_from_slice(pdu);
+        if let Some(fill) = self.config.padding {
+            data.resize(8, fill);
+        }
+        Frame::new(self.config.tx_id, &data).expect("ISO-TP frames fit in 8 bytes")
+    }
+
+    /// A single frame for `data`, if it fits in one.
+    pub(crate) fn single_frame(&self, data: &[u8]) -> Option<Frame> {
+        let fits = !data.is_empty() && data.len() < 8 - self.config.addressing.offset();
+        fits.then(|| self.frame(&[&[(SINGLE << 4) | data.len() as u8], data].concat()))
+    }
+
+    pub(crate) fn channel(&self) -> &Channel {
+        &self.channel
+    }
+
+    /// The next frame for this connection with the address byte stripped, within `timeout`.
+    fn next_pdu(&self, timeout: Duration, waiting_for: &'static str) -> Result<Vec<u8>, IsoTpError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let frame = match self.frames.recv_timeout(left) {
+                Ok(frame) => frame,
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => {
+                    return Err(IsoTpError::Timeout { waiting_for });
+                }
+            };
+            if frame.id() != self.config.rx_id || frame.is_remote() {
+                continue;
+            }
+            match (self.config.addressing, frame.data()) {
+                (Addressing::Normal, data) if !data.is_empty() => return Ok(data.to_vec()),
+                (Addressing::Extended { rx_address, .. }, [address, data @ ..]) if *address == rx_address && !data.is_empty() => {
+                    return Ok(data.to_vec());
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn split_pci(pdu: &[u8]) -> Result<(u8, &[u8]), IsoTpError> {
+    match pdu {
+        [pci, rest @ ..] => Ok((*pci, rest)),
+        [] => Err(IsoTpError::Malformed { data: Vec::new() }),
+    }
+}
+
+/// STmin as encoded in flow control: 0-127 ms, or 100-900 µs for F1-F9. Reserved values mean
+/// the longest time, 127 ms.
+fn st_min_duration(raw: u8) -> Duration {
+    match raw {
+        0x00..=0x7F => Duration::from_millis(u64::from(raw)),
+        0xF1..=0xF9 => Duration::from_micros(u64::from(raw - 0xF0) * 100),
+        _ => Duration::from_millis(0x7F),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::JoinHandle;
+
+    use super::*;
+    use crate::device::Device;
+    use crate::mock::started_pair;
+
+    const TESTER: u16 = 0x7E0;
+    const ECU: u16 = 0x7E8;
+
+    /// The far end of the connection, driven by hand: it sends raw PDUs as the ECU and sees
+    /// what the socket sends.
+    struct Peer {
+        channel: Channel,
+        frames: Subscription,
+        _device: Device,
+    }
+
+    impl Peer {
+        fn send(&self, pdu: &[u8]) {
+            self.channel.transmit(&Frame::new(Id::standard(ECU).unwrap(), pdu).unwrap()).unwrap();
+        }
+
+        /// The next frame the socket sent, if one comes within 200 ms.
+        fn next(&self) -> Option<Vec<u8>> {
+            let frame = self.frames.recv_timeout(Duration::from_millis(200)).ok()?;
+            assert_eq!(frame.id(), Id::standard(TESTER).unwrap());
+            Some(frame.data().to_vec())
+        }
+    }
+
+    fn config() -> IsoTpConfig {
+        IsoTpConfig { timeout: Duration::from_millis(200), ..IsoTpConfig::new(Id::standard(TESTER).unwrap(), Id::standard(ECU).unwrap()) }
+    }
+
+    /// A socket receiving with `config` in a thread of its own, and the peer.
+    fn receiving(config: IsoTpConfig, timeout: Duration) -> (JoinHandle<Result<Vec<u8>, IsoTpError>>, Peer) {
+        let (_mock, device, can1, can2) = started_pair();
+        let peer = Peer { frames: can2.subscribe(64), channel: can2, _device: device };
+        let socket = IsoTpSocket::new(&can1, config);
+        let receiver = thread::spawn(move || socket.recv(timeout));
+        // Let the socket's reader start before the peer talks.
+        thread::sleep(Duration::from_millis(20));
+        (receiver, peer)
+    }
+
+    fn flow_control(status: u8, block_size: u8) -> Vec<u8> {
+        vec![(FLOW_CONTROL << 4) | status, block_size, 0, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC]
+    }
+
+    /// Sends `message` segmented the way a well-behaved ECU would, checking each flow control.
+    fn send_segmented(peer: &Peer, message: &[u8], block_size: u8) {
+        let len = message.len() as u16;
+        peer.send(&[&[0x10 | (len >> 8) as u8, len as u8], &message[..6]].concat());
+        for (index, chunk) in message[6..].chunks(7).enumerate() {
+            if block_size == 0 && index == 0 || block_size > 0 && index % usize::from(block_size) == 0 {
+                assert_eq!(peer.next().unwrap(), flow_control(FC_CONTINUE, block_size));
+            }
+            peer.send(&[&[0x20 | ((index + 1) & 0x0F) as u8], chunk].concat());
+        }
+    }
+
+    #[test]
+    fn single_frame() {
+        let (receiver, peer) = receiving(config(), Duration::from_millis(500));
+        peer.send(&[0x03, 0x62, 0xF1, 0x90, 0xAA, 0xAA, 0xAA, 0xAA]);
+        assert_eq!(receiver.join().unwrap().unwrap(), [0x62, 0xF1, 0x90]);
+        assert_eq!(peer.next(), None, "single frames need no flow control");
+    }
+
+    #[test]
+    fn segmented_message_in_blocks() {
+        let message: Vec<u8> = (0..100).collect();
+        let (receiver, peer) = receiving(IsoTpConfig { block_size: 4, ..config() }, Duration::from_millis(500));
+        send_segmented(&peer, &message, 4);
+        assert_eq!(receiver.join().unwrap().unwrap(), message);
+        assert_eq!(peer.next(), None);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_after_fifteen() {
+        let message: Vec<u8> = (0..=255).cycle().take(ISOTP_MAX_LEN).collect();
+        let (receiver, peer) = receiving(config(), Duration::from_millis(500));
+        send_segmented(&peer, &message, 0);
+        assert_eq!(receiver.join().unwrap().unwrap(), message);
+    }
+
+    #[test]
+    fn single_frames_that_dont_fit_are_malformed() {
+        for pdu in [&[0x00, 0x55][..], &[0x05, 1, 2, 3]] {
+            let (receiver, peer) = receiving(config(), Duration::from_millis(500));
+            peer.send(pdu);
+            assert!(matches!(receiver.join().unwrap(), Err(IsoTpError::Malformed { data }) if data == pdu));
+        }
+    }
+
+    #[test]
+    fn first_frames_announcing_short_or_escaped_lengths_are_malformed() {
+        for len in [0, 1, 7] {
+            let (receiver, peer) = receiving(config(), Duration::from_millis(500));
+            peer.send(&[0x10, len, 1, 2, 3, 4, 5, 6]);
+            assert!(matches!(receiver.join().unwrap(), Err(IsoTpError::Malformed { .. })), "length {len}");
+            assert_eq!(peer.next(), None, "no flow control for length {len}");
+        }
+
+        let (receiver, peer) = receiving(config(), Duration::from_millis(500));
+        send_segmented(&peer, &[9; 8], 0);
+        assert_eq!(receiver.join().unwrap().unwrap(), [9; 8]);
+    }
+
+    #[test]
+    fn shortest_first_frame_depends_on_the_addressing() {
+        let extended = IsoTpConfig { addressing: Addressing::Extended { tx_address: 0xF1, rx_address: 0x10 }, padding: None, ..config() };
+        let (receiver, peer) = receiving(extended, Duration::from_millis(500));
+        peer.send(&[0x10, 0x10, 0x05, 1, 2, 3, 4, 5]);
+        assert!(matches!(receiver.join().unwrap(), Err(IsoTpError::Malformed { .. })));
+
+        let (receiver, peer) = receiving(extended, Duration::from_millis(500));
+        peer.send(&[0x10, 0x10, 0x07, 1, 2, 3, 4, 5]);
+        assert_eq!(peer.next().unwrap(), [0xF1, 0x30, 0, 0]);
+        peer.send(&[0x10, 0x21, 6, 7]);
+        assert_eq!(receiver.join().unwrap().unwrap(), [1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn too_long_messages_are_refused_with_overflow() {
+        let (receiver, peer) = receiving(IsoTpConfig { max_len: 64, ..config() }, Duration::from_millis(500));
+        peer.send(&[0x10, 65, 1, 2, 3, 4, 5, 6]);
+        assert!(matches!(receiver.join().unwrap(), Err(IsoTpError::Overflow { len: 65 })));
+        assert_eq!(peer.next().unwrap(), flow_control(FC_OVERFLOW, 0));
+    }
+
+    #[test]
+    fn wrong_sequence_number_aborts() {
+        let (receiver, peer) = receiving(config(), Duration::from_millis(500));
+        peer.send(&[0x10, 20, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(peer.next().unwrap(), flow_control(FC_CONTINUE, 0));
+        peer.send(&[0x21, 7, 8, 9, 10, 11, 12, 13]);
+        peer.send(&[0x23, 14, 15, 16, 17, 18, 19, 20]);
+        assert!(matches!(receiver.join().unwrap(), Err(IsoTpError::WrongSequence { expected: 2, got: 3 })));
+    }
+
+    #[test]
+    fn missing_consecutive_frame_times_out() {
+        let (receiver, peer) = receiving(config(), Duration::from_millis(500));
+        peer.send(&[0x10, 20, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(peer.next().unwrap(), flow_control(FC_CONTINUE, 0));
+        assert!(matches!(receiver.join().unwrap(), Err(IsoTpError::Timeout { waiting_for: "a consecutive frame" })));
+    }
+
+    /// A peer that sends frames out of turn: stray consecutive and flow control frames before
+    /// the message, a flow control and a reserved PCI in the middle of it, and a new first frame
+    /// that abandons it halfway.
+    #[test]
+    fn out_of_turn_frames_from_the_peer() {
+        let (receiver, peer) = receiving(config(), Duration::from_millis(500));
+        peer.send(&[0x21, 0xEE, 0xEE]);
+        peer.send(&[0x30, 0, 0]);
+        peer.send(&[0x10, 30, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(peer.next().unwrap(), flow_control(FC_CONTINUE, 0));
+        peer.send(&[0x21, 7, 8, 9, 10, 11, 12, 13]);
+        peer.send(&[0x30, 0, 0]);
+        peer.send(&[0x40, 0xEE]);
+
+        let message: Vec<u8> = (100..120).collect();
+        send_segmented(&peer, &message, 0);
+        assert_eq!(receiver.join().unwrap().unwrap(), message);
+        assert_eq!(peer.next(), None);
+    }
+
+    #[test]
+    fn stray_frames_dont_extend_the_wait() {
+        let (receiver, peer) = receiving(config(), Duration::from_millis(200));
+        let started = Instant::now();
+        while !receiver.is_finished() && started.elapsed() < Duration::from_secs(2) {
+            peer.send(&[0x21, 0xEE]);
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(matches!(receiver.join().unwrap(), Err(IsoTpError::Timeout { waiting_for: START_OF_MESSAGE })));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn st_min_encodings() {
+        assert_eq!(st_min_duration(0x00), Duration::ZERO);
+        assert_eq!(st_min_duration(0x7F), Duration::from_millis(127));
+        assert_eq!(st_min_duration(0xF1), Duration::from_micros(100));
+        assert_eq!(st_min_duration(0xF9), Duration::from_micros(900));
+        for reserved in [0x80, 0xF0, 0xFA, 0xFF] {
+            assert_eq!(st_min_duration(reserved), Duration::from_millis(127));
+        }
+    }
+}
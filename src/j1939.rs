@@ -0,0 +1,341 @@
+use std::fmt;
+
+use crate::id::Id;
+use crate::j1939_pgns::{pgn_def, PgnDef, SpnDef};
+
+/// Destination address meaning every node.
+pub const J1939_GLOBAL: u8 = 0xFF;
+
+/// A 29-bit identifier split into its J1939 fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct J1939Id {
+    pub priority: u8,
+    /// Parameter group number, including the data page bits. For destination-specific (PDU1)
+    /// groups the low byte is 0; the destination is in `destination`.
+    pub pgn: u32,
+    /// Destination address of a PDU1 group; `None` for broadcast (PDU2) groups.
+    pub destination: Option<u8>,
+    pub source: u8,
+}
+
+impl J1939Id {
+    /// `None` for standard IDs, which J1939 doesn't use.
+    pub fn from_id(id: Id) -> Option<Self> {
+        let Id::Extended(raw) = id else {
+            return None;
+        };
+        let pdu_format = (raw >> 16) as u8;
+        let pdu_specific = (raw >> 8) as u8;
+        let data_page = (raw >> 24) & 0x3;
+        let (pgn, destination) = if pdu_format < 240 {
+            (data_page << 16 | u32::from(pdu_format) << 8, Some(pdu_specific))
+        } else {
+            (data_page << 16 | u32::from(pdu_format) << 8 | u32::from(pdu_specific), None)
+        };
+        Some(Self { priority: (raw >> 26) as u8 & 0x7, pgn, destination, source: raw as u8 })
+    }
+
+    pub fn to_id(&self) -> Id {
+        let specific = self.destination.map_or(0, u32::from);
+        Id::Extended(u32::from(self.priority & 0x7) << 26 | (self.pgn & 0x3FFFF) << 8 | specific << 8 | u32::from(self.source))
+    }
+
+    /// The ID with priority and destination left out, so all frames of one PGN from one
+    /// source share it; used to group the monitor view.
+    pub fn group_id(id: Id) -> Id {
+        match Self::from_id(id) {
+            Some(j1939) => Id::Extended(j1939.pgn << 8 | u32::from(j1939.source)),
+            None => id,
+        }
+    }
+
+    /// The PGN's entry in [`PGNS`](crate::PGNS), if known.
+    pub fn pgn_def(&self) -> Option<&'static PgnDef> {
+        pgn_def(self.pgn)
+    }
+}
+
+impl fmt::Display for J1939Id {
+    /// `PGN 61444 EEC1 SA 0x00 P3`, with `DA 0x..` for destination-specific groups.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PGN {}", self.pgn)?;
+        if let Some(def) = self.pgn_def() {
+            write!(f, " {}", def.acronym)?;
+        }
+        write!(f, " SA 0x{:02X}", self.source)?;
+        if let Some(destination) = self.destination {
+            write!(f, " DA 0x{destination:02X}")?;
+        }
+        write!(f, " P{}", self.priority)
+    }
+}
+
+/// An SPN's raw value classified by the J1939 ranges: all ones means not available, and the
+/// 0xFE.. (or binary 10 for two-bit states) range an error indicator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpnValue {
+    Valid(f64),
+    Error,
+    NotAvailable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpnReading {
+    pub def: &'static SpnDef,
+    pub raw: u64,
+    pub value: SpnValue,
+}
+
+impl fmt::Display for SpnReading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=", self.def.name)?;
+        match self.value {
+            SpnValue::Valid(value) if self.def.unit.is_empty() => write!(f, "{value}"),
+            SpnValue::Valid(value) => write!(f, "{value} {}", self.def.unit),
+            SpnValue::Error => write!(f, "error"),
+            SpnValue::NotAvailable => write!(f, "n/a"),
+        }
+    }
+}
+
+/// Decodes the SPNs of `def` that fit in `data`.
+pub fn decode_spns(def: &'static PgnDef, data: &[u8]) -> Vec<SpnReading> {
+    def.spns
+        .iter()
+        .filter_map(|spn| {
+            let raw = extract(data, spn)?;
+            let all_ones = if spn.bits >= 64 { u64::MAX } else { (1 << spn.bits) - 1 };
+            let value = if raw == all_ones {
+                SpnValue::NotAvailable
+            } else if spn.bits >= 8 && raw >> (spn.bits - 8) >= 0xFE {
+                if raw >> (spn.bits - 8) == 0xFE { SpnValue::Error } else { SpnValue::NotAvailable }
+            } else if spn.bits == 2 && raw == 0b10 {
+                SpnValue::Error
+            } else {
+                SpnValue::Valid(raw as f64 * spn.scale + spn.offset)
+            };
+            Some(SpnReading { def: spn, raw, value })
+        })
+        .collect()
+}
+
+/// The little-endian bit field of `spn`, or `None` if `data` is too short.
+fn extract(data: &[u8], spn: &SpnDef) -> Option<u64> {
+    let start = usize::from(spn.byte.checked_sub(1)?) * 8 + usize::from(spn.bit.checked_sub(1)?);
+    let bits = usize::from(spn.bits);
+    if bits == 0 || bits > 64 || start + bits > data.len() * 8 {
+        return None;
+    }
+    let mut raw = 0u64;
+    for i in 0..bits {
+        let bit = start + i;
+        if data[bit / 8] >> (bit % 8) & 1 != 0 {
+            raw |= 1 << i;
+        }
+    }
+    Some(raw)
+}
+
+/// Lamp states from DM1 byte 1: 0 off, 1 on, 3 not available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lamps {
+    pub malfunction: u8,
+    pub red_stop: u8,
+    pub amber_warning: u8,
+    pub protect: u8,
+}
+
+/// One active diagnostic trouble code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dtc {
+    pub spn: u32,
+    /// Failure mode identifier.
+    pub fmi: u8,
+    pub occurrences: u8,
+}
+
+/// A DM1 message. A single frame carries at most one DTC; longer lists arrive over the transport
+/// protocol and decode the same way once reassembled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dm1 {
+    pub lamps: Lamps,
+    pub dtcs: Vec<Dtc>,
+}
+
+impl Dm1 {
+    /// `None` if `data` is shorter than the two lamp bytes. DTC slots that are all zeros or all
+    /// ones (no fault, padding) are skipped.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 2 {
+            return None;
+        }
+        let status = data[0];
+        let lamps = Lamps {
+            malfunction: status >> 6 & 0x3,
+            red_stop: status >> 4 & 0x3,
+            amber_warning: status >> 2 & 0x3,
+            protect: status & 0x3,
+        };
+        let dtcs = data[2..]
+            .chunks_exact(4)
+            .filter(|dtc| dtc.iter().any(|&b| b != 0) && dtc.iter().any(|&b| b != 0xFF))
+            .map(|dtc| Dtc {
+                spn: u32::from(dtc[0]) | u32::from(dtc[1]) << 8 | u32::from(dtc[2] >> 5) << 16,
+                fmi: dtc[2] & 0x1F,
+                occurrences: dtc[3] & 0x7F,
+            })
+            .collect();
+        Some(Self { lamps, dtcs })
+    }
+}
+
+impl fmt::Display for Dm1 {
+    /// `MIL off, RSL off, AWL on, PL off; SPN 110 FMI 0 (3x)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = |lamp: u8| match lamp {
+            0 => "off",
+            1 => "on",
+            _ => "n/a",
+        };
+        let Lamps { malfunction, red_stop, amber_warning, protect } = self.lamps;
+        write!(
+            f,
+            "MIL {}, RSL {}, AWL {}, PL {}",
+            state(malfunction),
+            state(red_stop),
+            state(amber_warning),
+            state(protect)
+        )?;
+        if self.dtcs.is_empty() {
+            return write!(f, "; no active DTCs");
+        }
+        for (i, dtc) in self.dtcs.iter().enumerate() {
+            write!(f, "{} SPN {} FMI {} ({}x)", if i == 0 { ";" } else { "," }, dtc.spn, dtc.fmi, dtc.occurrences)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candump::parse_candump_line;
+    use crate::frame::Frame;
+
+    /// From a candump of a tractor's J1939 backbone at idle, then pulling away.
+    const TRUCK_LOG: &str = "\
+(1602485021.104512) can0 0CF00400#F07D823C11FFFF7D
+(1602485021.114877) can0 18FEF100#F7A01E1F00FFFFFF
+(1602485021.120301) can0 18FEEE00#6E4A602DFFFFFFFF
+(1602485021.131044) can0 18FECA00#04FFBE000301FFFF
+(1602485021.140388) can0 18FEE000#A0860100C0D40100
+(1602485021.152730) can0 18EA00F9#00EE00
+(1602485021.161112) can0 0CF00403#F18282E02EFFFF83
+(1602485021.170950) can0 18FEEE00#FE4AFFFFFFFFFFFF
+";
+
+    fn frames() -> Vec<Frame> {
+        TRUCK_LOG.lines().map(|line| parse_candump_line(line).unwrap().frame).collect()
+    }
+
+    /// The readings of a known PGN as `name=value` strings.
+    fn readings(frame: &Frame) -> Vec<String> {
+        let def = J1939Id::from_id(frame.id()).unwrap().pgn_def().expect("a known PGN");
+        decode_spns(def, frame.data()).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn splits_the_ids_of_captured_frames() {
+        let ids: Vec<String> = frames().iter().map(|frame| J1939Id::from_id(frame.id()).unwrap().to_string()).collect();
+        assert_eq!(
+            ids,
+            [
+                "PGN 61444 EEC1 SA 0x00 P3",
+                "PGN 65265 CCVS SA 0x00 P6",
+                "PGN 65262 ET1 SA 0x00 P6",
+                "PGN 65226 DM1 SA 0x00 P6",
+                "PGN 65248 VD SA 0x00 P6",
+                "PGN 59904 SA 0xF9 DA 0x00 P6",
+                "PGN 61444 EEC1 SA 0x03 P3",
+                "PGN 65262 ET1 SA 0x00 P6",
+            ]
+        );
+        for frame in frames() {
+            assert_eq!(J1939Id::from_id(frame.id()).unwrap().to_id(), frame.id(), "{frame:?}");
+        }
+        assert_eq!(J1939Id::from_id(Id::Standard(0x7DF)), None);
+    }
+
+    #[test]
+    fn decodes_eec1_from_two_sources() {
+        let frames = frames();
+        assert_eq!(
+            readings(&frames[0]),
+            [
+                "Engine Torque Mode=0",
+                "Driver's Demand Engine - Percent Torque=0 %",
+                "Actual Engine - Percent Torque=5 %",
+                "Engine Speed=551.5 rpm",
+                "Source Address of Controlling Device=n/a",
+                "Engine Starter Mode=n/a",
+                "Engine Demand - Percent Torque=0 %",
+            ]
+        );
+        assert_eq!(readings(&frames[6])[3], "Engine Speed=1500 rpm");
+        assert_eq!(readings(&frames[6])[0], "Engine Torque Mode=1");
+        // Grouped by PGN and source, whatever the priority.
+        assert_ne!(J1939Id::group_id(frames[0].id()), J1939Id::group_id(frames[6].id()));
+        let lower_priority = J1939Id { priority: 6, ..J1939Id::from_id(frames[0].id()).unwrap() }.to_id();
+        assert_eq!(J1939Id::group_id(lower_priority), J1939Id::group_id(frames[0].id()));
+        assert_eq!(J1939Id::group_id(frames[0].id()), Id::Extended(0x00F0_0400));
+    }
+
+    #[test]
+    fn decodes_ccvs_switch_states_and_speed() {
+        let frame = frames()[1];
+        let def = pgn_def(65265).unwrap();
+        let values: Vec<(u32, SpnValue)> = decode_spns(def, frame.data()).iter().map(|reading| (reading.def.spn, reading.value)).collect();
+        assert_eq!(
+            values,
+            [
+                (69, SpnValue::NotAvailable),
+                (70, SpnValue::Valid(1.0)),
+                (1633, SpnValue::NotAvailable),
+                (84, SpnValue::Valid(30.625)),
+                (595, SpnValue::NotAvailable),
+                (596, SpnValue::NotAvailable),
+                (597, SpnValue::Valid(1.0)),
+                (598, SpnValue::Valid(0.0)),
+                (86, SpnValue::NotAvailable),
+                (976, SpnValue::NotAvailable),
+                (527, SpnValue::NotAvailable),
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_temperatures_distances_and_error_indicators() {
+        let frames = frames();
+        assert_eq!(readings(&frames[2]), ["Engine Coolant Temperature=70 °C", "Engine Fuel Temperature 1=34 °C", "Engine Oil Temperature 1=90 °C"]);
+        assert_eq!(readings(&frames[4]), ["Trip Distance=12500 km", "Total Vehicle Distance=15000 km"]);
+        // A failed coolant sensor reports 0xFE; the oil temperature is not fitted.
+        assert_eq!(readings(&frames[7]), ["Engine Coolant Temperature=error", "Engine Fuel Temperature 1=34 °C", "Engine Oil Temperature 1=n/a"]);
+        // SPNs past the end of a short frame are left out.
+        assert_eq!(readings(&Frame::new(frames[2].id(), &[0x6E]).unwrap()), ["Engine Coolant Temperature=70 °C"]);
+    }
+
+    #[test]
+    fn decodes_dm1_lamps_and_dtcs() {
+        let dm1 = Dm1::parse(frames()[3].data()).unwrap();
+        assert_eq!(dm1.lamps, Lamps { malfunction: 0, red_stop: 0, amber_warning: 1, protect: 0 });
+        assert_eq!(dm1.dtcs, [Dtc { spn: 190, fmi: 3, occurrences: 1 }]);
+        assert_eq!(dm1.to_string(), "MIL off, RSL off, AWL on, PL off; SPN 190 FMI 3 (1x)");
+
+        // Reassembled from the transport protocol: two DTCs, one with an SPN above 16 bits.
+        let dm1 = Dm1::parse(&[0x44, 0xFF, 0x6E, 0x00, 0x00, 0x05, 0x0B, 0xF2, 0x27, 0x01, 0xFF, 0xFF, 0xFF, 0xFF]).unwrap();
+        assert_eq!(dm1.dtcs, [Dtc { spn: 110, fmi: 0, occurrences: 5 }, Dtc { spn: 0x1_F20B, fmi: 7, occurrences: 1 }]);
+        assert_eq!(dm1.to_string(), "MIL on, RSL off, AWL on, PL off; SPN 110 FMI 0 (5x), SPN 127499 FMI 7 (1x)");
+        assert_eq!(Dm1::parse(&[0x00, 0xFF, 0, 0, 0, 0, 0xFF, 0xFF]).unwrap().to_string(), "MIL off, RSL off, AWL off, PL off; no active DTCs");
+        assert_eq!(Dm1::parse(&[0x00]), None);
+    }
+}
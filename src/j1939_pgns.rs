@@ -0,0 +1,117 @@
+//! J1939-71 parameter groups [`decode_spns`](crate::decode_spns) knows. To support another
+//! PGN, add its SPNs as a `const` slice and list it in [`PGNS`].
+
+/// Where an SPN sits in its PGN's data and how to scale it, as in the J1939-71 tables.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpnDef {
+    pub spn: u32,
+    pub name: &'static str,
+    /// Start position: 1-based byte, then 1-based bit within it (bit 1 is the LSB).
+    pub byte: u8,
+    pub bit: u8,
+    /// Width in bits; fields spanning bytes are little-endian.
+    pub bits: u8,
+    pub scale: f64,
+    pub offset: f64,
+    pub unit: &'static str,
+}
+
+/// A parameter group and the SPNs it carries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PgnDef {
+    pub pgn: u32,
+    pub acronym: &'static str,
+    pub name: &'static str,
+    pub spns: &'static [SpnDef],
+}
+
+/// Active Diagnostic Trouble Codes; its data is lamps and DTCs rather than SPNs, see
+/// [`Dm1`](crate::Dm1).
+pub const PGN_DM1: u32 = 65226;
+
+const fn spn(spn: u32, name: &'static str, (byte, bit): (u8, u8), bits: u8, scale: f64, offset: f64, unit: &'static str) -> SpnDef {
+    SpnDef { spn, name, byte, bit, bits, scale, offset, unit }
+}
+
+const EEC2: &[SpnDef] = &[
+    spn(91, "Accelerator Pedal Position 1", (2, 1), 8, 0.4, 0.0, "%"),
+    spn(92, "Engine Percent Load At Current Speed", (3, 1), 8, 1.0, 0.0, "%"),
+];
+
+const EEC1: &[SpnDef] = &[
+    spn(899, "Engine Torque Mode", (1, 1), 4, 1.0, 0.0, ""),
+    spn(512, "Driver's Demand Engine - Percent Torque", (2, 1), 8, 1.0, -125.0, "%"),
+    spn(513, "Actual Engine - Percent Torque", (3, 1), 8, 1.0, -125.0, "%"),
+    spn(190, "Engine Speed", (4, 1), 16, 0.125, 0.0, "rpm"),
+    spn(1483, "Source Address of Controlling Device", (6, 1), 8, 1.0, 0.0, ""),
+    spn(1675, "Engine Starter Mode", (7, 1), 4, 1.0, 0.0, ""),
+    spn(2432, "Engine Demand - Percent Torque", (8, 1), 8, 1.0, -125.0, "%"),
+];
+
+const ET1: &[SpnDef] = &[
+    spn(110, "Engine Coolant Temperature", (1, 1), 8, 1.0, -40.0, "°C"),
+    spn(174, "Engine Fuel Temperature 1", (2, 1), 8, 1.0, -40.0, "°C"),
+    spn(175, "Engine Oil Temperature 1", (3, 1), 16, 0.03125, -273.0, "°C"),
+];
+
+const EFL_P1: &[SpnDef] = &[
+    spn(94, "Engine Fuel Delivery Pressure", (1, 1), 8, 4.0, 0.0, "kPa"),
+    spn(98, "Engine Oil Level", (3, 1), 8, 0.4, 0.0, "%"),
+    spn(100, "Engine Oil Pressure", (4, 1), 8, 4.0, 0.0, "kPa"),
+    spn(111, "Engine Coolant Level", (8, 1), 8, 0.4, 0.0, "%"),
+];
+
+const CCVS: &[SpnDef] = &[
+    spn(69, "Two Speed Axle Switch", (1, 1), 2, 1.0, 0.0, ""),
+    spn(70, "Parking Brake Switch", (1, 3), 2, 1.0, 0.0, ""),
+    spn(1633, "Cruise Control Pause Switch", (1, 5), 2, 1.0, 0.0, ""),
+    spn(84, "Wheel-Based Vehicle Speed", (2, 1), 16, 1.0 / 256.0, 0.0, "km/h"),
+    spn(595, "Cruise Control Active", (4, 1), 2, 1.0, 0.0, ""),
+    spn(596, "Cruise Control Enable Switch", (4, 3), 2, 1.0, 0.0, ""),
+    spn(597, "Brake Switch", (4, 5), 2, 1.0, 0.0, ""),
+    spn(598, "Clutch Switch", (4, 7), 2, 1.0, 0.0, ""),
+    spn(86, "Cruise Control Set Speed", (6, 1), 8, 1.0, 0.0, "km/h"),
+    spn(976, "PTO Governor State", (7, 1), 5, 1.0, 0.0, ""),
+    spn(527, "Cruise Control States", (7, 6), 3, 1.0, 0.0, ""),
+];
+
+const LFE1: &[SpnDef] = &[
+    spn(183, "Engine Fuel Rate", (1, 1), 16, 0.05, 0.0, "L/h"),
+    spn(184, "Engine Instantaneous Fuel Economy", (3, 1), 16, 1.0 / 512.0, 0.0, "km/L"),
+    spn(51, "Engine Throttle Valve 1 Position", (7, 1), 8, 0.4, 0.0, "%"),
+];
+
+const VEP1: &[SpnDef] = &[
+    spn(114, "Net Battery Current", (1, 1), 8, 1.0, -125.0, "A"),
+    spn(115, "Alternator Current", (2, 1), 8, 1.0, 0.0, "A"),
+    spn(167, "Charging System Potential (Voltage)", (3, 1), 16, 0.05, 0.0, "V"),
+    spn(168, "Battery Potential / Power Input 1", (5, 1), 16, 0.05, 0.0, "V"),
+];
+
+const HOURS: &[SpnDef] = &[
+    spn(247, "Engine Total Hours of Operation", (1, 1), 32, 0.05, 0.0, "h"),
+    spn(249, "Engine Total Revolutions", (5, 1), 32, 1000.0, 0.0, "r"),
+];
+
+const VD: &[SpnDef] = &[
+    spn(244, "Trip Distance", (1, 1), 32, 0.125, 0.0, "km"),
+    spn(245, "Total Vehicle Distance", (5, 1), 32, 0.125, 0.0, "km"),
+];
+
+/// Known parameter groups, by PGN.
+pub const PGNS: &[PgnDef] = &[
+    PgnDef { pgn: 61443, acronym: "EEC2", name: "Electronic Engine Controller 2", spns: EEC2 },
+    PgnDef { pgn: 61444, acronym: "EEC1", name: "Electronic Engine Controller 1", spns: EEC1 },
+    PgnDef { pgn: PGN_DM1, acronym: "DM1", name: "Active Diagnostic Trouble Codes", spns: &[] },
+    PgnDef { pgn: 65248, acronym: "VD", name: "Vehicle Distance", spns: VD },
+    PgnDef { pgn: 65253, acronym: "HOURS", name: "Engine Hours, Revolutions", spns: HOURS },
+    PgnDef { pgn: 65262, acronym: "ET1", name: "Engine Temperature 1", spns: ET1 },
+    PgnDef { pgn: 65263, acronym: "EFL/P1", name: "Engine Fluid Level/Pressure 1", spns: EFL_P1 },
+    PgnDef { pgn: 65265, acronym: "CCVS", name: "Cruise Control/Vehicle Speed", spns: CCVS },
+    PgnDef { pgn: 65266, acronym: "LFE1", name: "Fuel Economy (Liquid)", spns: LFE1 },
+    PgnDef { pgn: 65271, acronym: "VEP1", name: "Vehicle Electrical Power 1", spns: VEP1 },
+];
+
+pub fn pgn_def(pgn: u32) -> Option<&'static PgnDef> {
+    PGNS.iter().find(|def| def.pgn == pgn)
+}
@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::frame::Frame;
+use crate::j1939::{J1939Id, J1939_GLOBAL};
+
+/// Transport protocol connection management (TP.CM).
+pub const PGN_TP_CM: u32 = 0xEC00;
+/// Transport protocol data transfer (TP.DT).
+pub const PGN_TP_DT: u32 = 0xEB00;
+/// Largest message the transport protocol carries: 255 packets of 7 bytes.
+pub const TP_MAX_LEN: usize = 1785;
+
+const RTS: u8 = 16;
+const CTS: u8 = 17;
+const BAM: u8 = 32;
+const ABORT: u8 = 255;
+
+/// J1939-21 timeouts: between data packets, after a CTS, after the last packet of a window
+/// (or an RTS), and after a CTS that holds the connection open.
+const T1: Duration = Duration::from_millis(750);
+const T2: Duration = Duration::from_millis(1250);
+const T3: Duration = Duration::from_millis(1250);
+const T4: Duration = Duration::from_millis(1050);
+
+/// A J1939 message with its addressing, either from one frame or reassembled from a transport
+/// protocol session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct J1939Message {
+    pub priority: u8,
+    pub pgn: u32,
+    pub source: u8,
+    /// `None` for broadcasts.
+    pub destination: Option<u8>,
+    pub data: Vec<u8>,
+    /// Whether the message came through TP.BAM or TP.CM/TP.DT rather than in a single frame.
+    pub reassembled: bool,
+}
+
+impl J1939Message {
+    /// The message in a single frame with an extended ID.
+    pub fn from_frame(frame: &Frame) -> Option<Self> {
+        let id = J1939Id::from_id(frame.id()).filter(|_| !frame.is_remote())?;
+        Some(Self {
+            priority: id.priority,
+            pgn: id.pgn,
+            source: id.source,
+            destination: id.destination.filter(|&destination| destination != J1939_GLOBAL),
+            data: frame.data().to_vec(),
+            reassembled: false,
+        })
+    }
+
+    pub fn id(&self) -> J1939Id {
+        // PDU1 groups carry the destination in place of the PGN's low byte.
+        let destination = (self.pgn >> 8 & 0xFF < 240).then_some(self.destination.unwrap_or(J1939_GLOBAL));
+        J1939Id { priority: self.priority, pgn: self.pgn, destination, source: self.source }
+    }
+}
+
+/// Why a transport session ended without a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TpFailureReason {
+    /// A J1939-21 timer (`"T1"` to `"T4"`) ran out.
+    Timeout { timer: &'static str },
+    /// One side sent a Connection Abort with this reason code.
+    Aborted { reason: u8 },
+    /// A new BAM or RTS between the same nodes arrived before this session finished.
+    Superseded,
+    /// A data packet outside the announced packets or the current CTS window.
+    BadSequence { seq: u8 },
+    /// The announcement's size doesn't match its packet count or exceeds [`TP_MAX_LEN`].
+    Invalid,
+}
+
+impl fmt::Display for TpFailureReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout { timer } => write!(f, "timed out ({timer})"),
+            Self::Aborted { reason } => write!(f, "aborted with reason {reason}"),
+            Self::Superseded => write!(f, "replaced by a new session"),
+            Self::BadSequence { seq } => write!(f, "unexpected packet {seq}"),
+            Self::Invalid => write!(f, "invalid announcement"),
+        }
+    }
+}
+
+/// A transport session that ended incomplete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TpFailure {
+    pub pgn: u32,
+    pub source: u8,
+    /// `None` for BAM sessions.
+    pub destination: Option<u8>,
+    pub received: u8,
+    pub packets: u8,
+    pub reason: TpFailureReason,
+}
+
+impl fmt::Display for TpFailure {
+    /// `TP PGN 65226 SA 0x00 -> global: 2 of 3 packets, timed out (T1)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TP PGN {} SA 0x{:02X} -> ", self.pgn, self.source)?;
+        match self.destination {
+            Some(destination) => write!(f, "DA 0x{destination:02X}")?,
+            None => write!(f, "global")?,
+        }
+        write!(f, ": {} of {} packets, {}", self.received, self.packets, self.reason)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TpEvent {
+    Message(J1939Message),
+    Failed(TpFailure),
+}
+
+struct Session {
+    pgn: u32,
+    priority: u8,
+    size: usize,
+    /// Packet data by sequence number - 1, in whatever order the packets arrived.
+    packets: Vec<Option<[u8; 7]>>,
+    received: u8,
+    /// Packets the receiver's last CTS allowed, `next..next + count`; `None` for BAM.
+    window: Option<(u8, u8)>,
+    deadline: Instant,
+    timer: &'static str,
+}
+
+impl Session {
+    fn expect(&mut self, timer: &'static str, timeout: Duration, now: Instant) {
+        self.timer = timer;
+        self.deadline = now + timeout;
+    }
+
+    fn failure(&self, (source, destination): (u8, u8), reason: TpFailureReason) -> TpFailure {
+        TpFailure {
+            pgn: self.pgn,
+            source,
+            destination: (destination != J1939_GLOBAL).then_some(destination),
+            received: self.received,
+            packets: self.packets.len() as u8,
+            reason,
+        }
+    }
+}
+
+/// Reassembles J1939 transport protocol messages seen on the bus: broadcasts (TP.BAM) and
+/// connection mode transfers (RTS/CTS) between other nodes, any number at once, keyed by
+/// source and destination. Data packets may arrive in any order within what was announced
+/// or allowed. Only observes: it never sends CTS or aborts itself.
+///
+/// Timeouts are checked whenever a frame is pushed, or on [`TpReassembler::expire`].
+#[derive(Default)]
+pub struct TpReassembler {
+    sessions: HashMap<(u8, u8), Session>,
+}
+
+impl TpReassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds one received frame, returning messages it completed and sessions that failed,
+    /// including ones whose timer ran out by `now`. Frames other than TP.CM and TP.DT are
+    /// ignored.
+    pub fn push(&mut self, frame: &Frame, now: Instant) -> Vec<TpEvent> {
+        let mut events = self.expire(now);
+        let Some(id) = J1939Id::from_id(frame.id()).filter(|_| !frame.is_remote()) else {
+            return events;
+        };
+        let (source, destination) = (id.source, id.destination.unwrap_or(J1939_GLOBAL));
+        let data = frame.data();
+        match (id.pgn, data) {
+            (PGN_TP_CM, [control, rest @ ..]) if rest.len() >= 7 => {
+                let pgn = u32::from_le_bytes([rest[4], rest[5], rest[6], 0]);
+                match *control {
+                    BAM | RTS => {
+                        let key = (source, if *control == BAM { J1939_GLOBAL } else { destination });
+                        if let Some(old) = self.sessions.remove(&key) {
+                            events.push(TpEvent::Failed(old.failure(key, TpFailureReason::Superseded)));
+                        }
+                        let size = usize::from(u16::from_le_bytes([rest[0], rest[1]]));
+                        let packets = usize::from(rest[2]);
+                        let session = Session {
+                            pgn,
+                            priority: id.priority,
+                            size,
+                            packets: vec![None; packets],
+                            received: 0,
+                            window: (*control == RTS).then_some((1, 0)),
+                            deadline: now + if *control == BAM { T1 } else { T3 },
+                            timer: if *control == BAM { "T1" } else { "T3" },
+                        };
+                        if size <= 8 || size > TP_MAX_LEN || packets != size.div_ceil(7) {
+                            events.push(TpEvent::Failed(session.failure(key, TpFailureReason::Invalid)));
+                        } else {
+                            self.sessions.insert(key, session);
+                        }
+                    }
+                    // From the receiver, so the session is keyed the other way round.
+                    CTS => {
+                        if let Some(session) = self.sessions.get_mut(&(destination, source)) {
+                            let (count, next) = (rest[0], rest[1]);
+                            session.window = Some((next, count));
+                            if count == 0 {
+                                session.expect("T4", T4, now);
+                            } else {
+                                session.expect("T2", T2, now);
+                            }
+                        }
+                    }
+                    ABORT => {
+                        let key = [(source, destination), (destination, source)]
+                            .into_iter()
+                            .find(|key| self.sessions.get(key).is_some_and(|session| session.pgn == pgn));
+                        if let Some(key) = key {
+                            let session = self.sessions.remove(&key).expect("found above");
+                            events.push(TpEvent::Failed(session.failure(key, TpFailureReason::Aborted { reason: rest[0] })));
+                        }
+                    }
+                    // End of message acks need nothing: sessions complete on their last data packet.
+                    _ => {}
+                }
+            }
+            (PGN_TP_DT, [seq, payload @ ..]) => {
+                let key = (source, destination);
+                let Some(session) = self.sessions.get_mut(&key) else {
+                    return events;
+                };
+                let in_window = match session.window {
+                    Some((next, count)) => (next..next.saturating_add(count)).contains(seq),
+                    None => true,
+                };
+                if *seq == 0 || usize::from(*seq) > session.packets.len() || !in_window {
+                    let session = self.sessions.remove(&key).expect("looked up above");
+                    events.push(TpEvent::Failed(session.failure(key, TpFailureReason::BadSequence { seq: *seq })));
+                    return events;
+                }
+                let slot = &mut session.packets[usize::from(*seq) - 1];
+                if slot.is_none() {
+                    let mut packet = [0xFF; 7];
+                    let len = payload.len().min(7);
+                    packet[..len].copy_from_slice(&payload[..len]);
+                    *slot = Some(packet);
+                    session.received += 1;
+                }
+                if usize::from(session.received) == session.packets.len() {
+                    let session = self.sessions.remove(&key).expect("looked up above");
+                    let mut data: Vec<u8> = session.packets.iter().flatten().flatten().copied().collect();
+                    data.truncate(session.size);
+                    events.push(TpEvent::Message(J1939Message {
+                        priority: session.priority,
+                        pgn: session.pgn,
+                        source,
+                        destination: (destination != J1939_GLOBAL).then_some(destination),
+                        data,
+                        reassembled: true,
+                    }));
+                } else {
+                    let window_done = session.window.is_some_and(|(next, count)| {
+                        (next..next.saturating_add(count))
+                            .filter_map(|seq| session.packets.get(usize::from(seq).wrapping_sub(1)))
+                            .all(Option::is_some)
+                    });
+                    if window_done {
+                        session.expect("T3", T3, now);
+                    } else {
+                        session.expect("T1", T1, now);
+                    }
+                }
+            }
+            _ => {}
+        }
+        events
+    }
+
+    /// Ends sessions whose timer ran out by `now`.
+    pub fn expire(&mut self, now: Instant) -> Vec<TpEvent> {
+        let expired: Vec<(u8, u8)> =
+            self.sessions.iter().filter(|(_, session)| session.deadline <= now).map(|(&key, _)| key).collect();
+        expired
+            .into_iter()
+            .map(|key| {
+                let session = self.sessions.remove(&key).expect("collected above");
+                TpEvent::Failed(session.failure(key, TpFailureReason::Timeout { timer: session.timer }))
+            })
+            .collect()
+    }
+
+    /// Sessions in progress.
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::Id;
+    use crate::j1939::Dm1;
+    use crate::j1939_pgns::PGN_DM1;
+
+    /// Component Identification, a typical multi-packet broadcast.
+    const PGN_COMPONENT_ID: u32 = 65259;
+
+    fn frame(pgn: u32, destination: u8, source: u8, data: &[u8]) -> Frame {
+        let id = J1939Id { priority: 7, pgn, destination: Some(destination), source };
+        Frame::new(id.to_id(), data).unwrap()
+    }
+
+    fn cm(source: u8, destination: u8, control: u8, args: [u8; 4], pgn: u32) -> Frame {
+        let [low, mid, high, _] = pgn.to_le_bytes();
+        frame(PGN_TP_CM, destination, source, &[control, args[0], args[1], args[2], args[3], low, mid, high])
+    }
+
+    /// The announcement and data packets of a BAM of `data` from `source`.
+    fn bam(source: u8, pgn: u32, data: &[u8]) -> Vec<Frame> {
+        let [size_low, size_high] = (data.len() as u16).to_le_bytes();
+        let packets = data.len().div_ceil(7);
+        let mut frames = vec![cm(source, J1939_GLOBAL, BAM, [size_low, size_high, packets as u8, 0xFF], pgn)];
+        for (index, chunk) in data.chunks(7).enumerate() {
+            let mut packet = [0xFF; 8];
+            packet[0] = index as u8 + 1;
+            packet[1..=chunk.len()].copy_from_slice(chunk);
+            frames.push(frame(PGN_TP_DT, J1939_GLOBAL, source, &packet));
+        }
+        frames
+    }
+
+    fn feed(reassembler: &mut TpReassembler, frames: &[Frame], start: Instant, step: Duration) -> Vec<TpEvent> {
+        frames.iter().enumerate().flat_map(|(n, frame)| reassembler.push(frame, start + step * n as u32)).collect()
+    }
+
+    fn message(source: u8, pgn: u32, data: &[u8]) -> TpEvent {
+        TpEvent::Message(J1939Message { priority: 7, pgn, source, destination: None, data: data.to_vec(), reassembled: true })
+    }
+
+    /// DM1 with three DTCs: 14 bytes, 2 packets.
+    const DM1: [u8; 14] = [0x04, 0xFF, 0x6E, 0x00, 0x00, 0x05, 0xBE, 0x00, 0x03, 0x01, 0x64, 0x00, 0x01, 0x02];
+
+    fn component_id() -> Vec<u8> {
+        b"VOLVO*D13K500*2226118*1234567*".to_vec()
+    }
+
+    /// Takes frames from each list in turn.
+    fn interleave(a: &[Frame], b: &[Frame]) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        for n in 0..a.len().max(b.len()) {
+            frames.extend(a.get(n));
+            frames.extend(b.get(n));
+        }
+        frames
+    }
+
+    #[test]
+    fn interleaved_bams_from_two_sources() {
+        let engine = bam(0x00, PGN_DM1, &DM1);
+        let transmission = bam(0x03, PGN_COMPONENT_ID, &component_id());
+        let mut frames = interleave(&engine, &transmission);
+        // Ordinary traffic in between is left alone.
+        frames.insert(3, Frame::new(Id::Extended(0x0CF0_0400), &[0xF0, 0x7D, 0x82, 0x3C, 0x11, 0xFF, 0xFF, 0x7D]).unwrap());
+
+        let mut reassembler = TpReassembler::new();
+        let events = feed(&mut reassembler, &frames, Instant::now(), Duration::from_millis(50));
+        assert_eq!(events, [message(0x00, PGN_DM1, &DM1), message(0x03, PGN_COMPONENT_ID, &component_id())]);
+        assert!(reassembler.is_empty());
+
+        let TpEvent::Message(dm1) = &events[0] else { unreachable!() };
+        assert_eq!(Dm1::parse(&dm1.data).unwrap().dtcs.len(), 3);
+        assert_eq!(dm1.id().to_string(), "PGN 65226 DM1 SA 0x00 P7");
+    }
+
+    #[test]
+    fn interleaved_bams_of_one_pgn_with_packets_out_of_order() {
+        let first = bam(0x00, PGN_COMPONENT_ID, &component_id());
+        let second_data: Vec<u8> = (0..20).collect();
+        let mut second = bam(0x21, PGN_COMPONENT_ID, &second_data);
+        second[1..].reverse();
+
+        let mut reassembler = TpReassembler::new();
+        let events = feed(&mut reassembler, &interleave(&second, &first), Instant::now(), Duration::from_millis(20));
+        assert_eq!(events, [message(0x21, PGN_COMPONENT_ID, &second_data), message(0x00, PGN_COMPONENT_ID, &component_id())]);
+    }
+
+    #[test]
+    fn one_stalled_bam_times_out_while_the_other_completes() {
+        let engine = bam(0x00, PGN_DM1, &component_id());
+        let transmission = bam(0x03, PGN_COMPONENT_ID, &component_id());
+        // The engine stops after its second packet.
+        let frames = interleave(&engine[..3], &transmission);
+        let start = Instant::now();
+        let mut reassembler = TpReassembler::new();
+        let events = feed(&mut reassembler, &frames, start, Duration::from_millis(100));
+        assert_eq!(events, [message(0x03, PGN_COMPONENT_ID, &component_id())]);
+        assert_eq!(reassembler.len(), 1);
+
+        let last_packet = start + Duration::from_millis(100 * 4);
+        assert!(reassembler.expire(last_packet + T1 - Duration::from_millis(1)).is_empty());
+        let failure = TpFailure { pgn: PGN_DM1, source: 0x00, destination: None, received: 2, packets: 5, reason: TpFailureReason::Timeout { timer: "T1" } };
+        assert_eq!(reassembler.expire(last_packet + T1), [TpEvent::Failed(failure)]);
+        assert_eq!(failure.to_string(), "TP PGN 65226 SA 0x00 -> global: 2 of 5 packets, timed out (T1)");
+    }
+
+    #[test]
+    fn a_new_bam_from_the_same_source_supersedes_the_old() {
+        let now = Instant::now();
+        let mut reassembler = TpReassembler::new();
+        let old = bam(0x00, PGN_COMPONENT_ID, &component_id());
+        assert!(feed(&mut reassembler, &old[..2], now, Duration::ZERO).is_empty());
+        let events = feed(&mut reassembler, &bam(0x00, PGN_DM1, &DM1), now, Duration::ZERO);
+        assert_eq!(events[1..], [message(0x00, PGN_DM1, &DM1)]);
+        assert!(matches!(events[0], TpEvent::Failed(TpFailure { pgn: PGN_COMPONENT_ID, received: 1, reason: TpFailureReason::Superseded, .. })));
+    }
+
+    #[test]
+    fn bad_packets_and_announcements_fail_the_session() {
+        let now = Instant::now();
+        let mut reassembler = TpReassembler::new();
+        let frames = bam(0x00, PGN_DM1, &DM1);
+        reassembler.push(&frames[0], now);
+        let events = reassembler.push(&frame(PGN_TP_DT, J1939_GLOBAL, 0x00, &[3, 0, 0, 0, 0, 0, 0, 0]), now);
+        assert!(matches!(events[..], [TpEvent::Failed(TpFailure { reason: TpFailureReason::BadSequence { seq: 3 }, .. })]), "{events:?}");
+
+        for announcement in [
+            cm(0x00, J1939_GLOBAL, BAM, [14, 0, 3, 0xFF], PGN_DM1),
+            cm(0x00, J1939_GLOBAL, BAM, [8, 0, 2, 0xFF], PGN_DM1),
+            cm(0x00, J1939_GLOBAL, BAM, [0xFF, 0x06, 255, 0xFF], PGN_DM1),
+        ] {
+            let events = reassembler.push(&announcement, now);
+            assert!(matches!(events[..], [TpEvent::Failed(TpFailure { reason: TpFailureReason::Invalid, .. })]), "{events:?}");
+        }
+        assert!(reassembler.is_empty());
+        // Data packets outside any session are ignored.
+        assert!(reassembler.push(&frames[1], now).is_empty());
+    }
+
+    #[test]
+    fn rts_cts_sessions_follow_the_windows() {
+        let data = component_id();
+        let now = Instant::now();
+        let mut reassembler = TpReassembler::new();
+        let packet = |seq: u8| {
+            let mut pdu = [0xFF; 8];
+            pdu[0] = seq;
+            let chunk = &data[usize::from(seq - 1) * 7..(usize::from(seq) * 7).min(data.len())];
+            pdu[1..=chunk.len()].copy_from_slice(chunk);
+            frame(PGN_TP_DT, 0x00, 0xF9, &pdu)
+        };
+        assert!(reassembler.push(&cm(0xF9, 0x00, RTS, [30, 0, 5, 2], PGN_COMPONENT_ID), now).is_empty());
+        // The engine lets two packets through, then holds the connection, then asks for the rest.
+        reassembler.push(&cm(0x00, 0xF9, CTS, [2, 1, 0xFF, 0xFF], PGN_COMPONENT_ID), now);
+        assert!(reassembler.push(&packet(2), now).is_empty());
+        assert!(reassembler.push(&packet(1), now).is_empty());
+        reassembler.push(&cm(0x00, 0xF9, CTS, [0, 0xFF, 0xFF, 0xFF], PGN_COMPONENT_ID), now);
+        assert!(reassembler.expire(now + T4 - Duration::from_millis(1)).is_empty(), "held open for T4");
+        reassembler.push(&cm(0x00, 0xF9, CTS, [3, 3, 0xFF, 0xFF], PGN_COMPONENT_ID), now + Duration::from_secs(1));
+        let mut events = Vec::new();
+        for seq in [3, 5, 4] {
+            events.extend(reassembler.push(&packet(seq), now + Duration::from_secs(1)));
+        }
+        let expected = J1939Message { priority: 7, pgn: PGN_COMPONENT_ID, source: 0xF9, destination: Some(0x00), data: data.clone(), reassembled: true };
+        assert_eq!(events, [TpEvent::Message(expected)]);
+
+        // A packet the CTS didn't ask for, and an abort from the receiver.
+        reassembler.push(&cm(0xF9, 0x00, RTS, [30, 0, 5, 2], PGN_COMPONENT_ID), now);
+        reassembler.push(&cm(0x00, 0xF9, CTS, [2, 1, 0xFF, 0xFF], PGN_COMPONENT_ID), now);
+        let events = reassembler.push(&packet(3), now);
+        assert!(matches!(events[..], [TpEvent::Failed(TpFailure { reason: TpFailureReason::BadSequence { seq: 3 }, destination: Some(0x00), .. })]));
+        reassembler.push(&cm(0xF9, 0x00, RTS, [30, 0, 5, 2], PGN_COMPONENT_ID), now);
+        let events = reassembler.push(&cm(0x00, 0xF9, ABORT, [3, 0xFF, 0xFF, 0xFF], PGN_COMPONENT_ID), now);
+        let TpEvent::Failed(failure) = events[0] else { panic!("{events:?}") };
+        assert_eq!(failure.to_string(), "TP PGN 65259 SA 0xF9 -> DA 0x00: 0 of 5 packets, aborted with reason 3");
+        // No CTS ever comes: T3 runs out.
+        reassembler.push(&cm(0xF9, 0x00, RTS, [30, 0, 5, 2], PGN_COMPONENT_ID), now);
+        assert!(matches!(reassembler.expire(now + T3)[..], [TpEvent::Failed(TpFailure { reason: TpFailureReason::Timeout { timer: "T3" }, .. })]));
+    }
+}
@@ -0,0 +1,190 @@
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::faults::FaultEvent;
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::j1939_tp::J1939Message;
+use crate::sink::{Direction, FrameSink};
+use crate::timestamp::host_time;
+
+/// One frame of `--output json`, e.g.
+/// `{"ts":1699999999.123456,"ch":0,"id":"0x123","ext":false,"rtr":false,"dlc":3,"data":"0A0B0C"}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonFrame {
+    /// Seconds since the Unix epoch, microsecond resolution.
+    pub ts: f64,
+    pub ch: u32,
+    pub id: String,
+    pub ext: bool,
+    pub rtr: bool,
+    pub dlc: u8,
+    /// Payload as uppercase hex, empty for remote frames.
+    pub data: String,
+}
+
+impl JsonFrame {
+    pub fn new(time: SystemTime, channel: u32, frame: &Frame) -> Self {
+        let ts = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as f64 / 1e6;
+        Self {
+            ts,
+            ch: channel,
+            id: format!("0x{:X}", frame.id().raw()),
+            ext: frame.is_extended(),
+            rtr: frame.is_remote(),
+            dlc: frame.dlc(),
+            data: frame.data().iter().map(|b| format!("{b:02X}")).collect(),
+        }
+    }
+
+    /// Rebuilds the frame; the JSON timestamp is not carried over.
+    pub fn to_frame(&self) -> Result<Frame, String> {
+        let digits = self.id.trim_start_matches("0x").trim_start_matches("0X");
+        let raw = u32::from_str_radix(digits, 16).map_err(|_| format!("invalid CAN ID '{}'", self.id))?;
+        let id = if self.ext { Id::extended(raw) } else { u16::try_from(raw).ok().and_then(Id::standard) }
+            .ok_or_else(|| format!("invalid CAN ID '{}'", self.id))?;
+        if self.rtr {
+            return Frame::remote(id, self.dlc).ok_or_else(|| format!("invalid DLC {}", self.dlc));
+        }
+        if !self.data.is_ascii() || !self.data.len().is_multiple_of(2) {
+            return Err(format!("invalid hex data '{}'", self.data));
+        }
+        let bytes = (0..self.data.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&self.data[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| format!("invalid hex data '{}'", self.data))?;
+        Frame::new(id, &bytes).ok_or_else(|| format!("'{}' is longer than 8 bytes", self.data))
+    }
+}
+
+/// A frame a client asks to send, e.g. `{"id":"0x123","data":"0A0B0C"}` or
+/// `{"id":"0x1ABCDEF","ext":true,"rtr":true,"dlc":4}`. A received [`JsonFrame`] reads as one too.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct JsonTransmit {
+    pub id: String,
+    #[serde(default)]
+    pub ext: bool,
+    #[serde(default)]
+    pub rtr: bool,
+    /// Only read for remote frames.
+    #[serde(default)]
+    pub dlc: u8,
+    #[serde(default)]
+    pub data: String,
+}
+
+impl JsonTransmit {
+    pub fn to_frame(&self) -> Result<Frame, String> {
+        let JsonTransmit { id, ext, rtr, dlc, data } = self.clone();
+        JsonFrame { ts: 0.0, ch: 0, id, ext, rtr, dlc, data }.to_frame()
+    }
+}
+
+/// A J1939 message reassembled from transport protocol frames, written alongside the frames
+/// that carried it, e.g.
+/// `{"ts":1699999999.123456,"ch":0,"id":"0x18FECA00","pgn":65226,"sa":0,"da":null,"len":10,"data":"...","reassembled":true}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonMessage {
+    pub ts: f64,
+    pub ch: u32,
+    /// The ID a single frame of this message would have.
+    pub id: String,
+    pub pgn: u32,
+    pub sa: u8,
+    pub da: Option<u8>,
+    pub len: usize,
+    pub data: String,
+    pub reassembled: bool,
+}
+
+impl JsonMessage {
+    pub fn new(time: SystemTime, channel: u32, message: &J1939Message) -> Self {
+        Self {
+            ts: time.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as f64 / 1e6,
+            ch: channel,
+            id: format!("0x{:X}", message.id().to_id().raw()),
+            pgn: message.pgn,
+            sa: message.source,
+            da: message.destination,
+            len: message.data.len(),
+            data: message.data.iter().map(|b| format!("{b:02X}")).collect(),
+            reassembled: message.reassembled,
+        }
+    }
+}
+
+/// A channel entering or leaving error-passive or bus-off, written among the frames, e.g.
+/// `{"ts":1699999999.123456,"ch":0,"fault":"bus-off","event":"entered","count":2,"recovery_ms":null}`.
+/// `count` is how many times the channel has entered the fault so far.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonFault {
+    pub ts: f64,
+    pub ch: u32,
+    pub fault: String,
+    /// `entered` or `recovered`.
+    pub event: String,
+    pub count: u64,
+    /// How long the channel was in the fault, on `recovered`.
+    pub recovery_ms: Option<f64>,
+}
+
+impl JsonFault {
+    pub fn new(channel: u32, event: &FaultEvent, count: u64) -> Self {
+        let (at, kind, recovery) = match *event {
+            FaultEvent::Entered { at, .. } => (at, "entered", None),
+            FaultEvent::Recovered { at, after, .. } => (at, "recovered", Some(after.as_micros() as f64 / 1000.0)),
+        };
+        Self {
+            ts: at.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as f64 / 1e6,
+            ch: channel,
+            fault: event.fault().to_string(),
+            event: kind.to_string(),
+            count,
+            recovery_ms: recovery,
+        }
+    }
+}
+
+/// Newline-delimited JSON, one [`JsonFrame`] per line. Each line is written with a single
+/// `write_all` and flushed, so a pipe reader never sees a partial object.
+pub struct JsonWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+        }
+    }
+
+    /// Writes a [`JsonMessage`] line, stamped now.
+    pub fn write_message(&mut self, channel: u32, message: &J1939Message) -> io::Result<()> {
+        self.write_line(&JsonMessage::new(SystemTime::now(), channel, message))
+    }
+
+    /// Writes a [`JsonFault`] line.
+    pub fn write_fault(&mut self, channel: u32, event: &FaultEvent, count: u64) -> io::Result<()> {
+        self.write_line(&JsonFault::new(channel, event, count))
+    }
+
+    fn write_line(&mut self, record: &impl Serialize) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        self.out.write_all(&line)?;
+        self.out.flush()
+    }
+}
+
+impl<W: Write + Send> FrameSink for JsonWriter<W> {
+    fn write_frame(&mut self, channel: u32, frame: &Frame, _direction: Direction) -> io::Result<()> {
+        self.write_line(&JsonFrame::new(host_time(frame), channel, frame))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
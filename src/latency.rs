@@ -0,0 +1,198 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::device::Channel;
+use crate::error::CanError;
+use crate::frame::Frame;
+use crate::id::Id;
+
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(10);
+const RECEIVE_BATCH: usize = 256;
+
+/// Round-trip measurement: `count` probe frames sent on one channel at a fixed interval and
+/// picked up on the other. Each probe carries its sequence number and the host send time
+/// (microseconds since the test started), both little-endian `u32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyTest {
+    pub id: Id,
+    pub count: u32,
+    pub interval: Duration,
+    /// How long to keep receiving after the last probe went out.
+    pub drain: Duration,
+}
+
+/// One probe that made it onto the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySample {
+    pub seq: u32,
+    /// Host send time, relative to the start of the test.
+    pub sent_at: Duration,
+    /// `None` if the probe never arrived.
+    pub latency: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyReport {
+    /// Probes transmitted successfully, in sequence order.
+    pub samples: Vec<LatencySample>,
+    /// Probes the DLL refused to send.
+    pub send_failures: u32,
+    /// Probes received more than once.
+    pub duplicates: u32,
+    /// Probes that arrived after one with a higher sequence number.
+    pub reordered: u32,
+}
+
+impl LatencyTest {
+    /// Sends the probes on `tx` and collects them on `rx`. Clearing `running` stops both sides
+    /// early; the report covers what was sent until then.
+    pub fn run(&self, tx: &Channel, rx: &Channel, running: &AtomicBool) -> Result<LatencyReport, CanError> {
+        let start = Instant::now();
+        let (sending, receiving) = (AtomicBool::new(true), AtomicBool::new(true));
+        thread::scope(|scope| {
+            let sender = scope.spawn(|| {
+                let sent = self.send(tx, start, || running.load(Ordering::SeqCst) && receiving.load(Ordering::SeqCst));
+                sending.store(false, Ordering::SeqCst);
+                sent
+            });
+            let received = self.receive(rx, start, running, &sending);
+            receiving.store(false, Ordering::SeqCst);
+            let (sent, send_failures) = sender.join().expect("latency sender panicked");
+            let Arrivals { latencies, duplicates, reordered } = received?;
+            let samples = sent
+                .into_iter()
+                .map(|(seq, sent_at)| LatencySample { seq, sent_at, latency: latencies[seq as usize] })
+                .collect();
+            Ok(LatencyReport { samples, send_failures, duplicates, reordered })
+        })
+    }
+
+    fn send(&self, tx: &Channel, start: Instant, keep_going: impl Fn() -> bool) -> (Vec<(u32, Duration)>, u32) {
+        let mut sent = Vec::with_capacity(self.count as usize);
+        let mut failures = 0;
+        for seq in 0..self.count {
+            if !keep_going() {
+                break;
+            }
+            let due = start + self.interval * seq;
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+            let sent_at = start.elapsed();
+            match tx.transmit(&probe(self.id, seq, sent_at)) {
+                Ok(()) => sent.push((seq, sent_at)),
+                Err(_) => failures += 1,
+            }
+        }
+        (sent, failures)
+    }
+
+    /// Collects probes until all have arrived, or `drain` after the sender finished. Latency is
+    /// taken from the send time in the payload, so probes can be matched in any order.
+    fn receive(&self, rx: &Channel, start: Instant, running: &AtomicBool, sending: &AtomicBool) -> Result<Arrivals, CanError> {
+        let mut arrivals = Arrivals { latencies: vec![None; self.count as usize], duplicates: 0, reordered: 0 };
+        let mut received = 0;
+        let mut highest = None;
+        let mut done_at = None;
+        while running.load(Ordering::SeqCst) && received < self.count {
+            if done_at.is_none() && !sending.load(Ordering::SeqCst) {
+                done_at = Some(Instant::now());
+            }
+            if done_at.is_some_and(|at| at.elapsed() >= self.drain) {
+                break;
+            }
+            let frames = rx.receive_pending(RECEIVE_BATCH, RECEIVE_TIMEOUT)?;
+            let now = start.elapsed().as_micros() as u32;
+            for frame in frames.iter().filter(|frame| frame.id() == self.id) {
+                let Some((seq, sent)) = parse_probe(frame).filter(|&(seq, _)| seq < self.count) else {
+                    continue;
+                };
+                let slot = &mut arrivals.latencies[seq as usize];
+                if slot.is_some() {
+                    arrivals.duplicates += 1;
+                    continue;
+                }
+                *slot = Some(Duration::from_micros(now.wrapping_sub(sent).into()));
+                received += 1;
+                if highest.is_some_and(|highest| seq < highest) {
+                    arrivals.reordered += 1;
+                }
+                highest = highest.max(Some(seq));
+            }
+        }
+        Ok(arrivals)
+    }
+}
+
+struct Arrivals {
+    /// Latency per sequence number.
+    latencies: Vec<Option<Duration>>,
+    duplicates: u32,
+    reordered: u32,
+}
+
+fn probe(id: Id, seq: u32, sent_at: Duration) -> Frame {
+    let mut data = [0u8; 8];
+    data[..4].copy_from_slice(&seq.to_le_bytes());
+    data[4..].copy_from_slice(&(sent_at.as_micros() as u32).to_le_bytes());
+    Frame::new(id, &data).expect("8 byte payload")
+}
+
+/// Sequence number and send time in microseconds from a probe frame.
+fn parse_probe(frame: &Frame) -> Option<(u32, u32)> {
+    let data: &[u8; 8] = frame.data().try_into().ok()?;
+    let seq = u32::from_le_bytes(data[..4].try_into().unwrap());
+    let sent = u32::from_le_bytes(data[4..].try_into().unwrap());
+    Some((seq, sent))
+}
+
+impl LatencyReport {
+    pub fn sent(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn received(&self) -> usize {
+        self.samples.iter().filter(|s| s.latency.is_some()).count()
+    }
+
+    pub fn lost(&self) -> usize {
+        self.sent() - self.received()
+    }
+
+    /// Lost probes as a percentage of those sent.
+    pub fn loss_percent(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.lost() as f64 * 100.0 / self.sent() as f64
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.iter().filter_map(|s| s.latency).min()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.iter().filter_map(|s| s.latency).max()
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        let received = self.received();
+        if received == 0 {
+            return None;
+        }
+        let total: Duration = self.samples.iter().filter_map(|s| s.latency).sum();
+        Some(total / received as u32)
+    }
+
+    /// Nearest-rank percentile of the received latencies, `percent` in 0..=100.
+    pub fn percentile(&self, percent: f64) -> Option<Duration> {
+        let mut latencies: Vec<Duration> = self.samples.iter().filter_map(|s| s.latency).collect();
+        latencies.sort();
+        if latencies.is_empty() {
+            return None;
+        }
+        let rank = (percent.clamp(0.0, 100.0) / 100.0 * latencies.len() as f64).ceil() as usize;
+        Some(latencies[rank.clamp(1, latencies.len()) - 1])
+    }
+}
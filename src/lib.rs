@@ -0,0 +1,177 @@
+mod acceptance;
+mod align;
+mod asc;
+mod autobaud;
+mod backend;
+mod benchmark;
+mod bitrate;
+mod board;
+mod busload;
+mod candump;
+mod canopen;
+mod capture;
+mod checksum;
+mod compress;
+mod csv;
+mod dbc;
+mod device;
+mod discovery;
+#[cfg(feature = "embedded-can")]
+mod embedded;
+mod error;
+mod fanout;
+mod faults;
+mod ffi;
+mod filter;
+mod frame;
+mod fuzz;
+mod gateway;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod handle;
+mod id;
+mod idstats;
+mod integrity;
+mod isotp;
+mod j1939;
+mod j1939_pgns;
+mod j1939_tp;
+mod json;
+mod latency;
+mod mdf;
+mod metrics;
+mod mock;
+mod mode;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod names;
+mod nmea2000;
+mod obd;
+mod pcap;
+mod plot;
+mod plugin;
+mod polling;
+mod processor;
+mod processors;
+mod reconnect;
+mod recovery;
+mod reference;
+mod replay;
+mod responder;
+mod retry;
+mod rotate;
+mod rules;
+mod scheduler;
+#[cfg(feature = "scripting")]
+mod script;
+mod selftest;
+mod shaper;
+mod sink;
+mod slcan;
+mod socketcand;
+#[cfg(all(feature = "socketcan-compat", target_os = "linux"))]
+mod socketcan_compat;
+mod status;
+#[cfg(feature = "async")]
+mod stream;
+mod timestamp;
+mod tracker;
+mod trc;
+mod tx_table;
+mod uds;
+mod watchdog;
+mod ws;
+
+pub use acceptance::{AcceptanceFilter, FilterBuilder, FrameKinds};
+pub use align::{AlignmentMode, AlignmentReport, ChannelAlignment};
+pub use asc::{format_asc_line, AscWriter};
+pub use autobaud::{AutoBaud, BaudDetection};
+pub use backend::Backend;
+pub use benchmark::{Benchmark, BenchmarkResult};
+pub use bitrate::{calc_btr, Bitrate, BtrError};
+pub use board::{format_version, BoardInfo};
+pub use busload::{frame_bits, BusLoad, BUS_LOAD_WINDOW};
+pub use candump::{
+    format_candump, parse_candump_frame, parse_candump_line, parse_frame_spec, read_candump, CandumpLog,
+    CandumpRecord, CandumpWriter,
+};
+pub use canopen::{
+    parse_heartbeat, HeartbeatMonitor, NmtCommand, NmtState, NodeEvent, NodeStatus, SdoAbort, SdoClient, SdoError,
+};
+pub use capture::{CaptureConfig, CaptureEvent, SinkFactory, Trigger, TriggeredCapture};
+pub use checksum::{crc8_sae_j1850, Checksum};
+pub use compress::{CompressedWriter, Compression};
+pub use csv::{format_csv_row, CsvWriter};
+pub use dbc::{encode_signals, ByteOrder, Dbc, DbcError, Message, Multiplex, OutOfRange, Signal, SignalValue};
+pub use device::{Channel, Device, CHANNEL_COUNT};
+pub use discovery::{DiscoveredId, Discovery};
+pub use error::CanError;
+pub use fanout::Subscription;
+pub use faults::{Fault, FaultAction, FaultAlert, FaultEvent, FaultRecord, FaultTracker};
+pub use ffi::{CanLibrary, VciBoardInfo, VciCanStatus, VciErrInfo, VciInitConfig};
+pub use filter::{parse_filters, parse_id_match, FilterTerm, IdMatch, IdPattern, IdSet, SoftwareFilter};
+pub use frame::{Frame, SendType};
+pub use fuzz::{FuzzConfig, Fuzzer};
+pub use gateway::{Gateway, GatewayStats};
+#[cfg(feature = "grpc")]
+pub use grpc::{proto, GrpcServer};
+pub use handle::{ChannelHandle, ChannelState};
+pub use id::Id;
+pub use idstats::IdStats;
+pub use integrity::{ChecksumField, CounterField, IntegrityChecker, IntegrityEvent, IntegritySpec, IntegrityStats};
+pub use isotp::{Addressing, IsoTpConfig, IsoTpError, IsoTpSocket, ISOTP_MAX_LEN};
+pub use j1939::{decode_spns, Dm1, Dtc, J1939Id, Lamps, SpnReading, SpnValue, J1939_GLOBAL};
+pub use j1939_pgns::{pgn_def, PgnDef, SpnDef, PGNS, PGN_DM1};
+pub use j1939_tp::{
+    J1939Message, TpEvent, TpFailure, TpFailureReason, TpReassembler, PGN_TP_CM, PGN_TP_DT, TP_MAX_LEN,
+};
+pub use json::{JsonFault, JsonFrame, JsonMessage, JsonTransmit, JsonWriter};
+pub use latency::{LatencyReport, LatencySample, LatencyTest};
+pub use mdf::MdfWriter;
+pub use metrics::{MetricKind, Metrics, MetricsServer, Sample, METRICS_PORT};
+pub use mock::{MockBackend, MockCall};
+pub use mode::ChannelMode;
+#[cfg(feature = "mqtt")]
+pub use mqtt::{MqttBridge, MqttConfig, MqttEvent, MqttObserver};
+pub use names::IdNames;
+pub use nmea2000::{
+    decode_n2k, format_n2k, is_fast_packet, n2k_pgn_def, FastPacketAssembler, N2kField, N2kPgnDef, N2kValue,
+    FAST_PACKET_MAX_LEN, FAST_PACKET_TIMEOUT, N2K_PGNS,
+};
+pub use obd::{
+    pid_info, ObdClient, ObdReading, PidInfo, SupportedPids, OBD_FUNCTIONAL_ID, OBD_RESPONSE_IDS, PIDS,
+};
+pub use pcap::{socketcan_bytes, PcapngWriter, LINKTYPE_CAN_SOCKETCAN};
+pub use plot::{sparkline, Plot, PlotSource};
+pub use plugin::{DynamicProcessor, PluginEmit, PluginError, PluginFrame, PluginVtable, PLUGIN_ABI_VERSION, PLUGIN_ENTRY};
+pub use polling::ReceivePolling;
+pub use processor::{EmitHandler, Emitter, FrameProcessor, Pipeline, ProcessorStats, Verdict};
+pub use processors::{builtin_processor, CsvLogger, RateLimiter, BUILTIN_PROCESSORS};
+pub use reconnect::{ConnectionObserver, ConnectionState, DisconnectedTx, Reconnect, HELD_TX_LIMIT};
+pub use recovery::BusOffRecovery;
+pub use reference::RefType;
+pub use replay::replay;
+pub use responder::RtrResponder;
+pub use retry::TxRetry;
+pub use rotate::{RotatedFile, RotatingSink, Rotation, WriterFactory};
+pub use rules::{GatewayRules, Rule, RuleAction, RuleError};
+pub use scheduler::{CyclicId, Scheduler, TransmitObserver};
+#[cfg(feature = "scripting")]
+pub use script::{FrameScript, ScriptAction, ScriptError};
+pub use selftest::{FrameCheck, SelfTest, SelfTestReport, SelfTestVerdict};
+pub use shaper::{ShaperStats, TokenBucket, TxShaping, TX_QUEUE_LIMIT};
+pub use sink::{Direction, FrameSink};
+pub use slcan::{format_slcan, slcan_bitrate, SlcanBridge, SlcanCommand, SlcanSession, SLCAN_PORT};
+pub use socketcand::{SocketcandServer, SOCKETCAND_PORT};
+#[cfg(all(feature = "socketcan-compat", target_os = "linux"))]
+pub use socketcan_compat::UnsupportedFrame;
+pub use status::{CanStatus, ErrorFlags, ErrorInfo, ErrorState};
+#[cfg(feature = "async")]
+pub use stream::FrameStream;
+pub use timestamp::{DeviceClock, REANCHOR_INTERVAL, TICK};
+pub use tracker::{IdTracker, TrackedId};
+pub use trc::{format_trc_line, read_trc, TrcLog, TrcRecord, TrcWriter};
+pub use tx_table::{parse_tx_table, TxEntry, TxTableError};
+pub use uds::{Nrc, UdsClient, UdsError};
+pub use watchdog::{Expectation, Watchdog, WatchdogEvent};
+pub use ws::{WsServer, WS_PORT};
@@ -1,177 +1,2727 @@
-use libloading::Library;
+mod cli;
+mod color;
+mod config;
+mod diag;
+mod monitor;
+mod pause;
+mod prompt;
+
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use cli::{
+    Args, CanopenCommand, Command, LogFormat, NmtAction, ObdArgs, OutputFormat, PlotSpec, SdoRequest, Server, SignalAssignment,
+    UdsArgs, UdsRequest,
+};
+use color::Colors;
+use monitor::MonitorOptions;
+use pause::Pause;
+use prompt::Prompt;
+use rustcanbus::{
+    builtin_processor, calc_btr, decode_spns, encode_signals, format_n2k, format_version,
+    is_fast_packet, parse_tx_table, pid_info, read_candump, read_trc, replay, Addressing, AlignmentReport, AscWriter, AutoBaud,
+    BaudDetection, Benchmark, Bitrate, BusOffRecovery, CanError, CanLibrary, CandumpRecord, CandumpWriter,
+    CaptureConfig, Channel, ChannelHandle, ChannelMode, CompressedWriter, Compression, ConnectionState, CsvWriter, Dbc, Device, Direction,
+    DisconnectedTx, DiscoveredId, Discovery, Dm1, DynamicProcessor, EmitHandler, ErrorFlags, ErrorState,
+    FastPacketAssembler, Fault, FaultAction, FaultAlert, FaultEvent, FaultTracker, FilterBuilder, Frame, FrameProcessor, FrameSink, FuzzConfig, Fuzzer,
+    Gateway, GatewayRules, HeartbeatMonitor, Id, IdTracker, IntegrityChecker, IntegritySpec,
+    IsoTpConfig, IsoTpSocket, J1939Message, JsonWriter, LatencyReport, LatencyTest, MdfWriter, MetricKind, Metrics,
+    MetricsServer, NmtCommand, NodeEvent, ObdClient, ObdReading, OutOfRange, PcapngWriter, Pipeline,
+    Plot, PlotSource, ReceivePolling, Reconnect, RefType, RotatingSink, Rotation, RtrResponder, Sample, Scheduler, SdoClient,
+    SelfTest, SelfTestReport, SelfTestVerdict, SendType, SinkFactory, SlcanBridge, SocketcandServer,
+    SoftwareFilter, TpEvent, TpReassembler, TrcWriter, TriggeredCapture, TxEntry, TxRetry, TxShaping,
+    UdsClient, VciInitConfig, Watchdog, WatchdogEvent, WsServer, OBD_FUNCTIONAL_ID, PGN_DM1,
+};
+#[cfg(feature = "grpc")]
+use rustcanbus::GrpcServer;
+#[cfg(feature = "mqtt")]
+use rustcanbus::{MqttBridge, MqttConfig, MqttEvent};
+#[cfg(feature = "scripting")]
+use rustcanbus::{FrameScript, ScriptAction};
 use std::{
-    sync::{Arc, atomic::{AtomicBool, Ordering}},
+    env,
+    ffi::OsString,
+    error::Error,
+    panic,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    io::{self, BufReader, BufWriter, IsTerminal, Seek, Write},
+    sync::{Arc, Mutex, RwLock, atomic::{AtomicBool, AtomicU64, Ordering}, mpsc::{self, RecvTimeoutError}},
     thread,
-    time::Duration,
+    process::{self, ExitCode},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    collections::BTreeMap,
 };
-use crossterm::event::{self, Event, KeyCode, KeyModifiers};
-use crossterm::terminal::{enable_raw_mode, disable_raw_mode};
-
-#[repr(C)]
-#[derive(Debug, Default)]
-struct VciCanObj {
-    id: u32,
-    time_stamp: u32,
-    time_flag: u8,
-    send_type: u8,
-    remote_flag: u8,
-    extern_flag: u8,
-    data_len: u8,
-    data: [u8; 8],
-    reserved: [u8; 3],
-}
-
-#[repr(C)]
-#[derive(Debug, Default)]
-struct VciInitConfig {
-    acc_code: u32,
-    acc_mask: u32,
-    reserved: u32,
-    filter: u8,
-    timing0: u8,
-    timing1: u8,
-    mode: u8,
-}
-
-struct CanLibrary {
-    _lib: Arc<Library>,
-    vci_open_device: unsafe extern "stdcall" fn(u32, u32, u32) -> i32,
-    vci_close_device: unsafe extern "stdcall" fn(u32, u32) -> i32,
-    vci_init_can: unsafe extern "stdcall" fn(u32, u32, u32, *const VciInitConfig) -> i32,
-    vci_start_can: unsafe extern "stdcall" fn(u32, u32, u32) -> i32,
-    vci_transmit: unsafe extern "stdcall" fn(u32, u32, u32, *const VciCanObj, u32) -> i32,
-    vci_receive: unsafe extern "stdcall" fn(u32, u32, u32, *mut VciCanObj, u32, i32) -> i32,
-}
-
-impl CanLibrary {
-    fn new(dll_name: &str) -> Arc<Self> {
-        let lib = Arc::new(unsafe { Library::new(dll_name) }.expect("DLL load failed"));
-
-        unsafe {
-            Arc::new(Self {
-                _lib: lib.clone(),
-                vci_open_device: *lib.get(b"VCI_OpenDevice").expect("Failed to get VCI_OpenDevice"),
-                vci_close_device: *lib.get(b"VCI_CloseDevice").expect("Failed to get VCI_CloseDevice"),
-                vci_init_can: *lib.get(b"VCI_InitCAN").expect("Failed to get VCI_InitCAN"),
-                vci_start_can: *lib.get(b"VCI_StartCAN").expect("Failed to get VCI_StartCAN"),
-                vci_transmit: *lib.get(b"VCI_Transmit").expect("Failed to get VCI_Transmit"),
-                vci_receive: *lib.get(b"VCI_Receive").expect("Failed to get VCI_Receive"),
-            })
+use serde::Serialize;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, enable_raw_mode, disable_raw_mode};
+use crossterm::{cursor, queue};
+use signal_hook::{consts::{SIGINT, SIGTERM}, flag};
+use tracing::{debug, error, info, warn};
+
+fn main() -> ExitCode {
+    let mut argv: Vec<OsString> = env::args_os().collect();
+    loop {
+        let (args, matches) = match parse_args(&argv) {
+            Ok(parsed) => parsed,
+            Err(code) => return code,
+        };
+        if args.dump_config {
+            print!("{}", config::dump(&matches, &[]));
+            return ExitCode::SUCCESS;
+        }
+        let profiles = Arc::new(Profiles { argv, matches, next: Mutex::new(None) });
+        if let Err(err) = run(args, &profiles) {
+            eprintln!("Error: {err}");
+            return ExitCode::FAILURE;
+        }
+        // 'l' ends the run to start again with another profile.
+        let next = profiles.next.lock().unwrap().take();
+        match next {
+            Some(next) => argv = next,
+            None => return ExitCode::SUCCESS,
         }
     }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let dll = CanLibrary::new("ControlCAN.dll");
+/// What the 'w' and 'l' keys need to save the running setup and switch profiles.
+struct Profiles {
+    /// The command line as given, before --config or --profile was expanded.
+    argv: Vec<OsString>,
+    matches: ArgMatches,
+    /// The command line to start again with, set by 'l' as it ends the run.
+    next: Mutex<Option<Vec<OsString>>>,
+}
 
-    let dev_type = 4;
-    let dev_index = 0;
-    let can1 = 0;
-    let can2 = 1;
-    let reserved = 0;
+/// Expands --config or --profile and loads --names, then parses the command line.
+fn parse_args(argv: &[OsString]) -> Result<(Args, ArgMatches), ExitCode> {
+    let (argv, config) = config::apply(argv.to_vec()).map_err(|err| {
+        eprintln!("Error: {err}");
+        ExitCode::FAILURE
+    })?;
+    if let Err(err) = cli::load_names(argv.iter().cloned()) {
+        eprintln!("Error: {err}");
+        return Err(ExitCode::FAILURE);
+    }
+    let matches = match Args::command().try_get_matches_from(&argv) {
+        Ok(matches) => matches,
+        Err(err) => {
+            if let Some(setting) = config.as_ref().and_then(|config| config.locate(&err)) {
+                eprintln!("In {setting}:");
+            }
+            err.exit()
+        }
+    };
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    Ok((args, matches))
+}
 
-    if unsafe { (dll.vci_open_device)(dev_type, dev_index, reserved) } != 1 {
-        println!("Failed to open device");
-        return Ok(());
+/// The command line switched to profile `name`, checked the way it will be parsed so a bad or
+/// conflicting profile leaves the running setup alone.
+fn switch_profile(profiles: &Profiles, name: &str) -> Result<Vec<OsString>, String> {
+    let argv = config::switch_profile(&profiles.argv, name);
+    let (merged, config) = config::apply(argv.clone())?;
+    let names = cli::names();
+    let parsed = cli::load_names(merged.iter().cloned()).and_then(|()| {
+        Args::command().try_get_matches_from(&merged).map(drop).map_err(|err| {
+            let message = err.to_string();
+            let message = message.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string();
+            match config.as_ref().and_then(|config| config.locate(&err)) {
+                Some(setting) => format!("{setting}: {message}"),
+                None => message,
+            }
+        })
+    });
+    cli::set_names(names);
+    parsed.map(|()| argv)
+}
+
+fn run(args: Args, profiles: &Arc<Profiles>) -> Result<(), Box<dyn Error>> {
+    // A scheduled task or service has no console to put into raw mode.
+    let headless = args.headless || !io::stdin().is_terminal();
+    let colored = !headless && !args.no_color && env::var_os("NO_COLOR").is_none();
+    diag::init(args.diag_json, args.diag_log.as_deref(), colored)?;
+    color::init(Colors {
+        enabled: colored && io::stdout().is_terminal(),
+        ids: args.colors.as_deref().map(color::load_id_colors).transpose()?.unwrap_or_default(),
+    });
+    if let (Some(path), Some(names)) = (&args.names, cli::names()) {
+        info!("Loaded {} ID names from {}", names.len(), path.display());
+    }
+    if args.list_devices {
+        return Ok(list_devices(args.dll.as_deref())?);
     }
-    println!("Device opened successfully");
 
-    let config = VciInitConfig {
-        acc_code: 0,
-        acc_mask: 0xFFFFFFFF,
-        reserved: 0,
-        filter: 1,
-        timing0: 0x01,
-        timing1: 0x1C,
-        mode: 0,
+    let replay_log = args.replay.as_deref().map(load_replay).transpose()?;
+    let tx_table = match &args.tx_table {
+        Some(path) => {
+            let entries = load_tx_table(path)?;
+            if args.dry_run {
+                for entry in &entries {
+                    println!("{entry}");
+                }
+                return Ok(());
+            }
+            entries
+        }
+        None => Vec::new(),
     };
 
-    if unsafe { (dll.vci_init_can)(dev_type, dev_index, can1, &config) } != 1 {
-        println!("Failed to initialize CAN1");
-        return Ok(());
+    let gateway_rules = match &args.gateway_rules {
+        Some(path) => {
+            let rules = GatewayRules::parse(&fs::read_to_string(path)?)?;
+            info!("Loaded {} gateway rules from {}", rules.rules.len(), path.display());
+            rules
+        }
+        None => GatewayRules::default(),
+    };
+    let processors = load_processors(&args)?;
+
+    #[cfg(feature = "scripting")]
+    let script = match &args.script {
+        Some(path) => {
+            let script = FrameScript::compile(&fs::read_to_string(path)?).map_err(|err| format!("{}: {err}", path.display()))?;
+            info!("Loaded script {}", path.display());
+            Some(script)
+        }
+        None => None,
+    };
+
+    let dbc = match &args.dbc {
+        Some(path) => {
+            let dbc = Dbc::parse(&fs::read_to_string(path)?)?;
+            info!("Loaded {} messages from {}", dbc.messages().len(), path.display());
+            Some(dbc)
+        }
+        None => None,
+    };
+    let plots = load_plots(&args, dbc.as_ref())?;
+
+    let log_options = LogOptions::new(&args, dbc.as_ref());
+    if args.log_compress.is_some() && [(args.log.is_some(), args.log_format), (args.capture.is_some(), args.capture_format)].contains(&(true, LogFormat::Mdf)) {
+        return Err("MDF logs can't be compressed with --log-compress".into());
     }
-    if unsafe { (dll.vci_init_can)(dev_type, dev_index, can2, &config) } != 1 {
-        println!("Failed to initialize CAN2");
+    let log = match &args.log {
+        Some(path) => Some(open_log(path, args.log_format, &log_options)?),
+        None => None,
+    };
+
+    let library = CanLibrary::load(args.dll.as_deref())?;
+    info!("Loaded {}", library.path().display());
+    let ports = ports(&args)?;
+    let devices = open_devices(&args, &library, ports)?;
+    if args.info {
+        let missing: Vec<&str> = library.optional_functions().iter().filter(|(_, present)| !present).map(|(name, _)| *name).collect();
+        if missing.is_empty() {
+            info!("DLL provides every optional function");
+        } else {
+            info!("DLL lacks: {}", missing.join(", "));
+        }
+        close_devices(devices)?;
         return Ok(());
     }
-    println!("CAN1 & CAN2 initialized successfully (250kbps)");
 
-    if unsafe { (dll.vci_start_can)(dev_type, dev_index, can1) } != 1 {
-        println!("Failed to start CAN1");
-        return Ok(());
+    let handles = take_handles(&devices, ports)?;
+    if args.timestamp_bits < 32 {
+        for handle in &handles {
+            handle.channel().set_timestamp_wrap(1 << args.timestamp_bits, 1 << (args.timestamp_bits - 1));
+        }
     }
-    if unsafe { (dll.vci_start_can)(dev_type, dev_index, can2) } != 1 {
-        println!("Failed to start CAN2");
+    let aligned = align_channels(&args, &devices, &handles)?;
+    let (bitrates, configs) = channel_configs(&args, &handles)?;
+    start_channels(&args, &handles, bitrates, &configs)?;
+    shape_transmits(&args, &handles)?;
+
+    if let Some(result) = run_one_off(&args, &handles, dbc.as_ref(), bitrates[0], &configs[0]) {
+        result?;
+        close_devices(devices)?;
         return Ok(());
     }
-    println!("CAN1 & CAN2 started. Ready for transmission and reception");
 
     let running = Arc::new(AtomicBool::new(true));
+    if args.tx_retry_ms > 0 {
+        let retry = TxRetry::new(Duration::from_millis(args.tx_retry_ms)).with_running(Arc::clone(&running));
+        for handle in &handles {
+            handle.channel().set_tx_retry(Some(retry.clone()));
+        }
+    }
+    // SIGINT/SIGTERM end the run like Ctrl+X; a second one exits at once.
+    let interrupted = interrupt_flag()?;
+    let monitor = !headless && args.demo.receives() && args.output == OutputFormat::Text && !args.stream && !args.gateway;
+    let panic_running = Arc::clone(&running);
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        panic_running.store(false, Ordering::SeqCst);
+        if !headless {
+            restore_terminal(monitor);
+        }
+        default_hook(info);
+    }));
 
-    let running_clone = Arc::clone(&running);
-    let keyboard_thread = thread::spawn(move || {
-        enable_raw_mode().expect("Failed to enable raw mode");
-        println!("Press 'Ctrl + X' to exit...");
-
-        while running_clone.load(Ordering::SeqCst) {
-            if event::poll(Duration::from_millis(100)).unwrap() {
-                if let Event::Key(key) = event::read().unwrap() {
-                    if key.code == KeyCode::Char('x') && key.modifiers.contains(KeyModifiers::CONTROL) {
-                        println!("Ctrl + X detected, closing...");
-                        running_clone.store(false, Ordering::SeqCst);
-                        break;
-                    }
+    let tracker = if args.merge_channels { IdTracker::merged() } else { IdTracker::new() };
+    let mut watchdog = Watchdog::new(Instant::now());
+    for expectation in &args.expect {
+        watchdog.declare(*expectation, Instant::now());
+    }
+    let mut integrity = IntegrityChecker::new();
+    for (id, spec) in &args.integrity {
+        integrity.declare(*id, spec.clone());
+    }
+    let plotting = monitor && !plots.is_empty();
+    let session = Arc::new(Session {
+        handles,
+        running,
+        received: AtomicU64::new(0),
+        sent: AtomicU64::new(0),
+        filters: RwLock::new(software_filters(&args)),
+        tracker: Mutex::new(if args.group_by_pgn { tracker.grouped_by_pgn() } else { tracker }),
+        pause: Pause::new(),
+        prompt: Arc::new(Prompt::new()),
+        hide_static: AtomicBool::new(false),
+        show_plots: AtomicBool::new(!plots.is_empty()),
+        plots: Mutex::new(plots),
+        watchdog: Mutex::new(watchdog),
+        integrity: Mutex::new(integrity),
+        capture: args.capture.as_deref().map(|path| Mutex::new(arm_capture(&args, path, &log_options))),
+        dbc: dbc.map(Arc::new),
+        log: log.map(Mutex::new),
+        log_tx: args.log_tx,
+        metrics: Arc::new(Metrics::new()),
+        monitor,
+        headless,
+        pgn_ids: if args.j1939 {
+            Some(PgnIds::J1939)
+        } else if args.nmea2000 {
+            Some(PgnIds::Nmea2000)
+        } else {
+            None
+        },
+    });
+    for device in &devices {
+        let (adapter, prompt) = (device.index(), Arc::clone(&session.prompt));
+        device.set_connection_observer(Some(Box::new(move |state| {
+            report_connection(adapter, state, monitor.then_some(&*prompt));
+        })));
+    }
+    let observed = Arc::clone(&session);
+    let scheduler = Arc::new(Scheduler::with_observer(Box::new(move |channel, frame, result| match result {
+        Ok(()) => {
+            observed.sent.fetch_add(1, Ordering::SeqCst);
+            let slot = observed.handles.iter().position(|handle| handle.channel() == channel).unwrap_or(0);
+            observed.log_tx(slot as u32, frame);
+        }
+        Err(err) => warn!("{err}"),
+    })));
+    let cyclic_enabled = args.replay.is_none() && !args.fuzz && args.demo.transmits() && args.mode_for(args.channel) != ChannelMode::ListenOnly && !args.gateway;
+
+    let reload_path = args.tx_table.clone().filter(|_| cyclic_enabled);
+    let keyboard_thread = spawn_keyboard(&session, &scheduler, profiles, interrupted, args.channel as usize, reload_path);
+
+    session.metrics.register_channels(&channels(&session.handles));
+    let (fault_thread, faults) = spawn_fault_watch(&session, &args);
+    let status_thread = match Duration::try_from_secs_f64(args.status_interval) {
+        Ok(interval) if headless && !interval.is_zero() => {
+            let out: Box<dyn Write + Send> = match &args.status_log {
+                Some(path) => Box::new(File::options().create(true).append(true).open(path).map_err(|err| format!("{}: {err}", path.display()))?),
+                None => Box::new(io::stderr()),
+            };
+            Some(spawn_status_lines(out, interval, &session))
+        }
+        _ => None,
+    };
+
+    let mut consumers = Vec::new();
+    let mut pipeline = None;
+    if args.demo.receives() && !args.gateway {
+        consumers.push(spawn_statistics(&session));
+        let json = args.output == OutputFormat::Json;
+        // Headless runs only print every frame when asked to with --stream.
+        if json || (!monitor && (!headless || args.stream)) {
+            consumers.push(spawn_display(&session, json));
+        }
+        if plotting {
+            consumers.push(spawn_consumer("plot", &session, |session, _, frame| {
+                for plot in session.plots.lock().unwrap().iter_mut() {
+                    plot.observe(frame, session.dbc.as_deref());
+                }
+            }));
+        }
+        if session.log.is_some() {
+            consumers.push(spawn_consumer("log", &session, |session, index, frame| {
+                let log = session.log.as_ref().expect("checked when spawned");
+                if let Err(err) = log.lock().unwrap().write_frame(index, frame, Direction::Rx) {
+                    error!("Log write failed: {err}");
                 }
+            }));
+        }
+        if session.capture.is_some() {
+            consumers.push(spawn_consumer("capture", &session, |session, index, frame| {
+                let capture = session.capture.as_ref().expect("checked when spawned");
+                for event in capture.lock().unwrap().observe(index, frame, Instant::now()) {
+                    report(event.to_string(), session.monitor_prompt());
+                }
+            }));
+        }
+        if !args.rtr_reply.is_empty() {
+            consumers.push(spawn_rtr_responder(&session, &args.rtr_reply));
+        }
+        if !processors.is_empty() {
+            let (consumer, started) = start_processors(&session, processors);
+            consumers.push(consumer);
+            pipeline = Some(started);
+        }
+        #[cfg(feature = "scripting")]
+        if let Some(script) = script {
+            consumers.extend(start_script(&session, &scheduler, script));
+        }
+    }
+
+    let transmit_thread = start_transmit(&session, &args, &scheduler, replay_log, &tx_table, cyclic_enabled)?;
+    let server = start_server(&args, &session.handles)?;
+    let mut metrics_server = match args.metrics_port {
+        Some(port) => {
+            let server = MetricsServer::start(("0.0.0.0", port), Arc::clone(&session.metrics), &channels(&session.handles))?;
+            info!("Metrics on http://{}/metrics, health on /healthz", server.local_addr());
+            Some(server)
+        }
+        None => None,
+    };
+    #[cfg(feature = "mqtt")]
+    let mut mqtt = args.mqtt.as_ref().map(|(host, port)| start_mqtt(&args, &session, host, *port));
+
+    let gateway_thread = args.gateway.then(|| spawn_gateway(&session, args.rx_buffer as usize, gateway_rules));
+    let watchdog_thread = (!args.expect.is_empty() && args.demo.receives() && !args.gateway).then(|| spawn_watchdog(&session));
+    let capture_thread = session.capture.is_some().then(|| spawn_capture_poll(&session));
+    let monitor_options = MonitorOptions {
+        hold: Duration::from_millis(args.highlight_ms),
+        changed_within: Duration::try_from_secs_f64(args.changed_within.max(0.0)).unwrap_or(Duration::MAX),
+        stats: args.stats,
+        pgn_ids: session.pgn_ids.is_some(),
+        names: cli::names(),
+    };
+    let monitor_thread = monitor.then(|| spawn_monitor(&session, monitor_options));
+
+    if let Some(handle) = transmit_thread {
+        handle.join().unwrap();
+    }
+    for consumer in consumers {
+        let (name, dropped) = consumer.join().unwrap();
+        if dropped > 0 {
+            warn!("The {name} consumer fell behind and missed {dropped} frames");
+        }
+    }
+    if let Some(mut pipeline) = pipeline.and_then(Arc::into_inner) {
+        for stats in pipeline.stats() {
+            if stats.overflowed() > 0 {
+                warn!("Processor {} fell behind and missed {} frames", stats.name(), stats.overflowed());
             }
         }
+        for err in pipeline.stop() {
+            warn!("Processor {err}");
+        }
+    }
+    keyboard_thread.join().unwrap();
+    report_channels(&session.handles);
+    if let Some((report, swapped)) = aligned.and_then(|(device, swapped)| Some((device.alignment_report()?, swapped))) {
+        print_alignment(&report, swapped);
+    }
+    drop(scheduler);
+    #[cfg(feature = "mqtt")]
+    if let Some(mqtt) = &mut mqtt {
+        mqtt.stop();
+        info!(
+            "MQTT: published {} messages, dropped {} while the broker lagged, transmitted {} frames",
+            mqtt.published(),
+            mqtt.dropped(),
+            mqtt.transmitted()
+        );
+    }
+    if let Some(server) = server {
+        stop_server(server);
+    }
+    if let Some(server) = &mut metrics_server {
+        server.stop();
+    }
+    if let Some(handle) = gateway_thread {
+        handle.join().unwrap();
+    }
+    if let Some(handle) = watchdog_thread {
+        handle.join().unwrap();
+    }
+    if let Some(handle) = monitor_thread {
+        handle.join().unwrap();
+    }
+    fault_thread.join().unwrap();
+    print_faults(&faults.lock().unwrap());
+    if let Some(handle) = status_thread {
+        handle.join().unwrap();
+    }
+    if let Some(handle) = capture_thread {
+        handle.join().unwrap();
+    }
+    finish_session(&args, &session);
 
-        disable_raw_mode().expect("Failed to disable raw mode");
-    });
+    info!(
+        "Frames sent: {}, received: {}",
+        session.sent.load(Ordering::SeqCst),
+        session.received.load(Ordering::SeqCst)
+    );
+    close_devices(devices)?;
+    info!("Device closed");
+
+    let timeouts = session.watchdog.lock().unwrap().timeouts();
+    if timeouts > 0 && !io::stdout().is_terminal() {
+        return Err(format!("{timeouts} watchdog timeout(s)").into());
+    }
+    Ok(())
+}
 
-    let running_clone1 = Arc::clone(&running);
-    let dll_clone1 = Arc::clone(&dll);
+/// Closes the capture and the log, then prints what --stats and --integrity collected.
+fn finish_session(args: &Args, session: &Session) {
+    if let Some(capture) = &session.capture {
+        let mut capture = capture.lock().unwrap();
+        for event in capture.finish() {
+            info!("{event}");
+        }
+        if capture.captures() == 0 {
+            info!("Capture: no trigger fired");
+        }
+    }
 
-    let receive_thread = thread::spawn(move || {
-        unsafe {
-            while running_clone1.load(Ordering::SeqCst) {
-                let mut recv_obj: VciCanObj = VciCanObj::default();
-                let received_frames = (dll_clone1.vci_receive)(dev_type, dev_index, can1, &mut recv_obj, 1, 500);
+    if let Some(log) = &session.log {
+        if let Err(err) = log.lock().unwrap().finish() {
+            error!("Log flush failed: {err}");
+        }
+    }
 
-                if received_frames > 0 {
-                    println!("CAN1 received: ID=0x{:X}, Data={:?}", recv_obj.id, &recv_obj.data[..recv_obj.data_len as usize]);
-                }
-                thread::sleep(Duration::from_millis(5));
+    if args.stats || args.stats_csv.is_some() {
+        let tracker = session.tracker.lock().unwrap();
+        if args.stats {
+            print_stats(&tracker);
+        }
+        if let Some(path) = &args.stats_csv {
+            if let Err(err) = write_stats_csv(path, &tracker) {
+                error!("Writing {} failed: {err}", path.display());
             }
         }
-    });
+    }
+
+    let integrity = session.integrity.lock().unwrap();
+    if !integrity.is_empty() {
+        print_integrity(&integrity);
+    }
+}
 
-    let dll_clone3 = Arc::clone(&dll);
-    let transmit_thread = thread::spawn(move || {
-        unsafe {
-            for data in 1..=255 {
-                let can_obj = VciCanObj {
-                    id: 0x1,
-                    data_len: 1,
-                    data: [data, 0, 0, 0, 0, 0, 0, 0],
-                    ..Default::default()
-                };
+/// What the threads of a run share, from the counters to the open log.
+struct Session {
+    /// Held until the run ends, so nothing else can claim the ports.
+    handles: [ChannelHandle; 2],
+    /// Cleared to end the run.
+    running: Arc<AtomicBool>,
+    received: AtomicU64,
+    sent: AtomicU64,
+    /// One filter per channel; entries without a channel go into both.
+    filters: RwLock<[SoftwareFilter; 2]>,
+    tracker: Mutex<IdTracker>,
+    pause: Pause,
+    prompt: Arc<Prompt>,
+    hide_static: AtomicBool,
+    plots: Mutex<Vec<Plot>>,
+    show_plots: AtomicBool,
+    watchdog: Mutex<Watchdog>,
+    integrity: Mutex<IntegrityChecker>,
+    capture: Option<Mutex<TriggeredCapture>>,
+    dbc: Option<Arc<Dbc>>,
+    log: Option<Mutex<Box<dyn FrameSink>>>,
+    /// Whether transmitted frames go into `log` too, with --log-tx.
+    log_tx: bool,
+    metrics: Arc<Metrics>,
+    /// Whether the monitor view, rather than scrolling output, is on the terminal.
+    monitor: bool,
+    headless: bool,
+    pgn_ids: Option<PgnIds>,
+}
 
-                let sent_frames = (dll_clone3.vci_transmit)(dev_type, dev_index, can1, &can_obj, 1);
-                if sent_frames > 0 {
-                    println!("CAN1 sent: {}", data);
-                }
+impl Session {
+    /// The prompt to report events above, in the monitor view.
+    fn monitor_prompt(&self) -> Option<&Prompt> {
+        self.monitor.then_some(&*self.prompt)
+    }
 
-                thread::sleep(Duration::from_millis(10));
+    fn log_tx(&self, slot: u32, frame: &Frame) {
+        if let Some(log) = self.log.as_ref().filter(|_| self.log_tx) {
+            if let Err(err) = log.lock().unwrap().write_frame(slot, frame, Direction::Tx) {
+                error!("Log write failed: {err}");
             }
         }
+    }
+
+    /// Queues `frame` on CAN`slot + 1` through its handle, counting and logging it.
+    fn transmit(&self, slot: u32, frame: &Frame) -> Result<(), CanError> {
+        self.handles[slot as usize].transmit(frame)?;
+        self.sent.fetch_add(1, Ordering::SeqCst);
+        self.log_tx(slot, frame);
+        Ok(())
+    }
+}
+
+/// The unchecked channels of `handles`, for the servers and bridges that take them.
+fn channels(handles: &[ChannelHandle]) -> Vec<Channel> {
+    handles.iter().map(|handle| handle.channel().clone()).collect()
+}
+
+fn load_replay(path: &Path) -> Result<Vec<CandumpRecord>, Box<dyn Error>> {
+    let file = BufReader::new(File::open(path)?);
+    let (records, errors) = match path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("trc")) {
+        true => {
+            let log = read_trc(file).map_err(|err| format!("{}: {err}", path.display()))?;
+            let records = log.records.iter().map(|record| CandumpRecord { time: record.time, channel: record.channel, frame: record.frame }).collect();
+            (records, log.errors)
+        }
+        false => {
+            let log = read_candump(file)?;
+            (log.records, log.errors)
+        }
+    };
+    for (line, reason) in &errors {
+        warn!("{}:{line}: skipped: {reason}", path.display());
+    }
+    info!("Loaded {} frames to replay from {}", records.len(), path.display());
+    Ok(records)
+}
+
+fn load_processors(args: &Args) -> Result<Vec<Box<dyn FrameProcessor>>, Box<dyn Error>> {
+    let mut processors: Vec<Box<dyn FrameProcessor>> = Vec::new();
+    for spec in &args.processors {
+        let processor = match builtin_processor(&spec.name, &spec.config) {
+            Some(processor) => processor?,
+            // Anything that isn't a built-in names a plugin library.
+            None => Box::new(unsafe { DynamicProcessor::load(Path::new(&spec.name), &spec.config) }?),
+        };
+        info!("Loaded processor {}", processor.name());
+        processors.push(processor);
+    }
+    Ok(processors)
+}
+
+fn load_plots(args: &Args, dbc: Option<&Dbc>) -> Result<Vec<Plot>, Box<dyn Error>> {
+    let mut plots = Vec::new();
+    for spec in &args.plot {
+        let source = match (spec, dbc) {
+            (PlotSpec::Byte { id, index }, _) => PlotSource::Byte { id: *id, index: *index },
+            (PlotSpec::Signal(name), Some(dbc)) => PlotSource::signal(dbc, name)?,
+            (PlotSpec::Signal(name), None) => return Err(format!("--plot {name} needs a --dbc that defines the signal").into()),
+        };
+        plots.push(Plot::new(source, args.plot_samples as usize));
+    }
+    Ok(plots)
+}
+
+/// The adapter and port of CAN1 and CAN2.
+fn ports(args: &Args) -> Result<[(u32, u32); 2], Box<dyn Error>> {
+    Ok(match *args.ports.as_slice() {
+        [] => [(args.dev_index, 0), (args.dev_index, 1)],
+        [(adapter, port)] => [(adapter, port), (adapter, port ^ 1)],
+        [first, second] if first != second => [first, second],
+        [_, _] => return Err("--device names the same port twice".into()),
+        _ => return Err("--device can be given at most twice, for CAN1 and CAN2".into()),
+    })
+}
+
+/// Opens each adapter `ports` are on once, and sets up its USB reset and reconnection.
+fn open_devices(args: &Args, library: &Arc<CanLibrary>, ports: [(u32, u32); 2]) -> Result<Vec<Device>, Box<dyn Error>> {
+    let mut devices: Vec<Device> = Vec::new();
+    for (adapter, _) in ports {
+        if devices.iter().any(|device| device.index() == adapter) {
+            continue;
+        }
+        let device = Device::open_with(Arc::clone(library), args.dev_type, adapter)?;
+        info!("Device {adapter} opened successfully");
+        match device.board_info() {
+            Ok(info) => info!("{info}"),
+            Err(err @ CanError::Unsupported(_)) => info!("{err}"),
+            Err(err) => return Err(err.into()),
+        }
+        device.set_auto_usb_reset((args.usb_reset_after > 0).then_some(args.usb_reset_after));
+        device.set_auto_reconnect((args.reconnect_after > 0).then_some(Reconnect {
+            after_failures: args.reconnect_after,
+            interval: Duration::from_millis(args.reconnect_interval_ms),
+            tx: if args.hold_tx { DisconnectedTx::Hold } else { DisconnectedTx::Drop },
+        }));
+        devices.push(device);
+    }
+    Ok(devices)
+}
+
+/// Claims CAN1 and CAN2 on the adapters opened for them.
+fn take_handles(devices: &[Device], ports: [(u32, u32); 2]) -> Result<[ChannelHandle; 2], CanError> {
+    let [handle1, handle2] = ports.map(|(adapter, port)| {
+        devices.iter().find(|device| device.index() == adapter).expect("opened above").take_channel(port)
     });
+    let handles = [handle1?, handle2?];
+    if devices.len() > 1 {
+        for (slot, handle) in handles.iter().enumerate() {
+            info!("CAN{} is adapter {} can{}", slot + 1, handle.channel().device_index(), handle.index());
+        }
+    }
+    Ok(handles)
+}
 
-    transmit_thread.join().unwrap();
-    receive_thread.join().unwrap();
-    keyboard_thread.join().unwrap();
+/// Sets up --align-channels: the adapter whose timestamps get aligned and whether CAN1 is its
+/// second port.
+fn align_channels<'a>(args: &Args, devices: &'a [Device], handles: &[ChannelHandle]) -> Result<Option<(&'a Device, bool)>, Box<dyn Error>> {
+    let Some(align) = args.align_channels else {
+        return Ok(None);
+    };
+    let (can1, can2) = (handles[0].channel(), handles[1].channel());
+    if can1.device_index() != can2.device_index() {
+        return Err("--align-channels needs CAN1 and CAN2 on one adapter, whose ports share a clock".into());
+    }
+    let device = devices.iter().find(|device| device.index() == can1.device_index()).expect("opened above");
+    device.set_channel_alignment(Some(align.into()));
+    Ok(Some((device, can1.index() != 0)))
+}
+
+/// The bitrate and init configuration of each channel, from --bitrate and --sample-point or
+/// detected with --auto-baud, with the --accept filter.
+fn channel_configs(args: &Args, handles: &[ChannelHandle]) -> Result<([Bitrate; 2], [VciInitConfig; 2]), Box<dyn Error>> {
+    let requested = [args.bitrate_for(0), args.bitrate_for(1)];
+    let mut bitrates = requested;
+    if let Some(sample_point) = args.sample_point {
+        for (slot, &bitrate) in requested.iter().enumerate() {
+            if slot > 0 && bitrate == requested[0] {
+                bitrates[slot] = bitrates[0];
+                continue;
+            }
+            let bps = bitrate.bps().unwrap_or(bitrate.actual_bps().round() as u32);
+            let (timing0, timing1) = calc_btr(bps, sample_point)?;
+            bitrates[slot] = Bitrate::from_timing(timing0, timing1);
+            info!(
+                "Bit timing for {bps} bps: BTR0=0x{timing0:02X} BTR1=0x{timing1:02X} ({:.1} bps, sample point {:.1}%)",
+                bitrates[slot].actual_bps(),
+                f64::from(bitrates[slot].sample_point_permille()) / 10.0
+            );
+        }
+    }
+    let mut configs = [0, 1].map(|slot| VciInitConfig::with_bitrate(bitrates[slot]).with_mode(args.mode_for(slot as u32)));
+    if args.auto_baud {
+        let bitrate = detect_bitrate(args, &handles[args.channel as usize], &configs[args.channel as usize])?;
+        bitrates = [bitrate; 2];
+        for config in &mut configs {
+            (config.timing0, config.timing1) = bitrate.timing();
+        }
+    }
+    if !args.accept.is_empty() {
+        let filter = args
+            .accept
+            .iter()
+            .fold(FilterBuilder::new(), |builder, &(first, last)| builder.range(first, last))
+            .build();
+        for config in &mut configs {
+            filter.apply(config);
+        }
+        info!("Acceptance filter: code=0x{:08X} mask=0x{:08X}", filter.acc_code, filter.acc_mask);
+        match filter.extra_ids {
+            None => warn!("Standard and extended IDs can't share one hardware filter, accepting everything"),
+            Some(0) => {}
+            Some(extra) => warn!("The hardware filter also passes {extra} unrequested IDs"),
+        }
+    }
+    Ok((bitrates, configs))
+}
+
+/// Listens on `handle`'s port at each --auto-baud candidate until one receives cleanly.
+fn detect_bitrate(args: &Args, handle: &ChannelHandle, config: &VciInitConfig) -> Result<Bitrate, Box<dyn Error>> {
+    let mut auto_baud = AutoBaud::default();
+    if !args.auto_baud_order.is_empty() {
+        auto_baud.candidates = args.auto_baud_order.clone();
+    }
+    auto_baud.timeout = Duration::try_from_secs_f64(args.auto_baud_timeout.max(0.0)).unwrap_or(Duration::MAX);
+    info!("CAN{}: detecting bitrate (listen-only)...", args.channel + 1);
+    let bitrate = match auto_baud.detect(handle.channel(), config)? {
+        BaudDetection::Detected(bitrate) => bitrate,
+        BaudDetection::NoTraffic => {
+            return Err(format!(
+                "no traffic on CAN{} within {:.0} s, can't detect the bitrate of an idle bus",
+                args.channel + 1,
+                auto_baud.timeout.as_secs_f64()
+            )
+            .into())
+        }
+        BaudDetection::NoMatch => {
+            return Err(format!("CAN{} saw bus activity but none of the probed bitrates matched", args.channel + 1).into())
+        }
+    };
+    info!("CAN{}: detected {bitrate}bps", args.channel + 1);
+    Ok(bitrate)
+}
+
+/// Initializes and starts both channels, then empties their receive buffers and sets how
+/// they are polled.
+fn start_channels(args: &Args, handles: &[ChannelHandle], bitrates: [Bitrate; 2], configs: &[VciInitConfig; 2]) -> Result<(), Box<dyn Error>> {
+    for (slot, (handle, bitrate)) in handles.iter().zip(bitrates).enumerate() {
+        if let Some(reference) = RefType::for_bitrate(bitrate) {
+            info!("CAN{}: {bitrate}bps also needs VCI_SetReference on some firmware, setting it", slot + 1);
+            handle.channel().set_reference(&reference)?;
+        }
+    }
+    for (handle, config) in handles.iter().zip(configs) {
+        handle.init(config)?;
+    }
+    let setup = |slot: usize| match args.mode_for(slot as u32) {
+        ChannelMode::Normal => format!("{}bps", bitrates[slot]),
+        mode => format!("{}bps, {mode}", bitrates[slot]),
+    };
+    match setup(0) == setup(1) {
+        true => info!("CAN1 & CAN2 initialized successfully ({})", setup(0)),
+        false => info!("CAN1 ({}) & CAN2 ({}) initialized successfully", setup(0), setup(1)),
+    }
+
+    for handle in handles {
+        handle.start()?;
+    }
+    for handle in handles {
+        match handle.channel().clear_buffer() {
+            Ok(()) | Err(CanError::Unsupported(_)) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    info!("CAN1 & CAN2 started. Ready for transmission and reception");
+    let polling = ReceivePolling {
+        wait: Duration::from_millis(args.rx_wait_ms),
+        sleep: Duration::from_millis(args.rx_sleep_ms),
+        adaptive: !args.rx_fixed_wait,
+        max_frames: args.rx_buffer as usize,
+    };
+    for handle in handles {
+        handle.channel().set_receive_polling(polling);
+    }
+    Ok(())
+}
+
+/// Applies --tx-rate, --tx-bus-load, --tx-queue and --tx-drop-on-full to both channels.
+fn shape_transmits(args: &Args, handles: &[ChannelHandle]) -> Result<(), CanError> {
+    let shaping = TxShaping { frames_per_sec: args.tx_rate, bus_load_percent: args.tx_bus_load, queue: args.tx_queue as usize, drop_on_full: args.tx_drop_on_full };
+    if shaping == TxShaping::default() {
+        return Ok(());
+    }
+    for handle in handles {
+        handle.channel().set_tx_shaping(Some(shaping))?;
+    }
+    let limits: Vec<String> = [args.tx_rate.map(|rate| format!("{rate} frames/s")), args.tx_bus_load.map(|load| format!("{load}% bus load"))]
+        .into_iter()
+        .flatten()
+        .collect();
+    let full = if args.tx_drop_on_full { "dropping" } else { "waiting" };
+    match limits.is_empty() {
+        true => info!("Transmit queue: {} frames queued before {full}", args.tx_queue),
+        false => info!("Transmit shaping: at most {} per channel, {} frames queued before {full}", limits.join(" and "), args.tx_queue),
+    }
+    Ok(())
+}
+
+/// Runs what the command line asked for instead of a session: a subcommand, --send-signal,
+/// --self-test, --latency-test, --benchmark or --discover. `None` if it asked for none of
+/// them.
+fn run_one_off(args: &Args, handles: &[ChannelHandle], dbc: Option<&Dbc>, bitrate: Bitrate, config: &VciInitConfig) -> Option<Result<(), Box<dyn Error>>> {
+    let handle = &handles[args.channel as usize];
+    if let Some(command) = &args.command {
+        return Some(match command {
+            Command::Uds(uds) => run_uds(handle, uds),
+            Command::Obd(obd) => run_obd(handle, obd, args.output),
+            Command::Canopen { command } => run_canopen(handle, command),
+        });
+    }
+    if !args.send_signal.is_empty() {
+        let range = if args.reject_out_of_range { OutOfRange::Reject } else { OutOfRange::Clamp };
+        let dbc = dbc.expect("--send-signal requires --dbc");
+        return Some(send_signals(dbc, handle, args.channel, &args.send_signal, range));
+    }
+    if args.self_test {
+        return Some(run_self_test(args, handles, config));
+    }
+    if args.latency_test {
+        return Some(run_latency_test(args, handles));
+    }
+    if args.benchmark {
+        return Some(run_benchmark(args, handles, bitrate));
+    }
+    args.discover.map(|seconds| run_discovery(args, handles, seconds))
+}
+
+fn run_self_test(args: &Args, handles: &[ChannelHandle], config: &VciInitConfig) -> Result<(), Box<dyn Error>> {
+    let test = SelfTest::default();
+    let looped = if args.self_test_looped { ", then CAN1 -> CAN2" } else { "" };
+    info!("Self-test: {} frames on CAN1 and CAN2 in self-test mode{looped}", test.frames.len());
+    let report = test.run_all(&channels(handles), config, args.self_test_looped)?;
+    print_self_test(&report);
+    match report.verdict() {
+        SelfTestVerdict::Passed => Ok(()),
+        verdict => Err(format!("self-test failed: {verdict}").into()),
+    }
+}
+
+fn run_latency_test(args: &Args, handles: &[ChannelHandle]) -> Result<(), Box<dyn Error>> {
+    let test = LatencyTest {
+        id: args.latency_id,
+        count: args.latency_count,
+        interval: Duration::from_secs(1) / args.latency_rate,
+        drain: Duration::from_millis(500),
+    };
+    info!("Latency test: {} probes at {} Hz, CAN1 -> CAN2", test.count, args.latency_rate);
+    let report = test.run(handles[0].channel(), handles[1].channel(), &AtomicBool::new(true))?;
+    print_latency(&report);
+    if let Some(path) = &args.latency_csv {
+        write_latency_csv(path, &report).map_err(|err| format!("{}: {err}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn run_benchmark(args: &Args, handles: &[ChannelHandle], bitrate: Bitrate) -> Result<(), Box<dyn Error>> {
+    let duration = Duration::try_from_secs_f64(args.benchmark_secs.max(0.0)).unwrap_or(Duration::MAX);
+    info!("Benchmark: CAN1 -> CAN2, {} frames per call", args.benchmark_batch);
+    println!("{:>3} {:>10} {:>12} {:>12} {:>8} {:>10}", "DLC", "Frames/s", "Accepted/s", "Short calls", "Lost", "Bus load");
+    for &dlc in &args.benchmark_dlc {
+        let benchmark = Benchmark { id: Id::Standard(0x7F1), dlc, duration, batch: args.benchmark_batch as usize };
+        let result = benchmark.run(handles[0].channel(), handles[1].channel(), &AtomicBool::new(true))?;
+        let utilization = bitrate.bps().map_or_else(|| "n/a".to_string(), |bps| format!("{:.1}%", result.utilization(bps)));
+        println!(
+            "{:>3} {:>10.0} {:>12.0} {:>12} {:>8} {:>10}",
+            result.dlc,
+            result.frames_per_sec(),
+            result.accepted_per_sec(),
+            result.short_batches,
+            result.accepted.saturating_sub(result.received),
+            utilization
+        );
+    }
+    Ok(())
+}
+
+fn run_discovery(args: &Args, handles: &[ChannelHandle], seconds: f64) -> Result<(), Box<dyn Error>> {
+    let duration = Duration::try_from_secs_f64(seconds.max(0.0)).unwrap_or(Duration::MAX);
+    info!("Discovering IDs on CAN1 and CAN2 for {seconds} s, Ctrl+C ends early...");
+    let discovery = Discovery::listen(&channels(handles), duration, &*interrupt_flag()?)?;
+    match args.output {
+        OutputFormat::Text => print_discovery(&discovery),
+        OutputFormat::Json => {
+            for entry in discovery.iter() {
+                println!("{}", serde_json::to_string(&discovery_json(entry))?);
+            }
+        }
+    }
+    if let Some(path) = &args.discover_csv {
+        write_discovery_csv(path, &discovery).map_err(|err| format!("{}: {err}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// The software filter of each channel, from --filter and --drop.
+fn software_filters(args: &Args) -> [SoftwareFilter; 2] {
+    let mut filters = [SoftwareFilter::new(), SoftwareFilter::new()];
+    for (slot, filter) in (0..).zip(&mut filters) {
+        for entry in args.filter.iter().filter(|entry| entry.channel.is_none_or(|channel| channel == slot)) {
+            filter.add(&entry.term);
+        }
+        for entry in args.drop.iter().filter(|entry| entry.channel.is_none_or(|channel| channel == slot)) {
+            filter.add(&entry.term.inverted());
+        }
+    }
+    filters
+}
+
+/// The --capture around --trigger, writing to `path`.
+fn arm_capture(args: &Args, path: &Path, options: &LogOptions) -> TriggeredCapture {
+    let config = CaptureConfig {
+        pre_trigger: Duration::from_secs_f64(args.pre_trigger.max(0.0)),
+        post_trigger: Duration::from_secs_f64(args.post_trigger.max(0.0)),
+        max_frames: args.capture_frames as usize,
+        rearm: args.rearm,
+    };
+    let (path, format, rearm, options) = (path.to_path_buf(), args.capture_format, args.rearm, options.clone());
+    let triggers: Vec<String> = args.trigger.iter().map(ToString::to_string).collect();
+    info!(
+        "Capture armed on {}: {} s before and {} s after the trigger to {}",
+        triggers.join(", "),
+        args.pre_trigger,
+        args.post_trigger,
+        path.display()
+    );
+    let open: SinkFactory = Box::new(move |n| open_log(&capture_path(&path, n, rearm), format, &options));
+    TriggeredCapture::new(config, args.trigger.clone(), open)
+}
+
+/// Added at the prompt, for 'w' to save along with the options.
+#[derive(Default)]
+struct PromptAdditions {
+    filters: Vec<String>,
+    cyclic: Vec<String>,
+}
+
+/// Reads the keyboard until the run ends: the 't', 'w' and 'l' prompt and the single-key
+/// commands, with cyclic messages added on CAN`slot + 1`. Headless, only waits for a signal.
+fn spawn_keyboard(
+    session: &Arc<Session>,
+    scheduler: &Arc<Scheduler>,
+    profiles: &Arc<Profiles>,
+    interrupted: Arc<AtomicBool>,
+    slot: usize,
+    reload_path: Option<PathBuf>,
+) -> thread::JoinHandle<()> {
+    let (session, scheduler, profiles) = (Arc::clone(session), Arc::clone(scheduler), Arc::clone(profiles));
+    thread::spawn(move || {
+        let running = &session.running;
+        if session.headless {
+            info!("Running headless; stop with SIGINT or SIGTERM");
+            while running.load(Ordering::SeqCst) {
+                if interrupted.load(Ordering::SeqCst) {
+                    info!("Interrupted, closing...");
+                    running.store(false, Ordering::SeqCst);
+                    break;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+            return;
+        }
+        let _raw_mode = match RawMode::enable() {
+            Ok(raw_mode) => raw_mode,
+            Err(err) => {
+                error!("Cannot read the keyboard, enabling raw mode failed: {err}; closing...");
+                running.store(false, Ordering::SeqCst);
+                return;
+            }
+        };
+        let capture = session.capture.as_ref().filter(|capture| capture.lock().unwrap().has_manual_trigger());
+        let monitor = session.monitor;
+        info!("Press 'Ctrl + X' to exit, 'c' to clear buffers and counters, 's' for controller status, 'f' to toggle the software filter, space to pause, 'n' to step while paused, 't' for the prompt (send, repeat or filter), 'w' to save a profile, 'l' to load one{}{}...", if monitor { ", 'h' to hide unchanging IDs, 'p' to show plots" } else { "" }, if capture.is_some() { ", 'g' to trigger a capture" } else { "" });
+
+        let mut added = PromptAdditions::default();
+        while running.load(Ordering::SeqCst) {
+            if interrupted.load(Ordering::SeqCst) {
+                info!("Interrupted, closing...");
+                running.store(false, Ordering::SeqCst);
+                break;
+            }
+            let event = match event::poll(Duration::from_millis(100)).and_then(|ready| ready.then(event::read).transpose()) {
+                Ok(event) => event,
+                Err(err) => {
+                    error!("Reading the keyboard failed: {err}, closing...");
+                    running.store(false, Ordering::SeqCst);
+                    break;
+                }
+            };
+            let Some(Event::Key(key)) = event else {
+                continue;
+            };
+            // Raw mode delivers Ctrl+C as a key rather than SIGINT.
+            if matches!(key.code, KeyCode::Char('x' | 'c')) && key.modifiers.contains(KeyModifiers::CONTROL) {
+                info!("Ctrl + {} detected, closing...", if key.code == KeyCode::Char('x') { 'X' } else { 'C' });
+                running.store(false, Ordering::SeqCst);
+                break;
+            }
+            let prompt = &session.prompt;
+            if prompt.is_open() {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Esc => {
+                        prompt.close();
+                        prompt.set_message("Cancelled".to_string());
+                    }
+                    KeyCode::Enter => {
+                        let input = prompt.close().unwrap_or_default();
+                        match run_prompt_line(&session, &scheduler, &profiles, slot, input.trim(), &mut added) {
+                            Some(message) => prompt.set_message(message),
+                            None => break,
+                        }
+                    }
+                    KeyCode::Backspace => prompt.backspace(),
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => prompt.push(c),
+                    _ => {}
+                }
+                if !monitor {
+                    draw_stream_prompt(prompt);
+                }
+                continue;
+            }
+            let opened = match key.code {
+                KeyCode::Char('t') => Some(""),
+                KeyCode::Char('w') => Some("save "),
+                KeyCode::Char('l') => Some("load "),
+                _ => None,
+            };
+            if let Some(input) = opened.filter(|_| key.modifiers.is_empty() && key.kind == KeyEventKind::Press) {
+                prompt.open(input);
+                if !monitor {
+                    draw_stream_prompt(prompt);
+                }
+                continue;
+            }
+            if key.modifiers.is_empty() {
+                if let KeyCode::Char(c) = key.code {
+                    run_key(&session, &scheduler, c, reload_path.as_deref(), capture);
+                }
+            }
+        }
+    })
+}
 
-    unsafe { (dll.vci_close_device)(dev_type, dev_index) };
-    println!("Device closed");
+/// Runs a line entered at the prompt on CAN`slot + 1`: a frame to send, a cyclic message, a
+/// filter, or a profile to save or load. Returns the result to show, or `None` when a profile
+/// was loaded and the run ends to start again with it.
+fn run_prompt_line(session: &Session, scheduler: &Scheduler, profiles: &Profiles, slot: usize, input: &str, added: &mut PromptAdditions) -> Option<String> {
+    Some(match input.split_once(' ') {
+        Some(("filter", term)) => match cli::parse_channel_filter(term) {
+            Ok(entry) => {
+                for (index, filter) in (0..).zip(session.filters.write().unwrap().iter_mut()) {
+                    if entry.channel.is_none_or(|channel| channel == index) {
+                        filter.add(&entry.term);
+                    }
+                }
+                added.filters.push(term.trim().to_string());
+                match entry.channel {
+                    Some(channel) => format!("CAN{} filter: added {}", channel + 1, term.trim()),
+                    None => format!("Filter: added {}", term.trim()),
+                }
+            }
+            Err(err) => format!("Invalid filter: {err}"),
+        },
+        Some(("save", name)) => {
+            let text = config::dump(&profiles.matches, &[("filter", &added.filters), ("cyclic", &added.cyclic)]);
+            match config::save_profile(&profiles.argv, name.trim(), &text) {
+                Ok(path) => format!("Saved profile '{}' to {}", name.trim(), path.display()),
+                Err(err) => format!("Not saved: {err}"),
+            }
+        }
+        Some(("load", name)) => match switch_profile(profiles, name.trim()) {
+            Ok(next) => {
+                info!("Switching to profile '{}', restarting...", name.trim());
+                *profiles.next.lock().unwrap() = Some(next);
+                session.running.store(false, Ordering::SeqCst);
+                return None;
+            }
+            Err(err) => format!("Keeping the current setup: {err}"),
+        },
+        _ if input.contains('@') => match cli::parse_cyclic(input) {
+            Ok((frame, period)) => {
+                scheduler.add(session.handles[slot].channel(), frame, period);
+                added.cyclic.push(input.to_string());
+                format!("CAN{} sending {input}", slot + 1)
+            }
+            Err(err) => format!("Invalid cyclic message: {err}"),
+        },
+        _ => match cli::parse_frame(input) {
+            Ok(frame) => match session.transmit(slot as u32, &frame) {
+                Ok(()) => format!("CAN{} sent {input}", slot + 1),
+                Err(err) => err.to_string(),
+            },
+            Err(err) => format!("Invalid frame: {err}"),
+        },
+    })
+}
 
+/// Runs the command of a key pressed without modifiers, outside the prompt.
+fn run_key(session: &Session, scheduler: &Scheduler, key: char, reload_path: Option<&Path>, capture: Option<&Mutex<TriggeredCapture>>) {
+    match key {
+        'c' => {
+            for handle in &session.handles {
+                if let Err(err) = handle.channel().clear_buffer() {
+                    warn!("{err}");
+                }
+            }
+            session.received.store(0, Ordering::SeqCst);
+            session.sent.store(0, Ordering::SeqCst);
+            session.tracker.lock().unwrap().clear();
+            session.plots.lock().unwrap().iter_mut().for_each(Plot::clear);
+            info!("Buffers and counters cleared");
+        }
+        'f' => {
+            let mut filters = session.filters.write().unwrap();
+            let enabled = !filters[0].is_enabled();
+            for filter in filters.iter_mut() {
+                filter.set_enabled(enabled);
+            }
+            info!("Software filter {}", if enabled { "enabled" } else { "disabled" });
+        }
+        ' ' => match session.pause.toggle() {
+            None if !session.monitor => info!("Display paused, frames are still captured"),
+            None => {}
+            Some(resumed) => {
+                if resumed.dropped > 0 {
+                    info!("{} frames dropped while paused", resumed.dropped);
+                }
+                for (channel, frame) in &resumed.frames {
+                    print_frame(*channel, frame, session.dbc.as_deref(), session.pgn_ids);
+                }
+            }
+        },
+        'n' if !session.monitor => match session.pause.step() {
+            Some((channel, frame)) => print_frame(channel, &frame, session.dbc.as_deref(), session.pgn_ids),
+            None if session.pause.is_paused() => info!("No buffered frames"),
+            None => {}
+        },
+        'r' => {
+            if let Some(path) = reload_path {
+                match load_tx_table(path) {
+                    Ok(entries) => {
+                        scheduler.clear();
+                        schedule_tx_table(scheduler, &session.handles, &entries);
+                        info!("Reloaded {} messages from {}", entries.len(), path.display());
+                    }
+                    Err(err) => warn!("Keeping the current schedule: {err}"),
+                }
+            }
+        }
+        'g' => {
+            if let Some(capture) = capture {
+                for event in capture.lock().unwrap().fire("the 'g' key", Instant::now()) {
+                    report(event.to_string(), session.monitor_prompt());
+                }
+            }
+        }
+        'h' => {
+            session.hide_static.fetch_xor(true, Ordering::SeqCst);
+        }
+        'p' if session.monitor => {
+            if session.plots.lock().unwrap().is_empty() {
+                session.prompt.set_message("Nothing to plot; add --plot ID:byteN or a DBC signal name".to_string());
+            } else {
+                session.show_plots.fetch_xor(true, Ordering::SeqCst);
+            }
+        }
+        's' => {
+            for (slot, handle) in session.handles.iter().enumerate() {
+                match handle.channel().status() {
+                    Ok(status) => info!("CAN{} status: {status}", slot + 1),
+                    Err(err) => warn!("{err}"),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// What the fault watch keeps about one channel between polls.
+struct ChannelWatch {
+    flags: ErrorFlags,
+    /// REC and TEC at the last poll, for --trigger on a jump in them.
+    counters: Option<(u8, u8)>,
+    recovery: BusOffRecovery,
+    recovered: Arc<AtomicU64>,
+    /// `VCI_Receive` failures warned about so far, and when the last warning was.
+    receive_warned: (u64, Option<Instant>),
+    /// --fault-alert frames held back until the channel leaves bus-off.
+    alert_frames: Vec<Frame>,
+}
+
+/// Polls both channels' error states once a second: logs changes, tracks error-passive and
+/// bus-off, fires --fault-alert actions and recovers from bus-off.
+struct FaultWatch {
+    channels: [ChannelWatch; 2],
+    trackers: Arc<Mutex<[FaultTracker; 2]>>,
+    alerts: Vec<FaultAlert>,
+    json: Option<JsonWriter<io::Stdout>>,
+    auto_recover: bool,
+}
+
+impl FaultWatch {
+    fn poll(&mut self, session: &Session, slot: usize) {
+        let channel = session.handles[slot].channel();
+        if channel.connection_state() != ConnectionState::Connected {
+            return;
+        }
+        let watch = &mut self.channels[slot];
+        let mut bus_off = false;
+        let mut passive = false;
+        let mut cause = None;
+        match channel.error_info() {
+            Ok(info) => {
+                cause = Some(info.flags);
+                passive |= info.flags.contains(ErrorFlags::ERROR_PASSIVE);
+                if info.flags != watch.flags {
+                    let line = format!(
+                        "CAN{} error state: {} (REC={}, TEC={})",
+                        slot + 1,
+                        info.flags,
+                        info.rx_error_counter(),
+                        info.tx_error_counter()
+                    );
+                    match info.flags.is_empty() {
+                        true => info!("{line}"),
+                        false => warn!("{line}"),
+                    }
+                    watch.flags = info.flags;
+                }
+                bus_off |= info.flags.contains(ErrorFlags::BUS_OFF);
+                let now = (info.rx_error_counter(), info.tx_error_counter());
+                let jump = session.capture.as_ref().and_then(|capture| Some((capture, capture.lock().unwrap().error_jump()?)));
+                if let (Some((capture, threshold)), Some((rec, tec))) = (jump, watch.counters.replace(now)) {
+                    if now.0 >= rec.saturating_add(threshold) || now.1 >= tec.saturating_add(threshold) {
+                        let reason = format!("CAN{} error counters (REC {rec}->{}, TEC {tec}->{})", slot + 1, now.0, now.1);
+                        for event in capture.lock().unwrap().fire(&reason, Instant::now()) {
+                            report(event.to_string(), session.monitor_prompt());
+                        }
+                    }
+                }
+            }
+            Err(CanError::Unsupported(_)) => {}
+            Err(err) => warn!("{err}"),
+        }
+        match channel.status() {
+            Ok(status) => {
+                bus_off |= status.bus_off();
+                passive |= status.error_state() == ErrorState::Passive;
+            }
+            Err(CanError::Unsupported(_)) => {}
+            Err(err) => warn!("{err}"),
+        }
+        let failures = channel.receive_failures();
+        let (reported, at) = &mut watch.receive_warned;
+        if failures > *reported && at.is_none_or(|at| at.elapsed() >= RECEIVE_WARNING_INTERVAL) {
+            let cause = cause.map(|flags| format!(", error state: {flags}")).unwrap_or_default();
+            warn!("CAN{}: VCI_Receive failed {} time(s){cause}", slot + 1, failures - *reported);
+            (*reported, *at) = (failures, Some(Instant::now()));
+        }
+        let mut trackers = self.trackers.lock().unwrap();
+        let tracker = &mut trackers[slot];
+        for event in tracker.update(passive, bus_off, Instant::now()) {
+            let count = tracker.count(event.fault());
+            match event {
+                FaultEvent::Entered { fault, .. } => warn!("CAN{} entered {fault} ({} time(s) this run)", slot + 1, count),
+                FaultEvent::Recovered { fault, after, .. } => info!("CAN{} left {fault} after {:.3} s", slot + 1, after.as_secs_f64()),
+            }
+            if let Some(json) = &mut self.json {
+                if let Err(err) = json.write_fault(slot as u32, &event, count) {
+                    error!("JSON output failed: {err}");
+                }
+            }
+            for alert in self.alerts.iter().filter(|alert| alert.fires(&event)) {
+                info!("CAN{} fault alert: {} {} time(s)", slot + 1, alert.fault, alert.count);
+                match &alert.action {
+                    FaultAction::Exec(command) => spawn_alert(command, slot as u32, alert.fault, count),
+                    FaultAction::Send(frame) => watch.alert_frames.push(*frame),
+                }
+            }
+        }
+        if !tracker.is_in(Fault::BusOff) {
+            for frame in watch.alert_frames.drain(..) {
+                if let Err(err) = session.handles[slot].transmit(&frame) {
+                    error!("CAN{} fault alert frame {} not sent: {err}", slot + 1, frame.id());
+                }
+            }
+        }
+        drop(trackers);
+        if self.auto_recover {
+            match watch.recovery.poll(channel, bus_off) {
+                Ok(true) => {
+                    watch.recovered.fetch_add(1, Ordering::Relaxed);
+                    warn!("CAN{} recovered from bus-off (recovery #{})", slot + 1, watch.recovery.recoveries());
+                }
+                Ok(false) => {}
+                Err(err) => error!("CAN{} bus-off recovery failed: {err}", slot + 1),
+            }
+        }
+    }
+}
+
+/// Registers the fault metrics and starts the [`FaultWatch`] thread; the trackers are for the
+/// summary at the end.
+fn spawn_fault_watch(session: &Arc<Session>, args: &Args) -> (thread::JoinHandle<()>, Arc<Mutex<[FaultTracker; 2]>>) {
+    let metrics = &session.metrics;
+    let recovered = [0, 1].map(|slot: u32| {
+        metrics.counter("rustcanbus_bus_off_recoveries_total", "Bus-off recoveries.", &[("channel", &slot.to_string())])
+    });
+    let faults = Arc::new(Mutex::new([(); 2].map(|_| FaultTracker::new(Instant::now()))));
+    for (name, help, fault) in [
+        ("rustcanbus_error_passive_total", "Times the channel entered error-passive.", Fault::ErrorPassive),
+        ("rustcanbus_bus_off_total", "Times the channel entered bus-off.", Fault::BusOff),
+    ] {
+        let faults = Arc::clone(&faults);
+        metrics.register(name, help, MetricKind::Counter, move || {
+            let trackers = faults.lock().unwrap();
+            trackers.iter().enumerate().map(|(slot, tracker)| Sample::new(&[("channel", &slot.to_string())], tracker.count(fault) as f64)).collect()
+        });
+    }
+    let recovery_faults = Arc::clone(&faults);
+    metrics.register("rustcanbus_fault_recovery_seconds", "How long the channel's last error-passive or bus-off lasted.", MetricKind::Gauge, move || {
+        let mut samples = Vec::new();
+        for (slot, tracker) in recovery_faults.lock().unwrap().iter().enumerate() {
+            for fault in [Fault::ErrorPassive, Fault::BusOff] {
+                if let Some(recovery) = tracker.last_recovery(fault) {
+                    samples.push(Sample::new(&[("channel", &slot.to_string()), ("fault", &fault.to_string())], recovery.as_secs_f64()));
+                }
+            }
+        }
+        samples
+    });
+    let mut watch = FaultWatch {
+        channels: recovered.map(|recovered| ChannelWatch {
+            flags: ErrorFlags::default(),
+            counters: None,
+            recovery: BusOffRecovery::new(Duration::from_secs(1)),
+            recovered,
+            receive_warned: (0, None),
+            alert_frames: Vec::new(),
+        }),
+        trackers: Arc::clone(&faults),
+        alerts: args.fault_alert.clone(),
+        json: (args.output == OutputFormat::Json).then(|| JsonWriter::new(io::stdout())),
+        auto_recover: !args.no_auto_recover,
+    };
+    let session = Arc::clone(session);
+    let thread = thread::spawn(move || {
+        while session.running.load(Ordering::SeqCst) {
+            for slot in 0..session.handles.len() {
+                watch.poll(&session, slot);
+            }
+            for _ in 0..10 {
+                if !session.running.load(Ordering::SeqCst) {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+    });
+    (thread, faults)
+}
+
+/// Counts, tracks and watches every received frame, and checks it against --integrity.
+fn spawn_statistics(session: &Arc<Session>) -> thread::JoinHandle<(&'static str, u64)> {
+    spawn_consumer("statistics", session, |session, index, frame| {
+        session.received.fetch_add(1, Ordering::SeqCst);
+        session.tracker.lock().unwrap().update(index, frame, Instant::now());
+        if let Some(event) = session.watchdog.lock().unwrap().observe(frame.id(), frame.instant().unwrap_or_else(Instant::now)) {
+            report_watchdog(&event, session.monitor_prompt());
+        }
+        for event in session.integrity.lock().unwrap().check(index, frame) {
+            report_error(format!("CAN{} {}: {event}", index + 1, frame.id()), session.monitor_prompt());
+        }
+    })
+}
+
+/// Prints every received frame, or writes it as a JSON line, with the J1939 or NMEA 2000
+/// messages reassembled from them.
+fn spawn_display(session: &Arc<Session>, json: bool) -> thread::JoinHandle<(&'static str, u64)> {
+    let mut json = json.then(|| JsonWriter::new(io::stdout()));
+    let mut transport = [TpReassembler::new(), TpReassembler::new()];
+    let mut fast_packets = [FastPacketAssembler::new(), FastPacketAssembler::new()];
+    spawn_consumer("display", session, move |session, index, frame| {
+        let pgn_ids = session.pgn_ids;
+        if let Some(json) = &mut json {
+            if let Err(err) = json.write_frame(index, frame, Direction::Rx) {
+                error!("JSON output failed: {err}");
+            }
+        } else if !session.pause.hold(index, frame) {
+            print_frame(index, frame, session.dbc.as_deref(), pgn_ids);
+        }
+        let events = match pgn_ids {
+            Some(PgnIds::J1939) => transport[index as usize].push(frame, Instant::now()),
+            // Single-frame messages were decoded with the frame.
+            Some(PgnIds::Nmea2000) => fast_packets[index as usize]
+                .push(frame, Instant::now())
+                .filter(|message| message.reassembled)
+                .map(TpEvent::Message)
+                .into_iter()
+                .collect(),
+            None => return,
+        };
+        for event in events {
+            match (event, &mut json) {
+                (TpEvent::Message(message), Some(json)) => {
+                    if let Err(err) = json.write_message(index, &message) {
+                        error!("JSON output failed: {err}");
+                    }
+                }
+                (TpEvent::Message(message), None) => {
+                    let label = format!("CAN{}", index + 1);
+                    println!("{label} reassembled: {} bytes, Data={:?}", message.data.len(), message.data);
+                    match pgn_ids {
+                        Some(PgnIds::Nmea2000) => println!("{label}   {}", format_n2k(&message)),
+                        _ => print_j1939(&label, &message),
+                    }
+                }
+                (TpEvent::Failed(failure), Some(_)) => warn!("CAN{}: {failure}", index + 1),
+                (TpEvent::Failed(failure), None) => println!("CAN{}   {failure}", index + 1),
+            }
+        }
+    })
+}
+
+/// Answers remote frames for the --rtr-reply IDs on the channel they arrived on.
+fn spawn_rtr_responder(session: &Arc<Session>, replies: &[Frame]) -> thread::JoinHandle<(&'static str, u64)> {
+    let mut responder = RtrResponder::new();
+    for reply in replies {
+        responder.insert(*reply);
+    }
+    spawn_consumer("RTR responder", session, move |session, index, frame| {
+        if let Some(reply) = responder.respond(frame) {
+            match session.handles[index as usize].transmit(reply) {
+                Ok(()) => {
+                    debug!("CAN{} answered RTR for {}", index + 1, reply.id());
+                    session.log_tx(index, reply);
+                }
+                Err(err) => warn!("{err}"),
+            }
+        }
+    })
+}
+
+/// Feeds received frames through the --processor pipeline, sending what it emits.
+fn start_processors(session: &Arc<Session>, processors: Vec<Box<dyn FrameProcessor>>) -> (thread::JoinHandle<(&'static str, u64)>, Arc<Pipeline>) {
+    let emitting = Arc::clone(session);
+    let emit: EmitHandler = Arc::new(move |channel, frame| match emitting.handles.get(channel as usize) {
+        Some(_) => {
+            if let Err(err) = emitting.transmit(channel, frame) {
+                warn!("{err}");
+            }
+        }
+        None => warn!("Processor emitted a frame for missing channel CAN{}", channel + 1),
+    });
+    let pipeline = Arc::new(Pipeline::start(processors, CONSUMER_QUEUE, Duration::from_millis(100), emit));
+    let input = Arc::clone(&pipeline);
+    let consumer = spawn_consumer("processor", session, move |_, index, frame| {
+        input.push(index, *frame);
+    });
+    (consumer, pipeline)
+}
+
+/// Runs the --script's start, then its frame handler on every received frame if it has one.
+#[cfg(feature = "scripting")]
+fn start_script(session: &Arc<Session>, scheduler: &Arc<Scheduler>, mut script: FrameScript) -> Option<thread::JoinHandle<(&'static str, u64)>> {
+    // Runtime errors cost the frame that caused them; the script keeps running.
+    match script.start() {
+        Ok(actions) => perform(session, scheduler, actions),
+        Err(err) => warn!("{err}"),
+    }
+    let scheduler = Arc::clone(scheduler);
+    script.handles_frames().then(|| {
+        spawn_consumer("script", session, move |session, index, frame| match script.on_frame(index, frame) {
+            Ok(actions) => perform(session, &scheduler, actions),
+            Err(err) => warn!("{err}"),
+        })
+    })
+}
+
+#[cfg(feature = "scripting")]
+fn perform(session: &Session, scheduler: &Scheduler, actions: Vec<ScriptAction>) {
+    for action in actions {
+        match action {
+            ScriptAction::Transmit { channel, frame } => {
+                if let Err(err) = session.transmit(channel, &frame) {
+                    warn!("{err}");
+                }
+            }
+            ScriptAction::Log(message) => info!("{message}"),
+            ScriptAction::SetCyclic { index, data } => {
+                let updated = scheduler.ids().get(index).is_some_and(|&id| {
+                    let frame = scheduler.frame(id).and_then(|frame| Frame::new(frame.id(), &data));
+                    frame.is_some_and(|frame| scheduler.update(id, frame))
+                });
+                if !updated {
+                    warn!("script: no cyclic message {index}");
+                }
+            }
+        }
+    }
+}
+
+/// Starts what sends without being asked: the --replay thread, --fuzz, or the cyclic
+/// messages of --cyclic and --tx-table, which run on `scheduler`.
+fn start_transmit(
+    session: &Arc<Session>,
+    args: &Args,
+    scheduler: &Scheduler,
+    replay_log: Option<Vec<CandumpRecord>>,
+    tx_table: &[TxEntry],
+    cyclic_enabled: bool,
+) -> io::Result<Option<thread::JoinHandle<()>>> {
+    let label = format!("CAN{}", args.channel + 1);
+    let handle = &session.handles[args.channel as usize];
+    handle.channel().set_send_type(args.send_type);
+    if matches!(args.send_type, SendType::SelfTest | SendType::SingleShotSelfTest) {
+        info!("{label} self-test send type: transmitted frames will also be received back");
+    }
+    if args.mode_for(args.channel) == ChannelMode::ListenOnly && args.demo.transmits() {
+        info!("{label}: listen-only, skipping the transmit demo");
+    }
+    if let Some(records) = replay_log {
+        let session = Arc::clone(session);
+        let (speed, looped, channel_override) = (args.speed, args.loop_replay, args.replay_channel);
+        return Ok(Some(thread::spawn(move || loop {
+            let finished = replay(&records, speed, &session.running, |r| r.time, |record| {
+                if let Err(err) = session.transmit(channel_override.unwrap_or(record.channel), &record.frame) {
+                    warn!("{err}");
+                }
+            });
+            if !finished || !looped {
+                info!("Replay finished");
+                break;
+            }
+        })));
+    }
+    if args.fuzz {
+        return start_fuzz(args, session).map(Some);
+    }
+    if cyclic_enabled {
+        if args.cyclic.is_empty() && tx_table.is_empty() {
+            let frame = Frame::new(Id::Standard(0x1), &[0x01]).expect("single byte payload");
+            scheduler.add(handle.channel(), frame, Duration::from_millis(10));
+        }
+        for (frame, period) in &args.cyclic {
+            scheduler.add(handle.channel(), *frame, *period);
+        }
+        schedule_tx_table(scheduler, &session.handles, tx_table);
+        info!("Sending {} cyclic messages", scheduler.len());
+    }
+    Ok(None)
+}
+
+/// Starts the --server, on CAN`--channel + 1` for slcan and both channels otherwise.
+fn start_server(args: &Args, handles: &[ChannelHandle]) -> Result<Option<RunningServer>, Box<dyn Error>> {
+    Ok(match args.server {
+        Some(Server::Socketcand) => {
+            let port = args.port.unwrap_or(Server::Socketcand.default_port());
+            let server = SocketcandServer::start(("0.0.0.0", port), &channels(handles))?;
+            info!("socketcand server listening on port {}", server.local_addr().port());
+            Some(RunningServer::Socketcand(server))
+        }
+        Some(Server::Slcan) => Some(RunningServer::Slcan(start_slcan(args, &handles[args.channel as usize])?)),
+        Some(Server::Ws) => {
+            let port = args.port.unwrap_or(Server::Ws.default_port());
+            let server = WsServer::start(("0.0.0.0", port), &channels(handles), args.max_clients as usize)?;
+            info!("WebSocket server listening on port {} (at most {} clients)", server.local_addr().port(), args.max_clients);
+            Some(RunningServer::Ws(server))
+        }
+        #[cfg(feature = "grpc")]
+        Some(Server::Grpc) => {
+            let port = args.port.unwrap_or(Server::Grpc.default_port());
+            let server = GrpcServer::start(([0, 0, 0, 0], port).into(), &channels(handles))?;
+            info!("gRPC server listening on port {}", server.local_addr().port());
+            Some(RunningServer::Grpc(server))
+        }
+        None => None,
+    })
+}
+
+fn stop_server(server: RunningServer) {
+    match server {
+        RunningServer::Socketcand(mut server) => server.stop(),
+        RunningServer::Slcan(mut bridge) => bridge.stop(),
+        RunningServer::Ws(mut server) => {
+            server.stop();
+            if server.dropped() > 0 || server.rejected() > 0 {
+                info!(
+                    "WebSocket clients lost {} frames to slow sockets; {} connections refused at the client limit",
+                    server.dropped(),
+                    server.rejected()
+                );
+            }
+        }
+        #[cfg(feature = "grpc")]
+        RunningServer::Grpc(mut server) => server.stop(),
+    }
+}
+
+/// Publishes both channels to the --mqtt broker at `host:port`.
+#[cfg(feature = "mqtt")]
+fn start_mqtt(args: &Args, session: &Session, host: &str, port: u16) -> MqttBridge {
+    let mut config = MqttConfig::new(host, port);
+    config.topic_prefix = args.topic_prefix.clone();
+    config.qos = args.mqtt_qos;
+    config.reconnect_delay = Duration::from_secs_f64(args.mqtt_reconnect.max(0.0));
+    config.buffer = args.mqtt_buffer as usize;
+    if let Some(client_id) = &args.mqtt_client_id {
+        config.client_id = client_id.clone();
+    }
+    info!("MQTT: publishing to {host}:{port} under {}", config.topic_prefix);
+    let (prompt, monitor) = (Arc::clone(&session.prompt), session.monitor);
+    MqttBridge::start(&config, &channels(&session.handles), session.dbc.clone(), Box::new(move |event| {
+        let message = match event {
+            MqttEvent::Connected => "MQTT: connected".to_string(),
+            MqttEvent::Disconnected(err) => format!("MQTT: disconnected ({err}), retrying"),
+            MqttEvent::TransmitFailed { topic, error } => format!("MQTT: {topic}: {error}"),
+        };
+        report(message, monitor.then_some(&*prompt));
+    }))
+}
+
+/// Forwards between CAN1 and CAN2 with --gateway, logging the counts when they change.
+fn spawn_gateway(session: &Arc<Session>, batch: usize, rules: GatewayRules) -> thread::JoinHandle<()> {
+    let gateway = Gateway::start(session.handles[0].channel(), session.handles[1].channel(), batch, rules);
+    info!("Gateway running: forwarding CAN1 <-> CAN2");
+    let session = Arc::clone(session);
+    thread::spawn(move || {
+        let mut last = [(0, 0, 0, 0); 2];
+        while session.running.load(Ordering::SeqCst) {
+            let now = [0, 1].map(|dir| {
+                let stats = gateway.stats(dir);
+                (stats.forwarded(), stats.dropped(), stats.filtered(), stats.echoes())
+            });
+            if now != last {
+                let [(f1, d1, r1, e1), (f2, d2, r2, e2)] = now;
+                info!(
+                    "CAN1->CAN2 forwarded {f1} dropped {d1} filtered {r1} echoes {e1} | \
+                     CAN2->CAN1 forwarded {f2} dropped {d2} filtered {r2} echoes {e2}"
+                );
+                last = now;
+            }
+            thread::sleep(Duration::from_millis(1000));
+        }
+    })
+}
+
+/// Reports the --expect IDs that went quiet.
+fn spawn_watchdog(session: &Arc<Session>) -> thread::JoinHandle<()> {
+    let session = Arc::clone(session);
+    thread::spawn(move || {
+        while session.running.load(Ordering::SeqCst) {
+            let events = session.watchdog.lock().unwrap().poll(Instant::now());
+            for event in &events {
+                report_watchdog(event, session.monitor_prompt());
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    })
+}
+
+/// Ends captures whose post-trigger window is over while the bus is quiet.
+fn spawn_capture_poll(session: &Arc<Session>) -> thread::JoinHandle<()> {
+    let session = Arc::clone(session);
+    thread::spawn(move || {
+        let capture = session.capture.as_ref().expect("checked when spawned");
+        while session.running.load(Ordering::SeqCst) {
+            for event in capture.lock().unwrap().poll(Instant::now()) {
+                report(event.to_string(), session.monitor_prompt());
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    })
+}
+
+fn spawn_monitor(session: &Arc<Session>, options: MonitorOptions) -> thread::JoinHandle<()> {
+    let session = Arc::clone(session);
+    thread::spawn(move || {
+        let shared = monitor::Shared {
+            tracker: &session.tracker,
+            pause: &session.pause,
+            prompt: &session.prompt,
+            hide_static: &session.hide_static,
+            plots: &session.plots,
+            show_plots: &session.show_plots,
+            handles: &session.handles,
+            integrity: &session.integrity,
+        };
+        diag::hold();
+        let result = monitor::run(&shared, &session.running, (&session.received, &session.sent), &options);
+        diag::release();
+        if let Err(err) = result {
+            error!("Monitor view failed: {err}");
+        }
+    })
+}
+
+/// Warns about what each channel lost or clamped during the run, and sums up its transmit
+/// queue.
+fn report_channels(handles: &[ChannelHandle]) {
+    for (slot, handle) in handles.iter().enumerate() {
+        let channel = handle.channel();
+        let dropped = channel.dropped_while_disconnected();
+        if dropped > 0 {
+            warn!("CAN{} dropped {dropped} frames while disconnected", slot + 1);
+        }
+        let suspect = channel.suspect_frames();
+        if suspect > 0 {
+            warn!("CAN{} received {suspect} malformed frames from the DLL, clamped and marked suspect", slot + 1);
+        }
+        let stats = channel.shaper_stats();
+        if stats.delayed > 0 || stats.dropped > 0 {
+            info!(
+                "CAN{} transmit queue: {} frames sent at once, {} delayed, {} dropped ({} refused with the queue full), {} still queued",
+                slot + 1,
+                stats.passed,
+                stats.delayed,
+                stats.dropped,
+                stats.refused,
+                stats.queued
+            );
+        }
+    }
+}
+
+/// Prints every frame of a self-test with its result, one phase at a time, and the verdict.
+fn print_self_test(report: &SelfTestReport) {
+    let phases = report
+        .internal
+        .iter()
+        .enumerate()
+        .map(|(slot, checks)| (format!("CAN{} internal loopback", slot + 1), checks))
+        .chain(report.external.iter().map(|checks| ("CAN1 -> CAN2".to_string(), checks)));
+    for (name, checks) in phases {
+        let passed = checks.iter().filter(|check| check.passed()).count();
+        println!("{name}: {passed}/{} passed", checks.len());
+        for check in checks {
+            println!("  {check}");
+        }
+    }
+    println!("Self-test {}", report.verdict());
+}
+
+/// Shows an adapter's connection change on the monitor's message line; the device logs it.
+fn report_connection(adapter: u32, state: ConnectionState, prompt: Option<&Prompt>) {
+    show(format!("Adapter {adapter}: connection {state}"), prompt);
+}
+
+/// Logs a status message, also showing it on the monitor's prompt line while the monitor view
+/// holds back the log.
+fn report(message: String, prompt: Option<&Prompt>) {
+    info!("{message}");
+    show(message, prompt);
+}
+
+/// Like [`report`], as a warning.
+fn report_error(message: String, prompt: Option<&Prompt>) {
+    warn!("{message}");
+    show(message, prompt);
+}
+
+fn show(message: String, prompt: Option<&Prompt>) {
+    if let Some(prompt) = prompt {
+        prompt.set_message(message);
+    }
+}
+
+/// Logs a watchdog event, timeouts as warnings, and shows it on the monitor's message line.
+fn report_watchdog(event: &WatchdogEvent, prompt: Option<&Prompt>) {
+    let message = match event {
+        WatchdogEvent::Timeout { id, last_seen: None, window } => {
+            format!("WATCHDOG: {id} never arrived (expected within {} ms)", window.as_millis())
+        }
+        WatchdogEvent::Timeout { id, window, .. } => {
+            format!("WATCHDOG: {id} missing (no frame within {} ms)", window.as_millis())
+        }
+        WatchdogEvent::Recovered { id, outage } => {
+            format!("WATCHDOG: {id} resumed after {} ms", outage.as_millis())
+        }
+    };
+    match event {
+        WatchdogEvent::Timeout { .. } => warn!("{message}"),
+        WatchdogEvent::Recovered { .. } => info!("{message}"),
+    }
+    show(message, prompt);
+}
+
+/// Frames a consumer can fall behind by before it starts missing them.
+const CONSUMER_QUEUE: usize = 10_000;
+
+/// Minimum time between warnings about failing `VCI_Receive` calls on one channel.
+const RECEIVE_WARNING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Runs `consume` on a thread for every frame received on the session's channels that passes
+/// the software filter, until the run ends. The thread returns `name` and the number of frames
+/// it missed because it couldn't keep up.
+fn spawn_consumer(
+    name: &'static str,
+    session: &Arc<Session>,
+    mut consume: impl FnMut(&Session, u32, &Frame) + Send + 'static,
+) -> thread::JoinHandle<(&'static str, u64)> {
+    // Every channel's frames, tagged with the channel, go through one queue so `consume` runs
+    // on a single thread in arrival order.
+    let (merged_tx, merged) = mpsc::sync_channel::<(u32, Frame)>(CONSUMER_QUEUE);
+    let dropped = session.metrics.counter(
+        "rustcanbus_consumer_dropped_frames_total",
+        "Frames a consumer missed because it fell behind.",
+        &[("consumer", name)],
+    );
+    let forwarders: Vec<_> = (0..)
+        .zip(&session.handles)
+        .map(|(index, handle)| {
+            let subscription = handle.channel().subscribe(CONSUMER_QUEUE);
+            let (running, merged_tx, dropped) = (Arc::clone(&session.running), merged_tx.clone(), Arc::clone(&dropped));
+            thread::spawn(move || {
+                let mut counted = 0;
+                while running.load(Ordering::SeqCst) {
+                    let frame = subscription.recv_timeout(Duration::from_millis(100));
+                    // Kept current for the metrics endpoint rather than summed up at the end.
+                    let missed = subscription.dropped();
+                    dropped.fetch_add(missed - counted, Ordering::Relaxed);
+                    counted = missed;
+                    match frame {
+                        Ok(frame) => {
+                            if merged_tx.send((index, frame)).is_err() {
+                                break;
+                            }
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                dropped.fetch_add(subscription.dropped() - counted, Ordering::Relaxed);
+            })
+        })
+        .collect();
+    drop(merged_tx);
+    let session = Arc::clone(session);
+    thread::spawn(move || {
+        while session.running.load(Ordering::SeqCst) {
+            match merged.recv_timeout(Duration::from_millis(100)) {
+                Ok((index, frame)) if session.filters.read().unwrap()[index as usize].accepts(&frame) => consume(&session, index, &frame),
+                Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        // Unblocks forwarders still waiting to hand over a frame.
+        drop(merged);
+        for forwarder in forwarders {
+            forwarder.join().unwrap();
+        }
+        (name, dropped.load(Ordering::Relaxed))
+    })
+}
+
+/// Keeps the terminal in raw mode while alive, so it is restored however the keyboard thread
+/// ends.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        enable_raw_mode()?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+    }
+}
+
+/// Runs --fuzz on its own scheduler until the count or duration is reached or the program
+/// stops; the thread ends with it.
+fn start_fuzz(args: &Args, session: &Arc<Session>) -> io::Result<thread::JoinHandle<()>> {
+    let seed = args
+        .fuzz_seed
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64);
+    let (first, last) = args.fuzz_ids;
+    let mut config = FuzzConfig::new(first, last, seed);
+    (config.min_dlc, config.max_dlc) = args.fuzz_dlc;
+    config.target = args.fuzz_target.map(|id| (id, f64::from(args.fuzz_weight) / 100.0));
+    config.bit_flip = args.fuzz_bit_flip;
+    let fuzz_log = match &args.fuzz_log {
+        Some(path) => Some(Mutex::new(open_log(path, LogFormat::Candump, &LogOptions::new(args, None))?)),
+        None => None,
+    };
+    let (slot, sent, observed) = (args.channel, Arc::new(AtomicU64::new(0)), Arc::clone(session));
+    let observer_sent = Arc::clone(&sent);
+    let mut scheduler = Scheduler::with_observer(Box::new(move |_, frame, result| match result {
+        Ok(()) => {
+            observer_sent.fetch_add(1, Ordering::SeqCst);
+            observed.sent.fetch_add(1, Ordering::SeqCst);
+            observed.log_tx(slot, frame);
+            if let Some(log) = &fuzz_log {
+                let mut log = log.lock().unwrap();
+                // Flushed every time: the log matters most when the device under test
+                // misbehaves, and the run may well be killed then.
+                if let Err(err) = log.write_frame(slot, frame, Direction::Tx).and_then(|()| log.flush()) {
+                    error!("Fuzz log write failed: {err}");
+                }
+            }
+        }
+        Err(err) => warn!("{err}"),
+    }));
+    let mut fuzzer = Fuzzer::new(config);
+    scheduler.add_generated(session.handles[slot as usize].channel(), Duration::from_secs_f64(1.0 / args.fuzz_rate), args.fuzz_count, move || {
+        fuzzer.next().expect("the fuzzer never runs out")
+    });
+    info!(
+        "CAN{}: fuzzing at {} frames/s with seed {seed} (repeat with --fuzz-seed {seed})",
+        slot + 1,
+        args.fuzz_rate
+    );
+    let deadline = args.fuzz_duration.map(|secs| Instant::now() + Duration::from_secs_f64(secs.max(0.0)));
+    let running = Arc::clone(&session.running);
+    Ok(thread::spawn(move || {
+        while running.load(Ordering::SeqCst) && !scheduler.is_empty() && deadline.is_none_or(|at| Instant::now() < at) {
+            thread::sleep(Duration::from_millis(20));
+        }
+        scheduler.stop();
+        info!("Fuzzing finished after {} frames", sent.load(Ordering::SeqCst));
+    }))
+}
+
+fn start_slcan(args: &Args, handle: &ChannelHandle) -> io::Result<SlcanBridge> {
+    #[cfg(unix)]
+    if args.pty {
+        let bridge = SlcanBridge::pty(handle.channel())?;
+        let path = bridge.pty_path().map(Path::display).map(|path| path.to_string()).unwrap_or_default();
+        info!("CAN{} slcan bridge on {path}; attach with: slcand -o -c {path} slcan0", args.channel + 1);
+        return Ok(bridge);
+    }
+    let port = args.port.unwrap_or(Server::Slcan.default_port());
+    let bridge = SlcanBridge::listen(("0.0.0.0", port), handle.channel())?;
+    let port = bridge.local_addr().map_or(port, |addr| addr.port());
+    info!("CAN{} slcan bridge listening on port {port}", args.channel + 1);
+    Ok(bridge)
+}
+
+/// Writes a line with the frame counts, rates and bus loads every `interval` until `running` is
+/// cleared, and once more then, e.g.
+/// `1700000000.123 up 3600 s: rx 36000 (10.0/s) tx 600 (0.2/s), CAN1 load 1.2%, CAN2 load 0.0%`.
+fn spawn_status_lines(mut out: Box<dyn Write + Send>, interval: Duration, session: &Arc<Session>) -> thread::JoinHandle<()> {
+    let session = Arc::clone(session);
+    thread::spawn(move || {
+        let (running, received, sent) = (&session.running, &session.received, &session.sent);
+        let started = Instant::now();
+        let mut last = (started, 0, 0);
+        loop {
+            let due = last.0 + interval;
+            while running.load(Ordering::SeqCst) && Instant::now() < due {
+                thread::sleep(due.saturating_duration_since(Instant::now()).min(Duration::from_millis(100)));
+            }
+            let (now, rx, tx) = (Instant::now(), received.load(Ordering::SeqCst), sent.load(Ordering::SeqCst));
+            let seconds = now.duration_since(last.0).as_secs_f64().max(f64::EPSILON);
+            let loads: Vec<String> = (1..)
+                .zip(&session.handles)
+                .map(|(number, handle)| match handle.channel().bus_load() {
+                    Some(load) => format!("CAN{number} load {load:.1}%"),
+                    None => format!("CAN{number} load n/a"),
+                })
+                .collect();
+            let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+            let line = format!(
+                "{time:.3} up {} s: rx {rx} ({:.1}/s) tx {tx} ({:.1}/s), {}",
+                now.duration_since(started).as_secs(),
+                rx.saturating_sub(last.1) as f64 / seconds,
+                tx.saturating_sub(last.2) as f64 / seconds,
+                loads.join(", ")
+            );
+            if writeln!(out, "{line}").and_then(|()| out.flush()).is_err() {
+                return;
+            }
+            last = (now, rx, tx);
+            if !running.load(Ordering::SeqCst) {
+                return;
+            }
+        }
+    })
+}
+
+/// Leaves raw mode, and the monitor's alternate screen, from the panic hook.
+fn restore_terminal(monitor: bool) {
+    let _ = disable_raw_mode();
+    if monitor {
+        let mut out = io::stdout();
+        let _ = queue!(out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = out.flush();
+    }
+}
+
+/// Shows the prompt line (or the last result) below the scrolling output.
+fn draw_stream_prompt(prompt: &Prompt) {
+    let state = prompt.snapshot();
+    let mut out = io::stdout();
+    let line = match (&state.input, &state.message) {
+        (Some(input), _) => format!("{}{input}", prompt::LABEL),
+        (None, Some(message)) => format!("{message}\n"),
+        (None, None) => String::new(),
+    };
+    let _ = queue!(out, cursor::MoveToColumn(0), terminal::Clear(terminal::ClearType::CurrentLine), Print(line));
+    if state.input.is_none() {
+        let _ = queue!(out, cursor::MoveToColumn(0));
+    }
+    let _ = out.flush();
+}
+
+/// How frame logs are written, whatever their format.
+#[derive(Clone)]
+struct LogOptions {
+    rotation: Option<Rotation>,
+    compression: Option<Compression>,
+    /// The DBC whose decoded signals MDF logs also get.
+    signals: Option<Arc<Dbc>>,
+}
+
+impl LogOptions {
+    fn new(args: &Args, dbc: Option<&Dbc>) -> Self {
+        Self {
+            rotation: args.log_rotate,
+            compression: args.log_compress.map(Into::into),
+            signals: dbc.filter(|_| args.mdf_signals).cloned().map(Arc::new),
+        }
+    }
+}
+
+/// Opens a frame log; compressed, `.gz` or `.zst` is added to `path` unless it ends with it.
+fn open_log(path: &Path, format: LogFormat, options: &LogOptions) -> io::Result<Box<dyn FrameSink>> {
+    let path = match options.compression {
+        Some(compression) if path.extension().is_none_or(|extension| extension != compression.extension()) => {
+            let mut name = path.as_os_str().to_owned();
+            name.push(format!(".{}", compression.extension()));
+            PathBuf::from(name)
+        }
+        _ => path.to_path_buf(),
+    };
+    if let Some(rotation) = options.rotation {
+        let options = options.clone();
+        let sink = RotatingSink::create(&path, rotation, Box::new(move |out| log_writer(out, format, &options)))?;
+        info!("Logging to {}", sink.current_path().display());
+        return Ok(Box::new(sink));
+    }
+    log_writer(BufWriter::new(File::create(&path)?), format, options)
+}
+
+fn log_writer<W: Write + Seek + Send + 'static>(out: W, format: LogFormat, options: &LogOptions) -> io::Result<Box<dyn FrameSink>> {
+    match (format, options.compression) {
+        (LogFormat::Mdf, None) => Ok(Box::new(MdfWriter::with_signals(out, options.signals.clone()))),
+        (_, Some(compression)) => stream_log_writer(CompressedWriter::new(compression, out)?, format),
+        (_, None) => stream_log_writer(out, format),
+    }
+}
+
+/// The writers of the formats that are written front to back, and so can be compressed.
+fn stream_log_writer<W: Write + Send + 'static>(out: W, format: LogFormat) -> io::Result<Box<dyn FrameSink>> {
+    Ok(match format {
+        LogFormat::Candump => Box::new(CandumpWriter::new(out)),
+        LogFormat::Asc => Box::new(AscWriter::new(out)),
+        LogFormat::Csv => Box::new(CsvWriter::new(out)),
+        LogFormat::Pcap => Box::new(PcapngWriter::new(out)),
+        LogFormat::Trc => Box::new(TrcWriter::new(out)),
+        LogFormat::Mdf => return Err(io::Error::other("MDF logs can't be compressed")),
+    })
+}
+
+/// `FILE` for a one-off capture; `FILE-N.ext` for capture `n` when re-arming.
+fn capture_path(path: &Path, n: u32, rearm: bool) -> PathBuf {
+    if !rearm {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}-{n}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{n}"),
+    };
+    path.with_file_name(name)
+}
+
+fn load_tx_table(path: &Path) -> Result<Vec<TxEntry>, Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    Ok(parse_tx_table(&text).map_err(|err| format!("{}: {err}", path.display()))?)
+}
+
+fn schedule_tx_table(scheduler: &Scheduler, handles: &[ChannelHandle], entries: &[TxEntry]) {
+    for entry in entries {
+        let channel = handles[entry.channel as usize].channel();
+        let id = scheduler.add_with(channel, entry.frame, entry.period, entry.start_delay, entry.repeat);
+        if entry.integrity != IntegritySpec::default() {
+            scheduler.protect(id, entry.integrity.clone());
+        }
+    }
+}
+
+fn gap_ms(gap: Option<Duration>) -> String {
+    gap.map_or_else(String::new, |gap| format!("{:.1}", gap.as_secs_f64() * 1000.0))
+}
+
+/// The ID column of a table, followed by the `--names` name in a column of its own when names
+/// are loaded.
+fn id_column(id: Option<Id>) -> String {
+    let Some(names) = cli::names() else {
+        return id.map_or_else(|| format!("{:<10}", "ID"), |id| format!("{:<10}", id.to_string()));
+    };
+    let name = match id {
+        Some(id) => names.name(id).unwrap_or(""),
+        None => "Name",
+    };
+    let width = names.max_name_len().max(4);
+    format!("{:<10} {name:<width$}", id.map_or_else(|| "ID".to_string(), |id| id.to_string()))
+}
+
+fn print_stats(tracker: &IdTracker) {
+    println!("{:<5} {} {:>8} {:>8} {:>10} {:>10} {:>10}", "Ch", id_column(None), "Count", "Rate Hz", "Min ms", "Max ms", "Mean ms");
+    for entry in tracker.iter() {
+        let stats = &entry.stats;
+        println!(
+            "CAN{:<2} {} {:>8} {:>8.1} {:>10} {:>10} {:>10}",
+            entry.channel + 1,
+            id_column(Some(entry.frame.id())),
+            stats.count(),
+            stats.rate_hz(),
+            gap_ms(stats.min_gap()),
+            gap_ms(stats.max_gap()),
+            gap_ms(stats.mean_gap())
+        );
+    }
+}
+
+/// `0,1,6`, or `-` for none.
+fn byte_list(bytes: &[usize]) -> String {
+    if bytes.is_empty() {
+        return "-".to_string();
+    }
+    bytes.iter().map(ToString::to_string).collect::<Vec<_>>().join(",")
+}
+
+fn print_discovery(discovery: &Discovery) {
+    let standard = discovery.standard().count();
+    println!(
+        "{} unique IDs ({standard} standard, {} extended) in {} frames",
+        discovery.len(),
+        discovery.len() - standard,
+        discovery.frames()
+    );
+    for (title, entries) in [("Standard IDs", discovery.standard().collect::<Vec<_>>()), ("Extended IDs", discovery.extended().collect())] {
+        if entries.is_empty() {
+            continue;
+        }
+        println!("{title}:");
+        println!("{:<5} {} {:>8} {:>10} {:<12} {:<8} Changing bytes", "Ch", id_column(None), "Count", "Period ms", "DLC", "Changed");
+        for entry in entries {
+            let dlcs: Vec<String> = entry.dlcs().iter().map(ToString::to_string).collect();
+            println!(
+                "CAN{:<2} {} {:>8} {:>10} {:<12} {:<8} {}",
+                entry.channel + 1,
+                id_column(Some(entry.id)),
+                entry.count,
+                gap_ms(entry.period()),
+                format!("{}{}", dlcs.join(","), if entry.remote { " RTR" } else { "" }),
+                if entry.payload_changed() { "yes" } else { "no" },
+                byte_list(&entry.changing_bytes())
+            );
+        }
+    }
+}
+
+/// One ID of `--discover` with `--output json`, e.g.
+/// `{"ch":0,"id":"0x123","ext":false,"count":300,"period_ms":100.0,"dlcs":[8],"rtr":false,"changed":true,"changing_bytes":[0,7]}`,
+/// with a `"name"` for IDs named by `--names`.
+#[derive(Serialize)]
+struct DiscoveryJson {
+    ch: u32,
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'static str>,
+    ext: bool,
+    count: u64,
+    period_ms: Option<f64>,
+    dlcs: Vec<u8>,
+    rtr: bool,
+    changed: bool,
+    changing_bytes: Vec<usize>,
+}
+
+fn discovery_json(entry: &DiscoveredId) -> DiscoveryJson {
+    DiscoveryJson {
+        ch: entry.channel,
+        id: format!("0x{:X}", entry.id.raw()),
+        name: cli::names().and_then(|names| names.name(entry.id)),
+        ext: entry.id.is_extended(),
+        count: entry.count,
+        period_ms: entry.period().map(|period| period.as_secs_f64() * 1000.0),
+        dlcs: entry.dlcs(),
+        rtr: entry.remote,
+        changed: entry.payload_changed(),
+        changing_bytes: entry.changing_bytes(),
+    }
+}
+
+fn write_discovery_csv(path: &Path, discovery: &Discovery) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "channel,id,extended,count,period_ms,dlcs,rtr,changed,changing_bytes")?;
+    for entry in discovery.iter() {
+        let dlcs: Vec<String> = entry.dlcs().iter().map(ToString::to_string).collect();
+        let bytes: Vec<String> = entry.changing_bytes().iter().map(ToString::to_string).collect();
+        writeln!(
+            out,
+            "{},0x{:X},{},{},{},{},{},{},{}",
+            entry.channel,
+            entry.id.raw(),
+            entry.id.is_extended() as u8,
+            entry.count,
+            gap_ms(entry.period()),
+            dlcs.join(" "),
+            entry.remote as u8,
+            entry.payload_changed() as u8,
+            bytes.join(" ")
+        )?;
+    }
+    out.flush()
+}
+
+/// Runs a --fault-alert command without waiting for it.
+fn spawn_alert(command: &str, channel: u32, fault: Fault, count: u64) {
+    #[cfg(windows)]
+    let mut shell = process::Command::new("cmd");
+    #[cfg(windows)]
+    shell.arg("/C");
+    #[cfg(not(windows))]
+    let mut shell = process::Command::new("sh");
+    #[cfg(not(windows))]
+    shell.arg("-c");
+    shell
+        .arg(command)
+        .env("RUSTCANBUS_CHANNEL", (channel + 1).to_string())
+        .env("RUSTCANBUS_FAULT", fault.to_string())
+        .env("RUSTCANBUS_COUNT", count.to_string())
+        .stdin(process::Stdio::null());
+    match shell.spawn() {
+        Ok(mut child) => {
+            let command = command.to_string();
+            thread::spawn(move || match child.wait() {
+                Ok(status) if !status.success() => warn!("Fault alert command '{command}' exited with {status}"),
+                Ok(_) => {}
+                Err(err) => warn!("Fault alert command '{command}': {err}"),
+            });
+        }
+        Err(err) => error!("Fault alert command '{command}' failed to start: {err}"),
+    }
+}
+
+fn print_faults(trackers: &[FaultTracker; 2]) {
+    for (slot, tracker) in trackers.iter().enumerate() {
+        let (passive, bus_off) = (tracker.count(Fault::ErrorPassive), tracker.count(Fault::BusOff));
+        if passive == 0 && bus_off == 0 {
+            continue;
+        }
+        info!("CAN{} faults: entered error-passive {passive} time(s), bus-off {bus_off} time(s)", slot + 1);
+        for record in tracker.history() {
+            let recovery = match record.recovery {
+                Some(recovery) => format!("recovered after {:.3} s", recovery.as_secs_f64()),
+                None => "still in it at exit".to_string(),
+            };
+            info!("CAN{}   {} at +{:.3} s, {recovery}", slot + 1, record.fault, record.entered.as_secs_f64());
+        }
+        let listed = tracker.history().len() as u64;
+        if passive + bus_off > listed {
+            info!("CAN{}   ...and {} more", slot + 1, passive + bus_off - listed);
+        }
+    }
+}
+
+/// `swapped` if CAN1 is the adapter's second port, whose offset the report gives.
+fn print_alignment(report: &AlignmentReport, swapped: bool) {
+    let behind = |us: i64| {
+        let us = if swapped { -us } else { us };
+        format!("{:.3} ms {}", us.unsigned_abs() as f64 / 1000.0, if us >= 0 { "behind" } else { "ahead of" })
+    };
+    let Some(start) = report.start_offset_us else {
+        return;
+    };
+    match (report.matched_offset_us, report.residual_mean_us, report.residual_max_us) {
+        (Some(matched), Some(mean), Some(max)) => info!(
+            "Channel alignment: CAN2's clock is {} CAN1's from {} frames seen on both channels, residual skew {:.3} ms on average and {:.3} ms at most; the start times gave {}",
+            behind(matched),
+            report.matched,
+            mean / 1000.0,
+            max as f64 / 1000.0,
+            behind(start)
+        ),
+        _ => info!("Channel alignment: CAN2's clock is {} CAN1's from the channels' start times", behind(start)),
+    }
+}
+
+fn print_integrity(integrity: &IntegrityChecker) {
+    println!("{:<5} {:<10} {:>8} {:>8} {:>8} {:>8} {:>9} {:>9}", "Ch", "ID", "Frames", "Skips", "Missed", "Repeats", "Checksum", "Too short");
+    for (channel, id, stats) in integrity.iter() {
+        println!(
+            "CAN{:<2} {:<10} {:>8} {:>8} {:>8} {:>8} {:>9} {:>9}",
+            channel + 1,
+            id.to_string(),
+            stats.frames,
+            stats.skips,
+            stats.missed,
+            stats.repeats,
+            stats.checksum_failures,
+            stats.too_short
+        );
+    }
+}
+
+fn write_stats_csv(path: &Path, tracker: &IdTracker) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "channel,id,extended,count,rate_hz,min_gap_ms,max_gap_ms,mean_gap_ms")?;
+    for entry in tracker.iter() {
+        let stats = &entry.stats;
+        writeln!(
+            out,
+            "{},0x{:X},{},{},{:.1},{},{},{}",
+            entry.channel,
+            entry.frame.id().raw(),
+            entry.frame.is_extended() as u8,
+            stats.count(),
+            stats.rate_hz(),
+            gap_ms(stats.min_gap()),
+            gap_ms(stats.max_gap()),
+            gap_ms(stats.mean_gap())
+        )?;
+    }
+    out.flush()
+}
+
+fn print_latency(report: &LatencyReport) {
+    let us = |latency: Option<Duration>| latency.map_or_else(|| "-".to_string(), |l| format!("{} us", l.as_micros()));
+    println!(
+        "Sent {}, received {}, lost {} ({:.2}%), send failures {}, duplicates {}, out of order {}",
+        report.sent(),
+        report.received(),
+        report.lost(),
+        report.loss_percent(),
+        report.send_failures,
+        report.duplicates,
+        report.reordered
+    );
+    println!(
+        "Latency min {} avg {} max {} p99 {}",
+        us(report.min()),
+        us(report.mean()),
+        us(report.max()),
+        us(report.percentile(99.0))
+    );
+}
+
+fn write_latency_csv(path: &Path, report: &LatencyReport) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "seq,sent_us,latency_us")?;
+    for sample in &report.samples {
+        let latency = sample.latency.map_or_else(String::new, |l| l.as_micros().to_string());
+        writeln!(out, "{},{},{latency}", sample.seq, sample.sent_at.as_micros())?;
+    }
+    out.flush()
+}
+
+enum RunningServer {
+    Socketcand(SocketcandServer),
+    Slcan(SlcanBridge),
+    Ws(WsServer),
+    #[cfg(feature = "grpc")]
+    Grpc(GrpcServer),
+}
+
+/// Which protocol `--j1939` or `--nmea2000` reads extended IDs as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PgnIds {
+    J1939,
+    Nmea2000,
+}
+
+fn print_frame(channel: u32, frame: &Frame, dbc: Option<&Dbc>, pgn_ids: Option<PgnIds>) {
+    let label = color::channel(channel, &format!("CAN{}", channel + 1));
+    let kind = match (frame.is_extended(), frame.is_suspect()) {
+        (true, false) => "ext",
+        (false, false) => "std",
+        (true, true) => "ext, suspect",
+        (false, true) => "std, suspect",
+    };
+    let id = match cli::names().and_then(|names| names.name(frame.id())) {
+        Some(name) => format!("{} {name}", color::id(frame.id(), &frame.id().to_string())),
+        None => color::id(frame.id(), &frame.id().to_string()),
+    };
+    if frame.is_remote() {
+        println!("{label} received: ID={id} ({kind}), RTR dlc={}", frame.dlc());
+    } else {
+        println!("{label} received: ID={id} ({kind}), Data={:?}", frame.data());
+    }
+    if let Some((message, signals)) = dbc.and_then(|dbc| dbc.decode(frame)) {
+        let values: Vec<String> = signals.iter().map(ToString::to_string).collect();
+        println!("{label}   {}: {}", message.name, values.join(", "));
+    }
+    match (pgn_ids, J1939Message::from_frame(frame)) {
+        (Some(PgnIds::J1939), Some(message)) => print_j1939(&label, &message),
+        // Fast packets are shown once reassembled.
+        (Some(PgnIds::Nmea2000), Some(message)) if !is_fast_packet(message.pgn) => {
+            println!("{label}   {}", format_n2k(&message));
+        }
+        _ => {}
+    }
+}
+
+/// The J1939 addressing of a message, with DM1 or the SPNs of a known PGN decoded.
+fn print_j1939(label: &str, message: &J1939Message) {
+    let id = message.id();
+    match id.pgn_def() {
+        Some(def) if def.pgn == PGN_DM1 => match Dm1::parse(&message.data) {
+            Some(dm1) => println!("{label}   {id}: {dm1}"),
+            None => println!("{label}   {id}"),
+        },
+        Some(def) => {
+            let values: Vec<String> = decode_spns(def, &message.data).iter().map(ToString::to_string).collect();
+            println!("{label}   {id}: {}", values.join(", "));
+        }
+        None => println!("{label}   {id}"),
+    }
+}
+
+/// Sends the `uds` subcommand's request and prints the positive response after the echoed
+/// service, sub-function or identifier.
+fn run_uds(handle: &ChannelHandle, uds: &UdsArgs) -> Result<(), Box<dyn Error>> {
+    let mut config = IsoTpConfig::new(uds.tx, uds.rx);
+    if let Some((tx_address, rx_address)) = uds.ext_addr {
+        config.addressing = Addressing::Extended { tx_address, rx_address };
+    }
+    let mut client = UdsClient::new(IsoTpSocket::new(handle.channel(), config));
+    client.p2 = Duration::from_millis(uds.p2_ms);
+    client.p2_star = Duration::from_millis(uds.p2_star_ms);
+    let scheduler = Scheduler::new();
+    if uds.tester_present_ms > 0 {
+        client.keep_alive(&scheduler, Duration::from_millis(uds.tester_present_ms));
+    }
+
+    let response = match &uds.request {
+        UdsRequest::Session { session } => client.diagnostic_session_control(*session)?,
+        UdsRequest::Reset { reset_type } => client.ecu_reset(*reset_type).map(|()| Vec::new())?,
+        UdsRequest::ReadDid { did } => client.read_data_by_identifier(*did)?,
+        UdsRequest::WriteDid { did, data } => client.write_data_by_identifier(*did, &data.0).map(|()| Vec::new())?,
+        UdsRequest::Routine { control, routine, data } => {
+            client.routine_control(*control, *routine, data.as_ref().map_or(&[], |data| &data.0))?
+        }
+        UdsRequest::TesterPresent => client.tester_present().map(|()| Vec::new())?,
+    };
+    println!("Positive response, {} data bytes", response.len());
+    if !response.is_empty() {
+        let hex: Vec<String> = response.iter().map(|byte| format!("{byte:02X}")).collect();
+        let ascii: String = response.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }).collect();
+        println!("Raw:   {}", hex.join(" "));
+        println!("ASCII: {ascii}");
+    }
+    Ok(())
+}
+
+/// Set once SIGINT or SIGTERM arrives; a second one exits at once.
+fn interrupt_flag() -> io::Result<Arc<AtomicBool>> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        flag::register_conditional_shutdown(signal, 1, Arc::clone(&interrupted))?;
+        flag::register(signal, Arc::clone(&interrupted))?;
+    }
+    Ok(interrupted)
+}
+
+/// Runs a `canopen` subcommand.
+fn run_canopen(handle: &ChannelHandle, command: &CanopenCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        CanopenCommand::Heartbeat { timeout_ms } => {
+            let interrupted = interrupt_flag()?;
+            let frames = handle.channel().subscribe(1024);
+            let mut monitor = HeartbeatMonitor::new(Duration::from_millis(*timeout_ms));
+            println!("Watching CANopen heartbeats, Ctrl+C to exit");
+            while !interrupted.load(Ordering::SeqCst) {
+                let now = Instant::now();
+                let mut events = monitor.check(now);
+                match frames.recv_timeout(Duration::from_millis(100)) {
+                    Ok(frame) => events.extend(monitor.observe(&frame, now)),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                for event in events {
+                    match event {
+                        NodeEvent::Changed { node, from: Some(from), to } => println!("Node 0x{node:02X}: {from} -> {to}"),
+                        NodeEvent::Changed { node, from: None, to } => println!("Node 0x{node:02X}: {to}"),
+                        NodeEvent::Lost { node, last } => {
+                            println!("Node 0x{node:02X}: heartbeat lost (last {last}, timeout {timeout_ms} ms)");
+                        }
+                    }
+                }
+            }
+            for (node, status) in monitor.nodes() {
+                println!("Node 0x{node:02X}: {}{}", status.state, if status.lost { " (lost)" } else { "" });
+            }
+        }
+        CanopenCommand::Nmt { command, node } => {
+            let command = match command {
+                NmtAction::Start => NmtCommand::Start,
+                NmtAction::Stop => NmtCommand::Stop,
+                NmtAction::PreOperational => NmtCommand::EnterPreOperational,
+                NmtAction::Reset => NmtCommand::ResetNode,
+                NmtAction::ResetComm => NmtCommand::ResetCommunication,
+            };
+            handle.transmit(&command.frame(*node))?;
+            match node {
+                0 => println!("Sent NMT {command:?} to all nodes"),
+                node => println!("Sent NMT {command:?} to node 0x{node:02X}"),
+            }
+        }
+        CanopenCommand::Sdo { timeout_ms, request } => match request {
+            SdoRequest::Read { node, index, subindex } => {
+                let mut client = SdoClient::new(handle.channel(), *node);
+                client.timeout = Duration::from_millis(*timeout_ms);
+                let data = client.upload(*index, *subindex)?;
+                let hex: Vec<String> = data.iter().map(|byte| format!("{byte:02X}")).collect();
+                let mut value = [0u8; 4];
+                value[..data.len()].copy_from_slice(&data);
+                let value = u32::from_le_bytes(value);
+                println!("0x{index:04X}:{subindex:02X} = {} ({value}, 0x{value:X})", hex.join(" "));
+            }
+            SdoRequest::Write { node, index, subindex, data } => {
+                let mut client = SdoClient::new(handle.channel(), *node);
+                client.timeout = Duration::from_millis(*timeout_ms);
+                client.download(*index, *subindex, &data.0)?;
+                println!("Wrote {} bytes to 0x{index:04X}:{subindex:02X} of node 0x{node:02X}", data.0.len());
+            }
+        },
+    }
+    Ok(())
+}
+
+/// One reading of the `obd` subcommand with `--output json`, e.g.
+/// `{"ts":1699999999.123456,"ecu":"0x7E8","pid":"0x0C","name":"engine_rpm","value":812.5,"unit":"rpm","data":"0CB2"}`.
+#[derive(Serialize)]
+struct ObdJson<'a> {
+    ts: f64,
+    ecu: String,
+    pid: String,
+    name: Option<&'a str>,
+    value: Option<f64>,
+    unit: Option<&'a str>,
+    data: String,
+}
+
+/// Polls the `obd` subcommand's PIDs from every ECU that supports them until Ctrl+C: as a live
+/// table on a terminal, as JSON lines with `--output json`, or as plain lines otherwise.
+fn run_obd(handle: &ChannelHandle, obd: &ObdArgs, output: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let mut client = ObdClient::new(handle.channel());
+    client.timeout = Duration::from_millis(obd.timeout_ms);
+    let supported = client.supported_pids()?;
+    if supported.is_empty() {
+        return Err("no ECU answered the OBD-II supported PIDs request".into());
+    }
+    for ecu in supported.ecus() {
+        let pids: Vec<String> = supported.pids(ecu).map(|pid| format!("{pid:02X}")).collect();
+        println!("ECU {ecu} supports {} PIDs: {}", pids.len(), pids.join(" "));
+    }
+    let mut pids = Vec::new();
+    for &pid in &obd.pids {
+        if supported.ecus_with(pid).is_empty() {
+            println!("PID 0x{pid:02X} is not supported by any ECU, skipping it");
+        } else if !pids.contains(&pid) {
+            pids.push(pid);
+        }
+    }
+    if pids.is_empty() {
+        return Err("none of the requested PIDs is supported".into());
+    }
+
+    let interrupted = interrupt_flag()?;
+    let table = output == OutputFormat::Text && io::stdout().is_terminal();
+    if table {
+        queue!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+    }
+    let poll = || -> Result<(), Box<dyn Error>> {
+        let interval = Duration::from_millis(obd.interval_ms);
+        let mut latest: BTreeMap<(Id, u8), ObdReading> = BTreeMap::new();
+        let mut polls = 0u64;
+        while !interrupted.load(Ordering::SeqCst) {
+            let started = Instant::now();
+            for &pid in &pids {
+                for reading in client.read(pid, &supported)? {
+                    match output {
+                        OutputFormat::Json => println!("{}", serde_json::to_string(&obd_json(&reading))?),
+                        OutputFormat::Text if !table => println!("{}", format_reading(&reading)),
+                        OutputFormat::Text => {}
+                    }
+                    latest.insert((reading.ecu, pid), reading);
+                }
+            }
+            polls += 1;
+            if table {
+                let now = Instant::now();
+                let lines: Vec<String> = latest
+                    .values()
+                    .map(|reading| format!("{}   {:.1} s ago", format_reading(reading), now.duration_since(reading.at).as_secs_f64()))
+                    .collect();
+                let header = format!(
+                    "OBD-II on {}: {} ECUs, {} PIDs every {} ms, poll {polls}   Ctrl+C to exit",
+                    OBD_FUNCTIONAL_ID,
+                    supported.ecus().count(),
+                    pids.len(),
+                    obd.interval_ms
+                );
+                monitor::draw_lines(&header, &lines)?;
+            }
+            thread::sleep(interval.saturating_sub(started.elapsed()));
+        }
+        Ok(())
+    };
+    let result = poll();
+    if table {
+        let mut out = io::stdout();
+        queue!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+        out.flush()?;
+    }
+    result
+}
+
+fn obd_json(reading: &ObdReading) -> ObdJson<'static> {
+    let info = pid_info(reading.pid);
+    ObdJson {
+        ts: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as f64 / 1e6,
+        ecu: format!("0x{:X}", reading.ecu.raw()),
+        pid: format!("0x{:02X}", reading.pid),
+        name: info.map(|info| info.name),
+        value: reading.value(),
+        unit: info.map(|info| info.unit),
+        data: reading.data.iter().map(|byte| format!("{byte:02X}")).collect(),
+    }
+}
+
+/// `ECU 0x7E8  0C  Engine speed    812.25 rpm`; PIDs without a known formula show their raw bytes.
+fn format_reading(reading: &ObdReading) -> String {
+    let raw: Vec<String> = reading.data.iter().map(|byte| format!("{byte:02X}")).collect();
+    let (label, value) = match (pid_info(reading.pid), reading.value()) {
+        (Some(info), Some(value)) => (info.label, format!("{} {}", (value * 100.0).round() / 100.0, info.unit)),
+        (info, _) => (info.map_or("Unknown PID", |info| info.label), format!("raw {}", raw.join(" "))),
+    };
+    format!("ECU {}  {:02X}  {label:<26} {value}", reading.ecu, reading.pid)
+}
+
+/// Sends one frame per message named in `assignments`, in the order the messages first appear.
+fn send_signals(
+    dbc: &Dbc,
+    handle: &ChannelHandle,
+    slot: u32,
+    assignments: &[SignalAssignment],
+    range: OutOfRange,
+) -> Result<(), Box<dyn Error>> {
+    let mut names: Vec<&str> = Vec::new();
+    for assignment in assignments {
+        if !names.contains(&assignment.message.as_str()) {
+            names.push(&assignment.message);
+        }
+    }
+    for name in names {
+        let message = dbc.message_by_name(name).ok_or_else(|| format!("DBC has no message '{name}'"))?;
+        let values: Vec<(&str, f64)> = assignments
+            .iter()
+            .filter(|a| a.message == name)
+            .map(|a| (a.signal.as_str(), a.value))
+            .collect();
+        let data = encode_signals(message, &values, range)?;
+        let frame = Frame::new(message.id, &data[..usize::from(message.dlc.min(8))]).ok_or("invalid DBC message ID")?;
+        handle.transmit(&frame)?;
+        println!("CAN{} sent {name}: ID={} Data={:?}", slot + 1, frame.id(), frame.data());
+    }
+    Ok(())
+}
+
+fn close_devices(devices: Vec<Device>) -> Result<(), CanError> {
+    for device in devices {
+        device.close()?;
+    }
+    Ok(())
+}
+
+fn list_devices(dll: Option<&Path>) -> Result<(), CanError> {
+    let devices = Device::enumerate_with(CanLibrary::load(dll)?)?;
+    if devices.is_empty() {
+        println!("No CANalyst-II adapters found");
+        return Ok(());
+    }
+    println!("{:<6} {:<22} {:<10} Type", "Index", "Serial", "Firmware");
+    for (index, info) in devices.iter().enumerate() {
+        println!(
+            "{:<6} {:<22} {:<10} {}",
+            index,
+            info.serial,
+            format!("V{}", format_version(info.fw_version)),
+            info.hw_type
+        );
+    }
     Ok(())
 }
@@ -0,0 +1,653 @@
+use std::collections::HashMap;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::dbc::Dbc;
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::sink::{Direction, FrameSink};
+use crate::timestamp::host_time;
+
+const ID_BLOCK_LEN: u64 = 64;
+/// `id_unfin_flags`: the cycle counters of the channel groups and the length of the DT block
+/// still have to be worked out by the reader.
+const UNFINISHED_FLAGS: u16 = 0x01 | 0x04;
+
+const CN_FIXED: u8 = 0;
+const CN_MASTER: u8 = 2;
+const SYNC_NONE: u8 = 0;
+const SYNC_TIME: u8 = 1;
+const DATA_UINT_LE: u8 = 0;
+const DATA_FLOAT_LE: u8 = 4;
+const DATA_BYTES: u8 = 10;
+/// `cn_flags` bit 10: part of a bus event.
+const CN_BUS_EVENT: u32 = 1 << 10;
+/// `cg_flags` bits 1 and 2: a bus event group, holding nothing but the event and its time.
+const CG_PLAIN_BUS_EVENT: u16 = 0x02 | 0x04;
+const SI_BUS: u8 = 2;
+const BUS_CAN: u8 = 2;
+
+/// Data frame records: time, `BusChannel`, `ID` with `IDE` in bit 31, `DLC`, `DataLength`,
+/// `Dir` in bit 0, then the 8 `DataBytes`.
+const DATA_FRAME_BYTES: u32 = 24;
+/// Remote frame records: the same without the data bytes.
+const REMOTE_FRAME_BYTES: u32 = 16;
+
+/// One channel of a channel group, or a member of a structure channel.
+struct Field {
+    name: String,
+    cn_type: u8,
+    sync_type: u8,
+    data_type: u8,
+    byte_offset: u32,
+    bit_offset: u8,
+    bit_count: u32,
+    flags: u32,
+    unit: Option<String>,
+    members: Vec<Field>,
+}
+
+impl Field {
+    fn new(name: impl Into<String>, data_type: u8, byte_offset: u32, bit_offset: u8, bit_count: u32) -> Self {
+        Self {
+            name: name.into(),
+            cn_type: CN_FIXED,
+            sync_type: SYNC_NONE,
+            data_type,
+            byte_offset,
+            bit_offset,
+            bit_count,
+            flags: 0,
+            unit: None,
+            members: Vec::new(),
+        }
+    }
+
+    fn time() -> Self {
+        Self { cn_type: CN_MASTER, sync_type: SYNC_TIME, unit: Some("s".to_string()), ..Self::new("Timestamp", DATA_FLOAT_LE, 0, 0, 64) }
+    }
+
+    fn bus_event(mut self) -> Self {
+        self.flags |= CN_BUS_EVENT;
+        self
+    }
+}
+
+/// A channel group as laid out in the file.
+struct Group {
+    /// Replaced by the real count on [`FrameSink::finish`].
+    cycle_count_at: u64,
+    cycles: u64,
+}
+
+/// Where the blocks describing the measurement point, once written.
+struct Layout {
+    groups: Vec<Group>,
+    record_id_bytes: usize,
+    /// Record IDs of the signal group of each DBC message.
+    message_groups: HashMap<Id, (u64, usize)>,
+    dt_at: u64,
+    end: u64,
+}
+
+/// Builds the metadata blocks in memory, each 8-byte aligned, at addresses counted from the end
+/// of the identification block.
+struct Blocks {
+    bytes: Vec<u8>,
+}
+
+impl Blocks {
+    fn address(&self) -> u64 {
+        ID_BLOCK_LEN + self.bytes.len() as u64
+    }
+
+    /// Adds a block with `links` zeroed links, to be set with [`Blocks::link`].
+    fn block(&mut self, id: &[u8; 4], links: usize, data: &[u8]) -> u64 {
+        let at = self.address();
+        let length = 24 + 8 * links + data.len().next_multiple_of(8);
+        self.bytes.extend_from_slice(id);
+        self.bytes.extend_from_slice(&[0; 4]);
+        self.bytes.extend_from_slice(&(length as u64).to_le_bytes());
+        self.bytes.extend_from_slice(&(links as u64).to_le_bytes());
+        self.bytes.resize(self.bytes.len() + 8 * links, 0);
+        self.bytes.extend_from_slice(data);
+        self.bytes.resize(self.bytes.len().next_multiple_of(8), 0);
+        at
+    }
+
+    fn link(&mut self, block: u64, index: usize, target: u64) {
+        let at = (block - ID_BLOCK_LEN) as usize + 24 + 8 * index;
+        self.bytes[at..at + 8].copy_from_slice(&target.to_le_bytes());
+    }
+
+    fn text(&mut self, text: &str) -> u64 {
+        let mut data = text.as_bytes().to_vec();
+        data.push(0);
+        self.block(b"##TX", 0, &data)
+    }
+
+    fn xml(&mut self, xml: &str) -> u64 {
+        let mut data = xml.as_bytes().to_vec();
+        data.push(0);
+        self.block(b"##MD", 0, &data)
+    }
+
+    /// Adds `fields` as a list of channels, members first, and returns the first.
+    fn channels(&mut self, fields: &[Field]) -> u64 {
+        let mut next = 0;
+        for field in fields.iter().rev() {
+            let composition = match field.members.is_empty() {
+                true => 0,
+                false => self.channels(&field.members),
+            };
+            let name = self.text(&field.name);
+            let unit = field.unit.as_deref().map(|unit| self.text(unit));
+            let mut data = vec![field.cn_type, field.sync_type, field.data_type, field.bit_offset];
+            data.extend_from_slice(&field.byte_offset.to_le_bytes());
+            data.extend_from_slice(&field.bit_count.to_le_bytes());
+            data.extend_from_slice(&field.flags.to_le_bytes());
+            // Invalidation bit position, precision, reserved, attachment count, then the value
+            // range and limits, all unused.
+            data.extend_from_slice(&[0; 8]);
+            data.extend_from_slice(&[0; 48]);
+            let channel = self.block(b"##CN", 8, &data);
+            self.link(channel, 0, next);
+            self.link(channel, 1, composition);
+            self.link(channel, 2, name);
+            if let Some(unit) = unit {
+                self.link(channel, 6, unit);
+            }
+            next = channel;
+        }
+        next
+    }
+
+    /// Adds a channel group with its acquisition name and source, and returns it.
+    fn group(&mut self, name: &str, record_id: u64, flags: u16, record_bytes: u32, fields: &[Field]) -> u64 {
+        let channels = self.channels(fields);
+        let acquisition_name = self.text(name);
+        let source_name = self.text("CAN");
+        let source_path = self.text("rustcanbus");
+        let source = self.block(b"##SI", 3, &[SI_BUS, BUS_CAN, 0, 0, 0, 0, 0, 0]);
+        self.link(source, 0, source_name);
+        self.link(source, 1, source_path);
+        let mut data = Vec::new();
+        data.extend_from_slice(&record_id.to_le_bytes());
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data.extend_from_slice(&flags.to_le_bytes());
+        data.extend_from_slice(&u16::from(b'.').to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&record_bytes.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        let group = self.block(b"##CG", 6, &data);
+        self.link(group, 1, channels);
+        self.link(group, 2, acquisition_name);
+        self.link(group, 3, source);
+        group
+    }
+}
+
+/// The members of a `CAN_DataFrame` or `CAN_RemoteFrame` structure, as ASAM's bus logging
+/// standard names them.
+fn frame_members(event: &str, data_bytes: bool) -> Vec<Field> {
+    let member = |name: &str, data_type, byte_offset, bit_offset, bit_count| {
+        Field::new(format!("{event}.{name}"), data_type, byte_offset, bit_offset, bit_count).bus_event()
+    };
+    let mut members = vec![
+        member("BusChannel", DATA_UINT_LE, 8, 0, 8),
+        member("ID", DATA_UINT_LE, 9, 0, 29),
+        member("IDE", DATA_UINT_LE, 12, 7, 1),
+        member("DLC", DATA_UINT_LE, 13, 0, 4),
+        member("DataLength", DATA_UINT_LE, 14, 0, 7),
+        member("Dir", DATA_UINT_LE, 15, 0, 1),
+    ];
+    if data_bytes {
+        members.push(member("DataBytes", DATA_BYTES, 16, 0, 64));
+    }
+    members
+}
+
+fn frame_fields(event: &str, record_bytes: u32) -> Vec<Field> {
+    let mut structure = Field::new(event, DATA_BYTES, 8, 0, (record_bytes - 8) * 8).bus_event();
+    structure.members = frame_members(event, record_bytes > REMOTE_FRAME_BYTES);
+    vec![Field::time().bus_event(), structure]
+}
+
+/// ASAM MDF 4.1 writer: received and transmitted frames go to the `CAN_DataFrame` and
+/// `CAN_RemoteFrame` channel groups of the bus logging standard, which CANape, asammdf and
+/// the like list as raw CAN traffic. Given a DBC, each of its messages also gets a channel group
+/// of its decoded signals, one record per frame.
+///
+/// All groups share one unsorted data group; the file must be written from its start, and `out`
+/// must be seekable because the cycle counters and the data block's length are filled in on
+/// [`FrameSink::finish`]. Until then the file is marked unfinalized, which MDF readers recover
+/// by scanning the records, so a capture cut short by a crash still opens.
+pub struct MdfWriter<W: Write + Seek> {
+    out: W,
+    start: SystemTime,
+    dbc: Option<Arc<Dbc>>,
+    layout: Option<Layout>,
+}
+
+impl<W: Write + Seek> MdfWriter<W> {
+    /// Starts a measurement now.
+    pub fn new(out: W) -> Self {
+        Self::with_signals(out, None)
+    }
+
+    /// Also writes the signals of `dbc`'s messages, decoded.
+    pub fn with_signals(out: W, dbc: Option<Arc<Dbc>>) -> Self {
+        Self { out, start: SystemTime::now(), dbc, layout: None }
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let messages = self.dbc.as_ref().map_or(&[][..], |dbc| dbc.messages());
+        let group_count = 2 + messages.len() as u64;
+        let record_id_bytes = if group_count <= u64::from(u8::MAX) { 1 } else { 2 };
+        let mut blocks = Blocks { bytes: Vec::new() };
+
+        let start_ns = self.start.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+        let mut time = start_ns.to_le_bytes().to_vec();
+        time.extend_from_slice(&[0; 8]);
+        // Start time, time zone and flags, then the start angle and distance, unused.
+        let header = blocks.block(b"##HD", 6, &[time.as_slice(), &[0; 16]].concat());
+        let history_comment = blocks.xml(&format!(
+            "<FHcomment><TX>Recorded by rustcanbus</TX><tool_id>rustcanbus</tool_id><tool_vendor>rustcanbus</tool_vendor><tool_version>{}</tool_version></FHcomment>",
+            env!("CARGO_PKG_VERSION")
+        ));
+        let history = blocks.block(b"##FH", 2, &time);
+        blocks.link(history, 1, history_comment);
+        let data_group = blocks.block(b"##DG", 4, &[record_id_bytes as u8, 0, 0, 0, 0, 0, 0, 0]);
+        blocks.link(header, 0, data_group);
+        blocks.link(header, 1, history);
+
+        let mut groups = vec![
+            blocks.group("CAN_DataFrame", 1, CG_PLAIN_BUS_EVENT, DATA_FRAME_BYTES, &frame_fields("CAN_DataFrame", DATA_FRAME_BYTES)),
+            blocks.group("CAN_RemoteFrame", 2, CG_PLAIN_BUS_EVENT, REMOTE_FRAME_BYTES, &frame_fields("CAN_RemoteFrame", REMOTE_FRAME_BYTES)),
+        ];
+        let mut message_groups = HashMap::new();
+        for (index, message) in messages.iter().enumerate() {
+            let record_id = 3 + index as u64;
+            let mut fields = vec![Field::time()];
+            for (n, signal) in message.signals.iter().enumerate() {
+                let mut field = Field::new(signal.name.as_str(), DATA_FLOAT_LE, 8 + 8 * n as u32, 0, 64);
+                field.unit = Some(signal.unit.clone()).filter(|unit| !unit.is_empty());
+                fields.push(field);
+            }
+            groups.push(blocks.group(&message.name, record_id, 0, 8 + 8 * message.signals.len() as u32, &fields));
+            message_groups.insert(message.id, (record_id, index + 2));
+        }
+        for pair in groups.windows(2) {
+            blocks.link(pair[0], 0, pair[1]);
+        }
+        blocks.link(data_group, 1, groups[0]);
+
+        let dt_at = blocks.address();
+        blocks.link(data_group, 2, dt_at);
+        blocks.block(b"##DT", 0, &[]);
+
+        self.out.write_all(&identification(false))?;
+        self.out.write_all(&blocks.bytes)?;
+        self.layout = Some(Layout {
+            // The cycle counter follows the block header, the six links and the record ID.
+            groups: groups.iter().map(|&at| Group { cycle_count_at: at + 24 + 48 + 8, cycles: 0 }).collect(),
+            record_id_bytes,
+            message_groups,
+            dt_at,
+            end: blocks.address(),
+        });
+        Ok(())
+    }
+
+    fn write_record(&mut self, record_id: u64, group: usize, record: &[u8]) -> io::Result<()> {
+        let layout = self.layout.as_mut().expect("header written");
+        self.out.write_all(&record_id.to_le_bytes()[..layout.record_id_bytes])?;
+        self.out.write_all(record)?;
+        layout.groups[group].cycles += 1;
+        layout.end += (layout.record_id_bytes + record.len()) as u64;
+        Ok(())
+    }
+}
+
+/// The identification block, marked unfinalized until `finalized`.
+fn identification(finalized: bool) -> [u8; ID_BLOCK_LEN as usize] {
+    let mut block = [0; ID_BLOCK_LEN as usize];
+    block[..8].copy_from_slice(if finalized { b"MDF     " } else { b"UnFinMF " });
+    block[8..16].copy_from_slice(b"4.10    ");
+    block[16..24].copy_from_slice(b"rustcan ");
+    block[28..30].copy_from_slice(&410u16.to_le_bytes());
+    if !finalized {
+        block[60..62].copy_from_slice(&UNFINISHED_FLAGS.to_le_bytes());
+    }
+    block
+}
+
+impl<W: Write + Seek + Send> FrameSink for MdfWriter<W> {
+    fn write_frame(&mut self, channel: u32, frame: &Frame, direction: Direction) -> io::Result<()> {
+        if self.layout.is_none() {
+            self.write_header()?;
+        }
+        let time = host_time(frame);
+        let offset = match time.duration_since(self.start) {
+            Ok(after) => after.as_secs_f64(),
+            Err(before) => -before.duration().as_secs_f64(),
+        };
+        let mut record = [0u8; DATA_FRAME_BYTES as usize];
+        record[..8].copy_from_slice(&offset.to_le_bytes());
+        record[8] = channel as u8 + 1;
+        let id = frame.id().raw() | if frame.is_extended() { 1 << 31 } else { 0 };
+        record[9..13].copy_from_slice(&id.to_le_bytes());
+        record[13] = frame.dlc();
+        record[15] = (direction == Direction::Tx) as u8;
+        if frame.is_remote() {
+            return self.write_record(2, 1, &record[..REMOTE_FRAME_BYTES as usize]);
+        }
+        record[14] = frame.data().len() as u8;
+        record[16..16 + frame.data().len()].copy_from_slice(frame.data());
+        self.write_record(1, 0, &record)?;
+
+        let Some(dbc) = &self.dbc else {
+            return Ok(());
+        };
+        let layout = self.layout.as_ref().expect("header written");
+        let (Some(&(record_id, group)), Some(message)) = (layout.message_groups.get(&frame.id()), dbc.message(frame.id())) else {
+            return Ok(());
+        };
+        let values = message.decode(frame.data());
+        let mut signals = offset.to_le_bytes().to_vec();
+        for signal in &message.signals {
+            // Multiplexed signals the frame doesn't carry, and those past its payload, are NaN.
+            let value = values.iter().find(|value| std::ptr::eq(value.signal, signal)).map_or(f64::NAN, |value| value.physical);
+            signals.extend_from_slice(&value.to_le_bytes());
+        }
+        self.write_record(record_id, group, &signals)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Fills in the cycle counters and the data block's length, then marks the file finalized.
+    fn finish(&mut self) -> io::Result<()> {
+        if self.layout.is_none() {
+            self.write_header()?;
+        }
+        let layout = self.layout.as_ref().expect("header written");
+        self.out.seek(SeekFrom::Start(layout.dt_at + 8))?;
+        self.out.write_all(&(layout.end - layout.dt_at).to_le_bytes())?;
+        for group in &layout.groups {
+            self.out.seek(SeekFrom::Start(group.cycle_count_at))?;
+            self.out.write_all(&group.cycles.to_le_bytes())?;
+        }
+        self.out.seek(SeekFrom::Start(0))?;
+        self.out.write_all(&identification(true))?;
+        self.out.seek(SeekFrom::Start(layout.end))?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    const START: u64 = 1_791_972_000;
+
+    const ENGINE: &str = r#"
+BO_ 256 Engine: 4 ECU
+ SG_ Speed : 0|16@1+ (0.25,0) [0|16383.75] "rpm" ECU
+ SG_ Temp : 16|8@1+ (1,-40) [-40|215] "degC" ECU
+ SG_ Load : 24|8@1+ (0.5,0) [0|127.5] "" ECU
+"#;
+
+    struct Block<'a> {
+        id: [u8; 4],
+        links: Vec<u64>,
+        data: &'a [u8],
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    /// Reads the block at `at`, checking what every MDF 4 block header promises.
+    fn block(file: &[u8], at: u64) -> Block<'_> {
+        let at = at as usize;
+        assert!(at.is_multiple_of(8) && at >= ID_BLOCK_LEN as usize, "block at {at}");
+        assert_eq!(&file[at..at + 2], b"##", "block at {at}");
+        let length = u64_at(file, at + 8) as usize;
+        let links = u64_at(file, at + 16) as usize;
+        assert!(length >= 24 + 8 * links && at + length <= file.len(), "block at {at} is {length} bytes");
+        Block {
+            id: file[at..at + 4].try_into().unwrap(),
+            links: (0..links).map(|n| u64_at(file, at + 24 + 8 * n)).collect(),
+            data: &file[at + 24 + 8 * links..at + length],
+        }
+    }
+
+    fn text(file: &[u8], at: u64) -> String {
+        let block = block(file, at);
+        assert_eq!(&block.id, b"##TX");
+        String::from_utf8(block.data.iter().copied().take_while(|&byte| byte != 0).collect()).unwrap()
+    }
+
+    /// Follows a linked list through the `next` link, which is the first of each block's.
+    fn list<'a>(file: &'a [u8], mut at: u64, id: &[u8; 4]) -> Vec<Block<'a>> {
+        let mut blocks = Vec::new();
+        while at != 0 {
+            let next = block(file, at);
+            assert_eq!(&next.id, id);
+            at = next.links[0];
+            blocks.push(next);
+        }
+        blocks
+    }
+
+    /// Names of a channel list, members of structures after the structure.
+    fn channel_names(file: &[u8], first: u64) -> Vec<String> {
+        let mut names = Vec::new();
+        for channel in list(file, first, b"##CN") {
+            names.push(text(file, channel.links[2]));
+            names.extend(channel_names(file, channel.links[1]));
+        }
+        names
+    }
+
+    struct ChannelGroup {
+        name: String,
+        record_id: u64,
+        cycles: u64,
+        record_bytes: usize,
+        channels: Vec<String>,
+    }
+
+    struct Measurement {
+        groups: Vec<ChannelGroup>,
+        /// Record ID and bytes of each record, in file order.
+        records: Vec<(u64, Vec<u8>)>,
+    }
+
+    fn read(file: &[u8]) -> Measurement {
+        let header = block(file, ID_BLOCK_LEN);
+        assert_eq!(&header.id, b"##HD");
+        assert_eq!(u64_at(header.data, 0), START * 1_000_000_000);
+        assert_eq!(&block(file, header.links[1]).id, b"##FH");
+        let data_group = block(file, header.links[0]);
+        assert_eq!(&data_group.id, b"##DG");
+        let record_id_bytes = data_group.data[0] as usize;
+        let groups: Vec<ChannelGroup> = list(file, data_group.links[1], b"##CG")
+            .into_iter()
+            .map(|group| {
+                assert_eq!(&block(file, group.links[3]).id, b"##SI");
+                ChannelGroup {
+                    name: text(file, group.links[2]),
+                    record_id: u64_at(group.data, 0),
+                    cycles: u64_at(group.data, 8),
+                    record_bytes: u32_at(group.data, 24) as usize,
+                    channels: channel_names(file, group.links[1]),
+                }
+            })
+            .collect();
+        let data = block(file, data_group.links[2]);
+        assert_eq!(&data.id, b"##DT");
+        let mut records = Vec::new();
+        let mut rest = data.data;
+        while !rest.is_empty() {
+            let mut id = [0; 8];
+            id[..record_id_bytes].copy_from_slice(&rest[..record_id_bytes]);
+            let record_id = u64::from_le_bytes(id);
+            let group = groups.iter().find(|group| group.record_id == record_id).expect("known record ID");
+            records.push((record_id, rest[record_id_bytes..record_id_bytes + group.record_bytes].to_vec()));
+            rest = &rest[record_id_bytes + group.record_bytes..];
+        }
+        Measurement { groups, records }
+    }
+
+    fn writer(dbc: Option<&str>) -> MdfWriter<Cursor<Vec<u8>>> {
+        let mut writer = MdfWriter::with_signals(Cursor::new(Vec::new()), dbc.map(|dbc| Arc::new(Dbc::parse(dbc).unwrap())));
+        writer.start = UNIX_EPOCH + Duration::from_secs(START);
+        writer
+    }
+
+    fn received(frame: Frame, offset: Duration) -> Frame {
+        Frame { host_time: Some((UNIX_EPOCH + Duration::from_secs(START) + offset, Instant::now())), ..frame }
+    }
+
+    fn traffic() -> Vec<(Duration, u32, Frame, Direction)> {
+        vec![
+            (Duration::from_millis(1), 0, Frame::new(Id::Standard(0x123), &[0x11, 0x22, 0x33]).unwrap(), Direction::Rx),
+            (Duration::from_millis(2), 1, Frame::new(Id::Extended(0x18FF_50E5), &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap(), Direction::Tx),
+            (Duration::from_millis(3), 0, Frame::remote(Id::Standard(0x7FF), 6).unwrap(), Direction::Rx),
+            (Duration::from_millis(4), 1, Frame::remote(Id::Extended(0x1FFF_FFFF), 0).unwrap(), Direction::Tx),
+            (Duration::from_millis(5), 0, Frame::new(Id::Standard(0), &[]).unwrap(), Direction::Rx),
+        ]
+    }
+
+    fn write(writer: &mut MdfWriter<Cursor<Vec<u8>>>, frames: &[(Duration, u32, Frame, Direction)]) {
+        for &(offset, channel, frame, direction) in frames {
+            writer.write_frame(channel, &received(frame, offset), direction).unwrap();
+        }
+    }
+
+    /// Decodes a `CAN_DataFrame` or `CAN_RemoteFrame` record.
+    fn frame_record(record_id: u64, record: &[u8]) -> (Duration, u32, Frame, Direction) {
+        let time = Duration::from_secs_f64(f64::from_le_bytes(record[..8].try_into().unwrap()));
+        let raw = u32_at(record, 9);
+        let id = match raw >> 31 {
+            1 => Id::Extended(raw & 0x1FFF_FFFF),
+            _ => Id::Standard(raw as u16),
+        };
+        let direction = if record[15] & 1 == 1 { Direction::Tx } else { Direction::Rx };
+        let frame = match record_id {
+            1 => Frame::new(id, &record[16..16 + record[14] as usize]).unwrap(),
+            _ => Frame::remote(id, record[13]).unwrap(),
+        };
+        (time, u32::from(record[8]) - 1, frame, direction)
+    }
+
+    #[test]
+    fn stays_unfinalized_until_finished() {
+        let mut writer = writer(None);
+        write(&mut writer, &traffic());
+        let file = writer.out.get_ref().clone();
+        assert_eq!(&file[..8], b"UnFinMF ");
+        assert_eq!(u16::from_le_bytes([file[60], file[61]]), UNFINISHED_FLAGS);
+        // What a reader recovers from: the records are all there, only the counts aren't.
+        let unfinished = read(&file[..]);
+        assert_eq!(unfinished.records.len(), 0, "the data block still claims to be empty");
+        assert!(unfinished.groups.iter().all(|group| group.cycles == 0));
+
+        writer.finish().unwrap();
+        let file = writer.out.into_inner();
+        assert_eq!(&file[..8], b"MDF     ");
+        assert_eq!(&file[8..16], b"4.10    ");
+        assert_eq!(u16::from_le_bytes([file[28], file[29]]), 410);
+        assert_eq!(&file[60..62], [0, 0]);
+        assert_eq!(read(&file).records.len(), traffic().len());
+    }
+
+    #[test]
+    fn linked_blocks_tile_the_file() {
+        let mut writer = writer(Some(ENGINE));
+        write(&mut writer, &traffic());
+        writer.finish().unwrap();
+        let file = writer.out.into_inner();
+        let mut seen = std::collections::BTreeMap::new();
+        let mut pending = vec![ID_BLOCK_LEN];
+        while let Some(at) = pending.pop() {
+            if at == 0 || seen.contains_key(&at) {
+                continue;
+            }
+            let block = block(&file, at);
+            seen.insert(at, 24 + 8 * block.links.len() as u64 + block.data.len() as u64);
+            pending.extend(&block.links);
+        }
+        // Reachable blocks tile the file from the identification block to its end.
+        let mut next = ID_BLOCK_LEN;
+        for (&at, &length) in &seen {
+            assert_eq!(at, next, "gap or overlap before the block at {at}");
+            next = at + length;
+        }
+        assert_eq!(next, file.len() as u64, "the data block ends the file");
+    }
+
+    #[test]
+    fn frames_read_back_from_the_bus_logging_groups() {
+        let mut writer = writer(None);
+        write(&mut writer, &traffic());
+        writer.finish().unwrap();
+        let measurement = read(&writer.out.into_inner());
+
+        let names: Vec<(&str, u64, u64, usize)> = measurement.groups.iter().map(|group| (group.name.as_str(), group.record_id, group.cycles, group.record_bytes)).collect();
+        assert_eq!(names, [("CAN_DataFrame", 1, 3, 24), ("CAN_RemoteFrame", 2, 2, 16)]);
+        let members = ["BusChannel", "ID", "IDE", "DLC", "DataLength", "Dir"];
+        let expected: Vec<String> = ["Timestamp", "CAN_DataFrame"].into_iter().map(String::from).chain(members.iter().chain(&["DataBytes"]).map(|member| format!("CAN_DataFrame.{member}"))).collect();
+        assert_eq!(measurement.groups[0].channels, expected);
+        let expected: Vec<String> = ["Timestamp", "CAN_RemoteFrame"].into_iter().map(String::from).chain(members.iter().map(|member| format!("CAN_RemoteFrame.{member}"))).collect();
+        assert_eq!(measurement.groups[1].channels, expected);
+
+        for ((record_id, record), (offset, channel, frame, direction)) in measurement.records.iter().zip(traffic()) {
+            let (read_offset, read_channel, read_frame, read_direction) = frame_record(*record_id, record);
+            assert!(read_offset.abs_diff(offset) < Duration::from_nanos(10));
+            assert_eq!((read_channel, read_frame, read_direction), (channel, frame, direction));
+        }
+    }
+
+    #[test]
+    fn decoded_signals_get_a_group_per_message() {
+        let mut writer = writer(Some(ENGINE));
+        write(
+            &mut writer,
+            &[
+                (Duration::from_millis(10), 0, Frame::new(Id::Standard(256), &[0x40, 0x1F, 130, 200]).unwrap(), Direction::Rx),
+                (Duration::from_millis(20), 0, Frame::new(Id::Standard(257), &[1]).unwrap(), Direction::Rx),
+                (Duration::from_millis(30), 1, Frame::new(Id::Standard(256), &[0x00, 0x01]).unwrap(), Direction::Rx),
+                (Duration::from_millis(40), 0, Frame::remote(Id::Standard(256), 4).unwrap(), Direction::Rx),
+            ],
+        );
+        writer.finish().unwrap();
+        let measurement = read(&writer.out.into_inner());
+        let engine = &measurement.groups[2];
+        assert_eq!((engine.name.as_str(), engine.record_id, engine.cycles, engine.record_bytes), ("Engine", 3, 2, 32));
+        assert_eq!(engine.channels, ["Timestamp", "Speed", "Temp", "Load"]);
+        assert_eq!(measurement.groups[0].cycles, 3);
+        assert_eq!(measurement.groups[1].cycles, 1);
+
+        let values: Vec<Vec<f64>> = measurement
+            .records
+            .iter()
+            .filter(|(record_id, _)| *record_id == 3)
+            .map(|(_, record)| record.chunks(8).map(|value| f64::from_le_bytes(value.try_into().unwrap())).collect())
+            .collect();
+        assert_eq!(values[0], [0.01, 2000.0, 90.0, 100.0]);
+        assert_eq!(values[1][..2], [0.03, 64.0]);
+        assert!(values[1][2..].iter().all(|value| value.is_nan()), "signals past a short payload are NaN: {:?}", values[1]);
+    }
+}
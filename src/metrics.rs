@@ -0,0 +1,469 @@
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::device::Channel;
+use crate::reconnect::ConnectionState;
+
+/// Port the metrics endpoint listens on by default.
+pub const METRICS_PORT: u16 = 9090;
+
+/// How often the accept loop looks at the stop flag.
+const POLL: Duration = Duration::from_millis(100);
+/// The most a scraper may take to send its request or read the reply.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+/// Requests are a single line and a few headers; anything longer is cut off.
+const MAX_REQUEST: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+impl MetricKind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+        }
+    }
+}
+
+/// One value of a metric family and the labels that tell it apart from the others.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub labels: Vec<(&'static str, String)>,
+    pub value: f64,
+}
+
+impl Sample {
+    pub fn new(labels: &[(&'static str, &str)], value: f64) -> Self {
+        Self { labels: labels.iter().map(|&(name, value)| (name, value.to_string())).collect(), value }
+    }
+}
+
+type Collector = Box<dyn Fn() -> Vec<Sample> + Send + Sync>;
+
+struct Family {
+    name: String,
+    help: String,
+    kind: MetricKind,
+    collectors: Vec<Collector>,
+}
+
+/// Every metric the program exports, read at each scrape and rendered in the Prometheus text
+/// format. Families are listed in the order they were first registered; registering a name
+/// again adds samples to the existing family.
+#[derive(Default)]
+pub struct Metrics {
+    families: Mutex<Vec<Family>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds samples that `collect` reads at every scrape to the family `name`.
+    pub fn register(
+        &self,
+        name: &str,
+        help: &str,
+        kind: MetricKind,
+        collect: impl Fn() -> Vec<Sample> + Send + Sync + 'static,
+    ) {
+        let mut families = self.families.lock().unwrap();
+        let index = match families.iter().position(|family| family.name == name) {
+            Some(index) => index,
+            None => {
+                families.push(Family { name: name.to_string(), help: help.to_string(), kind, collectors: Vec::new() });
+                families.len() - 1
+            }
+        };
+        families[index].collectors.push(Box::new(collect));
+    }
+
+    /// A counter the caller increments itself, e.g. a consumer's dropped frames.
+    pub fn counter(&self, name: &str, help: &str, labels: &[(&'static str, &str)]) -> Arc<AtomicU64> {
+        let counter = Arc::new(AtomicU64::new(0));
+        let sample = Sample::new(labels, 0.0);
+        let value = Arc::clone(&counter);
+        self.register(name, help, MetricKind::Counter, move || {
+            vec![Sample { value: value.load(Ordering::Relaxed) as f64, ..sample.clone() }]
+        });
+        counter
+    }
+
+    /// Registers what every channel keeps track of itself: frames received and transmitted,
+    /// receive errors, malformed frames, bus load, frames dropped while disconnected, the connection state, the
+    /// transmit queue, and the adapter's reconnects. Channels are labelled by their position in `channels`.
+    pub fn register_channels(&self, channels: &[Channel]) {
+        let per_channel = |name: &str, help: &str, kind: MetricKind, value: fn(&Channel) -> Option<f64>| {
+            let channels = channels.to_vec();
+            self.register(name, help, kind, move || {
+                (0..)
+                    .zip(&channels)
+                    .filter_map(|(index, channel): (u32, _)| {
+                        Some(Sample::new(&[("channel", &index.to_string())], value(channel)?))
+                    })
+                    .collect()
+            });
+        };
+        per_channel("rustcanbus_frames_received_total", "Frames received.", MetricKind::Counter, |channel| {
+            Some(channel.received() as f64)
+        });
+        per_channel("rustcanbus_frames_transmitted_total", "Frames accepted for transmission.", MetricKind::Counter, |channel| {
+            Some(channel.transmitted() as f64)
+        });
+        per_channel("rustcanbus_receive_errors_total", "VCI_Receive calls that failed.", MetricKind::Counter, |channel| {
+            Some(channel.receive_failures() as f64)
+        });
+        per_channel("rustcanbus_suspect_frames_total", "Frames the DLL delivered malformed, e.g. with a data length above 8.", MetricKind::Counter, |channel| {
+            Some(channel.suspect_frames() as f64)
+        });
+        per_channel("rustcanbus_bus_load_percent", "Bus load over the last second.", MetricKind::Gauge, |channel| {
+            channel.bus_load()
+        });
+        per_channel(
+            "rustcanbus_dropped_while_disconnected_total",
+            "Frames to transmit dropped while the adapter was disconnected.",
+            MetricKind::Counter,
+            |channel| Some(channel.dropped_while_disconnected() as f64),
+        );
+        per_channel("rustcanbus_connected", "1 while the channel's adapter is reachable.", MetricKind::Gauge, |channel| {
+            Some(f64::from(u8::from(channel.connection_state() == ConnectionState::Connected)))
+        });
+        per_channel("rustcanbus_tx_queued_frames", "Frames waiting for the transmit shaper.", MetricKind::Gauge, |channel| {
+            Some(channel.shaper_stats().queued as f64)
+        });
+        per_channel("rustcanbus_tx_delayed_total", "Frames the transmit shaper held back before sending.", MetricKind::Counter, |channel| {
+            Some(channel.shaper_stats().delayed as f64)
+        });
+        per_channel("rustcanbus_tx_refused_total", "Of the frames the transmit shaper dropped, those refused with the queue full.", MetricKind::Counter, |channel| {
+            Some(channel.shaper_stats().refused as f64)
+        });
+        per_channel("rustcanbus_tx_shaper_dropped_total", "Frames the transmit shaper dropped.", MetricKind::Counter, |channel| {
+            Some(channel.shaper_stats().dropped as f64)
+        });
+        let mut adapters: Vec<Channel> = Vec::new();
+        for channel in channels {
+            if !adapters.iter().any(|adapter| adapter.device_index() == channel.device_index()) {
+                adapters.push(channel.clone());
+            }
+        }
+        self.register("rustcanbus_reconnects_total", "Times an unplugged adapter was reopened.", MetricKind::Counter, move || {
+            adapters
+                .iter()
+                .map(|adapter| Sample::new(&[("adapter", &adapter.device_index().to_string())], adapter.reconnects() as f64))
+                .collect()
+        });
+    }
+
+    /// The text exposition format, version 0.0.4.
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut text = String::new();
+        for family in families.iter() {
+            let _ = writeln!(text, "# HELP {} {}", family.name, escape(&family.help, false));
+            let _ = writeln!(text, "# TYPE {} {}", family.name, family.kind.name());
+            for sample in family.collectors.iter().flat_map(|collect| collect()) {
+                text.push_str(&family.name);
+                if !sample.labels.is_empty() {
+                    let labels: Vec<String> =
+                        sample.labels.iter().map(|(name, value)| format!("{name}=\"{}\"", escape(value, true))).collect();
+                    let _ = write!(text, "{{{}}}", labels.join(","));
+                }
+                let _ = writeln!(text, " {}", format_value(sample.value));
+            }
+        }
+        text
+    }
+}
+
+/// Backslashes and newlines are escaped in help text, and double quotes too in label values.
+fn escape(text: &str, quotes: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '"' if quotes => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn format_value(value: f64) -> String {
+    match value {
+        f64::INFINITY => "+Inf".to_string(),
+        f64::NEG_INFINITY => "-Inf".to_string(),
+        value if value.is_nan() => "NaN".to_string(),
+        value => value.to_string(),
+    }
+}
+
+/// Serves `/metrics` to Prometheus and `/healthz`, which answers 503 while any of the channels'
+/// adapters is disconnected. Requests are answered one at a time on a single thread.
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    pub fn start(addr: impl ToSocketAddrs, metrics: Arc<Metrics>, channels: &[Channel]) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let endpoint = Endpoint { metrics, channels: channels.to_vec() };
+        let thread_stop = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            while !thread_stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    // A scraper that misbehaves only loses its own request.
+                    Ok((stream, _)) => {
+                        let _ = endpoint.serve(stream);
+                    }
+                    Err(_) => thread::sleep(POLL),
+                }
+            }
+        });
+        Ok(Self { addr, stop, thread: Some(thread) })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stops listening. Also done on drop.
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+struct Endpoint {
+    metrics: Arc<Metrics>,
+    channels: Vec<Channel>,
+}
+
+impl Endpoint {
+    fn serve(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !request.windows(4).any(|end| end == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+            let n = stream.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        let request = String::from_utf8_lossy(&request);
+        let mut words = request.lines().next().unwrap_or_default().split_whitespace();
+        let (method, path) = (words.next().unwrap_or_default(), words.next().unwrap_or_default());
+        // Query strings don't change anything here.
+        let path = path.split('?').next().unwrap_or_default();
+        let (status, content_type, body) = match (method, path) {
+            ("GET", "/metrics") => ("200 OK", "text/plain; version=0.0.4", self.metrics.render()),
+            ("GET", "/healthz") => match self.unhealthy() {
+                None => ("200 OK", "text/plain", "ok\n".to_string()),
+                Some(problems) => ("503 Service Unavailable", "text/plain", problems),
+            },
+            ("GET", _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+            _ => ("405 Method Not Allowed", "text/plain", "only GET is supported\n".to_string()),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )?;
+        stream.flush()
+    }
+
+    /// One line per channel whose adapter isn't connected, or `None` if all are.
+    fn unhealthy(&self) -> Option<String> {
+        let mut problems = String::new();
+        for (index, channel) in self.channels.iter().enumerate() {
+            let state = channel.connection_state();
+            if state != ConnectionState::Connected {
+                let _ = writeln!(problems, "channel {index}: adapter {} {state}", channel.device_index());
+            }
+        }
+        (!problems.is_empty()).then_some(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::Frame;
+    use crate::id::Id;
+    use crate::mock::{started_pair, MockCall};
+    use crate::reconnect::{DisconnectedTx, Reconnect};
+
+    const SNAPSHOT: &str = r#"# HELP rustcanbus_frames_received_total Frames received.
+# TYPE rustcanbus_frames_received_total counter
+rustcanbus_frames_received_total{channel="0"} 0
+rustcanbus_frames_received_total{channel="1"} 2
+# HELP rustcanbus_frames_transmitted_total Frames accepted for transmission.
+# TYPE rustcanbus_frames_transmitted_total counter
+rustcanbus_frames_transmitted_total{channel="0"} 2
+rustcanbus_frames_transmitted_total{channel="1"} 0
+# HELP rustcanbus_receive_errors_total VCI_Receive calls that failed.
+# TYPE rustcanbus_receive_errors_total counter
+rustcanbus_receive_errors_total{channel="0"} 0
+rustcanbus_receive_errors_total{channel="1"} 1
+# HELP rustcanbus_suspect_frames_total Frames the DLL delivered malformed, e.g. with a data length above 8.
+# TYPE rustcanbus_suspect_frames_total counter
+rustcanbus_suspect_frames_total{channel="0"} 0
+rustcanbus_suspect_frames_total{channel="1"} 0
+# HELP rustcanbus_bus_load_percent Bus load over the last second.
+# TYPE rustcanbus_bus_load_percent gauge
+rustcanbus_bus_load_percent{channel="0"} 0.049
+rustcanbus_bus_load_percent{channel="1"} 0.049
+# HELP rustcanbus_dropped_while_disconnected_total Frames to transmit dropped while the adapter was disconnected.
+# TYPE rustcanbus_dropped_while_disconnected_total counter
+rustcanbus_dropped_while_disconnected_total{channel="0"} 0
+rustcanbus_dropped_while_disconnected_total{channel="1"} 0
+# HELP rustcanbus_connected 1 while the channel's adapter is reachable.
+# TYPE rustcanbus_connected gauge
+rustcanbus_connected{channel="0"} 1
+rustcanbus_connected{channel="1"} 1
+# HELP rustcanbus_tx_queued_frames Frames waiting for the transmit shaper.
+# TYPE rustcanbus_tx_queued_frames gauge
+rustcanbus_tx_queued_frames{channel="0"} 0
+rustcanbus_tx_queued_frames{channel="1"} 0
+# HELP rustcanbus_tx_delayed_total Frames the transmit shaper held back before sending.
+# TYPE rustcanbus_tx_delayed_total counter
+rustcanbus_tx_delayed_total{channel="0"} 0
+rustcanbus_tx_delayed_total{channel="1"} 0
+# HELP rustcanbus_tx_refused_total Of the frames the transmit shaper dropped, those refused with the queue full.
+# TYPE rustcanbus_tx_refused_total counter
+rustcanbus_tx_refused_total{channel="0"} 0
+rustcanbus_tx_refused_total{channel="1"} 0
+# HELP rustcanbus_tx_shaper_dropped_total Frames the transmit shaper dropped.
+# TYPE rustcanbus_tx_shaper_dropped_total counter
+rustcanbus_tx_shaper_dropped_total{channel="0"} 0
+rustcanbus_tx_shaper_dropped_total{channel="1"} 0
+# HELP rustcanbus_reconnects_total Times an unplugged adapter was reopened.
+# TYPE rustcanbus_reconnects_total counter
+rustcanbus_reconnects_total{adapter="0"} 0
+# HELP rustcanbus_consumer_dropped_total Frames a consumer dropped.\nCounted per consumer, at C:\\logs.
+# TYPE rustcanbus_consumer_dropped_total counter
+rustcanbus_consumer_dropped_total{consumer="csv \"main\"",path="C:\\logs\nnext"} 7
+rustcanbus_consumer_dropped_total{consumer="ws"} 0
+# HELP rustcanbus_extremes Values without a number.
+# TYPE rustcanbus_extremes gauge
+rustcanbus_extremes{value="inf"} +Inf
+rustcanbus_extremes{value="-inf"} -Inf
+rustcanbus_extremes{value="nan"} NaN
+rustcanbus_extremes 0.5
+# HELP rustcanbus_script_errors_total Errors in the --script handlers.
+# TYPE rustcanbus_script_errors_total counter
+"#;
+
+    /// Channels with some traffic and a failed receive, and a few custom metrics.
+    fn sample_metrics() -> (Arc<crate::mock::MockBackend>, crate::device::Device, Channel, Channel, Arc<Metrics>) {
+        let (mock, device, can1, can2) = started_pair();
+        can1.transmit(&Frame::new(Id::Standard(0x123), &[1, 2, 3]).unwrap()).unwrap();
+        can1.transmit(&Frame::new(Id::Extended(0x18FF_50E5), &[0; 8]).unwrap()).unwrap();
+        assert!(can1.flush_transmit(Duration::from_millis(100)));
+        assert_eq!(can2.receive(Duration::from_millis(100)).unwrap().len(), 2);
+        mock.fail_next(MockCall::Receive, -1, 1);
+        assert!(can2.receive(Duration::from_millis(1)).is_err());
+
+        let metrics = Arc::new(Metrics::new());
+        metrics.register_channels(&[can1.clone(), can2.clone()]);
+        let help = "Frames a consumer dropped.\nCounted per consumer, at C:\\logs.";
+        metrics.counter("rustcanbus_consumer_dropped_total", help, &[("consumer", "csv \"main\""), ("path", "C:\\logs\nnext")]).store(7, Ordering::Relaxed);
+        metrics.counter("rustcanbus_consumer_dropped_total", "ignored, the family has its help", &[("consumer", "ws")]);
+        metrics.register("rustcanbus_extremes", "Values without a number.", MetricKind::Gauge, || {
+            ["inf", "-inf", "nan"].iter().zip([f64::INFINITY, f64::NEG_INFINITY, f64::NAN]).map(|(label, value)| Sample::new(&[("value", label)], value)).collect()
+        });
+        metrics.register("rustcanbus_extremes", "", MetricKind::Counter, || vec![Sample::new(&[], 0.5)]);
+        metrics.register("rustcanbus_script_errors_total", "Errors in the --script handlers.", MetricKind::Counter, Vec::new);
+        (mock, device, can1, can2, metrics)
+    }
+
+    #[test]
+    fn exposition_format_snapshot() {
+        let (_mock, _device, _can1, _can2, metrics) = sample_metrics();
+        assert_eq!(metrics.render(), SNAPSHOT);
+    }
+
+    #[test]
+    fn values_are_read_at_every_render() {
+        let metrics = Metrics::new();
+        let counter = metrics.counter("dropped_total", "Dropped.", &[]);
+        assert!(metrics.render().ends_with("\ndropped_total 0\n"));
+        counter.fetch_add(3, Ordering::Relaxed);
+        assert!(metrics.render().ends_with("\ndropped_total 3\n"));
+        assert_eq!(Metrics::new().render(), "");
+    }
+
+    fn get(server: &MetricsServer, request: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let length = head.lines().find_map(|line| line.strip_prefix("Content-Length: ")).unwrap();
+        assert_eq!(length.parse::<usize>().unwrap(), body.len(), "{head}");
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+
+    /// Polls `/healthz` until it answers with `status`, and returns the body; the connection
+    /// state changes on the reconnect thread.
+    fn health(server: &MetricsServer, status: &str) -> String {
+        let deadline = std::time::Instant::now() + Duration::from_secs(2);
+        loop {
+            let (got, body) = get(server, "GET /healthz HTTP/1.1\r\n\r\n");
+            if got == status {
+                return body;
+            }
+            assert!(std::time::Instant::now() < deadline, "still {got}: {body}");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn the_endpoint_serves_metrics_and_health() {
+        let (mock, device, can1, can2, metrics) = sample_metrics();
+        let server = MetricsServer::start("127.0.0.1:0", metrics, &[can1, can2.clone()]).unwrap();
+        let (status, body) = get(&server, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert_eq!((status.as_str(), body.as_str()), ("HTTP/1.1 200 OK", SNAPSHOT));
+        assert_eq!(get(&server, "GET /healthz?verbose=1 HTTP/1.1\r\n\r\n"), ("HTTP/1.1 200 OK".into(), "ok\n".into()));
+        assert_eq!(get(&server, "GET / HTTP/1.1\r\n\r\n").0, "HTTP/1.1 404 Not Found");
+        assert_eq!(get(&server, "POST /metrics HTTP/1.1\r\n\r\n").0, "HTTP/1.1 405 Method Not Allowed");
+
+        device.set_auto_reconnect(Some(Reconnect { after_failures: 3, interval: Duration::from_millis(10), tx: DisconnectedTx::Drop }));
+        mock.set_connected(false);
+        for _ in 0..3 {
+            assert!(can2.receive(Duration::from_millis(1)).is_err());
+        }
+        let body = health(&server, "HTTP/1.1 503 Service Unavailable");
+        assert!(body.starts_with("channel 0: adapter 0 ") && body.contains("\nchannel 1: adapter 0 "), "{body}");
+        mock.set_connected(true);
+        assert_eq!(health(&server, "HTTP/1.1 200 OK"), "ok\n");
+        let (_, body) = get(&server, "GET /metrics HTTP/1.1\r\n\r\n");
+        assert!(body.contains("\nrustcanbus_reconnects_total{adapter=\"0\"} 1\n"), "{body}");
+    }
+}
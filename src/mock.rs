@@ -0,0 +1,496 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::backend::CanBackend;
+use crate::device::CHANNEL_COUNT;
+use crate::ffi::{VciBoardInfo, VciCanObj, VciCanStatus, VciErrInfo, VciInitConfig};
+use crate::frame::Frame;
+use crate::mode::ChannelMode;
+use crate::status::ErrorFlags;
+
+/// SJA1000 status register bits reported by a bus-off mock channel.
+const STATUS_ERROR: u8 = 0x40;
+const STATUS_BUS_OFF: u8 = 0x80;
+
+/// A VCI call, for [`MockBackend::fail_next`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MockCall {
+    OpenDevice,
+    CloseDevice,
+    UsbDeviceReset,
+    FindUsbDevices,
+    ReadBoardInfo,
+    InitCan,
+    StartCan,
+    ResetCan,
+    ClearBuffer,
+    Transmit,
+    Receive,
+    GetReceiveNum,
+    ReadErrInfo,
+    ReadCanStatus,
+    SetReference,
+}
+
+/// An in-memory CANalyst-II for running a [`Device`](crate::Device) without hardware, via
+/// [`Device::open_with`](crate::Device::open_with).
+///
+/// The two channels are wired to each other: a frame transmitted on one arrives on the other
+/// after [`MockBackend::set_latency`], unless [`MockBackend::set_loss`] drops it. A channel in
+/// [`ChannelMode::SelfTest`] receives its own frames instead. Frames are only delivered to
+/// started channels and pass through the receiving channel's acceptance filter.
+pub struct MockBackend {
+    state: Mutex<MockState>,
+    delivered: Condvar,
+    epoch: Instant,
+}
+
+struct MockState {
+    open: bool,
+    /// Cleared by [`MockBackend::set_connected`] to simulate the adapter being unplugged.
+    plugged: bool,
+    latency: Duration,
+    /// Chance of dropping each transmitted frame, 0..=1.
+    loss: f64,
+    /// Chance of a transmit call taking only part of its batch, 0..=1.
+    partial: f64,
+    rng: XorShift,
+    failures: HashMap<MockCall, (i32, u32)>,
+    unsupported: HashSet<MockCall>,
+    channels: [MockChannel; CHANNEL_COUNT as usize],
+}
+
+/// xorshift64 from a fixed seed: good enough for simulated loss, and for the crate's fuzz and
+/// property tests, where a failing input must be reproducible.
+#[derive(Debug, Clone)]
+pub(crate) struct XorShift(pub(crate) u64);
+
+impl XorShift {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Below `bound`, which must not be 0.
+    #[cfg(test)]
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    #[cfg(test)]
+    pub(crate) fn pick<T: Copy>(&mut self, values: &[T]) -> T {
+        values[self.below(values.len() as u64) as usize]
+    }
+
+    /// Uniform in 0..1.
+    pub(crate) fn chance(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[derive(Default)]
+struct MockChannel {
+    config: Option<VciInitConfig>,
+    started: bool,
+    bus_off: bool,
+    /// Received frames with the time they become visible.
+    queue: VecDeque<(Instant, VciCanObj)>,
+    transmitted: Vec<Frame>,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockBackend {
+    /// No latency, no loss, no injected failures.
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(MockState {
+                open: false,
+                plugged: true,
+                latency: Duration::ZERO,
+                loss: 0.0,
+                partial: 0.0,
+                rng: XorShift(0x2545_F491_4F6C_DD1D),
+                failures: HashMap::new(),
+                unsupported: HashSet::new(),
+                channels: Default::default(),
+            }),
+            delivered: Condvar::new(),
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Delay between a transmit and the frame showing up on the receiving channel.
+    pub fn set_latency(&self, latency: Duration) {
+        self.lock().latency = latency;
+    }
+
+    /// Drops each transmitted frame with probability `loss` (clamped to 0..=1). The transmit
+    /// still reports success, as it would on a real bus with nobody acknowledging.
+    pub fn set_loss(&self, loss: f64) {
+        self.lock().loss = loss.clamp(0.0, 1.0);
+    }
+
+    /// Makes each transmit call, with probability `chance` (clamped to 0..=1), accept only a
+    /// random part of its batch, possibly none of it, as a full hardware transmit buffer does.
+    pub fn set_partial_transmit(&self, chance: f64) {
+        self.lock().partial = chance.clamp(0.0, 1.0);
+    }
+
+    /// Makes the next `times` calls of kind `call` return `code` without doing anything.
+    pub fn fail_next(&self, call: MockCall, code: i32, times: u32) {
+        self.lock().failures.insert(call, (code, times));
+    }
+
+    /// Simulates an older DLL lacking the function behind `call`. Only the functions a
+    /// [`CanLibrary`](crate::CanLibrary) treats as optional can be removed; others are ignored.
+    pub fn set_supported(&self, call: MockCall, supported: bool) {
+        let mut state = self.lock();
+        if supported {
+            state.unsupported.remove(&call);
+        } else {
+            state.unsupported.insert(call);
+        }
+    }
+
+    /// Puts `channel` into bus-off, as reported by the error info and status calls. Transmits
+    /// are refused until the channel is initialized again.
+    pub fn set_bus_off(&self, channel: u32, bus_off: bool) {
+        self.lock().channels[channel as usize].bus_off = bus_off;
+    }
+
+    /// Unplugs (`false`) or replugs (`true`) the adapter. Unplugging drops the handle and all
+    /// channel state, so receive and transmit return -1 and the device cannot be opened or
+    /// found until it is plugged back in.
+    pub fn set_connected(&self, connected: bool) {
+        let mut state = self.lock();
+        state.plugged = connected;
+        if !connected {
+            state.open = false;
+            for channel in &mut state.channels {
+                *channel = MockChannel::default();
+            }
+        }
+        self.delivered.notify_all();
+    }
+
+    /// Delivers `frame` to `channel` as if another node had sent it, subject to the channel
+    /// being started and its acceptance filter.
+    pub fn inject(&self, channel: u32, frame: &Frame) {
+        let mut state = self.lock();
+        let at = Instant::now() + state.latency;
+        let obj = self.stamped(frame, at);
+        state.channels[channel as usize].deliver(at, obj);
+        self.delivered.notify_all();
+    }
+
+    /// Frames successfully transmitted on `channel` since the last call.
+    pub fn take_transmitted(&self, channel: u32) -> Vec<Frame> {
+        std::mem::take(&mut self.lock().channels[channel as usize].transmitted)
+    }
+
+    /// Runs an optional VCI function unless it was removed with [`MockBackend::set_supported`]
+    /// or has an injected failure pending.
+    fn optional(&self, call: MockCall, f: impl FnOnce(&mut MockState) -> i32) -> Option<i32> {
+        let mut state = self.lock();
+        if state.unsupported.contains(&call) {
+            return None;
+        }
+        Some(state.failure(call).unwrap_or_else(|| f(&mut state)))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Timestamp in device ticks (0.1 ms) since the backend was created.
+    fn stamped(&self, frame: &Frame, at: Instant) -> VciCanObj {
+        let ticks = at.duration_since(self.epoch).as_micros() / 100;
+        VciCanObj { time_stamp: ticks as u32, time_flag: 1, ..VciCanObj::from(frame) }
+    }
+}
+
+impl MockState {
+    /// Consumes one injected failure for `call`, if any is pending.
+    fn failure(&mut self, call: MockCall) -> Option<i32> {
+        let (code, times) = self.failures.get_mut(&call)?;
+        let code = *code;
+        *times -= 1;
+        if *times == 0 {
+            self.failures.remove(&call);
+        }
+        Some(code)
+    }
+
+    fn channel(&mut self, dev_index: u32, can_index: u32) -> Option<&mut MockChannel> {
+        if !self.open || dev_index != 0 {
+            return None;
+        }
+        self.channels.get_mut(can_index as usize)
+    }
+
+    fn chance(&mut self) -> f64 {
+        self.rng.chance()
+    }
+}
+
+impl MockChannel {
+    fn mode(&self) -> Option<ChannelMode> {
+        self.config.and_then(|config| config.channel_mode())
+    }
+
+    fn deliver(&mut self, at: Instant, obj: VciCanObj) {
+        if self.started && self.config.is_some_and(|config| accepts(&config, &obj)) {
+            self.queue.push_back((at, obj));
+        }
+    }
+
+    fn ready(&self, now: Instant) -> usize {
+        self.queue.iter().take_while(|(at, _)| *at <= now).count()
+    }
+}
+
+/// The SJA1000 single-filter check on the left-aligned ID; mask bits of 1 are don't-care.
+fn accepts(config: &VciInitConfig, obj: &VciCanObj) -> bool {
+    let extended = obj.extern_flag != 0;
+    match config.filter {
+        2 if extended => return false,
+        3 if !extended => return false,
+        _ => {}
+    }
+    let (aligned, care) = if extended {
+        (obj.id << 3, !config.acc_mask & !0b111)
+    } else {
+        (obj.id << 21, !config.acc_mask & !0x001F_FFFF)
+    };
+    (aligned ^ config.acc_code) & care == 0
+}
+
+macro_rules! fail_or {
+    ($state:expr, $call:expr) => {
+        if let Some(code) = $state.failure($call) {
+            return code;
+        }
+    };
+}
+
+impl CanBackend for MockBackend {
+    fn open_device(&self, _dev_type: u32, dev_index: u32) -> i32 {
+        let mut state = self.lock();
+        fail_or!(state, MockCall::OpenDevice);
+        if dev_index != 0 || !state.plugged {
+            return 0;
+        }
+        state.open = true;
+        1
+    }
+
+    fn close_device(&self, _dev_type: u32, _dev_index: u32) -> i32 {
+        let mut state = self.lock();
+        fail_or!(state, MockCall::CloseDevice);
+        state.open = false;
+        for channel in &mut state.channels {
+            *channel = MockChannel::default();
+        }
+        1
+    }
+
+    fn usb_device_reset(&self, _dev_type: u32, dev_index: u32) -> Option<i32> {
+        self.optional(MockCall::UsbDeviceReset, |state| i32::from(dev_index == 0 && state.plugged))
+    }
+
+    fn find_usb_devices(&self, infos: &mut [VciBoardInfo]) -> Option<i32> {
+        self.optional(MockCall::FindUsbDevices, |state| {
+            let Some(info) = infos.first_mut().filter(|_| state.plugged) else {
+                return 0;
+            };
+            *info = mock_board_info();
+            1
+        })
+    }
+
+    fn read_board_info(&self, _dev_type: u32, dev_index: u32, info: &mut VciBoardInfo) -> Option<i32> {
+        self.optional(MockCall::ReadBoardInfo, |state| {
+            if !state.open || dev_index != 0 {
+                return 0;
+            }
+            *info = mock_board_info();
+            1
+        })
+    }
+
+    fn init_can(&self, _dev_type: u32, dev_index: u32, can_index: u32, config: &VciInitConfig) -> i32 {
+        let mut state = self.lock();
+        fail_or!(state, MockCall::InitCan);
+        let Some(channel) = state.channel(dev_index, can_index) else {
+            return 0;
+        };
+        channel.config = Some(*config);
+        channel.started = false;
+        channel.bus_off = false;
+        channel.queue.clear();
+        1
+    }
+
+    fn start_can(&self, _dev_type: u32, dev_index: u32, can_index: u32) -> i32 {
+        let mut state = self.lock();
+        fail_or!(state, MockCall::StartCan);
+        match state.channel(dev_index, can_index) {
+            Some(channel) if channel.config.is_some() => {
+                channel.started = true;
+                1
+            }
+            _ => 0,
+        }
+    }
+
+    fn reset_can(&self, _dev_type: u32, dev_index: u32, can_index: u32) -> Option<i32> {
+        self.optional(MockCall::ResetCan, |state| {
+            let Some(channel) = state.channel(dev_index, can_index) else {
+                return 0;
+            };
+            channel.started = false;
+            1
+        })
+    }
+
+    fn clear_buffer(&self, _dev_type: u32, dev_index: u32, can_index: u32) -> Option<i32> {
+        self.optional(MockCall::ClearBuffer, |state| {
+            let Some(channel) = state.channel(dev_index, can_index) else {
+                return 0;
+            };
+            channel.queue.clear();
+            1
+        })
+    }
+
+    fn transmit(&self, _dev_type: u32, dev_index: u32, can_index: u32, frames: &[VciCanObj]) -> i32 {
+        let mut state = self.lock();
+        fail_or!(state, MockCall::Transmit);
+        let Some(channel) = state.channel(dev_index, can_index) else {
+            return -1;
+        };
+        if !channel.started || channel.bus_off || channel.mode() == Some(ChannelMode::ListenOnly) {
+            return 0;
+        }
+        let self_test = channel.mode() == Some(ChannelMode::SelfTest);
+        let target = if self_test { can_index } else { can_index ^ 1 } as usize;
+        let at = Instant::now() + state.latency;
+        let accepted = match state.partial > 0.0 && state.chance() < state.partial {
+            true => (state.chance() * frames.len() as f64) as usize,
+            false => frames.len(),
+        };
+        let frames = &frames[..accepted.min(frames.len())];
+        for obj in frames {
+            let frame = Frame::from(obj);
+            state.channels[can_index as usize].transmitted.push(frame);
+            if state.loss > 0.0 && state.chance() < state.loss {
+                continue;
+            }
+            let obj = self.stamped(&frame, at);
+            state.channels[target].deliver(at, obj);
+        }
+        self.delivered.notify_all();
+        frames.len() as i32
+    }
+
+    fn receive(&self, _dev_type: u32, dev_index: u32, can_index: u32, frames: &mut [VciCanObj], wait_ms: i32) -> i32 {
+        let mut state = self.lock();
+        fail_or!(state, MockCall::Receive);
+        let deadline = Instant::now() + Duration::from_millis(wait_ms.max(0) as u64);
+        loop {
+            let now = Instant::now();
+            let Some(channel) = state.channel(dev_index, can_index) else {
+                return -1;
+            };
+            let ready = channel.ready(now).min(frames.len());
+            if ready > 0 {
+                for (slot, (_, obj)) in frames.iter_mut().zip(channel.queue.drain(..ready)) {
+                    *slot = obj;
+                }
+                return ready as i32;
+            }
+            if now >= deadline {
+                return 0;
+            }
+            // Wake for new frames, or when the first queued one comes due.
+            let due = channel.queue.front().map_or(deadline, |(at, _)| (*at).min(deadline));
+            state = self.delivered.wait_timeout(state, due - now).unwrap().0;
+        }
+    }
+
+    fn get_receive_num(&self, _dev_type: u32, dev_index: u32, can_index: u32) -> Option<i32> {
+        self.optional(MockCall::GetReceiveNum, |state| {
+            match state.channel(dev_index, can_index) {
+                Some(channel) => channel.ready(Instant::now()) as i32,
+                None => -1,
+            }
+        })
+    }
+
+    fn read_err_info(&self, _dev_type: u32, dev_index: u32, can_index: u32, info: &mut VciErrInfo) -> Option<i32> {
+        self.optional(MockCall::ReadErrInfo, |state| {
+            let Some(channel) = state.channel(dev_index, can_index) else {
+                return 0;
+            };
+            *info = VciErrInfo::default();
+            if channel.bus_off {
+                info.err_code = ErrorFlags::BUS_OFF.bits();
+            }
+            1
+        })
+    }
+
+    fn read_can_status(&self, _dev_type: u32, dev_index: u32, can_index: u32, status: &mut VciCanStatus) -> Option<i32> {
+        self.optional(MockCall::ReadCanStatus, |state| {
+            let Some(channel) = state.channel(dev_index, can_index) else {
+                return 0;
+            };
+            *status = VciCanStatus {
+                reg_mode: if channel.started { channel.mode().map_or(0, |mode| mode.raw() << 1) } else { 0x01 },
+                ..VciCanStatus::default()
+            };
+            if channel.bus_off {
+                status.reg_status = STATUS_ERROR | STATUS_BUS_OFF;
+                status.reg_te_counter = 255;
+            }
+            1
+        })
+    }
+
+    fn set_reference(&self, _dev_type: u32, dev_index: u32, can_index: u32, _ref_type: u32, _data: &mut [u8]) -> Option<i32> {
+        self.optional(MockCall::SetReference, |state| {
+            i32::from(state.channel(dev_index, can_index).is_some())
+        })
+    }
+}
+
+fn mock_board_info() -> VciBoardInfo {
+    let mut info = VciBoardInfo { hw_version: 0x0100, fw_version: 0x0100, can_num: CHANNEL_COUNT as u8, ..Default::default() };
+    info.str_serial_num[..8].copy_from_slice(b"MOCK0001");
+    info.str_hw_type[..7].copy_from_slice(b"Mock-II");
+    info
+}
+
+/// A mock adapter with both ports started at 500 kbit/s in normal mode, wired to each other,
+/// for the crate's unit tests.
+#[cfg(test)]
+pub(crate) fn started_pair() -> (std::sync::Arc<MockBackend>, crate::device::Device, crate::device::Channel, crate::device::Channel) {
+    let mock = std::sync::Arc::new(MockBackend::new());
+    let device = crate::device::Device::open_with(mock.clone(), 4, 0).expect("the mock opens");
+    let config = VciInitConfig::with_bitrate(crate::bitrate::Bitrate::Kbps500);
+    let [can1, can2] = [0, 1].map(|index| {
+        let channel = device.channel(index);
+        channel.init(&config).expect("init");
+        channel.start().expect("start");
+        channel
+    });
+    (mock, device, can1, can2)
+}
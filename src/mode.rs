@@ -0,0 +1,55 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Controller operating mode written to `VciInitConfig.mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelMode {
+    #[default]
+    Normal,
+    /// Receive only: the controller never ACKs or transmits, so it is invisible on the bus.
+    ListenOnly,
+    /// Internal loopback for testing the adapter without a bus.
+    SelfTest,
+}
+
+impl ChannelMode {
+    pub fn raw(self) -> u8 {
+        match self {
+            ChannelMode::Normal => 0,
+            ChannelMode::ListenOnly => 1,
+            ChannelMode::SelfTest => 2,
+        }
+    }
+
+    pub fn from_raw(raw: u8) -> Option<Self> {
+        match raw {
+            0 => Some(ChannelMode::Normal),
+            1 => Some(ChannelMode::ListenOnly),
+            2 => Some(ChannelMode::SelfTest),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ChannelMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ChannelMode::Normal => "normal",
+            ChannelMode::ListenOnly => "listen-only",
+            ChannelMode::SelfTest => "self-test",
+        })
+    }
+}
+
+impl FromStr for ChannelMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "normal" => Ok(ChannelMode::Normal),
+            "listen-only" => Ok(ChannelMode::ListenOnly),
+            "self-test" => Ok(ChannelMode::SelfTest),
+            _ => Err(format!("unknown channel mode '{s}', expected normal, listen-only or self-test")),
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor};
+use crossterm::{cursor, queue, terminal};
+use rustcanbus::{ChannelHandle, IdNames, IdTracker, IntegrityChecker, IntegrityStats, J1939Id, Plot, TrackedId};
+
+use crate::color;
+use crate::pause::Pause;
+use crate::prompt::{self, Prompt};
+
+const REFRESH: Duration = Duration::from_millis(100);
+/// Width of a row without the trailing counters: channel, ID, DLC and 8 data bytes.
+const ROW_WIDTH: usize = 5 + 1 + 10 + 1 + 3 + 1 + 23 + 2 + 8 + 1 + 9;
+/// Extra width of the optional statistics columns.
+const STATS_WIDTH: usize = 4 * 10;
+/// Extra width of the J1939 group acronym.
+const J1939_WIDTH: usize = 8;
+/// Extra width of the counter and checksum columns of IDs with an integrity check.
+const INTEGRITY_WIDTH: usize = 2 + 22 + 1 + 9;
+/// Longest `--names` name shown in full; longer ones are cut.
+const MAX_NAME_WIDTH: usize = 24;
+
+pub struct MonitorOptions {
+    /// How long a changed byte stays highlighted.
+    pub hold: Duration,
+    /// With `hide_static` set, IDs whose data hasn't changed for this long are hidden.
+    pub changed_within: Duration,
+    /// Show rate and min/max/mean gap columns.
+    pub stats: bool,
+    /// Show extended IDs as `PGN:SA`, followed by the acronym of known J1939 groups.
+    pub pgn_ids: bool,
+    /// From `--names`, shown in a column after the ID.
+    pub names: Option<&'static IdNames>,
+}
+
+impl MonitorOptions {
+    /// Width of the name column, without the space before it; 0 without names.
+    fn name_width(&self) -> usize {
+        self.names.map_or(0, |names| names.max_name_len().clamp(4, MAX_NAME_WIDTH))
+    }
+}
+
+/// State the monitor view reads from the receive and keyboard threads.
+pub struct Shared<'a> {
+    pub tracker: &'a Mutex<IdTracker>,
+    pub pause: &'a Pause,
+    pub prompt: &'a Prompt,
+    pub hide_static: &'a AtomicBool,
+    /// Drawn under the table, two lines each, while `show_plots` is set.
+    pub plots: &'a Mutex<Vec<Plot>>,
+    pub show_plots: &'a AtomicBool,
+    pub handles: &'a [ChannelHandle],
+    pub integrity: &'a Mutex<IntegrityChecker>,
+}
+
+/// Redraws the per-ID table in the alternate screen at ~10 Hz until `running` is cleared. While
+/// paused the last picture stays on screen and the tracker keeps updating underneath.
+pub fn run(
+    shared: &Shared,
+    running: &AtomicBool,
+    (received, sent): (&AtomicU64, &AtomicU64),
+    options: &MonitorOptions,
+) -> io::Result<()> {
+    let Shared { tracker, pause, prompt, hide_static, plots, show_plots, handles, integrity } = *shared;
+    let mut out = io::stdout();
+    queue!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let mut last_size = None;
+    let mut drawn_paused = false;
+
+    while running.load(Ordering::SeqCst) {
+        let size = terminal::size()?;
+        let paused = pause.is_paused();
+        let prompt_state = prompt.snapshot();
+        if paused && drawn_paused && last_size == Some(size) && prompt_state.input.is_none() {
+            thread::sleep(REFRESH);
+            continue;
+        }
+        drawn_paused = paused;
+        if last_size != Some(size) {
+            queue!(out, terminal::Clear(terminal::ClearType::All))?;
+            last_size = Some(size);
+        }
+        let (width, height) = (size.0 as usize, size.1 as usize);
+        let now = Instant::now();
+        let hiding = hide_static.load(Ordering::SeqCst);
+
+        let tracker = tracker.lock().unwrap();
+        let integrity = integrity.lock().unwrap();
+        let rows: Vec<&TrackedId> = tracker
+            .iter()
+            .filter(|entry| {
+                !hiding
+                    || entry
+                        .last_change()
+                        .is_some_and(|at| now.saturating_duration_since(at) <= options.changed_within)
+            })
+            .collect();
+        let header = format!(
+            "{:<5} {:<10} {}{:<3} {:<23}  {:>8} {:>9}{}   {} IDs{}, rx {} tx {}{}",
+            "Ch",
+            if options.pgn_ids { "ID/PGN:SA" } else { "ID" },
+            match options.name_width() {
+                0 => String::new(),
+                width => format!("{:<width$} ", "Name"),
+            },
+            "DLC",
+            "Data",
+            "Count",
+            "Cycle",
+            if options.stats { format!(" {:>9} {:>9} {:>9} {:>9}", "Rate", "Min gap", "Max gap", "Mean gap") } else { String::new() },
+            rows.len(),
+            if hiding { " changing" } else { "" },
+            received.load(Ordering::SeqCst),
+            sent.load(Ordering::SeqCst),
+            if paused { "  [paused]" } else { "" }
+        );
+        let load: Vec<String> = handles
+            .iter()
+            .enumerate()
+            .map(|(slot, handle)| match handle.channel().bus_load() {
+                Some(load) => format!("CAN{} load {load:.1}%", slot + 1),
+                None => format!("CAN{} load n/a", slot + 1),
+            })
+            .collect();
+        let header = format!("{header}   {}", load.join("  "));
+
+        let plot_lines = match show_plots.load(Ordering::SeqCst) {
+            true => plots.lock().unwrap().iter().flat_map(|plot| plot_lines(plot, width)).collect(),
+            false => Vec::new(),
+        };
+        let visible = height.saturating_sub(3 + plot_lines.len());
+        queue!(out, cursor::MoveTo(0, 0), Print(truncate(&header, width)))?;
+        queue!(out, terminal::Clear(terminal::ClearType::UntilNewLine))?;
+        for (y, entry) in rows.iter().take(visible).enumerate() {
+            queue!(out, cursor::MoveTo(0, y as u16 + 1))?;
+            let checked = integrity.is_declared(entry.frame.id()).then(|| {
+                integrity.stats(entry.channel, entry.frame.id()).copied().unwrap_or_default()
+            });
+            draw_row(&mut out, entry, checked.as_ref(), width, now, options)?;
+            queue!(out, terminal::Clear(terminal::ClearType::UntilNewLine))?;
+        }
+        if rows.len() > visible {
+            let more = format!("... {} more IDs (enlarge the terminal)", rows.len() - visible);
+            queue!(out, cursor::MoveTo(0, visible as u16 + 1), Print(truncate(&more, width)))?;
+            queue!(out, terminal::Clear(terminal::ClearType::UntilNewLine))?;
+        }
+        drop((tracker, integrity));
+        queue!(out, terminal::Clear(terminal::ClearType::FromCursorDown))?;
+        let top = height.saturating_sub(1 + plot_lines.len());
+        for (y, line) in plot_lines.iter().enumerate() {
+            queue!(out, cursor::MoveTo(0, (top + y) as u16), Print(truncate(line, width)))?;
+        }
+        let bottom = match (&prompt_state.input, &prompt_state.message) {
+            (Some(input), _) => Some(format!("{}{input}", prompt::LABEL)),
+            (None, message) => message.clone(),
+        };
+        if let Some(bottom) = bottom {
+            queue!(out, cursor::MoveTo(0, height.saturating_sub(1) as u16), Print(truncate(&bottom, width)))?;
+        }
+        out.flush()?;
+
+        thread::sleep(REFRESH);
+    }
+
+    queue!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+    out.flush()
+}
+
+/// Redraws `lines` under `header` in the alternate screen, for views such as `obd` that refresh
+/// after each poll rather than on a timer.
+pub fn draw_lines(header: &str, lines: &[String]) -> io::Result<()> {
+    let mut out = io::stdout();
+    let (width, height) = terminal::size()?;
+    let (width, height) = (width as usize, height as usize);
+    queue!(out, cursor::MoveTo(0, 0), Print(truncate(header, width)))?;
+    queue!(out, terminal::Clear(terminal::ClearType::UntilNewLine))?;
+    for (y, line) in lines.iter().take(height.saturating_sub(1)).enumerate() {
+        queue!(out, cursor::MoveTo(0, y as u16 + 1), Print(truncate(line, width)))?;
+        queue!(out, terminal::Clear(terminal::ClearType::UntilNewLine))?;
+    }
+    queue!(out, terminal::Clear(terminal::ClearType::FromCursorDown))?;
+    out.flush()
+}
+
+/// A title with the latest value and the range, then the sparkline of as many samples as fit.
+fn plot_lines(plot: &Plot, width: usize) -> [String; 2] {
+    let title = match (plot.last(), plot.range()) {
+        (Some(last), Some((min, max))) => format!("{}: {}  min {}  max {}", plot.source, value(last), value(min), value(max)),
+        _ => format!("{}: no samples yet", plot.source),
+    };
+    [title, plot.render(width)]
+}
+
+/// Whole numbers without decimals, the rest with three.
+fn value(value: f64) -> String {
+    match value.fract() == 0.0 && value.abs() < 1e15 {
+        true => format!("{value:.0}"),
+        false => format!("{value:.3}"),
+    }
+}
+
+fn truncate(line: &str, width: usize) -> String {
+    line.chars().take(width).collect()
+}
+
+fn gap_ms(gap: Option<Duration>) -> String {
+    match gap {
+        Some(gap) => format!("{:.1} ms", gap.as_secs_f64() * 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+/// Draws one row, highlighting bytes that changed within the hold time. Rows are drawn unstyled
+/// when the terminal is too narrow to fit them.
+fn draw_row(
+    out: &mut impl Write,
+    entry: &TrackedId,
+    integrity: Option<&IntegrityStats>,
+    width: usize,
+    now: Instant,
+    options: &MonitorOptions,
+) -> io::Result<()> {
+    let frame = &entry.frame;
+    let j1939 = J1939Id::from_id(frame.id()).filter(|_| options.pgn_ids);
+    let id = match j1939 {
+        Some(j1939) => format!("{}:{:02X}", j1939.pgn, j1939.source),
+        None => frame.id().to_string(),
+    };
+    let name = match (options.names, options.name_width()) {
+        (Some(names), width) => {
+            let name: String = names.name(frame.id()).unwrap_or("").chars().take(width).collect();
+            format!("{name:<width$} ")
+        }
+        (None, _) => String::new(),
+    };
+    let prefix = format!("CAN{:<2} {id:<10} {name}{:<3} ", entry.channel + 1, frame.dlc());
+    let mut suffix = format!("  {:>8} {:>9}", entry.count, gap_ms(entry.cycle));
+    let mut row_width = ROW_WIDTH + name.len();
+    if options.stats {
+        let stats = &entry.stats;
+        suffix += &format!(
+            " {:>9} {:>9} {:>9} {:>9}",
+            format!("{:.1} Hz", stats.rate_hz()),
+            gap_ms(stats.min_gap()),
+            gap_ms(stats.max_gap()),
+            gap_ms(stats.mean_gap())
+        );
+        row_width += STATS_WIDTH;
+    }
+    if let Some(def) = j1939.and_then(|j1939| j1939.pgn_def()) {
+        suffix += &format!("  {:<6}", def.acronym);
+        row_width += J1939_WIDTH;
+    }
+    if let Some(stats) = integrity {
+        let counter = format!("skips {} ({} missed)", stats.skips, stats.missed);
+        suffix += &format!("  {counter:<22} {:>9}", format!("cks {}", stats.checksum_failures));
+        row_width += INTEGRITY_WIDTH;
+    }
+
+    if frame.is_remote() {
+        return queue!(out, Print(truncate(&format!("{prefix}{:<23}{suffix}", "RTR"), width)));
+    }
+    let bytes: Vec<String> = frame.data().iter().map(|b| format!("{b:02X}")).collect();
+    if width < row_width {
+        return queue!(out, Print(truncate(&format!("{prefix}{:<23}{suffix}", bytes.join(" ")), width)));
+    }
+
+    // Only the full-width row is colored: escape codes would throw off truncating the others.
+    let (label, rest) = prefix.split_at(5);
+    let (id, rest) = rest.split_at(11);
+    match color::channel_color(entry.channel) {
+        Some(color) => queue!(out, SetForegroundColor(color), Print(label), ResetColor)?,
+        None => queue!(out, Print(label))?,
+    }
+    match color::id_color(frame.id()) {
+        Some(color) => queue!(out, SetForegroundColor(color), Print(id), ResetColor)?,
+        None => queue!(out, Print(id))?,
+    }
+    queue!(out, Print(rest))?;
+    for (i, byte) in bytes.iter().enumerate() {
+        if i > 0 {
+            queue!(out, Print(' '))?;
+        }
+        if entry.byte_changed(i, options.hold, now) {
+            queue!(out, SetForegroundColor(Color::Red), SetAttribute(Attribute::Bold), Print(byte))?;
+            queue!(out, SetAttribute(Attribute::Reset), ResetColor)?;
+        } else {
+            queue!(out, Print(byte))?;
+        }
+    }
+    let used = bytes.len() * 3 - usize::from(!bytes.is_empty());
+    queue!(out, Print(" ".repeat(23 - used)), Print(suffix))
+}
@@ -0,0 +1,278 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use rumqttc::{Client, Connection, Event, MqttOptions, Packet, QoS};
+
+use crate::dbc::Dbc;
+use crate::device::Channel;
+use crate::fanout::Subscription;
+use crate::frame::Frame;
+use crate::json::{JsonFrame, JsonTransmit};
+use crate::timestamp::host_time;
+
+/// How often the worker threads look at the stop flag.
+const POLL: Duration = Duration::from_millis(100);
+/// Frames queued per channel for the publisher thread.
+const QUEUE: usize = 4096;
+
+/// Settings for an [`MqttBridge`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    /// Put in front of every topic, e.g. `can/` for `can/0/123`.
+    pub topic_prefix: String,
+    /// 0, 1 or 2, for publishing and the transmit subscription alike.
+    pub qos: u8,
+    pub keep_alive: Duration,
+    /// Wait between attempts while the broker is unreachable.
+    pub reconnect_delay: Duration,
+    /// Publishes held while the broker is unreachable or slow; more are dropped and counted.
+    pub buffer: usize,
+}
+
+impl MqttConfig {
+    /// QoS 0, topics under `can/`, 30 s keep-alive, a reconnect every 5 s, 10000 buffered messages.
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            client_id: format!("rustcanbus-{}", std::process::id()),
+            topic_prefix: "can/".to_string(),
+            qos: 0,
+            keep_alive: Duration::from_secs(30),
+            reconnect_delay: Duration::from_secs(5),
+            buffer: 10_000,
+        }
+    }
+
+    fn qos(&self) -> QoS {
+        match self.qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            _ => QoS::ExactlyOnce,
+        }
+    }
+}
+
+/// Something the bridge reports while it runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MqttEvent {
+    Connected,
+    /// The connection failed or dropped; the bridge keeps retrying after the reconnect delay.
+    /// Reported once per outage, not for every failed attempt.
+    Disconnected(String),
+    /// A message on a transmit topic couldn't be sent: bad topic, bad JSON, or a transmit error.
+    TransmitFailed { topic: String, error: String },
+}
+
+pub type MqttObserver = Box<dyn Fn(MqttEvent) + Send + Sync>;
+
+#[derive(Debug, Default)]
+struct Counters {
+    published: AtomicU64,
+    dropped: AtomicU64,
+    transmitted: AtomicU64,
+}
+
+/// Publishes received frames to an MQTT broker and transmits frames published to it.
+///
+/// Every frame goes to `<prefix><channel>/<ID in hex>` as a [`JsonFrame`], and with a DBC
+/// each of its signals' physical value to `<prefix><channel>/<message>/<signal>` as plain
+/// text. A [`JsonTransmit`] published to `<prefix><channel>/tx` is sent on that channel.
+/// Publishing never blocks reception: while the broker is away up to
+/// [`MqttConfig::buffer`] messages wait, and the rest are dropped and counted.
+pub struct MqttBridge {
+    client: Client,
+    counters: Arc<Counters>,
+    connected: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl MqttBridge {
+    /// Starts connecting in the background; `observer` hears about connection changes and
+    /// failed transmit requests.
+    pub fn start(config: &MqttConfig, channels: &[Channel], dbc: Option<Arc<Dbc>>, observer: MqttObserver) -> Self {
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(config.keep_alive);
+        let (client, connection) = Client::new(options, config.buffer.max(1));
+        let counters = Arc::<Counters>::default();
+        let (connected, stop) = (Arc::new(AtomicBool::new(false)), Arc::new(AtomicBool::new(false)));
+        let mut threads = Vec::new();
+        for (index, channel) in (0u32..).zip(channels) {
+            let subscription = channel.subscribe(QUEUE);
+            let publisher = Publisher {
+                client: client.clone(),
+                channel: index,
+                prefix: config.topic_prefix.clone(),
+                qos: config.qos(),
+                dbc: dbc.clone(),
+                counters: Arc::clone(&counters),
+            };
+            let stop = Arc::clone(&stop);
+            threads.push(thread::spawn(move || publisher.run(&subscription, &stop)));
+        }
+        let session = Session {
+            client: client.clone(),
+            channels: channels.to_vec(),
+            config: config.clone(),
+            counters: Arc::clone(&counters),
+            connected: Arc::clone(&connected),
+            observer,
+        };
+        let worker_stop = Arc::clone(&stop);
+        threads.push(thread::spawn(move || session.run(connection, &worker_stop)));
+        Self { client, counters, connected, stop, threads }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Messages handed to the client for publishing.
+    pub fn published(&self) -> u64 {
+        self.counters.published.load(Ordering::Relaxed)
+    }
+
+    /// Messages dropped because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.counters.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Frames sent on request from a transmit topic.
+    pub fn transmitted(&self) -> u64 {
+        self.counters.transmitted.load(Ordering::Relaxed)
+    }
+
+    /// Disconnects from the broker; messages still buffered are lost. Also done on drop.
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = self.client.try_disconnect();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for MqttBridge {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+struct Publisher {
+    client: Client,
+    channel: u32,
+    prefix: String,
+    qos: QoS,
+    dbc: Option<Arc<Dbc>>,
+    counters: Arc<Counters>,
+}
+
+impl Publisher {
+    fn run(&self, subscription: &Subscription, stop: &AtomicBool) {
+        while !stop.load(Ordering::SeqCst) {
+            match subscription.recv_timeout(POLL) {
+                Ok(frame) => self.publish_frame(&frame),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+
+    fn publish_frame(&self, frame: &Frame) {
+        let json = JsonFrame::new(host_time(frame), self.channel, frame);
+        let topic = format!("{}{}/{:X}", self.prefix, self.channel, frame.id().raw());
+        self.publish(topic, serde_json::to_vec(&json).unwrap_or_default());
+        let Some((message, signals)) = self.dbc.as_ref().and_then(|dbc| dbc.decode(frame)) else {
+            return;
+        };
+        for value in signals {
+            let topic = format!("{}{}/{}/{}", self.prefix, self.channel, message.name, value.signal.name);
+            self.publish(topic, value.physical.to_string().into_bytes());
+        }
+    }
+
+    fn publish(&self, topic: String, payload: Vec<u8>) {
+        // `try_publish` only fails when the client's request queue is full (or closed on stop).
+        let counter = match self.client.try_publish(topic, self.qos, false, payload) {
+            Ok(()) => &self.counters.published,
+            Err(_) => &self.counters.dropped,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+struct Session {
+    client: Client,
+    channels: Vec<Channel>,
+    config: MqttConfig,
+    counters: Arc<Counters>,
+    connected: Arc<AtomicBool>,
+    observer: MqttObserver,
+}
+
+impl Session {
+    fn run(&self, mut connection: Connection, stop: &AtomicBool) {
+        let tx_topic = format!("{}+/tx", self.config.topic_prefix);
+        let mut outage_reported = false;
+        while !stop.load(Ordering::SeqCst) {
+            match connection.recv_timeout(POLL) {
+                Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => {
+                    // Sessions are clean, so the subscription is renewed on every connect.
+                    let _ = self.client.try_subscribe(&tx_topic, self.config.qos());
+                    self.connected.store(true, Ordering::SeqCst);
+                    outage_reported = false;
+                    (self.observer)(MqttEvent::Connected);
+                }
+                Ok(Ok(Event::Incoming(Packet::Publish(publish)))) => {
+                    if let Err(error) = self.transmit(&publish.topic, &publish.payload) {
+                        (self.observer)(MqttEvent::TransmitFailed { topic: publish.topic.clone(), error });
+                    }
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    self.connected.store(false, Ordering::SeqCst);
+                    if !outage_reported {
+                        outage_reported = true;
+                        (self.observer)(MqttEvent::Disconnected(err.to_string()));
+                    }
+                    sleep_unless_stopped(self.config.reconnect_delay, stop);
+                }
+                Err(rumqttc::RecvTimeoutError::Timeout) => {}
+                Err(rumqttc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        self.connected.store(false, Ordering::SeqCst);
+    }
+
+    fn transmit(&self, topic: &str, payload: &[u8]) -> Result<(), String> {
+        let index = topic
+            .strip_prefix(&self.config.topic_prefix)
+            .and_then(|rest| rest.strip_suffix("/tx"))
+            .and_then(|channel| channel.parse::<usize>().ok())
+            .filter(|&index| index < self.channels.len())
+            .ok_or("no such channel")?;
+        let request: JsonTransmit = serde_json::from_slice(payload).map_err(|err| format!("invalid frame: {err}"))?;
+        self.channels[index].transmit(&request.to_frame()?).map_err(|err| err.to_string())?;
+        self.counters.transmitted.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+fn sleep_unless_stopped(delay: Duration, stop: &AtomicBool) {
+    let mut left = delay;
+    while !left.is_zero() && !stop.load(Ordering::SeqCst) {
+        let step = left.min(POLL);
+        thread::sleep(step);
+        left -= step;
+    }
+}
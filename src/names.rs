@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use toml::{Table, Value};
+
+use crate::filter::parse_filter_id;
+use crate::id::Id;
+
+/// Human-readable names for IDs, for those without a DBC:
+///
+/// ```toml
+/// 0x321 = "BMS_Status"
+/// 18FEF100x = "Engine_Speed"
+/// ```
+///
+/// IDs are read as by `--filter`: an `x` suffix, 8 digits or a value above 0x7FF makes them
+/// extended. Names start with a letter or `_`, hold only letters, digits and `_`, and must not
+/// read as a hex ID themselves, so text such as `BMS_Status:7F0` can be resolved with
+/// [`IdNames::expand`].
+#[derive(Debug, Clone, Default)]
+pub struct IdNames {
+    names: HashMap<Id, String>,
+    ids: HashMap<String, Id>,
+}
+
+impl IdNames {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fails on a bad ID or name, an ID listed twice and a name given to two IDs.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let table: Table = text.parse().map_err(|err: toml::de::Error| {
+            let line = err.span().map_or(0, |span| text[..span.start].matches('\n').count() + 1);
+            format!("line {line}: {}", err.message().trim_end())
+        })?;
+        let mut names = Self::new();
+        for (key, value) in &table {
+            let id = parse_filter_id(key, false)?;
+            let Value::String(name) = value else {
+                return Err(format!("the name of {key} must be a string"));
+            };
+            names.insert(id, name)?;
+        }
+        Ok(names)
+    }
+
+    pub fn insert(&mut self, id: Id, name: &str) -> Result<(), String> {
+        check_name(name)?;
+        if let Some(other) = self.ids.get(name) {
+            return Err(format!("name '{name}' is given to both {other} and {id}"));
+        }
+        if let Some(other) = self.names.get(&id) {
+            return Err(format!("{id} is named both '{other}' and '{name}'"));
+        }
+        self.names.insert(id, name.to_string());
+        self.ids.insert(name.to_string(), id);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn name(&self, id: Id) -> Option<&str> {
+        self.names.get(&id).map(String::as_str)
+    }
+
+    /// Length of the longest name, for lining up columns.
+    pub fn max_name_len(&self) -> usize {
+        self.ids.keys().map(String::len).max().unwrap_or(0)
+    }
+
+    /// The ID called `name`; an unknown name is an error listing the closest known ones.
+    pub fn resolve(&self, name: &str) -> Result<Id, String> {
+        if let Some(&id) = self.ids.get(name) {
+            return Ok(id);
+        }
+        let close = self.close_matches(name);
+        match close.is_empty() {
+            true => Err(format!("unknown ID name '{name}'")),
+            false => Err(format!("unknown ID name '{name}', did you mean {}?", close.join(", "))),
+        }
+    }
+
+    /// Replaces every name in `text` with its ID in hex, three digits for standard IDs and
+    /// eight for extended ones, so `~BMS_Status` becomes `~321`. Words that read as an ID are
+    /// left alone.
+    pub fn expand(&self, text: &str) -> Result<String, String> {
+        let mut expanded = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(is_word_char) {
+            expanded.push_str(&rest[..start]);
+            let word = &rest[start..];
+            let end = word.find(|c: char| !is_word_char(c)).unwrap_or(word.len());
+            let (word, tail) = word.split_at(end);
+            if reads_as_id(word) {
+                expanded.push_str(word);
+            } else {
+                match self.resolve(word)? {
+                    Id::Standard(raw) => expanded.push_str(&format!("{raw:03X}")),
+                    Id::Extended(raw) => expanded.push_str(&format!("{raw:08X}")),
+                }
+            }
+            rest = tail;
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    /// Up to three names within a few edits of `name`, ignoring case, closest first.
+    fn close_matches(&self, name: &str) -> Vec<&str> {
+        let wanted = name.to_ascii_lowercase();
+        let limit = (wanted.len() / 3).max(2);
+        let mut close: Vec<(usize, &str)> = self
+            .ids
+            .keys()
+            .filter_map(|known| {
+                let lower = known.to_ascii_lowercase();
+                let distance = if lower.contains(&wanted) || wanted.contains(&lower) { 1 } else { edit_distance(&wanted, &lower) };
+                (distance <= limit).then_some((distance, known.as_str()))
+            })
+            .collect();
+        close.sort();
+        close.into_iter().take(3).map(|(_, known)| known).collect()
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Hex digits with an optional `0x` prefix and `x` suffix.
+fn reads_as_id(word: &str) -> bool {
+    let word = word.strip_suffix(['x', 'X']).unwrap_or(word);
+    let digits = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")).unwrap_or(word);
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    let valid_start = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+    if !valid_start || !name.chars().all(is_word_char) {
+        return Err(format!("invalid name '{name}': use letters, digits and '_', starting with a letter"));
+    }
+    if reads_as_id(name) {
+        return Err(format!("invalid name '{name}': it reads as a hex ID"));
+    }
+    Ok(())
+}
+
+/// Levenshtein distance over bytes; names are ASCII.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.as_bytes();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, &ca) in a.as_bytes().iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + usize::from(ca != cb)).min(above + 1).min(row[j] + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
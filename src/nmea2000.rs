@@ -0,0 +1,441 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::frame::Frame;
+use crate::j1939_tp::J1939Message;
+
+/// How long a partly received fast packet is kept waiting for its next frame.
+pub const FAST_PACKET_TIMEOUT: Duration = Duration::from_millis(750);
+/// Largest fast-packet payload: 6 bytes in the first frame and 7 in each of 31 more.
+pub const FAST_PACKET_MAX_LEN: usize = 223;
+
+/// NMEA 2000 angles are 0.0001 rad; decoded values are in degrees.
+const ANGLE: f64 = 0.0001 * 180.0 / PI;
+
+/// Standard PGNs sent as fast packets, besides the proprietary ranges 126720 and
+/// 130816-131071.
+const FAST_PACKET_PGNS: &[u32] = &[
+    126208, 126464, 126996, 126998, 127233, 127237, 127489, 127494, 127495, 127496, 127497, 127498,
+    127503, 127504, 127506, 127507, 127509, 127510, 127511, 127512, 127513, 127514, 128275, 128520,
+    129029, 129038, 129039, 129040, 129041, 129044, 129045, 129284, 129285, 129301, 129302, 129538,
+    129540, 129541, 129542, 129545, 129547, 129549, 129551, 129556, 129792, 129793, 129794, 129795,
+    129796, 129797, 129798, 129799, 129800, 129801, 129802, 129803, 129804, 129805, 129806, 129807,
+    129808, 129809, 129810, 130052, 130053, 130054, 130060, 130061, 130064, 130065, 130066, 130067,
+    130068, 130069, 130070, 130071, 130072, 130073, 130074, 130320, 130321, 130322, 130323, 130324,
+    130567, 130577, 130578,
+];
+
+/// Whether messages of `pgn` span several frames with the fast-packet framing.
+pub fn is_fast_packet(pgn: u32) -> bool {
+    pgn == 126720 || (130816..=131071).contains(&pgn) || FAST_PACKET_PGNS.contains(&pgn)
+}
+
+/// One field of an NMEA 2000 PGN: little-endian, `bits` wide, starting at bit `start` of
+/// the payload. The all-ones value (or the largest positive one for signed fields) means
+/// not available.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct N2kField {
+    pub name: &'static str,
+    pub start: u16,
+    pub bits: u8,
+    pub signed: bool,
+    pub scale: f64,
+    pub unit: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct N2kPgnDef {
+    pub pgn: u32,
+    pub name: &'static str,
+    pub fields: &'static [N2kField],
+}
+
+const fn field(name: &'static str, start: u16, bits: u8, signed: bool, scale: f64, unit: &'static str) -> N2kField {
+    N2kField { name, start, bits, signed, scale, unit }
+}
+
+/// PGNs [`decode_n2k`] knows.
+pub const N2K_PGNS: &[N2kPgnDef] = &[
+    N2kPgnDef {
+        pgn: 127250,
+        name: "Vessel Heading",
+        fields: &[
+            field("SID", 0, 8, false, 1.0, ""),
+            field("Heading", 8, 16, false, ANGLE, "°"),
+            field("Deviation", 24, 16, true, ANGLE, "°"),
+            field("Variation", 40, 16, true, ANGLE, "°"),
+            field("Reference", 56, 2, false, 1.0, ""),
+        ],
+    },
+    N2kPgnDef {
+        pgn: 128267,
+        name: "Water Depth",
+        fields: &[
+            field("SID", 0, 8, false, 1.0, ""),
+            field("Depth", 8, 32, false, 0.01, "m"),
+            field("Offset", 40, 16, true, 0.001, "m"),
+            field("Range", 56, 8, false, 10.0, "m"),
+        ],
+    },
+    N2kPgnDef {
+        pgn: 129025,
+        name: "Position, Rapid Update",
+        fields: &[field("Latitude", 0, 32, true, 1e-7, "°"), field("Longitude", 32, 32, true, 1e-7, "°")],
+    },
+    N2kPgnDef {
+        pgn: 129026,
+        name: "COG & SOG, Rapid Update",
+        fields: &[
+            field("SID", 0, 8, false, 1.0, ""),
+            field("COG Reference", 8, 2, false, 1.0, ""),
+            field("COG", 16, 16, false, ANGLE, "°"),
+            field("SOG", 32, 16, false, 0.01, "m/s"),
+        ],
+    },
+    N2kPgnDef {
+        pgn: 130306,
+        name: "Wind Data",
+        fields: &[
+            field("SID", 0, 8, false, 1.0, ""),
+            field("Wind Speed", 8, 16, false, 0.01, "m/s"),
+            field("Wind Angle", 24, 16, false, ANGLE, "°"),
+            field("Reference", 40, 3, false, 1.0, ""),
+        ],
+    },
+];
+
+pub fn n2k_pgn_def(pgn: u32) -> Option<&'static N2kPgnDef> {
+    N2K_PGNS.iter().find(|def| def.pgn == pgn)
+}
+
+/// A decoded field; `None` when the sender marked it not available.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct N2kValue {
+    pub field: &'static N2kField,
+    pub value: Option<f64>,
+}
+
+impl fmt::Display for N2kValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=", self.field.name)?;
+        match self.value {
+            Some(value) if self.field.unit.is_empty() => write!(f, "{value}"),
+            // Four decimals keep 0.0001 rad angles and 1e-7 degree positions readable.
+            Some(value) => write!(f, "{} {}", (value * 1e4).round() / 1e4, self.field.unit),
+            None => write!(f, "n/a"),
+        }
+    }
+}
+
+/// Decodes the fields of a known PGN that fit in `data`.
+pub fn decode_n2k(def: &'static N2kPgnDef, data: &[u8]) -> Vec<N2kValue> {
+    def.fields
+        .iter()
+        .filter_map(|field| {
+            let (start, bits) = (usize::from(field.start), usize::from(field.bits));
+            if start + bits > data.len() * 8 || bits == 0 || bits > 64 {
+                return None;
+            }
+            let mut raw = 0u64;
+            for i in 0..bits {
+                let bit = start + i;
+                if data[bit / 8] >> (bit % 8) & 1 != 0 {
+                    raw |= 1 << i;
+                }
+            }
+            let all_ones = if bits == 64 { u64::MAX } else { (1 << bits) - 1 };
+            let value = if field.signed {
+                let max = all_ones >> 1;
+                let signed = if raw > max { raw as i64 - (all_ones as i64) - 1 } else { raw as i64 };
+                (raw != max).then_some(signed as f64 * field.scale)
+            } else {
+                (raw != all_ones).then_some(raw as f64 * field.scale)
+            };
+            Some(N2kValue { field, value })
+        })
+        .collect()
+}
+
+struct Partial {
+    sequence: u8,
+    next_frame: u8,
+    len: usize,
+    data: Vec<u8>,
+    priority: u8,
+    destination: Option<u8>,
+    deadline: Instant,
+}
+
+/// Turns NMEA 2000 frames into messages, reassembling fast packets. Sequences from different
+/// sources, or of different PGNs from one source, are assembled side by side; a sequence that
+/// skips a frame or restarts is dropped, as are ones idle for [`FAST_PACKET_TIMEOUT`].
+#[derive(Default)]
+pub struct FastPacketAssembler {
+    partial: HashMap<(u8, u32), Partial>,
+    dropped: u64,
+}
+
+impl FastPacketAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the message `frame` completes: the frame itself for single-frame PGNs, or the
+    /// whole payload (marked reassembled) with the last frame of a fast packet.
+    pub fn push(&mut self, frame: &Frame, now: Instant) -> Option<J1939Message> {
+        self.expire(now);
+        let message = J1939Message::from_frame(frame)?;
+        if !is_fast_packet(message.pgn) {
+            return Some(message);
+        }
+        let (&counter, payload) = message.data.split_first()?;
+        let (sequence, index) = (counter >> 5, counter & 0x1F);
+        let key = (message.source, message.pgn);
+
+        if index == 0 {
+            let (&len, first) = payload.split_first()?;
+            if self.partial.remove(&key).is_some() {
+                self.dropped += 1;
+            }
+            let len = usize::from(len).min(FAST_PACKET_MAX_LEN);
+            let mut data = first.to_vec();
+            if data.len() >= len {
+                data.truncate(len);
+                return Some(J1939Message { data, reassembled: true, ..message });
+            }
+            self.partial.insert(key, Partial {
+                sequence,
+                next_frame: 1,
+                len,
+                data,
+                priority: message.priority,
+                destination: message.destination,
+                deadline: now + FAST_PACKET_TIMEOUT,
+            });
+            return None;
+        }
+
+        let partial = self.partial.get_mut(&key)?;
+        if partial.sequence != sequence || partial.next_frame != index {
+            self.partial.remove(&key);
+            self.dropped += 1;
+            return None;
+        }
+        partial.data.extend_from_slice(payload);
+        partial.next_frame += 1;
+        partial.deadline = now + FAST_PACKET_TIMEOUT;
+        if partial.data.len() < partial.len {
+            return None;
+        }
+        let mut partial = self.partial.remove(&key).expect("looked up above");
+        partial.data.truncate(partial.len);
+        Some(J1939Message {
+            priority: partial.priority,
+            pgn: message.pgn,
+            source: message.source,
+            destination: partial.destination,
+            data: partial.data,
+            reassembled: true,
+        })
+    }
+
+    /// Drops partial messages whose next frame is overdue.
+    pub fn expire(&mut self, now: Instant) {
+        let before = self.partial.len();
+        self.partial.retain(|_, partial| partial.deadline > now);
+        self.dropped += (before - self.partial.len()) as u64;
+    }
+
+    /// Fast packets abandoned so far: timed out, missing a frame or restarted.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// `PGN 127250 Vessel Heading SA 0x23: Heading=123.4 °, ...`, or just the PGN and source for
+/// PGNs without a definition.
+pub fn format_n2k(message: &J1939Message) -> String {
+    match n2k_pgn_def(message.pgn) {
+        Some(def) => {
+            let values: Vec<String> = decode_n2k(def, &message.data).iter().map(ToString::to_string).collect();
+            format!("PGN {} {} SA 0x{:02X}: {}", message.pgn, def.name, message.source, values.join(", "))
+        }
+        None => format!("PGN {} SA 0x{:02X}", message.pgn, message.source),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::j1939::J1939Id;
+
+    fn frame(priority: u8, pgn: u32, source: u8, data: &[u8]) -> Frame {
+        Frame::new(J1939Id { priority, pgn, destination: None, source }.to_id(), data).unwrap()
+    }
+
+    /// A line of canboat's analyzer input, `time,priority,pgn,source,destination,length,bytes`.
+    fn logged(line: &str) -> Frame {
+        let fields: Vec<&str> = line.split(',').collect();
+        let data: Vec<u8> = fields[6..].iter().map(|byte| u8::from_str_radix(byte, 16).unwrap()).collect();
+        assert_eq!(data.len(), fields[5].parse::<usize>().unwrap());
+        frame(fields[1].parse().unwrap(), fields[2].parse().unwrap(), fields[3].parse().unwrap(), &data)
+    }
+
+    fn decoded(line: &str) -> String {
+        let message = FastPacketAssembler::new().push(&logged(line), Instant::now()).expect("a single frame");
+        format_n2k(&message)
+    }
+
+    #[test]
+    fn decodes_example_frames() {
+        assert_eq!(
+            decoded("2011-11-24-22:42:04.388,2,127250,7,255,8,ff,7a,4f,ff,7f,09,04,fd"),
+            "PGN 127250 Vessel Heading SA 0x07: SID=n/a, Heading=116.574 °, Deviation=n/a, Variation=5.9187 °, Reference=1"
+        );
+        assert_eq!(
+            decoded("2011-11-24-22:42:04.390,3,128267,1,255,8,0b,f5,01,00,00,00,00,ff"),
+            "PGN 128267 Water Depth SA 0x01: SID=11, Depth=5.01 m, Offset=0 m, Range=n/a"
+        );
+        assert_eq!(
+            decoded("2011-11-24-22:42:04.391,2,129026,1,255,8,00,fc,b8,e8,0f,00,ff,ff"),
+            "PGN 129026 COG & SOG, Rapid Update SA 0x01: SID=0, COG Reference=0, COG=341.3453 °, SOG=0.15 m/s"
+        );
+        assert_eq!(
+            decoded("2011-11-24-22:42:04.392,2,129025,1,255,8,c0,d8,fb,1c,00,bf,b0,01"),
+            "PGN 129025 Position, Rapid Update SA 0x01: Latitude=48.6267 °, Longitude=2.836 °"
+        );
+        // Negative angles, and a PGN without a definition labeled by number only.
+        assert_eq!(
+            decoded("2011-11-24-22:42:04.393,2,127250,7,255,8,01,00,00,9c,ff,f6,fd,fc"),
+            "PGN 127250 Vessel Heading SA 0x07: SID=1, Heading=0 °, Deviation=-0.573 °, Variation=-2.9908 °, Reference=0"
+        );
+        assert_eq!(decoded("2011-11-24-22:42:04.394,2,127245,204,255,8,ff,f8,ff,7f,7a,00,ff,ff"), "PGN 127245 SA 0xCC");
+    }
+
+    #[test]
+    fn short_payloads_decode_what_fits() {
+        let def = n2k_pgn_def(128267).unwrap();
+        let values = decode_n2k(def, &[0x0B, 0xF5, 0x01, 0x00, 0x00]);
+        assert_eq!(values.iter().map(ToString::to_string).collect::<Vec<_>>(), ["SID=11", "Depth=5.01 m"]);
+    }
+
+    /// GNSS Position Data (129029): 43 bytes in 7 frames of sequence 2.
+    fn gnss_payload() -> Vec<u8> {
+        (0..43).map(|n| n as u8 * 3).collect()
+    }
+
+    fn fast_frames(source: u8, pgn: u32, sequence: u8, payload: &[u8]) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        let mut first = vec![sequence << 5, payload.len() as u8];
+        first.extend_from_slice(&payload[..6]);
+        frames.push(frame(3, pgn, source, &first));
+        for (index, chunk) in payload[6..].chunks(7).enumerate() {
+            let mut data = vec![sequence << 5 | (index as u8 + 1)];
+            data.extend_from_slice(chunk);
+            data.resize(8, 0xFF);
+            frames.push(frame(3, pgn, source, &data));
+        }
+        frames
+    }
+
+    fn feed(assembler: &mut FastPacketAssembler, frames: &[Frame], now: Instant) -> Vec<J1939Message> {
+        frames.iter().filter_map(|frame| assembler.push(frame, now)).collect()
+    }
+
+    #[test]
+    fn reassembles_a_fast_packet() {
+        let frames = fast_frames(0x05, 129029, 2, &gnss_payload());
+        assert_eq!(frames.len(), 7);
+        assert_eq!(frames[0].data()[..2], [0x40, 43]);
+        assert_eq!(frames[6].data()[0], 0x46);
+
+        let mut assembler = FastPacketAssembler::new();
+        let messages = feed(&mut assembler, &frames, Instant::now());
+        assert_eq!(messages.len(), 1);
+        assert_eq!((messages[0].pgn, messages[0].source, messages[0].reassembled), (129029, 0x05, true));
+        assert_eq!(messages[0].data, gnss_payload(), "the last frame's padding is cut off");
+        assert_eq!(format_n2k(&messages[0]), "PGN 129029 SA 0x05");
+        assert_eq!(assembler.dropped(), 0);
+
+        // Single-frame PGNs pass straight through.
+        let heading = logged("2011-11-24-22:42:04.388,2,127250,7,255,8,ff,7a,4f,ff,7f,09,04,fd");
+        let message = assembler.push(&heading, Instant::now()).unwrap();
+        assert_eq!((message.data.as_slice(), message.reassembled), (heading.data(), false));
+    }
+
+    #[test]
+    fn interleaved_sequences_are_assembled_side_by_side() {
+        let first = fast_frames(0x05, 129029, 2, &gnss_payload());
+        let other_source: Vec<u8> = (100..143).collect();
+        let second = fast_frames(0x10, 129029, 5, &other_source);
+        let other_pgn: Vec<u8> = (0..20).collect();
+        let third = fast_frames(0x05, 129540, 1, &other_pgn);
+
+        let mut frames = Vec::new();
+        for n in 0..7 {
+            frames.extend(first.get(n));
+            frames.extend(second[..6].get(n));
+            frames.extend(third.get(n));
+        }
+        // The second source's last frame comes after the others have completed.
+        frames.push(second[6]);
+        let mut assembler = FastPacketAssembler::new();
+        let messages: Vec<_> = feed(&mut assembler, &frames, Instant::now()).into_iter().map(|message| (message.source, message.pgn, message.data)).collect();
+        assert_eq!(messages, [(0x05, 129540, other_pgn), (0x05, 129029, gnss_payload()), (0x10, 129029, other_source)]);
+        assert_eq!(assembler.dropped(), 0);
+    }
+
+    #[test]
+    fn stale_partial_buffers_are_dropped() {
+        let frames = fast_frames(0x05, 129029, 2, &gnss_payload());
+        let start = Instant::now();
+        let mut assembler = FastPacketAssembler::new();
+        assert!(feed(&mut assembler, &frames[..3], start).is_empty());
+        // Just in time for the next frame, then too late.
+        assert!(assembler.push(&frames[3], start + FAST_PACKET_TIMEOUT - Duration::from_millis(1)).is_none());
+        let late = start + FAST_PACKET_TIMEOUT * 2;
+        assert!(feed(&mut assembler, &frames[4..], late).is_empty(), "the rest belongs to a dropped buffer");
+        assert_eq!(assembler.dropped(), 1);
+
+        // A later, complete sequence is unaffected.
+        assert_eq!(feed(&mut assembler, &frames, late).len(), 1);
+        assert_eq!(assembler.dropped(), 1);
+        assembler.push(&frames[0], late);
+        assembler.expire(late + FAST_PACKET_TIMEOUT);
+        assert_eq!(assembler.dropped(), 2);
+    }
+
+    #[test]
+    fn missing_frames_and_restarts_drop_the_sequence() {
+        let frames = fast_frames(0x05, 129029, 2, &gnss_payload());
+        let now = Instant::now();
+        let mut assembler = FastPacketAssembler::new();
+        let skipped: Vec<Frame> = frames.iter().enumerate().filter(|&(n, _)| n != 3).map(|(_, frame)| *frame).collect();
+        assert!(feed(&mut assembler, &skipped, now).is_empty());
+        assert_eq!(assembler.dropped(), 1);
+
+        // A new first frame abandons the sequence in progress and starts over.
+        let restarted = fast_frames(0x05, 129029, 3, &gnss_payload());
+        let frames: Vec<Frame> = frames[..4].iter().chain(&restarted).copied().collect();
+        assert_eq!(feed(&mut assembler, &frames, now).len(), 1);
+        assert_eq!(assembler.dropped(), 2);
+
+        // A frame of another sequence number in the middle.
+        let mut mixed = fast_frames(0x05, 129029, 2, &gnss_payload());
+        mixed[2] = restarted[2];
+        assert!(feed(&mut assembler, &mixed, now).is_empty());
+        assert_eq!(assembler.dropped(), 3);
+    }
+
+    #[test]
+    fn fast_packet_pgns() {
+        for pgn in [129029, 126996, 126720, 130816, 131071] {
+            assert!(is_fast_packet(pgn), "{pgn}");
+        }
+        for pgn in [127250, 128267, 129025, 129026, 130815, 59904] {
+            assert!(!is_fast_packet(pgn), "{pgn}");
+        }
+    }
+}
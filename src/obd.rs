@@ -0,0 +1,219 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+use crate::device::Channel;
+use crate::error::CanError;
+use crate::fanout::Subscription;
+use crate::frame::Frame;
+use crate::id::Id;
+
+/// Functional (broadcast) request ID every emissions-relevant ECU listens on.
+pub const OBD_FUNCTIONAL_ID: Id = Id::Standard(0x7DF);
+/// Physical response IDs, one per ECU: 0x7E8 is usually the engine controller.
+pub const OBD_RESPONSE_IDS: std::ops::RangeInclusive<u16> = 0x7E8..=0x7EF;
+
+/// Service 01: show current data.
+const CURRENT_DATA: u8 = 0x01;
+const POSITIVE_OFFSET: u8 = 0x40;
+const SUBSCRIPTION_CAPACITY: usize = 256;
+
+/// A mode 01 PID with its SAE J1979 scaling.
+#[derive(Debug, Clone, Copy)]
+pub struct PidInfo {
+    pub pid: u8,
+    /// snake_case name, e.g. for JSON output.
+    pub name: &'static str,
+    pub label: &'static str,
+    pub unit: &'static str,
+    /// Data bytes the formula reads.
+    len: usize,
+    formula: fn(&[u8]) -> f64,
+}
+
+impl PidInfo {
+    /// Scaled value of a response's data bytes (A, B, ...), or `None` if there are too few.
+    pub fn decode(&self, data: &[u8]) -> Option<f64> {
+        (data.len() >= self.len).then(|| (self.formula)(data))
+    }
+}
+
+fn word(d: &[u8]) -> f64 {
+    f64::from(u16::from_be_bytes([d[0], d[1]]))
+}
+
+fn percent(d: &[u8]) -> f64 {
+    f64::from(d[0]) * 100.0 / 255.0
+}
+
+fn temperature(d: &[u8]) -> f64 {
+    f64::from(d[0]) - 40.0
+}
+
+/// PIDs [`pid_info`] knows how to decode.
+pub const PIDS: [PidInfo; 13] = [
+    PidInfo { pid: 0x04, name: "engine_load", label: "Calculated engine load", unit: "%", len: 1, formula: percent },
+    PidInfo { pid: 0x05, name: "coolant_temp", label: "Coolant temperature", unit: "°C", len: 1, formula: temperature },
+    PidInfo { pid: 0x0B, name: "intake_map", label: "Intake manifold pressure", unit: "kPa", len: 1, formula: |d| f64::from(d[0]) },
+    PidInfo { pid: 0x0C, name: "engine_rpm", label: "Engine speed", unit: "rpm", len: 2, formula: |d| word(d) / 4.0 },
+    PidInfo { pid: 0x0D, name: "vehicle_speed", label: "Vehicle speed", unit: "km/h", len: 1, formula: |d| f64::from(d[0]) },
+    PidInfo { pid: 0x0F, name: "intake_temp", label: "Intake air temperature", unit: "°C", len: 1, formula: temperature },
+    PidInfo { pid: 0x10, name: "maf", label: "Mass air flow", unit: "g/s", len: 2, formula: |d| word(d) / 100.0 },
+    PidInfo { pid: 0x11, name: "throttle", label: "Throttle position", unit: "%", len: 1, formula: percent },
+    PidInfo { pid: 0x1F, name: "run_time", label: "Run time since start", unit: "s", len: 2, formula: word },
+    PidInfo { pid: 0x2F, name: "fuel_level", label: "Fuel level", unit: "%", len: 1, formula: percent },
+    PidInfo { pid: 0x42, name: "module_voltage", label: "Control module voltage", unit: "V", len: 2, formula: |d| word(d) / 1000.0 },
+    PidInfo { pid: 0x46, name: "ambient_temp", label: "Ambient air temperature", unit: "°C", len: 1, formula: temperature },
+    PidInfo { pid: 0x5C, name: "oil_temp", label: "Engine oil temperature", unit: "°C", len: 1, formula: temperature },
+];
+
+pub fn pid_info(pid: u8) -> Option<&'static PidInfo> {
+    PIDS.iter().find(|info| info.pid == pid)
+}
+
+/// Mode 01 PIDs each ECU reported through the 0x00, 0x20, ... bitmasks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SupportedPids(BTreeMap<Id, BTreeSet<u8>>);
+
+impl SupportedPids {
+    /// Response IDs of the ECUs that answered.
+    pub fn ecus(&self) -> impl Iterator<Item = Id> + '_ {
+        self.0.keys().copied()
+    }
+
+    pub fn pids(&self, ecu: Id) -> impl Iterator<Item = u8> + '_ {
+        self.0.get(&ecu).into_iter().flatten().copied()
+    }
+
+    pub fn supports(&self, ecu: Id, pid: u8) -> bool {
+        self.0.get(&ecu).is_some_and(|pids| pids.contains(&pid))
+    }
+
+    /// ECUs that support `pid`.
+    pub fn ecus_with(&self, pid: u8) -> BTreeSet<Id> {
+        self.ecus().filter(|&ecu| self.supports(ecu, pid)).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// One ECU's answer to a mode 01 request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObdReading {
+    pub ecu: Id,
+    pub pid: u8,
+    /// Data bytes after the echoed PID.
+    pub data: Vec<u8>,
+    /// When the response arrived.
+    pub at: Instant,
+}
+
+impl ObdReading {
+    /// Decoded value, for PIDs in [`PIDS`].
+    pub fn value(&self) -> Option<f64> {
+        pid_info(self.pid)?.decode(&self.data)
+    }
+}
+
+/// OBD-II (SAE J1979 over ISO 15765-4) client using 11-bit functional addressing: requests go
+/// to [`OBD_FUNCTIONAL_ID`] and every ECU answers on its own ID in [`OBD_RESPONSE_IDS`].
+/// Only single-PID mode 01 requests are made, so every answer fits in a single frame.
+pub struct ObdClient {
+    channel: Channel,
+    frames: Subscription,
+    /// How long to collect answers to a request from ECUs that haven't answered yet. J1979
+    /// gives ECUs 50 ms.
+    pub timeout: Duration,
+}
+
+impl ObdClient {
+    pub fn new(channel: &Channel) -> Self {
+        Self {
+            channel: channel.clone(),
+            frames: channel.subscribe(SUBSCRIPTION_CAPACITY),
+            timeout: Duration::from_millis(100),
+        }
+    }
+
+    /// Walks the supported-PID bitmasks (0x00, then 0x20, ... while the last bit of the previous
+    /// range is set) for every ECU that answers.
+    pub fn supported_pids(&self) -> Result<SupportedPids, CanError> {
+        let mut supported = SupportedPids::default();
+        let mut asking = None;
+        for base in (0x00..=0xE0u8).step_by(0x20) {
+            for reading in self.collect(base, asking.as_ref())? {
+                let Some(mask) = reading.data.get(..4) else {
+                    continue;
+                };
+                let mask = u32::from_be_bytes([mask[0], mask[1], mask[2], mask[3]]);
+                let pids = supported.0.entry(reading.ecu).or_default();
+                pids.extend((0..32u8).filter(|bit| mask & (1 << (31 - bit)) != 0).map(|bit| base + bit + 1));
+            }
+            let next = supported.ecus_with(base.wrapping_add(0x20));
+            if base == 0xE0 || next.is_empty() {
+                break;
+            }
+            asking = Some(next);
+        }
+        Ok(supported)
+    }
+
+    /// Reads `pid` from every ECU in `supported` that has it, returning as soon as they have
+    /// all answered.
+    pub fn read(&self, pid: u8, supported: &SupportedPids) -> Result<Vec<ObdReading>, CanError> {
+        let ecus = supported.ecus_with(pid);
+        if ecus.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.collect(pid, Some(&ecus))
+    }
+
+    /// Reads `pid` from whichever ECUs answer within the timeout.
+    pub fn current_data(&self, pid: u8) -> Result<Vec<ObdReading>, CanError> {
+        self.collect(pid, None)
+    }
+
+    /// Sends a mode 01 request for `pid` and gathers one answer per ECU, until `expected` have
+    /// all answered or the timeout passes.
+    fn collect(&self, pid: u8, expected: Option<&BTreeSet<Id>>) -> Result<Vec<ObdReading>, CanError> {
+        while self.frames.try_recv().is_ok() {}
+        let request = Frame::new(OBD_FUNCTIONAL_ID, &[0x02, CURRENT_DATA, pid, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC])
+            .expect("OBD request fits in 8 bytes");
+        self.channel.transmit(&request)?;
+
+        let deadline = Instant::now() + self.timeout;
+        let mut readings: Vec<ObdReading> = Vec::new();
+        while expected.is_none_or(|ecus| ecus.iter().any(|ecu| readings.iter().all(|r| r.ecu != *ecu))) {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let frame = match self.frames.recv_timeout(left) {
+                Ok(frame) => frame,
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+            };
+            let Id::Standard(raw) = frame.id() else {
+                continue;
+            };
+            if !OBD_RESPONSE_IDS.contains(&raw) || frame.is_remote() {
+                continue;
+            }
+            // A single frame: PCI 0x0N, then 0x41, the PID and its data.
+            let data = frame.data();
+            let Some((&pci, rest)) = data.split_first() else {
+                continue;
+            };
+            let len = usize::from(pci);
+            if pci >> 4 != 0 || len < 2 || len > rest.len() {
+                continue;
+            }
+            match &rest[..len] {
+                [service, answered, value @ ..] if *service == CURRENT_DATA + POSITIVE_OFFSET && *answered == pid => {
+                    readings.retain(|r| r.ecu != frame.id());
+                    readings.push(ObdReading { ecu: frame.id(), pid, data: value.to_vec(), at: Instant::now() });
+                }
+                _ => {}
+            }
+        }
+        Ok(readings)
+    }
+}
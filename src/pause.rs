@@ -0,0 +1,75 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use rustcanbus::Frame;
+
+/// Frames kept while the display is paused; older ones are dropped first.
+const MAX_BUFFERED: usize = 10_000;
+
+#[derive(Default)]
+struct State {
+    paused: bool,
+    buffered: VecDeque<(u32, Frame)>,
+    dropped: u64,
+}
+
+/// Pause state shared between the keyboard thread and the receive display.
+#[derive(Default)]
+pub struct Pause {
+    state: Mutex<State>,
+}
+
+/// Result of toggling the pause state.
+pub struct Resumed {
+    /// Frames buffered during the pause, to be shown now.
+    pub frames: Vec<(u32, Frame)>,
+    /// Frames discarded because the buffer was full.
+    pub dropped: u64,
+}
+
+impl Pause {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    /// Pauses, or resumes and hands back everything buffered meanwhile.
+    pub fn toggle(&self) -> Option<Resumed> {
+        let mut state = self.state.lock().unwrap();
+        state.paused = !state.paused;
+        if state.paused {
+            return None;
+        }
+        Some(Resumed {
+            frames: state.buffered.drain(..).collect(),
+            dropped: std::mem::take(&mut state.dropped),
+        })
+    }
+
+    /// Buffers `frame` if paused; returns `false` if it should be displayed right away.
+    pub fn hold(&self, channel: u32, frame: &Frame) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !state.paused {
+            return false;
+        }
+        if state.buffered.len() == MAX_BUFFERED {
+            state.buffered.pop_front();
+            state.dropped += 1;
+        }
+        state.buffered.push_back((channel, *frame));
+        true
+    }
+
+    /// Takes the oldest buffered frame while paused.
+    pub fn step(&self) -> Option<(u32, Frame)> {
+        let mut state = self.state.lock().unwrap();
+        if state.paused {
+            state.buffered.pop_front()
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    io::{self, Write},
+    time::UNIX_EPOCH,
+};
+
+use crate::device::CHANNEL_COUNT;
+use crate::frame::Frame;
+use crate::sink::{Direction, FrameSink};
+use crate::timestamp::host_time;
+
+/// `LINKTYPE_CAN_SOCKETCAN`
+pub const LINKTYPE_CAN_SOCKETCAN: u16 = 227;
+
+const BLOCK_SHB: u32 = 0x0A0D_0D0A;
+const BLOCK_IDB: u32 = 0x0000_0001;
+const BLOCK_EPB: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPT_END: u16 = 0;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_TSRESOL: u16 = 9;
+
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+
+/// Encodes `frame` as a 16-byte SocketCAN `struct can_frame`: big-endian ID word with EFF/RTR
+/// flag bits, DLC, three padding bytes, then the 8 data bytes zero-padded.
+pub fn socketcan_bytes(frame: &Frame) -> [u8; 16] {
+    let mut id = frame.id().raw();
+    if frame.is_extended() {
+        id |= CAN_EFF_FLAG;
+    }
+    if frame.is_remote() {
+        id |= CAN_RTR_FLAG;
+    }
+    let mut out = [0u8; 16];
+    out[..4].copy_from_slice(&id.to_be_bytes());
+    out[4] = frame.dlc();
+    out[8..8 + frame.data().len()].copy_from_slice(frame.data());
+    out
+}
+
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    body.resize(body.len().next_multiple_of(4), 0);
+}
+
+/// pcapng writer with one SocketCAN interface per CANalyst channel (`can0`, `can1`), so
+/// Wireshark lists the two buses separately. Timestamps are in microseconds. Frames for any
+/// other channel are refused with [`io::ErrorKind::InvalidInput`].
+pub struct PcapngWriter<W: Write> {
+    out: W,
+    header_written: bool,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            header_written: false,
+        }
+    }
+
+    fn write_block(&mut self, block_type: u32, body: &[u8]) -> io::Result<()> {
+        let total = (12 + body.len()) as u32;
+        self.out.write_all(&block_type.to_le_bytes())?;
+        self.out.write_all(&total.to_le_bytes())?;
+        self.out.write_all(body)?;
+        self.out.write_all(&total.to_le_bytes())
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let mut shb = Vec::new();
+        shb.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        shb.extend_from_slice(&1u16.to_le_bytes());
+        shb.extend_from_slice(&0u16.to_le_bytes());
+        shb.extend_from_slice(&u64::MAX.to_le_bytes());
+        self.write_block(BLOCK_SHB, &shb)?;
+
+        for channel in 0..CHANNEL_COUNT {
+            let mut idb = Vec::new();
+            idb.extend_from_slice(&LINKTYPE_CAN_SOCKETCAN.to_le_bytes());
+            idb.extend_from_slice(&0u16.to_le_bytes());
+            idb.extend_from_slice(&0u32.to_le_bytes());
+            push_option(&mut idb, OPT_IF_NAME, format!("can{channel}").as_bytes());
+            push_option(&mut idb, OPT_IF_TSRESOL, &[6]);
+            push_option(&mut idb, OPT_END, &[]);
+            self.write_block(BLOCK_IDB, &idb)?;
+        }
+        self.header_written = true;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> FrameSink for PcapngWriter<W> {
+    fn write_frame(&mut self, channel: u32, frame: &Frame, _direction: Direction) -> io::Result<()> {
+        if channel >= CHANNEL_COUNT {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("channel {channel} has no interface, the capture describes {CHANNEL_COUNT}")));
+        }
+        if !self.header_written {
+            self.write_header()?;
+        }
+        let time = host_time(frame);
+        let micros = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64;
+        let packet = socketcan_bytes(frame);
+
+        let mut epb = Vec::with_capacity(20 + packet.len());
+        epb.extend_from_slice(&channel.to_le_bytes());
+        epb.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+        epb.extend_from_slice(&(micros as u32).to_le_bytes());
+        epb.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        epb.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        epb.extend_from_slice(&packet);
+        self.write_block(BLOCK_EPB, &epb)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::id::Id;
+
+    fn received(frame: Frame, micros: u64) -> Frame {
+        Frame { host_time: Some((UNIX_EPOCH + Duration::from_micros(micros), Instant::now())), ..frame }
+    }
+
+    fn write(frames: &[(u32, Frame)]) -> Vec<u8> {
+        let mut writer = PcapngWriter::new(Vec::new());
+        for (channel, frame) in frames {
+            writer.write_frame(*channel, frame, Direction::Rx).unwrap();
+        }
+        writer.finish().unwrap();
+        writer.out
+    }
+
+    /// Splits a little-endian pcapng file into (block type, body), checking both length fields.
+    fn blocks(mut file: &[u8]) -> Vec<(u32, &[u8])> {
+        let word = |bytes: &[u8]| u32::from_le_bytes(bytes[..4].try_into().unwrap());
+        let mut blocks = Vec::new();
+        while !file.is_empty() {
+            let total = word(&file[4..]) as usize;
+            assert!(total.is_multiple_of(4) && total >= 12 && total <= file.len(), "block length {total}");
+            assert_eq!(word(&file[total - 4..]), total as u32, "trailing block length");
+            blocks.push((word(file), &file[8..total - 4]));
+            file = &file[total..];
+        }
+        blocks
+    }
+
+    fn interface_block(name: &[u8; 4]) -> Vec<u8> {
+        let mut block = vec![0x01, 0, 0, 0, 0x28, 0, 0, 0, 0xE3, 0, 0, 0, 0, 0, 0, 0];
+        block.extend_from_slice(&[0x02, 0, 0x04, 0]);
+        block.extend_from_slice(name);
+        block.extend_from_slice(&[0x09, 0, 0x01, 0, 0x06, 0, 0, 0, 0, 0, 0, 0, 0x28, 0, 0, 0]);
+        block
+    }
+
+    #[test]
+    fn writes_the_blocks_byte_for_byte() {
+        let frame = received(Frame::new(Id::Extended(0x18FF_50E5), &[1, 2, 3]).unwrap(), 1_791_972_000_123_456);
+        let mut expected = vec![
+            0x0A, 0x0D, 0x0D, 0x0A, 0x1C, 0, 0, 0, 0x4D, 0x3C, 0x2B, 0x1A, 0x01, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x1C, 0, 0, 0,
+        ];
+        expected.extend(interface_block(b"can0"));
+        expected.extend(interface_block(b"can1"));
+        expected.extend_from_slice(&[0x06, 0, 0, 0, 0x30, 0, 0, 0, 0x01, 0, 0, 0, 0xC9, 0x5D, 0x06, 0, 0x40, 0xCA, 0x83, 0xFE, 0x10, 0, 0, 0, 0x10, 0, 0, 0]);
+        expected.extend_from_slice(&[0x98, 0xFF, 0x50, 0xE5, 0x03, 0, 0, 0, 0x01, 0x02, 0x03, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[0x30, 0, 0, 0]);
+        assert_eq!(write(&[(1, frame)]), expected);
+    }
+
+    #[test]
+    fn socketcan_frames_carry_the_flags_in_the_id_word() {
+        assert_eq!(socketcan_bytes(&Frame::new(Id::Standard(0x123), &[0xAA; 8]).unwrap()), [0, 0, 0x01, 0x23, 8, 0, 0, 0, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA]);
+        assert_eq!(socketcan_bytes(&Frame::remote(Id::Standard(0x7FF), 4).unwrap()), [0x40, 0, 0x07, 0xFF, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(socketcan_bytes(&Frame::remote(Id::Extended(1), 0).unwrap())[..5], [0xC0, 0, 0, 0x01, 0]);
+    }
+
+    #[test]
+    fn packets_go_to_their_channels_interface() {
+        let frame = received(Frame::new(Id::Standard(1), &[]).unwrap(), 0);
+        let file = write(&[(0, frame), (1, frame), (1, frame)]);
+        let blocks = blocks(&file);
+        let types: Vec<u32> = blocks.iter().map(|&(block_type, _)| block_type).collect();
+        assert_eq!(types, [BLOCK_SHB, BLOCK_IDB, BLOCK_IDB, BLOCK_EPB, BLOCK_EPB, BLOCK_EPB]);
+        let interfaces: Vec<u32> = blocks[3..].iter().map(|(_, body)| u32::from_le_bytes(body[..4].try_into().unwrap())).collect();
+        assert_eq!(interfaces, [0, 1, 1]);
+    }
+
+    #[test]
+    fn channels_past_the_last_interface_are_refused() {
+        let frame = received(Frame::new(Id::Standard(1), &[]).unwrap(), 0);
+        let mut writer = PcapngWriter::new(Vec::new());
+        writer.write_frame(0, &frame, Direction::Rx).unwrap();
+        let err = writer.write_frame(CHANNEL_COUNT, &frame, Direction::Rx).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(writer.write_frame(7, &frame, Direction::Rx).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        writer.finish().unwrap();
+        let types: Vec<u32> = blocks(&writer.out).iter().map(|&(block_type, _)| block_type).collect();
+        assert_eq!(types, [BLOCK_SHB, BLOCK_IDB, BLOCK_IDB, BLOCK_EPB], "nothing written for the refused frames");
+    }
+
+    #[test]
+    fn empty_captures_still_describe_both_interfaces() {
+        let file = write(&[]);
+        assert_eq!(file.len(), 28 + 2 * 40);
+        assert_eq!(blocks(&file).len(), 3);
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::dbc::Dbc;
+use crate::frame::Frame;
+use crate::id::Id;
+
+/// Block characters from lowest to highest.
+const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// What a [`Plot`] samples from each frame of its ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlotSource {
+    /// One data byte, unsigned.
+    Byte { id: Id, index: usize },
+    /// A signal decoded with the DBC, in physical units.
+    Signal { id: Id, name: String },
+}
+
+impl PlotSource {
+    /// The signal called `name` in `dbc`, in whichever message defines it.
+    pub fn signal(dbc: &Dbc, name: &str) -> Result<Self, String> {
+        dbc.messages()
+            .iter()
+            .find(|message| message.signals.iter().any(|signal| signal.name == name))
+            .map(|message| Self::Signal { id: message.id, name: name.to_string() })
+            .ok_or_else(|| format!("no signal '{name}' in the DBC"))
+    }
+
+    pub fn id(&self) -> Id {
+        match self {
+            Self::Byte { id, .. } | Self::Signal { id, .. } => *id,
+        }
+    }
+
+    /// The value in `frame`, if it has the ID and carries the byte or signal.
+    pub fn sample(&self, frame: &Frame, dbc: Option<&Dbc>) -> Option<f64> {
+        if frame.id() != self.id() || frame.is_remote() {
+            return None;
+        }
+        match self {
+            Self::Byte { index, .. } => frame.data().get(*index).map(|&byte| f64::from(byte)),
+            Self::Signal { name, .. } => {
+                let (_, signals) = dbc?.decode(frame)?;
+                signals.iter().find(|value| value.signal.name == *name).map(|value| value.physical)
+            }
+        }
+    }
+}
+
+impl fmt::Display for PlotSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Byte { id, index } => write!(f, "{id} byte{index}"),
+            Self::Signal { name, .. } => f.write_str(name),
+        }
+    }
+}
+
+/// The last `capacity` samples of one [`PlotSource`], drawn with [`sparkline`].
+#[derive(Debug, Clone)]
+pub struct Plot {
+    pub source: PlotSource,
+    samples: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl Plot {
+    pub fn new(source: PlotSource, capacity: usize) -> Self {
+        Self { source, samples: VecDeque::with_capacity(capacity), capacity: capacity.max(1) }
+    }
+
+    /// Records the sample in `frame`, if there is one; whether there was.
+    pub fn observe(&mut self, frame: &Frame, dbc: Option<&Dbc>) -> bool {
+        let Some(value) = self.source.sample(frame, dbc) else {
+            return false;
+        };
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(value);
+        true
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = f64> + '_ {
+        self.samples.iter().copied()
+    }
+
+    pub fn last(&self) -> Option<f64> {
+        self.samples.back().copied()
+    }
+
+    /// Smallest and largest finite sample.
+    pub fn range(&self) -> Option<(f64, f64)> {
+        range(self.samples.iter().copied())
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// The newest samples that fit in `width` characters.
+    pub fn render(&self, width: usize) -> String {
+        let skip = self.samples.len().saturating_sub(width);
+        sparkline(&self.samples.iter().copied().skip(skip).collect::<Vec<_>>())
+    }
+}
+
+/// One block character per value, scaled from the smallest value to the largest. A flat line,
+/// including a single value, is drawn at half height; values that aren't finite are blanks.
+pub fn sparkline(values: &[f64]) -> String {
+    let Some((min, max)) = range(values.iter().copied()) else {
+        return " ".repeat(values.len());
+    };
+    let span = max - min;
+    values
+        .iter()
+        .map(|&value| match value.is_finite() {
+            false => ' ',
+            true if span <= f64::EPSILON * max.abs().max(1.0) => LEVELS[LEVELS.len() / 2 - 1],
+            true => LEVELS[(((value - min) / span) * (LEVELS.len() - 1) as f64).round() as usize],
+        })
+        .collect()
+}
+
+fn range(values: impl Iterator<Item = f64>) -> Option<(f64, f64)> {
+    values.filter(|value| value.is_finite()).fold(None, |range, value| match range {
+        None => Some((value, value)),
+        Some((min, max)) => Some((min.min(value), max.max(value))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DBC: &str = r#"
+BO_ 801 Engine: 2 ECU
+ SG_ Coolant : 0|8@1- (0.5,0) [-64|63.5] "degC" ECU
+ SG_ Load : 8|8@1+ (1,0) [0|255] "%" ECU
+"#;
+
+    fn frame(id: u16, data: &[u8]) -> Frame {
+        Frame::new(Id::Standard(id), data).unwrap()
+    }
+
+    #[test]
+    fn values_scale_from_the_smallest_to_the_largest() {
+        assert_eq!(sparkline(&[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]), "▁▂▃▄▅▆▇█");
+        assert_eq!(sparkline(&[7.0, 0.0, 3.5, 7.0]), "█▁▅█", "halfway rounds up");
+        assert_eq!(sparkline(&[1000.0, 1001.0]), "▁█", "the range, not zero, is the bottom");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn flat_lines_and_single_samples_sit_at_half_height() {
+        for values in [&[5.0, 5.0, 5.0][..], &[0.0, 0.0], &[-12.5; 4], &[1e12, 1e12], &[3.0]] {
+            let line = sparkline(values);
+            assert_eq!(line, "▄".repeat(values.len()), "{values:?}");
+        }
+        // Rounding noise on a large value is still flat.
+        assert_eq!(sparkline(&[1e9, 1e9 + 1e-7]), "▄▄");
+        assert_eq!(sparkline(&[f64::NAN, 2.0, f64::INFINITY]), " ▄ ", "the one finite value is a single sample");
+    }
+
+    #[test]
+    fn negative_values_scale_like_any_others() {
+        assert_eq!(sparkline(&[-3.0, -2.0, -1.0]), sparkline(&[1.0, 2.0, 3.0]));
+        assert_eq!(sparkline(&[-40.0, -16.0, 0.0, 8.0]), "▁▅▇█");
+        assert_eq!(sparkline(&[-1.0, 1.0, -1.0]), "▁█▁");
+    }
+
+    #[test]
+    fn values_that_arent_finite_are_blanks() {
+        assert_eq!(sparkline(&[f64::NAN, 0.0, f64::NEG_INFINITY, 7.0]), " ▁ █");
+        assert_eq!(sparkline(&[f64::NAN, f64::NAN]), "  ");
+    }
+
+    #[test]
+    fn plots_keep_the_newest_samples() {
+        let mut plot = Plot::new(PlotSource::Byte { id: Id::Standard(0x321), index: 2 }, 4);
+        assert_eq!((plot.last(), plot.range(), plot.render(10)), (None, None, String::new()));
+        for byte in [10, 20, 30, 40, 50, 60] {
+            assert!(plot.observe(&frame(0x321, &[0, 0, byte]), None));
+        }
+        assert!(!plot.observe(&frame(0x322, &[0, 0, 1]), None), "another ID");
+        assert!(!plot.observe(&frame(0x321, &[0, 0]), None), "too short for byte 2");
+        assert!(!plot.observe(&Frame::remote(Id::Standard(0x321), 8).unwrap(), None));
+        assert_eq!(plot.samples().collect::<Vec<_>>(), [30.0, 40.0, 50.0, 60.0]);
+        assert_eq!((plot.last(), plot.range()), (Some(60.0), Some((30.0, 60.0))));
+        assert_eq!(plot.render(10), "▁▃▆█");
+        assert_eq!(plot.render(2), "▁█", "rescaled to what fits");
+        assert_eq!(plot.render(1), "▄");
+        assert_eq!(plot.render(0), "");
+        assert_eq!(plot.source.to_string(), "0x321 byte2");
+        plot.clear();
+        assert_eq!(plot.render(10), "");
+    }
+
+    #[test]
+    fn signals_plot_in_physical_units() {
+        let dbc = Dbc::parse(DBC).unwrap();
+        let source = PlotSource::signal(&dbc, "Coolant").unwrap();
+        assert_eq!(source, PlotSource::Signal { id: Id::Standard(801), name: "Coolant".to_string() });
+        assert_eq!(PlotSource::signal(&dbc, "Boost").unwrap_err(), "no signal 'Boost' in the DBC");
+
+        let mut plot = Plot::new(source, 16);
+        for raw in [0xB0, 0xE0, 0x00, 0x10] {
+            assert!(plot.observe(&frame(801, &[raw, 0]), Some(&dbc)));
+        }
+        assert!(!plot.observe(&frame(801, &[0x10, 0]), None), "no DBC, no signal");
+        assert_eq!(plot.samples().collect::<Vec<_>>(), [-40.0, -16.0, 0.0, 8.0]);
+        assert_eq!(plot.range(), Some((-40.0, 8.0)));
+        assert_eq!(plot.render(16), "▁▅▇█");
+        assert_eq!(plot.source.to_string(), "Coolant");
+    }
+}
@@ -0,0 +1,228 @@
+//! Frame processors loaded from shared libraries through a C vtable, so plugins can be built
+//! with any compiler (or Rust version) that can export a C function.
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::fmt;
+use std::path::Path;
+use std::time::Instant;
+
+use libloading::Library;
+
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::processor::{Emitter, FrameProcessor, Verdict};
+
+/// Bumped whenever [`PluginVtable`] or [`PluginFrame`] change layout.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+/// The function a plugin exports: `const PluginVtable *rustcanbus_plugin(void)`.
+pub const PLUGIN_ENTRY: &str = "rustcanbus_plugin";
+
+/// A frame as plugins see it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PluginFrame {
+    pub id: u32,
+    /// Nonzero for a 29-bit ID.
+    pub extended: u8,
+    /// Nonzero for a remote frame, whose `len` is the requested DLC.
+    pub remote: u8,
+    pub len: u8,
+    pub data: [u8; 8],
+}
+
+impl PluginFrame {
+    pub fn from_frame(frame: &Frame) -> Self {
+        let mut data = [0; 8];
+        data[..frame.data().len()].copy_from_slice(frame.data());
+        Self {
+            id: frame.id().raw(),
+            extended: frame.is_extended() as u8,
+            remote: frame.is_remote() as u8,
+            len: frame.dlc(),
+            data,
+        }
+    }
+
+    /// `None` for an invalid ID or a length above 8.
+    pub fn to_frame(&self) -> Option<Frame> {
+        let id = if self.extended != 0 { Id::extended(self.id) } else { Id::standard(u16::try_from(self.id).ok()?) }?;
+        if self.remote != 0 {
+            Frame::remote(id, self.len)
+        } else {
+            Frame::new(id, self.data.get(..usize::from(self.len))?)
+        }
+    }
+}
+
+/// Called by a plugin to send a frame; `context` is the one it was handed.
+pub type PluginEmit = unsafe extern "C" fn(context: *mut c_void, channel: u32, frame: *const PluginFrame);
+
+/// What [`PLUGIN_ENTRY`] returns. `state` is whatever `create` returned; the optional
+/// functions may be null.
+#[repr(C)]
+pub struct PluginVtable {
+    /// Must be [`PLUGIN_ABI_VERSION`].
+    pub abi_version: u32,
+    /// NUL-terminated processor name.
+    pub name: *const c_char,
+    /// Builds the processor from a NUL-terminated config string; null if the config is bad.
+    pub create: unsafe extern "C" fn(config: *const c_char) -> *mut c_void,
+    pub destroy: unsafe extern "C" fn(state: *mut c_void),
+    pub on_start: Option<unsafe extern "C" fn(state: *mut c_void)>,
+    /// May change `*frame`; returns 0 to pass it on and anything else to drop it.
+    pub on_frame: Option<
+        unsafe extern "C" fn(
+            state: *mut c_void,
+            channel: u32,
+            frame: *mut PluginFrame,
+            emit: PluginEmit,
+            context: *mut c_void,
+        ) -> i32,
+    >,
+    /// `elapsed_ms` counts from `on_start`.
+    pub on_tick: Option<unsafe extern "C" fn(state: *mut c_void, elapsed_ms: u64, emit: PluginEmit, context: *mut c_void)>,
+    pub on_stop: Option<unsafe extern "C" fn(state: *mut c_void)>,
+}
+
+#[derive(Debug)]
+pub enum PluginError {
+    Load(libloading::Error),
+    /// The library doesn't export [`PLUGIN_ENTRY`].
+    MissingEntry(libloading::Error),
+    AbiVersion { found: u32 },
+    /// `create` returned null, or the config contained a NUL.
+    Create { name: String },
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Load(err) => write!(f, "failed to load plugin: {err}"),
+            Self::MissingEntry(_) => write!(f, "library does not export {PLUGIN_ENTRY}"),
+            Self::AbiVersion { found } => {
+                write!(f, "plugin ABI version {found}, expected {PLUGIN_ABI_VERSION}")
+            }
+            Self::Create { name } => write!(f, "plugin {name} rejected its config"),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Load(err) | Self::MissingEntry(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A [`FrameProcessor`] from a shared library.
+pub struct DynamicProcessor {
+    vtable: *const PluginVtable,
+    state: *mut c_void,
+    name: String,
+    started: Instant,
+    // Dropped last: the vtable and state live in the library.
+    _library: Library,
+}
+
+// The plugin's state is only touched from the one thread that owns the processor.
+unsafe impl Send for DynamicProcessor {}
+
+impl DynamicProcessor {
+    /// Loads the library at `path` and creates the processor with `config`.
+    ///
+    /// # Safety
+    ///
+    /// The library runs arbitrary code when loaded, and its vtable must match
+    /// [`PluginVtable`].
+    pub unsafe fn load(path: &Path, config: &str) -> Result<Self, PluginError> {
+        let library = Library::new(path.as_os_str()).map_err(PluginError::Load)?;
+        let entry = *library
+            .get::<unsafe extern "C" fn() -> *const PluginVtable>(PLUGIN_ENTRY.as_bytes())
+            .map_err(PluginError::MissingEntry)?;
+        let vtable = entry();
+        let found = vtable.as_ref().map_or(0, |vtable| vtable.abi_version);
+        if found != PLUGIN_ABI_VERSION {
+            return Err(PluginError::AbiVersion { found });
+        }
+        let name = match (*vtable).name {
+            name if name.is_null() => path.display().to_string(),
+            name => CStr::from_ptr(name).to_string_lossy().into_owned(),
+        };
+        let Ok(config) = CString::new(config) else {
+            return Err(PluginError::Create { name });
+        };
+        let state = ((*vtable).create)(config.as_ptr());
+        if state.is_null() {
+            return Err(PluginError::Create { name });
+        }
+        Ok(Self { vtable, state, name, started: Instant::now(), _library: library })
+    }
+
+    fn vtable(&self) -> &PluginVtable {
+        // Checked non-null in `load`, and kept alive by `_library`.
+        unsafe { &*self.vtable }
+    }
+}
+
+unsafe extern "C" fn emit_frame(context: *mut c_void, channel: u32, frame: *const PluginFrame) {
+    let emitter = &mut *context.cast::<Emitter>();
+    // Frames the plugin got wrong are ignored rather than trusted.
+    if let Some(frame) = frame.as_ref().and_then(PluginFrame::to_frame) {
+        emitter.emit(channel, frame);
+    }
+}
+
+impl FrameProcessor for DynamicProcessor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn on_start(&mut self) {
+        self.started = Instant::now();
+        if let Some(on_start) = self.vtable().on_start {
+            unsafe { on_start(self.state) };
+        }
+    }
+
+    fn on_frame(&mut self, channel: u32, frame: &mut Frame, emit: &mut Emitter) -> Verdict {
+        let Some(on_frame) = self.vtable().on_frame else {
+            return Verdict::Pass;
+        };
+        let original = PluginFrame::from_frame(frame);
+        let mut plugin_frame = original;
+        let context = (emit as *mut Emitter).cast::<c_void>();
+        if unsafe { on_frame(self.state, channel, &mut plugin_frame, emit_frame, context) } != 0 {
+            return Verdict::Drop;
+        }
+        // Unchanged frames keep their timestamps.
+        if plugin_frame != original {
+            match plugin_frame.to_frame() {
+                Some(changed) => *frame = changed,
+                None => return Verdict::Drop,
+            }
+        }
+        Verdict::Pass
+    }
+
+    fn on_tick(&mut self, now: Instant, emit: &mut Emitter) {
+        if let Some(on_tick) = self.vtable().on_tick {
+            let elapsed = now.saturating_duration_since(self.started).as_millis() as u64;
+            unsafe { on_tick(self.state, elapsed, emit_frame, (emit as *mut Emitter).cast()) };
+        }
+    }
+
+    fn on_stop(&mut self) -> Result<(), String> {
+        if let Some(on_stop) = self.vtable().on_stop {
+            unsafe { on_stop(self.state) };
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DynamicProcessor {
+    fn drop(&mut self) {
+        unsafe { (self.vtable().destroy)(self.state) };
+    }
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+/// Longest single `VCI_Receive` wait or sleep of a reader thread. Longer ones are split up, so
+/// the reader notices within this long that it should stop.
+pub(crate) const MAX_POLL_WAIT: Duration = Duration::from_millis(100);
+
+/// Most frames one `VCI_Receive` call can return, the size of the adapter's receive buffer.
+pub(crate) const MAX_BATCH: usize = 2500;
+
+/// Smallest wait an adaptive reader backs off from once the bus goes quiet.
+const MIN_ADAPTIVE_WAIT: Duration = Duration::from_millis(1);
+
+/// How a channel's subscription reader polls the adapter, see
+/// [`Channel::set_receive_polling`](crate::Channel::set_receive_polling).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReceivePolling {
+    /// `wait_time` passed to `VCI_Receive`: how long a call blocks for the first frame. With
+    /// `adaptive` this is the longest wait, reached once the bus has been idle for a while.
+    pub wait: Duration,
+    /// Sleep after a poll that received nothing, on top of the wait.
+    pub sleep: Duration,
+    /// Poll again straight away while frames are flowing, and double the wait after each empty
+    /// poll, from 1 ms up to `wait`. Keeps latency low on a busy bus and the CPU idle on a
+    /// quiet one.
+    pub adaptive: bool,
+    /// Most frames requested per `VCI_Receive` call, up to 2500; more than are pending is
+    /// never asked for.
+    pub max_frames: usize,
+}
+
+impl Default for ReceivePolling {
+    fn default() -> Self {
+        Self { wait: Duration::from_millis(50), sleep: Duration::ZERO, adaptive: true, max_frames: MAX_BATCH }
+    }
+}
+
+/// The wait a reader thread passes to its next `VCI_Receive` call.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Poller {
+    current: Duration,
+}
+
+impl Poller {
+    pub(crate) fn new() -> Self {
+        Self { current: Duration::ZERO }
+    }
+
+    pub(crate) fn wait(&self, config: &ReceivePolling) -> Duration {
+        match config.adaptive {
+            true => self.current.min(config.wait),
+            false => config.wait,
+        }
+        .min(MAX_POLL_WAIT)
+    }
+
+    /// Takes in how many frames the last call received; the sleep to take before the next.
+    pub(crate) fn received(&mut self, frames: usize, config: &ReceivePolling) -> Duration {
+        if frames > 0 {
+            self.current = Duration::ZERO;
+            return Duration::ZERO;
+        }
+        self.current = (self.current * 2).max(MIN_ADAPTIVE_WAIT).min(config.wait);
+        config.sleep
+    }
+}
@@ -0,0 +1,482 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::frame::Frame;
+
+/// What happens to a frame after a processor has seen it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Hand the frame, including any changes, to the next processor.
+    Pass,
+    Drop,
+}
+
+/// Frames a processor wants sent, collected during one call.
+#[derive(Debug, Default)]
+pub struct Emitter {
+    frames: Vec<(u32, Frame)>,
+}
+
+impl Emitter {
+    pub fn emit(&mut self, channel: u32, frame: Frame) {
+        self.frames.push((channel, frame));
+    }
+}
+
+/// One stage of a [`Pipeline`]. Every stage runs on its own thread, so implementations can
+/// block (on a file, say) without holding up reception or the other stages.
+pub trait FrameProcessor: Send {
+    fn name(&self) -> &str;
+
+    fn on_start(&mut self) {}
+
+    /// Sees one received frame, and may change it before passing it on.
+    fn on_frame(&mut self, channel: u32, frame: &mut Frame, emit: &mut Emitter) -> Verdict;
+
+    /// Called every [`Pipeline`] tick, whether frames arrived or not.
+    fn on_tick(&mut self, _now: Instant, _emit: &mut Emitter) {}
+
+    /// Called once when the pipeline stops, after the stage's queue has drained.
+    fn on_stop(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Sends the frames processors emit; shared by every stage.
+pub type EmitHandler = Arc<dyn Fn(u32, &Frame) + Send + Sync>;
+
+/// Counters for one pipeline stage.
+#[derive(Debug)]
+pub struct ProcessorStats {
+    name: String,
+    processed: AtomicU64,
+    overflowed: AtomicU64,
+    dropped: AtomicU64,
+    emitted: AtomicU64,
+}
+
+impl ProcessorStats {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Frames the processor has seen.
+    pub fn processed(&self) -> u64 {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    /// Frames lost because the stage's queue was full.
+    pub fn overflowed(&self) -> u64 {
+        self.overflowed.load(Ordering::Relaxed)
+    }
+
+    /// Frames the processor dropped.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn emitted(&self) -> u64 {
+        self.emitted.load(Ordering::Relaxed)
+    }
+}
+
+/// A stage's input queue and counters, as the stage before it sees them.
+type Downstream = (SyncSender<(u32, Frame)>, Arc<ProcessorStats>);
+
+struct Stage {
+    stats: Arc<ProcessorStats>,
+    thread: JoinHandle<Result<(), String>>,
+}
+
+/// Received frames run through processors in order, each seeing what the one before passed.
+/// Every stage has its own bounded queue: when a stage falls behind, frames meant for it are
+/// dropped and counted in [`ProcessorStats::overflowed`] rather than blocking the stage (or
+/// the receiver) feeding it. Frames processors emit go straight to the [`EmitHandler`], not
+/// through later stages.
+pub struct Pipeline {
+    input: Option<SyncSender<(u32, Frame)>>,
+    stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /// Starts one thread per processor, calling `on_start` on each, and `on_tick` every `tick`.
+    pub fn start(processors: Vec<Box<dyn FrameProcessor>>, queue: usize, tick: Duration, emit: EmitHandler) -> Self {
+        let tick = tick.max(Duration::from_millis(1));
+        let mut next: Option<Downstream> = None;
+        let mut stages = Vec::new();
+        // Built from the end so every stage has the queue of the one after it.
+        for processor in processors.into_iter().rev() {
+            let stats = Arc::new(ProcessorStats {
+                name: processor.name().to_string(),
+                processed: AtomicU64::new(0),
+                overflowed: AtomicU64::new(0),
+                dropped: AtomicU64::new(0),
+                emitted: AtomicU64::new(0),
+            });
+            let (tx, rx) = mpsc::sync_channel(queue.max(1));
+            let (worker_stats, emit) = (Arc::clone(&stats), Arc::clone(&emit));
+            let downstream = next.take();
+            let thread = thread::spawn(move || run_stage(processor, &rx, downstream, &worker_stats, tick, &emit));
+            stages.push(Stage { stats: Arc::clone(&stats), thread });
+            next = Some((tx, stats));
+        }
+        stages.reverse();
+        Self { input: next.map(|(tx, _)| tx), stages }
+    }
+
+    /// Queues a frame for the first stage; `false` if its queue was full (or the pipeline is
+    /// empty or stopped).
+    pub fn push(&self, channel: u32, frame: Frame) -> bool {
+        let Some(input) = &self.input else {
+            return false;
+        };
+        match input.try_send((channel, frame)) {
+            Ok(()) => true,
+            Err(err) => {
+                if let TrySendError::Full(_) = err {
+                    self.stages[0].stats.overflowed.fetch_add(1, Ordering::Relaxed);
+                }
+                false
+            }
+        }
+    }
+
+    /// Stage counters, in pipeline order.
+    pub fn stats(&self) -> Vec<Arc<ProcessorStats>> {
+        self.stages.iter().map(|stage| Arc::clone(&stage.stats)).collect()
+    }
+
+    /// Lets every stage drain its queue and calls `on_stop`, returning the errors processors
+    /// reported as `name: error`. Also done (discarding the errors) on drop.
+    pub fn stop(&mut self) -> Vec<String> {
+        self.input = None;
+        self.stages
+            .drain(..)
+            .filter_map(|stage| match stage.thread.join() {
+                Ok(Ok(())) => None,
+                Ok(Err(err)) => Some(format!("{}: {err}", stage.stats.name)),
+                Err(_) => Some(format!("{}: panicked", stage.stats.name)),
+            })
+            .collect()
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run_stage(
+    mut processor: Box<dyn FrameProcessor>,
+    input: &Receiver<(u32, Frame)>,
+    downstream: Option<Downstream>,
+    stats: &ProcessorStats,
+    tick: Duration,
+    emit: &EmitHandler,
+) -> Result<(), String> {
+    let send = |emitter: Emitter| {
+        for (channel, frame) in &emitter.frames {
+            stats.emitted.fetch_add(1, Ordering::Relaxed);
+            emit(*channel, frame);
+        }
+    };
+    processor.on_start();
+    let mut next_tick = Instant::now() + tick;
+    loop {
+        match input.recv_timeout(next_tick.saturating_duration_since(Instant::now())) {
+            Ok((channel, mut frame)) => {
+                stats.processed.fetch_add(1, Ordering::Relaxed);
+                let mut emitter = Emitter::default();
+                let verdict = processor.on_frame(channel, &mut frame, &mut emitter);
+                send(emitter);
+                match (verdict, &downstream) {
+                    (Verdict::Drop, _) => {
+                        stats.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                    (Verdict::Pass, Some((next, next_stats))) => {
+                        // A stopped next stage only happens on shutdown, so only a full queue counts.
+                        if let Err(TrySendError::Full(_)) = next.try_send((channel, frame)) {
+                            next_stats.overflowed.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    (Verdict::Pass, None) => {}
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        let now = Instant::now();
+        if now >= next_tick {
+            let mut emitter = Emitter::default();
+            processor.on_tick(now, &mut emitter);
+            send(emitter);
+            // Skip ticks missed while a frame took long, instead of catching up in a burst.
+            next_tick += tick;
+            if next_tick <= now {
+                next_tick = now + tick;
+            }
+        }
+    }
+    processor.on_stop()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Condvar, Mutex};
+
+    use super::*;
+    use crate::id::Id;
+
+    type Handler = Box<dyn FnMut(u32, &mut Frame, &mut Emitter) -> Verdict + Send>;
+    type Log = Arc<Mutex<Vec<String>>>;
+    type Sent = Arc<Mutex<Vec<(u32, Frame)>>>;
+
+    /// A processor made of a closure, logging every call as `name:event`.
+    struct Probe {
+        name: &'static str,
+        handler: Handler,
+        log: Log,
+        stop: Result<(), String>,
+    }
+
+    impl FrameProcessor for Probe {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn on_start(&mut self) {
+            self.log.lock().unwrap().push(format!("{}:start", self.name));
+        }
+
+        fn on_frame(&mut self, channel: u32, frame: &mut Frame, emit: &mut Emitter) -> Verdict {
+            self.log.lock().unwrap().push(format!("{}:{channel}:{:X}:{:?}", self.name, frame.id().raw(), frame.data()));
+            (self.handler)(channel, frame, emit)
+        }
+
+        fn on_stop(&mut self) -> Result<(), String> {
+            self.log.lock().unwrap().push(format!("{}:stop", self.name));
+            self.stop.clone()
+        }
+    }
+
+    fn probe(name: &'static str, log: &Log, handler: impl FnMut(u32, &mut Frame, &mut Emitter) -> Verdict + Send + 'static) -> Box<Probe> {
+        Box::new(Probe { name, handler: Box::new(handler), log: Arc::clone(log), stop: Ok(()) })
+    }
+
+    fn pass(_: u32, _: &mut Frame, _: &mut Emitter) -> Verdict {
+        Verdict::Pass
+    }
+
+    fn frame(id: u16, data: &[u8]) -> Frame {
+        Frame::new(Id::Standard(id), data).unwrap()
+    }
+
+    fn emitted() -> (EmitHandler, Sent) {
+        let frames = Sent::default();
+        let sink = Arc::clone(&frames);
+        (Arc::new(move |channel, frame: &Frame| sink.lock().unwrap().push((channel, *frame))), frames)
+    }
+
+    fn events(log: &Log, name: &str) -> Vec<String> {
+        let prefix = format!("{name}:");
+        log.lock().unwrap().iter().filter(|event| event.starts_with(&prefix)).cloned().collect()
+    }
+
+    fn counts(pipeline: &Pipeline) -> Vec<(String, u64, u64, u64, u64)> {
+        let stats = pipeline.stats();
+        stats.iter().map(|stats| (stats.name().to_string(), stats.processed(), stats.dropped(), stats.overflowed(), stats.emitted())).collect()
+    }
+
+    fn wait_for(mut condition: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    const NO_TICKS: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn stages_see_frames_in_order_with_earlier_changes() {
+        let log = Log::default();
+        let processors: Vec<Box<dyn FrameProcessor>> = vec![
+            probe("double", &log, |_, frame, _| {
+                let data: Vec<u8> = frame.data().iter().map(|byte| byte * 2).collect();
+                *frame = Frame::new(frame.id(), &data).unwrap();
+                Verdict::Pass
+            }),
+            probe("odd", &log, |_, frame, _| if frame.id().raw() % 2 == 1 { Verdict::Drop } else { Verdict::Pass }),
+            probe("last", &log, pass),
+        ];
+        let (emit, sent) = emitted();
+        let mut pipeline = Pipeline::start(processors, 64, NO_TICKS, emit);
+        for id in 0..6 {
+            assert!(pipeline.push(u32::from(id % 2), frame(id, &[id as u8])));
+        }
+        let stats = pipeline.stats();
+        assert_eq!(pipeline.stop(), Vec::<String>::new());
+
+        let double = ["double:start", "double:0:0:[0]", "double:1:1:[1]", "double:0:2:[2]", "double:1:3:[3]", "double:0:4:[4]", "double:1:5:[5]", "double:stop"];
+        assert_eq!(events(&log, "double"), double);
+        let odd = ["odd:start", "odd:0:0:[0]", "odd:1:1:[2]", "odd:0:2:[4]", "odd:1:3:[6]", "odd:0:4:[8]", "odd:1:5:[10]", "odd:stop"];
+        assert_eq!(events(&log, "odd"), odd, "with the first stage's change");
+        assert_eq!(events(&log, "last"), ["last:start", "last:0:0:[0]", "last:0:2:[4]", "last:0:4:[8]", "last:stop"], "dropped frames go no further");
+        assert!(sent.lock().unwrap().is_empty());
+
+        let seen: Vec<_> = stats.iter().map(|stats| (stats.name(), stats.processed(), stats.dropped(), stats.overflowed())).collect();
+        assert_eq!(seen, [("double", 6, 0, 0), ("odd", 6, 3, 0), ("last", 3, 0, 0)]);
+    }
+
+    #[test]
+    fn emitted_frames_skip_later_stages() {
+        let log = Log::default();
+        let processors: Vec<Box<dyn FrameProcessor>> = vec![
+            probe("echo", &log, |channel, frame, emit| {
+                emit.emit(1 - channel, *frame);
+                Verdict::Pass
+            }),
+            probe("reply", &log, |_, frame, emit| {
+                emit.emit(0, Frame::new(frame.id(), &[0xAA]).unwrap());
+                Verdict::Drop
+            }),
+            probe("last", &log, pass),
+        ];
+        let (emit, sent) = emitted();
+        let mut pipeline = Pipeline::start(processors, 64, NO_TICKS, emit);
+        pipeline.push(0, frame(0x10, &[1]));
+        pipeline.push(1, frame(0x20, &[2]));
+        let expected = [("echo".into(), 2, 0, 0, 2), ("reply".into(), 2, 2, 0, 2), ("last".into(), 0, 0, 0, 0)];
+        wait_for(|| counts(&pipeline) == expected);
+        pipeline.stop();
+
+        let mut sent = sent.lock().unwrap().clone();
+        // The two stages emit independently; each stage's own frames stay in order.
+        sent.sort_by_key(|(_, frame)| frame.data()[0] == 0xAA);
+        assert_eq!(sent, [(1, frame(0x10, &[1])), (0, frame(0x20, &[2])), (0, frame(0x10, &[0xAA])), (0, frame(0x20, &[0xAA]))]);
+        assert_eq!(events(&log, "reply")[1..3], ["reply:0:10:[1]".to_string(), "reply:1:20:[2]".into()], "not the emitted frames");
+        assert_eq!(events(&log, "last"), ["last:start", "last:stop"]);
+    }
+
+    #[test]
+    fn a_slow_stage_overflows_its_own_queue_only() {
+        let log = Log::default();
+        let gate = Arc::new((Mutex::new(false), Condvar::new()));
+        let held = Arc::clone(&gate);
+        let processors: Vec<Box<dyn FrameProcessor>> = vec![
+            probe("fast", &log, pass),
+            probe("slow", &log, move |_, _, _| {
+                let (open, opened) = &*held;
+                drop(opened.wait_while(open.lock().unwrap(), |open| !*open).unwrap());
+                Verdict::Pass
+            }),
+        ];
+        let (emit, _) = emitted();
+        let mut pipeline = Pipeline::start(processors, 4, NO_TICKS, emit);
+        let stats = pipeline.stats();
+        assert!(pipeline.push(0, frame(0, &[])));
+        wait_for(|| events(&log, "slow").len() == 2);
+        // One at a time, so only the blocked stage's queue can fill.
+        for id in 1..20 {
+            assert!(pipeline.push(0, frame(id, &[])));
+            wait_for(|| stats[0].processed() == u64::from(id) + 1);
+        }
+        // `slow` holds frame 0, its queue frames 1 to 4, and the rest were lost.
+        wait_for(|| stats[1].overflowed() == 15);
+        assert_eq!(stats[0].overflowed(), 0);
+
+        *gate.0.lock().unwrap() = true;
+        gate.1.notify_all();
+        assert_eq!(pipeline.stop(), Vec::<String>::new());
+        assert_eq!(events(&log, "slow"), ["slow:start", "slow:0:0:[]", "slow:0:1:[]", "slow:0:2:[]", "slow:0:3:[]", "slow:0:4:[]", "slow:stop"]);
+        assert_eq!(stats[0].processed(), 20);
+    }
+
+    #[test]
+    fn a_full_first_queue_refuses_the_push() {
+        let gate = Arc::new(Mutex::new(()));
+        let held = gate.lock().unwrap();
+        let blocker = Arc::clone(&gate);
+        let log = Log::default();
+        let processors: Vec<Box<dyn FrameProcessor>> = vec![probe("first", &log, move |_, _, _| {
+            drop(blocker.lock().unwrap());
+            Verdict::Pass
+        })];
+        let (emit, _) = emitted();
+        let mut pipeline = Pipeline::start(processors, 2, NO_TICKS, emit);
+        let stats = pipeline.stats();
+        assert!(pipeline.push(0, frame(0, &[])));
+        wait_for(|| stats[0].processed() == 1);
+        let accepted = (1..6).filter(|&id| pipeline.push(0, frame(id, &[]))).count();
+        assert_eq!((accepted, stats[0].overflowed()), (2, 3));
+        drop(held);
+        pipeline.stop();
+        assert_eq!(stats[0].processed(), 3);
+
+        let mut empty = Pipeline::start(Vec::new(), 2, NO_TICKS, emitted().0);
+        assert!(!empty.push(0, frame(0, &[])));
+        assert!(empty.stats().is_empty() && empty.stop().is_empty());
+    }
+
+    #[test]
+    fn stopping_drains_every_queue_and_reports_failures() {
+        let log = Log::default();
+        let mut failing = probe("failing", &log, |_, _, _| {
+            thread::sleep(Duration::from_millis(1));
+            Verdict::Pass
+        });
+        failing.stop = Err("disk full".into());
+        let processors: Vec<Box<dyn FrameProcessor>> = vec![
+            failing,
+            probe("last", &log, pass),
+            probe("panicking", &log, |_, frame, _| if frame.id().raw() == 9 { panic!("bad frame") } else { Verdict::Pass }),
+        ];
+        let (emit, _) = emitted();
+        let mut pipeline = Pipeline::start(processors, 64, NO_TICKS, emit);
+        let stats = pipeline.stats();
+        for id in 0..10 {
+            pipeline.push(0, frame(id, &[]));
+        }
+        assert_eq!(pipeline.stop(), ["failing: disk full", "panicking: panicked"]);
+        assert_eq!(stats[1].processed(), 10, "everything queued was processed");
+        assert_eq!(events(&log, "last").last().unwrap(), "last:stop");
+        assert!(!pipeline.push(0, frame(0, &[])), "stopped");
+    }
+
+    struct Ticker {
+        ticks: Arc<AtomicU64>,
+    }
+
+    impl FrameProcessor for Ticker {
+        fn name(&self) -> &str {
+            "ticker"
+        }
+
+        fn on_frame(&mut self, _channel: u32, _frame: &mut Frame, _emit: &mut Emitter) -> Verdict {
+            Verdict::Pass
+        }
+
+        fn on_tick(&mut self, _now: Instant, emit: &mut Emitter) {
+            let n = self.ticks.fetch_add(1, Ordering::SeqCst);
+            emit.emit(0, frame(0x7FF, &[n as u8]));
+        }
+    }
+
+    #[test]
+    fn ticks_come_without_frames_and_may_emit() {
+        let ticks = Arc::new(AtomicU64::new(0));
+        let (emit, sent) = emitted();
+        let mut pipeline = Pipeline::start(vec![Box::new(Ticker { ticks: Arc::clone(&ticks) })], 4, Duration::from_millis(5), emit);
+        wait_for(|| ticks.load(Ordering::SeqCst) >= 3);
+        let stats = pipeline.stats();
+        pipeline.stop();
+        let sent = sent.lock().unwrap();
+        assert_eq!(stats[0].emitted(), sent.len() as u64);
+        assert!(sent.iter().enumerate().all(|(n, (_, frame))| frame.data() == [n as u8]));
+    }
+}
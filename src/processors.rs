@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::csv::CsvWriter;
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::processor::{Emitter, FrameProcessor, Verdict};
+use crate::sink::{Direction, FrameSink};
+
+/// Built-in processors by name, with what their config string means.
+pub const BUILTIN_PROCESSORS: &[(&str, &str)] =
+    &[("rate-limit", "most frames per second passed for each ID"), ("csv", "file to write")];
+
+/// Creates the built-in processor `name`; `None` for names not in [`BUILTIN_PROCESSORS`].
+pub fn builtin_processor(name: &str, config: &str) -> Option<Result<Box<dyn FrameProcessor>, String>> {
+    let processor = match name {
+        "rate-limit" => config
+            .parse::<f64>()
+            .ok()
+            .filter(|rate| *rate > 0.0)
+            .map(|rate| Box::new(RateLimiter::new(Duration::from_secs_f64(1.0 / rate))) as Box<dyn FrameProcessor>)
+            .ok_or_else(|| format!("invalid rate '{config}'")),
+        "csv" => CsvLogger::create(Path::new(config))
+            .map(|logger| Box::new(logger) as Box<dyn FrameProcessor>)
+            .map_err(|err| format!("{config}: {err}")),
+        _ => return None,
+    };
+    Some(processor)
+}
+
+/// Passes at most one frame per channel and ID every `interval`, dropping the rest.
+pub struct RateLimiter {
+    interval: Duration,
+    last: HashMap<(u32, Id), Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, last: HashMap::new() }
+    }
+}
+
+impl FrameProcessor for RateLimiter {
+    fn name(&self) -> &str {
+        "rate-limit"
+    }
+
+    fn on_frame(&mut self, channel: u32, frame: &mut Frame, _emit: &mut Emitter) -> Verdict {
+        let now = frame.instant().unwrap_or_else(Instant::now);
+        match self.last.get(&(channel, frame.id())) {
+            Some(last) if now.saturating_duration_since(*last) < self.interval => Verdict::Drop,
+            _ => {
+                self.last.insert((channel, frame.id()), now);
+                Verdict::Pass
+            }
+        }
+    }
+}
+
+/// Writes every frame that reaches it in the `--log-format csv` layout and passes it on. After
+/// a write error it stops writing; the error is reported when the pipeline stops.
+pub struct CsvLogger {
+    writer: CsvWriter<BufWriter<File>>,
+    error: Option<io::Error>,
+}
+
+impl CsvLogger {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self { writer: CsvWriter::new(BufWriter::new(File::create(path)?)), error: None })
+    }
+}
+
+impl FrameProcessor for CsvLogger {
+    fn name(&self) -> &str {
+        "csv"
+    }
+
+    fn on_frame(&mut self, channel: u32, frame: &mut Frame, _emit: &mut Emitter) -> Verdict {
+        if self.error.is_none() {
+            self.error = self.writer.write_frame(channel, frame, Direction::Rx).err();
+        }
+        Verdict::Pass
+    }
+
+    fn on_tick(&mut self, _now: Instant, _emit: &mut Emitter) {
+        if self.error.is_none() {
+            self.error = self.writer.flush().err();
+        }
+    }
+
+    fn on_stop(&mut self) -> Result<(), String> {
+        match self.error.take() {
+            Some(err) => Err(format!("write failed: {err}")),
+            None => self.writer.finish().map_err(|err| format!("write failed: {err}")),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::sync::Mutex;
+
+/// Shown before what is typed at the prompt.
+pub const LABEL: &str = "ID#DATA to send, ID#DATA@MS to repeat, filter TERM, save/load PROFILE (Esc cancels)> ";
+
+/// What the monitor view shows on its bottom line.
+#[derive(Debug, Clone, Default)]
+pub struct PromptState {
+    /// Text typed so far while the prompt is open.
+    pub input: Option<String>,
+    /// Result of the last command.
+    pub message: Option<String>,
+}
+
+/// One-line input prompt edited by the keyboard thread and drawn by the display.
+#[derive(Default)]
+pub struct Prompt {
+    state: Mutex<PromptState>,
+}
+
+impl Prompt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state.lock().unwrap().input.is_some()
+    }
+
+    /// Opens the prompt with `input` already typed.
+    pub fn open(&self, input: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.input = Some(input.to_string());
+        state.message = None;
+    }
+
+    pub fn push(&self, c: char) {
+        if let Some(input) = &mut self.state.lock().unwrap().input {
+            input.push(c);
+        }
+    }
+
+    pub fn backspace(&self) {
+        if let Some(input) = &mut self.state.lock().unwrap().input {
+            input.pop();
+        }
+    }
+
+    /// Closes the prompt and returns what was typed.
+    pub fn close(&self) -> Option<String> {
+        self.state.lock().unwrap().input.take()
+    }
+
+    pub fn set_message(&self, message: String) {
+        self.state.lock().unwrap().message = Some(message);
+    }
+
+    pub fn snapshot(&self) -> PromptState {
+        self.state.lock().unwrap().clone()
+    }
+}
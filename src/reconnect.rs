@@ -0,0 +1,50 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Whether a [`Device`](crate::Device) can currently reach its adapter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// Calls kept failing; the handle has been given up.
+    Lost,
+    /// Trying to reopen the adapter and restore every channel's configuration.
+    Reconnecting,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Connected => "connected",
+            Self::Lost => "lost",
+            Self::Reconnecting => "reconnecting",
+        })
+    }
+}
+
+/// Called with every change of a device's [`ConnectionState`].
+pub type ConnectionObserver = Box<dyn Fn(ConnectionState) + Send + Sync>;
+
+/// What [`Channel::transmit`](crate::Channel::transmit) does while the adapter is away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisconnectedTx {
+    /// Fail with [`CanError::Disconnected`](crate::CanError::Disconnected) and count the frame
+    /// in [`Channel::dropped_while_disconnected`](crate::Channel::dropped_while_disconnected).
+    #[default]
+    Drop,
+    /// Queue up to [`HELD_TX_LIMIT`] frames per channel and send them once reconnected; frames
+    /// beyond that are dropped and counted.
+    Hold,
+}
+
+/// Frames per channel kept by [`DisconnectedTx::Hold`].
+pub const HELD_TX_LIMIT: usize = 1000;
+
+/// Automatic reconnection, see [`Device::set_auto_reconnect`](crate::Device::set_auto_reconnect).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reconnect {
+    /// Consecutive -1 returns from receive or transmit that count as the adapter being gone.
+    pub after_failures: u32,
+    /// Pause between reopen attempts.
+    pub interval: Duration,
+    pub tx: DisconnectedTx,
+}
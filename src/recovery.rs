@@ -0,0 +1,48 @@
+use std::time::{Duration, Instant};
+
+use tracing::error;
+
+use crate::device::Channel;
+use crate::error::CanError;
+
+/// Rate-limited bus-off recovery for one channel.
+///
+/// Feed it the bus-off state from each status poll; it resets, re-initializes and restarts the
+/// channel at most once per `min_interval` so a persistent fault doesn't thrash the controller.
+pub struct BusOffRecovery {
+    min_interval: Duration,
+    last_attempt: Option<Instant>,
+    recoveries: u64,
+}
+
+impl BusOffRecovery {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last_attempt: None,
+            recoveries: 0,
+        }
+    }
+
+    /// Successful recoveries so far.
+    pub fn recoveries(&self) -> u64 {
+        self.recoveries
+    }
+
+    /// Attempts recovery if `bus_off` and the rate limit allows. Returns `Ok(true)` when the
+    /// channel was recovered by this call.
+    pub fn poll(&mut self, channel: &Channel, bus_off: bool) -> Result<bool, CanError> {
+        if !bus_off {
+            return Ok(false);
+        }
+        let now = Instant::now();
+        if self.last_attempt.is_some_and(|last| now.duration_since(last) < self.min_interval) {
+            return Ok(false);
+        }
+        self.last_attempt = Some(now);
+        error!(channel = channel.index(), "bus-off, resetting the controller");
+        channel.recover()?;
+        self.recoveries += 1;
+        Ok(true)
+    }
+}
@@ -0,0 +1,40 @@
+use crate::bitrate::Bitrate;
+
+/// A setting for `VCI_SetReference`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefType {
+    /// Reference type 0: baud rate, as `BTR0 | BTR1 << 8`. Needed by firmware that ignores the
+    /// `VCI_InitCAN` timing for some rates.
+    BaudRate(u32),
+    /// Reference type 4: transmit timeout in milliseconds.
+    TransmitTimeout(u32),
+    /// Any other reference type. `data` is passed as-is and must be as large as the firmware
+    /// expects for `ref_type`.
+    Raw { ref_type: u32, data: Vec<u8> },
+}
+
+impl RefType {
+    /// The baud rate reference for `bitrate`, if [`Bitrate::requires_reference`] flags it.
+    pub fn for_bitrate(bitrate: Bitrate) -> Option<Self> {
+        bitrate.requires_reference().then(|| {
+            let (timing0, timing1) = bitrate.timing();
+            RefType::BaudRate(u32::from(timing0) | u32::from(timing1) << 8)
+        })
+    }
+
+    pub fn raw_type(&self) -> u32 {
+        match self {
+            RefType::BaudRate(_) => 0,
+            RefType::TransmitTimeout(_) => 4,
+            RefType::Raw { ref_type, .. } => *ref_type,
+        }
+    }
+
+    /// Bytes `pData` points to.
+    pub(crate) fn data(&self) -> Vec<u8> {
+        match self {
+            RefType::BaudRate(value) | RefType::TransmitTimeout(value) => value.to_le_bytes().to_vec(),
+            RefType::Raw { data, .. } => data.clone(),
+        }
+    }
+}
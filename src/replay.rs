@@ -0,0 +1,46 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Longest single sleep, so a stop request is noticed promptly even across long gaps.
+const MAX_SLEEP: Duration = Duration::from_millis(50);
+
+/// Plays `items` back with their recorded spacing, calling `send` for each one on schedule.
+///
+/// `time_of` gives each item's capture time; gaps are divided by `speed` (2.0 is twice as
+/// fast). Deadlines are measured from the start of playback rather than accumulated sleep by
+/// sleep, so per-frame overhead doesn't drift the timeline. Returns early, with `false`, when
+/// `running` is cleared.
+pub fn replay<T>(
+    items: &[T],
+    speed: f64,
+    running: &AtomicBool,
+    time_of: impl Fn(&T) -> Duration,
+    mut send: impl FnMut(&T),
+) -> bool {
+    let Some(first) = items.first() else {
+        return true;
+    };
+    let origin = time_of(first);
+    let start = Instant::now();
+    let speed = if speed > 0.0 { speed } else { 1.0 };
+
+    for item in items {
+        let offset = time_of(item).saturating_sub(origin).div_f64(speed);
+        let due = start + offset;
+        loop {
+            if !running.load(Ordering::SeqCst) {
+                return false;
+            }
+            let now = Instant::now();
+            if now >= due {
+                break;
+            }
+            thread::sleep((due - now).min(MAX_SLEEP));
+        }
+        send(item);
+    }
+    true
+}
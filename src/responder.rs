@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+
+use crate::frame::Frame;
+use crate::id::Id;
+
+/// Answers remote frames with canned data frames.
+#[derive(Debug, Clone, Default)]
+pub struct RtrResponder {
+    replies: HashMap<Id, Frame>,
+}
+
+impl RtrResponder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replies to remote requests for `reply.id()` with `reply`.
+    pub fn insert(&mut self, reply: Frame) {
+        self.replies.insert(reply.id(), reply);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replies.is_empty()
+    }
+
+    /// The canned reply for `frame`, if it is a remote request we have an entry for.
+    pub fn respond(&self, frame: &Frame) -> Option<&Frame> {
+        if !frame.is_remote() {
+            return None;
+        }
+        self.replies.get(&frame.id())
+    }
+}
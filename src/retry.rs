@@ -0,0 +1,44 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How a channel retries frames `VCI_Transmit` didn't take because the adapter's transmit
+/// buffer was full, see [`Channel::set_tx_retry`](crate::Channel::set_tx_retry). The unsent
+/// rest is offered again after `initial_backoff`, doubling up to `max_backoff`, until
+/// `deadline` has passed since the first attempt; then the TX worker gives up on it with
+/// [`CanError::TxTimeout`](crate::CanError::TxTimeout) and counts it as dropped.
+#[derive(Debug, Clone)]
+pub struct TxRetry {
+    pub deadline: Duration,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Retrying stops as soon as this is cleared, e.g. on shutdown, as it does when the device
+    /// is closed.
+    pub running: Option<Arc<AtomicBool>>,
+}
+
+impl TxRetry {
+    /// Retries for up to `deadline`, backing off from 1 ms to 20 ms.
+    pub fn new(deadline: Duration) -> Self {
+        Self { deadline, initial_backoff: Duration::from_millis(1), max_backoff: Duration::from_millis(20), running: None }
+    }
+
+    pub fn with_running(self, running: Arc<AtomicBool>) -> Self {
+        Self { running: Some(running), ..self }
+    }
+
+    /// Sleeps for `backoff`, cut short at `give_up`, and doubles it for next time. `false`,
+    /// without sleeping, once it is time to give up: past `give_up`, `running` cleared, or
+    /// `closed` set.
+    pub(crate) fn pause(&self, backoff: &mut Duration, give_up: Instant, closed: &AtomicBool) -> bool {
+        let stopped = self.running.as_ref().is_some_and(|running| !running.load(Ordering::SeqCst));
+        let now = Instant::now();
+        if stopped || closed.load(Ordering::SeqCst) || now >= give_up {
+            return false;
+        }
+        thread::sleep((*backoff).min(give_up - now));
+        *backoff = (*backoff * 2).min(self.max_backoff.max(self.initial_backoff));
+        true
+    }
+}
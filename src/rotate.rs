@@ -0,0 +1,223 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use tracing::{info, warn};
+
+use crate::frame::Frame;
+use crate::sink::{Direction, FrameSink};
+use crate::timestamp::CivilTime;
+
+/// When a [`RotatingSink`] moves on to a new file, and how many it keeps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rotation {
+    /// A file is closed once this many bytes have been written to it.
+    pub max_bytes: Option<u64>,
+    /// A file is closed at the first frame this long after it was opened.
+    pub max_age: Option<Duration>,
+    /// Only the newest this many files are kept; older ones are deleted as new ones open.
+    pub keep: Option<usize>,
+}
+
+/// Puts one file's output in the log format, e.g. `|out| Ok(Box::new(CandumpWriter::new(out)))`.
+pub type WriterFactory = Box<dyn FnMut(RotatedFile) -> io::Result<Box<dyn FrameSink>> + Send>;
+
+/// One file of a [`RotatingSink`]'s series, buffered. It counts the bytes handed to it, so the
+/// size limit doesn't wait for the buffer to drain.
+pub struct RotatedFile {
+    out: BufWriter<File>,
+    written: Arc<AtomicU64>,
+}
+
+impl Seek for RotatedFile {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        self.out.seek(position)
+    }
+}
+
+impl Write for RotatedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.out.write(buf)?;
+        self.written.fetch_add(written as u64, Ordering::Relaxed);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// A sink that writes `PATH` as a series of files named `STEM-YYYYMMDD-HHMMSS.EXT` (UTC, when
+/// each was opened), moving on to the next when a [`Rotation`] limit is reached.
+///
+/// The switch happens between two frames: the old file is finished with its format's trailer and
+/// the new one starts with its header, so every file stands alone and each frame is in exactly
+/// one. Whoever shares the sink holds its lock for the switch, and frames arriving meanwhile
+/// wait in their subscription's queue.
+pub struct RotatingSink {
+    path: PathBuf,
+    rotation: Rotation,
+    open: WriterFactory,
+    current: Box<dyn FrameSink>,
+    current_path: PathBuf,
+    written: Arc<AtomicU64>,
+    opened: Instant,
+    frames: u64,
+}
+
+impl RotatingSink {
+    pub fn create(path: &Path, rotation: Rotation, mut open: WriterFactory) -> io::Result<Self> {
+        let (current_path, out, written) = create_next(path)?;
+        let sink = Self {
+            path: path.to_path_buf(),
+            rotation,
+            current: open(out)?,
+            open,
+            current_path,
+            written,
+            opened: Instant::now(),
+            frames: 0,
+        };
+        sink.prune();
+        Ok(sink)
+    }
+
+    /// The file being written now.
+    pub fn current_path(&self) -> &Path {
+        &self.current_path
+    }
+
+    fn due(&self) -> bool {
+        // An empty file is never rotated, however large its header.
+        self.frames > 0
+            && (self.rotation.max_bytes.is_some_and(|max| self.written.load(Ordering::Relaxed) >= max)
+                || self.rotation.max_age.is_some_and(|max| self.opened.elapsed() >= max))
+    }
+
+    /// Opens the next file, then finishes the current one. Fails without switching if the next
+    /// file can't be created.
+    fn rotate(&mut self) -> io::Result<()> {
+        let (next_path, out, written) = create_next(&self.path)?;
+        let next = (self.open)(out)?;
+        let mut previous = std::mem::replace(&mut self.current, next);
+        let previous_path = std::mem::replace(&mut self.current_path, next_path);
+        self.written = written;
+        self.opened = Instant::now();
+        self.frames = 0;
+        info!(from = %previous_path.display(), to = %self.current_path.display(), "log rotated");
+        let finished = previous.finish().map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", previous_path.display())));
+        drop(previous);
+        self.prune();
+        finished
+    }
+
+    /// Deletes the oldest files of this series beyond [`Rotation::keep`], the current one included
+    /// in the count.
+    fn prune(&self) {
+        let Some(keep) = self.rotation.keep else {
+            return;
+        };
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!(dir = %dir.display(), %err, "can't list old log files");
+                return;
+            }
+        };
+        let mut series: Vec<((String, u32), PathBuf)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| Some((series_key(&self.path, &entry.file_name().to_string_lossy())?, entry.path())))
+            .collect();
+        series.sort();
+        let excess = series.len().saturating_sub(keep.max(1));
+        for (_, path) in series.into_iter().take(excess) {
+            match fs::remove_file(&path) {
+                Ok(()) => info!(path = %path.display(), "old log deleted"),
+                Err(err) => warn!(path = %path.display(), %err, "can't delete old log"),
+            }
+        }
+    }
+}
+
+impl FrameSink for RotatingSink {
+    fn write_frame(&mut self, channel: u32, frame: &Frame, direction: Direction) -> io::Result<()> {
+        if self.due() {
+            if let Err(err) = self.rotate() {
+                warn!(%err, "log rotation failed, writing on to {}", self.current_path.display());
+                // Try again after another full period or size rather than on every frame.
+                self.opened = Instant::now();
+                self.written.store(0, Ordering::Relaxed);
+            }
+        }
+        self.current.write_frame(channel, frame, direction)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.current.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.current.finish()
+    }
+}
+
+/// Creates `STEM-YYYYMMDD-HHMMSS.EXT` for now, adding `-2`, `-3`... if files were already opened
+/// within the same second.
+fn create_next(path: &Path) -> io::Result<(PathBuf, RotatedFile, Arc<AtomicU64>)> {
+    let t = CivilTime::from_system_time(SystemTime::now());
+    let stamp = format!("{:04}{:02}{:02}-{:02}{:02}{:02}", t.year, t.month, t.day, t.hour, t.minute, t.second);
+    let (stem, extension) = split_name(path);
+    let mut n = 0;
+    loop {
+        n += 1;
+        let suffix = if n == 1 { String::new() } else { format!("-{n}") };
+        let next = path.with_file_name(format!("{stem}-{stamp}{suffix}{extension}"));
+        match File::options().write(true).create_new(true).open(&next) {
+            Ok(file) => {
+                let written = Arc::new(AtomicU64::new(0));
+                let out = RotatedFile { out: BufWriter::new(file), written: Arc::clone(&written) };
+                return Ok((next, out, written));
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(io::Error::new(err.kind(), format!("{}: {err}", next.display()))),
+        }
+    }
+}
+
+/// The sort key of `name` if it's a file of `path`'s series: its timestamp, then its `-N`.
+fn series_key(path: &Path, name: &str) -> Option<(String, u32)> {
+    let (stem, extension) = split_name(path);
+    let rest = name.strip_prefix(&stem)?.strip_prefix('-')?.strip_suffix(&extension)?;
+    let (stamp, n) = match rest.get(15..) {
+        Some("") => (rest, 1),
+        Some(n) => (&rest[..15], n.strip_prefix('-')?.parse().ok()?),
+        None => return None,
+    };
+    let digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    match stamp.split_once('-') {
+        Some((date, time)) if date.len() == 8 && time.len() == 6 && digits(date) && digits(time) => Some((stamp.to_string(), n)),
+        _ => None,
+    }
+}
+
+/// `bus.log` as `("bus", ".log")`; the extension of a compressed log keeps the one before it, as
+/// in `("bus", ".log.gz")`.
+fn split_name(path: &Path) -> (String, String) {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut dots = name.match_indices('.').map(|(at, _)| at).filter(|&at| at > 0).rev();
+    let split = match (dots.next(), dots.next()) {
+        (Some(last), Some(before)) if matches!(&name[last..], ".gz" | ".zst") => before,
+        (Some(last), _) => last,
+        (None, _) => name.len(),
+    };
+    (name[..split].to_string(), name[split..].to_string())
+}
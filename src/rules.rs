@@ -0,0 +1,279 @@
+use std::fmt;
+
+use crate::filter::{parse_filter_id, parse_id_match, IdMatch};
+use crate::frame::Frame;
+use crate::id::Id;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleAction {
+    Forward,
+    Drop,
+    /// Forward with this ID instead.
+    Map(Id),
+}
+
+/// One line of a rules file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    /// Gateway direction the rule applies to (`0` is CAN1→CAN2, `1` is CAN2→CAN1), `None` for
+    /// both.
+    pub direction: Option<usize>,
+    pub matcher: IdMatch,
+    pub action: RuleAction,
+    /// `(byte index, value)` overwrites applied to forwarded frames.
+    pub rewrites: Vec<(usize, u8)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rules line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+/// Ordered gateway rules; the first matching rule decides, frames matching none follow the
+/// default.
+///
+/// ```text
+/// # direction  match     action     rewrites
+/// 1->2         100       map 200
+/// any          7FF       drop
+/// 2->1         18FF0000x/1FFF0000 forward set 0=FF set 7=00
+/// any          300:7F0   forward
+/// default drop
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GatewayRules {
+    pub rules: Vec<Rule>,
+    pub default_forward: bool,
+}
+
+impl Default for GatewayRules {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            default_forward: true,
+        }
+    }
+}
+
+impl GatewayRules {
+    pub fn parse(text: &str) -> Result<Self, RuleError> {
+        let mut rules = GatewayRules::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |message: String| RuleError { line: index + 1, message };
+            let mut words = line.split_whitespace();
+            let first = words.next().expect("line is not empty");
+            if first == "default" {
+                rules.default_forward = match (words.next(), words.next()) {
+                    (Some("forward"), None) => true,
+                    (Some("drop"), None) => false,
+                    _ => return Err(err("expected 'default forward' or 'default drop'".to_string())),
+                };
+                continue;
+            }
+            let direction = match first {
+                "any" | "*" => None,
+                "1->2" => Some(0),
+                "2->1" => Some(1),
+                _ => return Err(err(format!("expected a direction (1->2, 2->1 or any), got '{first}'"))),
+            };
+            let matcher = parse_id_match(words.next().ok_or_else(|| err("missing ID".to_string()))?).map_err(err)?;
+            let action = match words.next() {
+                Some("forward") => RuleAction::Forward,
+                Some("drop") => RuleAction::Drop,
+                Some("map") => {
+                    let id = words.next().ok_or_else(|| err("'map' needs a new ID".to_string()))?;
+                    RuleAction::Map(parse_filter_id(id, false).map_err(err)?)
+                }
+                Some(other) => return Err(err(format!("unknown action '{other}'"))),
+                None => return Err(err("missing action (forward, drop or map)".to_string())),
+            };
+
+            let mut rewrites = Vec::new();
+            while let Some(word) = words.next() {
+                if word != "set" {
+                    return Err(err(format!("unexpected '{word}', expected 'set N=HH'")));
+                }
+                let spec = words.next().ok_or_else(|| err("'set' needs N=HH".to_string()))?;
+                rewrites.push(parse_rewrite(spec).map_err(err)?);
+            }
+            if action == RuleAction::Drop && !rewrites.is_empty() {
+                return Err(err("byte rewrites on a drop rule have no effect".to_string()));
+            }
+            rules.rules.push(Rule {
+                direction,
+                matcher,
+                action,
+                rewrites,
+            });
+        }
+        Ok(rules)
+    }
+
+    /// The frame to transmit for `frame` arriving in `direction`, or `None` to drop it.
+    pub fn apply(&self, direction: usize, frame: &Frame) -> Option<Frame> {
+        let Some(rule) = self
+            .rules
+            .iter()
+            .find(|rule| rule.direction.is_none_or(|d| d == direction) && rule.matcher.matches(frame.id()))
+        else {
+            return self.default_forward.then_some(*frame);
+        };
+
+        let id = match rule.action {
+            RuleAction::Drop => return None,
+            RuleAction::Forward => frame.id(),
+            RuleAction::Map(id) => id,
+        };
+        if frame.is_remote() {
+            return Frame::remote(id, frame.dlc());
+        }
+        let mut data = [0u8; 8];
+        let mut len = frame.data().len();
+        data[..len].copy_from_slice(frame.data());
+        for &(index, value) in &rule.rewrites {
+            data[index] = value;
+            len = len.max(index + 1);
+        }
+        Frame::new(id, &data[..len])
+    }
+}
+
+/// `N=HH`, byte index 0-7 and a hex value.
+fn parse_rewrite(s: &str) -> Result<(usize, u8), String> {
+    let (index, value) = s.split_once('=').ok_or_else(|| format!("expected N=HH, got '{s}'"))?;
+    let index: usize = index.parse().map_err(|_| format!("invalid byte index '{index}'"))?;
+    if index > 7 {
+        return Err(format!("byte index {index} is beyond 7"));
+    }
+    let value = u8::from_str_radix(value, 16).map_err(|_| format!("invalid byte value '{value}'"))?;
+    Ok((index, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "\
+# direction  match     action     rewrites
+1->2         100       map 200
+any          7FF       drop
+
+2->1         18FF0000x/1FFF0000 forward set 0=FF set 7=00   # trailing comment
+*            300:7F0   forward
+default drop
+";
+
+    fn std_frame(id: u16, data: &[u8]) -> Frame {
+        Frame::new(Id::Standard(id), data).unwrap()
+    }
+
+    fn error(text: &str) -> RuleError {
+        GatewayRules::parse(text).expect_err(text)
+    }
+
+    #[test]
+    fn parses_a_rules_file() {
+        let rules = GatewayRules::parse(EXAMPLE).unwrap();
+        assert!(!rules.default_forward);
+        assert_eq!(
+            rules.rules,
+            [
+                Rule { direction: Some(0), matcher: IdMatch::exact(Id::Standard(0x100)), action: RuleAction::Map(Id::Standard(0x200)), rewrites: vec![] },
+                Rule { direction: None, matcher: IdMatch::exact(Id::Standard(0x7FF)), action: RuleAction::Drop, rewrites: vec![] },
+                Rule {
+                    direction: Some(1),
+                    matcher: IdMatch { id: Id::Extended(0x18FF_0000), mask: 0x1FFF_0000 },
+                    action: RuleAction::Forward,
+                    rewrites: vec![(0, 0xFF), (7, 0x00)],
+                },
+                Rule { direction: None, matcher: IdMatch { id: Id::Standard(0x300), mask: 0x7F0 }, action: RuleAction::Forward, rewrites: vec![] },
+            ]
+        );
+        assert_eq!(GatewayRules::parse("# nothing\n\n").unwrap(), GatewayRules::default());
+        assert!(GatewayRules::default().default_forward);
+        assert_eq!(GatewayRules::parse("any 100 map 1234").unwrap().rules[0].action, RuleAction::Map(Id::Extended(0x1234)));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let cases = [
+            ("any 100 forward\nsideways 100 drop", 2, "expected a direction (1->2, 2->1 or any), got 'sideways'"),
+            ("\n\nany", 3, "missing ID"),
+            ("any 100", 1, "missing action (forward, drop or map)"),
+            ("any 100 bounce", 1, "unknown action 'bounce'"),
+            ("any 100 map", 1, "'map' needs a new ID"),
+            ("any 100 forward set", 1, "'set' needs N=HH"),
+            ("any 100 forward 0=FF", 1, "unexpected '0=FF', expected 'set N=HH'"),
+            ("any 100 forward set 8=00", 1, "byte index 8 is beyond 7"),
+            ("any 100 forward set 0=GG", 1, "invalid byte value 'GG'"),
+            ("any 100 forward set x=00", 1, "invalid byte index 'x'"),
+            ("any 100 drop set 0=00", 1, "byte rewrites on a drop rule have no effect"),
+            ("default", 1, "expected 'default forward' or 'default drop'"),
+            ("default drop now", 1, "expected 'default forward' or 'default drop'"),
+            ("any 123/FFF forward", 1, "mask 0xFFF is wider than the 11 bits of 0x123"),
+            ("any 12G forward", 1, "invalid CAN ID '12G'"),
+        ];
+        for (text, line, message) in cases {
+            assert_eq!(error(text), RuleError { line, message: message.to_string() }, "{text:?}");
+        }
+        assert_eq!(error("any 100 bounce").to_string(), "rules line 1: unknown action 'bounce'");
+    }
+
+    #[test]
+    fn applies_the_first_matching_rule() {
+        let rules = GatewayRules::parse(EXAMPLE).unwrap();
+        let frame = std_frame(0x100, &[1, 2]);
+        assert_eq!(rules.apply(0, &frame), Some(std_frame(0x200, &[1, 2])));
+        assert_eq!(rules.apply(1, &frame), None, "the map is CAN1 to CAN2 only, and the default drops");
+        assert_eq!(rules.apply(0, &std_frame(0x7FF, &[])), None);
+        assert_eq!(rules.apply(1, &std_frame(0x7FF, &[])), None);
+        assert_eq!(rules.apply(0, &std_frame(0x30A, &[9])), Some(std_frame(0x30A, &[9])));
+        assert_eq!(rules.apply(0, &std_frame(0x310, &[9])), None);
+
+        let ordered = GatewayRules::parse("any 100 drop\nany 100:700 forward\nany 100 map 200").unwrap();
+        assert_eq!(ordered.apply(0, &std_frame(0x100, &[])), None);
+        assert_eq!(ordered.apply(0, &std_frame(0x101, &[])), Some(std_frame(0x101, &[])));
+        let ordered = GatewayRules::parse("any 100:700 map 7E0\nany 100 drop").unwrap();
+        assert_eq!(ordered.apply(1, &std_frame(0x100, &[])), Some(std_frame(0x7E0, &[])));
+    }
+
+    #[test]
+    fn rewrites_overwrite_and_extend_the_payload() {
+        let rules = GatewayRules::parse(EXAMPLE).unwrap();
+        let frame = Frame::new(Id::Extended(0x18FF_50E5), &[1, 2, 3]).unwrap();
+        let out = rules.apply(1, &frame).unwrap();
+        assert_eq!((out.id(), out.data()), (Id::Extended(0x18FF_50E5), &[0xFF, 2, 3, 0, 0, 0, 0, 0][..]));
+        assert_eq!(rules.apply(0, &frame), None, "2->1 only");
+        // A standard ID with the same bits is another ID.
+        assert_eq!(rules.apply(1, &std_frame(0x0E5, &[])), None);
+
+        let rules = GatewayRules::parse("any 123 map 18DAF110 set 1=AA").unwrap();
+        let out = rules.apply(0, &std_frame(0x123, &[])).unwrap();
+        assert_eq!((out.id(), out.data()), (Id::Extended(0x18DA_F110), &[0, 0xAA][..]));
+        let remote = rules.apply(0, &Frame::remote(Id::Standard(0x123), 4).unwrap()).unwrap();
+        assert!(remote.is_remote());
+        assert_eq!((remote.id(), remote.dlc()), (Id::Extended(0x18DA_F110), 4), "rewrites leave requests alone");
+    }
+
+    #[test]
+    fn unmatched_frames_follow_the_default() {
+        let frame = std_frame(0x555, &[5]);
+        assert_eq!(GatewayRules::default().apply(0, &frame), Some(frame));
+        assert_eq!(GatewayRules::parse("any 100 drop\ndefault forward").unwrap().apply(1, &frame), Some(frame));
+        assert_eq!(GatewayRules::parse("default drop\nany 555 forward").unwrap().apply(1, &frame), Some(frame), "rules anywhere in the file come first");
+        assert_eq!(GatewayRules::parse("1->2 555 forward\ndefault drop").unwrap().apply(1, &frame), None);
+    }
+}
@@ -0,0 +1,370 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::device::Channel;
+use crate::error::CanError;
+use crate::frame::Frame;
+use crate::integrity::IntegritySpec;
+
+/// Sleeping is only accurate to a millisecond or so (far worse with the default Windows timer),
+/// so the last stretch before a deadline is spent yielding instead.
+const SPIN_WINDOW: Duration = Duration::from_millis(2);
+
+/// Handle for a message added to a [`Scheduler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CyclicId(u64);
+
+/// Called after every transmit attempt with the channel, the frame and the result.
+pub type TransmitObserver = Box<dyn Fn(&Channel, &Frame, &Result<(), CanError>) + Send + Sync>;
+
+/// Makes the frame a message sends next, see [`Scheduler::add_generated`].
+type Generator = Box<dyn FnMut() -> Frame + Send>;
+
+struct Entry {
+    channel: Channel,
+    frame: Frame,
+    period: Duration,
+    next: Instant,
+    remaining: Option<u64>,
+    generate: Option<Generator>,
+    protection: Option<Protection>,
+}
+
+/// A rolling counter and checksum filled in on every send, see [`Scheduler::protect`].
+struct Protection {
+    spec: IntegritySpec,
+    counter: u8,
+}
+
+#[derive(Default)]
+struct State {
+    entries: HashMap<u64, Entry>,
+    /// Next-due times; stale items (removed or rescheduled messages) are skipped when popped.
+    queue: BinaryHeap<Reverse<(Instant, u64)>>,
+    next_id: u64,
+    stopped: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    wake: Condvar,
+    observer: Option<TransmitObserver>,
+}
+
+/// Sends any number of cyclic messages from one thread, ordered by a queue of next-due times.
+///
+/// Deadlines are absolute, so a late send doesn't push later ones back; a message that falls
+/// more than a whole period behind skips the missed slots instead of bursting.
+pub struct Scheduler {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::start(None)
+    }
+
+    pub fn with_observer(observer: TransmitObserver) -> Self {
+        Self::start(Some(observer))
+    }
+
+    fn start(observer: Option<TransmitObserver>) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            wake: Condvar::new(),
+            observer,
+        });
+        let worker = Arc::clone(&shared);
+        let thread = thread::spawn(move || run(&worker));
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Sends `frame` on `channel` every `period`, starting now.
+    pub fn add(&self, channel: &Channel, frame: Frame, period: Duration) -> CyclicId {
+        self.add_with(channel, frame, period, Duration::ZERO, None)
+    }
+
+    /// Like [`add`](Self::add), with the first send after `delay` and at most `count` sends in
+    /// total (`None` repeats forever).
+    pub fn add_with(
+        &self,
+        channel: &Channel,
+        frame: Frame,
+        period: Duration,
+        delay: Duration,
+        count: Option<u64>,
+    ) -> CyclicId {
+        self.insert(channel, frame, period, delay, count, None)
+    }
+
+    /// Sends a new frame from `generate` every `period`, starting now, at most `count` times
+    /// (`None` for no limit). Paced like any other message; the observer sees each frame sent.
+    pub fn add_generated(
+        &self,
+        channel: &Channel,
+        period: Duration,
+        count: Option<u64>,
+        mut generate: impl FnMut() -> Frame + Send + 'static,
+    ) -> CyclicId {
+        let frame = generate();
+        self.insert(channel, frame, period, Duration::ZERO, count, Some(Box::new(generate)))
+    }
+
+    fn insert(
+        &self,
+        channel: &Channel,
+        frame: Frame,
+        period: Duration,
+        delay: Duration,
+        count: Option<u64>,
+        generate: Option<Generator>,
+    ) -> CyclicId {
+        let mut state = self.shared.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        if count == Some(0) {
+            return CyclicId(id);
+        }
+        let next = Instant::now() + delay;
+        state.entries.insert(
+            id,
+            Entry {
+                channel: channel.clone(),
+                frame,
+                period: period.max(Duration::from_millis(1)),
+                next,
+                remaining: count,
+                generate,
+                protection: None,
+            },
+        );
+        state.queue.push(Reverse((next, id)));
+        self.shared.wake.notify_one();
+        CyclicId(id)
+    }
+
+    /// Stops sending a message; `false` if it was already removed or finished.
+    pub fn remove(&self, id: CyclicId) -> bool {
+        self.shared.state.lock().unwrap().entries.remove(&id.0).is_some()
+    }
+
+    /// Replaces the payload sent from the next period on. A generated message goes back to
+    /// its generator after that one send.
+    pub fn update(&self, id: CyclicId, frame: Frame) -> bool {
+        match self.shared.state.lock().unwrap().entries.get_mut(&id.0) {
+            Some(entry) => {
+                entry.frame = frame;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// From the next send on, sets the counter declared in `spec` and then recomputes its
+    /// checksum in every frame of the message, after any [`update`](Self::update). The counter
+    /// starts from its value in the current payload and goes up by one per send, wrapping at its
+    /// width.
+    pub fn protect(&self, id: CyclicId, spec: IntegritySpec) -> bool {
+        match self.shared.state.lock().unwrap().entries.get_mut(&id.0) {
+            Some(entry) => {
+                let counter = spec.counter.and_then(|field| field.read(entry.frame.data())).unwrap_or(0);
+                entry.protection = Some(Protection { spec, counter });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The frame a message currently sends, before [`protect`](Self::protect) fills it in.
+    pub fn frame(&self, id: CyclicId) -> Option<Frame> {
+        self.shared.state.lock().unwrap().entries.get(&id.0).map(|entry| entry.frame)
+    }
+
+    /// Messages still being sent, in the order they were added.
+    pub fn ids(&self) -> Vec<CyclicId> {
+        let mut ids: Vec<u64> = self.shared.state.lock().unwrap().entries.keys().copied().collect();
+        ids.sort_unstable();
+        ids.into_iter().map(CyclicId).collect()
+    }
+
+    /// Removes every message.
+    pub fn clear(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.entries.clear();
+        state.queue.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stops the worker thread. Also done on drop.
+    pub fn stop(&mut self) {
+        self.shared.state.lock().unwrap().stopped = true;
+        self.shared.wake.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run(shared: &Shared) {
+    let mut state = shared.state.lock().unwrap();
+    loop {
+        if state.stopped {
+            return;
+        }
+        let Some(&Reverse((due, id))) = state.queue.peek() else {
+            state = shared.wake.wait(state).unwrap();
+            continue;
+        };
+        if state.entries.get(&id).is_none_or(|entry| entry.next != due) {
+            state.queue.pop();
+            continue;
+        }
+
+        let now = Instant::now();
+        if due > now {
+            let wait = due - now;
+            if wait > SPIN_WINDOW {
+                state = shared.wake.wait_timeout(state, wait - SPIN_WINDOW).unwrap().0;
+            } else {
+                drop(state);
+                thread::yield_now();
+                state = shared.state.lock().unwrap();
+            }
+            continue;
+        }
+
+        state.queue.pop();
+        let entry = state.entries.get_mut(&id).expect("checked above");
+        let (channel, mut frame) = (entry.channel.clone(), entry.frame);
+        if let Some(generate) = &mut entry.generate {
+            entry.frame = generate();
+        }
+        if let Some(protection) = &mut entry.protection {
+            frame = protection.spec.apply(&frame, protection.counter);
+            if let Some(field) = protection.spec.counter {
+                protection.counter = field.next(protection.counter);
+            }
+        }
+        entry.next = if now.duration_since(due) > entry.period { now + entry.period } else { due + entry.period };
+        let next = entry.next;
+        let finished = match &mut entry.remaining {
+            Some(remaining) => {
+                *remaining = remaining.saturating_sub(1);
+                *remaining == 0
+            }
+            None => false,
+        };
+        if finished {
+            state.entries.remove(&id);
+        } else {
+            state.queue.push(Reverse((next, id)));
+        }
+
+        drop(state);
+        let result = channel.transmit(&frame);
+        if let Some(observer) = &shared.observer {
+            observer(&channel, &frame, &result);
+        }
+        state = shared.state.lock().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::started_pair;
+    use crate::tx_table::parse_tx_table;
+
+    const TABLE: &str = r#"
+[Torque]
+id = 0x0C9
+channel = 0
+period_ms = 2
+data = "10270000 0000AE00"
+counter = { byte = 6 }
+checksum = { algorithm = "crc8", byte = 7 }
+
+[Status]
+id = 0x3E9
+channel = 0
+period_ms = 2
+data = "FE000000 99"
+counter = { byte = 0, width = 8 }
+checksum = { algorithm = "xor", byte = 3, first = 0, last = 3 }
+"#;
+
+    /// The payloads `entry` of [`TABLE`] sends in its first `count` cycles, protected and
+    /// started after `update` replaced its payload.
+    fn capture(entry: usize, count: u64, update: Option<&[u8]>) -> Vec<Vec<u8>> {
+        let (mock, _device, can1, _can2) = started_pair();
+        let entry = parse_tx_table(TABLE).unwrap().remove(entry);
+        let scheduler = Scheduler::new();
+        // The delay keeps the first send from going out before the counter is declared.
+        let id = scheduler.add_with(&can1, entry.frame, entry.period, Duration::from_millis(20), Some(count));
+        assert!(scheduler.protect(id, entry.integrity));
+        if let Some(data) = update {
+            assert!(scheduler.update(id, Frame::new(entry.frame.id(), data).unwrap()));
+        }
+        let mut sent = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while sent.len() < count as usize && Instant::now() < deadline {
+            sent.extend(mock.take_transmitted(0).iter().map(|frame| frame.data().to_vec()));
+            thread::sleep(Duration::from_millis(5));
+        }
+        sent
+    }
+
+    #[test]
+    fn a_nibble_counter_and_crc_match_the_capture() {
+        // Recorded from the ECU: the counter in the low nibble of byte 6 wraps past 15, and the
+        // CRC-8 over bytes 0 to 6 follows it.
+        let captured: [[u8; 8]; 5] = [
+            [0x10, 0x27, 0x00, 0x00, 0x00, 0x00, 0xAE, 0x53],
+            [0x10, 0x27, 0x00, 0x00, 0x00, 0x00, 0xAF, 0x4E],
+            [0x10, 0x27, 0x00, 0x00, 0x00, 0x00, 0xA0, 0xF5],
+            [0x10, 0x27, 0x00, 0x00, 0x00, 0x00, 0xA1, 0xE8],
+            [0x10, 0x27, 0x00, 0x00, 0x00, 0x00, 0xA2, 0xCF],
+        ];
+        assert_eq!(capture(0, 5, None), captured);
+    }
+
+    #[test]
+    fn a_byte_counter_and_xor_match_the_capture() {
+        // The checksum range takes in its own byte 3, which is left out; byte 4 is outside it.
+        let captured: [[u8; 5]; 4] = [
+            [0xFE, 0x12, 0x34, 0xD8, 0x99],
+            [0xFF, 0x12, 0x34, 0xD9, 0x99],
+            [0x00, 0x12, 0x34, 0x26, 0x99],
+            [0x01, 0x12, 0x34, 0x27, 0x99],
+        ];
+        // The update's own counter and checksum bytes are overwritten; the counter carries on
+        // from the payload it was declared on.
+        assert_eq!(capture(1, 4, Some(&[0x42, 0x12, 0x34, 0xEE, 0x99])), captured);
+    }
+}
@@ -0,0 +1,214 @@
+//! Frame handlers written in [rhai](https://rhai.rs), behind the `scripting` feature.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use rhai::{Array, Blob, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, INT};
+
+use crate::device::CHANNEL_COUNT;
+use crate::frame::Frame;
+use crate::id::Id;
+
+/// Operations one `on_frame` call may run before it's stopped, so a runaway loop costs one
+/// frame instead of the receive pipeline.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Something a script asked for; the caller carries it out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptAction {
+    Transmit { channel: u32, frame: Frame },
+    Log(String),
+    /// Replace the payload of the `index`th cyclic message still being sent, counting from 0 in
+    /// the order they were added (see [`Scheduler::ids`](crate::Scheduler::ids)).
+    SetCyclic { index: usize, data: Vec<u8> },
+}
+
+/// A compile or runtime error, with the script line it happened on when rhai knows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl ScriptError {
+    fn from_eval(err: Box<EvalAltResult>) -> Self {
+        let outer = err.position().line();
+        // Errors inside script functions come wrapped in the call; the inner one has the line.
+        let mut err = err;
+        while let EvalAltResult::ErrorInFunctionCall(.., inner, _) = *err {
+            err = inner;
+        }
+        let line = err.take_position().line().or(outer);
+        Self { line, message: err.to_string() }
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "script line {line}: {}", self.message),
+            None => write!(f, "script: {}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// A compiled script. Its top-level statements run once on [`FrameScript::start`]; after that
+/// an `on_frame(frame)` function, if it defines one, runs for every received frame.
+///
+/// `frame` is a map with `ch` (0 or 1), `id`, `extended`, `remote`, `dlc` and `data` (a blob;
+/// `frame.data[2]` reads a byte). Scripts can call:
+///
+/// - `transmit(ch, id, data)`: send a frame; IDs above 0x7FF are sent extended. `data` is an
+///   array or blob of up to 8 bytes.
+/// - `transmit_ext(ch, id, data)`: the same, always with an extended ID.
+/// - `log(msg)`: print a message.
+/// - `set_cyclic(index, data)`: change the payload of a cyclic message from its next period.
+/// - `set(name, value)` / `get(name)`: variables kept between calls, also readable from Rust
+///   with [`FrameScript::var`]; `get` returns `()` for unset names. Script functions can't see
+///   top-level variables, so this is where state goes.
+///
+/// ```text
+/// fn on_frame(frame) {
+///     if frame.id == 0x321 && frame.data[2] > 0x80 {
+///         transmit(0, 0x100, [0x01]);
+///         set("alarms", get("alarms") + 1);
+///     }
+/// }
+/// set("alarms", 0);
+/// ```
+pub struct FrameScript {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    actions: Arc<Mutex<Vec<ScriptAction>>>,
+    vars: Arc<Mutex<HashMap<String, Dynamic>>>,
+    on_frame: bool,
+}
+
+impl FrameScript {
+    pub fn compile(source: &str) -> Result<Self, ScriptError> {
+        let actions = Arc::new(Mutex::new(Vec::new()));
+        let vars = Arc::new(Mutex::new(HashMap::new()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        register_api(&mut engine, &actions, &vars);
+        let ast = engine
+            .compile(source)
+            .map_err(|err| ScriptError { line: err.1.line(), message: err.0.to_string() })?;
+        let on_frame = ast.iter_functions().any(|f| f.name == "on_frame" && f.params.len() == 1);
+        Ok(Self { engine, ast, scope: Scope::new(), actions, vars, on_frame })
+    }
+
+    /// Runs the top-level statements.
+    pub fn start(&mut self) -> Result<Vec<ScriptAction>, ScriptError> {
+        let result = self.engine.run_ast_with_scope(&mut self.scope, &self.ast);
+        let actions = self.take_actions();
+        result.map(|_| actions).map_err(ScriptError::from_eval)
+    }
+
+    /// Whether the script defines `on_frame(frame)`.
+    pub fn handles_frames(&self) -> bool {
+        self.on_frame
+    }
+
+    /// Calls `on_frame` for a received frame. On an error, actions the call asked for before it
+    /// failed are discarded.
+    pub fn on_frame(&mut self, channel: u32, frame: &Frame) -> Result<Vec<ScriptAction>, ScriptError> {
+        if !self.on_frame {
+            return Ok(Vec::new());
+        }
+        let mut map = Map::new();
+        map.insert("ch".into(), Dynamic::from(INT::from(channel)));
+        map.insert("id".into(), Dynamic::from(INT::from(frame.id().raw())));
+        map.insert("extended".into(), Dynamic::from(frame.is_extended()));
+        map.insert("remote".into(), Dynamic::from(frame.is_remote()));
+        map.insert("dlc".into(), Dynamic::from(INT::from(frame.dlc())));
+        map.insert("data".into(), Dynamic::from_blob(frame.data().to_vec()));
+        // Top-level statements already ran in `start`; calling doesn't repeat them.
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = self.engine.call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, "on_frame", (map,));
+        let actions = self.take_actions();
+        result.map(|_| actions).map_err(ScriptError::from_eval)
+    }
+
+    /// A variable the script stored with `set`.
+    pub fn var(&self, name: &str) -> Option<Dynamic> {
+        self.vars.lock().unwrap().get(name).cloned()
+    }
+
+    pub fn set_var(&self, name: &str, value: Dynamic) {
+        self.vars.lock().unwrap().insert(name.to_string(), value);
+    }
+
+    fn take_actions(&self) -> Vec<ScriptAction> {
+        std::mem::take(&mut *self.actions.lock().unwrap())
+    }
+}
+
+fn register_api(engine: &mut Engine, actions: &Arc<Mutex<Vec<ScriptAction>>>, vars: &Arc<Mutex<HashMap<String, Dynamic>>>) {
+    for extended in [false, true] {
+        let name = if extended { "transmit_ext" } else { "transmit" };
+        let push = Arc::clone(actions);
+        engine.register_fn(name, move |channel: INT, id: INT, data: Array| {
+            let data = array_bytes(data)?;
+            push.lock().unwrap().push(transmit_action(channel, id, &data, extended)?);
+            Ok::<_, Box<EvalAltResult>>(())
+        });
+        let push = Arc::clone(actions);
+        engine.register_fn(name, move |channel: INT, id: INT, data: Blob| {
+            push.lock().unwrap().push(transmit_action(channel, id, &data, extended)?);
+            Ok::<_, Box<EvalAltResult>>(())
+        });
+    }
+    let push = Arc::clone(actions);
+    engine.register_fn("log", move |message: &str| push.lock().unwrap().push(ScriptAction::Log(message.to_string())));
+    let push = Arc::clone(actions);
+    engine.register_fn("set_cyclic", move |index: INT, data: Array| {
+        let data = array_bytes(data)?;
+        push.lock().unwrap().push(set_cyclic_action(index, data)?);
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    let push = Arc::clone(actions);
+    engine.register_fn("set_cyclic", move |index: INT, data: Blob| {
+        push.lock().unwrap().push(set_cyclic_action(index, data)?);
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    let store = Arc::clone(vars);
+    engine.register_fn("set", move |name: &str, value: Dynamic| {
+        store.lock().unwrap().insert(name.to_string(), value);
+    });
+    let store = Arc::clone(vars);
+    engine.register_fn("get", move |name: &str| store.lock().unwrap().get(name).cloned().unwrap_or(Dynamic::UNIT));
+}
+
+fn array_bytes(data: Array) -> Result<Vec<u8>, Box<EvalAltResult>> {
+    data.into_iter()
+        .map(|value| match value.as_int() {
+            Ok(byte) => u8::try_from(byte).map_err(|_| format!("byte {byte} out of range").into()),
+            Err(kind) => Err(format!("expected a byte, got {kind}").into()),
+        })
+        .collect()
+}
+
+fn transmit_action(channel: INT, id: INT, data: &[u8], extended: bool) -> Result<ScriptAction, Box<EvalAltResult>> {
+    let channel = u32::try_from(channel)
+        .ok()
+        .filter(|&channel| channel < CHANNEL_COUNT)
+        .ok_or_else(|| format!("no channel {channel}"))?;
+    let raw = u32::try_from(id).map_err(|_| format!("invalid ID {id}"))?;
+    let id = if extended || raw > u32::from(Id::MAX_STANDARD) { Id::extended(raw) } else { Id::standard(raw as u16) }
+        .ok_or_else(|| format!("invalid ID 0x{raw:X}"))?;
+    let frame = Frame::new(id, data).ok_or_else(|| format!("{} data bytes, at most 8 fit", data.len()))?;
+    Ok(ScriptAction::Transmit { channel, frame })
+}
+
+fn set_cyclic_action(index: INT, data: Vec<u8>) -> Result<ScriptAction, Box<EvalAltResult>> {
+    if data.len() > 8 {
+        return Err(format!("{} data bytes, at most 8 fit", data.len()).into());
+    }
+    let index = usize::try_from(index).map_err(|_| format!("no cyclic message {index}"))?;
+    Ok(ScriptAction::SetCyclic { index, data })
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::device::Channel;
+use crate::error::CanError;
+use crate::ffi::VciInitConfig;
+use crate::frame::{Frame, SendType};
+use crate::id::Id;
+use crate::mode::ChannelMode;
+
+/// Checks that an adapter and its DLL send and receive frames intact: each channel first on its
+/// own in the SJA1000 self-test mode, which needs no wiring, then optionally CAN1 -> CAN2 over
+/// a physical loop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTest {
+    pub frames: Vec<Frame>,
+    /// How long each frame gets to come back.
+    pub timeout: Duration,
+}
+
+impl Default for SelfTest {
+    /// Standard and extended IDs at the edges of their ranges, every DLC, alternating and
+    /// all-ones bit patterns, and a remote frame.
+    fn default() -> Self {
+        let data = |id: Id, payload: &[u8]| Frame::new(id, payload).expect("valid test frame");
+        let standard = |id| Id::standard(id).expect("valid standard ID");
+        let extended = |id| Id::extended(id).expect("valid extended ID");
+        let mut frames = vec![data(standard(0x000), &[]), data(standard(0x7FF), &[0xFF; 8])];
+        frames.extend((1..=8u8).map(|dlc| data(standard(0x100 + u16::from(dlc)), &[0x55, 0xAA, 0x0F, 0xF0, 0x01, 0x80, 0x7E, 0x81][..dlc as usize])));
+        frames.push(data(extended(0x0000_0001), &[0xAA; 8]));
+        frames.push(data(extended(0x1FFF_FFFF), &[0x12, 0x34, 0x56, 0x78]));
+        frames.push(Frame::remote(standard(0x321), 4).expect("valid remote frame"));
+        Self { frames, timeout: Duration::from_millis(200) }
+    }
+}
+
+/// One frame of a [`SelfTest`] phase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameCheck {
+    pub sent: Frame,
+    /// What came back with the same ID, if anything did in time.
+    pub received: Option<Frame>,
+    /// Set if the DLL refused to send the frame.
+    pub send_failed: bool,
+}
+
+impl FrameCheck {
+    /// Whether the frame came back with matching ID, flags and data.
+    pub fn passed(&self) -> bool {
+        self.received.is_some_and(|received| same_frame(&self.sent, &received))
+    }
+}
+
+impl fmt::Display for FrameCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sent = spec(&self.sent);
+        match self.received {
+            _ if self.send_failed => write!(f, "FAIL {sent}: transmit failed"),
+            None => write!(f, "FAIL {sent}: not received"),
+            Some(received) if !same_frame(&self.sent, &received) => write!(f, "FAIL {sent}: received {}", spec(&received)),
+            Some(_) => write!(f, "pass {sent}"),
+        }
+    }
+}
+
+/// Overall result of a [`SelfTest`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTestVerdict {
+    Passed,
+    /// A channel failed on its own, so the adapter or DLL is at fault.
+    InternalFailed,
+    /// Every channel passed on its own but frames didn't make it across, so the wiring,
+    /// termination or bitrate is.
+    ExternalFailed,
+}
+
+impl fmt::Display for SelfTestVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Passed => "passed",
+            Self::InternalFailed => "internal loopback failed",
+            Self::ExternalFailed => "internal loopback ok, external failed",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelfTestReport {
+    /// One phase per channel tested, in the order given.
+    pub internal: Vec<Vec<FrameCheck>>,
+    /// The CAN1 -> CAN2 phase, if it ran.
+    pub external: Option<Vec<FrameCheck>>,
+}
+
+impl SelfTestReport {
+    pub fn verdict(&self) -> SelfTestVerdict {
+        let passed = |checks: &Vec<FrameCheck>| checks.iter().all(FrameCheck::passed);
+        if !self.internal.iter().all(passed) {
+            SelfTestVerdict::InternalFailed
+        } else if !self.external.iter().all(passed) {
+            SelfTestVerdict::ExternalFailed
+        } else {
+            SelfTestVerdict::Passed
+        }
+    }
+}
+
+impl SelfTest {
+    /// Re-initializes `channel` with `config` in self-test mode, sends every frame with the
+    /// self-test send type and checks it comes back, then restores `config`.
+    pub fn internal(&self, channel: &Channel, config: &VciInitConfig) -> Result<Vec<FrameCheck>, CanError> {
+        channel.reconfigure(&config.with_mode(ChannelMode::SelfTest))?;
+        let checks = self.run(channel, channel, SendType::SelfTest);
+        channel.reconfigure(config)?;
+        checks
+    }
+
+    /// Sends every frame on `tx` and checks it arrives on `rx`; both must be started.
+    pub fn external(&self, tx: &Channel, rx: &Channel) -> Result<Vec<FrameCheck>, CanError> {
+        self.run(tx, rx, SendType::Normal)
+    }
+
+    /// Runs both phases: `channels` one at a time on their own, then, with `looped`, the first
+    /// to the second.
+    pub fn run_all(&self, channels: &[Channel], config: &VciInitConfig, looped: bool) -> Result<SelfTestReport, CanError> {
+        let internal = channels.iter().map(|channel| self.internal(channel, config)).collect::<Result<_, _>>()?;
+        let external = match channels {
+            [tx, rx, ..] if looped => Some(self.external(tx, rx)?),
+            _ => None,
+        };
+        Ok(SelfTestReport { internal, external })
+    }
+
+    fn run(&self, tx: &Channel, rx: &Channel, send_type: SendType) -> Result<Vec<FrameCheck>, CanError> {
+        match rx.clear_buffer() {
+            Ok(()) | Err(CanError::Unsupported(_)) => {}
+            Err(err) => return Err(err),
+        }
+        let mut checks = Vec::with_capacity(self.frames.len());
+        for sent in &self.frames {
+            if tx.transmit_with(sent, send_type).is_err() {
+                checks.push(FrameCheck { sent: *sent, received: None, send_failed: true });
+                continue;
+            }
+            let until = Instant::now() + self.timeout;
+            let mut received = None;
+            // Other traffic on the bus is skipped; the first frame with the ID is the echo.
+            while let Some(wait) = until.checked_duration_since(Instant::now()) {
+                let frames = rx.receive(wait)?;
+                if frames.is_empty() {
+                    break;
+                }
+                if let Some(frame) = frames.into_iter().find(|frame| frame.id() == sent.id()) {
+                    received = Some(frame);
+                    break;
+                }
+            }
+            checks.push(FrameCheck { sent: *sent, received, send_failed: false });
+        }
+        Ok(checks)
+    }
+}
+
+fn same_frame(a: &Frame, b: &Frame) -> bool {
+    a.id() == b.id() && a.is_remote() == b.is_remote() && a.dlc() == b.dlc() && a.data() == b.data()
+}
+
+/// `0x123#0102` or `0x321#R4`.
+fn spec(frame: &Frame) -> String {
+    match frame.is_remote() {
+        true => format!("{}#R{}", frame.id(), frame.dlc()),
+        false => format!("{}#{}", frame.id(), frame.data().iter().map(|byte| format!("{byte:02X}")).collect::<String>()),
+    }
+}
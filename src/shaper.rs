@@ -0,0 +1,335 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::busload::frame_bits;
+use crate::ffi::VciCanObj;
+use crate::frame::Frame;
+
+/// Frames per channel queued for its TX worker by default.
+pub const TX_QUEUE_LIMIT: usize = 1000;
+
+/// How much sending a bucket lets through at once after a quiet spell, as time at its rate.
+const BURST: Duration = Duration::from_millis(10);
+
+/// Longest frame on the wire: extended ID, 8 bytes, worst-case stuffing.
+const MAX_FRAME_BITS: f64 = 160.0;
+
+/// Limits on what a channel may transmit, and the queue in front of its TX worker, see
+/// [`Channel::set_tx_shaping`](crate::Channel::set_tx_shaping). Limits left `None` don't apply.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TxShaping {
+    pub frames_per_sec: Option<f64>,
+    /// Share of the bus our own frames may take, in percent, counted with
+    /// [`frame_bits`](crate::frame_bits). Needs the channel's bitrate.
+    pub bus_load_percent: Option<f64>,
+    /// Frames waiting for the TX worker, whether over a limit or not sent yet. Once it is full,
+    /// transmitting waits for room.
+    pub queue: usize,
+    /// Refuse frames with [`CanError::TxQueueFull`](crate::CanError::TxQueueFull) when the
+    /// queue is full, instead of waiting.
+    pub drop_on_full: bool,
+}
+
+impl Default for TxShaping {
+    fn default() -> Self {
+        Self { frames_per_sec: None, bus_load_percent: None, queue: TX_QUEUE_LIMIT, drop_on_full: false }
+    }
+}
+
+/// Tokens flowing in at `rate` per second, up to `capacity`; taking one spends it. Fed the
+/// current time by the caller, so it can be driven by a simulated clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub fn new(rate: f64, capacity: f64, now: Instant) -> Self {
+        Self { rate, capacity, tokens: capacity, updated: now }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Tokens available at `now`. Time going backwards adds none.
+    pub fn tokens(&mut self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = self.updated.max(now);
+        self.tokens
+    }
+
+    /// Takes `cost` tokens if they are there.
+    pub fn try_take(&mut self, cost: f64, now: Instant) -> bool {
+        if self.tokens(now) < cost.min(self.capacity) {
+            return false;
+        }
+        self.tokens -= cost;
+        true
+    }
+
+    /// How long until `cost` tokens are there; zero if they are now. A cost above the capacity
+    /// only waits for a full bucket, and then takes it below zero.
+    pub fn time_until(&mut self, cost: f64, now: Instant) -> Duration {
+        let missing = cost.min(self.capacity) - self.tokens(now);
+        if missing <= 0.0 || self.rate <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::try_from_secs_f64(missing / self.rate).unwrap_or(Duration::MAX)
+    }
+}
+
+/// What a channel's transmit queue has done so far; see
+/// [`Channel::shaper_stats`](crate::Channel::shaper_stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShaperStats {
+    /// Frames the TX worker sent as soon as it got to them, within the limits.
+    pub passed: u64,
+    /// Frames a limit held back before they went out.
+    pub delayed: u64,
+    /// Frames refused with a full queue, or that failed to send from it.
+    pub dropped: u64,
+    /// Of the dropped frames, the ones refused because the queue was full.
+    pub refused: u64,
+    /// Frames waiting right now.
+    pub queued: usize,
+}
+
+/// The buckets and queue in front of one channel's `VCI_Transmit`.
+pub(crate) struct Shaper {
+    pub(crate) config: TxShaping,
+    frames: Option<TokenBucket>,
+    bits: Option<TokenBucket>,
+    pub(crate) queue: VecDeque<VciCanObj>,
+    /// Frames the TX worker took from the queue and is handing to the DLL.
+    pub(crate) sending: usize,
+    /// Set while a limit keeps the front of the queue waiting.
+    pub(crate) held_back: bool,
+    pub(crate) stats: ShaperStats,
+}
+
+impl Default for Shaper {
+    fn default() -> Self {
+        Self::new(TxShaping::default(), None, Instant::now())
+    }
+}
+
+impl Shaper {
+    /// `bps` is the channel's bitrate, needed for a bus load limit.
+    pub(crate) fn new(config: TxShaping, bps: Option<u32>, now: Instant) -> Self {
+        let bucket = |rate: f64, smallest: f64| TokenBucket::new(rate, (rate * BURST.as_secs_f64()).max(smallest), now);
+        let frames = config.frames_per_sec.map(|rate| bucket(rate, 1.0));
+        let bits = config
+            .bus_load_percent
+            .zip(bps)
+            .map(|(percent, bps)| bucket(f64::from(bps) * percent / 100.0, MAX_FRAME_BITS));
+        Self { config, frames, bits, queue: VecDeque::new(), sending: 0, held_back: false, stats: ShaperStats::default() }
+    }
+
+    /// Switches to `config` with full buckets, keeping what is queued and the stats.
+    pub(crate) fn reconfigure(&mut self, config: TxShaping, bps: Option<u32>, now: Instant) {
+        let Shaper { frames, bits, .. } = Shaper::new(config, bps, now);
+        (self.config, self.frames, self.bits) = (config, frames, bits);
+    }
+
+    /// Spends the tokens for `frame` if every limit has them.
+    pub(crate) fn admit(&mut self, frame: &Frame, now: Instant) -> bool {
+        let bits = f64::from(frame_bits(frame));
+        let frames_ok = self.frames.as_mut().is_none_or(|bucket| bucket.tokens(now) >= 1.0);
+        let bits_ok = self.bits.as_mut().is_none_or(|bucket| bucket.time_until(bits, now).is_zero());
+        if !(frames_ok && bits_ok) {
+            return false;
+        }
+        if let Some(bucket) = &mut self.frames {
+            bucket.try_take(1.0, now);
+        }
+        if let Some(bucket) = &mut self.bits {
+            bucket.try_take(bits, now);
+        }
+        true
+    }
+
+    /// How long until `frame` is within every limit.
+    pub(crate) fn wait(&mut self, frame: &Frame, now: Instant) -> Duration {
+        let frames = self.frames.as_mut().map_or(Duration::ZERO, |bucket| bucket.time_until(1.0, now));
+        let bits = self.bits.as_mut().map_or(Duration::ZERO, |bucket| bucket.time_until(f64::from(frame_bits(frame)), now));
+        frames.max(bits)
+    }
+
+    pub(crate) fn stats(&self) -> ShaperStats {
+        ShaperStats { queued: self.queue.len(), ..self.stats }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::Id;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn std_frame(len: usize) -> Frame {
+        Frame::new(Id::Standard(0x123), &[0; 8][..len]).unwrap()
+    }
+
+    fn ext_frame(len: usize) -> Frame {
+        Frame::new(Id::Extended(0x18DA_F110), &[0; 8][..len]).unwrap()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    /// Within a microsecond, for waits that went through floating point.
+    fn about(wait: Duration, expected: Duration) -> bool {
+        wait.abs_diff(expected) < Duration::from_micros(1)
+    }
+
+    #[test]
+    fn buckets_refill_at_their_rate_up_to_their_capacity() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(100.0, 5.0, start);
+        assert!(close(bucket.tokens(start), 5.0), "starts full");
+        for _ in 0..5 {
+            assert!(bucket.try_take(1.0, start));
+        }
+        assert!(!bucket.try_take(1.0, start));
+        assert!(close(bucket.tokens(start + 5 * MS), 0.5));
+        assert!(!bucket.try_take(1.0, start + 5 * MS));
+        assert!(bucket.try_take(1.0, start + 10 * MS));
+        assert!(close(bucket.tokens(start + 10 * MS), 0.0));
+        assert!(close(bucket.tokens(start + Duration::from_secs(60)), 5.0), "capped after a quiet spell");
+    }
+
+    #[test]
+    fn time_going_backwards_adds_nothing() {
+        let start = Instant::now() + Duration::from_secs(1);
+        let mut bucket = TokenBucket::new(1000.0, 2.0, start);
+        assert!(bucket.try_take(2.0, start));
+        assert!(close(bucket.tokens(start - 500 * MS), 0.0));
+        // Nor does coming back to the present count the same span twice.
+        assert!(close(bucket.tokens(start + MS), 1.0));
+        assert!(close(bucket.tokens(start), 1.0));
+        assert!(close(bucket.tokens(start + MS), 1.0));
+    }
+
+    #[test]
+    fn waits_are_what_is_missing_at_the_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(50.0, 1.0, start);
+        assert_eq!(bucket.time_until(1.0, start), Duration::ZERO);
+        assert!(bucket.try_take(1.0, start));
+        assert!(about(bucket.time_until(1.0, start), 20 * MS));
+        assert!(about(bucket.time_until(1.0, start + 15 * MS), 5 * MS));
+        assert_eq!(bucket.time_until(1.0, start + 20 * MS), Duration::ZERO);
+
+        // A cost above the capacity waits for a full bucket, then takes it below zero.
+        let mut bucket = TokenBucket::new(1000.0, 4.0, start);
+        assert_eq!(bucket.time_until(10.0, start), Duration::ZERO);
+        assert!(bucket.try_take(10.0, start));
+        assert!(close(bucket.tokens(start), -6.0));
+        assert!(about(bucket.time_until(10.0, start), 10 * MS));
+        assert!(!bucket.try_take(10.0, start + 9 * MS));
+        assert!(bucket.try_take(10.0, start + 10 * MS));
+
+        // No rate, no waiting on it.
+        let mut empty = TokenBucket::new(0.0, 1.0, start);
+        assert!(empty.try_take(1.0, start));
+        assert_eq!(empty.time_until(1.0, start + Duration::from_secs(1)), Duration::ZERO);
+        assert!(!empty.try_take(1.0, start + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn a_frame_rate_lets_a_burst_through_then_paces() {
+        let start = Instant::now();
+        let shaping = TxShaping { frames_per_sec: Some(1000.0), ..TxShaping::default() };
+        let mut shaper = Shaper::new(shaping, None, start);
+        let frame = std_frame(8);
+        // 10 ms worth at once, then one a millisecond.
+        assert_eq!((0..20).filter(|_| shaper.admit(&frame, start)).count(), 10);
+        assert!(about(shaper.wait(&frame, start), MS));
+        let mut now = start;
+        for _ in 0..100 {
+            now += MS;
+            assert!(shaper.admit(&frame, now));
+            assert!(!shaper.admit(&frame, now));
+        }
+
+        // Below 100 frames a second the burst is still one frame.
+        let shaping = TxShaping { frames_per_sec: Some(50.0), ..TxShaping::default() };
+        let mut shaper = Shaper::new(shaping, None, start);
+        assert!(shaper.admit(&frame, start));
+        assert!(about(shaper.wait(&frame, start + 5 * MS), 15 * MS));
+        assert!(!shaper.admit(&frame, start + 19 * MS));
+        assert!(shaper.admit(&frame, start + 20 * MS));
+    }
+
+    #[test]
+    fn a_bus_load_limit_counts_bits() {
+        let start = Instant::now();
+        // 10 % of 500 kbit/s, 50 bits a millisecond, with a 500-bit burst: three 135-bit frames.
+        let shaping = TxShaping { bus_load_percent: Some(10.0), ..TxShaping::default() };
+        let mut shaper = Shaper::new(shaping, Some(500_000), start);
+        let (long, short) = (std_frame(8), std_frame(0));
+        assert_eq!((0..10).filter(|_| shaper.admit(&long, start)).count(), 3);
+        assert!(about(shaper.wait(&long, start), Duration::from_micros(800)), "40 bits missing");
+        assert!(shaper.admit(&short, start), "55 bits still fit");
+        assert!(!shaper.admit(&short, start));
+        assert!(shaper.admit(&long, start + Duration::from_micros(2700)));
+
+        // However low the limit, the longest frame fits in the burst.
+        let shaping = TxShaping { bus_load_percent: Some(1.0), ..TxShaping::default() };
+        let mut shaper = Shaper::new(shaping, Some(125_000), start);
+        assert!(shaper.admit(&ext_frame(8), start));
+        assert!(!shaper.admit(&std_frame(0), start));
+        assert!(about(shaper.wait(&ext_frame(8), start), 128 * MS), "160 bits at 1250 bit/s");
+
+        // Without a bitrate there is nothing to count against.
+        let mut shaper = Shaper::new(shaping, None, start);
+        assert!((0..1000).all(|_| shaper.admit(&long, start)));
+    }
+
+    #[test]
+    fn a_refused_frame_spends_no_tokens() {
+        let start = Instant::now();
+        let shaping = TxShaping { frames_per_sec: Some(1000.0), bus_load_percent: Some(10.0), ..TxShaping::default() };
+        let mut shaper = Shaper::new(shaping, Some(500_000), start);
+        let frame = std_frame(8);
+        assert_eq!((0..10).filter(|_| shaper.admit(&frame, start)).count(), 3, "the bits run out first");
+        assert!(close(shaper.frames.as_mut().unwrap().tokens(start), 7.0));
+        assert!(close(shaper.bits.as_mut().unwrap().tokens(start), 95.0));
+
+        // The longer wait of the two decides.
+        assert!(about(shaper.wait(&frame, start), Duration::from_micros(800)));
+        assert!(shaper.admit(&frame, start + Duration::from_micros(800)));
+
+        // And with the frames running out first, the bits are kept.
+        let shaping = TxShaping { frames_per_sec: Some(100.0), bus_load_percent: Some(50.0), ..TxShaping::default() };
+        let mut shaper = Shaper::new(shaping, Some(1_000_000), start);
+        assert!(shaper.admit(&frame, start));
+        assert!(!shaper.admit(&frame, start));
+        assert!(close(shaper.bits.as_mut().unwrap().tokens(start), 5000.0 - 135.0));
+        assert!(about(shaper.wait(&frame, start), 10 * MS));
+    }
+
+    #[test]
+    fn stats_count_the_queue_as_it_is() {
+        let mut shaper = Shaper::new(TxShaping { queue: 4, ..TxShaping::default() }, None, Instant::now());
+        shaper.stats.passed = 3;
+        shaper.queue.push_back(VciCanObj::default());
+        assert_eq!(shaper.stats(), ShaperStats { passed: 3, queued: 1, ..ShaperStats::default() });
+        assert!(shaper.admit(&std_frame(8), Instant::now()), "no limits");
+
+        // New limits start with full buckets and keep the queue and stats.
+        let now = Instant::now();
+        shaper.reconfigure(TxShaping { frames_per_sec: Some(1.0), ..TxShaping::default() }, None, now);
+        assert_eq!(shaper.stats(), ShaperStats { passed: 3, queued: 1, ..ShaperStats::default() });
+        assert!(shaper.admit(&std_frame(8), now));
+        assert!(!shaper.admit(&std_frame(8), now));
+    }
+}
@@ -0,0 +1,21 @@
+use std::io;
+
+use crate::frame::Frame;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Rx,
+    Tx,
+}
+
+/// Destination for captured frames, implemented by every log format.
+pub trait FrameSink: Send {
+    fn write_frame(&mut self, channel: u32, frame: &Frame, direction: Direction) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()>;
+
+    /// Writes any trailer the format requires and flushes. Called once on shutdown.
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
@@ -0,0 +1,661 @@
+#[cfg(unix)]
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::TryRecvError;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::bitrate::Bitrate;
+use crate::device::Channel;
+use crate::fanout::Subscription;
+use crate::ffi::VciInitConfig;
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::mode::ChannelMode;
+use crate::timestamp::host_time;
+
+/// Port the TCP bridge listens on by default; slcan over TCP has no standard one.
+pub const SLCAN_PORT: u16 = 3333;
+
+/// How long a read waits before the bridge forwards received frames and checks for stop.
+const POLL: Duration = Duration::from_millis(10);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+const QUEUE: usize = 4096;
+/// Longest line accepted (an extended frame with 8 bytes is 27 characters).
+const MAX_LINE: usize = 64;
+
+const OK: &str = "\r";
+const ERROR: &str = "\x07";
+
+/// One slcan (LAWICEL) command, without its trailing carriage return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlcanCommand {
+    /// `tIIILDD..`, `TIIIIIIIILDD..`, `rIIIL` or `RIIIIIIIIL`.
+    Transmit(Frame),
+    /// `S0`-`S8`, or `sXXYY` with raw BTR0/BTR1 values.
+    Bitrate(Bitrate),
+    /// `O`, or `L` for listen-only.
+    Open { listen_only: bool },
+    Close,
+    /// `MXXXXXXXX` and `mXXXXXXXX`, in SJA1000 register layout like the VCI's own filter.
+    AcceptanceCode(u32),
+    AcceptanceMask(u32),
+    /// `Z0` or `Z1`: append a millisecond timestamp to received frames.
+    Timestamps(bool),
+    Version,
+    Serial,
+    Status,
+}
+
+/// Bitrate for `S0` to `S8`.
+pub fn slcan_bitrate(code: u8) -> Option<Bitrate> {
+    Some(match code {
+        b'0' => Bitrate::Kbps10,
+        b'1' => Bitrate::Kbps20,
+        b'2' => Bitrate::Kbps50,
+        b'3' => Bitrate::Kbps100,
+        b'4' => Bitrate::Kbps125,
+        b'5' => Bitrate::Kbps250,
+        b'6' => Bitrate::Kbps500,
+        b'7' => Bitrate::Kbps800,
+        b'8' => Bitrate::Mbps1,
+        _ => return None,
+    })
+}
+
+/// Hex digits only; `from_str_radix` alone would also take a sign.
+fn hex(s: &str) -> Option<u32> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(s, 16).ok()
+}
+
+impl SlcanCommand {
+    /// `None` for unknown or malformed commands.
+    pub fn parse(line: &str) -> Option<Self> {
+        let (kind, rest) = (line.bytes().next()?, line.get(1..)?);
+        Some(match (kind, rest) {
+            (b't' | b'T' | b'r' | b'R', _) => {
+                let id_len = if kind.is_ascii_uppercase() { 8 } else { 3 };
+                let id = hex(rest.get(..id_len)?)?;
+                let dlc = u8::try_from(hex(rest.get(id_len..id_len + 1)?)?).ok().filter(|&dlc| dlc <= 8)?;
+                let id = if id_len == 8 { Id::extended(id)? } else { Id::standard(id as u16)? };
+                let data = &rest[id_len + 1..];
+                let frame = if kind == b'r' || kind == b'R' {
+                    if !data.is_empty() {
+                        return None;
+                    }
+                    Frame::remote(id, dlc)?
+                } else {
+                    if data.len() != usize::from(dlc) * 2 {
+                        return None;
+                    }
+                    let bytes: Option<Vec<u8>> = (0..data.len()).step_by(2).map(|i| hex(data.get(i..i + 2)?).map(|b| b as u8)).collect();
+                    Frame::new(id, &bytes?)?
+                };
+                Self::Transmit(frame)
+            }
+            (b'S', code) if code.len() == 1 => Self::Bitrate(slcan_bitrate(code.as_bytes()[0])?),
+            (b's', btr) if btr.len() == 4 => {
+                let btr = hex(btr)?;
+                Self::Bitrate(Bitrate::from_timing((btr >> 8) as u8, btr as u8))
+            }
+            (b'O', "") => Self::Open { listen_only: false },
+            (b'L', "") => Self::Open { listen_only: true },
+            (b'C', "") => Self::Close,
+            (b'M', code) if code.len() == 8 => Self::AcceptanceCode(hex(code)?),
+            (b'm', mask) if mask.len() == 8 => Self::AcceptanceMask(hex(mask)?),
+            (b'Z', "0") => Self::Timestamps(false),
+            (b'Z', "1") => Self::Timestamps(true),
+            (b'V', "") => Self::Version,
+            (b'N', "") => Self::Serial,
+            (b'F', "") => Self::Status,
+            _ => return None,
+        })
+    }
+}
+
+/// `t1232AABB\r`, `T1234567880102030405060708\r` or `r1230\r`, with the timestamp as four more
+/// hex digits before the `\r` when given. Timestamps are milliseconds and wrap at 60000.
+pub fn format_slcan(frame: &Frame, timestamp: Option<u16>) -> String {
+    let kind = match (frame.is_remote(), frame.is_extended()) {
+        (false, false) => 't',
+        (false, true) => 'T',
+        (true, false) => 'r',
+        (true, true) => 'R',
+    };
+    let mut line = match frame.id() {
+        Id::Standard(id) => format!("{kind}{id:03X}{}", frame.dlc()),
+        Id::Extended(id) => format!("{kind}{id:08X}{}", frame.dlc()),
+    };
+    for byte in frame.data() {
+        line.push_str(&format!("{byte:02X}"));
+    }
+    if let Some(timestamp) = timestamp {
+        line.push_str(&format!("{:04X}", timestamp % 60000));
+    }
+    line.push('\r');
+    line
+}
+
+/// Protocol state for one slcan connection to a channel.
+///
+/// `S`/`s`, `M`/`m` and `L` take effect on the next open, which re-initializes the channel with
+/// them; a plain `O` uses the channel as it is. `C` only stops forwarding: the channel keeps
+/// running for whatever else uses it. Commands reply `\r` (or `z\r`/`Z\r` for transmits) on
+/// success and BEL on error, e.g. `S6` `\r`, `O` `\r`, `t1232AABB` `z\r`, `O` again BEL.
+pub struct SlcanSession {
+    channel: Channel,
+    config: Option<VciInitConfig>,
+    subscription: Option<Subscription>,
+    listen_only: bool,
+    timestamps: bool,
+}
+
+impl SlcanSession {
+    pub fn new(channel: Channel) -> Self {
+        Self { channel, config: None, subscription: None, listen_only: false, timestamps: false }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.subscription.is_some()
+    }
+
+    /// Runs one command line (without `\r`) and returns the reply.
+    pub fn handle(&mut self, line: &str) -> String {
+        match self.command(line) {
+            Ok(reply) => reply.to_string(),
+            Err(()) => ERROR.to_string(),
+        }
+    }
+
+    fn pending_config(&mut self) -> &mut VciInitConfig {
+        let current = self.channel.config();
+        self.config.get_or_insert_with(|| current.unwrap_or_else(|| VciInitConfig::with_bitrate(Bitrate::Kbps500)))
+    }
+
+    fn command(&mut self, line: &str) -> Result<&'static str, ()> {
+        let command = SlcanCommand::parse(line).ok_or(())?;
+        let open = self.is_open();
+        match command {
+            SlcanCommand::Transmit(frame) if open && !self.listen_only => {
+                self.channel.transmit(&frame).map_err(|_| ())?;
+                Ok(if frame.is_extended() { "Z\r" } else { "z\r" })
+            }
+            SlcanCommand::Transmit(_) => Err(()),
+            // Like the LAWICEL adapters, settings only change while closed.
+            _ if open && !matches!(command, SlcanCommand::Close | SlcanCommand::Version | SlcanCommand::Serial | SlcanCommand::Status) => {
+                Err(())
+            }
+            SlcanCommand::Bitrate(bitrate) => {
+                let (timing0, timing1) = bitrate.timing();
+                let config = self.pending_config();
+                (config.timing0, config.timing1) = (timing0, timing1);
+                Ok(OK)
+            }
+            SlcanCommand::AcceptanceCode(code) => {
+                self.pending_config().acc_code = code;
+                Ok(OK)
+            }
+            SlcanCommand::AcceptanceMask(mask) => {
+                self.pending_config().acc_mask = mask;
+                Ok(OK)
+            }
+            SlcanCommand::Timestamps(on) => {
+                self.timestamps = on;
+                Ok(OK)
+            }
+            SlcanCommand::Open { listen_only } => {
+                if listen_only {
+                    let config = *self.pending_config();
+                    self.config = Some(config.with_mode(ChannelMode::ListenOnly));
+                } else if self.listen_only {
+                    // Back to normal mode after an `L`, which left the channel listening only.
+                    self.pending_config();
+                }
+                if let Some(config) = self.config.take() {
+                    let config = if listen_only { config } else { config.with_mode(ChannelMode::Normal) };
+                    self.channel.reconfigure(&config).map_err(|_| ())?;
+                }
+                self.listen_only = listen_only;
+                self.subscription = Some(self.channel.subscribe(QUEUE));
+                Ok(OK)
+            }
+            SlcanCommand::Close if open => {
+                self.subscription = None;
+                Ok(OK)
+            }
+            SlcanCommand::Close => Err(()),
+            SlcanCommand::Version => Ok("V1013\r"),
+            SlcanCommand::Serial => Ok("NCA2I\r"),
+            SlcanCommand::Status => Ok("F00\r"),
+        }
+    }
+
+    /// Received frames waiting to go out, encoded.
+    pub fn pending_frames(&mut self) -> String {
+        let mut out = String::new();
+        let Some(subscription) = &self.subscription else {
+            return out;
+        };
+        loop {
+            match subscription.try_recv() {
+                Ok(frame) => {
+                    let timestamp = self.timestamps.then(|| {
+                        (host_time(&frame).duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() % 60000) as u16
+                    });
+                    out.push_str(&format_slcan(&frame, timestamp));
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.subscription = None;
+                    break;
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Runs a session over one connection until it closes or `stop` is set. Reads must time out
+/// (with `WouldBlock` or `TimedOut`) regularly so received frames get forwarded.
+fn serve(mut port: impl Read + Write, channel: &Channel, stop: &AtomicBool) -> io::Result<()> {
+    let mut session = SlcanSession::new(channel.clone());
+    let (mut line, mut chunk) = (Vec::new(), [0; 256]);
+    while !stop.load(Ordering::SeqCst) {
+        let mut replies = String::new();
+        match port.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => {
+                for &byte in &chunk[..n] {
+                    match byte {
+                        b'\r' => {
+                            replies.push_str(&session.handle(&String::from_utf8_lossy(&line)));
+                            line.clear();
+                        }
+                        // slcand and some terminals send `\r\n`.
+                        b'\n' => {}
+                        _ if line.len() < MAX_LINE => line.push(byte),
+                        _ => {}
+                    }
+                }
+            }
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => {}
+            Err(err) => return Err(err),
+        }
+        replies.push_str(&session.pending_frames());
+        if !replies.is_empty() {
+            port.write_all(replies.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Exposes one channel through the slcan ASCII protocol, over TCP or (on Unix) a
+/// pseudo-terminal that `slcand` can attach to. One client at a time.
+pub struct SlcanBridge {
+    local_addr: Option<SocketAddr>,
+    pty: Option<PathBuf>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SlcanBridge {
+    /// Listens on `addr`; further clients wait until the current one disconnects.
+    pub fn listen(addr: impl ToSocketAddrs, channel: &Channel) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        let (stop, channel) = (Arc::new(AtomicBool::new(false)), channel.clone());
+        let worker_stop = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            while !worker_stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let _ = serve_tcp(stream, &channel, &worker_stop);
+                    }
+                    // WouldBlock while nobody connects.
+                    Err(_) => thread::sleep(POLL * 10),
+                }
+            }
+        });
+        Ok(Self { local_addr: Some(local_addr), pty: None, stop, thread: Some(thread) })
+    }
+
+    /// Creates a pseudo-terminal; attach to it with e.g. `slcand -o -s6 /dev/pts/3 slcan0`.
+    /// Survives the other side closing and reopening it.
+    #[cfg(unix)]
+    pub fn pty(channel: &Channel) -> io::Result<Self> {
+        let (master, path) = open_pty()?;
+        let (stop, channel) = (Arc::new(AtomicBool::new(false)), channel.clone());
+        let worker_stop = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            while !worker_stop.load(Ordering::SeqCst) {
+                let Ok(master) = master.try_clone() else {
+                    break;
+                };
+                // Every time the terminal is opened starts a fresh session.
+                if serve(Pty(master), &channel, &worker_stop).is_err() {
+                    thread::sleep(POLL * 10);
+                }
+            }
+        });
+        Ok(Self { local_addr: None, pty: Some(path), stop, thread: Some(thread) })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// The terminal device clients open.
+    pub fn pty_path(&self) -> Option<&Path> {
+        self.pty.as_deref()
+    }
+
+    /// Also done on drop.
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for SlcanBridge {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn serve_tcp(stream: TcpStream, channel: &Channel, stop: &AtomicBool) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(POLL))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    serve(stream, channel, stop)
+}
+
+/// The master side of a pseudo-terminal, made to time out like a socket. Reads fail with EIO
+/// while no one has the terminal open, which ends the session like a closed connection.
+#[cfg(unix)]
+struct Pty(File);
+
+#[cfg(unix)]
+impl Read for Pty {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(POLL);
+                Err(io::Error::from(ErrorKind::TimedOut))
+            }
+            Err(err) if err.raw_os_error() == Some(libc::EIO) => {
+                thread::sleep(POLL);
+                Ok(0)
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Write for Pty {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Opens a non-blocking pseudo-terminal master in raw mode and returns it with the path of
+/// its slave side.
+#[cfg(unix)]
+fn open_pty() -> io::Result<(File, PathBuf)> {
+    use std::ffi::CStr;
+    use std::os::fd::FromRawFd;
+
+    unsafe {
+        let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // Owned from here on, so the descriptor is closed on every error path below.
+        let master = File::from_raw_fd(fd);
+        if libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut termios = std::mem::zeroed::<libc::termios>();
+        if libc::tcgetattr(fd, &mut termios) == 0 {
+            libc::cfmakeraw(&mut termios);
+            libc::tcsetattr(fd, libc::TCSANOW, &termios);
+        }
+        let name = libc::ptsname(fd);
+        if name.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let path = PathBuf::from(CStr::from_ptr(name).to_string_lossy().into_owned());
+        Ok((master, path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::started_pair;
+
+    fn frame(id: Id, data: &[u8]) -> Frame {
+        Frame::new(id, data).unwrap()
+    }
+
+    #[test]
+    fn parses_commands() {
+        let cases = [
+            ("t1232AABB", Some(SlcanCommand::Transmit(frame(Id::Standard(0x123), &[0xAA, 0xBB])))),
+            ("t7FF0", Some(SlcanCommand::Transmit(frame(Id::Standard(0x7FF), &[])))),
+            ("t0008a1b2c3d4e5f60718", Some(SlcanCommand::Transmit(frame(Id::Standard(0), &[0xA1, 0xB2, 0xC3, 0xD4, 0xE5, 0xF6, 0x07, 0x18])))),
+            ("T1234567880102030405060708", Some(SlcanCommand::Transmit(frame(Id::Extended(0x1234_5678), &[1, 2, 3, 4, 5, 6, 7, 8])))),
+            ("T0000012310A", Some(SlcanCommand::Transmit(frame(Id::Extended(0x123), &[0x0A])))),
+            ("r7DF8", Some(SlcanCommand::Transmit(Frame::remote(Id::Standard(0x7DF), 8).unwrap()))),
+            ("R1FFFFFFF0", Some(SlcanCommand::Transmit(Frame::remote(Id::Extended(0x1FFF_FFFF), 0).unwrap()))),
+            ("S0", Some(SlcanCommand::Bitrate(Bitrate::Kbps10))),
+            ("S6", Some(SlcanCommand::Bitrate(Bitrate::Kbps500))),
+            ("S8", Some(SlcanCommand::Bitrate(Bitrate::Mbps1))),
+            ("s001C", Some(SlcanCommand::Bitrate(Bitrate::Kbps500))),
+            ("s0314", Some(SlcanCommand::Bitrate(Bitrate::Custom { timing0: 0x03, timing1: 0x14 }))),
+            ("O", Some(SlcanCommand::Open { listen_only: false })),
+            ("L", Some(SlcanCommand::Open { listen_only: true })),
+            ("C", Some(SlcanCommand::Close)),
+            ("M00000000", Some(SlcanCommand::AcceptanceCode(0))),
+            ("mFFFFFFFF", Some(SlcanCommand::AcceptanceMask(0xFFFF_FFFF))),
+            ("Z1", Some(SlcanCommand::Timestamps(true))),
+            ("Z0", Some(SlcanCommand::Timestamps(false))),
+            ("V", Some(SlcanCommand::Version)),
+            ("N", Some(SlcanCommand::Serial)),
+            ("F", Some(SlcanCommand::Status)),
+            // Malformed: wrong lengths, bad digits, out-of-range values, trailing characters.
+            ("", None),
+            ("t123", None),
+            ("t1232AA", None),
+            ("t1232AABBCC", None),
+            ("t1239000000000000000000", None),
+            ("t8000", None),
+            ("t12G0", None),
+            ("t1231+A", None),
+            ("T2000000000", None),
+            ("T1230", None),
+            ("r1230AA", None),
+            ("r1239", None),
+            ("S9", None),
+            ("S", None),
+            ("S66", None),
+            ("s01C", None),
+            ("O1", None),
+            ("M0000", None),
+            ("Z2", None),
+            ("X", None),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(SlcanCommand::parse(line), expected, "{line:?}");
+        }
+    }
+
+    #[test]
+    fn formats_frames() {
+        let cases = [
+            (frame(Id::Standard(0x123), &[0xAA, 0xBB]), None, "t1232AABB\r"),
+            (frame(Id::Standard(0x7), &[]), None, "t0070\r"),
+            (frame(Id::Extended(0x1234_5678), &[1, 2, 3, 4, 5, 6, 7, 8]), None, "T1234567880102030405060708\r"),
+            (frame(Id::Extended(0x123), &[0x0A]), None, "T0000012310A\r"),
+            (Frame::remote(Id::Standard(0x7DF), 8).unwrap(), None, "r7DF8\r"),
+            (Frame::remote(Id::Extended(0x1FFF_FFFF), 2).unwrap(), None, "R1FFFFFFF2\r"),
+            (frame(Id::Standard(0x123), &[0xAA]), Some(0), "t1231AA0000\r"),
+            (frame(Id::Standard(0x123), &[]), Some(59_999), "t1230EA5F\r"),
+            (frame(Id::Extended(0x1), &[]), Some(60_001), "T0000000100001\r"),
+        ];
+        for (frame, timestamp, line) in cases {
+            assert_eq!(format_slcan(&frame, timestamp), line);
+            // Every line parses back to the frame it came from.
+            let without_timestamp = &line[..line.len() - 1 - timestamp.map_or(0, |_| 4)];
+            assert_eq!(SlcanCommand::parse(without_timestamp), Some(SlcanCommand::Transmit(frame)), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn a_session_answers_like_a_lawicel_adapter() {
+        let (mock, _device, can1, _can2) = started_pair();
+        let mut session = SlcanSession::new(can1.clone());
+        let conversation = [
+            ("V", "V1013\r"),
+            ("N", "NCA2I\r"),
+            ("F", "F00\r"),
+            ("t1230", ERROR),
+            ("C", ERROR),
+            ("S9", ERROR),
+            ("nonsense", ERROR),
+            ("S4", OK),
+            ("M00000000", OK),
+            ("mFFFFFFFF", OK),
+            ("Z0", OK),
+            ("O", OK),
+            ("O", ERROR),
+            ("S6", ERROR),
+            ("Z1", ERROR),
+            ("V", "V1013\r"),
+            ("t1232AABB", "z\r"),
+            ("T1234567880102030405060708", "Z\r"),
+            ("r7DF8", "z\r"),
+            ("R1FFFFFFF0", "Z\r"),
+            ("t12", ERROR),
+            ("C", OK),
+            ("C", ERROR),
+            ("t1230", ERROR),
+        ];
+        for (command, reply) in conversation {
+            assert_eq!(session.handle(command), reply, "{command:?}");
+        }
+        let sent: Vec<String> = mock.take_transmitted(0).iter().map(|frame| format_slcan(frame, None)).collect();
+        assert_eq!(sent, ["t1232AABB\r", "T1234567880102030405060708\r", "r7DF8\r", "R1FFFFFFF0\r"]);
+        let config = can1.config().unwrap();
+        assert_eq!((config.timing0, config.timing1), Bitrate::Kbps125.timing(), "S4 applied on open");
+        assert_eq!((config.acc_code, config.acc_mask, config.channel_mode()), (0, 0xFFFF_FFFF, Some(ChannelMode::Normal)));
+    }
+
+    /// Forwarded lines, polling until `count` have arrived.
+    fn forwarded(session: &mut SlcanSession, count: usize) -> Vec<String> {
+        let deadline = std::time::Instant::now() + Duration::from_secs(2);
+        let mut out = String::new();
+        while out.matches('\r').count() < count {
+            assert!(std::time::Instant::now() < deadline, "only got {out:?}");
+            out.push_str(&session.pending_frames());
+            thread::sleep(Duration::from_millis(1));
+        }
+        out.split_inclusive('\r').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn open_sessions_forward_received_frames() {
+        let (mock, _device, can1, _can2) = started_pair();
+        let mut session = SlcanSession::new(can1);
+        assert_eq!(session.pending_frames(), "", "closed");
+        assert_eq!(session.handle("O"), OK);
+        mock.inject(0, &frame(Id::Standard(0x123), &[0xAA, 0xBB]));
+        mock.inject(0, &Frame::remote(Id::Extended(0x18DA_F110), 3).unwrap());
+        assert_eq!(forwarded(&mut session, 2), ["t1232AABB\r", "R18DAF1103\r"]);
+
+        assert_eq!(session.handle("C"), OK);
+        mock.inject(0, &frame(Id::Standard(0x1), &[]));
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(session.pending_frames(), "", "closed again");
+
+        // With timestamps, four more hex digits of milliseconds below 60000.
+        assert_eq!(session.handle("Z1"), OK);
+        assert_eq!(session.handle("O"), OK);
+        mock.inject(0, &frame(Id::Extended(0x1), &[0x10]));
+        let lines = forwarded(&mut session, 1);
+        let timestamped = lines.last().unwrap();
+        assert!(timestamped.starts_with("T00000001110") && timestamped.len() == "T00000001110".len() + 5, "{timestamped:?}");
+        assert!(hex(&timestamped[12..16]).unwrap() < 60000);
+    }
+
+    #[test]
+    fn listen_only_sessions_refuse_transmits() {
+        let (mock, _device, can1, can2) = started_pair();
+        let mut session = SlcanSession::new(can1.clone());
+        for (command, reply) in [("S5", OK), ("L", OK), ("t1230", ERROR), ("T000001230", ERROR), ("C", OK)] {
+            assert_eq!(session.handle(command), reply, "{command:?}");
+        }
+        assert!(mock.take_transmitted(0).is_empty());
+        let config = can1.config().unwrap();
+        assert_eq!(((config.timing0, config.timing1), config.channel_mode()), (Bitrate::Kbps250.timing(), Some(ChannelMode::ListenOnly)));
+
+        // A plain `O` afterwards goes back to normal mode, with the settings kept.
+        assert_eq!(session.handle("O"), OK);
+        assert_eq!(session.handle("t1230"), "z\r");
+        let config = can1.config().unwrap();
+        assert_eq!(((config.timing0, config.timing1), config.channel_mode()), (Bitrate::Kbps250.timing(), Some(ChannelMode::Normal)));
+        assert_eq!(can2.config().unwrap().channel_mode(), Some(ChannelMode::Normal), "the other port is left alone");
+    }
+
+    #[test]
+    fn the_tcp_bridge_speaks_the_protocol() {
+        let (mock, _device, can1, _can2) = started_pair();
+        let mut bridge = SlcanBridge::listen("127.0.0.1:0", &can1).unwrap();
+        let mut client = TcpStream::connect(bridge.local_addr().unwrap()).unwrap();
+        client.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+        let read = |client: &mut TcpStream, expected: &str| {
+            let mut got = Vec::new();
+            while got.len() < expected.len() {
+                let mut chunk = [0; 256];
+                let n = client.read(&mut chunk).unwrap();
+                assert!(n > 0, "closed after {:?}", String::from_utf8_lossy(&got));
+                got.extend_from_slice(&chunk[..n]);
+            }
+            assert_eq!(String::from_utf8(got).unwrap(), expected);
+        };
+
+        // Commands may be split anywhere, and end in `\r` or `\r\n`.
+        client.write_all(b"V\rS6\r\nO").unwrap();
+        thread::sleep(Duration::from_millis(20));
+        client.write_all(b"\rt1232AABB\rT12345678").unwrap();
+        read(&mut client, "V1013\r\r\rz\r");
+        client.write_all(b"0\rbogus\r").unwrap();
+        read(&mut client, "Z\r\x07");
+        assert!(can1.flush_transmit(Duration::from_millis(500)));
+        assert_eq!(mock.take_transmitted(0), [frame(Id::Standard(0x123), &[0xAA, 0xBB]), frame(Id::Extended(0x1234_5678), &[])]);
+
+        mock.inject(0, &frame(Id::Standard(0x7FF), &[1, 2, 3]));
+        read(&mut client, "t7FF3010203\r");
+
+        // A line too long to be a command is cut short and fails instead of growing forever.
+        client.write_all(&[b'x'; 1000]).unwrap();
+        client.write_all(b"\rF\r").unwrap();
+        read(&mut client, "\x07F00\r");
+        bridge.stop();
+    }
+}
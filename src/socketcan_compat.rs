@@ -0,0 +1,168 @@
+//! Conversions between [`Frame`] and the `socketcan` crate's classic CAN frame types.
+
+use std::fmt;
+
+use socketcan::{CanDataFrame, CanFrame, CanRemoteFrame, EmbeddedFrame, ExtendedId, StandardId};
+
+use crate::frame::Frame;
+use crate::id::Id;
+
+/// A `socketcan` frame with no [`Frame`] equivalent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsupportedFrame {
+    /// Error frames are reported by the controller, not carried on the bus as frames.
+    Error,
+    /// A remote frame can't be a `CanDataFrame`.
+    Remote,
+}
+
+impl fmt::Display for UnsupportedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error frames have no CANalyst-II equivalent"),
+            Self::Remote => write!(f, "a remote frame can't be converted to a data frame"),
+        }
+    }
+}
+
+impl std::error::Error for UnsupportedFrame {}
+
+fn to_socketcan_id(id: Id) -> socketcan::Id {
+    match id {
+        Id::Standard(id) => StandardId::new(id).expect("frames only hold valid IDs").into(),
+        Id::Extended(id) => ExtendedId::new(id).expect("frames only hold valid IDs").into(),
+    }
+}
+
+fn from_socketcan_id(id: socketcan::Id) -> Id {
+    match id {
+        socketcan::Id::Standard(id) => Id::Standard(id.as_raw()),
+        socketcan::Id::Extended(id) => Id::Extended(id.as_raw()),
+    }
+}
+
+/// Builds our frame from any classic `socketcan` frame that isn't an error frame.
+fn from_embedded(frame: &impl EmbeddedFrame) -> Frame {
+    let id = from_socketcan_id(frame.id());
+    if frame.is_remote_frame() {
+        Frame::remote(id, frame.dlc() as u8).expect("classic frames request at most 8 bytes")
+    } else {
+        Frame::new(id, frame.data()).expect("classic frames carry at most 8 bytes")
+    }
+}
+
+impl From<Frame> for CanFrame {
+    fn from(frame: Frame) -> Self {
+        let id = to_socketcan_id(frame.id());
+        let converted = if frame.is_remote() {
+            CanFrame::new_remote(id, frame.dlc().into())
+        } else {
+            CanFrame::new(id, frame.data())
+        };
+        converted.expect("frame fits a classic CAN frame")
+    }
+}
+
+impl TryFrom<Frame> for CanDataFrame {
+    type Error = UnsupportedFrame;
+
+    fn try_from(frame: Frame) -> Result<Self, UnsupportedFrame> {
+        if frame.is_remote() {
+            return Err(UnsupportedFrame::Remote);
+        }
+        Ok(CanDataFrame::new(to_socketcan_id(frame.id()), frame.data()).expect("at most 8 bytes"))
+    }
+}
+
+impl TryFrom<CanFrame> for Frame {
+    type Error = UnsupportedFrame;
+
+    fn try_from(frame: CanFrame) -> Result<Self, UnsupportedFrame> {
+        match frame {
+            CanFrame::Data(frame) => Ok(from_embedded(&frame)),
+            CanFrame::Remote(frame) => Ok(from_embedded(&frame)),
+            CanFrame::Error(_) => Err(UnsupportedFrame::Error),
+        }
+    }
+}
+
+impl From<CanDataFrame> for Frame {
+    fn from(frame: CanDataFrame) -> Self {
+        from_embedded(&frame)
+    }
+}
+
+impl From<CanRemoteFrame> for Frame {
+    fn from(frame: CanRemoteFrame) -> Self {
+        from_embedded(&frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use socketcan::{CanErrorFrame, Frame as _};
+
+    use super::*;
+
+    fn frames() -> Vec<Frame> {
+        vec![
+            Frame::new(Id::Standard(0x123), &[1, 2, 3]).unwrap(),
+            Frame::new(Id::Standard(0x7FF), &[0xFF; 8]).unwrap(),
+            Frame::new(Id::Standard(0x000), &[]).unwrap(),
+            Frame::new(Id::Extended(0x18FF_50E5), &[0xAA; 8]).unwrap(),
+            Frame::new(Id::Extended(0x123), &[4]).unwrap(),
+            Frame::new(Id::Extended(0x1FFF_FFFF), &[]).unwrap(),
+            Frame::remote(Id::Standard(0x7DF), 8).unwrap(),
+            Frame::remote(Id::Standard(0x100), 0).unwrap(),
+            Frame::remote(Id::Extended(0x18DA_F110), 3).unwrap(),
+        ]
+    }
+
+    #[test]
+    fn frames_round_trip_through_can_frame() {
+        for frame in frames() {
+            let converted = CanFrame::from(frame);
+            assert_eq!(converted.is_extended(), frame.is_extended(), "{frame:?}");
+            assert_eq!(converted.is_remote_frame(), frame.is_remote(), "{frame:?}");
+            assert_eq!(converted.dlc(), usize::from(frame.dlc()), "{frame:?}");
+            assert_eq!(converted.raw_id(), frame.id().raw(), "{frame:?}");
+            if !frame.is_remote() {
+                assert_eq!(converted.data(), frame.data(), "{frame:?}");
+            }
+            assert_eq!(Frame::try_from(converted), Ok(frame));
+        }
+    }
+
+    #[test]
+    fn data_frames_round_trip_through_can_data_frame() {
+        for frame in frames().into_iter().filter(|frame| !frame.is_remote()) {
+            let converted = CanDataFrame::try_from(frame).unwrap();
+            assert_eq!((converted.is_extended(), converted.data()), (frame.is_extended(), frame.data()));
+            assert_eq!(Frame::from(converted), frame);
+        }
+        for frame in frames().into_iter().filter(Frame::is_remote) {
+            assert_eq!(CanDataFrame::try_from(frame), Err(UnsupportedFrame::Remote));
+            let CanFrame::Remote(remote) = CanFrame::from(frame) else { panic!("{frame:?} isn't a remote frame") };
+            assert_eq!(Frame::from(remote), frame);
+        }
+    }
+
+    #[test]
+    fn zero_length_frames_keep_their_length() {
+        let data = Frame::try_from(CanFrame::new(StandardId::ZERO, &[]).unwrap()).unwrap();
+        assert_eq!((data.dlc(), data.data(), data.is_remote()), (0, &[][..], false));
+        let remote = Frame::try_from(CanFrame::new_remote(ExtendedId::ZERO, 0).unwrap()).unwrap();
+        assert_eq!((remote.dlc(), remote.is_remote(), remote.id()), (0, true, Id::Extended(0)));
+        // A remote frame's DLC makes it across even though it carries no data.
+        let remote = Frame::try_from(CanFrame::new_remote(StandardId::MAX, 8).unwrap()).unwrap();
+        assert_eq!((remote.dlc(), remote.data()), (8, &[][..]));
+    }
+
+    #[test]
+    fn error_frames_are_refused() {
+        let error = CanFrame::from(CanErrorFrame::new_error(0x004, &[0, 0x10]).unwrap());
+        assert!(error.is_error_frame());
+        assert_eq!(Frame::try_from(error), Err(UnsupportedFrame::Error));
+        assert_eq!(UnsupportedFrame::Error.to_string(), "error frames have no CANalyst-II equivalent");
+    }
+}
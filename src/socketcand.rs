@@ -0,0 +1,237 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::device::Channel;
+use crate::fanout::Subscription;
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::timestamp::host_time;
+
+/// Port socketcand listens on by default.
+pub const SOCKETCAND_PORT: u16 = 29536;
+
+/// How often blocked reads and accepts look at the stop flag.
+const POLL: Duration = Duration::from_millis(100);
+/// A client that can't take a frame for this long is disconnected.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Frames queued per client before it counts as too slow.
+const CLIENT_QUEUE: usize = 4096;
+/// Longest command accepted; anything longer without a closing `>` is discarded.
+const MAX_COMMAND: usize = 1024;
+
+/// Serves the channels to socketcand clients (candump via socketcand, Kayak, python-can's
+/// `socketcand` interface), one bus per channel named `can0`, `can1`, ...
+///
+/// Implements the raw mode subset: `< open canX >`, `< rawmode >`, `< send ID DLC BYTES... >`,
+/// `< echo >`, and `< frame ID SECONDS.MICROS DATA >` for every received frame. Every client
+/// gets the whole stream of its bus from its own queue; a client that falls behind is
+/// disconnected instead of holding up reception or the other clients.
+pub struct SocketcandServer {
+    addr: SocketAddr,
+    clients: Arc<AtomicUsize>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SocketcandServer {
+    pub fn start(addr: impl ToSocketAddrs, channels: &[Channel]) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        let (clients, stop) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicBool::new(false)));
+        let (channels, worker_clients, worker_stop) = (channels.to_vec(), Arc::clone(&clients), Arc::clone(&stop));
+        let thread = thread::spawn(move || accept(&listener, &channels, &worker_clients, &worker_stop));
+        Ok(Self { addr, clients, stop, thread: Some(thread) })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Clients currently connected.
+    pub fn clients(&self) -> usize {
+        self.clients.load(Ordering::SeqCst)
+    }
+
+    /// Disconnects every client and stops listening. Also done on drop.
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for SocketcandServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn accept(listener: &TcpListener, channels: &[Channel], clients: &Arc<AtomicUsize>, stop: &Arc<AtomicBool>) {
+    let mut sessions: Vec<JoinHandle<()>> = Vec::new();
+    while !stop.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let (channels, clients, stop) = (channels.to_vec(), Arc::clone(clients), Arc::clone(stop));
+                clients.fetch_add(1, Ordering::SeqCst);
+                sessions.push(thread::spawn(move || {
+                    let _ = serve(stream, &channels, &stop);
+                    clients.fetch_sub(1, Ordering::SeqCst);
+                }));
+            }
+            // WouldBlock while nobody connects; other errors only concern the failed connection.
+            Err(_) => thread::sleep(POLL),
+        }
+        sessions.retain(|session| !session.is_finished());
+    }
+    for session in sessions {
+        let _ = session.join();
+    }
+}
+
+/// Writes one message; replies and frames share the stream, so each goes out whole.
+fn send_line(stream: &Mutex<TcpStream>, message: &str) -> io::Result<()> {
+    stream.lock().unwrap().write_all(message.as_bytes())
+}
+
+fn serve(stream: TcpStream, channels: &[Channel], stop: &Arc<AtomicBool>) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(POLL))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    let closed = Arc::new(AtomicBool::new(false));
+    let mut streamer = None;
+    let result = session(&stream, &writer, channels, stop, &closed, &mut streamer);
+    closed.store(true, Ordering::SeqCst);
+    let _ = stream.shutdown(Shutdown::Both);
+    if let Some(streamer) = streamer {
+        let _ = streamer.join();
+    }
+    result
+}
+
+fn session(
+    stream: &TcpStream,
+    writer: &Arc<Mutex<TcpStream>>,
+    channels: &[Channel],
+    stop: &AtomicBool,
+    closed: &Arc<AtomicBool>,
+    streamer: &mut Option<JoinHandle<()>>,
+) -> io::Result<()> {
+    let mut bus = None;
+    send_line(writer, "< hi >")?;
+    let (mut pending, mut chunk) = (Vec::new(), [0; 1024]);
+    while !stop.load(Ordering::SeqCst) && !closed.load(Ordering::SeqCst) {
+        match (&*stream).read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => pending.extend_from_slice(&chunk[..n]),
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(err) => return Err(err),
+        }
+        while let Some(command) = next_command(&mut pending) {
+            let words: Vec<&str> = command.split_whitespace().collect();
+            let reply = match (words.as_slice(), bus) {
+                (["open", name], None) => match bus_index(name, channels.len()) {
+                    Some(index) => {
+                        bus = Some(index);
+                        Some("< ok >".to_string())
+                    }
+                    None => Some(format!("< error could not open bus {name} >")),
+                },
+                (["open", _], Some(_)) => Some("< error bus already open >".to_string()),
+                (["echo"], _) => Some("< echo >".to_string()),
+                (["rawmode"], Some(index)) => {
+                    if streamer.is_none() {
+                        let subscription = channels[index].subscribe(CLIENT_QUEUE);
+                        let (writer, closed) = (Arc::clone(writer), Arc::clone(closed));
+                        *streamer = Some(thread::spawn(move || stream_frames(&subscription, &writer, &closed)));
+                    }
+                    Some("< ok >".to_string())
+                }
+                (["send", frame @ ..], Some(index)) => match parse_send(frame) {
+                    Ok(frame) => channels[index].transmit(&frame).err().map(|err| format!("< error {err} >")),
+                    Err(err) => Some(format!("< error {err} >")),
+                },
+                ([command, ..], None) if *command != "open" => Some("< error no bus open >".to_string()),
+                _ => Some(format!("< error unsupported command {command} >")),
+            };
+            if let Some(reply) = reply {
+                send_line(writer, &reply)?;
+            }
+        }
+        if pending.len() > MAX_COMMAND {
+            pending.clear();
+        }
+    }
+    Ok(())
+}
+
+/// Sends received frames until the client goes away or falls behind.
+fn stream_frames(subscription: &Subscription, writer: &Mutex<TcpStream>, closed: &AtomicBool) {
+    while !closed.load(Ordering::SeqCst) {
+        let frame = match subscription.recv_timeout(POLL) {
+            Ok(frame) => frame,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        // Raw mode has no way to show remote frames.
+        if frame.is_remote() {
+            continue;
+        }
+        // A full queue means the client isn't keeping up; it gets cut off rather than a gap.
+        if subscription.dropped() > 0 || send_line(writer, &format_frame(&frame)).is_err() {
+            break;
+        }
+    }
+    closed.store(true, Ordering::SeqCst);
+    let _ = writer.lock().unwrap().shutdown(Shutdown::Both);
+}
+
+/// The next `< ... >` command's contents, removing it (and anything before it) from `pending`.
+fn next_command(pending: &mut Vec<u8>) -> Option<String> {
+    let start = pending.iter().position(|&b| b == b'<')?;
+    let end = start + pending[start..].iter().position(|&b| b == b'>')?;
+    let command = String::from_utf8_lossy(&pending[start + 1..end]).trim().to_string();
+    pending.drain(..=end);
+    Some(command)
+}
+
+fn bus_index(name: &str, channels: usize) -> Option<usize> {
+    name.strip_prefix("can")?.parse().ok().filter(|&index| index < channels)
+}
+
+/// `ID DLC BYTES...`, with 8-digit IDs extended as socketcand reads them.
+fn parse_send(words: &[&str]) -> Result<Frame, String> {
+    let [text, dlc, bytes @ ..] = words else {
+        return Err("send needs an ID and a DLC".to_string());
+    };
+    let raw = u32::from_str_radix(text, 16).ok();
+    let id = if text.len() == 8 { raw.and_then(Id::extended) } else { raw.and_then(|raw| Id::standard(u16::try_from(raw).ok()?)) };
+    let id = id.ok_or_else(|| format!("invalid ID {text}"))?;
+    let dlc: usize = dlc.parse().map_err(|_| format!("invalid DLC {dlc}"))?;
+    if dlc != bytes.len() {
+        return Err(format!("DLC {dlc} but {} data bytes", bytes.len()));
+    }
+    let data = bytes
+        .iter()
+        .map(|byte| u8::from_str_radix(byte, 16).map_err(|_| format!("invalid data byte {byte}")))
+        .collect::<Result<Vec<u8>, String>>()?;
+    Frame::new(id, &data).ok_or_else(|| format!("{dlc} data bytes, at most 8 fit"))
+}
+
+/// `< frame 123 1700000000.123456 DEADBEEF >`; extended IDs have 8 digits, standard ones 3.
+fn format_frame(frame: &Frame) -> String {
+    let time = host_time(frame).duration_since(UNIX_EPOCH).unwrap_or_default();
+    let id = match frame.id() {
+        Id::Standard(id) => format!("{id:03X}"),
+        Id::Extended(id) => format!("{id:08X}"),
+    };
+    let data: String = frame.data().iter().map(|byte| format!("{byte:02X}")).collect();
+    format!("< frame {id} {}.{:06} {data} >", time.as_secs(), time.subsec_micros())
+}
@@ -0,0 +1,227 @@
+use std::fmt;
+
+/// Decoded `VCI_ERR_INFO.ErrCode` bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct ErrorFlags(pub u32);
+
+impl ErrorFlags {
+    pub const CAN_OVERFLOW: ErrorFlags = ErrorFlags(0x0001);
+    pub const ERROR_WARNING: ErrorFlags = ErrorFlags(0x0002);
+    pub const ERROR_PASSIVE: ErrorFlags = ErrorFlags(0x0004);
+    pub const ARBITRATION_LOST: ErrorFlags = ErrorFlags(0x0008);
+    pub const BUS_ERROR: ErrorFlags = ErrorFlags(0x0010);
+    pub const BUS_OFF: ErrorFlags = ErrorFlags(0x0020);
+    pub const BUFFER_OVERFLOW: ErrorFlags = ErrorFlags(0x0040);
+    pub const DEVICE_OPENED: ErrorFlags = ErrorFlags(0x0100);
+    pub const DEVICE_OPEN: ErrorFlags = ErrorFlags(0x0200);
+    pub const DEVICE_NOT_OPEN: ErrorFlags = ErrorFlags(0x0400);
+    pub const DEVICE_BUFFER_OVERFLOW: ErrorFlags = ErrorFlags(0x0800);
+    pub const DEVICE_NOT_EXIST: ErrorFlags = ErrorFlags(0x1000);
+    pub const LOAD_KERNEL_DLL: ErrorFlags = ErrorFlags(0x2000);
+    pub const CMD_FAILED: ErrorFlags = ErrorFlags(0x4000);
+    pub const BUFFER_CREATE: ErrorFlags = ErrorFlags(0x8000);
+
+    const NAMES: [(ErrorFlags, &'static str); 15] = [
+        (Self::CAN_OVERFLOW, "controller FIFO overflow"),
+        (Self::ERROR_WARNING, "error warning"),
+        (Self::ERROR_PASSIVE, "error-passive"),
+        (Self::ARBITRATION_LOST, "arbitration lost"),
+        (Self::BUS_ERROR, "bus error"),
+        (Self::BUS_OFF, "bus-off"),
+        (Self::BUFFER_OVERFLOW, "buffer overflow"),
+        (Self::DEVICE_OPENED, "device already opened"),
+        (Self::DEVICE_OPEN, "device open failed"),
+        (Self::DEVICE_NOT_OPEN, "device not open"),
+        (Self::DEVICE_BUFFER_OVERFLOW, "device buffer overflow"),
+        (Self::DEVICE_NOT_EXIST, "device does not exist"),
+        (Self::LOAD_KERNEL_DLL, "kernel DLL load failed"),
+        (Self::CMD_FAILED, "command failed"),
+        (Self::BUFFER_CREATE, "buffer allocation failed"),
+    ];
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, other: ErrorFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Human-readable names of every set flag, in bit order.
+    pub fn names(self) -> Vec<&'static str> {
+        Self::NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|&(_, name)| name)
+            .collect()
+    }
+}
+
+impl fmt::Display for ErrorFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no error");
+        }
+        let mut names = self.names();
+        let known = Self::NAMES.iter().fold(0, |acc, (flag, _)| acc | flag.0);
+        let unknown = format!("unknown 0x{:X}", self.0 & !known);
+        if self.0 & !known != 0 {
+            names.push(&unknown);
+        }
+        write!(f, "{}", names.join(", "))
+    }
+}
+
+/// Last error reported by `VCI_ReadErrInfo` for a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ErrorInfo {
+    pub flags: ErrorFlags,
+    /// Error code capture, receive error counter and transmit error counter, valid when
+    /// error-passive is flagged.
+    pub passive: [u8; 3],
+    /// Arbitration lost capture register, valid when arbitration lost is flagged.
+    pub arbitration_lost: u8,
+}
+
+impl ErrorInfo {
+    pub fn rx_error_counter(&self) -> u8 {
+        self.passive[1]
+    }
+
+    pub fn tx_error_counter(&self) -> u8 {
+        self.passive[2]
+    }
+}
+
+/// Fault confinement state derived from the SJA1000 error counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorState {
+    Active,
+    Warning,
+    Passive,
+    BusOff,
+}
+
+impl fmt::Display for ErrorState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorState::Active => "error-active",
+            ErrorState::Warning => "error-warning",
+            ErrorState::Passive => "error-passive",
+            ErrorState::BusOff => "bus-off",
+        })
+    }
+}
+
+/// SJA1000 register snapshot from `VCI_ReadCANStatus`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CanStatus {
+    pub error_interrupt: u8,
+    pub mode: u8,
+    pub status: u8,
+    pub arbitration_lost_capture: u8,
+    pub error_code_capture: u8,
+    pub error_warning_limit: u8,
+    pub rx_error_counter: u8,
+    pub tx_error_counter: u8,
+}
+
+impl CanStatus {
+    pub fn receive_buffer_full(&self) -> bool {
+        self.status & 0x01 != 0
+    }
+
+    pub fn data_overrun(&self) -> bool {
+        self.status & 0x02 != 0
+    }
+
+    pub fn transmit_buffer_released(&self) -> bool {
+        self.status & 0x04 != 0
+    }
+
+    pub fn transmission_complete(&self) -> bool {
+        self.status & 0x08 != 0
+    }
+
+    pub fn receiving(&self) -> bool {
+        self.status & 0x10 != 0
+    }
+
+    pub fn transmitting(&self) -> bool {
+        self.status & 0x20 != 0
+    }
+
+    pub fn error_status(&self) -> bool {
+        self.status & 0x40 != 0
+    }
+
+    pub fn bus_off(&self) -> bool {
+        self.status & 0x80 != 0
+    }
+
+    pub fn reset_mode(&self) -> bool {
+        self.mode & 0x01 != 0
+    }
+
+    pub fn listen_only(&self) -> bool {
+        self.mode & 0x02 != 0
+    }
+
+    pub fn self_test(&self) -> bool {
+        self.mode & 0x04 != 0
+    }
+
+    pub fn error_state(&self) -> ErrorState {
+        let worst = self.rx_error_counter.max(self.tx_error_counter);
+        let warning_limit = if self.error_warning_limit == 0 { 96 } else { self.error_warning_limit };
+        if self.bus_off() {
+            ErrorState::BusOff
+        } else if worst >= 128 {
+            ErrorState::Passive
+        } else if worst >= warning_limit || self.error_status() {
+            ErrorState::Warning
+        } else {
+            ErrorState::Active
+        }
+    }
+}
+
+impl fmt::Display for CanStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buffer = Vec::new();
+        if self.receive_buffer_full() {
+            buffer.push("rx buffer full");
+        }
+        if self.data_overrun() {
+            buffer.push("data overrun");
+        }
+        if !self.transmit_buffer_released() {
+            buffer.push("tx buffer locked");
+        }
+        let buffer = if buffer.is_empty() { "ok".to_string() } else { buffer.join(", ") };
+        let mode = if self.reset_mode() {
+            "reset"
+        } else if self.listen_only() {
+            "listen-only"
+        } else if self.self_test() {
+            "self-test"
+        } else {
+            "normal"
+        };
+        write!(
+            f,
+            "{} | REC={} TEC={} | buffer: {} | mode: {} (MOD=0x{:02X} SR=0x{:02X})",
+            self.error_state(),
+            self.rx_error_counter,
+            self.tx_error_counter,
+            buffer,
+            mode,
+            self.mode,
+            self.status
+        )
+    }
+}
@@ -0,0 +1,131 @@
+//! Async receive and transmit for tokio-based code.
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use futures_core::Stream;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::device::Channel;
+use crate::error::CanError;
+use crate::frame::Frame;
+
+/// Upper bound on how long the reader thread takes to notice the stream was dropped.
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(20);
+const RECEIVE_BATCH: usize = 256;
+
+/// Frames received on a channel, read by a dedicated thread and handed over through a bounded
+/// queue. When the queue is full, newly received frames are dropped (and counted in
+/// [`FrameStream::dropped`]) rather than blocking the reader, so a slow consumer loses the
+/// newest traffic but never stalls the adapter's receive buffer.
+///
+/// Dropping the stream stops the reader thread; the drop blocks for at most one receive poll.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use rustcanbus::{Bitrate, CanError, Device, Frame, Id, MockBackend, VciInitConfig};
+/// # let mock = Arc::new(MockBackend::new());
+/// # let device = Device::open_with(mock.clone(), 4, 0)?;
+/// # let channel = device.channel(0);
+/// # channel.init(&VciInitConfig::with_bitrate(Bitrate::Kbps500))?;
+/// # channel.start()?;
+/// # mock.inject(0, &Frame::new(Id::Standard(0x123), &[1, 2, 3]).unwrap());
+/// # let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// # runtime.block_on(async {
+/// let mut stream = channel.frames(1024);
+/// while let Some(frame) = stream.next().await {
+///     let frame = frame?;
+///     println!("{} {:02X?}", frame.id(), frame.data());
+/// #   assert_eq!((frame.id(), frame.data()), (Id::Standard(0x123), &[1, 2, 3][..]));
+/// #   break;
+/// }
+/// # Ok::<(), CanError>(())
+/// # })?;
+/// # Ok::<(), CanError>(())
+/// ```
+pub struct FrameStream {
+    frames: mpsc::Receiver<Result<Frame, CanError>>,
+    stop: Arc<AtomicBool>,
+    dropped: Arc<AtomicU64>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl FrameStream {
+    /// The next frame, or `None` once the reader has stopped.
+    pub async fn next(&mut self) -> Option<Result<Frame, CanError>> {
+        self.frames.recv().await
+    }
+
+    /// Frames discarded because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Stream for FrameStream {
+    type Item = Result<Frame, CanError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.frames.poll_recv(cx)
+    }
+}
+
+impl Drop for FrameStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+fn read(channel: &Channel, queue: &mpsc::Sender<Result<Frame, CanError>>, stop: &AtomicBool, dropped: &AtomicU64) {
+    while !stop.load(Ordering::SeqCst) {
+        match channel.receive_pending(RECEIVE_BATCH, RECEIVE_TIMEOUT) {
+            Ok(frames) => {
+                for frame in frames {
+                    match queue.try_send(Ok(frame)) {
+                        Ok(()) => {}
+                        Err(TrySendError::Full(_)) => {
+                            dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(TrySendError::Closed(_)) => return,
+                    }
+                }
+            }
+            Err(err) => {
+                if let Err(TrySendError::Closed(_)) = queue.try_send(Err(err)) {
+                    return;
+                }
+                thread::sleep(RECEIVE_TIMEOUT);
+            }
+        }
+    }
+}
+
+impl Channel {
+    /// Starts a reader thread and returns its frames as a stream; `capacity` bounds the queue
+    /// between them (see [`FrameStream`] for what happens when it fills up).
+    pub fn frames(&self, capacity: usize) -> FrameStream {
+        let (sender, frames) = mpsc::channel(capacity.max(1));
+        let stop = Arc::new(AtomicBool::new(false));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let reader = {
+            let (channel, stop, dropped) = (self.clone(), Arc::clone(&stop), Arc::clone(&dropped));
+            thread::spawn(move || read(&channel, &sender, &stop, &dropped))
+        };
+        FrameStream { frames, stop, dropped, reader: Some(reader) }
+    }
+
+    /// [`Channel::transmit`] on tokio's blocking pool. Must be called within a tokio runtime.
+    pub async fn transmit_async(&self, frame: Frame) -> Result<(), CanError> {
+        let channel = self.clone();
+        tokio::task::spawn_blocking(move || channel.transmit(&frame))
+            .await
+            .expect("transmit task panicked")
+    }
+}
@@ -0,0 +1,294 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::frame::Frame;
+
+/// Device timestamp tick: `VciCanObj.time_stamp` counts in 0.1 ms units.
+pub const TICK: Duration = Duration::from_micros(100);
+
+/// How much device time passes between drift corrections in [`DeviceClock`].
+pub const REANCHOR_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Largest oscillator drift [`DeviceClock`] corrects for, as a fraction.
+const MAX_DRIFT: f64 = 0.01;
+
+/// Maps a channel's device timestamps onto host time.
+///
+/// The first frame anchors the device clock to the host clock. Every [`REANCHOR_INTERVAL`] the
+/// anchor is then moved by the smallest gap seen between a frame's arrival on the host and its
+/// mapped time, and the clock rate adjusted to match, which follows the drift between the
+/// adapter's oscillator and the host without picking up USB latency jitter. After the first few
+/// intervals converted times are within the best-case USB latency plus a fraction of a
+/// millisecond of host time; intervals between frames keep the device's 0.1 ms resolution.
+/// Times never go backwards.
+///
+/// The raw counter wraps after 2^32 ticks (about 4.97 days), or sooner on firmware with a
+/// narrower counter (see [`DeviceClock::with_wrap`]); [`DeviceClock::unwrap_ticks`] extends it
+/// to 64 bits by counting the wraps.
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceClock {
+    /// Raw timestamps count modulo this many ticks.
+    modulus: u64,
+    /// A step back of more than this many ticks is a wrap rather than reordering.
+    threshold: u64,
+    /// Latest raw timestamp, reduced modulo `modulus`.
+    last_raw: Option<u64>,
+    wraps: u64,
+    /// Host wall-clock and monotonic time at the first frame, to derive one from the other.
+    origin: Option<(SystemTime, Instant)>,
+    /// Unwrapped device timestamp and the host instant it maps to.
+    anchor: Option<(u64, Instant)>,
+    /// Smallest arrival-minus-mapped offset since the anchor, in nanoseconds.
+    min_offset: Option<i64>,
+    /// Host time per unit of device time, learned from the corrections; 0 until the first one.
+    rate: f64,
+    last: Option<Instant>,
+}
+
+impl Default for DeviceClock {
+    fn default() -> Self {
+        Self::with_wrap(1 << 32, 1 << 31)
+    }
+}
+
+impl DeviceClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// For a counter that wraps after `modulus` ticks (at most 2^32). A step back of more than
+    /// `threshold` ticks counts as a wrap; smaller ones are taken as reordered frames.
+    pub fn with_wrap(modulus: u64, threshold: u64) -> Self {
+        Self {
+            modulus: modulus.clamp(1, 1 << 32),
+            threshold,
+            last_raw: None,
+            wraps: 0,
+            origin: None,
+            anchor: None,
+            min_offset: None,
+            rate: 0.0,
+            last: None,
+        }
+    }
+
+    /// Starts over for a device that restarted its timestamps, keeping the wrap settings.
+    pub fn reset(&mut self) {
+        *self = Self::with_wrap(self.modulus, self.threshold);
+    }
+
+    /// Extends a raw timestamp to 64 bits, counting a wrap whenever the counter steps back by
+    /// more than the threshold. Steps back of less, including ones across the wrap by a frame
+    /// from before it that arrives just after it, are reordered frames; they get their own
+    /// epoch's time and leave the latest timestamp alone, so the next frame doesn't count the
+    /// same wrap again.
+    pub fn unwrap_ticks(&mut self, time_stamp: u32) -> u64 {
+        let raw = u64::from(time_stamp) % self.modulus;
+        match self.last_raw {
+            Some(last) if raw < last && last - raw > self.threshold => {
+                self.wraps += 1;
+                self.last_raw = Some(raw);
+            }
+            Some(last) if raw < last => {}
+            Some(last) if self.wraps > 0 && raw - last > self.modulus.saturating_sub(self.threshold) => {
+                return (self.wraps - 1) * self.modulus + raw;
+            }
+            _ => self.last_raw = Some(raw),
+        }
+        self.wraps * self.modulus + raw
+    }
+
+    /// Converts a timestamp for a frame that is being handled now.
+    pub fn to_system_time(&mut self, time_stamp: u32) -> SystemTime {
+        let ticks = self.unwrap_ticks(time_stamp);
+        self.convert(ticks, Instant::now()).0
+    }
+
+    /// Host wall-clock and monotonic time of a frame with unwrapped device timestamp `ticks`
+    /// that the host received at `received`.
+    pub fn convert(&mut self, ticks: u64, received: Instant) -> (SystemTime, Instant) {
+        let origin = *self.origin.get_or_insert_with(|| (SystemTime::now(), received));
+        let (base, at) = *self.anchor.get_or_insert((ticks, received));
+        let elapsed = ticks_duration(ticks.saturating_sub(base));
+        let rate = if self.rate > 0.0 { self.rate } else { 1.0 };
+        let mut mapped = at + elapsed.mul_f64(rate);
+
+        let offset = signed_nanos(received, mapped);
+        let min_offset = self.min_offset.map_or(offset, |min| min.min(offset));
+        if elapsed >= REANCHOR_INTERVAL {
+            let correction = min_offset as f64 / elapsed.as_nanos() as f64;
+            self.rate = (rate * (1.0 + correction)).clamp(1.0 - MAX_DRIFT, 1.0 + MAX_DRIFT);
+            mapped = shift(mapped, min_offset);
+            self.anchor = Some((ticks, mapped));
+            self.min_offset = None;
+        } else {
+            self.min_offset = Some(min_offset);
+        }
+        if let Some(last) = self.last {
+            mapped = mapped.max(last);
+        }
+        self.last = Some(mapped);
+
+        let wall = match mapped.checked_duration_since(origin.1) {
+            Some(since) => origin.0 + since,
+            None => origin.0 - origin.1.duration_since(mapped),
+        };
+        (wall, mapped)
+    }
+}
+
+/// When a frame handed to a log writer was on the bus: its converted device time, or now for
+/// frames built locally, such as transmitted ones.
+pub(crate) fn host_time(frame: &Frame) -> SystemTime {
+    frame.timestamp().unwrap_or_else(SystemTime::now)
+}
+
+pub(crate) fn ticks_duration(ticks: u64) -> Duration {
+    Duration::from_nanos(ticks.saturating_mul(TICK.as_nanos() as u64))
+}
+
+/// `a - b` in nanoseconds.
+pub(crate) fn signed_nanos(a: Instant, b: Instant) -> i64 {
+    match a.checked_duration_since(b) {
+        Some(d) => d.as_nanos() as i64,
+        None => -(b.duration_since(a).as_nanos() as i64),
+    }
+}
+
+pub(crate) fn shift(instant: Instant, nanos: i64) -> Instant {
+    let by = Duration::from_nanos(nanos.unsigned_abs());
+    if nanos >= 0 {
+        instant + by
+    } else {
+        instant.checked_sub(by).unwrap_or(instant)
+    }
+}
+
+/// UTC calendar fields for a wall-clock time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CivilTime {
+    pub(crate) year: i64,
+    pub(crate) month: u32,
+    pub(crate) day: u32,
+    pub(crate) hour: u32,
+    pub(crate) minute: u32,
+    pub(crate) second: u32,
+    pub(crate) millis: u32,
+    /// 0 = Sunday.
+    pub(crate) weekday: u32,
+}
+
+impl CivilTime {
+    pub(crate) fn from_system_time(time: SystemTime) -> Self {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since_epoch.as_secs() as i64;
+        let days = secs.div_euclid(86_400);
+        let of_day = secs.rem_euclid(86_400) as u32;
+
+        // Howard Hinnant's days-from-civil inverse.
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        Self {
+            year,
+            month,
+            day,
+            hour: of_day / 3_600,
+            minute: of_day / 60 % 60,
+            second: of_day % 60,
+            millis: since_epoch.subsec_millis(),
+            weekday: (days + 4).rem_euclid(7) as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::XorShift;
+
+    fn unwrap_all(clock: &mut DeviceClock, raw: &[u64]) -> Vec<u64> {
+        raw.iter().map(|&raw| clock.unwrap_ticks(raw as u32)).collect()
+    }
+
+    #[test]
+    fn counts_wraps_of_the_full_counter() {
+        let mut clock = DeviceClock::new();
+        let top = u64::from(u32::MAX);
+        let ticks = unwrap_all(&mut clock, &[top - 15, top, 5, 10, top - 2, 3]);
+        assert_eq!(ticks, [top - 15, top, top + 6, top + 11, top - 2, top + 4]);
+    }
+
+    #[test]
+    fn late_frame_from_before_the_wrap_counts_it_once() {
+        let modulus = 1 << 16;
+        let mut clock = DeviceClock::with_wrap(modulus, modulus / 2);
+        let ticks = unwrap_all(&mut clock, &[modulus - 10, 2, modulus - 5, 4, modulus - 1, 8, 9]);
+        assert_eq!(ticks, [modulus - 10, modulus + 2, modulus - 5, modulus + 4, modulus - 1, modulus + 8, modulus + 9]);
+    }
+
+    #[test]
+    fn reordering_within_an_epoch_is_not_a_wrap() {
+        let mut clock = DeviceClock::with_wrap(1000, 500);
+        assert_eq!(unwrap_all(&mut clock, &[100, 90, 110, 80, 120]), [100, 90, 110, 80, 120]);
+        assert_eq!(unwrap_all(&mut clock, &[700, 150]), [700, 1150]);
+    }
+
+    #[test]
+    fn far_ahead_before_any_wrap_is_a_forward_step() {
+        let mut clock = DeviceClock::with_wrap(1000, 500);
+        assert_eq!(unwrap_all(&mut clock, &[10, 900, 950, 20]), [10, 900, 950, 1020]);
+    }
+
+    /// Frames a few ticks apart over many wraps, each shuffled by up to three places the way
+    /// USB transfers can deliver them, must all unwrap to their true time.
+    #[test]
+    fn shuffled_sequences_over_many_wraps() {
+        let mut rng = XorShift(0x2545_F491_4F6C_DD1D);
+        for (modulus, threshold) in [(1 << 8, 1 << 7), (1000, 500), (1 << 16, 1 << 15), (1 << 32, 1 << 31)] {
+            let start = modulus - 40 % modulus;
+            let mut truth: Vec<u64> = (0..4_000).scan(start, |time, _| {
+                *time += 1 + rng.below(4);
+                Some(*time)
+            }).collect();
+            for window in truth.chunks_mut(4) {
+                let (a, b) = (rng.below(4) as usize, rng.below(4) as usize);
+                if a < window.len() && b < window.len() {
+                    window.swap(a, b);
+                }
+            }
+            let mut clock = DeviceClock::with_wrap(modulus, threshold);
+            let raw: Vec<u64> = truth.iter().map(|time| time % modulus).collect();
+            let base = start / modulus * modulus;
+            let unwrapped: Vec<u64> = unwrap_all(&mut clock, &raw).iter().map(|ticks| ticks + base).collect();
+            assert_eq!(unwrapped, truth, "modulus {modulus}");
+        }
+    }
+
+    #[test]
+    fn reset_keeps_the_wrap_settings() {
+        let mut clock = DeviceClock::with_wrap(1000, 500);
+        assert_eq!(unwrap_all(&mut clock, &[900, 100]), [900, 1100]);
+        clock.reset();
+        assert_eq!(unwrap_all(&mut clock, &[5, 990, 3]), [5, 990, 1003]);
+    }
+
+    #[test]
+    fn converted_times_never_go_backwards() {
+        let mut clock = DeviceClock::with_wrap(1000, 500);
+        let now = Instant::now();
+        let mut last = None;
+        for (raw, received) in [(990, 0), (5, 2), (995, 2), (10, 3), (20, 4)] {
+            let ticks = clock.unwrap_ticks(raw);
+            let (_, at) = clock.convert(ticks, now + Duration::from_millis(received));
+            assert!(last.is_none_or(|last| at >= last));
+            last = Some(at);
+        }
+    }
+}
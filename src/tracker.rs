@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::idstats::IdStats;
+use crate::j1939::J1939Id;
+
+/// Latest state of one ID seen on one channel.
+#[derive(Debug, Clone)]
+pub struct TrackedId {
+    pub channel: u32,
+    pub frame: Frame,
+    pub count: u64,
+    pub last_seen: Instant,
+    /// Time between the last two frames, `None` until the ID has been seen twice.
+    pub cycle: Option<Duration>,
+    /// Frame received before `frame`.
+    pub previous: Option<Frame>,
+    /// When each payload byte last differed from the frame before it. All bytes of the first
+    /// frame count as changed.
+    pub changed_at: [Option<Instant>; 8],
+    /// Gap statistics from the device timestamps.
+    pub stats: IdStats,
+}
+
+impl TrackedId {
+    /// Whether byte `index` changed within `hold` of `now`.
+    pub fn byte_changed(&self, index: usize, hold: Duration, now: Instant) -> bool {
+        self.changed_at
+            .get(index)
+            .copied()
+            .flatten()
+            .is_some_and(|at| now.saturating_duration_since(at) <= hold)
+    }
+
+    /// Most recent byte change (or DLC change) of this ID.
+    pub fn last_change(&self) -> Option<Instant> {
+        self.changed_at.iter().flatten().max().copied()
+    }
+}
+
+/// Per-ID table of the most recent frame, as shown by the monitor view. Entries iterate in ID
+/// order, then channel.
+#[derive(Debug, Clone, Default)]
+pub struct IdTracker {
+    entries: BTreeMap<(Id, u32), TrackedId>,
+    merged: bool,
+    by_pgn: bool,
+}
+
+impl IdTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tracks each ID once whichever channel it arrives on; an entry's `channel` is the one it
+    /// was last seen on.
+    pub fn merged() -> Self {
+        Self { merged: true, ..Self::default() }
+    }
+
+    /// Tracks extended IDs by J1939 PGN and source address, so one entry covers a group sent
+    /// with different priorities or to different destinations; see [`J1939Id::group_id`].
+    pub fn grouped_by_pgn(self) -> Self {
+        Self { by_pgn: true, ..self }
+    }
+
+    pub fn update(&mut self, channel: u32, frame: &Frame, now: Instant) {
+        let key = if self.merged { 0 } else { channel };
+        let id = if self.by_pgn { J1939Id::group_id(frame.id()) } else { frame.id() };
+        self.entries
+            .entry((id, key))
+            .and_modify(|entry| {
+                entry.channel = channel;
+                let (old, new) = (entry.frame.data(), frame.data());
+                for (i, changed_at) in entry.changed_at.iter_mut().enumerate() {
+                    if old.get(i) != new.get(i) {
+                        *changed_at = Some(now);
+                    }
+                }
+                entry.cycle = Some(now.saturating_duration_since(entry.last_seen));
+                entry.previous = Some(entry.frame);
+                entry.frame = *frame;
+                entry.count += 1;
+                entry.last_seen = now;
+                entry.stats.record(frame.device_ticks());
+            })
+            .or_insert_with(|| TrackedId {
+                channel,
+                frame: *frame,
+                count: 1,
+                last_seen: now,
+                cycle: None,
+                previous: None,
+                changed_at: std::array::from_fn(|i| (i < frame.data().len()).then_some(now)),
+                stats: {
+                    let mut stats = IdStats::new();
+                    stats.record(frame.device_ticks());
+                    stats
+                },
+            });
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TrackedId> {
+        self.entries.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
@@ -0,0 +1,567 @@
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::device::CHANNEL_COUNT;
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::sink::{Direction, FrameSink};
+use crate::timestamp::{host_time, CivilTime};
+
+/// `;$STARTTIME` counts days since 1899-12-30, as OLE automation dates do; this is 1970-01-01.
+const UNIX_EPOCH_DAYS: f64 = 25_569.0;
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// Columns of the data lines this writes, named by `;$COLUMNS`: message number, time offset,
+/// type, bus, ID, direction, reserved, data length and data.
+const COLUMNS: &str = "N,O,T,B,I,d,R,L,D";
+
+/// Formats one TRC 2.1 data line, e.g. `      1       811.000 DT 1      00A0 Rx -  3    00 01 02`.
+/// `offset` is the time since the start of the trace and `number` counts from 1; buses are
+/// numbered from 1 as in PCAN-View, and extended IDs are written with 8 hex digits, standard
+/// ones with 4.
+pub fn format_trc_line(number: u64, offset: Duration, channel: u32, frame: &Frame, direction: Direction) -> String {
+    let id = if frame.is_extended() {
+        format!("{:08X}", frame.id().raw())
+    } else {
+        format!("{:04X}", frame.id().raw())
+    };
+    let (kind, length) = match frame.is_remote() {
+        true => ("RR", frame.dlc()),
+        false => ("DT", frame.data().len() as u8),
+    };
+    let dir = match direction {
+        Direction::Rx => "Rx",
+        Direction::Tx => "Tx",
+    };
+    let mut line = format!("{number:>7} {:>13.3} {kind} {:<2} {id:>8} {dir} -  {length:<4}", offset.as_secs_f64() * 1000.0, channel + 1);
+    for byte in frame.data() {
+        let _ = write!(line, " {byte:02X}");
+    }
+    line.truncate(line.trim_end().len());
+    line
+}
+
+/// PEAK TRC 2.1 writer, the format PCAN-View saves traces in. The start time in the header is
+/// UTC.
+pub struct TrcWriter<W: Write> {
+    out: W,
+    start: SystemTime,
+    messages: u64,
+    header_written: bool,
+}
+
+impl<W: Write> TrcWriter<W> {
+    /// Starts a trace now.
+    pub fn new(out: W) -> Self {
+        Self::with_start(out, SystemTime::now())
+    }
+
+    pub fn with_start(out: W, start: SystemTime) -> Self {
+        Self {
+            out,
+            start,
+            messages: 0,
+            header_written: false,
+        }
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let since_epoch = self.start.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let t = CivilTime::from_system_time(self.start);
+        writeln!(self.out, ";$FILEVERSION=2.1")?;
+        writeln!(self.out, ";$STARTTIME={:.10}", UNIX_EPOCH_DAYS + since_epoch.as_secs_f64() / SECONDS_PER_DAY)?;
+        writeln!(self.out, ";$COLUMNS={COLUMNS}")?;
+        writeln!(self.out, ";")?;
+        writeln!(
+            self.out,
+            ";   Start time: {:02}.{:02}.{} {:02}:{:02}:{:02}.{:03}.{}",
+            t.day,
+            t.month,
+            t.year,
+            t.hour,
+            t.minute,
+            t.second,
+            t.millis,
+            since_epoch.subsec_micros() % 1000 / 100
+        )?;
+        writeln!(self.out, ";   Generated by rustcanbus {}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(self.out, ";-------------------------------------------------------------------------------")?;
+        for bus in 1..=CHANNEL_COUNT {
+            writeln!(self.out, ";   Bus {bus}: CAN{bus}")?;
+        }
+        writeln!(self.out, ";-------------------------------------------------------------------------------")?;
+        writeln!(self.out, ";   Message   Time    Type    ID     Rx/Tx")?;
+        writeln!(self.out, ";   Number    Offset  |  Bus  [hex]  |  Reserved")?;
+        writeln!(self.out, ";   |         [ms]    |  |    |      |  |  Data Length")?;
+        writeln!(self.out, ";   |         |       |  |    |      |  |  |    Data [hex] ...")?;
+        writeln!(self.out, ";   |         |       |  |    |      |  |  |    |")?;
+        writeln!(self.out, ";---+-- ------+------ +- +- --+----- +- +- +--- +- -- -- -- -- -- -- --")?;
+        self.header_written = true;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> FrameSink for TrcWriter<W> {
+    fn write_frame(&mut self, channel: u32, frame: &Frame, direction: Direction) -> io::Result<()> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+        self.messages += 1;
+        let offset = host_time(frame).duration_since(self.start).unwrap_or_default();
+        writeln!(self.out, "{}", format_trc_line(self.messages, offset, channel, frame, direction))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+        self.out.flush()
+    }
+}
+
+/// One frame read from a TRC file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrcRecord {
+    /// Since the Unix epoch: the header's start time plus the line's offset. Files without a
+    /// start time count from their start instead.
+    pub time: Duration,
+    /// Bus number minus one; files before version 2.1 have no bus column and use channel 0.
+    pub channel: u32,
+    pub frame: Frame,
+    pub direction: Direction,
+}
+
+/// Result of reading a TRC file: its version, frames, and `(line number, reason)` for every
+/// line that failed to parse.
+#[derive(Debug, Clone, Default)]
+pub struct TrcLog {
+    pub version: String,
+    pub records: Vec<TrcRecord>,
+    pub errors: Vec<(usize, String)>,
+}
+
+/// Reads a PCAN-View TRC file of version 1.1, 2.0 or 2.1, skipping (and reporting) malformed
+/// lines. Status, error and error counter lines are skipped silently, CAN FD frames are reported.
+/// Fails if the `;$FILEVERSION` line is missing or names another version.
+pub fn read_trc<R: BufRead>(reader: R) -> io::Result<TrcLog> {
+    let unsupported = |found: &str| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{found}; only TRC versions 1.1, 2.0 and 2.1 are supported"))
+    };
+    let mut log = TrcLog::default();
+    let mut start = Duration::ZERO;
+    let mut columns: Vec<char> = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix(";$") {
+            let (key, value) = header.split_once('=').unwrap_or((header, ""));
+            match key {
+                "FILEVERSION" => {
+                    columns = match value {
+                        "1.1" => "N,O,d,I,l,D",
+                        "2.0" => "N,O,T,I,d,l,D",
+                        "2.1" => COLUMNS,
+                        _ => return Err(unsupported(&format!("TRC version {value}"))),
+                    }
+                    .split(',')
+                    .filter_map(|column| column.chars().next())
+                    .collect();
+                    log.version = value.to_string();
+                }
+                "STARTTIME" => {
+                    let days: f64 = value.parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: invalid start time '{value}'", index + 1)))?;
+                    start = Duration::try_from_secs_f64((days - UNIX_EPOCH_DAYS) * SECONDS_PER_DAY).unwrap_or_default();
+                }
+                "COLUMNS" if log.version == "2.1" => {
+                    columns = value.split(',').filter_map(|column| column.trim().chars().next()).collect();
+                }
+                _ => {}
+            }
+            continue;
+        }
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        if columns.is_empty() {
+            return Err(unsupported("no ;$FILEVERSION line before the first message (TRC 1.0 or older?)"));
+        }
+        match parse_trc_line(line, &columns, start) {
+            Ok(Some(record)) => log.records.push(record),
+            Ok(None) => {}
+            Err(reason) => log.errors.push((index + 1, reason)),
+        }
+    }
+    if columns.is_empty() {
+        return Err(unsupported("no ;$FILEVERSION line"));
+    }
+    Ok(log)
+}
+
+/// Parses a data line whose fields are `columns`, as `;$COLUMNS` letters. `None` for lines
+/// that aren't frames.
+fn parse_trc_line(line: &str, columns: &[char], start: Duration) -> Result<Option<TrcRecord>, String> {
+    let mut fields = line.split_whitespace();
+    let (mut offset, mut kind, mut channel, mut id, mut direction, mut length) = (None, "DT", 0, None, Direction::Rx, None);
+    let mut data = Vec::new();
+    for &column in columns {
+        if column == 'D' {
+            data.extend(fields.by_ref());
+            break;
+        }
+        let field = fields.next().ok_or("line ends early")?;
+        match column {
+            'O' => offset = Some(field.parse::<f64>().ok().filter(|ms| *ms >= 0.0).ok_or(format!("invalid time offset '{field}'"))?),
+            // Other types, such as status and error counter lines, have other columns after this.
+            'T' => match field {
+                "DT" | "RR" => kind = field,
+                "FD" | "FB" | "FE" | "BI" => return Err("CAN FD frames aren't supported".to_string()),
+                _ => return Ok(None),
+            },
+            'B' => {
+                channel = field
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|bus| (1..=CHANNEL_COUNT).contains(bus))
+                    .ok_or(format!("unknown bus '{field}', expected 1 or 2"))?
+                    - 1
+            }
+            'I' => id = Some(field),
+            'd' => {
+                direction = match field {
+                    "Rx" => Direction::Rx,
+                    "Tx" => Direction::Tx,
+                    // 1.1 marks error and warning events in the direction column.
+                    "Error" | "Warng" => return Ok(None),
+                    _ => return Err(format!("invalid direction '{field}', expected Rx or Tx")),
+                }
+            }
+            'l' | 'L' => length = Some(field.parse::<u8>().map_err(|_| format!("invalid data length '{field}'"))?),
+            _ => {}
+        }
+    }
+    let (offset, id, length) = match (offset, id, length) {
+        (Some(offset), Some(id), Some(length)) => (offset, id, length),
+        _ => return Err("missing time offset, ID or data length".to_string()),
+    };
+    let raw = u32::from_str_radix(id, 16).map_err(|_| format!("invalid CAN ID '{id}'"))?;
+    let id = match id.len() {
+        8 => Id::extended(raw).ok_or_else(|| format!("CAN ID '{id}' exceeds 29 bits"))?,
+        _ if raw <= Id::MAX_STANDARD as u32 => Id::Standard(raw as u16),
+        _ => return Err(format!("standard CAN ID '{id}' exceeds 0x7FF; extended IDs have 8 digits")),
+    };
+    let frame = if kind == "RR" || data == ["RTR"] {
+        Frame::remote(id, length).ok_or_else(|| format!("RTR length {length} exceeds 8"))?
+    } else {
+        let bytes = data
+            .iter()
+            .map(|byte| u8::from_str_radix(byte, 16).map_err(|_| format!("invalid data byte '{byte}'")))
+            .collect::<Result<Vec<u8>, _>>()?;
+        if bytes.len() != length as usize {
+            return Err(format!("{} data bytes for a data length of {length}", bytes.len()));
+        }
+        Frame::new(id, &bytes).ok_or_else(|| format!("{length} data bytes exceed 8"))?
+    };
+    let time = start + Duration::from_micros((offset * 1000.0).round() as u64);
+    Ok(Some(TrcRecord { time, channel, frame, direction }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::mock::XorShift;
+
+    /// Saved by PCAN-View 3, which has no type or bus column and puts events in the direction one.
+    const PCAN_VIEW_1_1: &str = "\
+;$FILEVERSION=1.1
+;$STARTTIME=41766.4648963872
+;
+;   Start time: 07.05.2014 11:09:27.047.8
+;   Generated by PCAN-View v3.2.0.174
+;
+;   Message Number
+;   |         Time Offset (ms)
+;   |         |        Type
+;   |         |        |        ID (hex)
+;   |         |        |        |     Data Length
+;   |         |        |        |     |   Data Bytes (hex) ...
+;   |         |        |        |     |   |
+;---+--   ----+----  --+--  ----+---  +  -+ -- -- -- -- -- -- --
+     1)      1059.9  Rx         0300  8  00 00 00 00 04 00 00 00 
+     2)      1283.2  Rx         0300  8  00 00 00 00 04 00 00 00 
+     3)      1298.9  Tx         0400  4  00 00 00 00 
+     4)      1346.7  Rx     18EFC034  8  00 00 00 00 06 00 00 00 
+     5)      1349.0  Warng  FFFFFFFF  4  00 00 00 08  BUSHEAVY
+     6)      1449.9  Error  00000088  4  00 02 00 08 
+     7)      1510.0  Tx         0300  1  RTR 
+";
+
+    const PCAN_VIEW_2_0: &str = "\
+;$FILEVERSION=2.0
+;$STARTTIME=42209.4075997106
+;$COLUMNS=N,O,T,I,d,l,D
+;
+;   C:\\Users\\test\\Documents\\Sample.trc
+;   Start time: 24.07.2015 09:46:56.615.0
+;   Generated by PCAN-View v4.0.29.426
+;-------------------------------------------------------------------------------
+;   Connection                 Bit rate
+;   PCANLight_USB_16@pcan_usb  Nominal 1 MBit/s, Data 2 Mbit/s
+;-------------------------------------------------------------------------------
+;   Message   Time    Type    ID     Rx/Tx
+;   Number    Offset  |  Bus  [hex]  |  Reserved
+;   |         [ms]    |  |    |      |  |  Data Length Code
+;   |         |       |  |    |      |  |  |    Data [hex] ...
+;   |         |       |  |    |      |  |  |    |
+;---+-- ------+------ +- +- --+----- +- +- +--- +- -- -- -- -- -- -- --
+      1      1059.900 DT     0300 Rx 7  00 00 00 00 04 00 00 
+      2      1283.231 DT     0300 Rx 7  00 00 00 00 04 00 00 
+      3      1298.945 DT     0400 Tx 3  00 00 00 
+      4      1334.416 FD     0500 Tx 12 01 02 03 04 05 06 07 08 09 0A 0B 0C 
+      5      1334.522 ER          Rx 04 00 02 00 00 
+      6      1334.531 ST          Rx 00 00 00 08 
+      7      1334.643 EC          Rx 02 02 
+      8      1335.156 DT 18EFC034 Tx 7  01 02 03 04 05 06 07 
+      9      1336.512 RR     0300 Rx 4 
+";
+
+    const PCAN_VIEW_2_1: &str = "\
+;$FILEVERSION=2.1
+;$STARTTIME=43474.4753837731
+;$COLUMNS=N,O,T,B,I,d,R,L,D
+;
+;   C:\\Users\\test\\Documents\\Sample.trc
+;   Start time: 09.01.2019 11:24:33.158.0
+;   Generated by PCAN-View v4.2.1.533
+;-------------------------------------------------------------------------------
+;   Bus   Connection   Net Connection    Protocol  Bit rate
+;   1     Connection1  TestNet@pcan_usb  CAN       500 kbit/s
+;   2     Connection2  TestNet2@pcan_usb CAN       250 kbit/s
+;-------------------------------------------------------------------------------
+;   Message   Time    Type    ID     Rx/Tx
+;   Number    Offset  |  Bus  [hex]  |  Reserved
+;   |         [ms]    |  |    |      |  |  Data Length
+;   |         |       |  |    |      |  |  |    Data [hex] ...
+;   |         |       |  |    |      |  |  |    |
+;---+-- ------+------ +- +- --+----- +- +- +--- +- -- -- -- -- -- -- --
+      1      1059.900 DT 1      0300 Rx -  8    00 00 00 00 04 00 00 00
+      2      1283.231 DT 1      0300 Rx -  8    00 00 00 00 04 00 00 00
+      3      1298.945 DT 1      0400 Tx -  4    00 00 00 00
+      4      1334.416 FD 1      0500 Tx -  12   01 02 03 04 05 06 07 08 09 0A 0B 0C
+      5      1334.522 ER 1      -    Rx -  5    04 00 02 00 00
+      6      1334.531 ST 1      -    Rx -  4    00 00 00 08
+      7      1334.643 EC 1      -    Rx -  2    02 02
+      8      1335.156 DT 1  18EFC034 Tx -  8    01 02 03 04 05 06 07 08
+      9      1336.512 RR 2      0300 Rx -  4
+     10      1337.600 DT 2      0123 Rx -  0
+";
+
+    fn std_frame(id: u16, data: &[u8]) -> Frame {
+        Frame::new(Id::Standard(id), data).unwrap()
+    }
+
+    /// The records as (milliseconds after the first, channel, frame, direction).
+    fn relative(log: &TrcLog) -> Vec<(f64, u32, Frame, Direction)> {
+        let first = log.records[0].time;
+        log.records.iter().map(|record| ((record.time - first).as_secs_f64() * 1000.0, record.channel, record.frame, record.direction)).collect()
+    }
+
+    fn assert_starts_at(log: &TrcLog, unix_millis: u64) {
+        let first = log.records[0].time.as_secs_f64() * 1000.0;
+        assert!((first - unix_millis as f64).abs() < 1.0, "first frame at {first} ms, expected {unix_millis}");
+    }
+
+    #[test]
+    fn reads_pcan_view_1_1() {
+        let log = read_trc(PCAN_VIEW_1_1.as_bytes()).unwrap();
+        assert_eq!(log.version, "1.1");
+        assert!(log.errors.is_empty(), "{:?}", log.errors);
+        // 07.05.2014 11:09:27.047 plus 1059.9 ms.
+        assert_starts_at(&log, 1_399_460_968_107);
+        let records = relative(&log);
+        let expected = [
+            (0.0, 0, std_frame(0x300, &[0, 0, 0, 0, 4, 0, 0, 0]), Direction::Rx),
+            (223.3, 0, std_frame(0x300, &[0, 0, 0, 0, 4, 0, 0, 0]), Direction::Rx),
+            (239.0, 0, std_frame(0x400, &[0; 4]), Direction::Tx),
+            (286.8, 0, Frame::new(Id::Extended(0x18EF_C034), &[0, 0, 0, 0, 6, 0, 0, 0]).unwrap(), Direction::Rx),
+            (450.1, 0, Frame::remote(Id::Standard(0x300), 1).unwrap(), Direction::Tx),
+        ];
+        assert_eq!(records.len(), expected.len());
+        for (record, expected) in records.iter().zip(&expected) {
+            assert!((record.0 - expected.0).abs() < 0.001, "{record:?}");
+            assert_eq!((record.1, record.2, record.3), (expected.1, expected.2, expected.3));
+        }
+    }
+
+    #[test]
+    fn reads_pcan_view_2_0() {
+        let log = read_trc(PCAN_VIEW_2_0.as_bytes()).unwrap();
+        assert_eq!(log.version, "2.0");
+        assert_eq!(log.errors, [(21, "CAN FD frames aren't supported".to_string())]);
+        // 24.07.2015 09:46:56.615 plus 1059.9 ms.
+        assert_starts_at(&log, 1_437_731_217_675);
+        let records = relative(&log);
+        let expected = [
+            (0.0, std_frame(0x300, &[0, 0, 0, 0, 4, 0, 0]), Direction::Rx),
+            (223.331, std_frame(0x300, &[0, 0, 0, 0, 4, 0, 0]), Direction::Rx),
+            (239.045, std_frame(0x400, &[0; 3]), Direction::Tx),
+            (275.256, Frame::new(Id::Extended(0x18EF_C034), &[1, 2, 3, 4, 5, 6, 7]).unwrap(), Direction::Tx),
+            (276.612, Frame::remote(Id::Standard(0x300), 4).unwrap(), Direction::Rx),
+        ];
+        assert_eq!(records.len(), expected.len());
+        for (record, expected) in records.iter().zip(&expected) {
+            assert!((record.0 - expected.0).abs() < 0.000_5, "{record:?}");
+            assert_eq!((record.1, record.2, record.3), (0, expected.1, expected.2));
+        }
+    }
+
+    #[test]
+    fn reads_pcan_view_2_1() {
+        let log = read_trc(PCAN_VIEW_2_1.as_bytes()).unwrap();
+        assert_eq!(log.version, "2.1");
+        assert_eq!(log.errors, [(22, "CAN FD frames aren't supported".to_string())]);
+        // 09.01.2019 11:24:33.158 plus 1059.9 ms.
+        assert_starts_at(&log, 1_547_033_074_218);
+        let records = relative(&log);
+        let expected = [
+            (0.0, 0, std_frame(0x300, &[0, 0, 0, 0, 4, 0, 0, 0]), Direction::Rx),
+            (223.331, 0, std_frame(0x300, &[0, 0, 0, 0, 4, 0, 0, 0]), Direction::Rx),
+            (239.045, 0, std_frame(0x400, &[0; 4]), Direction::Tx),
+            (275.256, 0, Frame::new(Id::Extended(0x18EF_C034), &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap(), Direction::Tx),
+            (276.612, 1, Frame::remote(Id::Standard(0x300), 4).unwrap(), Direction::Rx),
+            (277.7, 1, std_frame(0x123, &[]), Direction::Rx),
+        ];
+        assert_eq!(records.len(), expected.len());
+        for (record, expected) in records.iter().zip(&expected) {
+            assert!((record.0 - expected.0).abs() < 0.000_5, "{record:?}");
+            assert_eq!((record.1, record.2, record.3), (expected.1, expected.2, expected.3));
+        }
+    }
+
+    #[test]
+    fn other_versions_fail_naming_the_supported_ones() {
+        let error = |text: &str| read_trc(text.as_bytes()).unwrap_err().to_string();
+        assert_eq!(error(";$FILEVERSION=1.3\n"), "TRC version 1.3; only TRC versions 1.1, 2.0 and 2.1 are supported");
+        assert_eq!(error(";$FILEVERSION=3.0\n      1      1059.900 DT 1      0300 Rx -  0\n"), "TRC version 3.0; only TRC versions 1.1, 2.0 and 2.1 are supported");
+        assert_eq!(
+            error(";   Start time: 07.05.2014 11:09:27.047.8\n     1)      1059.9  Rx         0300  0\n"),
+            "no ;$FILEVERSION line before the first message (TRC 1.0 or older?); only TRC versions 1.1, 2.0 and 2.1 are supported"
+        );
+        assert_eq!(error(""), "no ;$FILEVERSION line; only TRC versions 1.1, 2.0 and 2.1 are supported");
+        assert_eq!(error(";$FILEVERSION=2.1\n;$STARTTIME=yesterday\n"), "line 2: invalid start time 'yesterday'");
+    }
+
+    #[test]
+    fn malformed_lines_are_reported_by_number() {
+        let text = ";$FILEVERSION=2.1\n;$COLUMNS=N,O,T,B,I,d,R,L,D\n\
+            1 -1.000 DT 1 0300 Rx - 0\n\
+            2 1.000 DT 3 0300 Rx - 0\n\
+            3 1.000 DT 1 0800 Rx - 0\n\
+            4 1.000 DT 1 20000000 Rx - 0\n\
+            5 1.000 DT 1 0300 Up - 0\n\
+            6 1.000 DT 1 0300 Rx - 2 00\n\
+            7 1.000 DT 1 0300 Rx - 1 0G\n\
+            8 1.000 RR 1 0300 Rx - 9\n\
+            9 1.000 DT 1\n\
+            10 1.000 DT 1 0300 Rx - 1 01\n";
+        let log = read_trc(text.as_bytes()).unwrap();
+        assert_eq!(log.records.len(), 1);
+        let reasons: Vec<(usize, &str)> = log.errors.iter().map(|(line, reason)| (*line, reason.as_str())).collect();
+        assert_eq!(
+            reasons,
+            [
+                (3, "invalid time offset '-1.000'"),
+                (4, "unknown bus '3', expected 1 or 2"),
+                (5, "standard CAN ID '0800' exceeds 0x7FF; extended IDs have 8 digits"),
+                (6, "CAN ID '20000000' exceeds 29 bits"),
+                (7, "invalid direction 'Up', expected Rx or Tx"),
+                (8, "1 data bytes for a data length of 2"),
+                (9, "invalid data byte '0G'"),
+                (10, "RTR length 9 exceeds 8"),
+                (11, "line ends early"),
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_pcan_view_lines() {
+        let line = format_trc_line(1, Duration::from_micros(811_000), 0, &std_frame(0xA0, &[0, 1, 2]), Direction::Rx);
+        assert_eq!(line, "      1       811.000 DT 1      00A0 Rx -  3    00 01 02");
+        let line = format_trc_line(12, Duration::from_micros(1_336_512), 1, &Frame::remote(Id::Extended(0x18EF_C034), 4).unwrap(), Direction::Tx);
+        assert_eq!(line, "     12      1336.512 RR 2  18EFC034 Tx -  4");
+        // Lines as PCAN-View writes them, from the 2.1 fixture.
+        let frame = Frame::new(Id::Extended(0x18EF_C034), &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        assert_eq!(format_trc_line(8, Duration::from_micros(1_335_156), 0, &frame, Direction::Tx), PCAN_VIEW_2_1.lines().nth(25).unwrap());
+        assert_eq!(format_trc_line(10, Duration::from_micros(1_337_600), 1, &std_frame(0x123, &[]), Direction::Rx), PCAN_VIEW_2_1.lines().nth(27).unwrap());
+    }
+
+    fn write(start: SystemTime, frames: &[(Duration, u32, Frame, Direction)]) -> String {
+        let mut writer = TrcWriter::with_start(Vec::new(), start);
+        for &(offset, channel, frame, direction) in frames {
+            let frame = Frame { host_time: Some((start + offset, Instant::now())), ..frame };
+            writer.write_frame(channel, &frame, direction).unwrap();
+        }
+        writer.finish().unwrap();
+        String::from_utf8(writer.out).unwrap()
+    }
+
+    #[test]
+    fn written_traces_read_back() {
+        let start = UNIX_EPOCH + Duration::from_micros(1_791_972_000_123_456);
+        let frames = [
+            (Duration::from_micros(1_000), 0, std_frame(0x123, &[0x11, 0x22, 0x33]), Direction::Rx),
+            (Duration::from_micros(12_345), 1, Frame::new(Id::Extended(0x18FF_50E5), &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap(), Direction::Tx),
+            (Duration::from_micros(12_345), 0, Frame::remote(Id::Standard(0x7FF), 8).unwrap(), Direction::Rx),
+            (Duration::from_micros(99_999_999), 1, Frame::new(Id::Extended(0), &[]).unwrap(), Direction::Rx),
+        ];
+        let written = write(start, &frames);
+        assert!(written.contains(";   Start time: 14.10.2026 10:00:00.123.4\n"), "{written}");
+        let log = read_trc(written.as_bytes()).unwrap();
+        assert_eq!(log.version, "2.1");
+        assert!(log.errors.is_empty(), "{:?}", log.errors);
+        assert_eq!(log.records.len(), frames.len());
+        let start = start.duration_since(UNIX_EPOCH).unwrap();
+        for (record, &(offset, channel, frame, direction)) in log.records.iter().zip(&frames) {
+            // The start time has 10 decimals of a day, about 9 µs.
+            assert!(record.time.abs_diff(start + offset) < Duration::from_micros(10), "{record:?}");
+            assert_eq!((record.channel, record.frame, record.direction), (channel, frame, direction));
+        }
+    }
+
+    #[test]
+    fn random_traces_read_back() {
+        let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+        let mut offset = Duration::ZERO;
+        let frames: Vec<(Duration, u32, Frame, Direction)> = (0..2000)
+            .map(|_| {
+                offset += Duration::from_micros(rng.below(5_000));
+                let len = rng.below(9) as usize;
+                let data = rng.next().to_le_bytes();
+                let id = match rng.below(2) {
+                    0 => Id::Standard(rng.below(0x800) as u16),
+                    _ => Id::Extended(rng.below(0x2000_0000) as u32),
+                };
+                let frame = match rng.below(5) {
+                    0 => Frame::remote(id, len as u8).unwrap(),
+                    _ => Frame::new(id, &data[..len]).unwrap(),
+                };
+                let direction = if rng.below(2) == 0 { Direction::Rx } else { Direction::Tx };
+                (offset, rng.below(2) as u32, frame, direction)
+            })
+            .collect();
+        let log = read_trc(write(UNIX_EPOCH + Duration::from_secs(1_791_972_000), &frames).as_bytes()).unwrap();
+        assert!(log.errors.is_empty(), "{:?}", log.errors);
+        let first = log.records[0].time;
+        let read: Vec<_> = log.records.iter().map(|record| (record.time - first, record.channel, record.frame, record.direction)).collect();
+        let written: Vec<_> = frames.iter().map(|&(offset, channel, frame, direction)| (offset - frames[0].0, channel, frame, direction)).collect();
+        assert_eq!(read, written);
+    }
+}
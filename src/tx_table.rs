@@ -0,0 +1,290 @@
+use std::fmt;
+use std::time::Duration;
+
+use toml::{Table, Value};
+
+use crate::checksum::Checksum;
+use crate::device::CHANNEL_COUNT;
+use crate::frame::Frame;
+use crate::id::Id;
+use crate::integrity::{ChecksumField, CounterField, IntegritySpec};
+
+const FIELDS: [&str; 11] =
+    ["id", "extended", "channel", "period_ms", "data", "rtr", "dlc", "start_delay_ms", "repeat", "counter", "checksum"];
+const COUNTER_FIELDS: [&str; 3] = ["byte", "shift", "width"];
+const CHECKSUM_FIELDS: [&str; 4] = ["algorithm", "byte", "first", "last"];
+
+/// One cyclic message of a transmit table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxEntry {
+    pub name: String,
+    pub channel: u32,
+    pub frame: Frame,
+    pub period: Duration,
+    pub start_delay: Duration,
+    /// Number of sends, `None` for forever.
+    pub repeat: Option<u64>,
+    /// Counter and checksum filled in on every send, see [`Scheduler::protect`](crate::Scheduler::protect).
+    pub integrity: IntegritySpec,
+}
+
+impl fmt::Display for TxEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: CAN{} ID={} ", self.name, self.channel + 1, self.frame.id())?;
+        if self.frame.is_remote() {
+            write!(f, "RTR dlc={}", self.frame.dlc())?;
+        } else {
+            write!(f, "Data={:02X?}", self.frame.data())?;
+        }
+        write!(f, " every {} ms", self.period.as_millis())?;
+        if !self.start_delay.is_zero() {
+            write!(f, ", starting after {} ms", self.start_delay.as_millis())?;
+        }
+        if let Some(repeat) = self.repeat {
+            write!(f, ", {repeat} times")?;
+        }
+        if let Some(counter) = self.integrity.counter {
+            write!(f, ", counter in byte {} bits {}-{}", counter.byte, counter.shift, counter.shift + counter.width - 1)?;
+        }
+        if let Some(checksum) = &self.integrity.checksum {
+            write!(f, ", {} in byte {}", checksum.algorithm, checksum.byte)?;
+            if let Some(range) = &checksum.range {
+                write!(f, " over bytes {}-{}", range.start, range.end - 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxTableError {
+    pub entry: Option<String>,
+    pub field: Option<&'static str>,
+    pub reason: String,
+}
+
+impl fmt::Display for TxTableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.entry, self.field) {
+            (Some(entry), Some(field)) => write!(f, "entry '{entry}', field '{field}': {}", self.reason),
+            (Some(entry), None) => write!(f, "entry '{entry}': {}", self.reason),
+            _ => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+impl std::error::Error for TxTableError {}
+
+/// Parses a transmit table with one TOML table per message, in file order:
+///
+/// ```toml
+/// [EngineCmd]
+/// id = 0x123            # or "0x123"
+/// extended = false      # optional, default false
+/// channel = 0
+/// period_ms = 10
+/// data = "DEADBEEF"     # or rtr = true with dlc = 8
+/// start_delay_ms = 500  # optional
+/// repeat = 20           # optional, default forever
+/// counter = { byte = 6, shift = 0, width = 4 }           # optional rolling counter
+/// checksum = { algorithm = "crc8", byte = 7, first = 0, last = 6 }  # optional
+/// ```
+///
+/// The counter goes up by one per send, wrapping at `width` bits (default 4, from bit `shift`,
+/// default 0). The checksum (`xor`, `sum-complement` or `crc8`) is recomputed after it over
+/// bytes `first` to `last`, by default the whole payload, leaving out the checksum byte itself.
+pub fn parse_tx_table(text: &str) -> Result<Vec<TxEntry>, TxTableError> {
+    let table: Table = text.parse().map_err(|err: toml::de::Error| {
+        let line = err.span().map_or(0, |span| text[..span.start].matches('\n').count() + 1);
+        TxTableError {
+            entry: None,
+            field: None,
+            reason: format!("line {line}: {}", err.message().trim_end()),
+        }
+    })?;
+    table
+        .iter()
+        .map(|(name, value)| {
+            let fields = value.as_table().ok_or_else(|| TxTableError {
+                entry: Some(name.clone()),
+                field: None,
+                reason: "expected a table of fields".to_string(),
+            })?;
+            parse_entry(name, fields)
+        })
+        .collect()
+}
+
+fn parse_entry(name: &str, fields: &Table) -> Result<TxEntry, TxTableError> {
+    let err = |field: Option<&'static str>, reason: String| TxTableError {
+        entry: Some(name.to_string()),
+        field,
+        reason,
+    };
+    if let Some(unknown) = fields.keys().find(|key| !FIELDS.contains(&key.as_str())) {
+        return Err(err(None, format!("unknown field '{unknown}'")));
+    }
+
+    let integer = |field: &'static str| -> Result<Option<u64>, TxTableError> {
+        match fields.get(field) {
+            None => Ok(None),
+            Some(Value::Integer(n)) if *n >= 0 => Ok(Some(*n as u64)),
+            Some(_) => Err(err(Some(field), "expected a non-negative integer".to_string())),
+        }
+    };
+    let boolean = |field: &'static str| -> Result<bool, TxTableError> {
+        match fields.get(field) {
+            None => Ok(false),
+            Some(Value::Boolean(b)) => Ok(*b),
+            Some(_) => Err(err(Some(field), "expected true or false".to_string())),
+        }
+    };
+
+    let raw_id = match fields.get("id") {
+        None => return Err(err(Some("id"), "missing".to_string())),
+        Some(Value::Integer(n)) => u32::try_from(*n).map_err(|_| err(Some("id"), format!("{n} is out of range")))?,
+        Some(Value::String(s)) => {
+            let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+            u32::from_str_radix(digits, 16).map_err(|_| err(Some("id"), format!("invalid hex ID '{s}'")))?
+        }
+        Some(_) => return Err(err(Some("id"), "expected an integer or hex string".to_string())),
+    };
+    let id = if boolean("extended")? {
+        Id::extended(raw_id).ok_or_else(|| err(Some("id"), format!("0x{raw_id:X} exceeds 29 bits")))?
+    } else {
+        u16::try_from(raw_id)
+            .ok()
+            .and_then(Id::standard)
+            .ok_or_else(|| err(Some("id"), format!("0x{raw_id:X} exceeds 11 bits; set extended = true")))?
+    };
+
+    let channel = integer("channel")?.ok_or_else(|| err(Some("channel"), "missing".to_string()))?;
+    if channel >= CHANNEL_COUNT as u64 {
+        return Err(err(Some("channel"), format!("must be below {CHANNEL_COUNT}")));
+    }
+    let period = match integer("period_ms")? {
+        Some(0) => return Err(err(Some("period_ms"), "must be at least 1".to_string())),
+        Some(ms) => Duration::from_millis(ms),
+        None => return Err(err(Some("period_ms"), "missing".to_string())),
+    };
+
+    let data = match fields.get("data") {
+        None => "",
+        Some(Value::String(s)) => s.as_str(),
+        Some(_) => return Err(err(Some("data"), "expected a hex string".to_string())),
+    };
+    let frame = if boolean("rtr")? {
+        if !data.is_empty() {
+            return Err(err(Some("data"), "remote frames carry no data; use dlc".to_string()));
+        }
+        let dlc = integer("dlc")?.unwrap_or(0);
+        u8::try_from(dlc)
+            .ok()
+            .and_then(|dlc| Frame::remote(id, dlc))
+            .ok_or_else(|| err(Some("dlc"), format!("{dlc} exceeds 8")))?
+    } else {
+        let hex: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+        if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+            return Err(err(Some("data"), format!("expected an even number of hex digits, got '{data}'")));
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| err(Some("data"), format!("invalid hex data '{data}'")))?;
+        Frame::new(id, &bytes).ok_or_else(|| err(Some("data"), format!("{} bytes is more than 8", bytes.len())))?
+    };
+
+    if frame.is_remote() {
+        if let Some(field) = ["counter", "checksum"].into_iter().find(|field| fields.contains_key(*field)) {
+            return Err(err(Some(field), "remote frames carry no data".to_string()));
+        }
+    }
+    let integrity = IntegritySpec {
+        counter: fields
+            .get("counter")
+            .map(|value| parse_counter(value, frame.dlc()))
+            .transpose()
+            .map_err(|reason| err(Some("counter"), reason))?,
+        checksum: fields
+            .get("checksum")
+            .map(|value| parse_checksum(value, frame.dlc()))
+            .transpose()
+            .map_err(|reason| err(Some("checksum"), reason))?,
+    };
+    if let (Some(counter), Some(checksum)) = (integrity.counter, &integrity.checksum) {
+        if counter.byte == checksum.byte {
+            return Err(err(Some("checksum"), "shares its byte with the counter".to_string()));
+        }
+    }
+
+    let repeat = match integer("repeat")? {
+        Some(0) => return Err(err(Some("repeat"), "must be at least 1 (omit it to repeat forever)".to_string())),
+        repeat => repeat,
+    };
+
+    Ok(TxEntry {
+        name: name.to_string(),
+        channel: channel as u32,
+        frame,
+        period,
+        start_delay: Duration::from_millis(integer("start_delay_ms")?.unwrap_or(0)),
+        repeat,
+        integrity,
+    })
+}
+
+/// An inline table like `{ byte = 6, width = 4 }`, refusing unknown keys.
+fn inline_table(value: &Value, known: &[&str]) -> Result<Table, String> {
+    let table = value.as_table().ok_or("expected an inline table such as { byte = 7 }")?;
+    if let Some(unknown) = table.keys().find(|key| !known.contains(&key.as_str())) {
+        return Err(format!("unknown key '{unknown}'"));
+    }
+    Ok(table.clone())
+}
+
+fn small_integer(table: &Table, key: &str) -> Result<Option<u8>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Integer(n)) => u8::try_from(*n).map(Some).map_err(|_| format!("{key} = {n} is out of range")),
+        Some(_) => Err(format!("{key}: expected an integer")),
+    }
+}
+
+fn parse_counter(value: &Value, dlc: u8) -> Result<CounterField, String> {
+    let table = inline_table(value, &COUNTER_FIELDS)?;
+    let byte = small_integer(&table, "byte")?.ok_or("byte is missing")?;
+    let shift = small_integer(&table, "shift")?.unwrap_or(0);
+    let width = small_integer(&table, "width")?.unwrap_or(4);
+    if byte >= dlc {
+        return Err(format!("byte {byte} is past the {dlc}-byte payload"));
+    }
+    if width == 0 || shift + width > 8 {
+        return Err(format!("{width} bits from bit {shift} don't fit in a byte"));
+    }
+    Ok(CounterField { byte: byte.into(), shift, width })
+}
+
+fn parse_checksum(value: &Value, dlc: u8) -> Result<ChecksumField, String> {
+    let table = inline_table(value, &CHECKSUM_FIELDS)?;
+    let algorithm: Checksum = match table.get("algorithm") {
+        Some(Value::String(name)) => name.parse()?,
+        Some(_) => return Err("algorithm: expected a string".to_string()),
+        None => return Err("algorithm is missing".to_string()),
+    };
+    let byte = small_integer(&table, "byte")?.ok_or("byte is missing")?;
+    if byte >= dlc {
+        return Err(format!("byte {byte} is past the {dlc}-byte payload"));
+    }
+    let range = match (small_integer(&table, "first")?, small_integer(&table, "last")?) {
+        (None, None) => None,
+        (first, last) => {
+            let (first, last) = (first.unwrap_or(0), last.unwrap_or(dlc.saturating_sub(1)));
+            if first > last || last >= dlc {
+                return Err(format!("bytes {first}-{last} aren't within the {dlc}-byte payload"));
+            }
+            Some(usize::from(first)..usize::from(last) + 1)
+        }
+    };
+    Ok(ChecksumField { algorithm, byte: byte.into(), range })
+}
@@ -0,0 +1,400 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::isotp::{IsoTpError, IsoTpSocket, START_OF_MESSAGE};
+use crate::scheduler::{CyclicId, Scheduler};
+
+const NEGATIVE_RESPONSE: u8 = 0x7F;
+const POSITIVE_OFFSET: u8 = 0x40;
+/// Sub-function bit asking the server not to send a positive response.
+const SUPPRESS_POSITIVE: u8 = 0x80;
+
+/// UDS (ISO 14229) service identifiers used by [`UdsClient`].
+const DIAGNOSTIC_SESSION_CONTROL: u8 = 0x10;
+const ECU_RESET: u8 = 0x11;
+const READ_DATA_BY_IDENTIFIER: u8 = 0x22;
+const WRITE_DATA_BY_IDENTIFIER: u8 = 0x2E;
+const ROUTINE_CONTROL: u8 = 0x31;
+const TESTER_PRESENT: u8 = 0x3E;
+
+/// Negative response code from a `7F` response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Nrc(pub u8);
+
+impl Nrc {
+    pub const GENERAL_REJECT: Nrc = Nrc(0x10);
+    pub const SERVICE_NOT_SUPPORTED: Nrc = Nrc(0x11);
+    pub const SUB_FUNCTION_NOT_SUPPORTED: Nrc = Nrc(0x12);
+    pub const INCORRECT_MESSAGE_LENGTH: Nrc = Nrc(0x13);
+    pub const RESPONSE_TOO_LONG: Nrc = Nrc(0x14);
+    pub const BUSY_REPEAT_REQUEST: Nrc = Nrc(0x21);
+    pub const CONDITIONS_NOT_CORRECT: Nrc = Nrc(0x22);
+    pub const REQUEST_SEQUENCE_ERROR: Nrc = Nrc(0x24);
+    pub const NO_RESPONSE_FROM_SUBNET: Nrc = Nrc(0x25);
+    pub const FAILURE_PREVENTS_EXECUTION: Nrc = Nrc(0x26);
+    pub const REQUEST_OUT_OF_RANGE: Nrc = Nrc(0x31);
+    pub const SECURITY_ACCESS_DENIED: Nrc = Nrc(0x33);
+    pub const INVALID_KEY: Nrc = Nrc(0x35);
+    pub const EXCEEDED_NUMBER_OF_ATTEMPTS: Nrc = Nrc(0x36);
+    pub const REQUIRED_TIME_DELAY_NOT_EXPIRED: Nrc = Nrc(0x37);
+    pub const UPLOAD_DOWNLOAD_NOT_ACCEPTED: Nrc = Nrc(0x70);
+    pub const TRANSFER_DATA_SUSPENDED: Nrc = Nrc(0x71);
+    pub const GENERAL_PROGRAMMING_FAILURE: Nrc = Nrc(0x72);
+    pub const WRONG_BLOCK_SEQUENCE_COUNTER: Nrc = Nrc(0x73);
+    /// Not an error: the server needs more time and will answer within P2*.
+    pub const RESPONSE_PENDING: Nrc = Nrc(0x78);
+    pub const SUB_FUNCTION_NOT_SUPPORTED_IN_SESSION: Nrc = Nrc(0x7E);
+    pub const SERVICE_NOT_SUPPORTED_IN_SESSION: Nrc = Nrc(0x7F);
+
+    const NAMES: [(Nrc, &'static str); 22] = [
+        (Self::GENERAL_REJECT, "generalReject"),
+        (Self::SERVICE_NOT_SUPPORTED, "serviceNotSupported"),
+        (Self::SUB_FUNCTION_NOT_SUPPORTED, "subFunctionNotSupported"),
+        (Self::INCORRECT_MESSAGE_LENGTH, "incorrectMessageLengthOrInvalidFormat"),
+        (Self::RESPONSE_TOO_LONG, "responseTooLong"),
+        (Self::BUSY_REPEAT_REQUEST, "busyRepeatRequest"),
+        (Self::CONDITIONS_NOT_CORRECT, "conditionsNotCorrect"),
+        (Self::REQUEST_SEQUENCE_ERROR, "requestSequenceError"),
+        (Self::NO_RESPONSE_FROM_SUBNET, "noResponseFromSubnetComponent"),
+        (Self::FAILURE_PREVENTS_EXECUTION, "failurePreventsExecutionOfRequestedAction"),
+        (Self::REQUEST_OUT_OF_RANGE, "requestOutOfRange"),
+        (Self::SECURITY_ACCESS_DENIED, "securityAccessDenied"),
+        (Self::INVALID_KEY, "invalidKey"),
+        (Self::EXCEEDED_NUMBER_OF_ATTEMPTS, "exceededNumberOfAttempts"),
+        (Self::REQUIRED_TIME_DELAY_NOT_EXPIRED, "requiredTimeDelayNotExpired"),
+        (Self::UPLOAD_DOWNLOAD_NOT_ACCEPTED, "uploadDownloadNotAccepted"),
+        (Self::TRANSFER_DATA_SUSPENDED, "transferDataSuspended"),
+        (Self::GENERAL_PROGRAMMING_FAILURE, "generalProgrammingFailure"),
+        (Self::WRONG_BLOCK_SEQUENCE_COUNTER, "wrongBlockSequenceCounter"),
+        (Self::RESPONSE_PENDING, "requestCorrectlyReceived-ResponsePending"),
+        (Self::SUB_FUNCTION_NOT_SUPPORTED_IN_SESSION, "subFunctionNotSupportedInActiveSession"),
+        (Self::SERVICE_NOT_SUPPORTED_IN_SESSION, "serviceNotSupportedInActiveSession"),
+    ];
+
+    /// ISO 14229 name of the code, if it is one of the common ones.
+    pub fn name(self) -> Option<&'static str> {
+        Self::NAMES.iter().find(|(nrc, _)| *nrc == self).map(|&(_, name)| name)
+    }
+}
+
+impl fmt::Display for Nrc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{name} (0x{:02X})", self.0),
+            None => write!(f, "NRC 0x{:02X}", self.0),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum UdsError {
+    IsoTp(IsoTpError),
+    /// No response within P2, or within P2* after a response-pending.
+    NoResponse { service: u8 },
+    Negative { service: u8, nrc: Nrc },
+    /// A positive response that doesn't match the request, e.g. echoing another DID.
+    Unexpected { service: u8, response: Vec<u8> },
+}
+
+impl fmt::Display for UdsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IsoTp(err) => err.fmt(f),
+            Self::NoResponse { service } => write!(f, "no response to service 0x{service:02X}"),
+            Self::Negative { service, nrc } => write!(f, "service 0x{service:02X} rejected: {nrc}"),
+            Self::Unexpected { service, response } => {
+                write!(f, "unexpected response to service 0x{service:02X}: {response:02X?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for UdsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::IsoTp(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<IsoTpError> for UdsError {
+    fn from(err: IsoTpError) -> Self {
+        Self::IsoTp(err)
+    }
+}
+
+/// Diagnostic client talking to one ECU over an [`IsoTpSocket`]. Requests are blocking and one
+/// at a time; response-pending answers extend the wait from `p2` to `p2_star`.
+pub struct UdsClient {
+    socket: IsoTpSocket,
+    /// How long the ECU has to start answering a request.
+    pub p2: Duration,
+    /// How long to wait after each response-pending (NRC 0x78).
+    pub p2_star: Duration,
+}
+
+impl UdsClient {
+    /// P2 150 ms, P2* 5 s.
+    pub fn new(socket: IsoTpSocket) -> Self {
+        Self { socket, p2: Duration::from_millis(150), p2_star: Duration::from_secs(5) }
+    }
+
+    pub fn socket(&self) -> &IsoTpSocket {
+        &self.socket
+    }
+
+    /// Sends a raw request (service ID first) and returns the positive response after its
+    /// service ID.
+    pub fn request(&self, request: &[u8]) -> Result<Vec<u8>, UdsError> {
+        let service = request.first().copied().unwrap_or_default();
+        self.socket.send(request)?;
+        let mut deadline = Instant::now() + self.p2;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let response = match self.socket.recv(left) {
+                Ok(response) => response,
+                Err(IsoTpError::Timeout { waiting_for: START_OF_MESSAGE }) => {
+                    return Err(UdsError::NoResponse { service });
+                }
+                Err(err) => return Err(err.into()),
+            };
+            match *response.as_slice() {
+                [NEGATIVE_RESPONSE, sid, code, ..] if sid == service => match Nrc(code) {
+                    Nrc::RESPONSE_PENDING => deadline = Instant::now() + self.p2_star,
+                    nrc => return Err(UdsError::Negative { service, nrc }),
+                },
+                [sid, ..] if sid == service.wrapping_add(POSITIVE_OFFSET) => return Ok(response[1..].to_vec()),
+                // Late answers to earlier requests, or traffic for someone else.
+                _ => {}
+            }
+        }
+    }
+
+    /// Switches to `session` (1 default, 2 programming, 3 extended) and returns the session
+    /// parameter record (P2 and P2* as the server reports them).
+    pub fn diagnostic_session_control(&self, session: u8) -> Result<Vec<u8>, UdsError> {
+        let response = self.request(&[DIAGNOSTIC_SESSION_CONTROL, session])?;
+        strip_echo(DIAGNOSTIC_SESSION_CONTROL, response, &[session & !SUPPRESS_POSITIVE])
+    }
+
+    /// Resets the ECU (1 hard, 2 key off/on, 3 soft).
+    pub fn ecu_reset(&self, reset_type: u8) -> Result<(), UdsError> {
+        let response = self.request(&[ECU_RESET, reset_type])?;
+        strip_echo(ECU_RESET, response, &[reset_type & !SUPPRESS_POSITIVE]).map(drop)
+    }
+
+    pub fn read_data_by_identifier(&self, did: u16) -> Result<Vec<u8>, UdsError> {
+        let [high, low] = did.to_be_bytes();
+        let response = self.request(&[READ_DATA_BY_IDENTIFIER, high, low])?;
+        strip_echo(READ_DATA_BY_IDENTIFIER, response, &[high, low])
+    }
+
+    pub fn write_data_by_identifier(&self, did: u16, data: &[u8]) -> Result<(), UdsError> {
+        let [high, low] = did.to_be_bytes();
+        let response = self.request(&[&[WRITE_DATA_BY_IDENTIFIER, high, low], data].concat())?;
+        strip_echo(WRITE_DATA_BY_IDENTIFIER, response, &[high, low]).map(drop)
+    }
+
+    /// Starts (1), stops (2) or asks for the results of (3) `routine`, returning the routine
+    /// status record.
+    pub fn routine_control(&self, control: u8, routine: u16, data: &[u8]) -> Result<Vec<u8>, UdsError> {
+        let [high, low] = routine.to_be_bytes();
+        let response = self.request(&[&[ROUTINE_CONTROL, control, high, low], data].concat())?;
+        strip_echo(ROUTINE_CONTROL, response, &[control & !SUPPRESS_POSITIVE, high, low])
+    }
+
+    pub fn tester_present(&self) -> Result<(), UdsError> {
+        let response = self.request(&[TESTER_PRESENT, 0x00])?;
+        strip_echo(TESTER_PRESENT, response, &[0x00]).map(drop)
+    }
+
+    /// Keeps the session alive with a TesterPresent (positive response suppressed) every
+    /// `period`, sent by `scheduler`; remove the returned entry to stop.
+    pub fn keep_alive(&self, scheduler: &Scheduler, period: Duration) -> CyclicId {
+        let frame = self
+            .socket
+            .single_frame(&[TESTER_PRESENT, SUPPRESS_POSITIVE])
+            .expect("TesterPresent fits in a single frame");
+        scheduler.add(self.socket.channel(), frame, period)
+    }
+}
+
+/// Checks that a positive response starts with `echo` and returns what follows.
+fn strip_echo(service: u8, response: Vec<u8>, echo: &[u8]) -> Result<Vec<u8>, UdsError> {
+    match response.strip_prefix(echo) {
+        Some(rest) => Ok(rest.to_vec()),
+        None => Err(UdsError::Unexpected { service, response }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::{self, JoinHandle};
+
+    use super::*;
+    use crate::device::Device;
+    use crate::frame::Frame;
+    use crate::id::Id;
+    use crate::isotp::IsoTpConfig;
+    use crate::mock::{started_pair, MockBackend};
+
+    const TESTER: u16 = 0x7E0;
+    const ECU: u16 = 0x7E8;
+
+    /// What the fake ECU does once a request arrives.
+    enum Reply {
+        Send(&'static [u8]),
+        Wait(u64),
+    }
+    use Reply::{Send, Wait};
+
+    /// An ISO-TP peer on CAN2 answering each request in `script` in turn, after checking it is
+    /// the request expected. Returns the client on CAN1, and the ECU thread, which fails if a
+    /// request differs from the script.
+    struct Bench {
+        client: UdsClient,
+        ecu: JoinHandle<()>,
+        mock: std::sync::Arc<MockBackend>,
+        can2: crate::device::Channel,
+        _device: Device,
+    }
+
+    fn bench(script: Vec<(&'static [u8], Vec<Reply>)>) -> Bench {
+        let (mock, device, can1, can2) = started_pair();
+        let id = |id| Id::standard(id).unwrap();
+        let ecu_socket = IsoTpSocket::new(&can2, IsoTpConfig::new(id(ECU), id(TESTER)));
+        let ecu = thread::spawn(move || {
+            for (expected, replies) in script {
+                let request = ecu_socket.recv(Duration::from_secs(2)).expect("a request");
+                assert_eq!(request, expected);
+                for reply in replies {
+                    match reply {
+                        Send(response) => ecu_socket.send(response).unwrap(),
+                        Wait(ms) => thread::sleep(Duration::from_millis(ms)),
+                    }
+                }
+            }
+        });
+        let client = UdsClient::new(IsoTpSocket::new(&can1, IsoTpConfig::new(id(TESTER), id(ECU))));
+        Bench { client, ecu, mock, can2, _device: device }
+    }
+
+    impl Bench {
+        fn finish(self) {
+            self.ecu.join().expect("the ECU saw the requests it was scripted for");
+        }
+    }
+
+    #[test]
+    fn services_against_a_scripted_ecu() {
+        let bench = bench(vec![
+            (&[0x10, 0x03], vec![Send(&[0x50, 0x03, 0x00, 0x32, 0x01, 0xF4])]),
+            (&[0x22, 0xF1, 0x90], vec![Send(b"\x62\xF1\x90WDB1234567A123456")]),
+            (&[0x2E, 0xF1, 0x98, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06], vec![Send(&[0x6E, 0xF1, 0x98])]),
+            (&[0x31, 0x01, 0xFF, 0x00, 0xAA], vec![Send(&[0x71, 0x01, 0xFF, 0x00, 0x02])]),
+            (&[0x3E, 0x00], vec![Send(&[0x7E, 0x00])]),
+            (&[0x11, 0x01], vec![Send(&[0x51, 0x01])]),
+        ]);
+        let client = &bench.client;
+        assert_eq!(client.diagnostic_session_control(0x03).unwrap(), [0x00, 0x32, 0x01, 0xF4]);
+        assert_eq!(client.read_data_by_identifier(0xF190).unwrap(), b"WDB1234567A123456", "a segmented response");
+        client.write_data_by_identifier(0xF198, &[1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(client.routine_control(0x01, 0xFF00, &[0xAA]).unwrap(), [0x02]);
+        client.tester_present().unwrap();
+        client.ecu_reset(0x01).unwrap();
+        bench.finish();
+    }
+
+    #[test]
+    fn response_pending_extends_the_wait_to_p2_star() {
+        let mut bench = bench(vec![(
+            &[0x31, 0x01, 0x02, 0x03],
+            vec![Send(&[0x7F, 0x31, 0x78]), Wait(150), Send(&[0x7F, 0x31, 0x78]), Wait(150), Send(&[0x71, 0x01, 0x02, 0x03, 0x00])],
+        )]);
+        bench.client.p2 = Duration::from_millis(100);
+        bench.client.p2_star = Duration::from_millis(400);
+        let started = Instant::now();
+        assert_eq!(bench.client.routine_control(0x01, 0x0203, &[]).unwrap(), [0x00]);
+        assert!(started.elapsed() >= Duration::from_millis(300), "answered after {:?}", started.elapsed());
+        bench.finish();
+    }
+
+    #[test]
+    fn silence_is_no_response_within_p2_or_p2_star() {
+        let mut bench = bench(vec![
+            (&[0x3E, 0x00], vec![]),
+            (&[0x22, 0x12, 0x34], vec![Send(&[0x7F, 0x22, 0x78]), Wait(300)]),
+        ]);
+        bench.client.p2 = Duration::from_millis(100);
+        bench.client.p2_star = Duration::from_millis(200);
+        let started = Instant::now();
+        assert!(matches!(bench.client.tester_present(), Err(UdsError::NoResponse { service: 0x3E })));
+        assert!((Duration::from_millis(100)..Duration::from_millis(300)).contains(&started.elapsed()));
+        let started = Instant::now();
+        assert!(matches!(bench.client.read_data_by_identifier(0x1234), Err(UdsError::NoResponse { service: 0x22 })));
+        assert!(started.elapsed() >= Duration::from_millis(200), "gave up after {:?}", started.elapsed());
+        bench.finish();
+    }
+
+    #[test]
+    fn negative_responses_name_the_nrc() {
+        let bench = bench(vec![
+            (&[0x22, 0xF1, 0x90], vec![Send(&[0x7F, 0x22, 0x31])]),
+            (&[0x10, 0x02], vec![Send(&[0x7F, 0x10, 0x7E])]),
+            (&[0x11, 0x01], vec![Send(&[0x7F, 0x11, 0x78]), Send(&[0x7F, 0x11, 0x22])]),
+            (&[0x2E, 0x01, 0x00, 0xFF], vec![Send(&[0x7F, 0x2E, 0x99])]),
+        ]);
+        let client = &bench.client;
+        let err = client.read_data_by_identifier(0xF190).unwrap_err();
+        assert!(matches!(err, UdsError::Negative { service: 0x22, nrc: Nrc::REQUEST_OUT_OF_RANGE }), "{err:?}");
+        assert_eq!(err.to_string(), "service 0x22 rejected: requestOutOfRange (0x31)");
+        let err = client.diagnostic_session_control(0x02).unwrap_err();
+        assert_eq!(err.to_string(), "service 0x10 rejected: subFunctionNotSupportedInActiveSession (0x7E)");
+        let err = client.ecu_reset(0x01).unwrap_err();
+        assert!(matches!(err, UdsError::Negative { nrc: Nrc::CONDITIONS_NOT_CORRECT, .. }), "the NRC after a pending: {err:?}");
+        let err = client.write_data_by_identifier(0x0100, &[0xFF]).unwrap_err();
+        assert_eq!(err.to_string(), "service 0x2E rejected: NRC 0x99");
+        bench.finish();
+    }
+
+    #[test]
+    fn stray_and_mismatched_responses() {
+        let bench = bench(vec![
+            // A late answer to something else, and a negative response for another service,
+            // before the one asked for.
+            (&[0x22, 0xF1, 0x90], vec![Send(&[0x50, 0x01]), Send(&[0x7F, 0x10, 0x11]), Send(&[0x62, 0xF1, 0x90, 0x42])]),
+            (&[0x22, 0xF1, 0x91], vec![Send(&[0x62, 0xF1, 0x90, 0x42])]),
+        ]);
+        assert_eq!(bench.client.read_data_by_identifier(0xF190).unwrap(), [0x42]);
+        let err = bench.client.read_data_by_identifier(0xF191).unwrap_err();
+        assert!(matches!(&err, UdsError::Unexpected { service: 0x22, response } if response == &[0xF1, 0x90, 0x42]), "{err:?}");
+        bench.finish();
+    }
+
+    #[test]
+    fn keep_alive_sends_suppressed_tester_present() {
+        let bench = bench(vec![]);
+        let frames = bench.can2.subscribe(64);
+        let scheduler = Scheduler::new();
+        let entry = bench.client.keep_alive(&scheduler, Duration::from_millis(20));
+        let sent: Vec<Frame> = (0..3).map(|_| frames.recv_timeout(Duration::from_millis(500)).unwrap()).collect();
+        for frame in &sent {
+            assert_eq!(frame.id(), Id::Standard(TESTER));
+            assert_eq!(frame.data(), [0x02, 0x3E, 0x80, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC]);
+        }
+        assert!(scheduler.remove(entry));
+        thread::sleep(Duration::from_millis(50));
+        while frames.try_recv().is_ok() {}
+        assert!(frames.recv_timeout(Duration::from_millis(100)).is_err(), "stopped with the entry");
+        assert_eq!(bench.mock.take_transmitted(1), [], "the ECU never answers a suppressed request");
+        bench.finish();
+    }
+
+    #[test]
+    fn nrc_names() {
+        assert_eq!(Nrc::RESPONSE_PENDING.name(), Some("requestCorrectlyReceived-ResponsePending"));
+        assert_eq!(Nrc(0x33).to_string(), "securityAccessDenied (0x33)");
+        assert_eq!(Nrc(0x00).name(), None);
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::id::Id;
+
+/// Timer wheel resolution; timeouts are reported at most this late.
+const SLOT: Duration = Duration::from_millis(10);
+const SLOTS: usize = 512;
+
+/// A cyclic message that must keep arriving.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Expectation {
+    pub id: Id,
+    pub period: Duration,
+    /// Allowed lateness as a fraction of `period` (0.5 = ±50%).
+    pub tolerance: f64,
+}
+
+impl Expectation {
+    /// Longest acceptable gap before the message counts as missing.
+    pub fn window(&self) -> Duration {
+        self.period.mul_f64(1.0 + self.tolerance.max(0.0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogEvent {
+    /// `id` hasn't arrived within its window. `last_seen` is `None` if it never arrived.
+    Timeout { id: Id, last_seen: Option<Instant>, window: Duration },
+    /// `id` arrived again after a timeout; `outage` is the gap since the frame before.
+    Recovered { id: Id, outage: Duration },
+}
+
+struct Watch {
+    expectation: Expectation,
+    /// When the current window started: the last arrival, or the time it was declared.
+    since: Instant,
+    last_seen: Option<Instant>,
+    timed_out: bool,
+    /// Bumped on re-declaration so wheel entries for the old expectation are ignored.
+    generation: u32,
+}
+
+/// Detects missing cyclic messages.
+///
+/// Arrivals only update the message's own entry; deadlines live in a hashed timer wheel that
+/// [`Watchdog::poll`] advances, so the cost per frame doesn't grow with the number of
+/// declarations. Entries are rescheduled lazily when their slot comes up early.
+pub struct Watchdog {
+    watches: Vec<Watch>,
+    by_id: HashMap<Id, usize>,
+    wheel: Vec<Vec<(usize, u32)>>,
+    /// Start time of the slot at `cursor`.
+    wheel_time: Instant,
+    cursor: usize,
+    timeouts: u64,
+}
+
+impl Watchdog {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            watches: Vec::new(),
+            by_id: HashMap::new(),
+            wheel: vec![Vec::new(); SLOTS],
+            wheel_time: now,
+            cursor: 0,
+            timeouts: 0,
+        }
+    }
+
+    /// Starts watching `expectation.id`; the first frame is due one window after `now`.
+    /// Declaring an ID again replaces its expectation.
+    pub fn declare(&mut self, expectation: Expectation, now: Instant) {
+        let mut watch = Watch {
+            expectation,
+            since: now,
+            last_seen: None,
+            timed_out: false,
+            generation: 0,
+        };
+        let index = match self.by_id.get(&expectation.id) {
+            Some(&index) => {
+                watch.generation = self.watches[index].generation.wrapping_add(1);
+                self.watches[index] = watch;
+                index
+            }
+            None => {
+                self.watches.push(watch);
+                self.by_id.insert(expectation.id, self.watches.len() - 1);
+                self.watches.len() - 1
+            }
+        };
+        self.schedule(index, now + expectation.window());
+    }
+
+    /// Records an arrival. Returns a recovery event if the ID had timed out.
+    pub fn observe(&mut self, id: Id, now: Instant) -> Option<WatchdogEvent> {
+        let &index = self.by_id.get(&id)?;
+        let watch = &mut self.watches[index];
+        let previous = watch.last_seen.unwrap_or(watch.since);
+        watch.since = now;
+        watch.last_seen = Some(now);
+        if !watch.timed_out {
+            // Still in the wheel; the slot will see the new deadline and reschedule.
+            return None;
+        }
+        watch.timed_out = false;
+        let deadline = now + watch.expectation.window();
+        self.schedule(index, deadline);
+        Some(WatchdogEvent::Recovered { id, outage: now.saturating_duration_since(previous) })
+    }
+
+    /// Advances the wheel to `now` and returns the timeouts that fell due.
+    pub fn poll(&mut self, now: Instant) -> Vec<WatchdogEvent> {
+        let mut events = Vec::new();
+        while self.wheel_time + SLOT <= now {
+            let due = std::mem::take(&mut self.wheel[self.cursor]);
+            let slot_end = self.wheel_time + SLOT;
+            for (index, generation) in due {
+                let watch = &mut self.watches[index];
+                if watch.generation != generation {
+                    continue;
+                }
+                let deadline = watch.since + watch.expectation.window();
+                if deadline > slot_end {
+                    self.schedule_from(index, deadline, slot_end);
+                } else if !watch.timed_out {
+                    watch.timed_out = true;
+                    self.timeouts += 1;
+                    events.push(WatchdogEvent::Timeout {
+                        id: watch.expectation.id,
+                        last_seen: watch.last_seen,
+                        window: watch.expectation.window(),
+                    });
+                }
+            }
+            self.cursor = (self.cursor + 1) % SLOTS;
+            self.wheel_time = slot_end;
+        }
+        events
+    }
+
+    /// Timeouts reported so far.
+    pub fn timeouts(&self) -> u64 {
+        self.timeouts
+    }
+
+    /// IDs currently missing.
+    pub fn missing(&self) -> impl Iterator<Item = Id> + '_ {
+        self.watches.iter().filter(|w| w.timed_out).map(|w| w.expectation.id)
+    }
+
+    fn schedule(&mut self, index: usize, deadline: Instant) {
+        self.schedule_from(index, deadline, self.wheel_time);
+    }
+
+    /// Puts `index` in the slot covering `deadline`, counting slots from `from` (the start of
+    /// the cursor slot or the one after it). Deadlines beyond one turn of the wheel land early
+    /// and are rescheduled when reached.
+    fn schedule_from(&mut self, index: usize, deadline: Instant, from: Instant) {
+        let slots = |d: Duration| (d.as_nanos() / SLOT.as_nanos()).min(SLOTS as u128 - 1) as usize;
+        let base = self.cursor + slots(from.saturating_duration_since(self.wheel_time));
+        let ahead = slots(deadline.saturating_duration_since(from));
+        let generation = self.watches[index].generation;
+        self.wheel[(base + ahead) % SLOTS].push((index, generation));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::Frame;
+    use crate::mock::started_pair;
+
+    const HEARTBEAT: Id = Id::Standard(0x2A0);
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    /// `0x2A0 every 100ms ±50%`, a 150 ms window.
+    fn heartbeat() -> Expectation {
+        Expectation { id: HEARTBEAT, period: ms(100), tolerance: 0.5 }
+    }
+
+    /// Polls every millisecond from `from` up to `to`, with the arrivals at `arrivals`
+    /// (milliseconds from `start`) observed on the way, returning each event with its time.
+    fn run(watchdog: &mut Watchdog, start: Instant, arrivals: &[u64], to: u64) -> Vec<(u64, WatchdogEvent)> {
+        let mut events = Vec::new();
+        for now in 0..=to {
+            if arrivals.contains(&now) {
+                events.extend(watchdog.observe(HEARTBEAT, start + ms(now)).map(|event| (now, event)));
+            }
+            events.extend(watchdog.poll(start + ms(now)).into_iter().map(|event| (now, event)));
+        }
+        events
+    }
+
+    #[test]
+    fn messages_within_tolerance_never_time_out() {
+        let start = Instant::now();
+        let mut watchdog = Watchdog::new(start);
+        watchdog.declare(heartbeat(), start);
+        // Jittering between 50 and 149 ms apart.
+        let mut at = 0;
+        let arrivals: Vec<u64> = [100, 149, 50, 120, 149, 149, 80].iter().cycle().take(100).map(|gap| { at += gap; at }).collect();
+        assert!(run(&mut watchdog, start, &arrivals, at + 140).is_empty());
+        assert_eq!((watchdog.timeouts(), watchdog.missing().count()), (0, 0));
+        assert_eq!(heartbeat().window(), ms(150));
+    }
+
+    #[test]
+    fn a_gap_times_out_once_and_recovers_with_the_outage() {
+        let start = Instant::now();
+        let mut watchdog = Watchdog::new(start);
+        watchdog.declare(heartbeat(), start);
+        // Every 100 ms to 500, then nothing until 1234.
+        let arrivals = [100, 200, 300, 400, 500, 1234, 1334];
+        let events = run(&mut watchdog, start, &arrivals, 1400);
+        assert_eq!(events.len(), 2, "{events:?}");
+
+        let (at, timeout) = events[0];
+        assert_eq!(timeout, WatchdogEvent::Timeout { id: HEARTBEAT, last_seen: Some(start + ms(500)), window: ms(150) });
+        assert!((650..=650 + SLOT.as_millis() as u64).contains(&at), "reported at {at} ms for a 650 ms deadline");
+        assert_eq!(events[1], (1234, WatchdogEvent::Recovered { id: HEARTBEAT, outage: ms(734) }));
+        assert_eq!(watchdog.timeouts(), 1);
+        assert_eq!(watchdog.missing().count(), 0);
+    }
+
+    #[test]
+    fn a_message_that_never_arrives_is_missing_from_the_start() {
+        let start = Instant::now();
+        let mut watchdog = Watchdog::new(start);
+        watchdog.declare(heartbeat(), start + ms(30));
+        assert!(watchdog.poll(start + ms(179)).is_empty());
+        let events = watchdog.poll(start + ms(190));
+        assert_eq!(events, [WatchdogEvent::Timeout { id: HEARTBEAT, last_seen: None, window: ms(150) }]);
+        assert_eq!(watchdog.missing().collect::<Vec<_>>(), [HEARTBEAT]);
+        assert!(watchdog.poll(start + ms(5000)).is_empty(), "reported once");
+        assert_eq!(watchdog.observe(HEARTBEAT, start + ms(5030)), Some(WatchdogEvent::Recovered { id: HEARTBEAT, outage: ms(5000) }));
+        assert_eq!(watchdog.observe(Id::Standard(0x2A1), start + ms(5030)), None, "undeclared IDs are ignored");
+    }
+
+    #[test]
+    fn hundreds_of_declarations_and_periods_beyond_a_wheel_turn() {
+        let start = Instant::now();
+        let mut watchdog = Watchdog::new(start);
+        for n in 0..300u32 {
+            watchdog.declare(Expectation { id: Id::Extended(n), period: ms(10 + 10 * u64::from(n)), tolerance: 0.0 }, start);
+        }
+        let slow = Id::Standard(0x7FF);
+        watchdog.declare(Expectation { id: slow, period: Duration::from_secs(8), tolerance: 0.25 }, start);
+
+        let mut reported = HashMap::new();
+        for now in (0..=10_100).step_by(5) {
+            for event in watchdog.poll(start + ms(now)) {
+                let WatchdogEvent::Timeout { id, .. } = event else { panic!("{event:?}") };
+                assert!(reported.insert(id, now).is_none(), "{id} reported twice");
+            }
+        }
+        assert_eq!(reported.len(), 301);
+        for n in 0..300u32 {
+            let deadline = 10 + 10 * u64::from(n);
+            let at = reported[&Id::Extended(n)];
+            assert!((deadline..=deadline + SLOT.as_millis() as u64).contains(&at), "{n}: {at} ms for {deadline} ms");
+        }
+        let at = reported[&slow];
+        assert!((10_000..=10_010).contains(&at), "10 s deadline reported at {at} ms");
+    }
+
+    #[test]
+    fn declaring_again_replaces_the_expectation() {
+        let start = Instant::now();
+        let mut watchdog = Watchdog::new(start);
+        watchdog.declare(heartbeat(), start);
+        watchdog.declare(Expectation { period: ms(1000), ..heartbeat() }, start + ms(100));
+        assert!(watchdog.poll(start + ms(1600)).is_empty(), "the old 150 ms window is gone");
+        assert_eq!(watchdog.poll(start + ms(1610)).len(), 1);
+    }
+
+    #[test]
+    fn gaps_injected_into_the_mock_are_detected() {
+        let (mock, _device, _can1, can2) = started_pair();
+        let heartbeat_frame = Frame::new(HEARTBEAT, &[0]).unwrap();
+        let mut arrivals = Vec::new();
+        for _ in 0..3 {
+            mock.inject(1, &heartbeat_frame);
+            mock.inject(1, &Frame::new(Id::Standard(0x100), &[]).unwrap());
+            arrivals.extend(can2.receive(Duration::from_millis(500)).unwrap());
+        }
+        let times: Vec<Instant> = arrivals.iter().filter(|frame| frame.id() == HEARTBEAT).map(|frame| frame.instant().unwrap()).collect();
+        assert_eq!(times.len(), 3);
+
+        let mut watchdog = Watchdog::new(times[0]);
+        watchdog.declare(heartbeat(), times[0]);
+        for frame in &arrivals {
+            assert_eq!(watchdog.observe(frame.id(), frame.instant().unwrap()), None);
+        }
+        let last = times[2];
+        assert!(watchdog.poll(last + ms(149)).is_empty());
+        assert!(matches!(watchdog.poll(last + ms(160))[..], [WatchdogEvent::Timeout { id: HEARTBEAT, last_seen: Some(seen), .. }] if seen == last));
+
+        // The ECU comes back.
+        mock.inject(1, &heartbeat_frame);
+        let resumed = can2.receive(Duration::from_millis(500)).unwrap();
+        let at = resumed[0].instant().unwrap().max(last + ms(160));
+        assert_eq!(watchdog.observe(HEARTBEAT, at), Some(WatchdogEvent::Recovered { id: HEARTBEAT, outage: at - last }));
+    }
+}
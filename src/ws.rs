@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde::Deserialize;
+use tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tungstenite::http::StatusCode;
+use tungstenite::{Message, WebSocket};
+
+use crate::device::Channel;
+use crate::fanout::Subscription;
+use crate::filter::{FilterTerm, SoftwareFilter};
+use crate::id::Id;
+use crate::json::{JsonFrame, JsonTransmit};
+use crate::timestamp::host_time;
+
+/// Port the WebSocket server listens on by default.
+pub const WS_PORT: u16 = 8080;
+
+/// How long a client's thread waits for a message before forwarding frames; also the most a
+/// write may block before the client counts as backed up.
+const POLL: Duration = Duration::from_millis(20);
+/// Frames queued per client and channel between two polls.
+const CLIENT_QUEUE: usize = 4096;
+
+/// What clients send, e.g. `{"type":"filter","ranges":[{"first":"0x100","last":"0x1FF"}]}`,
+/// `{"type":"filter","filters":["100-1FF","~123"]}` or
+/// `{"type":"transmit","ch":0,"id":"0x123","data":"0A0B0C"}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClientRequest {
+    /// Only frames with an ID in one of the ranges or matching `filters`, in the syntax of
+    /// `--filter`, are sent; neither means every frame.
+    Filter {
+        #[serde(default)]
+        ranges: Vec<IdRange>,
+        #[serde(default)]
+        filters: Vec<String>,
+    },
+    Transmit {
+        ch: u32,
+        #[serde(flatten)]
+        frame: JsonTransmit,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct IdRange {
+    first: String,
+    /// Defaults to `first`.
+    last: Option<String>,
+    #[serde(default)]
+    ext: bool,
+}
+
+fn parse_id(text: &str, extended: bool) -> Result<Id, String> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    let raw = u32::from_str_radix(digits, 16).ok();
+    let id = if extended { raw.and_then(Id::extended) } else { raw.and_then(|raw| Id::standard(u16::try_from(raw).ok()?)) };
+    id.ok_or_else(|| format!("invalid CAN ID '{text}'"))
+}
+
+/// Per-client counters shared with the server.
+#[derive(Debug, Default)]
+struct ClientStats {
+    /// Frames that arrived while the socket was backed up.
+    dropped: AtomicU64,
+    /// Frames the fan-out layer couldn't queue because the client's thread fell behind.
+    overflowed: AtomicU64,
+}
+
+impl ClientStats {
+    fn lost(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed) + self.overflowed.load(Ordering::Relaxed)
+    }
+}
+
+/// Streams received frames to WebSocket clients as the [`JsonFrame`]s of `--output json`, one
+/// per text message, and transmits the frames they ask for.
+///
+/// Each client has its own queue per channel on the fan-out layer and its own ID filter. A
+/// client whose socket backs up loses frames instead of slowing anything else down; they are
+/// counted per client. Connections past the client limit are refused with HTTP 503.
+pub struct WsServer {
+    addr: SocketAddr,
+    clients: Arc<Mutex<HashMap<SocketAddr, Arc<ClientStats>>>>,
+    /// Drops of clients that have since disconnected.
+    dropped: Arc<AtomicU64>,
+    rejected: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WsServer {
+    pub fn start(addr: impl ToSocketAddrs, channels: &[Channel], max_clients: usize) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        let shared = Shared {
+            channels: channels.to_vec(),
+            max_clients,
+            connecting: Arc::default(),
+            clients: Arc::default(),
+            dropped: Arc::default(),
+            rejected: Arc::default(),
+            stop: Arc::new(AtomicBool::new(false)),
+        };
+        let Shared { clients, dropped, rejected, stop, .. } = shared.clone();
+        let thread = thread::spawn(move || accept(&listener, &shared));
+        Ok(Self { addr, clients, dropped, rejected, stop, thread: Some(thread) })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Clients currently connected.
+    pub fn clients(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    /// Frames each connected client has lost to a backed-up socket.
+    pub fn client_drops(&self) -> Vec<(SocketAddr, u64)> {
+        let clients = self.clients.lock().unwrap();
+        clients.iter().map(|(addr, stats)| (*addr, stats.lost())).collect()
+    }
+
+    /// Frames lost to backed-up sockets over all clients, including ones that left.
+    pub fn dropped(&self) -> u64 {
+        let connected: u64 = self.client_drops().iter().map(|(_, dropped)| dropped).sum();
+        self.dropped.load(Ordering::Relaxed) + connected
+    }
+
+    /// Connections refused because the client limit was reached.
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Disconnects every client and stops listening. Also done on drop.
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for WsServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[derive(Clone)]
+struct Shared {
+    channels: Vec<Channel>,
+    max_clients: usize,
+    /// Connections past the TCP accept, including ones still in the handshake.
+    connecting: Arc<AtomicUsize>,
+    clients: Arc<Mutex<HashMap<SocketAddr, Arc<ClientStats>>>>,
+    dropped: Arc<AtomicU64>,
+    rejected: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+}
+
+fn accept(listener: &TcpListener, shared: &Shared) {
+    let mut sessions: Vec<JoinHandle<()>> = Vec::new();
+    while !shared.stop.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, addr)) => {
+                let shared = shared.clone();
+                sessions.push(thread::spawn(move || serve(stream, addr, &shared)));
+            }
+            // WouldBlock while nobody connects; other errors only concern the failed connection.
+            Err(_) => thread::sleep(POLL * 5),
+        }
+        sessions.retain(|session| !session.is_finished());
+    }
+    for session in sessions {
+        let _ = session.join();
+    }
+}
+
+fn serve(stream: TcpStream, addr: SocketAddr, shared: &Shared) {
+    let admitted = shared
+        .connecting
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n < shared.max_clients).then_some(n + 1))
+        .is_ok();
+    if !admitted {
+        shared.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+    let stats = Arc::new(ClientStats::default());
+    if let Some(socket) = handshake(stream, admitted).filter(|_| admitted) {
+        shared.clients.lock().unwrap().insert(addr, Arc::clone(&stats));
+        session(socket, &stats, shared);
+        shared.clients.lock().unwrap().remove(&addr);
+    }
+    if admitted {
+        shared.dropped.fetch_add(stats.lost(), Ordering::Relaxed);
+        shared.connecting.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Refuses the handshake of a client past the limit.
+struct Admission(bool);
+
+impl Callback for Admission {
+    fn on_request(self, _request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        if self.0 {
+            return Ok(response);
+        }
+        let mut refusal = ErrorResponse::new(Some("too many clients".to_string()));
+        *refusal.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        Err(refusal)
+    }
+}
+
+/// Completes the WebSocket handshake, or answers it with 503 when the client isn't `admitted`.
+fn handshake(stream: TcpStream, admitted: bool) -> Option<WebSocket<TcpStream>> {
+    stream.set_nonblocking(false).ok()?;
+    stream.set_nodelay(true).ok()?;
+    // Generous for the handshake; shortened to the poll interval once it's done.
+    stream.set_read_timeout(Some(Duration::from_secs(1))).ok()?;
+    stream.set_write_timeout(Some(POLL)).ok()?;
+    let socket = tungstenite::accept_hdr(stream, Admission(admitted)).ok()?;
+    socket.get_ref().set_read_timeout(Some(POLL)).ok()?;
+    Some(socket)
+}
+
+/// `Some(true)` once everything buffered is out, `Some(false)` while the socket is backed up,
+/// `None` when the connection is gone.
+fn written(result: tungstenite::Result<()>) -> Option<bool> {
+    match result {
+        Ok(()) => Some(true),
+        Err(tungstenite::Error::Io(err)) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            Some(false)
+        }
+        Err(_) => None,
+    }
+}
+
+fn session(mut socket: WebSocket<TcpStream>, stats: &ClientStats, shared: &Shared) {
+    let subscriptions: Vec<Subscription> = shared.channels.iter().map(|channel| channel.subscribe(CLIENT_QUEUE)).collect();
+    let mut filter = SoftwareFilter::new();
+    // Cleared once the socket takes what is buffered; frames arriving until then are dropped.
+    let mut flushed = true;
+    while !shared.stop.load(Ordering::SeqCst) {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                if let Err(err) = handle(&text, &mut filter, &shared.channels) {
+                    let reply = serde_json::json!({ "error": err }).to_string();
+                    let Some(done) = written(socket.send(Message::text(reply))) else {
+                        break;
+                    };
+                    flushed = done;
+                }
+            }
+            Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => break,
+            // Pings are answered by tungstenite itself; binary messages mean nothing here.
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err)) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => break,
+        }
+        if !flushed {
+            let Some(done) = written(socket.flush()) else {
+                break;
+            };
+            flushed = done;
+        }
+        let mut wrote = false;
+        for (index, subscription) in (0u32..).zip(&subscriptions) {
+            while let Ok(frame) = subscription.try_recv() {
+                if !filter.accepts(&frame) {
+                    continue;
+                }
+                if !flushed {
+                    stats.dropped.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                let json = JsonFrame::new(host_time(&frame), index, &frame);
+                let Some(done) = written(socket.write(Message::text(serde_json::to_string(&json).unwrap_or_default()))) else {
+                    return;
+                };
+                (flushed, wrote) = (done, true);
+            }
+        }
+        stats.overflowed.store(subscriptions.iter().map(Subscription::dropped).sum(), Ordering::Relaxed);
+        if wrote && flushed {
+            let Some(done) = written(socket.flush()) else {
+                break;
+            };
+            flushed = done;
+        }
+    }
+    stats.overflowed.store(subscriptions.iter().map(Subscription::dropped).sum(), Ordering::Relaxed);
+    let _ = socket.close(None);
+    let _ = socket.flush();
+}
+
+/// Applies one client message; the error is sent back as `{"error":"..."}`.
+fn handle(text: &str, filter: &mut SoftwareFilter, channels: &[Channel]) -> Result<(), String> {
+    match serde_json::from_str(text).map_err(|err| format!("invalid request: {err}"))? {
+        ClientRequest::Filter { ranges, filters } => {
+            let mut new = SoftwareFilter::new();
+            for range in ranges {
+                let first = parse_id(&range.first, range.ext)?;
+                let last = range.last.as_deref().map_or(Ok(first), |last| parse_id(last, range.ext))?;
+                new.allow.insert(first, last);
+            }
+            for text in &filters {
+                let term: FilterTerm = text.parse().map_err(|err| format!("filter '{text}': {err}"))?;
+                new.add(&term);
+            }
+            *filter = new;
+            Ok(())
+        }
+        ClientRequest::Transmit { ch, frame } => {
+            let channel = channels.get(ch as usize).ok_or_else(|| format!("no channel {ch}"))?;
+            channel.transmit(&frame.to_frame()?).map_err(|err| err.to_string())
+        }
+    }
+}
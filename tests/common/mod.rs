@@ -0,0 +1,78 @@
+//! Setup shared by the integration tests, which run a [`Device`] against a [`MockBackend`].
+#![allow(dead_code)]
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rustcanbus::{Bitrate, Channel, ChannelMode, Device, Frame, Id, MockBackend, VciInitConfig};
+
+/// `VCI_USBCAN2`, the CANalyst-II's device type.
+pub const DEV_TYPE: u32 = 4;
+
+/// Long enough for a frame to cross the mock on a loaded test machine.
+pub const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A mock adapter, opened.
+pub fn open() -> (Arc<MockBackend>, Device) {
+    let mock = Arc::new(MockBackend::new());
+    let device = Device::open_with(mock.clone(), DEV_TYPE, 0).expect("the mock opens");
+    (mock, device)
+}
+
+/// Initializes and starts port `index` with `config`.
+pub fn start(device: &Device, index: u32, config: &VciInitConfig) -> Channel {
+    let channel = device.channel(index);
+    channel.init(config).expect("init");
+    channel.start().expect("start");
+    channel
+}
+
+/// A mock adapter with both ports started at 500 kbit/s in normal mode, wired to each other.
+pub fn open_pair() -> (Arc<MockBackend>, Device, Channel, Channel) {
+    let (mock, device) = open();
+    let config = VciInitConfig::with_bitrate(Bitrate::Kbps500);
+    let can1 = start(&device, 0, &config);
+    let can2 = start(&device, 1, &config);
+    (mock, device, can1, can2)
+}
+
+pub fn config(mode: ChannelMode) -> VciInitConfig {
+    VciInitConfig::with_bitrate(Bitrate::Kbps500).with_mode(mode)
+}
+
+pub fn std_frame(id: u16, data: &[u8]) -> Frame {
+    Frame::new(Id::standard(id).unwrap(), data).unwrap()
+}
+
+pub fn ext_frame(id: u32, data: &[u8]) -> Frame {
+    Frame::new(Id::extended(id).unwrap(), data).unwrap()
+}
+
+/// What identifies a frame on the bus, leaving out the timestamps the channel adds.
+pub fn content(frame: &Frame) -> (Id, bool, u8, Vec<u8>) {
+    (frame.id(), frame.is_remote(), frame.dlc(), frame.data().to_vec())
+}
+
+/// Receives until the channel stays idle for `idle`.
+pub fn drain(channel: &Channel, idle: Duration) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    loop {
+        match channel.receive_batch(256, idle).expect("receive") {
+            batch if batch.is_empty() => return frames,
+            batch => frames.extend(batch),
+        }
+    }
+}
+
+/// Polls `condition` until it holds or `timeout` passes, and returns whether it held.
+pub fn wait_for(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    while !condition() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    true
+}
@@ -0,0 +1,141 @@
+//! Compressed, rotated candump logs written from a [`Channel::subscribe`] consumer, the way
+//! `--log` with `--log-compress` and `--log-rotate` runs, while a [`MockBackend`] receives 5000
+//! frames a second.
+
+mod common;
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use common::{content, ext_frame, open_pair, TIMEOUT};
+use rustcanbus::{read_candump, CandumpWriter, CompressedWriter, Compression, Direction, Frame, FrameSink, RotatingSink, Rotation};
+
+/// Frames a second, in bursts every 10 ms.
+const RATE: u32 = 5000;
+const BURST: u32 = RATE / 100;
+
+/// A directory of its own for each test, as they run in parallel.
+fn log_dir() -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let name = format!("rustcanbus-compress-test-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+    let dir = std::env::temp_dir().join(name);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn numbered(n: u32) -> Frame {
+    ext_frame(n, &n.to_le_bytes())
+}
+
+/// A candump log through `compression`, rotated as `rotation` says.
+fn sink(dir: &Path, compression: Compression, rotation: Rotation) -> RotatingSink {
+    let path = dir.join(format!("bus.log.{}", compression.extension()));
+    RotatingSink::create(&path, rotation, Box::new(move |out| Ok(Box::new(CandumpWriter::new(CompressedWriter::new(compression, out)?)) as Box<dyn FrameSink>))).unwrap()
+}
+
+/// Decompresses one file on its own, as a reader given just that file would.
+fn decompress(compression: Compression, path: &Path) -> io::Result<String> {
+    let file = BufReader::new(File::open(path)?);
+    let mut text = String::new();
+    match compression {
+        Compression::Gzip => flate2::read::MultiGzDecoder::new(file).read_to_string(&mut text)?,
+        Compression::Zstd => zstd::Decoder::new(file)?.read_to_string(&mut text)?,
+    };
+    Ok(text)
+}
+
+/// The files of the series, oldest first.
+fn series(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+    // Names sort by opening time, then by the `-N` of files opened within the same second.
+    files.sort_by_key(|path| {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let stem = name.split('.').next().unwrap().to_string();
+        let n: u32 = stem.splitn(4, '-').nth(3).map_or(1, |n| n.parse().unwrap());
+        (stem.splitn(4, '-').take(3).collect::<Vec<_>>().join("-"), n)
+    });
+    files
+}
+
+fn logs_every_frame_while_reception_keeps_up(compression: Compression) {
+    let (mock, _device, _can1, can2) = open_pair();
+    let dir = log_dir();
+    // The compressors hold on to a second of these frames, so sizes would hardly rotate.
+    let mut log = sink(&dir, compression, Rotation { max_age: Some(Duration::from_millis(200)), ..Rotation::default() });
+    let logged = can2.subscribe(10_000);
+    let live = can2.subscribe(10_000);
+    let writer = thread::spawn(move || {
+        for _ in 0..RATE {
+            let frame = logged.recv_timeout(TIMEOUT * 4).expect("the log consumer kept up");
+            log.write_frame(1, &frame, Direction::Rx).unwrap();
+        }
+        log.finish().unwrap();
+        logged.dropped()
+    });
+    let reader = thread::spawn(move || (0..RATE).map(|_| live.recv_timeout(TIMEOUT).expect("reception kept up")).collect::<Vec<_>>());
+
+    for burst in 0..RATE / BURST {
+        for n in burst * BURST..(burst + 1) * BURST {
+            mock.inject(1, &numbered(n));
+        }
+        thread::sleep(Duration::from_millis(10));
+        assert!(can2.pending().unwrap() <= 2 * BURST, "the adapter backed up behind the compressor");
+    }
+    let received = reader.join().unwrap();
+    assert!(received.iter().map(|frame| frame.id().raw()).eq(0..RATE), "in order, none missing");
+    assert_eq!(writer.join().unwrap(), 0, "the log consumer missed nothing");
+
+    let files = series(&dir);
+    assert!(files.len() >= 3, "rotated into {} file(s)", files.len());
+    let mut records = Vec::new();
+    for path in &files {
+        let text = decompress(compression, path).unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+        let log = read_candump(text.as_bytes()).unwrap();
+        assert!(log.errors.is_empty(), "{}: {:?}", path.display(), log.errors);
+        assert!(!log.records.is_empty(), "{} is empty", path.display());
+        records.extend(log.records);
+    }
+    let expected: Vec<_> = received.iter().map(content).collect();
+    assert_eq!(records.iter().map(|record| content(&record.frame)).collect::<Vec<_>>(), expected, "each frame in exactly one file");
+    assert!(records.iter().all(|record| record.channel == 1));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn gzip_logs_keep_up_with_5000_frames_a_second() {
+    logs_every_frame_while_reception_keeps_up(Compression::Gzip);
+}
+
+#[test]
+fn zstd_logs_keep_up_with_5000_frames_a_second() {
+    logs_every_frame_while_reception_keeps_up(Compression::Zstd);
+}
+
+#[test]
+fn a_flushed_log_reads_back_without_being_finished() {
+    for compression in [Compression::Gzip, Compression::Zstd] {
+        let dir = log_dir();
+        let mut writer = sink(&dir, compression, Rotation::default());
+        for n in 0..100 {
+            writer.write_frame(0, &numbered(n), Direction::Rx).unwrap();
+        }
+        writer.flush().unwrap();
+        // Read while the last member or frame is still open, as after a power loss.
+        let path = series(&dir).remove(0);
+        let mut text = Vec::new();
+        let file = BufReader::new(File::open(&path).unwrap());
+        let result = match compression {
+            Compression::Gzip => flate2::read::MultiGzDecoder::new(file).read_to_end(&mut text),
+            Compression::Zstd => zstd::Decoder::new(file).unwrap().read_to_end(&mut text),
+        };
+        assert!(result.is_err(), "{compression:?}: the stream is unfinished");
+        let log = read_candump(&text[..]).unwrap();
+        assert_eq!(log.records.len(), 100, "{compression:?}: everything flushed reads back");
+        drop(writer);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
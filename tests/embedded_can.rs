@@ -0,0 +1,105 @@
+//! [`embedded_can`] conformance: code written against the traits only, driving [`MockBackend`]
+//! channels.
+#![cfg(feature = "embedded-can")]
+
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{config, open, open_pair, start};
+use embedded_can::blocking::Can;
+use embedded_can::{Error as _, ErrorKind, ExtendedId, StandardId};
+use rustcanbus::{CanError, ChannelMode, Frame, MockCall};
+
+/// Sends `frame` from `tx` and returns what `rx` receives, knowing nothing but the traits.
+fn echo<C: Can>(tx: &mut C, rx: &mut C, frame: &C::Frame) -> Result<C::Frame, C::Error> {
+    tx.transmit(frame)?;
+    rx.receive()
+}
+
+/// A frame's identity as the trait reports it.
+fn describe<F: embedded_can::Frame>(frame: &F) -> (embedded_can::Id, bool, bool, usize, Vec<u8>) {
+    (frame.id(), frame.is_extended(), frame.is_remote_frame(), frame.dlc(), frame.data().to_vec())
+}
+
+fn generic_frames<F: embedded_can::Frame>() -> Vec<F> {
+    vec![
+        F::new(StandardId::new(0x123).unwrap(), &[1, 2, 3]).unwrap(),
+        F::new(StandardId::MAX, &[0xFF; 8]).unwrap(),
+        F::new(StandardId::ZERO, &[]).unwrap(),
+        F::new(ExtendedId::new(0x18FF_50E5).unwrap(), &[0xAA; 8]).unwrap(),
+        F::new(ExtendedId::new(0x123).unwrap(), &[4]).unwrap(),
+        F::new_remote(StandardId::new(0x7DF).unwrap(), 8).unwrap(),
+        F::new_remote(ExtendedId::MAX, 0).unwrap(),
+    ]
+}
+
+#[test]
+fn frames_cross_the_bus_through_the_traits() {
+    let (_mock, _device, mut can1, mut can2) = open_pair();
+    for frame in generic_frames::<Frame>() {
+        let received = echo(&mut can1, &mut can2, &frame).unwrap();
+        assert_eq!(describe(&received), describe(&frame));
+        let back = echo(&mut can2, &mut can1, &received).unwrap();
+        assert_eq!(describe(&back), describe(&frame));
+    }
+}
+
+#[test]
+fn trait_accessors_agree_with_the_frame() {
+    for frame in generic_frames::<Frame>() {
+        assert_eq!(embedded_can::Frame::is_extended(&frame), frame.id().is_extended());
+        assert_eq!(embedded_can::Frame::is_remote_frame(&frame), frame.is_remote());
+        assert_eq!(embedded_can::Frame::dlc(&frame), usize::from(Frame::dlc(&frame)));
+        assert_eq!(embedded_can::Frame::data(&frame), Frame::data(&frame));
+        assert_eq!(rustcanbus::Id::from(embedded_can::Frame::id(&frame)), Frame::id(&frame));
+        assert!(embedded_can::Frame::is_data_frame(&frame) != frame.is_remote());
+    }
+    let standard = generic_frames::<Frame>()[0];
+    assert_eq!(Frame::id(&standard), rustcanbus::Id::Standard(0x123));
+    assert_eq!(Frame::id(&generic_frames::<Frame>()[4]), rustcanbus::Id::Extended(0x123), "an extended ID stays extended");
+    assert_eq!(embedded_can::Id::from(rustcanbus::Id::Extended(0x1FFF_FFFF)), embedded_can::Id::Extended(ExtendedId::MAX));
+}
+
+#[test]
+fn out_of_range_frames_are_refused() {
+    assert!(<Frame as embedded_can::Frame>::new(StandardId::ZERO, &[0; 9]).is_none());
+    assert!(<Frame as embedded_can::Frame>::new_remote(StandardId::ZERO, 9).is_none());
+    assert!(<Frame as embedded_can::Frame>::new_remote(ExtendedId::ZERO, usize::MAX).is_none());
+}
+
+#[test]
+fn receive_blocks_until_a_frame_arrives() {
+    let (mock, _device, _can1, mut can2) = open_pair();
+    let frame = Frame::new(rustcanbus::Id::Standard(0x321), &[9]).unwrap();
+    let injector = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(250));
+        mock.inject(1, &frame);
+    });
+    let started = Instant::now();
+    let received = Can::receive(&mut can2).unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(200), "returned after {:?}", started.elapsed());
+    assert_eq!(describe(&received), describe(&frame));
+    injector.join().unwrap();
+}
+
+#[test]
+fn vci_errors_surface_through_the_error_type() {
+    let (mock, _device, mut can1, mut can2) = open_pair();
+    let frame = generic_frames::<Frame>()[0];
+    // VCI_Transmit is called on the TX thread, so its failures only show as dropped frames.
+    mock.fail_next(MockCall::Transmit, -1, 1);
+    Can::transmit(&mut can1, &frame).unwrap();
+    assert!(can1.flush_transmit(Duration::from_millis(500)));
+    assert_eq!(can1.shaper_stats().dropped, 1);
+
+    mock.fail_next(MockCall::Receive, -1, 1);
+    let err = Can::receive(&mut can2).unwrap_err();
+    assert!(matches!(err, CanError::Receive { channel: 1, code: -1 }), "{err:?}");
+    assert_eq!(err.kind(), ErrorKind::Other);
+
+    let (_mock, device) = open();
+    let mut sniffer = start(&device, 0, &config(ChannelMode::ListenOnly));
+    assert!(matches!(Can::transmit(&mut sniffer, &frame), Err(CanError::ListenOnly { channel: 0 })));
+}
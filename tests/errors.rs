@@ -0,0 +1,134 @@
+//! How each failing VCI return value surfaces as a [`CanError`], driven through [`MockBackend`].
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::{config, open, open_pair, std_frame, DEV_TYPE, TIMEOUT};
+use rustcanbus::{CanError, ChannelMode, Device, MockBackend, MockCall, RefType};
+
+/// Return values the VCI status calls fail with: 0 for a refused call, -1 for a device error,
+/// and something no DLL version documents.
+const FAILURES: [i32; 3] = [0, -1, -7];
+
+type Call = fn(&Device) -> Result<(), CanError>;
+
+/// Calls returning 1 on success, the error each maps to as `Debug` with `{code}` for the code.
+const STATUS_CALLS: [(MockCall, Call, &str); 9] = [
+    (MockCall::ReadBoardInfo, |device| device.board_info().map(drop), "ReadBoardInfo { code: {code} }"),
+    (MockCall::InitCan, |device| device.channel(1).init(&config(ChannelMode::Normal)), "InitCan { channel: 1, code: {code} }"),
+    (MockCall::StartCan, |device| device.channel(0).start(), "StartCan { channel: 0, code: {code} }"),
+    (MockCall::ResetCan, |device| device.channel(1).reset(), "ResetCan { channel: 1, code: {code} }"),
+    (MockCall::ClearBuffer, |device| device.channel(0).clear_buffer(), "ClearBuffer { channel: 0, code: {code} }"),
+    (MockCall::ReadErrInfo, |device| device.channel(1).error_info().map(drop), "ReadErrInfo { channel: 1, code: {code} }"),
+    (MockCall::ReadCanStatus, |device| device.channel(0).status().map(drop), "ReadCanStatus { channel: 0, code: {code} }"),
+    (MockCall::SetReference, |device| device.channel(1).set_reference(&RefType::TransmitTimeout(100)), "SetReference { channel: 1, code: {code} }"),
+    (MockCall::UsbDeviceReset, Device::usb_reset, "UsbReset { code: {code} }"),
+];
+
+#[test]
+fn failed_status_calls_keep_their_code() {
+    for (call, run, expected) in STATUS_CALLS {
+        for code in FAILURES {
+            let (mock, device, _can1, _can2) = open_pair();
+            mock.fail_next(call, code, 1);
+            let err = run(&device).expect_err(expected);
+            assert_eq!(format!("{err:?}"), expected.replace("{code}", &code.to_string()), "{call:?} returning {code}");
+            assert_eq!(err.code(), Some(code));
+            assert!(err.to_string().ends_with(&format!("returned {code})")), "{err}");
+            run(&device).unwrap_or_else(|err| panic!("{call:?} after the failure: {err}"));
+        }
+    }
+}
+
+#[test]
+fn failed_open_and_close_keep_their_code() {
+    for code in FAILURES {
+        let mock = Arc::new(MockBackend::new());
+        mock.fail_next(MockCall::OpenDevice, code, 1);
+        let err = Device::open_with(mock.clone(), DEV_TYPE, 0).err().expect("open fails");
+        assert!(matches!(err, CanError::OpenDevice { code: c } if c == code), "{err:?}");
+        assert_eq!(err.to_string(), format!("failed to open device (VCI_OpenDevice returned {code})"));
+
+        let device = Device::open_with(mock.clone(), DEV_TYPE, 0).unwrap();
+        mock.fail_next(MockCall::CloseDevice, code, 1);
+        let err = device.close().expect_err("close fails");
+        assert!(matches!(err, CanError::CloseDevice { code: c } if c == code), "{err:?}");
+    }
+}
+
+#[test]
+fn transmit_failures_and_refusals() {
+    let (mock, _device, can1, _can2) = open_pair();
+    // VCI_Transmit is called on the TX thread, so what it fails or refuses is dropped there.
+    for code in [-1, -7] {
+        mock.fail_next(MockCall::Transmit, code, 1);
+        can1.transmit(&std_frame(0x1, &[])).unwrap();
+        assert!(can1.flush_transmit(TIMEOUT));
+        mock.fail_next(MockCall::Transmit, code, 1);
+        assert_eq!(can1.transmit_all(&[std_frame(0x1, &[])]).unwrap(), 1);
+        assert!(can1.flush_transmit(TIMEOUT));
+    }
+    // No frames taken means the adapter's buffer is full; without retries they are dropped too.
+    mock.fail_next(MockCall::Transmit, 0, 1);
+    assert_eq!(can1.transmit_all(&[std_frame(0x1, &[]), std_frame(0x2, &[])]).unwrap(), 2);
+    assert!(can1.flush_transmit(TIMEOUT));
+    assert_eq!(can1.shaper_stats().dropped, 6);
+    assert!(mock.take_transmitted(0).is_empty());
+}
+
+#[test]
+fn receive_failures_and_empty_buffers() {
+    let (mock, _device, _can1, can2) = open_pair();
+    for code in [-1, -7] {
+        mock.fail_next(MockCall::Receive, code, 1);
+        let err = can2.receive_batch(10, Duration::from_millis(1)).unwrap_err();
+        assert!(matches!(err, CanError::Receive { channel: 1, code: c } if c == code), "{err:?}");
+        mock.fail_next(MockCall::GetReceiveNum, code, 1);
+        let err = can2.pending().unwrap_err();
+        assert!(matches!(err, CanError::GetReceiveNum { channel: 1, code: c } if c == code), "{err:?}");
+    }
+    // Zero frames is an idle bus.
+    mock.fail_next(MockCall::Receive, 0, 1);
+    assert!(can2.receive_batch(10, Duration::from_millis(1)).unwrap().is_empty());
+    mock.fail_next(MockCall::GetReceiveNum, 0, 1);
+    assert_eq!(can2.pending().unwrap(), 0);
+}
+
+#[test]
+fn missing_optional_functions_are_unsupported() {
+    let (mock, device) = open();
+    let channel = device.channel(0);
+    for call in [MockCall::ClearBuffer, MockCall::ReadErrInfo, MockCall::ReadCanStatus, MockCall::GetReceiveNum, MockCall::SetReference] {
+        mock.set_supported(call, false);
+    }
+    let results = [
+        ("VCI_ClearBuffer", channel.clear_buffer()),
+        ("VCI_ReadErrInfo", channel.error_info().map(drop)),
+        ("VCI_ReadCANStatus", channel.status().map(drop)),
+        ("VCI_GetReceiveNum", channel.pending().map(drop)),
+        ("VCI_SetReference", channel.set_reference(&RefType::TransmitTimeout(100))),
+    ];
+    for (symbol, result) in results {
+        let err = result.unwrap_err();
+        assert!(matches!(err, CanError::Unsupported(s) if s == symbol), "{err:?}");
+        assert_eq!(err.code(), None);
+        assert_eq!(err.to_string(), format!("{symbol} is not available in the loaded DLL"));
+    }
+}
+
+#[test]
+fn errors_name_the_port_from_one() {
+    let cases = [
+        (CanError::InitCan { channel: 0, code: 0 }, "failed to initialize CAN1 (VCI_InitCAN returned 0)"),
+        (CanError::StartCan { channel: 1, code: -1 }, "failed to start CAN2 (VCI_StartCAN returned -1)"),
+        (CanError::Transmit { channel: 1, code: 0 }, "failed to transmit on CAN2 (VCI_Transmit returned 0)"),
+        (CanError::Receive { channel: 0, code: -1 }, "failed to receive on CAN1 (VCI_Receive returned -1)"),
+        (CanError::ListenOnly { channel: 1 }, "CAN2 is in listen-only mode and cannot transmit"),
+        (CanError::SymbolMissing("VCI_OpenDevice"), "DLL does not export VCI_OpenDevice"),
+    ];
+    for (err, message) in cases {
+        assert_eq!(err.to_string(), message);
+    }
+}
@@ -0,0 +1,165 @@
+//! The generated gRPC client against a [`GrpcServer`] serving [`MockBackend`] channels.
+#![cfg(feature = "grpc")]
+
+mod common;
+
+use std::future::Future;
+use std::time::Duration;
+
+use common::{drain, ext_frame, open_pair, std_frame, TIMEOUT};
+use rustcanbus::proto::can_bus_client::CanBusClient;
+use rustcanbus::proto::{self, ChannelConfig, FilterRequest, IdRange, Mode, StatusRequest};
+use rustcanbus::{GrpcServer, Id};
+use tonic::transport::Channel as Connection;
+use tonic::Code;
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap()
+}
+
+async fn connect(server: &GrpcServer) -> CanBusClient<Connection> {
+    CanBusClient::connect(format!("http://{}", server.local_addr())).await.expect("the server accepts")
+}
+
+/// Fails the test instead of hanging it when the server stops answering.
+async fn within<T>(future: impl Future<Output = T>) -> T {
+    tokio::time::timeout(TIMEOUT * 4, future).await.expect("the server answers in time")
+}
+
+fn message(channel: u32, id: u32, extended: bool, data: &[u8]) -> proto::Frame {
+    proto::Frame { channel, id, extended, dlc: data.len() as u32, data: data.to_vec(), ..Default::default() }
+}
+
+#[test]
+fn streams_the_frames_the_filter_passes() {
+    let (_mock, _device, can1, can2) = open_pair();
+    let server = GrpcServer::start("127.0.0.1:0".parse().unwrap(), &[can1.clone(), can2.clone()]).unwrap();
+    runtime().block_on(async {
+        let mut client = connect(&server).await;
+        let request = FilterRequest {
+            channel: Some(1),
+            ranges: vec![IdRange { first: 0x100, last: 0x1FF, extended: false }],
+            filters: vec!["~150".to_string()],
+        };
+        // Subscribed once the call returns, so everything sent from here on is streamed.
+        let mut stream = within(client.stream_frames(request)).await.unwrap().into_inner();
+        for frame in [std_frame(0x050, &[1]), std_frame(0x150, &[2]), std_frame(0x180, &[3, 4]), ext_frame(0x180, &[5]), std_frame(0x250, &[6])] {
+            can1.transmit(&frame).unwrap();
+        }
+        // CAN2 sending isn't heard on CAN2, and the stream is only for CAN2.
+        can2.transmit(&std_frame(0x181, &[])).unwrap();
+        can1.transmit(&std_frame(0x1FF, &[])).unwrap();
+
+        let first = within(stream.message()).await.unwrap().expect("a frame");
+        assert_eq!((first.channel, first.id, first.extended, first.remote, first.dlc, first.data.as_slice()), (1, 0x180, false, false, 2, &[3, 4][..]));
+        assert!(first.timestamp_us > 0);
+        let second = within(stream.message()).await.unwrap().expect("a frame");
+        assert_eq!((second.id, second.extended, second.dlc), (0x1FF, false, 0));
+    });
+}
+
+#[test]
+fn streams_every_channel_without_a_filter() {
+    let (mock, _device, can1, can2) = open_pair();
+    let server = GrpcServer::start("127.0.0.1:0".parse().unwrap(), &[can1.clone(), can2.clone()]).unwrap();
+    runtime().block_on(async {
+        let mut client = connect(&server).await;
+        let mut stream = within(client.stream_frames(FilterRequest::default())).await.unwrap().into_inner();
+        mock.inject(0, &ext_frame(0x18FF_50E5, &[0xAA; 8]));
+        mock.inject(1, &rustcanbus::Frame::remote(Id::Standard(0x7DF), 3).unwrap());
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            let frame = within(stream.message()).await.unwrap().expect("a frame");
+            received.push((frame.channel, frame.id, frame.extended, frame.remote, frame.dlc, frame.data.len()));
+        }
+        received.sort();
+        assert_eq!(received, [(0, 0x18FF_50E5, true, false, 8, 8), (1, 0x7DF, false, true, 3, 0)]);
+    });
+}
+
+#[test]
+fn bad_stream_requests_are_invalid_arguments() {
+    let (_mock, _device, can1, can2) = open_pair();
+    let server = GrpcServer::start("127.0.0.1:0".parse().unwrap(), &[can1, can2]).unwrap();
+    runtime().block_on(async {
+        let mut client = connect(&server).await;
+        for request in [
+            FilterRequest { channel: Some(2), ..Default::default() },
+            FilterRequest { ranges: vec![IdRange { first: 0x800, last: 0, extended: false }], ..Default::default() },
+            FilterRequest { filters: vec!["12G".to_string()], ..Default::default() },
+        ] {
+            let status = within(client.stream_frames(request.clone())).await.err().unwrap_or_else(|| panic!("{request:?} was accepted"));
+            assert_eq!(status.code(), Code::InvalidArgument, "{request:?}: {status}");
+        }
+    });
+}
+
+#[test]
+fn transmits_on_the_requested_channel() {
+    let (mock, _device, can1, can2) = open_pair();
+    let server = GrpcServer::start("127.0.0.1:0".parse().unwrap(), &[can1.clone(), can2.clone()]).unwrap();
+    runtime().block_on(async {
+        let mut client = connect(&server).await;
+        within(client.transmit(message(0, 0x123, false, &[1, 2, 3]))).await.unwrap();
+        within(client.transmit(message(0, 0x18FF_50E5, true, &[]))).await.unwrap();
+        within(client.transmit(proto::Frame { channel: 0, id: 0x7DF, remote: true, dlc: 8, ..Default::default() })).await.unwrap();
+
+        for (request, code) in [
+            (message(2, 0x123, false, &[]), Code::InvalidArgument),
+            (message(0, 0x800, false, &[]), Code::InvalidArgument),
+            (message(0, 0x2000_0000, true, &[]), Code::InvalidArgument),
+            (message(0, 0x123, false, &[0; 9]), Code::InvalidArgument),
+            (proto::Frame { channel: 0, id: 0x123, remote: true, dlc: 9, ..Default::default() }, Code::InvalidArgument),
+        ] {
+            let status = within(client.transmit(request.clone())).await.unwrap_err();
+            assert_eq!(status.code(), code, "{request:?}: {status}");
+        }
+    });
+    let sent = vec![std_frame(0x123, &[1, 2, 3]), ext_frame(0x18FF_50E5, &[]), rustcanbus::Frame::remote(Id::Standard(0x7DF), 8).unwrap()];
+    assert!(can1.flush_transmit(TIMEOUT));
+    assert_eq!(mock.take_transmitted(0), sent);
+    let received: Vec<_> = drain(&can2, Duration::from_millis(50)).iter().map(common::content).collect();
+    assert_eq!(received, sent.iter().map(common::content).collect::<Vec<_>>());
+}
+
+#[test]
+fn reports_and_changes_the_channel_configuration() {
+    let (mock, _device, can1, can2) = open_pair();
+    let server = GrpcServer::start("127.0.0.1:0".parse().unwrap(), &[can1, can2.clone()]).unwrap();
+    runtime().block_on(async {
+        let mut client = connect(&server).await;
+        let status = within(client.get_status(StatusRequest {})).await.unwrap().into_inner();
+        let summary: Vec<_> = status.channels.iter().map(|channel| (channel.channel, channel.bitrate.as_str(), channel.mode(), channel.error_state.as_str())).collect();
+        assert_eq!(summary, [(0, "500k", Mode::Normal, "error-active"), (1, "500k", Mode::Normal, "error-active")]);
+
+        let config = ChannelConfig { channel: 1, bitrate: "250k".to_string(), mode: Mode::ListenOnly as i32 };
+        let status = within(client.configure(config)).await.unwrap().into_inner();
+        assert_eq!((status.channel, status.bitrate.as_str(), status.mode()), (1, "250k", Mode::ListenOnly));
+        // Unset fields keep what the channel has.
+        let status = within(client.configure(ChannelConfig { channel: 1, ..Default::default() })).await.unwrap().into_inner();
+        assert_eq!((status.bitrate.as_str(), status.mode()), ("250k", Mode::ListenOnly));
+
+        let status = within(client.transmit(message(1, 0x123, false, &[]))).await.unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition, "{status}");
+        for config in [ChannelConfig { channel: 2, ..Default::default() }, ChannelConfig { channel: 0, bitrate: "fast".to_string(), ..Default::default() }] {
+            let status = within(client.configure(config.clone())).await.unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument, "{config:?}: {status}");
+        }
+    });
+    assert!(mock.take_transmitted(1).is_empty());
+    assert_eq!(can2.config().unwrap().bitrate().to_string(), "250k");
+}
+
+#[test]
+fn stopping_the_server_ends_the_streams() {
+    let (_mock, _device, can1, can2) = open_pair();
+    let mut server = GrpcServer::start("127.0.0.1:0".parse().unwrap(), &[can1, can2]).unwrap();
+    let runtime = runtime();
+    let mut stream = runtime.block_on(async {
+        let mut client = connect(&server).await;
+        within(client.stream_frames(FilterRequest::default())).await.unwrap().into_inner()
+    });
+    server.stop();
+    let end = runtime.block_on(within(stream.message()));
+    assert!(!matches!(end, Ok(Some(_))), "{end:?}");
+}
@@ -0,0 +1,76 @@
+//! Standard and extended identifiers round-tripped through a [`Device`] against [`MockBackend`].
+
+mod common;
+
+use std::time::Duration;
+
+use common::{content, drain, ext_frame, open_pair, std_frame, TIMEOUT};
+use rustcanbus::{Frame, Id};
+
+#[test]
+fn both_kinds_cross_the_bus_unchanged() {
+    let (mock, _device, can1, can2) = open_pair();
+    let sent = [
+        std_frame(0x000, &[]),
+        std_frame(0x123, &[1, 2, 3]),
+        std_frame(0x7FF, &[0xFF; 8]),
+        ext_frame(0x000, &[]),
+        ext_frame(0x7FF, &[4]),
+        ext_frame(0x800, &[5, 6]),
+        ext_frame(0x18FF_50E5, &[0xAA; 8]),
+        ext_frame(0x1FFF_FFFF, &[7]),
+        Frame::remote(Id::Standard(0x7DF), 8).unwrap(),
+        Frame::remote(Id::Extended(0x18DA_F110), 0).unwrap(),
+    ];
+    for frame in &sent {
+        can1.transmit(frame).unwrap();
+    }
+    assert!(can1.flush_transmit(TIMEOUT));
+    let expected: Vec<_> = sent.iter().map(content).collect();
+    assert_eq!(mock.take_transmitted(0).iter().map(content).collect::<Vec<_>>(), expected);
+
+    let received = drain(&can2, Duration::from_millis(50));
+    assert_eq!(received.iter().map(content).collect::<Vec<_>>(), expected);
+    for (frame, sent) in received.iter().zip(&sent) {
+        assert_eq!(frame.is_extended(), sent.is_extended(), "{frame:?}");
+        assert_eq!(frame.is_extended(), frame.id().is_extended(), "{frame:?}");
+        assert!(!frame.is_suspect(), "{frame:?}");
+    }
+}
+
+#[test]
+fn the_same_number_is_a_different_id_in_each_space() {
+    let (mock, _device, can1, can2) = open_pair();
+    can1.transmit(&std_frame(0x123, &[1])).unwrap();
+    can1.transmit(&ext_frame(0x123, &[2])).unwrap();
+    assert!(can1.flush_transmit(TIMEOUT));
+    mock.inject(1, &ext_frame(0x7FF, &[3]));
+    mock.inject(1, &std_frame(0x7FF, &[4]));
+
+    let received = drain(&can2, Duration::from_millis(50));
+    let ids: Vec<_> = received.iter().map(|frame| (frame.id(), frame.data()[0])).collect();
+    assert_eq!(ids, [(Id::Standard(0x123), 1), (Id::Extended(0x123), 2), (Id::Extended(0x7FF), 3), (Id::Standard(0x7FF), 4)]);
+    assert_ne!(received[0].id(), received[1].id());
+    assert_eq!(received[0].id().raw(), received[1].id().raw());
+}
+
+#[test]
+fn ids_out_of_range_are_refused() {
+    assert_eq!(Id::standard(0x7FF), Some(Id::Standard(0x7FF)));
+    assert_eq!(Id::standard(0x800), None);
+    assert_eq!(Id::extended(0x1FFF_FFFF), Some(Id::Extended(0x1FFF_FFFF)));
+    assert_eq!(Id::extended(0x2000_0000), None);
+    assert!(Frame::new(Id::Standard(0x800), &[]).is_none());
+    assert!(Frame::new(Id::Extended(0x2000_0000), &[]).is_none());
+    assert!(Frame::remote(Id::Standard(0xFFFF), 0).is_none());
+}
+
+#[test]
+fn received_ids_print_with_their_kinds_width() {
+    let (_mock, _device, can1, can2) = open_pair();
+    for frame in [std_frame(0x123, &[]), std_frame(0x7, &[]), ext_frame(0x18FF_50E5, &[]), ext_frame(0x123, &[])] {
+        can1.transmit(&frame).unwrap();
+    }
+    let printed: Vec<_> = drain(&can2, Duration::from_millis(50)).iter().map(|frame| frame.id().to_string()).collect();
+    assert_eq!(printed, ["0x123", "0x007", "0x18FF50E5", "0x00000123"]);
+}
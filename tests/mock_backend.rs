@@ -0,0 +1,261 @@
+//! A [`Device`] driven end to end against [`MockBackend`]: frames crossing between the ports,
+//! the acceptance and software filters, and recovery from failed calls, bus-off and unplugging.
+
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{config, content, drain, ext_frame, open, open_pair, start, std_frame, wait_for, TIMEOUT};
+use rustcanbus::{
+    parse_filters, CanError, ChannelMode, ConnectionState, DisconnectedTx, FilterBuilder, FrameKinds, Id, MockCall,
+    ReceivePolling, Reconnect, SoftwareFilter,
+};
+
+#[test]
+fn transmit_arrives_on_the_other_port() {
+    let (mock, _device, can1, can2) = open_pair();
+    let sent = [std_frame(0x123, &[1, 2, 3]), ext_frame(0x18FF_1234, &[0xAA; 8]), std_frame(0x7FF, &[])];
+    for frame in &sent {
+        can1.transmit(frame).unwrap();
+    }
+
+    let received = drain(&can2, Duration::from_millis(50));
+    assert_eq!(received.iter().map(content).collect::<Vec<_>>(), sent.iter().map(content).collect::<Vec<_>>());
+    assert!(received.iter().all(|frame| frame.timestamp().is_some() && !frame.is_suspect()));
+    assert_eq!(mock.take_transmitted(0).iter().map(content).collect::<Vec<_>>(), sent.iter().map(content).collect::<Vec<_>>());
+    assert_eq!((can1.transmitted(), can2.received()), (3, 3));
+    assert!(drain(&can1, Duration::from_millis(20)).is_empty(), "a normal port doesn't hear itself");
+}
+
+#[test]
+fn self_test_port_receives_its_own_frames() {
+    let (_mock, device) = open();
+    let can1 = start(&device, 0, &config(ChannelMode::SelfTest));
+    let can2 = start(&device, 1, &config(ChannelMode::Normal));
+    can1.transmit(&std_frame(0x42, &[9])).unwrap();
+
+    let echoed = can1.receive(TIMEOUT).unwrap().pop().expect("looped back");
+    assert_eq!(content(&echoed), content(&std_frame(0x42, &[9])));
+    assert!(can2.receive(Duration::from_millis(20)).unwrap().is_empty());
+}
+
+#[test]
+fn latency_delays_delivery_and_loss_drops_frames() {
+    let (mock, _device, can1, can2) = open_pair();
+    mock.set_latency(Duration::from_millis(100));
+    can1.transmit(&std_frame(0x10, &[1])).unwrap();
+    assert!(can2.receive(Duration::from_millis(10)).unwrap().is_empty());
+    assert_eq!(can2.receive(TIMEOUT).unwrap().len(), 1);
+
+    mock.set_latency(Duration::ZERO);
+    mock.set_loss(1.0);
+    can1.transmit(&std_frame(0x11, &[2])).unwrap();
+    assert!(can1.flush_transmit(TIMEOUT));
+    assert!(can2.receive(Duration::from_millis(20)).unwrap().is_empty());
+    assert_eq!(mock.take_transmitted(0).len(), 2, "lost frames still count as sent");
+}
+
+#[test]
+fn unstarted_port_hears_nothing() {
+    let (_mock, device) = open();
+    let can1 = start(&device, 0, &config(ChannelMode::Normal));
+    device.channel(1).init(&config(ChannelMode::Normal)).unwrap();
+    can1.transmit(&std_frame(0x1, &[])).unwrap();
+    assert!(can1.flush_transmit(TIMEOUT));
+    device.channel(1).start().unwrap();
+    assert!(device.channel(1).receive(Duration::from_millis(20)).unwrap().is_empty());
+}
+
+/// 2000 frames/s for a second, read back by a subscription reader asking for ten frames per
+/// call, as with `--rx-buffer 10`.
+#[test]
+fn small_receive_batches_keep_up_with_2000_frames_per_second() {
+    let (_mock, _device, can1, can2) = open_pair();
+    can2.set_receive_polling(ReceivePolling { max_frames: 10, ..ReceivePolling::default() });
+    let subscription = can2.subscribe(1024);
+    let consumer = thread::spawn(move || {
+        let mut ids = Vec::new();
+        while let Ok(frame) = subscription.recv_timeout(TIMEOUT) {
+            ids.push(frame.id().raw());
+            if ids.len() == 2000 {
+                break;
+            }
+        }
+        (ids, subscription.dropped())
+    });
+    let started = Instant::now();
+    for tick in 0..100u32 {
+        for n in 0..20u32 {
+            can1.transmit(&ext_frame(tick * 20 + n, &(tick * 20 + n).to_le_bytes())).unwrap();
+        }
+        if let Some(wait) = (started + Duration::from_millis(u64::from(tick + 1) * 10)).checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+
+    let (ids, dropped) = consumer.join().unwrap();
+    assert_eq!((ids.len(), dropped, can2.received()), (2000, 0, 2000));
+    assert!(ids.iter().copied().eq(0..2000), "frames arrive in order");
+}
+
+#[test]
+fn acceptance_filter_passes_only_its_range() {
+    let (mock, device) = open();
+    let can1 = start(&device, 0, &config(ChannelMode::Normal));
+    let mut filtered = config(ChannelMode::Normal);
+    let filter = FilterBuilder::new().range(Id::standard(0x100).unwrap(), Id::standard(0x10F).unwrap()).build();
+    assert_eq!(filter.extra_ids, Some(0));
+    filter.apply(&mut filtered);
+    let can2 = start(&device, 1, &filtered);
+
+    for id in [0x0FF, 0x100, 0x108, 0x10F, 0x110, 0x200] {
+        can1.transmit(&std_frame(id, &[])).unwrap();
+    }
+    can1.transmit(&ext_frame(0x100, &[])).unwrap();
+    assert!(can1.flush_transmit(TIMEOUT));
+    mock.inject(1, &std_frame(0x105, &[5]));
+
+    let ids: Vec<u32> = drain(&can2, Duration::from_millis(50)).iter().map(|frame| frame.id().raw()).collect();
+    assert_eq!(ids, [0x100, 0x108, 0x10F, 0x105]);
+}
+
+#[test]
+fn acceptance_filter_frame_kinds() {
+    let (_mock, device) = open();
+    let can1 = start(&device, 0, &config(ChannelMode::Normal));
+    let mut extended_only = config(ChannelMode::Normal);
+    FilterBuilder::new().kinds(FrameKinds::ExtendedOnly).build().apply(&mut extended_only);
+    let can2 = start(&device, 1, &extended_only);
+
+    can1.transmit(&std_frame(0x123, &[])).unwrap();
+    can1.transmit(&ext_frame(0x123, &[])).unwrap();
+    let received = drain(&can2, Duration::from_millis(50));
+    assert_eq!(received.len(), 1);
+    assert!(received[0].is_extended());
+}
+
+#[test]
+fn software_filter_behind_an_accept_all_controller() {
+    let (_mock, _device, can1, can2) = open_pair();
+    let mut filter = SoftwareFilter::new();
+    for term in parse_filters("100-1FF,~150,18FF0000:1FFF0000").unwrap() {
+        filter.add(&term);
+    }
+    for frame in [std_frame(0x100, &[]), std_frame(0x150, &[]), std_frame(0x1FF, &[]), std_frame(0x200, &[]), ext_frame(0x18FF_00AB, &[]), ext_frame(0x18FE_00AB, &[])] {
+        can1.transmit(&frame).unwrap();
+    }
+
+    let received = drain(&can2, Duration::from_millis(50));
+    assert_eq!(received.len(), 6, "the controller passes everything");
+    let passed: Vec<u32> = received.iter().filter(|frame| filter.accepts(frame)).map(|frame| frame.id().raw()).collect();
+    assert_eq!(passed, [0x100, 0x1FF, 0x18FF_00AB]);
+}
+
+#[test]
+fn failed_receives_are_reported_then_recover() {
+    let (mock, _device, can1, can2) = open_pair();
+    mock.fail_next(MockCall::Receive, -1, 2);
+    can1.transmit(&std_frame(0x321, &[1])).unwrap();
+
+    for _ in 0..2 {
+        assert!(matches!(can2.receive(TIMEOUT), Err(CanError::Receive { channel: 1, code: -1 })));
+    }
+    assert_eq!(can2.receive_failures(), 2);
+    let frame = can2.receive(TIMEOUT).unwrap().pop().expect("the queued frame survives the failures");
+    assert_eq!(frame.id().raw(), 0x321);
+    assert_eq!(can2.receive_failures(), 2);
+}
+
+#[test]
+fn intermittent_receive_failures_keep_the_connection() {
+    let (mock, device, can1, can2) = open_pair();
+    device.set_auto_reconnect(Some(Reconnect { after_failures: 3, interval: Duration::from_millis(20), tx: DisconnectedTx::Drop }));
+    for round in 0..5u8 {
+        mock.fail_next(MockCall::Receive, -1, 2);
+        can1.transmit(&std_frame(0x100, &[round])).unwrap();
+        for _ in 0..2 {
+            assert!(matches!(can2.receive(TIMEOUT), Err(CanError::Receive { channel: 1, code: -1 })));
+        }
+        let frames = can2.receive(TIMEOUT).unwrap();
+        assert_eq!(frames.iter().map(content).collect::<Vec<_>>(), [content(&std_frame(0x100, &[round]))]);
+    }
+    assert!(can2.receive(Duration::from_millis(20)).unwrap().is_empty(), "an idle bus is not a failure");
+    assert_eq!(can2.receive_failures(), 10);
+    assert_eq!(device.connection_state(), ConnectionState::Connected);
+    assert_eq!(can2.reconnects(), 0);
+}
+
+#[test]
+fn persistent_receive_failures_are_never_an_idle_bus() {
+    let (mock, _device, _can1, can2) = open_pair();
+    mock.fail_next(MockCall::Receive, -1, u32::MAX);
+    for _ in 0..20 {
+        assert!(matches!(can2.receive(Duration::from_millis(1)), Err(CanError::Receive { channel: 1, code: -1 })));
+        assert!(matches!(can2.receive_batch(16, Duration::from_millis(1)), Err(CanError::Receive { channel: 1, code: -1 })));
+    }
+    assert_eq!(can2.receive_failures(), 40);
+}
+
+#[test]
+fn persistent_receive_failures_escalate_to_a_reconnect() {
+    let (mock, device, can1, can2) = open_pair();
+    device.set_auto_reconnect(Some(Reconnect { after_failures: 3, interval: Duration::from_millis(20), tx: DisconnectedTx::Drop }));
+    mock.fail_next(MockCall::Receive, -1, 3);
+    for _ in 0..3 {
+        assert!(matches!(can2.receive(Duration::from_millis(5)), Err(CanError::Receive { code: -1, .. })));
+    }
+    assert!(wait_for(TIMEOUT, || can2.reconnects() == 1));
+    assert_eq!(device.connection_state(), ConnectionState::Connected);
+    can1.transmit(&std_frame(0x3, &[3])).unwrap();
+    assert_eq!(can2.receive(TIMEOUT).unwrap()[0].id().raw(), 0x3);
+}
+
+#[test]
+fn failed_transmit_is_dropped_and_nothing_is_sent() {
+    let (mock, _device, can1, can2) = open_pair();
+    mock.fail_next(MockCall::Transmit, -1, 1);
+    can1.transmit(&std_frame(0x1, &[])).unwrap();
+    assert!(can1.flush_transmit(TIMEOUT));
+    assert_eq!(can1.shaper_stats().dropped, 1);
+    assert!(mock.take_transmitted(0).is_empty());
+    can1.transmit(&std_frame(0x2, &[])).unwrap();
+    assert_eq!(can2.receive(TIMEOUT).unwrap()[0].id().raw(), 0x2);
+}
+
+#[test]
+fn bus_off_refuses_transmits_until_recovered() {
+    let (mock, _device, can1, can2) = open_pair();
+    mock.set_bus_off(0, true);
+    assert!(can1.status().unwrap().bus_off());
+    can1.transmit(&std_frame(0x1, &[])).unwrap();
+    assert!(can1.flush_transmit(TIMEOUT));
+    assert_eq!(can1.shaper_stats().dropped, 1, "the adapter took nothing");
+
+    can1.recover().unwrap();
+    assert!(!can1.status().unwrap().bus_off());
+    can1.transmit(&std_frame(0x2, &[])).unwrap();
+    assert_eq!(can2.receive(TIMEOUT).unwrap()[0].id().raw(), 0x2);
+}
+
+#[test]
+fn unplugged_adapter_reconnects_and_restores_the_ports() {
+    let (mock, device, can1, can2) = open_pair();
+    device.set_auto_reconnect(Some(Reconnect { after_failures: 2, interval: Duration::from_millis(20), tx: DisconnectedTx::Drop }));
+    mock.set_connected(false);
+
+    for _ in 0..2 {
+        assert!(matches!(can2.receive(Duration::from_millis(5)), Err(CanError::Receive { code: -1, .. })));
+    }
+    assert!(wait_for(TIMEOUT, || device.connection_state() == ConnectionState::Reconnecting));
+    assert!(matches!(can2.receive(Duration::from_millis(5)), Err(CanError::Disconnected { channel: 1 })));
+    assert!(matches!(can1.transmit(&std_frame(0x1, &[])), Err(CanError::Disconnected { channel: 0 })));
+    assert_eq!(can1.dropped_while_disconnected(), 1);
+
+    mock.set_connected(true);
+    assert!(wait_for(TIMEOUT, || device.connection_state() == ConnectionState::Connected));
+    assert_eq!(can1.reconnects(), 1);
+    can1.transmit(&std_frame(0x2, &[7])).unwrap();
+    let frame = can2.receive(TIMEOUT).unwrap().pop().expect("both ports were restarted");
+    assert_eq!(content(&frame), content(&std_frame(0x2, &[7])));
+}
@@ -0,0 +1,120 @@
+//! Receiving through a [`Device`] against [`MockBackend`]: sizing reads from the pending count,
+//! and clearing the adapter's buffer.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{drain, open_pair, std_frame, TIMEOUT};
+use rustcanbus::{CanError, Frame, MockCall};
+
+fn ids(frames: &[Frame]) -> Vec<u32> {
+    frames.iter().map(|frame| frame.id().raw()).collect()
+}
+
+#[test]
+fn pending_counts_the_frames_waiting() {
+    let (mock, _device, _can1, can2) = open_pair();
+    assert_eq!(can2.pending().unwrap(), 0);
+    for id in 0..300 {
+        mock.inject(1, &std_frame(id, &[]));
+    }
+    assert_eq!(can2.pending().unwrap(), 300);
+    let frames = can2.receive(TIMEOUT).unwrap();
+    assert_eq!(ids(&frames), (0..300).collect::<Vec<_>>(), "one read takes everything pending");
+    assert_eq!(can2.pending().unwrap(), 0);
+}
+
+#[test]
+fn fewer_frames_than_counted_are_returned_as_they_are() {
+    let (mock, _device, _can1, can2) = open_pair();
+    for id in 0..3 {
+        mock.inject(1, &std_frame(id, &[]));
+    }
+    // As if other frames were read, or cleared, between the query and the read.
+    mock.fail_next(MockCall::GetReceiveNum, 300, 1);
+    let started = Instant::now();
+    let frames = can2.receive(TIMEOUT).unwrap();
+    assert_eq!(ids(&frames), [0, 1, 2]);
+    assert!(started.elapsed() < TIMEOUT, "waited for the frames that weren't there");
+
+    // And none at all: the count was stale by the time of the read.
+    mock.fail_next(MockCall::GetReceiveNum, 5, 1);
+    assert!(can2.receive(Duration::from_millis(10)).unwrap().is_empty());
+}
+
+#[test]
+fn frames_arriving_after_the_query_wait_for_the_next_read() {
+    let (mock, _device, _can1, can2) = open_pair();
+    for id in 0..5 {
+        mock.inject(1, &std_frame(id, &[]));
+    }
+    let pending = can2.pending().unwrap() as usize;
+    for id in 5..10 {
+        mock.inject(1, &std_frame(id, &[]));
+    }
+    assert_eq!(ids(&can2.receive_batch(pending, TIMEOUT).unwrap()), [0, 1, 2, 3, 4]);
+    assert_eq!(ids(&can2.receive(TIMEOUT).unwrap()), [5, 6, 7, 8, 9]);
+}
+
+#[test]
+fn counts_out_of_range_still_read_frames() {
+    let (mock, _device, _can1, can2) = open_pair();
+    // Nothing pending when asked, then a frame: the read still waits for one.
+    mock.fail_next(MockCall::GetReceiveNum, 0, 1);
+    mock.set_latency(Duration::from_millis(20));
+    mock.inject(1, &std_frame(0x1, &[]));
+    assert_eq!(ids(&can2.receive(TIMEOUT).unwrap()), [0x1]);
+
+    mock.set_latency(Duration::ZERO);
+    for id in 0..10 {
+        mock.inject(1, &std_frame(id, &[]));
+    }
+    // A count far beyond what one read takes.
+    mock.fail_next(MockCall::GetReceiveNum, i32::MAX, 1);
+    assert_eq!(ids(&can2.receive(TIMEOUT).unwrap()), (0..10).collect::<Vec<_>>());
+    assert!(drain(&can2, Duration::from_millis(10)).is_empty());
+}
+
+#[test]
+fn without_the_count_reads_are_still_batched() {
+    let (mock, _device, _can1, can2) = open_pair();
+    mock.set_supported(MockCall::GetReceiveNum, false);
+    for id in 0..4 {
+        mock.inject(1, &std_frame(id, &[]));
+    }
+    let mut received = Vec::new();
+    while received.len() < 4 {
+        let batch = can2.receive(TIMEOUT).unwrap();
+        assert!(!batch.is_empty());
+        received.extend(batch);
+    }
+    assert_eq!(ids(&received), [0, 1, 2, 3]);
+}
+
+#[test]
+fn frames_queued_before_clearing_are_never_delivered() {
+    let (mock, _device, can1, can2) = open_pair();
+    for id in 0..50 {
+        can1.transmit(&std_frame(id, &[])).unwrap();
+    }
+    assert!(can1.flush_transmit(TIMEOUT));
+    mock.inject(0, &std_frame(0x7FF, &[]));
+    assert_eq!(can2.pending().unwrap(), 50);
+    can2.clear_buffer().unwrap();
+    assert_eq!(can2.pending().unwrap(), 0);
+    assert!(can2.receive(Duration::from_millis(20)).unwrap().is_empty());
+
+    can1.transmit(&std_frame(0x100, &[1])).unwrap();
+    assert_eq!(ids(&drain(&can2, Duration::from_millis(20))), [0x100], "only what arrived after the clear");
+    assert_eq!(ids(&drain(&can1, Duration::from_millis(20))), [0x7FF], "the other port's buffer is its own");
+}
+
+#[test]
+fn clearing_without_the_function_is_unsupported() {
+    let (mock, _device, can1, can2) = open_pair();
+    mock.set_supported(MockCall::ClearBuffer, false);
+    can1.transmit(&std_frame(0x1, &[])).unwrap();
+    assert!(matches!(can2.clear_buffer(), Err(CanError::Unsupported("VCI_ClearBuffer"))));
+    assert_eq!(ids(&drain(&can2, Duration::from_millis(20))), [0x1], "nothing was cleared");
+}
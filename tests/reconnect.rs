@@ -0,0 +1,145 @@
+//! Unplugging and replugging a [`MockBackend`] adapter under [`Device::set_auto_reconnect`]:
+//! when the connection counts as lost, the states reported on the way back, and what happens
+//! to frames transmitted meanwhile.
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use common::{content, drain, open_pair, std_frame, wait_for, TIMEOUT};
+use rustcanbus::{CanError, Channel, ConnectionState, Device, DisconnectedTx, MockBackend, MockCall, Reconnect, HELD_TX_LIMIT};
+
+fn reconnect(tx: DisconnectedTx) -> Reconnect {
+    Reconnect { after_failures: 3, interval: Duration::from_millis(20), tx }
+}
+
+/// Pulls the cable and fails receives on `channel` until the device gives the handle up.
+fn unplug(mock: &MockBackend, device: &Device, channel: &Channel) {
+    mock.set_connected(false);
+    for _ in 0..3 {
+        assert!(matches!(channel.receive(Duration::from_millis(1)), Err(CanError::Receive { code: -1, .. })));
+    }
+    assert!(wait_for(TIMEOUT, || device.connection_state() == ConnectionState::Reconnecting));
+}
+
+fn replug(mock: &MockBackend, device: &Device) {
+    mock.set_connected(true);
+    assert!(wait_for(TIMEOUT, || device.connection_state() == ConnectionState::Connected));
+}
+
+#[test]
+fn reports_each_state_on_the_way_back() {
+    let (mock, device, _can1, can2) = open_pair();
+    device.set_auto_reconnect(Some(reconnect(DisconnectedTx::Drop)));
+    let states = Arc::new(Mutex::new(Vec::new()));
+    let seen = Arc::clone(&states);
+    device.set_connection_observer(Some(Box::new(move |state| seen.lock().unwrap().push(state))));
+
+    unplug(&mock, &device, &can2);
+    // Failed reopen attempts while unplugged don't report anything new.
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(can2.reconnects(), 0);
+    replug(&mock, &device);
+    assert_eq!(*states.lock().unwrap(), [ConnectionState::Lost, ConnectionState::Reconnecting, ConnectionState::Connected]);
+    assert_eq!(can2.reconnects(), 1);
+    assert_eq!(ConnectionState::Reconnecting.to_string(), "reconnecting");
+}
+
+#[test]
+fn only_consecutive_failures_count() {
+    let (mock, device, can1, can2) = open_pair();
+    device.set_auto_reconnect(Some(reconnect(DisconnectedTx::Drop)));
+    for _ in 0..5 {
+        mock.fail_next(MockCall::Receive, -1, 2);
+        for _ in 0..2 {
+            assert!(can2.receive(Duration::from_millis(1)).is_err());
+        }
+        // A transmit going through in between resets the count.
+        can1.transmit(&std_frame(0x1, &[])).unwrap();
+        assert!(can1.flush_transmit(TIMEOUT));
+    }
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(device.connection_state(), ConnectionState::Connected);
+
+    // Failures on either port add up.
+    mock.fail_next(MockCall::Receive, -1, 2);
+    mock.fail_next(MockCall::Transmit, -1, 1);
+    assert!(can2.receive(Duration::from_millis(1)).is_err());
+    assert!(can1.receive(Duration::from_millis(1)).is_err());
+    // The TX thread's failure is the third; the mock is back at once, so look for the reconnect.
+    can1.transmit(&std_frame(0x2, &[])).unwrap();
+    assert!(wait_for(TIMEOUT, || can1.reconnects() == 1));
+}
+
+#[test]
+fn held_frames_are_sent_once_reconnected() {
+    let (mock, device, can1, can2) = open_pair();
+    device.set_auto_reconnect(Some(reconnect(DisconnectedTx::Hold)));
+    unplug(&mock, &device, &can2);
+
+    let held: Vec<_> = (0..10u16).map(|n| std_frame(0x100 + n, &[n as u8])).collect();
+    for frame in &held[..5] {
+        can1.transmit(frame).unwrap();
+    }
+    assert_eq!(can1.transmit_all(&held[5..]).unwrap(), 5);
+    assert!(matches!(can2.receive(Duration::from_millis(1)), Err(CanError::Disconnected { channel: 1 })));
+    assert_eq!(can1.transmitted(), 0);
+    assert!(mock.take_transmitted(0).is_empty());
+
+    replug(&mock, &device);
+    assert!(wait_for(TIMEOUT, || can1.transmitted() == 10));
+    let expected: Vec<_> = held.iter().map(content).collect();
+    assert_eq!(mock.take_transmitted(0).iter().map(content).collect::<Vec<_>>(), expected, "in the order they were offered");
+    assert_eq!(drain(&can2, Duration::from_millis(50)).iter().map(content).collect::<Vec<_>>(), expected);
+    assert_eq!(can1.dropped_while_disconnected(), 0);
+}
+
+#[test]
+fn frames_beyond_the_hold_limit_are_dropped_and_counted() {
+    let (mock, device, can1, can2) = open_pair();
+    device.set_auto_reconnect(Some(reconnect(DisconnectedTx::Hold)));
+    unplug(&mock, &device, &can2);
+
+    let frames: Vec<_> = (0..HELD_TX_LIMIT + 5).map(|n| std_frame((n % 0x800) as u16, &[])).collect();
+    assert_eq!(can1.transmit_all(&frames).unwrap(), HELD_TX_LIMIT);
+    assert!(matches!(can1.transmit(&std_frame(0x7FF, &[])), Err(CanError::Disconnected { channel: 0 })));
+    assert_eq!(can1.dropped_while_disconnected(), 6);
+
+    replug(&mock, &device);
+    assert!(wait_for(TIMEOUT, || can1.transmitted() == HELD_TX_LIMIT as u64));
+    assert_eq!(mock.take_transmitted(0).len(), HELD_TX_LIMIT);
+}
+
+#[test]
+fn dropped_frames_are_refused_and_never_sent() {
+    let (mock, device, can1, can2) = open_pair();
+    device.set_auto_reconnect(Some(reconnect(DisconnectedTx::Drop)));
+    unplug(&mock, &device, &can2);
+
+    assert!(matches!(can1.transmit(&std_frame(0x1, &[])), Err(CanError::Disconnected { channel: 0 })));
+    assert!(matches!(can1.transmit_all(&[std_frame(0x2, &[]), std_frame(0x3, &[])]), Err(CanError::Disconnected { channel: 0 })));
+    assert!(matches!(can2.transmit(&std_frame(0x4, &[])), Err(CanError::Disconnected { channel: 1 })));
+    assert_eq!((can1.dropped_while_disconnected(), can2.dropped_while_disconnected()), (3, 1));
+
+    replug(&mock, &device);
+    thread::sleep(Duration::from_millis(50));
+    assert!(mock.take_transmitted(0).is_empty() && mock.take_transmitted(1).is_empty());
+    can1.transmit(&std_frame(0x5, &[])).unwrap();
+    assert_eq!(drain(&can2, Duration::from_millis(50)).iter().map(content).collect::<Vec<_>>(), [content(&std_frame(0x5, &[]))]);
+}
+
+#[test]
+fn without_auto_reconnect_failures_are_just_errors() {
+    let (mock, device, can1, can2) = open_pair();
+    mock.set_connected(false);
+    for _ in 0..10 {
+        assert!(matches!(can2.receive(Duration::from_millis(1)), Err(CanError::Receive { code: -1, .. })));
+        can1.transmit(&std_frame(0x1, &[])).unwrap();
+        assert!(can1.flush_transmit(TIMEOUT));
+    }
+    assert_eq!(can1.shaper_stats().dropped, 10, "every transmit failed");
+    assert_eq!(device.connection_state(), ConnectionState::Connected);
+    assert_eq!(can1.dropped_while_disconnected(), 0);
+}
@@ -0,0 +1,124 @@
+//! [`Channel::set_tx_retry`] against a [`MockBackend`] whose transmit calls take only part of
+//! their batch: the TX thread resending the rest with backoff, giving up on it at the deadline
+//! with [`CanError::TxTimeout`], and stopping when the running flag is cleared.
+
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{content, drain, ext_frame, open_pair, TIMEOUT};
+use rustcanbus::{CanError, Frame, MockCall, TxRetry};
+
+fn numbered(count: u32) -> Vec<Frame> {
+    (0..count).map(|n| ext_frame(n, &n.to_le_bytes())).collect()
+}
+
+#[test]
+fn partial_batches_are_resent_until_everything_went_out() {
+    let (mock, _device, can1, can2) = open_pair();
+    mock.set_partial_transmit(0.7);
+    can1.set_tx_retry(Some(TxRetry::new(Duration::from_secs(5))));
+    let frames = numbered(600);
+    for batch in frames.chunks(50) {
+        assert_eq!(can1.transmit_all(batch).unwrap(), batch.len());
+    }
+    for frame in &frames[..20] {
+        can1.transmit(frame).unwrap();
+    }
+    assert!(can1.flush_transmit(Duration::from_secs(5)));
+
+    let expected: Vec<_> = frames.iter().chain(&frames[..20]).map(content).collect();
+    assert_eq!(mock.take_transmitted(0).iter().map(content).collect::<Vec<_>>(), expected, "in order, none twice");
+    assert_eq!(drain(&can2, Duration::from_millis(50)).iter().map(content).collect::<Vec<_>>(), expected);
+    assert_eq!(can1.transmitted(), 620);
+    assert_eq!(can1.shaper_stats().dropped, 0);
+}
+
+#[test]
+fn without_retry_a_full_buffer_drops_the_rest() {
+    let (mock, _device, can1, _can2) = open_pair();
+    mock.set_partial_transmit(1.0);
+    let frames = numbered(8);
+    let mut shorts = 0;
+    for _ in 0..50 {
+        let dropped = can1.shaper_stats().dropped;
+        assert_eq!(can1.transmit_all(&frames).unwrap(), frames.len());
+        assert!(can1.flush_transmit(TIMEOUT));
+        // What was taken is the front of the batch; the rest is dropped.
+        let taken: Vec<_> = mock.take_transmitted(0).iter().map(content).collect();
+        assert_eq!(taken, frames[..taken.len()].iter().map(content).collect::<Vec<_>>());
+        assert_eq!(can1.shaper_stats().dropped - dropped, (frames.len() - taken.len()) as u64);
+        shorts += usize::from(taken.len() < frames.len());
+    }
+    assert!(shorts > 0, "some call took nothing");
+}
+
+#[test]
+fn a_buffer_that_stays_full_abandons_the_rest_at_the_deadline() {
+    let (mock, _device, can1, _can2) = open_pair();
+    can1.set_tx_retry(Some(TxRetry::new(Duration::from_millis(60))));
+    mock.fail_next(MockCall::Transmit, 0, u32::MAX);
+    let start = Instant::now();
+    assert_eq!(can1.transmit_all(&numbered(5)).unwrap(), 5);
+    assert!(can1.flush_transmit(Duration::from_secs(1)));
+    assert!(start.elapsed() >= Duration::from_millis(60), "gave up after {:?}", start.elapsed());
+    assert!(start.elapsed() < Duration::from_secs(1), "overshot to {:?}", start.elapsed());
+    assert_eq!(can1.shaper_stats().dropped, 5);
+    assert!(mock.take_transmitted(0).is_empty());
+    let err = CanError::TxTimeout { channel: 0, sent: 0, abandoned: 5 };
+    assert_eq!(err.to_string(), "CAN1 transmit buffer stayed full, gave up on 5 frame(s) after sending 0");
+
+    // Whatever went out before the buffer filled up is counted apart from what didn't.
+    let (mock, _device, can1, _can2) = open_pair();
+    mock.set_partial_transmit(1.0);
+    can1.set_tx_retry(Some(TxRetry::new(Duration::ZERO)));
+    let frames = numbered(200);
+    assert_eq!(can1.transmit_all(&frames).unwrap(), 200);
+    assert!(can1.flush_transmit(TIMEOUT));
+    let sent = mock.take_transmitted(0);
+    assert_eq!(sent.iter().map(content).collect::<Vec<_>>(), frames[..sent.len()].iter().map(content).collect::<Vec<_>>());
+    assert_eq!(can1.shaper_stats().dropped, (200 - sent.len()) as u64);
+    assert_eq!(can1.transmitted(), sent.len() as u64);
+}
+
+#[test]
+fn a_short_full_spell_is_waited_out_with_growing_backoff() {
+    let (mock, _device, can1, can2) = open_pair();
+    let retry = TxRetry { initial_backoff: Duration::from_millis(10), max_backoff: Duration::from_millis(40), ..TxRetry::new(Duration::from_secs(5)) };
+    can1.set_tx_retry(Some(retry));
+    mock.fail_next(MockCall::Transmit, 0, 5);
+    let start = Instant::now();
+    assert_eq!(can1.transmit_all(&numbered(3)).unwrap(), 3);
+    assert!(can1.flush_transmit(Duration::from_secs(5)));
+    // 10 + 20 + 40 + 40 + 40 ms between the six calls.
+    assert!(start.elapsed() >= Duration::from_millis(150), "backed off only {:?}", start.elapsed());
+    assert_eq!(drain(&can2, Duration::from_millis(50)).len(), 3);
+
+    // The next batch starts from the initial backoff again.
+    mock.fail_next(MockCall::Transmit, 0, 1);
+    let start = Instant::now();
+    can1.transmit(&numbered(1)[0]).unwrap();
+    assert!(can1.flush_transmit(TIMEOUT));
+    assert!(start.elapsed() < Duration::from_millis(40), "{:?}", start.elapsed());
+    assert_eq!(can1.shaper_stats().dropped, 0);
+}
+
+#[test]
+fn clearing_the_running_flag_stops_the_retries() {
+    let (mock, _device, can1, _can2) = open_pair();
+    let running = Arc::new(AtomicBool::new(true));
+    can1.set_tx_retry(Some(TxRetry::new(Duration::from_secs(30)).with_running(Arc::clone(&running))));
+    mock.fail_next(MockCall::Transmit, 0, u32::MAX);
+    let stopper = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        running.store(false, Ordering::SeqCst);
+    });
+    let start = Instant::now();
+    assert_eq!(can1.transmit_all(&numbered(4)).unwrap(), 4);
+    assert!(can1.flush_transmit(Duration::from_secs(1)), "kept retrying for {:?}", start.elapsed());
+    assert_eq!(can1.shaper_stats().dropped, 4);
+    stopper.join().unwrap();
+}
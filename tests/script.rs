@@ -0,0 +1,213 @@
+//! [`FrameScript`] handlers fed from [`MockBackend`] channels, with their actions carried out the
+//! way the `--script` option does: transmits on the channels, payload changes on a [`Scheduler`].
+#![cfg(feature = "scripting")]
+
+mod common;
+
+use std::time::Duration;
+
+use common::{content, drain, ext_frame, open_pair, std_frame, wait_for, TIMEOUT};
+use rustcanbus::{Channel, Frame, FrameScript, MockBackend, Scheduler, ScriptAction, ScriptError};
+
+const ALARM: &str = r#"
+fn on_frame(frame) {
+    if frame.id == 0x321 && frame.data[2] > 0x80 {
+        transmit(0, 0x100, [0x01]);
+        set("alarms", get("alarms") + 1);
+        log(`alarm ${get("alarms")} on CAN${frame.ch + 1}`);
+    }
+}
+set("alarms", 0);
+"#;
+
+/// Carries out `actions`, returning what was logged.
+fn perform(actions: Vec<ScriptAction>, channels: &[&Channel], scheduler: &Scheduler) -> Vec<String> {
+    let mut logged = Vec::new();
+    for action in actions {
+        match action {
+            ScriptAction::Transmit { channel, frame } => channels[channel as usize].transmit(&frame).unwrap(),
+            ScriptAction::Log(message) => logged.push(message),
+            ScriptAction::SetCyclic { index, data } => {
+                let id = scheduler.ids()[index];
+                let frame = Frame::new(scheduler.frame(id).unwrap().id(), &data).unwrap();
+                assert!(scheduler.update(id, frame));
+            }
+        }
+    }
+    logged
+}
+
+/// Injects `frame` on CAN1 and runs the script on what CAN1 receives. What scripts send on CAN1
+/// arrives on CAN2 instead, once the TX thread has sent it.
+fn feed(script: &mut FrameScript, mock: &MockBackend, channels: &[&Channel], scheduler: &Scheduler, frame: &Frame) -> Result<Vec<String>, ScriptError> {
+    mock.inject(0, frame);
+    let received = channels[0].receive(TIMEOUT).unwrap();
+    assert_eq!(received.len(), 1);
+    let result = script.on_frame(0, &received[0]).map(|actions| perform(actions, channels, scheduler));
+    assert!(channels[0].flush_transmit(TIMEOUT));
+    result
+}
+
+#[test]
+fn handlers_transmit_log_and_keep_state() {
+    let (mock, _device, can1, can2) = open_pair();
+    let (channels, scheduler) = ([&can1, &can2], Scheduler::new());
+    let mut script = FrameScript::compile(ALARM).unwrap();
+    assert!(script.handles_frames());
+    assert_eq!(script.start().unwrap(), []);
+    assert_eq!(script.var("alarms").unwrap().as_int(), Ok(0));
+
+    let quiet = [std_frame(0x321, &[0, 0, 0x80]), std_frame(0x322, &[0, 0, 0xFF]), ext_frame(0x321, &[0, 0, 0x10])];
+    for frame in &quiet {
+        assert_eq!(feed(&mut script, &mock, &channels, &scheduler, frame).unwrap(), Vec::<String>::new());
+    }
+    assert!(mock.take_transmitted(0).is_empty());
+
+    assert_eq!(feed(&mut script, &mock, &channels, &scheduler, &std_frame(0x321, &[0, 0, 0x81])).unwrap(), ["alarm 1 on CAN1"]);
+    assert_eq!(feed(&mut script, &mock, &channels, &scheduler, &std_frame(0x321, &[0, 0, 0xFF, 4])).unwrap(), ["alarm 2 on CAN1"]);
+    let sent: Vec<_> = mock.take_transmitted(0).iter().map(content).collect();
+    assert_eq!(sent, [content(&std_frame(0x100, &[1])), content(&std_frame(0x100, &[1]))]);
+    // What the script sent went out on CAN1 and crossed to CAN2.
+    assert_eq!(drain(&can2, Duration::from_millis(50)).len(), 2);
+    assert_eq!(script.var("alarms").unwrap().as_int(), Ok(2));
+
+    // Rust and the script share the variables.
+    script.set_var("alarms", 10.into());
+    assert_eq!(feed(&mut script, &mock, &channels, &scheduler, &std_frame(0x321, &[0, 0, 0x90])).unwrap(), ["alarm 11 on CAN1"]);
+}
+
+#[test]
+fn handlers_see_every_frame_field() {
+    let (mock, _device, can1, can2) = open_pair();
+    let (channels, scheduler) = ([&can1, &can2], Scheduler::new());
+    let mut script = FrameScript::compile(
+        "fn on_frame(frame) { log(`${frame.ch} ${frame.id} ${frame.extended} ${frame.remote} ${frame.dlc} ${frame.data.len()}`); }",
+    )
+    .unwrap();
+    script.start().unwrap();
+    assert_eq!(feed(&mut script, &mock, &channels, &scheduler, &std_frame(0x7FF, &[1, 2, 3])).unwrap(), ["0 2047 false false 3 3"]);
+    assert_eq!(feed(&mut script, &mock, &channels, &scheduler, &ext_frame(0x18FF_50E5, &[0; 8])).unwrap(), ["0 419385573 true false 8 8"]);
+    let remote = Frame::remote(rustcanbus::Id::Standard(0x7DF), 8).unwrap();
+    assert_eq!(feed(&mut script, &mock, &channels, &scheduler, &remote).unwrap(), ["0 2015 false true 8 0"]);
+}
+
+#[test]
+fn transmits_are_checked_before_they_are_sent() {
+    let mut script = FrameScript::compile(
+        "
+fn on_frame(frame) {
+    switch frame.id {
+        1 => transmit(0, 0x7FF, [1, 2]),
+        2 => transmit(1, 0x800, frame.data),
+        3 => transmit_ext(0, 0x12, []),
+        4 => transmit(2, 0x1, []),
+        5 => transmit(0, 0x2000_0000, []),
+        6 => transmit(0, 0x1, [0, 1, 2, 3, 4, 5, 6, 7, 8]),
+        7 => transmit(0, 0x1, [256]),
+        8 => transmit(0, -1, []),
+        9 => transmit(0, 0x1, [\"a\"]),
+    }
+}",
+    )
+    .unwrap();
+    script.start().unwrap();
+    let run = |script: &mut FrameScript, id: u16| script.on_frame(1, &std_frame(id, &[9, 8, 7]));
+    assert_eq!(run(&mut script, 1).unwrap(), [ScriptAction::Transmit { channel: 0, frame: std_frame(0x7FF, &[1, 2]) }]);
+    assert_eq!(run(&mut script, 2).unwrap(), [ScriptAction::Transmit { channel: 1, frame: ext_frame(0x800, &[9, 8, 7]) }], "above 0x7FF goes extended");
+    assert_eq!(run(&mut script, 3).unwrap(), [ScriptAction::Transmit { channel: 0, frame: ext_frame(0x12, &[]) }]);
+    for (id, message) in [
+        (4, "no channel 2"),
+        (5, "invalid ID 0x20000000"),
+        (6, "9 data bytes, at most 8 fit"),
+        (7, "byte 256 out of range"),
+        (8, "invalid ID -1"),
+        (9, "expected a byte, got string"),
+    ] {
+        let err = run(&mut script, id).unwrap_err();
+        assert!(err.message.contains(message), "{id}: {err}");
+        assert_eq!(err.line, Some(3 + usize::from(id)), "{id}: {err}");
+    }
+}
+
+#[test]
+fn errors_name_the_script_line() {
+    let err = FrameScript::compile("fn on_frame(frame) {\n    let x = ;\n}").err().unwrap();
+    assert_eq!(err.line, Some(2), "{err}");
+    assert!(err.to_string().starts_with("script line 2: "), "{err}");
+
+    let mut script = FrameScript::compile("set(\"n\", 0);\nlet x = 1;\nx.no_such_method();").unwrap();
+    let err = script.start().unwrap_err();
+    assert_eq!(err.line, Some(3), "{err}");
+
+    // Inside a function called from `on_frame`, the line is where it failed, not the call.
+    let mut script = FrameScript::compile("fn byte(frame) {\n    frame.data[5]\n}\n\nfn on_frame(frame) {\n    log(`${byte(frame)}`);\n}").unwrap();
+    script.start().unwrap();
+    let err = script.on_frame(0, &std_frame(0x1, &[1])).unwrap_err();
+    assert_eq!(err.line, Some(2), "{err}");
+}
+
+#[test]
+fn a_failing_handler_costs_only_its_frame() {
+    let (mock, _device, can1, can2) = open_pair();
+    let (channels, scheduler) = ([&can1, &can2], Scheduler::new());
+    let mut script = FrameScript::compile(
+        r#"
+fn on_frame(frame) {
+    transmit(0, 0x200, [frame.data[0]]);
+    if frame.data[0] == 0xEE {
+        throw "bad frame";
+    }
+    if frame.data[0] == 0x10 {
+        loop {}
+    }
+    set("seen", get("seen") + 1);
+}
+set("seen", 0);
+"#,
+    )
+    .unwrap();
+    script.start().unwrap();
+    for byte in [1, 0xEE, 2, 0x10, 3] {
+        match feed(&mut script, &mock, &channels, &scheduler, &std_frame(0x1, &[byte])) {
+            Ok(_) => assert!(![0xEE, 0x10].contains(&byte)),
+            Err(err) if byte == 0xEE => assert_eq!((err.line, err.message.contains("bad frame")), (Some(5), true), "{err}"),
+            // The runaway loop is stopped rather than stalling the pipeline.
+            Err(err) => assert_eq!((byte, err.message.contains("Too many operations")), (0x10, true), "{err}"),
+        }
+    }
+    assert_eq!(script.var("seen").unwrap().as_int(), Ok(3));
+    // The transmits of the failed calls were discarded with them.
+    let sent: Vec<_> = mock.take_transmitted(0).iter().map(|frame| frame.data()[0]).collect();
+    assert_eq!(sent, [1, 2, 3]);
+}
+
+#[test]
+fn scripts_change_cyclic_payloads() {
+    let (mock, _device, can1, can2) = open_pair();
+    let (channels, scheduler) = ([&can1, &can2], Scheduler::new());
+    scheduler.add(&can1, std_frame(0x400, &[0]), Duration::from_millis(10));
+    scheduler.add(&can1, std_frame(0x401, &[0, 0]), Duration::from_millis(10));
+    let mut script = FrameScript::compile("fn on_frame(frame) { set_cyclic(1, frame.data); }\nset_cyclic(0, [0xAA]);").unwrap();
+    assert!(perform(script.start().unwrap(), &channels, &scheduler).is_empty());
+    feed(&mut script, &mock, &channels, &scheduler, &std_frame(0x1, &[0xBB, 0xCC, 0xDD])).unwrap();
+
+    let updated = |id: u16, data: &'static [u8]| {
+        let mock = mock.clone();
+        move || mock.take_transmitted(0).iter().any(|frame| content(frame) == content(&std_frame(id, data)))
+    };
+    assert!(wait_for(TIMEOUT, updated(0x400, &[0xAA])));
+    assert!(wait_for(TIMEOUT, updated(0x401, &[0xBB, 0xCC, 0xDD])));
+
+    let err = FrameScript::compile("set_cyclic(-1, []);").unwrap().start().unwrap_err();
+    assert!(err.message.contains("no cyclic message -1"), "{err}");
+    let err = FrameScript::compile("set_cyclic(0, [0, 0, 0, 0, 0, 0, 0, 0, 0]);").unwrap().start().unwrap_err();
+    assert!(err.message.contains("9 data bytes"), "{err}");
+}
+
+#[test]
+fn scripts_without_a_handler_only_run_once() {
+    let mut script = FrameScript::compile("log(\"started\");\nfn on_frame(a, b) {}").unwrap();
+    assert!(!script.handles_frames(), "on_frame takes the frame alone");
+    assert_eq!(script.start().unwrap(), [ScriptAction::Log("started".into())]);
+    assert_eq!(script.on_frame(0, &std_frame(0x1, &[])).unwrap(), []);
+}
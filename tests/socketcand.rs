@@ -0,0 +1,214 @@
+//! [`SocketcandServer`] on the wire: a scripted TCP client talking to a server in front of
+//! [`MockBackend`] channels.
+
+mod common;
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use common::{content, ext_frame, open_pair, std_frame, wait_for, TIMEOUT};
+use rustcanbus::{Frame, Id, SocketcandServer};
+
+struct Client {
+    stream: TcpStream,
+    pending: Vec<u8>,
+}
+
+impl Client {
+    fn connect(server: &SocketcandServer) -> Self {
+        let stream = TcpStream::connect(server.local_addr()).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let mut client = Self { stream, pending: Vec::new() };
+        client.expect("< hi >");
+        client
+    }
+
+    /// Connects and opens `bus` in raw mode.
+    fn raw(server: &SocketcandServer, bus: &str) -> Self {
+        let mut client = Self::connect(server);
+        client.send(&format!("< open {bus} >"));
+        client.expect("< ok >");
+        client.send("< rawmode >");
+        client.expect("< ok >");
+        client
+    }
+
+    fn send(&mut self, text: &str) {
+        self.stream.write_all(text.as_bytes()).unwrap();
+    }
+
+    /// The next `< ... >` message, or `None` if nothing came within the timeout or the server
+    /// hung up.
+    fn next(&mut self) -> Option<String> {
+        loop {
+            if let Some(end) = self.pending.iter().position(|&b| b == b'>') {
+                let message = String::from_utf8(self.pending.drain(..=end).collect()).unwrap();
+                return Some(message.trim_start().to_string());
+            }
+            let mut chunk = [0; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return None,
+                Ok(n) => self.pending.extend_from_slice(&chunk[..n]),
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return None,
+                Err(err) => panic!("{err}"),
+            }
+        }
+    }
+
+    fn expect(&mut self, message: &str) {
+        assert_eq!(self.next().as_deref(), Some(message));
+    }
+
+    /// The next message, which must be a frame, as its ID, data and timestamp.
+    fn frame(&mut self) -> (String, String, f64) {
+        let message = self.next().expect("a frame");
+        let words: Vec<&str> = message.split_whitespace().collect();
+        match words.as_slice() {
+            ["<", "frame", id, time, data, ">"] => (id.to_string(), data.to_string(), time.parse().unwrap()),
+            ["<", "frame", id, time, ">"] => (id.to_string(), String::new(), time.parse().unwrap()),
+            _ => panic!("not a frame: {message}"),
+        }
+    }
+}
+
+#[test]
+fn commands_and_their_replies() {
+    let (mock, _device, can1, can2) = open_pair();
+    let server = SocketcandServer::start("127.0.0.1:0", &[can1, can2.clone()]).unwrap();
+    let mut client = Client::connect(&server);
+
+    client.send("< send 123 0 >");
+    client.expect("< error no bus open >");
+    client.send("< echo >");
+    client.expect("< echo >");
+    client.send("< open can2 >< open vcan0 >");
+    client.expect("< error could not open bus can2 >");
+    client.expect("< error could not open bus vcan0 >");
+    client.send("< open can1 >");
+    client.expect("< ok >");
+    client.send("< open can0 >");
+    client.expect("< error bus already open >");
+    client.send("< bcmmode >");
+    client.expect("< error unsupported command bcmmode >");
+
+    for (command, reply) in [
+        ("< send 800 0 >", "< error invalid ID 800 >"),
+        ("< send 20000000 0 >", "< error invalid ID 20000000 >"),
+        ("< send 123 >", "< error send needs an ID and a DLC >"),
+        ("< send 123 x >", "< error invalid DLC x >"),
+        ("< send 123 2 11 >", "< error DLC 2 but 1 data bytes >"),
+        ("< send 123 1 1G >", "< error invalid data byte 1G >"),
+        ("< send 123 9 0 1 2 3 4 5 6 7 8 >", "< error 9 data bytes, at most 8 fit >"),
+    ] {
+        client.send(command);
+        client.expect(reply);
+    }
+    assert!(mock.take_transmitted(1).is_empty());
+
+    // Successful sends have no reply; commands may be split across writes or share one.
+    client.send("< send 123 2 11 22 >< send 0000001F 1 AA");
+    thread::sleep(Duration::from_millis(20));
+    client.send(" >  < send 1FFFFFFF 0 >");
+    client.send("< send 7ff 8 0 1 2 3 4 5 6 ff >< echo >");
+    client.expect("< echo >");
+    assert!(can2.flush_transmit(TIMEOUT));
+    let sent: Vec<_> = mock.take_transmitted(1).iter().map(content).collect();
+    let expected = [std_frame(0x123, &[0x11, 0x22]), ext_frame(0x1F, &[0xAA]), ext_frame(0x1FFF_FFFF, &[]), std_frame(0x7FF, &[0, 1, 2, 3, 4, 5, 6, 0xFF])];
+    assert_eq!(sent, expected.iter().map(content).collect::<Vec<_>>(), "8 ID digits mean extended");
+    assert!(mock.take_transmitted(0).is_empty(), "can1 is CAN2");
+}
+
+#[test]
+fn every_raw_client_gets_its_buss_frames() {
+    let (mock, _device, can1, can2) = open_pair();
+    let server = SocketcandServer::start("127.0.0.1:0", &[can1, can2]).unwrap();
+    let mut first = Client::raw(&server, "can1");
+    let mut second = Client::raw(&server, "can1");
+    let mut other = Client::raw(&server, "can0");
+    assert!(wait_for(TIMEOUT, || server.clients() == 3));
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+    mock.inject(1, &std_frame(0x12, &[0xDE, 0xAD, 0xBE, 0xEF]));
+    mock.inject(1, &Frame::remote(Id::Standard(0x7DF), 8).unwrap());
+    mock.inject(1, &ext_frame(0x18FF_50E5, &[1, 2, 3, 4, 5, 6, 7, 8]));
+    mock.inject(1, &std_frame(0x7FF, &[]));
+    mock.inject(0, &ext_frame(0x123, &[0x0A]));
+
+    for client in [&mut first, &mut second] {
+        let frames: Vec<_> = (0..3).map(|_| client.frame()).collect();
+        let ids: Vec<_> = frames.iter().map(|(id, data, _)| (id.as_str(), data.as_str())).collect();
+        assert_eq!(ids, [("012", "DEADBEEF"), ("18FF50E5", "0102030405060708"), ("7FF", "")], "remote frames are left out");
+        for (_, _, time) in &frames {
+            assert!((time - now).abs() < 60.0, "{time} is not the time of reception");
+        }
+        assert!(client.next().is_none());
+    }
+    assert_eq!(other.frame().0, "00000123");
+    assert!(other.next().is_none());
+
+    // Commands still work while frames stream.
+    first.send("< send 321 1 01 >");
+    mock.inject(1, &std_frame(0x1, &[]));
+    assert_eq!(first.frame().0, "001");
+    let mut sent = Vec::new();
+    assert!(wait_for(TIMEOUT, || {
+        sent.extend(mock.take_transmitted(1).iter().map(content));
+        !sent.is_empty()
+    }));
+    assert_eq!(sent, [content(&std_frame(0x321, &[1]))]);
+    assert_eq!(other.frame().0, "321", "what a client sends on CAN2 shows up on CAN1");
+}
+
+#[test]
+fn frames_need_raw_mode() {
+    let (mock, _device, can1, can2) = open_pair();
+    let server = SocketcandServer::start("127.0.0.1:0", &[can1, can2]).unwrap();
+    let mut client = Client::connect(&server);
+    client.send("< rawmode >");
+    client.expect("< error no bus open >");
+    client.send("< open can0 >");
+    client.expect("< ok >");
+    mock.inject(0, &std_frame(0x1, &[]));
+    assert!(client.next().is_none());
+    client.send("< rawmode >< rawmode >");
+    client.expect("< ok >");
+    client.expect("< ok >");
+    assert_eq!(client.frame().0, "001", "it waited at the adapter");
+    mock.inject(0, &std_frame(0x2, &[]));
+    assert_eq!(client.frame().0, "002", "once, however often asked");
+    assert!(client.next().is_none());
+}
+
+#[test]
+fn a_client_that_stops_reading_is_disconnected() {
+    let (mock, _device, can1, can2) = open_pair();
+    let mut server = SocketcandServer::start("127.0.0.1:0", &[can1, can2.clone()]).unwrap();
+    let stuck = Client::raw(&server, "can1");
+    let mut reader = Client::raw(&server, "can1");
+    let reading = thread::spawn(move || {
+        let mut ids = Vec::new();
+        while let Some(message) = reader.next() {
+            ids.push(u32::from_str_radix(message.split_whitespace().nth(2).unwrap(), 16).unwrap());
+        }
+        ids
+    });
+
+    // Until the unread client's socket and queue are full.
+    let mut injected = 0;
+    while server.clients() == 2 {
+        assert!(injected < 1_000_000, "never disconnected");
+        for _ in 0..500 {
+            mock.inject(1, &ext_frame(injected, &[0; 8]));
+            injected += 1;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    mock.inject(1, &ext_frame(injected, &[]));
+    assert!(wait_for(TIMEOUT, || can2.pending().unwrap() == 0), "reception kept going");
+    server.stop();
+    let ids = reading.join().unwrap();
+    assert!(ids.iter().copied().eq(0..=injected), "the other client got every frame in order");
+    drop(stuck);
+}
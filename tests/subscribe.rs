@@ -0,0 +1,88 @@
+//! [`Channel::subscribe`] against [`MockBackend`]: the reader thread draining the adapter, and
+//! subscribers that can't keep up losing frames from their own queue only.
+
+mod common;
+
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::Duration;
+
+use common::{ext_frame, open_pair, wait_for, TIMEOUT};
+use rustcanbus::Frame;
+
+fn numbered(n: u32) -> Frame {
+    ext_frame(n, &n.to_le_bytes())
+}
+
+#[test]
+fn a_sleeping_consumer_costs_no_frames_at_the_adapter() {
+    let (mock, _device, _can1, can2) = open_pair();
+    let fast = can2.subscribe(4096);
+    let stuck = can2.subscribe(16);
+    let consumer = thread::spawn(move || (0..2000).map(|_| fast.recv_timeout(TIMEOUT * 4).expect("every frame reaches the fast consumer")).collect::<Vec<_>>());
+
+    // 2000 frames a second, in bursts, while `stuck` is never read.
+    for burst in 0..20 {
+        for n in burst * 100..(burst + 1) * 100 {
+            mock.inject(1, &numbered(n));
+        }
+        thread::sleep(Duration::from_millis(50));
+        assert!(can2.pending().unwrap() <= 200, "the adapter backed up behind the stuck consumer");
+    }
+
+    let received = consumer.join().unwrap();
+    assert!(received.iter().map(|frame| frame.id().raw()).eq(0..2000), "in order, none missing");
+    assert!(wait_for(TIMEOUT, || can2.pending().unwrap() == 0));
+    assert_eq!(can2.received(), 2000);
+
+    // `stuck` kept the first frames that fit, and counted the rest.
+    assert_eq!(stuck.dropped(), 2000 - 16);
+    let kept: Vec<_> = (0..16).map(|_| stuck.try_recv().unwrap().id().raw()).collect();
+    assert_eq!(kept, (0..16).collect::<Vec<_>>());
+    assert!(stuck.try_recv().is_err());
+}
+
+#[test]
+fn every_subscriber_gets_every_frame() {
+    let (mock, _device, can1, can2) = open_pair();
+    let subscriptions: Vec<_> = (0..4).map(|_| can2.subscribe(256)).collect();
+    for n in 0..100 {
+        can1.transmit(&numbered(n)).unwrap();
+    }
+    assert!(can1.flush_transmit(TIMEOUT));
+    mock.inject(1, &numbered(100));
+    for subscription in &subscriptions {
+        let ids: Vec<_> = (0..101).map(|_| subscription.recv_timeout(TIMEOUT).unwrap().id().raw()).collect();
+        assert_eq!(ids, (0..101).collect::<Vec<_>>());
+        assert_eq!(subscription.dropped(), 0);
+    }
+    // A subscription to the other port hears nothing of these.
+    let other = can1.subscribe(16);
+    mock.inject(1, &numbered(101));
+    assert!(matches!(other.recv_timeout(Duration::from_millis(50)), Err(RecvTimeoutError::Timeout)));
+}
+
+#[test]
+fn the_reader_stops_with_the_last_subscription() {
+    let (mock, _device, _can1, can2) = open_pair();
+    let first = can2.subscribe(16);
+    let second = can2.subscribe(16);
+    mock.inject(1, &numbered(1));
+    assert_eq!(first.recv_timeout(TIMEOUT).unwrap().id().raw(), 1);
+    assert_eq!(second.recv_timeout(TIMEOUT).unwrap().id().raw(), 1);
+    drop(first);
+    mock.inject(1, &numbered(2));
+    assert_eq!(second.recv_timeout(TIMEOUT).unwrap().id().raw(), 2, "still read for the one left");
+    drop(second);
+
+    // Nothing drains the adapter any more, so frames wait there for the next read.
+    thread::sleep(Duration::from_millis(200));
+    for n in 3..6 {
+        mock.inject(1, &numbered(n));
+    }
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(can2.pending().unwrap(), 3);
+    let again = can2.subscribe(16);
+    let ids: Vec<_> = (0..3).map(|_| again.recv_timeout(TIMEOUT).unwrap().id().raw()).collect();
+    assert_eq!(ids, [3, 4, 5]);
+}
@@ -0,0 +1,214 @@
+//! Transmitting through a [`Device`] against [`MockBackend`]: the TX thread every started port
+//! sends from and its queue, and ports that must not transmit at all.
+
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{config, content, drain, open, open_pair, start, std_frame, wait_for, TIMEOUT};
+use rustcanbus::{CanError, ChannelMode, MockCall, SendType, ShaperStats, TxRetry, TxShaping};
+
+/// A queue of `queue` frames, without limits.
+fn queue(queue: usize) -> TxShaping {
+    TxShaping { queue, ..TxShaping::default() }
+}
+
+/// One frame a second through the TX thread, behind a queue of `queue`.
+fn slow(queue: usize) -> TxShaping {
+    TxShaping { frames_per_sec: Some(1.0), queue, ..TxShaping::default() }
+}
+
+#[test]
+fn tx_thread_sends_everything_in_order() {
+    let (mock, _device, can1, can2) = open_pair();
+    can1.set_tx_shaping(Some(queue(64))).unwrap();
+    let sent: Vec<_> = (0..500u16).map(|n| std_frame(n % 0x800, &n.to_le_bytes())).collect();
+    for frame in &sent {
+        can1.transmit(frame).unwrap();
+    }
+
+    assert!(can1.flush_transmit(TIMEOUT * 4));
+    assert_eq!(can1.shaper_stats(), ShaperStats { passed: 500, ..ShaperStats::default() });
+    assert_eq!(can1.transmitted(), 500);
+    let expected: Vec<_> = sent.iter().map(content).collect();
+    assert_eq!(mock.take_transmitted(0).iter().map(content).collect::<Vec<_>>(), expected);
+    assert_eq!(drain(&can2, Duration::from_millis(50)).iter().map(content).collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn frames_from_several_threads_keep_their_order_per_thread() {
+    let (mock, _device, can1, _can2) = open_pair();
+    can1.set_tx_shaping(Some(queue(16))).unwrap();
+    let senders: Vec<_> = (0..4u16)
+        .map(|sender| {
+            let can1 = can1.clone();
+            thread::spawn(move || (0..200u16).for_each(|n| can1.transmit(&std_frame(sender, &n.to_le_bytes())).unwrap()))
+        })
+        .collect();
+    senders.into_iter().for_each(|sender| sender.join().unwrap());
+
+    assert!(can1.flush_transmit(TIMEOUT * 4));
+    assert_eq!(can1.shaper_stats().passed, 800);
+    let transmitted = mock.take_transmitted(0);
+    for sender in 0..4 {
+        let counts: Vec<u16> = transmitted.iter().filter(|frame| frame.id().raw() == sender).map(|frame| u16::from_le_bytes([frame.data()[0], frame.data()[1]])).collect();
+        assert_eq!(counts, (0..200).collect::<Vec<_>>(), "sender {sender}");
+    }
+}
+
+#[test]
+fn try_transmit_refuses_frames_once_the_queue_is_full() {
+    let (mock, _device, can1, _can2) = open_pair();
+    can1.set_tx_shaping(Some(slow(4))).unwrap();
+    // Spends the one frame the limit lets out now; the next can go in a second.
+    can1.transmit(&std_frame(0x100, &[])).unwrap();
+    assert!(wait_for(TIMEOUT, || mock.take_transmitted(0).len() == 1));
+
+    let results: Vec<_> = (1..=6).map(|n| can1.try_transmit(&std_frame(0x100 + n, &[]))).collect();
+    assert!(results[..4].iter().all(Result::is_ok), "{results:?}");
+    assert!(results[4..].iter().all(|result| matches!(result, Err(CanError::TxQueueFull { channel: 0 }))), "{results:?}");
+    assert_eq!(can1.shaper_stats(), ShaperStats { passed: 1, dropped: 2, refused: 2, queued: 4, ..ShaperStats::default() });
+    assert!(mock.take_transmitted(0).is_empty(), "refused frames never reach the DLL");
+}
+
+#[test]
+fn transmit_timeout_gives_up_after_the_timeout() {
+    let (mock, _device, can1, _can2) = open_pair();
+    can1.set_tx_shaping(Some(slow(1))).unwrap();
+    can1.transmit(&std_frame(0x100, &[])).unwrap();
+    assert!(wait_for(TIMEOUT, || mock.take_transmitted(0).len() == 1));
+    can1.transmit(&std_frame(0x101, &[])).unwrap();
+
+    let started = Instant::now();
+    let result = can1.transmit_timeout(&std_frame(0x102, &[]), Duration::from_millis(100));
+    let waited = started.elapsed();
+    assert!(matches!(result, Err(CanError::TxQueueFull { channel: 0 })), "{result:?}");
+    assert!(waited >= Duration::from_millis(100) && waited < Duration::from_millis(600), "gave up after {waited:?}");
+    assert_eq!(can1.shaper_stats(), ShaperStats { passed: 1, dropped: 1, refused: 1, queued: 1, ..ShaperStats::default() });
+}
+
+#[test]
+fn transmit_timeout_succeeds_once_the_thread_makes_room() {
+    let (mock, _device, can1, _can2) = open_pair();
+    can1.set_tx_shaping(Some(slow(1))).unwrap();
+    can1.transmit(&std_frame(0x100, &[])).unwrap();
+    assert!(wait_for(TIMEOUT, || mock.take_transmitted(0).len() == 1));
+    can1.transmit(&std_frame(0x101, &[])).unwrap();
+
+    // 0x101 goes out about a second after 0x100, which frees its place in the queue.
+    let started = Instant::now();
+    can1.transmit_timeout(&std_frame(0x102, &[]), Duration::from_secs(3)).unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(500), "queued after {:?} with the queue full", started.elapsed());
+    assert!(wait_for(TIMEOUT, || can1.shaper_stats().delayed == 1));
+    assert_eq!(can1.shaper_stats(), ShaperStats { passed: 1, delayed: 1, queued: 1, ..ShaperStats::default() });
+    assert_eq!(mock.take_transmitted(0).iter().map(content).collect::<Vec<_>>(), [content(&std_frame(0x101, &[]))]);
+}
+
+#[test]
+fn drop_on_full_refuses_instead_of_waiting() {
+    let (mock, _device, can1, _can2) = open_pair();
+    can1.set_tx_shaping(Some(TxShaping { drop_on_full: true, ..slow(2) })).unwrap();
+    can1.transmit(&std_frame(0x100, &[])).unwrap();
+    assert!(wait_for(TIMEOUT, || mock.take_transmitted(0).len() == 1));
+
+    let results: Vec<_> = (1..=5).map(|n| can1.transmit(&std_frame(0x100 + n, &[]))).collect();
+    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 2, "{results:?}");
+    assert_eq!(can1.shaper_stats(), ShaperStats { passed: 1, dropped: 3, refused: 3, queued: 2, ..ShaperStats::default() });
+    // Lifting the limit sends what the queue holds straight away.
+    can1.set_tx_shaping(None).unwrap();
+    assert!(can1.flush_transmit(TIMEOUT));
+    assert_eq!(mock.take_transmitted(0).iter().map(|frame| frame.id().raw()).collect::<Vec<_>>(), [0x101, 0x102]);
+    assert_eq!(can1.transmitted(), 3);
+    let stats = can1.shaper_stats();
+    assert_eq!((stats.passed + stats.delayed, stats.dropped, stats.refused, stats.queued), (3, 3, 3, 0), "{stats:?}");
+}
+
+#[test]
+fn try_transmit_never_waits_for_the_dll() {
+    let (mock, _device, can1, _can2) = open_pair();
+    can1.set_tx_shaping(Some(queue(2))).unwrap();
+    // The TX thread keeps offering the first frame to a buffer that stays full.
+    can1.set_tx_retry(Some(TxRetry::new(Duration::from_secs(5))));
+    mock.fail_next(MockCall::Transmit, 0, u32::MAX);
+    can1.transmit(&std_frame(0x100, &[])).unwrap();
+    assert!(wait_for(TIMEOUT, || can1.shaper_stats().queued == 0));
+
+    let started = Instant::now();
+    let results: Vec<_> = (1..=4).map(|n| can1.try_transmit(&std_frame(0x100 + n, &[]))).collect();
+    assert!(started.elapsed() < Duration::from_millis(100), "waited {:?}", started.elapsed());
+    assert!(results[..2].iter().all(Result::is_ok), "{results:?}");
+    assert!(results[2..].iter().all(|result| matches!(result, Err(CanError::TxQueueFull { channel: 0 }))), "{results:?}");
+    assert!(!can1.flush_transmit(Duration::from_millis(50)), "still sending the first");
+}
+
+#[test]
+fn dll_failures_are_counted_as_dropped() {
+    let (mock, _device, can1, can2) = open_pair();
+    mock.fail_next(MockCall::Transmit, -5, 1);
+    can1.transmit_all(&[std_frame(0x100, &[]), std_frame(0x101, &[])]).unwrap();
+    assert!(can1.flush_transmit(TIMEOUT));
+    can1.transmit(&std_frame(0x102, &[])).unwrap();
+    assert!(can1.flush_transmit(TIMEOUT));
+    assert_eq!(can1.shaper_stats(), ShaperStats { passed: 1, dropped: 2, ..ShaperStats::default() });
+    assert_eq!(drain(&can2, Duration::from_millis(50)).iter().map(content).collect::<Vec<_>>(), [content(&std_frame(0x102, &[]))]);
+}
+
+#[test]
+fn closing_the_device_sends_what_is_still_queued() {
+    let (_mock, device, can1, _can2) = open_pair();
+    can1.set_tx_shaping(Some(TxShaping { frames_per_sec: Some(100.0), ..TxShaping::default() })).unwrap();
+    let frames: Vec<_> = (0..20u16).map(|n| std_frame(n, &[])).collect();
+    assert_eq!(can1.transmit_all(&frames).unwrap(), 20);
+    drop(device);
+    assert_eq!(can1.transmitted(), 20);
+    assert_eq!(can1.shaper_stats().queued, 0);
+}
+
+#[test]
+fn listen_only_ports_refuse_to_transmit_without_calling_the_dll() {
+    let (mock, device) = open();
+    let sniffer = start(&device, 0, &config(ChannelMode::ListenOnly));
+    let peer = start(&device, 1, &config(ChannelMode::Normal));
+    // Armed on the DLL's transmit: had any of these reached it, they'd fail with -5 instead.
+    mock.fail_next(MockCall::Transmit, -5, 1);
+    let frame = std_frame(0x123, &[1]);
+    let results = [
+        sniffer.transmit(&frame),
+        sniffer.try_transmit(&frame),
+        sniffer.transmit_timeout(&frame, Duration::from_millis(10)),
+        sniffer.transmit_with(&frame, SendType::SingleShot),
+        sniffer.transmit_all(&[frame, frame]).map(drop),
+    ];
+    for result in &results {
+        assert!(matches!(result, Err(CanError::ListenOnly { channel: 0 })), "{results:?}");
+    }
+    sniffer.set_tx_shaping(Some(queue(8))).unwrap();
+    assert!(matches!(sniffer.transmit(&frame), Err(CanError::ListenOnly { channel: 0 })));
+    assert_eq!(sniffer.shaper_stats(), ShaperStats::default(), "nothing reaches the TX thread's queue either");
+
+    assert!(mock.take_transmitted(0).is_empty());
+    assert!(drain(&peer, Duration::from_millis(20)).is_empty());
+    assert_eq!(sniffer.transmitted(), 0);
+    peer.transmit(&frame).unwrap();
+    assert!(peer.flush_transmit(TIMEOUT));
+    assert_eq!(peer.shaper_stats().dropped, 1, "the armed failure was still there");
+
+    // Still listening, though.
+    peer.transmit(&std_frame(0x456, &[2])).unwrap();
+    assert_eq!(sniffer.receive(TIMEOUT).unwrap().iter().map(content).collect::<Vec<_>>(), [content(&std_frame(0x456, &[2]))]);
+}
+
+#[test]
+fn reconfiguring_out_of_listen_only_allows_transmitting() {
+    let (mock, device) = open();
+    let can1 = start(&device, 0, &config(ChannelMode::ListenOnly));
+    let _can2 = start(&device, 1, &config(ChannelMode::Normal));
+    assert!(matches!(can1.transmit(&std_frame(0x1, &[])), Err(CanError::ListenOnly { channel: 0 })));
+    can1.reconfigure(&config(ChannelMode::Normal)).unwrap();
+    can1.transmit(&std_frame(0x2, &[])).unwrap();
+    assert!(can1.flush_transmit(TIMEOUT));
+    can1.reconfigure(&config(ChannelMode::ListenOnly)).unwrap();
+    assert!(matches!(can1.transmit(&std_frame(0x3, &[])), Err(CanError::ListenOnly { channel: 0 })));
+    assert_eq!(mock.take_transmitted(0).iter().map(|frame| frame.id().raw()).collect::<Vec<_>>(), [0x2]);
+}
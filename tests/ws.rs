@@ -0,0 +1,241 @@
+//! [`WsServer`] against [`MockBackend`] channels, driven by a tungstenite client the way a
+//! browser dashboard would use it.
+
+mod common;
+
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use common::{content, ext_frame, open_pair, std_frame, wait_for, TIMEOUT};
+use rustcanbus::{Frame, Id, JsonFrame, WsServer};
+use serde_json::Value;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+type Client = WebSocket<MaybeTlsStream<TcpStream>>;
+
+fn url(server: &WsServer) -> String {
+    format!("ws://{}/", server.local_addr())
+}
+
+fn connect(server: &WsServer) -> Client {
+    let (client, _) = tungstenite::connect(url(server)).expect("the handshake succeeds");
+    if let MaybeTlsStream::Plain(stream) = client.get_ref() {
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    }
+    client
+}
+
+fn local_addr(client: &Client) -> SocketAddr {
+    match client.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream.local_addr().unwrap(),
+        _ => unreachable!(),
+    }
+}
+
+fn send(client: &mut Client, request: Value) {
+    client.send(Message::text(request.to_string())).unwrap();
+}
+
+/// The next text message as JSON, or `None` if nothing came within the timeout.
+fn next(client: &mut Client) -> Option<Value> {
+    loop {
+        match client.read() {
+            Ok(Message::Text(text)) => return Some(serde_json::from_str(&text).unwrap()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err)) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return None,
+            Err(err) => panic!("{err}"),
+        }
+    }
+}
+
+/// The next text message, which must come, allowing a loaded machine a few timeouts for it.
+fn expect(client: &mut Client, what: &str) -> Value {
+    (0..4).find_map(|_| next(client)).unwrap_or_else(|| panic!("no {what}"))
+}
+
+fn frame(client: &mut Client) -> JsonFrame {
+    serde_json::from_value(expect(client, "frame")).unwrap()
+}
+
+/// The error the server answers `request` with.
+fn error(client: &mut Client, request: Value) -> String {
+    send(client, request);
+    let reply = expect(client, "reply");
+    reply["error"].as_str().unwrap_or_else(|| panic!("not an error: {reply}")).to_string()
+}
+
+/// Returns once the server has handled everything `client` sent so far: requests are handled
+/// in order, and this one fails.
+fn sync(client: &mut Client) {
+    error(client, serde_json::json!({ "type": "sync" }));
+}
+
+fn ids(frames: &[JsonFrame]) -> Vec<(&str, bool)> {
+    frames.iter().map(|frame| (frame.id.as_str(), frame.ext)).collect()
+}
+
+#[test]
+fn every_client_gets_received_frames_as_json() {
+    let (mock, _device, can1, can2) = open_pair();
+    let server = WsServer::start("127.0.0.1:0", &[can1, can2], 8).unwrap();
+    let mut clients = [connect(&server), connect(&server)];
+    assert!(wait_for(TIMEOUT, || server.clients() == 2));
+    clients.iter_mut().for_each(sync);
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+    mock.inject(0, &std_frame(0x123, &[0x0A, 0x0B, 0x0C]));
+    mock.inject(1, &ext_frame(0x18FF_50E5, &[0xFF; 8]));
+    mock.inject(1, &Frame::remote(Id::Standard(0x7DF), 8).unwrap());
+    for client in &mut clients {
+        let mut frames: Vec<_> = (0..3).map(|_| frame(client)).collect();
+        assert!(next(client).is_none());
+        assert!(frames.iter().all(|frame| (frame.ts - now).abs() < 60.0), "{frames:?}");
+        // Each channel's frames are in order; the channels interleave freely.
+        frames.sort_by_key(|frame| frame.ch);
+        frames.iter_mut().for_each(|frame| frame.ts = 0.0);
+        let json = |ch, frame: &Frame| JsonFrame { ts: 0.0, ..JsonFrame::new(UNIX_EPOCH, ch, frame) };
+        assert_eq!(
+            frames,
+            [
+                json(0, &std_frame(0x123, &[0x0A, 0x0B, 0x0C])),
+                json(1, &ext_frame(0x18FF_50E5, &[0xFF; 8])),
+                json(1, &Frame::remote(Id::Standard(0x7DF), 8).unwrap()),
+            ]
+        );
+        assert_eq!((frames[0].id.as_str(), frames[0].data.as_str(), frames[2].rtr, frames[2].dlc), ("0x123", "0A0B0C", true, 8));
+    }
+    assert_eq!(server.dropped(), 0);
+}
+
+#[test]
+fn filters_are_per_client() {
+    let (mock, _device, can1, can2) = open_pair();
+    let server = WsServer::start("127.0.0.1:0", &[can1, can2], 8).unwrap();
+    let (mut ranged, mut everything, mut terms) = (connect(&server), connect(&server), connect(&server));
+    send(&mut ranged, serde_json::json!({ "type": "filter", "ranges": [{ "first": "0x100", "last": "0x1FF" }, { "first": "7FF" }, { "first": "0x100", "last": "0x10F", "ext": true }] }));
+    send(&mut terms, serde_json::json!({ "type": "filter", "filters": ["100-1FF", "~150"] }));
+    [&mut ranged, &mut everything, &mut terms].into_iter().for_each(sync);
+
+    let sent = [std_frame(0xFF, &[]), std_frame(0x100, &[]), std_frame(0x150, &[]), std_frame(0x1FF, &[]), std_frame(0x200, &[]), std_frame(0x7FF, &[]), ext_frame(0x105, &[]), ext_frame(0x110, &[])];
+    for frame in &sent {
+        mock.inject(0, frame);
+    }
+    let got = |client: &mut Client, count| (0..count).map(|_| frame(client)).collect::<Vec<_>>();
+    let frames = got(&mut ranged, 5);
+    assert_eq!(ids(&frames), [("0x100", false), ("0x150", false), ("0x1FF", false), ("0x7FF", false), ("0x105", true)]);
+    let frames = got(&mut terms, 2);
+    assert_eq!(ids(&frames), [("0x100", false), ("0x1FF", false)], "the block term wins");
+    assert_eq!(got(&mut everything, 8).len(), 8);
+    for client in [&mut ranged, &mut everything, &mut terms] {
+        assert!(next(client).is_none());
+    }
+
+    // An empty filter passes everything again.
+    send(&mut ranged, serde_json::json!({ "type": "filter" }));
+    sync(&mut ranged);
+    mock.inject(1, &std_frame(0x1, &[]));
+    assert_eq!(frame(&mut ranged).id, "0x1");
+}
+
+#[test]
+fn clients_transmit_and_hear_about_bad_requests() {
+    let (mock, _device, can1, can2) = open_pair();
+    let server = WsServer::start("127.0.0.1:0", &[can1, can2], 8).unwrap();
+    let mut client = connect(&server);
+
+    send(&mut client, serde_json::json!({ "type": "transmit", "ch": 0, "id": "0x123", "data": "0A0B0C" }));
+    send(&mut client, serde_json::json!({ "type": "transmit", "ch": 1, "id": "18FF50E5", "ext": true, "data": "" }));
+    send(&mut client, serde_json::json!({ "type": "transmit", "ch": 0, "id": "0x7DF", "rtr": true, "dlc": 8 }));
+    // The client hears what it sent on CAN1 come in on CAN2, and the other way around.
+    let mut echoed: Vec<_> = (0..3).map(|_| frame(&mut client)).map(|frame| (frame.ch, frame.id)).collect();
+    echoed.sort();
+    assert_eq!(echoed, [(0, "0x18FF50E5".to_string()), (1, "0x123".into()), (1, "0x7DF".into())]);
+    let expected = [std_frame(0x123, &[0x0A, 0x0B, 0x0C]), Frame::remote(Id::Standard(0x7DF), 8).unwrap()];
+    assert_eq!(mock.take_transmitted(0).iter().map(content).collect::<Vec<_>>(), expected.iter().map(content).collect::<Vec<_>>());
+    assert_eq!(mock.take_transmitted(1).iter().map(content).collect::<Vec<_>>(), [content(&ext_frame(0x18FF_50E5, &[]))]);
+
+    let transmit = |fields: Value| {
+        let mut request = serde_json::json!({ "type": "transmit", "ch": 0, "id": "0x1" });
+        request.as_object_mut().unwrap().extend(fields.as_object().unwrap().clone());
+        request
+    };
+    assert_eq!(error(&mut client, transmit(serde_json::json!({ "ch": 2 }))), "no channel 2");
+    assert_eq!(error(&mut client, transmit(serde_json::json!({ "id": "0x800" }))), "invalid CAN ID '0x800'");
+    assert!(error(&mut client, transmit(serde_json::json!({ "data": "0A0" }))).contains("data"));
+    assert!(error(&mut client, transmit(serde_json::json!({ "data": "000102030405060708" }))).contains("8"));
+    assert_eq!(error(&mut client, serde_json::json!({ "type": "filter", "ranges": [{ "first": "0x800" }] })), "invalid CAN ID '0x800'");
+    assert!(error(&mut client, serde_json::json!({ "type": "filter", "filters": ["1-"] })).starts_with("filter '1-': "));
+    assert!(error(&mut client, serde_json::json!({ "type": "subscribe" })).starts_with("invalid request: "));
+    send(&mut client, serde_json::json!({ "type": "transmit" }));
+    client.send(Message::text("not json")).unwrap();
+    assert!(next(&mut client).unwrap()["error"].as_str().unwrap().starts_with("invalid request: "));
+    assert!(next(&mut client).unwrap()["error"].as_str().unwrap().starts_with("invalid request: "));
+    assert!(mock.take_transmitted(0).is_empty(), "nothing was sent for the bad requests");
+}
+
+#[test]
+fn clients_past_the_limit_are_refused() {
+    let (_mock, _device, can1, can2) = open_pair();
+    let server = WsServer::start("127.0.0.1:0", &[can1, can2], 2).unwrap();
+    let mut first = connect(&server);
+    let _second = connect(&server);
+    assert!(wait_for(TIMEOUT, || server.clients() == 2));
+    match tungstenite::connect(url(&server)) {
+        Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), 503),
+        other => panic!("expected a refusal, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!((server.clients(), server.rejected()), (2, 1));
+
+    first.close(None).unwrap();
+    while first.read().is_ok() {}
+    assert!(wait_for(TIMEOUT, || server.clients() == 1));
+    // Room again once a client has left.
+    let _third = connect(&server);
+    assert!(wait_for(TIMEOUT, || server.clients() == 2));
+    assert_eq!(server.rejected(), 1);
+}
+
+#[test]
+fn a_backed_up_client_loses_frames_on_its_own() {
+    let (mock, _device, can1, can2) = open_pair();
+    let mut server = WsServer::start("127.0.0.1:0", &[can1, can2.clone()], 8).unwrap();
+    let stuck = connect(&server);
+    let mut reader = connect(&server);
+    let (stuck_addr, reader_addr) = (local_addr(&stuck), local_addr(&reader));
+    assert!(wait_for(TIMEOUT, || server.clients() == 2));
+    sync(&mut reader);
+    let reading = thread::spawn(move || {
+        // Just the ID, to keep up without parsing the JSON.
+        let mut count = 0u32;
+        while let Ok(message) = reader.read() {
+            let text = message.into_text().unwrap();
+            assert!(text.contains(&format!("\"id\":\"0x{count:X}\"")), "{count} in order, none missing: {text}");
+            count += 1;
+        }
+        (count, reader)
+    });
+
+    let mut injected = 0;
+    while server.dropped() == 0 {
+        assert!(injected < 1_000_000, "the unread client never backed up");
+        for _ in 0..200 {
+            mock.inject(1, &ext_frame(injected, &[0; 8]));
+            injected += 1;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(wait_for(TIMEOUT, || can2.pending().unwrap() == 0), "reception kept going");
+    let (count, _reader) = reading.join().unwrap();
+    assert_eq!(count, injected, "the reading client got everything");
+
+    let drops = server.client_drops();
+    let drops_of = |addr| drops.iter().find(|(client, _)| *client == addr).map(|(_, dropped)| *dropped);
+    assert!(drops_of(stuck_addr).unwrap() > 0);
+    assert_eq!(drops_of(reader_addr), Some(0));
+    assert_eq!(server.clients(), 2, "backed up is not disconnected");
+    drop(stuck);
+    server.stop();
+}